@@ -0,0 +1,101 @@
+use std::{fmt::Write, sync::Arc};
+
+use smol_str::SmolStr;
+
+use crate::{delegate::TransformDelegate, types::Tags};
+
+/// The reserved tag carrying the additional addresses of a member, see
+/// [`Serf::set_advertise_addresses`](crate::Serf::set_advertise_addresses).
+///
+/// Each address is encoded with [`TransformDelegate::encode_address`] and
+/// written as lowercase hex, the addresses are separated by a `,` in order of
+/// preference.
+pub(crate) const ADDRESSES_TAG: &str = "_ruserf_addrs";
+
+/// Returns the value of the tag advertising the addresses, empty if there
+/// are none.
+pub(crate) fn encode<D: TransformDelegate>(addresses: &[D::Address]) -> Result<SmolStr, D::Error> {
+  let mut encoded = String::new();
+  let mut buf = Vec::new();
+  for (i, addr) in addresses.iter().enumerate() {
+    buf.resize(D::address_encoded_len(addr), 0);
+    let len = D::encode_address(addr, &mut buf)?;
+    if i > 0 {
+      encoded.push(',');
+    }
+    for b in &buf[..len] {
+      let _ = write!(encoded, "{b:02x}");
+    }
+  }
+  Ok(SmolStr::from(encoded))
+}
+
+/// Adds the additional addresses to the tags advertised in the meta of the
+/// local node, if it has any.
+pub(crate) fn advertise(tags: &mut Tags, encoded: &SmolStr) {
+  if !encoded.is_empty() {
+    tags.insert(SmolStr::new(ADDRESSES_TAG), encoded.clone());
+  }
+}
+
+/// Removes the additional addresses from the decoded tags of a member, and
+/// returns them. The malformed addresses are skipped.
+pub(crate) fn split<D: TransformDelegate>(tags: &mut Tags) -> Arc<[D::Address]> {
+  let Some(encoded) = tags.shift_remove(ADDRESSES_TAG) else {
+    return Arc::from(Vec::new());
+  };
+
+  encoded
+    .split(',')
+    .filter_map(|hex| {
+      let bytes = decode_hex(hex)?;
+      D::decode_address(&bytes).ok().map(|(_, addr)| addr)
+    })
+    .collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+  if hex.len() % 2 != 0 {
+    return None;
+  }
+
+  (0..hex.len())
+    .step_by(2)
+    .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::SocketAddr;
+
+  use crate::delegate::LpeTransfromDelegate;
+
+  use super::*;
+
+  type Lpe = LpeTransfromDelegate<SmolStr, SocketAddr>;
+
+  #[test]
+  fn test_addresses_tag() {
+    let mut tags = Tags::default();
+    advertise(&mut tags, &encode::<Lpe>(&[]).unwrap());
+    assert!(tags.is_empty());
+    assert!(split::<Lpe>(&mut tags).is_empty());
+
+    let addresses: [SocketAddr; 2] = [
+      "10.0.0.1:7946".parse().unwrap(),
+      "[2001:db8::1]:7946".parse().unwrap(),
+    ];
+    advertise(&mut tags, &encode::<Lpe>(&addresses).unwrap());
+    assert_eq!(split::<Lpe>(&mut tags).as_ref(), &addresses);
+    assert!(tags.is_empty());
+
+    // The malformed addresses are skipped
+    let encoded = encode::<Lpe>(&addresses[..1]).unwrap();
+    tags.insert(
+      SmolStr::new(ADDRESSES_TAG),
+      SmolStr::from(format!("zz,{encoded},0")),
+    );
+    assert_eq!(split::<Lpe>(&mut tags).as_ref(), &addresses[..1]);
+  }
+}
@@ -0,0 +1,266 @@
+use std::time::Duration;
+
+use memberlist_core::{
+  bytes::{BufMut, Bytes, BytesMut},
+  tracing,
+  transport::{AddressResolver, MaybeResolvedAddress, Node, Transport},
+  types::TinyVec,
+  CheapClone,
+};
+use rand::seq::SliceRandom;
+use smol_str::SmolStr;
+
+use crate::{
+  delegate::{Delegate, TransformDelegate},
+  error::Error,
+  event::{InternalQueryEvent, INTERNAL_AUDIT},
+  types::{Filter, LamportTime, MemberStatus},
+  Serf,
+};
+
+/// The number of the buckets of a [`MemberDigest`].
+const BUCKETS: usize = 32;
+
+/// Enables the periodic anti-entropy audit of the member map, see
+/// [`Options::anti_entropy_audit`](crate::Options::anti_entropy_audit).
+///
+/// Every `interval`, the local node compares a digest of its member map with
+/// the one of a random alive member. The digest buckets the members by id,
+/// so the divergence is the number of the buckets which differ. Once it is
+/// above `max_divergence`, the member maps are reconciled right away with a
+/// push/pull rather than waiting for the next periodic one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AntiEntropyAudit {
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  interval: Duration,
+  max_divergence: usize,
+}
+
+impl Default for AntiEntropyAudit {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl AntiEntropyAudit {
+  /// Returns a new configuration, auditing every minute and tolerating
+  /// 2 diverging buckets out of 32 for the members changing in flight.
+  #[inline]
+  pub const fn new() -> Self {
+    Self {
+      interval: Duration::from_secs(60),
+      max_divergence: 2,
+    }
+  }
+
+  /// Sets how often the member map is audited.
+  #[inline]
+  pub const fn with_interval(mut self, interval: Duration) -> Self {
+    self.interval = interval;
+    self
+  }
+
+  /// Sets the number of the diverging buckets tolerated before the member
+  /// maps are reconciled, at most 32.
+  #[inline]
+  pub const fn with_max_divergence(mut self, max_divergence: usize) -> Self {
+    self.max_divergence = max_divergence;
+    self
+  }
+
+  /// Returns how often the member map is audited.
+  #[inline]
+  pub const fn interval(&self) -> Duration {
+    self.interval
+  }
+
+  /// Returns the number of the diverging buckets tolerated.
+  #[inline]
+  pub const fn max_divergence(&self) -> usize {
+    self.max_divergence
+  }
+}
+
+/// A digest of the ids and the status Lamport times of the known members.
+///
+/// The members are spread over the buckets by the hash of their id, each
+/// bucket sums the hashes of its members, so the digest does not depend on
+/// the order the members are visited in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct MemberDigest([u32; BUCKETS]);
+
+impl MemberDigest {
+  pub(crate) const fn new() -> Self {
+    Self([0; BUCKETS])
+  }
+
+  /// Adds a member by its encoded id.
+  pub(crate) fn add(&mut self, id: &[u8], status_time: LamportTime) {
+    let bucket = crc32fast::hash(id) as usize % BUCKETS;
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(id);
+    hasher.update(&u64::from(status_time).to_be_bytes());
+    self.0[bucket] = self.0[bucket].wrapping_add(hasher.finalize());
+  }
+
+  /// Returns the number of the buckets which differ.
+  pub(crate) fn divergence(&self, other: &Self) -> usize {
+    self
+      .0
+      .iter()
+      .zip(other.0.iter())
+      .filter(|(a, b)| a != b)
+      .count()
+  }
+
+  pub(crate) fn encode(&self) -> Bytes {
+    let mut buf = BytesMut::with_capacity(BUCKETS * 4);
+    for bucket in self.0 {
+      buf.put_u32(bucket);
+    }
+    buf.freeze()
+  }
+
+  pub(crate) fn decode(src: &[u8]) -> Option<Self> {
+    if src.len() != BUCKETS * 4 {
+      return None;
+    }
+
+    let mut digest = Self::new();
+    for (bucket, chunk) in digest.0.iter_mut().zip(src.chunks_exact(4)) {
+      *bucket = u32::from_be_bytes(chunk.try_into().ok()?);
+    }
+    Some(digest)
+  }
+}
+
+impl<T, D> Serf<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  /// Returns the digest of the member map of the local node.
+  pub(crate) async fn member_digest(&self) -> Result<MemberDigest, Error<T, D>> {
+    let members = self.inner.members.read().await;
+    let mut digest = MemberDigest::new();
+    let mut buf = Vec::new();
+    for state in members.states.values() {
+      let id = state.member.node.id();
+      buf.resize(<D as TransformDelegate>::id_encoded_len(id), 0);
+      let len =
+        <D as TransformDelegate>::encode_id(id, &mut buf).map_err(Error::transform_delegate)?;
+      digest.add(&buf[..len], state.status_time);
+    }
+    Ok(digest)
+  }
+
+  /// Compares the digest of the member map with the one of a random alive
+  /// member, and reconciles them with a push/pull if they diverge by more
+  /// than `max_divergence` buckets.
+  ///
+  /// Returns the divergence, `None` if there is no other alive member.
+  pub(crate) async fn audit_member_map(
+    &self,
+    max_divergence: usize,
+  ) -> Result<Option<usize>, Error<T, D>> {
+    let peer = {
+      let members = self.inner.members.read().await;
+      let local_id = self.inner.memberlist.local_id();
+      let peers = members
+        .states
+        .values()
+        .filter(|m| m.member.status == MemberStatus::Alive && m.member.node.id().ne(local_id))
+        .map(|m| m.member.node.cheap_clone())
+        .collect::<Vec<_>>();
+      match peers.choose(&mut *self.inner.rng.lock()) {
+        Some(peer) => peer.cheap_clone(),
+        None => return Ok(None),
+      }
+    };
+
+    let mut params = self.default_query_param().await;
+    params
+      .filters
+      .push(Filter::Id(TinyVec::from(peer.id().cheap_clone())));
+    let resp = self
+      .internal_query(
+        SmolStr::new(INTERNAL_AUDIT),
+        Bytes::new(),
+        Some(params),
+        InternalQueryEvent::Audit,
+      )
+      .await?;
+
+    let resp_rx = resp.response_rx();
+    let remote = loop {
+      let Ok(r) = resp_rx.recv().await else {
+        return Err(Error::query_timeout());
+      };
+      if r.from().id().ne(peer.id()) {
+        continue;
+      }
+
+      match MemberDigest::decode(r.payload()) {
+        Some(digest) => break digest,
+        None => tracing::warn!("ruserf: invalid audit response from {}", peer.id()),
+      }
+    };
+
+    // Compare with the member map as it is now, the peer answered after the query was sent
+    let divergence = self.member_digest().await?.divergence(&remote);
+    if divergence > max_divergence {
+      tracing::warn!(
+        divergence,
+        "ruserf: member map diverges from {}, reconciling",
+        peer.id()
+      );
+
+      #[cfg(feature = "metrics")]
+      {
+        metrics::counter!(
+          "ruserf.antientropy.divergence",
+          self.inner.opts.memberlist_options.metric_labels().iter()
+        )
+        .increment(1);
+      }
+
+      let (id, address) = peer.into_components();
+      if let Err(e) = self
+        .inner
+        .memberlist
+        .join(Node::new(id, MaybeResolvedAddress::resolved(address)))
+        .await
+      {
+        tracing::warn!(err=%e, "ruserf: failed to reconcile the member map");
+      }
+    }
+
+    Ok(Some(divergence))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_member_digest() {
+    let mut a = MemberDigest::new();
+    let mut b = MemberDigest::new();
+    a.add(b"node-1", LamportTime::new(1));
+    a.add(b"node-2", LamportTime::new(2));
+    // The order the members are added in does not matter
+    b.add(b"node-2", LamportTime::new(2));
+    b.add(b"node-1", LamportTime::new(1));
+    assert_eq!(a.divergence(&b), 0);
+    assert_eq!(MemberDigest::decode(&a.encode()), Some(a));
+    assert!(MemberDigest::decode(&a.encode()[1..]).is_none());
+
+    // A newer status time diverges
+    let mut c = MemberDigest::new();
+    c.add(b"node-1", LamportTime::new(3));
+    c.add(b"node-2", LamportTime::new(2));
+    assert_eq!(a.divergence(&c), 1);
+  }
+}
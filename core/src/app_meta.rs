@@ -0,0 +1,62 @@
+use memberlist_core::bytes::Bytes;
+
+/// The application metadata follows the encoded tags in the meta of a
+/// member, prefixed with its length as a big endian `u32`.
+///
+/// The decoders of the tags skip it by the length prefix of the tags, so the
+/// members not knowing about it only see the tags.
+const LEN_PREFIX: usize = 4;
+
+/// Returns the number of bytes the application metadata takes in the meta.
+pub(crate) fn encoded_len(app_meta: &[u8]) -> usize {
+  if app_meta.is_empty() {
+    0
+  } else {
+    LEN_PREFIX + app_meta.len()
+  }
+}
+
+/// Encodes the application metadata into the given buffer, which must be at
+/// least [`encoded_len`] bytes long, returning the number of bytes written.
+pub(crate) fn encode(app_meta: &[u8], dst: &mut [u8]) -> usize {
+  if app_meta.is_empty() {
+    return 0;
+  }
+
+  dst[..LEN_PREFIX].copy_from_slice(&(app_meta.len() as u32).to_be_bytes());
+  dst[LEN_PREFIX..LEN_PREFIX + app_meta.len()].copy_from_slice(app_meta);
+  LEN_PREFIX + app_meta.len()
+}
+
+/// Decodes the application metadata following the tags, a missing or
+/// truncated one is empty.
+pub(crate) fn decode(src: &[u8]) -> Bytes {
+  if src.len() < LEN_PREFIX {
+    return Bytes::new();
+  }
+
+  let len = u32::from_be_bytes(src[..LEN_PREFIX].try_into().unwrap()) as usize;
+  match src.get(LEN_PREFIX..LEN_PREFIX + len) {
+    Some(app_meta) => Bytes::copy_from_slice(app_meta),
+    None => Bytes::new(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_app_meta_encode_decode() {
+    assert_eq!(encoded_len(&[]), 0);
+    assert!(decode(&[]).is_empty());
+
+    let app_meta = [1, 2, 3, 0, 255];
+    let mut buf = vec![0; encoded_len(&app_meta)];
+    assert_eq!(encode(&app_meta, &mut buf), buf.len());
+    assert_eq!(decode(&buf).as_ref(), &app_meta);
+
+    // Truncated
+    assert!(decode(&buf[..buf.len() - 1]).is_empty());
+  }
+}
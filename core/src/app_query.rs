@@ -0,0 +1,93 @@
+use std::{collections::HashMap, sync::Arc};
+
+use futures::future::BoxFuture;
+use memberlist_core::{
+  bytes::Bytes,
+  transport::{AddressResolver, Transport},
+};
+use smol_str::{format_smolstr, SmolStr};
+
+use crate::{
+  delegate::Delegate,
+  error::Error,
+  event::InternalQueryEvent,
+  serf::{QueryParam, QueryResponse},
+  Serf,
+};
+
+/// The prefix of the query names answered by an [`AppQueryHandler`].
+pub const APP_QUERY_PREFIX: &str = "_ruserf_app/";
+
+/// Answers the application queries sent with [`Serf::app_query`], see
+/// [`Serf::register_query_handler`].
+///
+/// The handler is invoked by the query processing of [`Serf`] itself, so the
+/// queries are answered without consuming the event channel.
+#[auto_impl::auto_impl(Box, Arc)]
+pub trait AppQueryHandler: Send + Sync + 'static {
+  /// Returns the response to the query `name`, without the
+  /// [`APP_QUERY_PREFIX`], or `None` to not respond.
+  fn handle(&self, name: &str, payload: Bytes) -> BoxFuture<'static, Option<Bytes>>;
+}
+
+/// The handlers registered on the local node, by query name.
+#[derive(Default)]
+pub(crate) struct AppQueryHandlers {
+  handlers: parking_lot::RwLock<HashMap<SmolStr, Arc<dyn AppQueryHandler>>>,
+}
+
+impl AppQueryHandlers {
+  pub(crate) fn get(&self, name: &str) -> Option<Arc<dyn AppQueryHandler>> {
+    self.handlers.read().get(name).cloned()
+  }
+}
+
+impl<T, D> Serf<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  /// Registers the handler answering the application query `name` on this
+  /// node, replacing and returning the previous one, if any.
+  pub fn register_query_handler(
+    &self,
+    name: impl Into<SmolStr>,
+    handler: impl AppQueryHandler,
+  ) -> Option<Arc<dyn AppQueryHandler>> {
+    self
+      .inner
+      .app_queries
+      .handlers
+      .write()
+      .insert(name.into(), Arc::new(handler))
+  }
+
+  /// Unregisters the handler of the application query `name`, returns it if
+  /// it was registered. The queries of that name are no longer answered by
+  /// this node.
+  pub fn unregister_query_handler(&self, name: &str) -> Option<Arc<dyn AppQueryHandler>> {
+    self.inner.app_queries.handlers.write().remove(name)
+  }
+
+  /// Sends the application query `name`, answered by the members which
+  /// registered a handler for it with [`Serf::register_query_handler`].
+  ///
+  /// The query is sent under the [`APP_QUERY_PREFIX`], so it never reaches
+  /// the event channel of the members.
+  pub async fn app_query(
+    &self,
+    name: &str,
+    payload: impl Into<Bytes>,
+    params: Option<QueryParam<T::Id>>,
+  ) -> Result<QueryResponse<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>, Error<T, D>>
+  {
+    self
+      .internal_query(
+        format_smolstr!("{APP_QUERY_PREFIX}{name}"),
+        payload.into(),
+        params,
+        InternalQueryEvent::App,
+      )
+      .await
+  }
+}
@@ -1,6 +1,9 @@
 use async_channel::Sender;
 use memberlist_core::{bytes::Bytes, Broadcast};
 
+#[cfg(feature = "metrics")]
+use crate::types::Epoch;
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub(crate) struct BroadcastId;
 
@@ -10,16 +13,39 @@ impl core::fmt::Display for BroadcastId {
   }
 }
 
+/// A queued gossip message.
+#[derive(Debug, Clone)]
+pub(crate) struct SerfBroadcastMessage {
+  pub(crate) payload: Bytes,
+  /// When the message was queued, used to report the message age at transmit time.
+  #[cfg(feature = "metrics")]
+  pub(crate) queued_at: Epoch,
+}
+
 #[viewit::viewit]
 #[derive(Debug)]
 pub(crate) struct SerfBroadcast {
-  msg: Bytes,
+  msg: SerfBroadcastMessage,
   notify_tx: Option<Sender<()>>,
 }
 
+impl SerfBroadcast {
+  #[inline]
+  pub(crate) fn new(msg: Bytes, notify_tx: Option<Sender<()>>) -> Self {
+    Self {
+      msg: SerfBroadcastMessage {
+        payload: msg,
+        #[cfg(feature = "metrics")]
+        queued_at: Epoch::now(),
+      },
+      notify_tx,
+    }
+  }
+}
+
 impl Broadcast for SerfBroadcast {
   type Id = BroadcastId;
-  type Message = Bytes;
+  type Message = SerfBroadcastMessage;
 
   fn id(&self) -> Option<&Self::Id> {
     None
@@ -29,6 +55,12 @@ impl Broadcast for SerfBroadcast {
     false
   }
 
+  // Serf messages never invalidate each other, this also keeps the queue from
+  // reusing the ids of back-to-back broadcasts of the same size.
+  fn is_unique(&self) -> bool {
+    true
+  }
+
   fn message(&self) -> &Self::Message {
     &self.msg
   }
@@ -40,7 +72,7 @@ impl Broadcast for SerfBroadcast {
   }
 
   fn encoded_len(msg: &Self::Message) -> usize {
-    msg.len()
+    msg.payload.len()
   }
 }
 
@@ -52,10 +84,7 @@ async fn test_broadcast_finished() {
 
   let (tx, rx) = async_channel::unbounded();
 
-  let b = SerfBroadcast {
-    msg: Bytes::new(),
-    notify_tx: Some(tx),
-  };
+  let b = SerfBroadcast::new(Bytes::new(), Some(tx));
 
   b.finished().await;
 
@@ -70,10 +99,7 @@ async fn test_broadcast_finished() {
 #[cfg(test)]
 #[tokio::test]
 async fn test_broadcast_finished_no_sender() {
-  let b = SerfBroadcast {
-    msg: Bytes::new(),
-    notify_tx: None,
-  };
+  let b = SerfBroadcast::new(Bytes::new(), None);
 
   b.finished().await;
 }
@@ -0,0 +1,234 @@
+use std::{
+  future::Future,
+  sync::Arc,
+  time::{Duration, Instant},
+};
+
+use futures::{
+  channel::oneshot,
+  future::{BoxFuture, Either},
+  FutureExt,
+};
+use memberlist_core::agnostic_lite::RuntimeLite;
+
+use crate::types::Epoch;
+
+/// The source of time of the background tasks of [`Serf`](crate::Serf), see
+/// [`Options::clock`](crate::Options::clock).
+///
+/// The reaper, the reconnector, the event coalescers, the query deadlines
+/// and the waits of the leave, the join and the removal of a member sleep on
+/// the clock, and the ages of the tombstones, failed members and intents are
+/// measured with it. The gossip timers of the memberlist are not affected.
+#[auto_impl::auto_impl(Box, Arc)]
+pub trait Clock: Send + Sync + 'static {
+  /// Returns the time elapsed since the clock was created.
+  fn elapsed(&self) -> Duration;
+
+  /// Returns a future which completes once `duration` has passed on the clock.
+  fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+#[derive(Debug, Default)]
+struct ManualState {
+  now: Duration,
+  sleepers: Vec<(Duration, oneshot::Sender<()>)>,
+}
+
+/// A [`Clock`] which only moves forward when [`ManualClock::advance`] is called,
+/// so tests can drive the timers without waiting on the wall clock.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock(Arc<parking_lot::Mutex<ManualState>>);
+
+impl ManualClock {
+  /// Returns a clock stopped at zero.
+  #[inline]
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Moves the clock forward, completing the sleeps which are due.
+  pub fn advance(&self, duration: Duration) {
+    let due = {
+      let mut state = self.0.lock();
+      state.now += duration;
+      let now = state.now;
+      let (due, pending) = std::mem::take(&mut state.sleepers)
+        .into_iter()
+        .partition::<Vec<_>, _>(|(deadline, _)| *deadline <= now);
+      state.sleepers = pending;
+      due
+    };
+
+    for (_, tx) in due {
+      let _ = tx.send(());
+    }
+  }
+
+  /// Returns the number of sleeps which are still waiting on the clock.
+  pub fn sleepers(&self) -> usize {
+    let mut state = self.0.lock();
+    state.sleepers.retain(|(_, tx)| !tx.is_canceled());
+    state.sleepers.len()
+  }
+}
+
+impl Clock for ManualClock {
+  fn elapsed(&self) -> Duration {
+    self.0.lock().now
+  }
+
+  fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+    let mut state = self.0.lock();
+    // A sleep past the end of time never completes
+    let Some(deadline) = state.now.checked_add(duration) else {
+      return std::future::pending().boxed();
+    };
+    if deadline <= state.now {
+      return std::future::ready(()).boxed();
+    }
+
+    let (tx, rx) = oneshot::channel();
+    state.sleepers.push((deadline, tx));
+    async move {
+      if rx.await.is_err() {
+        std::future::pending::<()>().await;
+      }
+    }
+    .boxed()
+  }
+}
+
+/// The clock used by a [`Serf`](crate::Serf) instance, which falls back to
+/// the runtime timers if no [`Clock`] is configured.
+#[derive(Clone, Default)]
+pub(crate) struct Timer {
+  /// The configured clock, with the wall time and the clock time when it was adopted.
+  clock: Option<(Arc<dyn Clock>, Epoch, Duration)>,
+}
+
+impl Timer {
+  pub(crate) fn new(clock: Option<Arc<dyn Clock>>) -> Self {
+    Self {
+      clock: clock.map(|c| {
+        let base = c.elapsed();
+        (c, Epoch::now(), base)
+      }),
+    }
+  }
+
+  /// Returns the current time.
+  pub(crate) fn now(&self) -> Epoch {
+    match &self.clock {
+      Some((clock, origin, base)) => *origin + clock.elapsed().saturating_sub(*base),
+      None => Epoch::now(),
+    }
+  }
+
+  /// Returns a future which completes once `duration` has passed.
+  pub(crate) fn sleep<R: RuntimeLite>(
+    &self,
+    duration: Duration,
+  ) -> impl Future<Output = ()> + Send + 'static {
+    match &self.clock {
+      Some((clock, _, _)) => Either::Left(clock.sleep(duration)),
+      None => Either::Right(R::sleep(duration).map(|_| ())),
+    }
+  }
+
+  /// Returns a deadline which passes once `timeout` has passed.
+  pub(crate) fn deadline(&self, timeout: Duration) -> Deadline {
+    Deadline {
+      timer: self.clone(),
+      at: self.now() + timeout,
+      instant: Instant::now() + timeout,
+    }
+  }
+}
+
+/// A deadline measured on a [`Timer`].
+///
+/// Nothing waits for the deadline, it is only compared with the time of the
+/// timer when checked, so a [`ManualClock`] drives it like the sleeps.
+#[derive(Clone)]
+pub(crate) struct Deadline {
+  timer: Timer,
+  /// The time of the timer the deadline passes at
+  at: Epoch,
+  /// The wall time the deadline passes at, as reported to the users
+  instant: Instant,
+}
+
+impl core::fmt::Debug for Deadline {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_struct("Deadline")
+      .field("at", &self.at)
+      .field("instant", &self.instant)
+      .finish()
+  }
+}
+
+impl Deadline {
+  /// Returns the wall time the deadline passes at.
+  #[inline]
+  pub(crate) const fn instant(&self) -> Instant {
+    self.instant
+  }
+
+  /// Returns `true` if the deadline has passed.
+  #[inline]
+  pub(crate) fn is_expired(&self) -> bool {
+    self.timer.now() >= self.at
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use agnostic_lite::tokio::TokioRuntime;
+
+  use super::*;
+
+  #[tokio::test]
+  async fn test_manual_clock() {
+    let clock = ManualClock::new();
+    let timer = Timer::new(Some(Arc::new(clock.clone())));
+    let start = timer.now();
+
+    let short = timer.sleep::<TokioRuntime>(Duration::from_secs(10));
+    let long = timer.sleep::<TokioRuntime>(Duration::from_secs(60));
+    futures::pin_mut!(short, long);
+    assert_eq!(clock.sleepers(), 2);
+
+    clock.advance(Duration::from_secs(9));
+    assert!(futures::poll!(short.as_mut()).is_pending());
+
+    clock.advance(Duration::from_secs(1));
+    assert!(futures::poll!(short.as_mut()).is_ready());
+    assert!(futures::poll!(long.as_mut()).is_pending());
+    assert_eq!(clock.sleepers(), 1);
+    assert_eq!(timer.now() - start, Duration::from_secs(10));
+
+    clock.advance(Duration::from_secs(3600));
+    assert!(futures::poll!(long.as_mut()).is_ready());
+    assert_eq!(clock.sleepers(), 0);
+
+    // zero sleeps complete right away
+    let zero = timer.sleep::<TokioRuntime>(Duration::ZERO);
+    futures::pin_mut!(zero);
+    assert!(futures::poll!(zero).is_ready());
+  }
+
+  #[test]
+  fn test_deadline_follows_timer() {
+    let clock = ManualClock::new();
+    let timer = Timer::new(Some(Arc::new(clock.clone())));
+    let deadline = timer.deadline(Duration::from_secs(3600));
+    assert!(!deadline.is_expired());
+
+    clock.advance(Duration::from_secs(1800));
+    assert!(!deadline.is_expired());
+
+    clock.advance(Duration::from_secs(1800));
+    assert!(deadline.is_expired());
+  }
+}
@@ -0,0 +1,46 @@
+use smol_str::{format_smolstr, SmolStr};
+
+use crate::types::Tags;
+
+/// The reserved tag carrying the hash of the cluster name of a member, see
+/// [`Options::cluster_name`](crate::Options::cluster_name).
+pub(crate) const CLUSTER_TAG: &str = "_ruserf_cluster";
+
+/// Returns the hash of a cluster name, advertised rather than the name so
+/// the name of the cluster is not disclosed to the foreign nodes.
+pub(crate) fn hash(name: &str) -> SmolStr {
+  format_smolstr!("{:08x}", crc32fast::hash(name.as_bytes()))
+}
+
+/// Adds the hash of the cluster name to the tags advertised in the meta of
+/// the local node, if it is named.
+pub(crate) fn advertise(tags: &mut Tags, cluster: Option<&SmolStr>) {
+  if let Some(cluster) = cluster {
+    tags.insert(SmolStr::new(CLUSTER_TAG), cluster.clone());
+  }
+}
+
+/// Removes the hash of the cluster name from the decoded tags of a member,
+/// and returns it.
+pub(crate) fn split(tags: &mut Tags) -> Option<SmolStr> {
+  tags.shift_remove(CLUSTER_TAG)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_cluster_tag() {
+    let prod = hash("prod");
+    assert_eq!(prod, hash("prod"));
+    assert_ne!(prod, hash("staging"));
+
+    let mut tags = Tags::default();
+    advertise(&mut tags, None);
+    assert!(tags.is_empty());
+    advertise(&mut tags, Some(&prod));
+    assert_eq!(split(&mut tags), Some(prod));
+    assert!(tags.is_empty());
+  }
+}
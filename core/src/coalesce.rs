@@ -1,3 +1,5 @@
+mod batch;
+pub(crate) use batch::*;
 mod member;
 pub(crate) use member::*;
 mod user;
@@ -9,11 +11,15 @@ use async_channel::{bounded, Receiver, Sender};
 use futures::FutureExt;
 use memberlist_core::{
   agnostic_lite::RuntimeLite,
-  tracing,
+  tracing::{self, Instrument},
   transport::{AddressResolver, Transport},
 };
 
-use crate::delegate::Delegate;
+use crate::{
+  clock::Timer,
+  delegate::Delegate,
+  task::{TaskHeartbeat, TaskRegistry},
+};
 
 use super::event::CrateEvent;
 
@@ -45,37 +51,40 @@ pub(crate) trait Coalescer: Send + Sync + 'static {
 pub(crate) fn coalesced_event<C: Coalescer>(
   out_tx: Sender<CrateEvent<C::Transport, C::Delegate>>,
   shutdown_rx: Receiver<()>,
-  c_period: Duration,
-  q_period: Duration,
+  timer: Timer,
+  periods: impl Fn() -> (Duration, Duration) + Send + Sync + 'static,
   c: C,
+  tasks: &TaskRegistry,
 ) -> Sender<CrateEvent<C::Transport, C::Delegate>> {
   let (in_tx, in_rx) = bounded(1024);
-  <<C::Transport as Transport>::Runtime as RuntimeLite>::spawn_detach(coalesce_loop::<C>(
-    in_rx,
-    out_tx,
-    shutdown_rx,
-    c_period,
-    q_period,
-    c,
-  ));
+  let heartbeat = tasks.register(c.name());
+  let span = heartbeat.span();
+  <<C::Transport as Transport>::Runtime as RuntimeLite>::spawn_detach(
+    coalesce_loop::<C>(in_rx, out_tx, shutdown_rx, timer, periods, c, heartbeat).instrument(span),
+  );
   in_tx
 }
 
 /// A simple long-running routine that manages the high-level
 /// flow of coalescing based on quiescence and a maximum quantum period.
+///
+/// `periods` returns the coalesce and quiescent periods, it is invoked
+/// for every coalesced event so that the periods can be changed at runtime.
 async fn coalesce_loop<C: Coalescer>(
   in_rx: Receiver<CrateEvent<C::Transport, C::Delegate>>,
   out_tx: Sender<CrateEvent<C::Transport, C::Delegate>>,
   shutdown_rx: Receiver<()>,
-  coalesce_peirod: Duration,
-  quiescent_period: Duration,
+  timer: Timer,
+  periods: impl Fn() -> (Duration, Duration),
   mut c: C,
+  heartbeat: TaskHeartbeat,
 ) {
   let mut quiescent = None;
   let mut quantum = None;
   let mut shutdown = false;
 
   loop {
+    heartbeat.beat();
     futures::select! {
       ev = in_rx.recv().fuse() => {
         let Ok(ev) = ev else {
@@ -94,16 +103,18 @@ async fn coalesce_loop<C: Coalescer>(
 
         // Start a new quantum if we need to
         // and restart the quiescent timer
+        let (coalesce_peirod, quiescent_period) = periods();
         if quantum.is_none() {
-          quantum = Some(<<C::Transport as Transport>::Runtime as RuntimeLite>::sleep(coalesce_peirod));
+          quantum = Some(Box::pin(timer.sleep::<<C::Transport as Transport>::Runtime>(coalesce_peirod)));
         }
-        quiescent = Some(<<C::Transport as Transport>::Runtime as RuntimeLite>::sleep(quiescent_period));
+        quiescent = Some(Box::pin(timer.sleep::<<C::Transport as Transport>::Runtime>(quiescent_period)));
 
         // Coalesce the event
         c.coalesce(ev);
       }
       _ = async {
-        if let Some(quantum) = quantum.take() {
+        // Polled by reference, the timer must survive the events passing through
+        if let Some(quantum) = quantum.as_mut() {
           quantum.await;
 
         } else {
@@ -126,7 +137,7 @@ async fn coalesce_loop<C: Coalescer>(
         return;
       }
       _ = async {
-        if let Some(quiescent) = quiescent.take() {
+        if let Some(quiescent) = quiescent.as_mut() {
           quiescent.await;
         } else {
           std::future::pending::<()>().await;
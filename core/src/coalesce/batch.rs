@@ -0,0 +1,189 @@
+use std::marker::PhantomData;
+
+use async_channel::Sender;
+use memberlist_core::transport::{AddressResolver, Transport};
+
+use crate::{
+  delegate::Delegate,
+  event::{CrateEvent, MemberEventMut},
+};
+
+use super::Coalescer;
+
+/// Groups the consecutive member events of the same type, see
+/// [`Options::member_event_batch_window`](crate::Options::member_event_batch_window).
+///
+/// Unlike [`MemberEventCoalescer`](super::MemberEventCoalescer), every event
+/// is kept and the order of the events is preserved.
+pub(crate) struct MemberEventBatcher<T: Transport, D> {
+  batches: Vec<MemberEventMut<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>>,
+  _m: PhantomData<D>,
+}
+
+impl<T: Transport, D> MemberEventBatcher<T, D> {
+  pub(crate) fn new() -> Self {
+    Self {
+      batches: Vec::new(),
+      _m: PhantomData,
+    }
+  }
+}
+
+impl<T, D> Coalescer for MemberEventBatcher<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  type Delegate = D;
+  type Transport = T;
+
+  fn name(&self) -> &'static str {
+    "member_event_batcher"
+  }
+
+  fn handle(&self, event: &CrateEvent<Self::Transport, Self::Delegate>) -> bool {
+    matches!(event, CrateEvent::Member(_))
+  }
+
+  fn coalesce(&mut self, event: CrateEvent<Self::Transport, Self::Delegate>) {
+    let CrateEvent::Member(event) = event else {
+      unreachable!();
+    };
+
+    let (ty, members) = event.into();
+    match self.batches.last_mut() {
+      Some(batch) if batch.ty == ty => batch.members.extend(members.iter().cloned()),
+      _ => self.batches.push(MemberEventMut {
+        ty,
+        members: members.iter().cloned().collect(),
+      }),
+    }
+  }
+
+  async fn flush(
+    &mut self,
+    out_tx: &Sender<CrateEvent<Self::Transport, Self::Delegate>>,
+  ) -> Result<(), super::ClosedOutChannel> {
+    for batch in self.batches.drain(..) {
+      if out_tx.send(CrateEvent::from(batch.freeze())).await.is_err() {
+        return Err(super::ClosedOutChannel);
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(all(test, feature = "test"))]
+mod tests {
+  use std::{net::SocketAddr, time::Duration};
+
+  use memberlist_core::{
+    agnostic_lite::tokio::TokioRuntime,
+    transport::{
+      resolver::socket_addr::SocketAddrResolver, tests::UnimplementedTransport, Lpe, Node,
+    },
+    types::TinyVec,
+  };
+  use ruserf_types::{MemberStatus, UserEventMessage};
+  use smol_str::SmolStr;
+
+  use crate::{
+    clock::Timer,
+    coalesce::coalesced_event,
+    event::{MemberEvent, MemberEventType},
+    task::TaskRegistry,
+    types::Member,
+    DefaultDelegate,
+  };
+
+  use super::*;
+
+  type Transport = UnimplementedTransport<
+    SmolStr,
+    SocketAddrResolver<TokioRuntime>,
+    Lpe<SmolStr, SocketAddr>,
+    TokioRuntime,
+  >;
+
+  type Delegate = DefaultDelegate<Transport>;
+
+  fn member_event(ty: MemberEventType, id: &str) -> CrateEvent<Transport, Delegate> {
+    CrateEvent::from(MemberEvent {
+      ty,
+      members: TinyVec::from(Member::new(
+        Node::new(id.into(), "127.0.0.1:8080".parse().unwrap()),
+        Default::default(),
+        MemberStatus::None,
+      ))
+      .into(),
+    })
+  }
+
+  #[tokio::test]
+  async fn test_member_event_batch() {
+    let (tx, rx) = async_channel::unbounded();
+    let (_shutdown_tx, shutdown_rx) = async_channel::bounded(1);
+    let batcher = MemberEventBatcher::<Transport, Delegate>::new();
+
+    let in_ = coalesced_event(
+      tx,
+      shutdown_rx,
+      Timer::default(),
+      || (Duration::from_millis(20), Duration::from_millis(20)),
+      batcher,
+      &TaskRegistry::default(),
+    );
+
+    let send = [
+      member_event(MemberEventType::Join, "a"),
+      member_event(MemberEventType::Join, "b"),
+      member_event(MemberEventType::Join, "c"),
+      member_event(MemberEventType::Leave, "a"),
+      member_event(MemberEventType::Join, "a"),
+    ];
+    for event in send {
+      in_.send(event).await.unwrap();
+    }
+    // The other events pass through
+    in_
+      .send(CrateEvent::from(UserEventMessage::default()))
+      .await
+      .unwrap();
+
+    let mut received = Vec::new();
+    while received.len() < 4 {
+      received.push(
+        <TokioRuntime as memberlist_core::agnostic_lite::RuntimeLite>::timeout(
+          Duration::from_secs(1),
+          rx.recv(),
+        )
+        .await
+        .unwrap()
+        .unwrap(),
+      );
+    }
+
+    assert!(matches!(received[0], CrateEvent::User(_)));
+    let batches = received[1..]
+      .iter()
+      .map(|e| match e {
+        CrateEvent::Member(e) => (
+          e.ty,
+          e.members
+            .iter()
+            .map(|m| m.node.id().as_str())
+            .collect::<Vec<_>>(),
+        ),
+        _ => panic!("expected a member event"),
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(
+      batches,
+      [
+        (MemberEventType::Join, vec!["a", "b", "c"]),
+        (MemberEventType::Leave, vec!["a"]),
+        (MemberEventType::Join, vec!["a"]),
+      ]
+    );
+  }
+}
@@ -54,7 +54,8 @@ where
   }
 
   fn handle(&self, event: &CrateEvent<Self::Transport, Self::Delegate>) -> bool {
-    matches!(event, CrateEvent::Member(_))
+    // Flaps are reported alongside the join, coalescing them would hide the join
+    matches!(event, CrateEvent::Member(e) if e.ty != MemberEventType::Flap)
   }
 
   fn coalesce(&mut self, event: CrateEvent<Self::Transport, Self::Delegate>) {
@@ -132,8 +133,10 @@ mod tests {
   use smol_str::SmolStr;
 
   use crate::{
+    clock::Timer,
     coalesce::coalesced_event,
     event::{CrateEventType, MemberEvent},
+    task::TaskRegistry,
     DefaultDelegate,
   };
 
@@ -157,9 +160,10 @@ mod tests {
     let in_ = coalesced_event(
       tx,
       shutdown_rx,
-      Duration::from_millis(20),
-      Duration::from_millis(20),
+      Timer::default(),
+      || (Duration::from_millis(20), Duration::from_millis(20)),
       coalescer,
+      &TaskRegistry::default(),
     );
 
     let send = vec![
@@ -289,9 +293,10 @@ mod tests {
     let in_ = coalesced_event(
       tx,
       shutdown_rx,
-      Duration::from_millis(5),
-      Duration::from_millis(5),
+      Timer::default(),
+      || (Duration::from_millis(5), Duration::from_millis(5)),
       coalescer,
+      &TaskRegistry::default(),
     );
 
     in_
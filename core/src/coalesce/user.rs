@@ -108,6 +108,7 @@ mod tests {
 
   use crate::{
     event::{MemberEvent, MemberEventType},
+    task::TaskRegistry,
     DefaultDelegate,
   };
 
@@ -131,9 +132,10 @@ mod tests {
     let in_ = coalesced_event(
       tx,
       shutdown_rx,
-      Duration::from_millis(20),
-      Duration::from_millis(20),
+      Timer::default(),
+      || (Duration::from_millis(20), Duration::from_millis(20)),
       coalescer,
+      &TaskRegistry::default(),
     );
 
     let send = vec![
@@ -0,0 +1,383 @@
+use std::io;
+
+use memberlist_core::bytes::{BufMut, Bytes, BytesMut};
+
+/// Compresses the payloads of user events and queries, see
+/// [`Options::compressor`](crate::Options::compressor).
+///
+/// A compressed payload is prefixed with the [`id`](Compressor::id) of the
+/// compressor, so a node only inflates payloads produced by the same algorithm.
+#[auto_impl::auto_impl(Box, Arc)]
+pub trait Compressor: Send + Sync + 'static {
+  /// Returns the unique id of the compression algorithm.
+  fn id(&self) -> u8;
+
+  /// Compresses the given payload.
+  fn compress(&self, src: &[u8]) -> io::Result<Vec<u8>>;
+
+  /// Decompresses the given payload, failing if it inflates to more than
+  /// `limit` bytes, so a forged payload cannot exhaust the memory.
+  fn decompress(&self, src: &[u8], limit: usize) -> io::Result<Vec<u8>>;
+}
+
+/// Returns the error of a payload inflating to more than `limit` bytes.
+#[cfg(any(test, feature = "gzip", feature = "zstd", feature = "lz4"))]
+fn too_large(limit: usize) -> io::Error {
+  io::Error::new(
+    io::ErrorKind::InvalidData,
+    format!("decompressed payload exceeds {limit} bytes"),
+  )
+}
+
+/// Reads at most `limit` bytes from a decompressing reader.
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+fn read_limited(reader: impl io::Read, capacity: usize, limit: usize) -> io::Result<Vec<u8>> {
+  use std::io::Read;
+
+  let mut buf = Vec::with_capacity(capacity.min(limit));
+  reader
+    .take((limit as u64).saturating_add(1))
+    .read_to_end(&mut buf)?;
+  if buf.len() > limit {
+    return Err(too_large(limit));
+  }
+  Ok(buf)
+}
+
+/// Compresses the payload if it is at least `threshold` bytes and the
+/// compression actually saves space. Returns `None` if the payload should
+/// be sent as is.
+pub(crate) fn compress_payload(
+  compressor: &dyn Compressor,
+  threshold: usize,
+  payload: &[u8],
+) -> io::Result<Option<Bytes>> {
+  if payload.len() < threshold {
+    return Ok(None);
+  }
+
+  let compressed = compressor.compress(payload)?;
+  // + 1 for the compressor id
+  if compressed.len() + 1 >= payload.len() {
+    return Ok(None);
+  }
+
+  let mut buf = BytesMut::with_capacity(compressed.len() + 1);
+  buf.put_u8(compressor.id());
+  buf.put_slice(&compressed);
+  Ok(Some(buf.freeze()))
+}
+
+/// Decompresses a payload produced by [`compress_payload`], up to `limit` bytes.
+pub(crate) fn decompress_payload(
+  compressor: Option<&dyn Compressor>,
+  payload: &[u8],
+  limit: usize,
+) -> io::Result<Bytes> {
+  let Some((&id, compressed)) = payload.split_first() else {
+    return Err(io::Error::new(
+      io::ErrorKind::UnexpectedEof,
+      "empty compressed payload",
+    ));
+  };
+
+  match compressor {
+    Some(compressor) if compressor.id() == id => {
+      compressor.decompress(compressed, limit).map(Into::into)
+    }
+    _ => Err(io::Error::new(
+      io::ErrorKind::Unsupported,
+      format!("no compressor configured for the compression algorithm {id}"),
+    )),
+  }
+}
+
+/// Decompresses data written by the compressor with the given id, using
+/// the configured compressor if it matches, the built-in algorithm compiled
+/// in otherwise. Used for the data persisted by a previous configuration.
+pub(crate) fn decompress_with(
+  compressor: Option<&dyn Compressor>,
+  id: u8,
+  src: &[u8],
+  limit: usize,
+) -> io::Result<Vec<u8>> {
+  match compressor {
+    Some(compressor) if compressor.id() == id => compressor.decompress(src, limit),
+    _ => match id {
+      #[cfg(feature = "gzip")]
+      1 => Gzip::new().decompress(src, limit),
+      #[cfg(feature = "zstd")]
+      2 => Zstd::new().decompress(src, limit),
+      #[cfg(feature = "lz4")]
+      3 => Lz4.decompress(src, limit),
+      _ => Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("no compressor available for the compression algorithm {id}"),
+      )),
+    },
+  }
+}
+
+/// Replaces the message type of a push/pull state compressed with LZ4. The
+/// members speaking the first protocol reject it as an unknown message type.
+pub(crate) const COMPRESSED_PUSH_PULL_TAG: u8 = 252;
+
+/// Compresses an encoded push/pull state, message type included. Returns
+/// `None` if the compression saves no space.
+#[cfg(feature = "lz4")]
+pub(crate) fn compress_push_pull(state: &[u8]) -> Option<Bytes> {
+  let compressed = lz4_flex::compress_prepend_size(state);
+  // + 1 for the tag
+  if compressed.len() + 1 >= state.len() {
+    return None;
+  }
+
+  let mut buf = BytesMut::with_capacity(compressed.len() + 1);
+  buf.put_u8(COMPRESSED_PUSH_PULL_TAG);
+  buf.put_slice(&compressed);
+  Some(buf.freeze())
+}
+
+/// Decompresses a push/pull state produced by [`compress_push_pull`], the
+/// tag excluded.
+#[cfg(feature = "lz4")]
+pub(crate) fn decompress_push_pull(src: &[u8]) -> io::Result<Bytes> {
+  let (size, compressed) =
+    lz4_flex::block::uncompressed_size(src).map_err(crate::invalid_data_io_error)?;
+  // LZ4 inflates a block at most 255 times, so a larger size is forged and
+  // must not be allocated
+  if size > compressed.len().saturating_mul(255) {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidData,
+      format!("compressed push/pull state claims {size} bytes"),
+    ));
+  }
+
+  lz4_flex::block::decompress(compressed, size)
+    .map(Into::into)
+    .map_err(crate::invalid_data_io_error)
+}
+
+/// Gzip compression.
+#[cfg(feature = "gzip")]
+#[cfg_attr(docsrs, doc(cfg(feature = "gzip")))]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Gzip {
+  level: Option<u32>,
+}
+
+#[cfg(feature = "gzip")]
+impl Gzip {
+  /// Returns a gzip compressor with the default compression level.
+  #[inline]
+  pub const fn new() -> Self {
+    Self { level: None }
+  }
+
+  /// Returns a gzip compressor with the given compression level (0-9).
+  #[inline]
+  pub const fn with_level(level: u32) -> Self {
+    Self { level: Some(level) }
+  }
+}
+
+#[cfg(feature = "gzip")]
+impl Compressor for Gzip {
+  fn id(&self) -> u8 {
+    1
+  }
+
+  fn compress(&self, src: &[u8]) -> io::Result<Vec<u8>> {
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    let level = self.level.map(Compression::new).unwrap_or_default();
+    let mut encoder = GzEncoder::new(Vec::with_capacity(src.len()), level);
+    encoder.write_all(src)?;
+    encoder.finish()
+  }
+
+  fn decompress(&self, src: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+    read_limited(flate2::read::GzDecoder::new(src), src.len() * 2, limit)
+  }
+}
+
+/// Zstandard compression.
+#[cfg(feature = "zstd")]
+#[cfg_attr(docsrs, doc(cfg(feature = "zstd")))]
+#[derive(Debug, Clone, Copy)]
+pub struct Zstd {
+  level: i32,
+}
+
+#[cfg(feature = "zstd")]
+impl Default for Zstd {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(feature = "zstd")]
+impl Zstd {
+  /// Returns a zstd compressor with the default compression level.
+  #[inline]
+  pub const fn new() -> Self {
+    Self { level: 0 }
+  }
+
+  /// Returns a zstd compressor with the given compression level.
+  #[inline]
+  pub const fn with_level(level: i32) -> Self {
+    Self { level }
+  }
+}
+
+#[cfg(feature = "zstd")]
+impl Compressor for Zstd {
+  fn id(&self) -> u8 {
+    2
+  }
+
+  fn compress(&self, src: &[u8]) -> io::Result<Vec<u8>> {
+    zstd::bulk::compress(src, self.level)
+  }
+
+  fn decompress(&self, src: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+    read_limited(zstd::stream::Decoder::new(src)?, src.len() * 2, limit)
+  }
+}
+
+/// LZ4 compression.
+#[cfg(feature = "lz4")]
+#[cfg_attr(docsrs, doc(cfg(feature = "lz4")))]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Lz4;
+
+#[cfg(feature = "lz4")]
+impl Compressor for Lz4 {
+  fn id(&self) -> u8 {
+    3
+  }
+
+  fn compress(&self, src: &[u8]) -> io::Result<Vec<u8>> {
+    Ok(lz4_flex::compress_prepend_size(src))
+  }
+
+  fn decompress(&self, src: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+    let (size, compressed) =
+      lz4_flex::block::uncompressed_size(src).map_err(crate::invalid_data_io_error)?;
+    // The size is prepended by the sender, check it before allocating
+    if size > limit {
+      return Err(too_large(limit));
+    }
+    lz4_flex::block::decompress(compressed, size).map_err(crate::invalid_data_io_error)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  struct Identity;
+
+  impl Compressor for Identity {
+    fn id(&self) -> u8 {
+      42
+    }
+
+    fn compress(&self, src: &[u8]) -> io::Result<Vec<u8>> {
+      Ok(src[..src.len() / 2].to_vec())
+    }
+
+    fn decompress(&self, src: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+      if src.len() * 2 > limit {
+        return Err(too_large(limit));
+      }
+      Ok([src, src].concat())
+    }
+  }
+
+  #[test]
+  fn test_compress_payload_roundtrip() {
+    let payload = [7u8; 64];
+    assert!(compress_payload(&Identity, 128, &payload)
+      .unwrap()
+      .is_none());
+
+    let compressed = compress_payload(&Identity, 16, &payload).unwrap().unwrap();
+    assert_eq!(compressed[0], 42);
+    assert_eq!(compressed.len(), 33);
+
+    let decompressed = decompress_payload(Some(&Identity), &compressed, 64).unwrap();
+    assert_eq!(decompressed.as_ref(), payload.as_slice());
+
+    // a node without the same compressor cannot inflate the payload
+    assert!(decompress_payload(None, &compressed, 64).is_err());
+    // nor can it inflate the payload past the limit
+    assert!(decompress_payload(Some(&Identity), &compressed, 63).is_err());
+  }
+
+  #[test]
+  fn test_decompress_with() {
+    let compressed = Identity.compress(&[7u8; 64]).unwrap();
+    assert_eq!(
+      decompress_with(Some(&Identity), 42, &compressed, usize::MAX).unwrap(),
+      [7u8; 64]
+    );
+    assert!(decompress_with(None, 42, &compressed, usize::MAX).is_err());
+
+    #[cfg(feature = "lz4")]
+    {
+      // The built-in algorithms do not need to be configured
+      let compressed = Lz4.compress(&[7u8; 64]).unwrap();
+      assert_eq!(
+        decompress_with(Some(&Identity), 3, &compressed, usize::MAX).unwrap(),
+        [7u8; 64]
+      );
+    }
+  }
+
+  #[cfg(feature = "lz4")]
+  #[test]
+  fn test_compress_push_pull() {
+    let state = [vec![2u8], b"ruserf".repeat(64)].concat();
+    let compressed = compress_push_pull(&state).unwrap();
+    assert_eq!(compressed[0], COMPRESSED_PUSH_PULL_TAG);
+    assert!(compressed.len() < state.len());
+    assert_eq!(
+      decompress_push_pull(&compressed[1..]).unwrap().as_ref(),
+      state.as_slice()
+    );
+
+    // incompressible states are sent as is
+    assert!(compress_push_pull(&[2, 0, 1]).is_none());
+    assert!(decompress_push_pull(&[0xff; 8]).is_err());
+  }
+
+  #[cfg(feature = "gzip")]
+  #[test]
+  fn test_gzip() {
+    let payload = b"ruserf".repeat(64);
+    let compressed = Gzip::new().compress(&payload).unwrap();
+    assert_eq!(Gzip::new().decompress(&compressed, 384).unwrap(), payload);
+    assert!(Gzip::new().decompress(&compressed, 383).is_err());
+  }
+
+  #[cfg(feature = "zstd")]
+  #[test]
+  fn test_zstd() {
+    let payload = b"ruserf".repeat(64);
+    let compressed = Zstd::new().compress(&payload).unwrap();
+    assert_eq!(Zstd::new().decompress(&compressed, 384).unwrap(), payload);
+    assert!(Zstd::new().decompress(&compressed, 383).is_err());
+  }
+
+  #[cfg(feature = "lz4")]
+  #[test]
+  fn test_lz4() {
+    let payload = b"ruserf".repeat(64);
+    let compressed = Lz4.compress(&payload).unwrap();
+    assert_eq!(Lz4.decompress(&compressed, 384).unwrap(), payload);
+    assert!(Lz4.decompress(&compressed, 383).is_err());
+  }
+}
@@ -0,0 +1,170 @@
+use std::{
+  any::Any,
+  sync::Arc,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use memberlist_core::types::NodeState;
+use smol_str::{format_smolstr, SmolStr};
+
+use crate::types::Tags;
+
+/// The reserved tag carrying the start time of a member, in milliseconds
+/// since the Unix epoch, only advertised when the age of the members
+/// settles the name conflicts.
+pub(crate) const STARTED_TAG: &str = "_ruserf_start";
+
+type HandlerFn<I, A> = dyn Fn(&NodeState<I, A>, &NodeState<I, A>) -> bool + Send + Sync;
+
+/// Decides whether the local node stays in the cluster after losing a name
+/// conflict vote, see [`ConflictResolution::Custom`].
+///
+/// The handler receives the state of the local node first and the state of
+/// the conflicting node second, and returns `true` to keep the local node
+/// running. The id and address types must be the ones of the transport,
+/// otherwise the handler is ignored and the node shuts down.
+#[derive(Clone)]
+pub struct ConflictHandler(Arc<dyn Any + Send + Sync>);
+
+impl ConflictHandler {
+  /// Returns a new handler from the given function.
+  pub fn new<I, A, F>(f: F) -> Self
+  where
+    I: 'static,
+    A: 'static,
+    F: Fn(&NodeState<I, A>, &NodeState<I, A>) -> bool + Send + Sync + 'static,
+  {
+    let f: Arc<HandlerFn<I, A>> = Arc::new(f);
+    Self(Arc::new(f))
+  }
+
+  /// Returns `None` if the handler was created for other id or address types.
+  pub(crate) fn call<I: 'static, A: 'static>(
+    &self,
+    local: &NodeState<I, A>,
+    other: &NodeState<I, A>,
+  ) -> Option<bool> {
+    self
+      .0
+      .downcast_ref::<Arc<HandlerFn<I, A>>>()
+      .map(|f| f(local, other))
+  }
+}
+
+impl core::fmt::Debug for ConflictHandler {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.write_str("ConflictHandler")
+  }
+}
+
+/// What the local node does when it loses the vote of a name conflict, see
+/// [`Options::conflict_resolution`](crate::Options::conflict_resolution).
+///
+/// The winner of the vote always stays, so the conflict outlives the vote
+/// whenever the loser stays as well.
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ConflictResolution {
+  /// The node shuts down.
+  #[default]
+  Shutdown,
+  /// The node stays if it started before the conflicting node.
+  ///
+  /// The nodes advertise their start time in their tags, a node whose
+  /// conflicting node does not advertise it shuts down.
+  KeepOldest,
+  /// The node stays if it started after the conflicting node.
+  ///
+  /// The nodes advertise their start time in their tags, a node whose
+  /// conflicting node does not advertise it shuts down.
+  KeepNewest,
+  /// The node stays if the handler returns `true`.
+  #[cfg_attr(feature = "serde", serde(skip))]
+  Custom(ConflictHandler),
+}
+
+impl ConflictResolution {
+  /// Returns the string representation of the conflict resolution
+  #[inline]
+  pub const fn as_str(&self) -> &'static str {
+    match self {
+      Self::Shutdown => "shutdown",
+      Self::KeepOldest => "keep_oldest",
+      Self::KeepNewest => "keep_newest",
+      Self::Custom(_) => "custom",
+    }
+  }
+
+  /// Returns the start time the local node advertises, if the resolution
+  /// compares the ages of the nodes.
+  pub(crate) fn started(&self) -> Option<u64> {
+    match self {
+      Self::KeepOldest | Self::KeepNewest => Some(
+        SystemTime::now()
+          .duration_since(UNIX_EPOCH)
+          .map(|d| d.as_millis() as u64)
+          .unwrap_or_default(),
+      ),
+      Self::Shutdown | Self::Custom(_) => None,
+    }
+  }
+
+  /// Returns whether the node which lost the vote stays, given the start
+  /// times of the local and the conflicting nodes.
+  pub(crate) fn keep_by_age(&self, local: Option<u64>, other: Option<u64>) -> bool {
+    match (self, local, other) {
+      (Self::KeepOldest, Some(local), Some(other)) => local < other,
+      (Self::KeepNewest, Some(local), Some(other)) => local > other,
+      _ => false,
+    }
+  }
+}
+
+impl core::fmt::Display for ConflictResolution {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+/// Adds the start time to the tags advertised in the meta of the local node.
+pub(crate) fn advertise(tags: &mut Tags, started: Option<u64>) {
+  if let Some(started) = started {
+    tags.insert(SmolStr::new(STARTED_TAG), format_smolstr!("{started}"));
+  }
+}
+
+/// Removes the start time from the decoded tags of a member, and returns it.
+pub(crate) fn split(tags: &mut Tags) -> Option<u64> {
+  tags.shift_remove(STARTED_TAG).and_then(|v| v.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_conflict_keep_by_age() {
+    let oldest = ConflictResolution::KeepOldest;
+    assert!(oldest.keep_by_age(Some(1), Some(2)));
+    assert!(!oldest.keep_by_age(Some(2), Some(1)));
+    assert!(!oldest.keep_by_age(Some(1), Some(1)));
+    assert!(!oldest.keep_by_age(Some(1), None));
+
+    let newest = ConflictResolution::KeepNewest;
+    assert!(newest.keep_by_age(Some(2), Some(1)));
+    assert!(!newest.keep_by_age(Some(1), Some(2)));
+    assert!(!ConflictResolution::Shutdown.keep_by_age(Some(1), Some(2)));
+  }
+
+  #[test]
+  fn test_conflict_started_tag() {
+    let mut tags = Tags::default();
+    advertise(&mut tags, None);
+    assert!(tags.is_empty());
+
+    advertise(&mut tags, Some(42));
+    assert_eq!(split(&mut tags), Some(42));
+    assert!(tags.is_empty());
+  }
+}
@@ -3,15 +3,36 @@ use memberlist_core::{transport::Id, CheapClone};
 mod merge;
 pub use merge::*;
 
+mod outbound;
+pub use outbound::*;
+
 mod reconnect;
 pub use reconnect::*;
 
+mod shutdown;
+pub use shutdown::*;
+
+mod snapshot;
+pub use snapshot::*;
+
 mod transform;
 pub use transform::*;
 
+mod address;
+pub use address::*;
+
+mod health;
+pub use health::*;
+
 mod composite;
 pub use composite::*;
 
+#[cfg(any(test, feature = "test"))]
+mod dropper;
+#[cfg(any(test, feature = "test"))]
+#[cfg_attr(docsrs, doc(cfg(feature = "test")))]
+pub use dropper::*;
+
 /// [`Delegate`] is the trait that clients must implement if they want to hook
 /// into the gossip layer of [`Serf`](crate::Serf). All the methods must be thread-safe,
 /// as they can and generally will be called concurrently.
@@ -19,6 +40,10 @@ pub trait Delegate:
   MergeDelegate<Id = <Self as Delegate>::Id, Address = <Self as Delegate>::Address>
   + TransformDelegate<Id = <Self as Delegate>::Id, Address = <Self as Delegate>::Address>
   + ReconnectDelegate<Id = <Self as Delegate>::Id, Address = <Self as Delegate>::Address>
+  + ShutdownDelegate<Id = <Self as Delegate>::Id, Address = <Self as Delegate>::Address>
+  + OutboundDelegate<Id = <Self as Delegate>::Id, Address = <Self as Delegate>::Address>
+  + AddressDelegate<Id = <Self as Delegate>::Id, Address = <Self as Delegate>::Address>
+  + HealthDelegate<Id = <Self as Delegate>::Id, Address = <Self as Delegate>::Address>
 {
   /// The id type of the delegate
   type Id: Id;
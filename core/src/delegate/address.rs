@@ -0,0 +1,60 @@
+use memberlist_core::{
+  transport::{Id, Node},
+  CheapClone,
+};
+
+/// Implemented to rewrite the addresses of the nodes embedded in the serf
+/// messages, for the clusters spanning NATed networks where the raw socket
+/// addresses are meaningless to the peers.
+///
+/// The address of the origin of a query and the destination of a relayed
+/// query response are passed through [`outbound_address`](AddressDelegate::outbound_address)
+/// before they are encoded, e.g. to map a private address to a routable one
+/// or to a token, and through [`inbound_address`](AddressDelegate::inbound_address)
+/// once decoded, before anything is sent to them, e.g. to resolve the token.
+/// The push/pull messages only carry the ids of the members, and the addresses
+/// gossiped by memberlist itself are not rewritten.
+#[auto_impl::auto_impl(Box, Arc)]
+pub trait AddressDelegate: Send + Sync + 'static {
+  /// The id type of the delegate
+  type Id: Id;
+  /// The address type of the delegate
+  type Address: CheapClone + Send + Sync + 'static;
+
+  /// Returns the address of the node to embed in an outgoing message.
+  fn outbound_address(&self, node: &Node<Self::Id, Self::Address>) -> Self::Address {
+    node.address().cheap_clone()
+  }
+
+  /// Returns the address to use for the node decoded from an incoming message.
+  fn inbound_address(&self, node: &Node<Self::Id, Self::Address>) -> Self::Address {
+    node.address().cheap_clone()
+  }
+}
+
+/// Noop implementation of `AddressDelegate`.
+#[derive(Debug)]
+pub struct NoopAddressDelegate<I, A>(std::marker::PhantomData<(I, A)>);
+
+impl<I, A> Default for NoopAddressDelegate<I, A> {
+  fn default() -> Self {
+    Self(Default::default())
+  }
+}
+
+impl<I, A> Clone for NoopAddressDelegate<I, A> {
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+
+impl<I, A> Copy for NoopAddressDelegate<I, A> {}
+
+impl<I, A> AddressDelegate for NoopAddressDelegate<I, A>
+where
+  I: Id,
+  A: CheapClone + Send + Sync + 'static,
+{
+  type Id = I;
+  type Address = A;
+}
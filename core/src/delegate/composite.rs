@@ -7,12 +7,14 @@ use ruserf_types::MessageType;
 
 use crate::{
   coordinate::Coordinate,
-  types::{AsMessageRef, Filter, Member, SerfMessage, Tags},
+  types::{AsMessageRef, Filter, Member, SerfMessage, SerfMessageRef, Tags},
 };
 
 use super::{
-  DefaultMergeDelegate, Delegate, LpeTransfromDelegate, MergeDelegate, NoopReconnectDelegate,
-  ReconnectDelegate, TransformDelegate,
+  AddressDelegate, Decision, DefaultMergeDelegate, Delegate, HealthDelegate, LpeTransfromDelegate,
+  MergeDelegate, NoopAddressDelegate, NoopHealthDelegate, NoopOutboundDelegate,
+  NoopReconnectDelegate, NoopShutdownDelegate, OutboundDelegate, ReapDecision, ReapReason,
+  ReconnectDelegate, ShutdownDelegate, ShutdownPhase, TransformDelegate,
 };
 
 /// `CompositeDelegate` is a helpful struct to split the [`Delegate`] into multiple small delegates,
@@ -24,10 +26,18 @@ pub struct CompositeDelegate<
   M = DefaultMergeDelegate<I, A>,
   R = NoopReconnectDelegate<I, A>,
   T = LpeTransfromDelegate<I, A>,
+  S = NoopShutdownDelegate<I, A>,
+  O = NoopOutboundDelegate<I, A>,
+  N = NoopAddressDelegate<I, A>,
+  H = NoopHealthDelegate<I, A>,
 > {
   merge: M,
   reconnect: R,
   transform: T,
+  shutdown: S,
+  outbound: O,
+  address: N,
+  health: H,
   _m: std::marker::PhantomData<(I, A)>,
 }
 
@@ -44,57 +54,159 @@ impl<I, A> CompositeDelegate<I, A> {
       merge: Default::default(),
       reconnect: Default::default(),
       transform: Default::default(),
+      shutdown: Default::default(),
+      outbound: Default::default(),
+      address: Default::default(),
+      health: Default::default(),
       _m: std::marker::PhantomData,
     }
   }
 }
 
-impl<I, A, M, R, T> CompositeDelegate<I, A, M, R, T>
+impl<I, A, M, R, T, S, O, N, H> CompositeDelegate<I, A, M, R, T, S, O, N, H>
 where
   M: MergeDelegate<Id = I, Address = A>,
 {
   /// Set the [`MergeDelegate`] for the `CompositeDelegate`.
-  pub fn with_merge_delegate<NM>(self, merge: NM) -> CompositeDelegate<I, A, NM, R, T> {
+  pub fn with_merge_delegate<NM>(self, merge: NM) -> CompositeDelegate<I, A, NM, R, T, S, O, N, H> {
     CompositeDelegate {
       merge,
       reconnect: self.reconnect,
       transform: self.transform,
+      shutdown: self.shutdown,
+      outbound: self.outbound,
+      address: self.address,
+      health: self.health,
       _m: std::marker::PhantomData,
     }
   }
 }
 
-impl<I, A, M, R, T> CompositeDelegate<I, A, M, R, T> {
+impl<I, A, M, R, T, S, O, N, H> CompositeDelegate<I, A, M, R, T, S, O, N, H> {
   /// Set the [`ReconnectDelegate`] for the `CompositeDelegate`.
-  pub fn with_reconnect_delegate<NR>(self, reconnect: NR) -> CompositeDelegate<I, A, M, NR, T> {
+  pub fn with_reconnect_delegate<NR>(
+    self,
+    reconnect: NR,
+  ) -> CompositeDelegate<I, A, M, NR, T, S, O, N, H> {
     CompositeDelegate {
       reconnect,
       merge: self.merge,
       transform: self.transform,
+      shutdown: self.shutdown,
+      outbound: self.outbound,
+      address: self.address,
+      health: self.health,
       _m: std::marker::PhantomData,
     }
   }
 }
 
-impl<I, A, M, R, T> CompositeDelegate<I, A, M, R, T> {
+impl<I, A, M, R, T, S, O, N, H> CompositeDelegate<I, A, M, R, T, S, O, N, H> {
   /// Set the [`TransformDelegate`] for the `CompositeDelegate`.
-  pub fn with_transform_delegate<NT>(self, transform: NT) -> CompositeDelegate<I, A, M, R, NT> {
+  pub fn with_transform_delegate<NT>(
+    self,
+    transform: NT,
+  ) -> CompositeDelegate<I, A, M, R, NT, S, O, N, H> {
     CompositeDelegate {
       transform,
       merge: self.merge,
       reconnect: self.reconnect,
+      shutdown: self.shutdown,
+      outbound: self.outbound,
+      address: self.address,
+      health: self.health,
       _m: std::marker::PhantomData,
     }
   }
 }
 
-impl<I, A, M, R, T> MergeDelegate for CompositeDelegate<I, A, M, R, T>
+impl<I, A, M, R, T, S, O, N, H> CompositeDelegate<I, A, M, R, T, S, O, N, H> {
+  /// Set the [`ShutdownDelegate`] for the `CompositeDelegate`.
+  pub fn with_shutdown_delegate<NS>(
+    self,
+    shutdown: NS,
+  ) -> CompositeDelegate<I, A, M, R, T, NS, O, N, H> {
+    CompositeDelegate {
+      shutdown,
+      merge: self.merge,
+      reconnect: self.reconnect,
+      transform: self.transform,
+      outbound: self.outbound,
+      address: self.address,
+      health: self.health,
+      _m: std::marker::PhantomData,
+    }
+  }
+}
+
+impl<I, A, M, R, T, S, O, N, H> CompositeDelegate<I, A, M, R, T, S, O, N, H> {
+  /// Set the [`OutboundDelegate`] for the `CompositeDelegate`.
+  pub fn with_outbound_delegate<NO>(
+    self,
+    outbound: NO,
+  ) -> CompositeDelegate<I, A, M, R, T, S, NO, N, H> {
+    CompositeDelegate {
+      outbound,
+      merge: self.merge,
+      reconnect: self.reconnect,
+      transform: self.transform,
+      shutdown: self.shutdown,
+      address: self.address,
+      health: self.health,
+      _m: std::marker::PhantomData,
+    }
+  }
+}
+
+impl<I, A, M, R, T, S, O, N, H> CompositeDelegate<I, A, M, R, T, S, O, N, H> {
+  /// Set the [`AddressDelegate`] for the `CompositeDelegate`.
+  pub fn with_address_delegate<NN>(
+    self,
+    address: NN,
+  ) -> CompositeDelegate<I, A, M, R, T, S, O, NN, H> {
+    CompositeDelegate {
+      address,
+      merge: self.merge,
+      reconnect: self.reconnect,
+      transform: self.transform,
+      shutdown: self.shutdown,
+      outbound: self.outbound,
+      health: self.health,
+      _m: std::marker::PhantomData,
+    }
+  }
+}
+
+impl<I, A, M, R, T, S, O, N, H> CompositeDelegate<I, A, M, R, T, S, O, N, H> {
+  /// Set the [`HealthDelegate`] for the `CompositeDelegate`.
+  pub fn with_health_delegate<NH>(
+    self,
+    health: NH,
+  ) -> CompositeDelegate<I, A, M, R, T, S, O, N, NH> {
+    CompositeDelegate {
+      health,
+      merge: self.merge,
+      reconnect: self.reconnect,
+      transform: self.transform,
+      shutdown: self.shutdown,
+      outbound: self.outbound,
+      address: self.address,
+      _m: std::marker::PhantomData,
+    }
+  }
+}
+
+impl<I, A, M, R, T, S, O, N, H> MergeDelegate for CompositeDelegate<I, A, M, R, T, S, O, N, H>
 where
   I: Id,
   A: CheapClone + Send + Sync + 'static,
   M: MergeDelegate<Id = I, Address = A>,
   R: Send + Sync + 'static,
   T: Send + Sync + 'static,
+  S: Send + Sync + 'static,
+  O: Send + Sync + 'static,
+  N: Send + Sync + 'static,
+  H: Send + Sync + 'static,
 {
   type Error = M::Error;
 
@@ -110,13 +222,17 @@ where
   }
 }
 
-impl<I, A, M, R, T> ReconnectDelegate for CompositeDelegate<I, A, M, R, T>
+impl<I, A, M, R, T, S, O, N, H> ReconnectDelegate for CompositeDelegate<I, A, M, R, T, S, O, N, H>
 where
   I: Id,
   A: CheapClone + Send + Sync + 'static,
   M: Send + Sync + 'static,
   R: ReconnectDelegate<Id = I, Address = A>,
   T: Send + Sync + 'static,
+  S: Send + Sync + 'static,
+  O: Send + Sync + 'static,
+  N: Send + Sync + 'static,
+  H: Send + Sync + 'static,
 {
   type Id = R::Id;
 
@@ -129,15 +245,34 @@ where
   ) -> std::time::Duration {
     self.reconnect.reconnect_timeout(member, timeout)
   }
+
+  fn reconnect_address(
+    &self,
+    member: &Member<Self::Id, Self::Address>,
+  ) -> impl std::future::Future<Output = Option<Self::Address>> + Send {
+    self.reconnect.reconnect_address(member)
+  }
+
+  fn on_reap(
+    &self,
+    member: &Member<Self::Id, Self::Address>,
+    reason: ReapReason,
+  ) -> impl std::future::Future<Output = ReapDecision> + Send {
+    self.reconnect.on_reap(member, reason)
+  }
 }
 
-impl<I, A, M, R, T> TransformDelegate for CompositeDelegate<I, A, M, R, T>
+impl<I, A, M, R, T, S, O, N, H> TransformDelegate for CompositeDelegate<I, A, M, R, T, S, O, N, H>
 where
   I: Id,
   A: CheapClone + Send + Sync + 'static,
   M: Send + Sync + 'static,
   R: Send + Sync + 'static,
   T: TransformDelegate<Id = I, Address = A>,
+  S: Send + Sync + 'static,
+  O: Send + Sync + 'static,
+  N: Send + Sync + 'static,
+  H: Send + Sync + 'static,
 {
   type Error = T::Error;
 
@@ -196,6 +331,18 @@ where
     T::decode_address(bytes)
   }
 
+  fn address_ip(address: &Self::Address) -> Option<std::net::IpAddr> {
+    T::address_ip(address)
+  }
+
+  fn address_scope_id(address: &Self::Address) -> Option<u32> {
+    T::address_scope_id(address)
+  }
+
+  fn with_address_scope_id(address: &Self::Address, scope_id: u32) -> Option<Self::Address> {
+    T::with_address_scope_id(address, scope_id)
+  }
+
   fn coordinate_encoded_len(coordinate: &Coordinate) -> usize {
     T::coordinate_encoded_len(coordinate)
   }
@@ -237,15 +384,117 @@ where
   ) -> Result<(usize, SerfMessage<Self::Id, Self::Address>), Self::Error> {
     T::decode_message(ty, bytes)
   }
+
+  fn decode_message_from_bytes(
+    ty: MessageType,
+    bytes: &memberlist_core::bytes::Bytes,
+  ) -> Result<(usize, SerfMessage<Self::Id, Self::Address>), Self::Error> {
+    T::decode_message_from_bytes(ty, bytes)
+  }
+}
+
+impl<I, A, M, R, T, S, O, N, H> ShutdownDelegate for CompositeDelegate<I, A, M, R, T, S, O, N, H>
+where
+  I: Id,
+  A: CheapClone + Send + Sync + 'static,
+  M: Send + Sync + 'static,
+  R: Send + Sync + 'static,
+  T: Send + Sync + 'static,
+  S: ShutdownDelegate<Id = I, Address = A>,
+  O: Send + Sync + 'static,
+  N: Send + Sync + 'static,
+  H: Send + Sync + 'static,
+{
+  type Id = S::Id;
+
+  type Address = S::Address;
+
+  async fn on_shutdown(&self, phase: ShutdownPhase) {
+    self.shutdown.on_shutdown(phase).await
+  }
+}
+
+impl<I, A, M, R, T, S, O, N, H> OutboundDelegate for CompositeDelegate<I, A, M, R, T, S, O, N, H>
+where
+  I: Id,
+  A: CheapClone + Send + Sync + 'static,
+  M: Send + Sync + 'static,
+  R: Send + Sync + 'static,
+  T: Send + Sync + 'static,
+  S: Send + Sync + 'static,
+  O: OutboundDelegate<Id = I, Address = A>,
+  N: Send + Sync + 'static,
+  H: Send + Sync + 'static,
+{
+  type Id = O::Id;
+
+  type Address = O::Address;
+
+  fn before_broadcast(
+    &self,
+    msg: &SerfMessageRef<'_, Self::Id, Self::Address>,
+  ) -> Decision<Self::Id, Self::Address> {
+    self.outbound.before_broadcast(msg)
+  }
+}
+
+impl<I, A, M, R, T, S, O, N, H> AddressDelegate for CompositeDelegate<I, A, M, R, T, S, O, N, H>
+where
+  I: Id,
+  A: CheapClone + Send + Sync + 'static,
+  M: Send + Sync + 'static,
+  R: Send + Sync + 'static,
+  T: Send + Sync + 'static,
+  S: Send + Sync + 'static,
+  O: Send + Sync + 'static,
+  N: AddressDelegate<Id = I, Address = A>,
+  H: Send + Sync + 'static,
+{
+  type Id = N::Id;
+
+  type Address = N::Address;
+
+  fn outbound_address(&self, node: &Node<Self::Id, Self::Address>) -> Self::Address {
+    self.address.outbound_address(node)
+  }
+
+  fn inbound_address(&self, node: &Node<Self::Id, Self::Address>) -> Self::Address {
+    self.address.inbound_address(node)
+  }
+}
+
+impl<I, A, M, R, T, S, O, N, H> HealthDelegate for CompositeDelegate<I, A, M, R, T, S, O, N, H>
+where
+  I: Id,
+  A: CheapClone + Send + Sync + 'static,
+  M: Send + Sync + 'static,
+  R: Send + Sync + 'static,
+  T: Send + Sync + 'static,
+  S: Send + Sync + 'static,
+  O: Send + Sync + 'static,
+  N: Send + Sync + 'static,
+  H: HealthDelegate<Id = I, Address = A>,
+{
+  type Id = H::Id;
+
+  type Address = H::Address;
+
+  fn app_health(&self) -> Option<u8> {
+    self.health.app_health()
+  }
 }
 
-impl<I, A, M, R, T> Delegate for CompositeDelegate<I, A, M, R, T>
+impl<I, A, M, R, T, S, O, N, H> Delegate for CompositeDelegate<I, A, M, R, T, S, O, N, H>
 where
   I: Id,
   A: CheapClone + Send + Sync + 'static,
   M: MergeDelegate<Id = I, Address = A>,
   R: ReconnectDelegate<Id = I, Address = A>,
   T: TransformDelegate<Id = I, Address = A>,
+  S: ShutdownDelegate<Id = I, Address = A>,
+  O: OutboundDelegate<Id = I, Address = A>,
+  N: AddressDelegate<Id = I, Address = A>,
+  H: HealthDelegate<Id = I, Address = A>,
 {
   type Id = I;
 
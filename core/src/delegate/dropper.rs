@@ -0,0 +1,134 @@
+use std::{collections::HashMap, time::Duration};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::types::MessageType;
+
+/// Injects faults into the incoming gossip messages, used to simulate lossy
+/// networks in tests, see [`Options::message_dropper`](crate::Options::message_dropper).
+#[auto_impl::auto_impl(Box, Arc)]
+pub trait MessageDropper: Send + Sync + 'static {
+  /// Returns `true` if the incoming message of the given type should be dropped.
+  fn should_drop(&self, ty: MessageType) -> bool;
+
+  /// Returns how long the incoming message of the given type is held back
+  /// before being processed, the messages received meanwhile overtake it.
+  fn delay(&self, _ty: MessageType) -> Option<Duration> {
+    None
+  }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Faults {
+  drop: f64,
+  delay: Option<(Duration, Duration)>,
+  reorder: f64,
+  reorder_window: Duration,
+}
+
+/// A [`MessageDropper`] which drops, delays or reorders the messages of each
+/// [`MessageType`] with the configured probabilities.
+///
+/// The faults are drawn from a seeded generator, so the same sequence of
+/// incoming messages always suffers the same faults.
+#[derive(Debug)]
+pub struct LossyNetwork {
+  rng: parking_lot::Mutex<StdRng>,
+  faults: HashMap<MessageType, Faults>,
+}
+
+impl LossyNetwork {
+  /// Returns a network without faults, using the given seed.
+  pub fn new(seed: u64) -> Self {
+    Self {
+      rng: parking_lot::Mutex::new(StdRng::seed_from_u64(seed)),
+      faults: HashMap::new(),
+    }
+  }
+
+  /// Drops the messages of the given type with the given probability.
+  pub fn with_drop(mut self, ty: MessageType, probability: f64) -> Self {
+    self.faults.entry(ty).or_default().drop = probability.clamp(0.0, 1.0);
+    self
+  }
+
+  /// Delays the messages of the given type by a uniformly random duration
+  /// between `min` and `max`.
+  pub fn with_delay(mut self, ty: MessageType, min: Duration, max: Duration) -> Self {
+    self.faults.entry(ty).or_default().delay = Some((min.min(max), min.max(max)));
+    self
+  }
+
+  /// Holds back the messages of the given type by `window` with the given
+  /// probability, so the messages received within the window overtake them.
+  pub fn with_reorder(mut self, ty: MessageType, probability: f64, window: Duration) -> Self {
+    let faults = self.faults.entry(ty).or_default();
+    faults.reorder = probability.clamp(0.0, 1.0);
+    faults.reorder_window = window;
+    self
+  }
+}
+
+impl MessageDropper for LossyNetwork {
+  fn should_drop(&self, ty: MessageType) -> bool {
+    match self.faults.get(&ty) {
+      Some(faults) if faults.drop > 0.0 => self.rng.lock().gen_bool(faults.drop),
+      _ => false,
+    }
+  }
+
+  fn delay(&self, ty: MessageType) -> Option<Duration> {
+    let faults = self.faults.get(&ty)?;
+    let mut rng = self.rng.lock();
+    let mut delay = match faults.delay {
+      Some((min, max)) if min < max => rng.gen_range(min..=max),
+      Some((min, _)) => min,
+      None => Duration::ZERO,
+    };
+    if faults.reorder > 0.0 && rng.gen_bool(faults.reorder) {
+      delay += faults.reorder_window;
+    }
+    (!delay.is_zero()).then_some(delay)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_lossy_network_is_deterministic() {
+    let network = || {
+      LossyNetwork::new(42)
+        .with_drop(MessageType::UserEvent, 0.5)
+        .with_delay(
+          MessageType::Query,
+          Duration::from_millis(1),
+          Duration::from_millis(10),
+        )
+        .with_reorder(MessageType::Query, 0.5, Duration::from_millis(100))
+    };
+
+    let (a, b) = (network(), network());
+    for _ in 0..64 {
+      assert_eq!(
+        a.should_drop(MessageType::UserEvent),
+        b.should_drop(MessageType::UserEvent)
+      );
+      let delay = a.delay(MessageType::Query);
+      assert_eq!(delay, b.delay(MessageType::Query));
+      let delay = delay.unwrap();
+      assert!(delay >= Duration::from_millis(1) && delay <= Duration::from_millis(110));
+    }
+
+    // unconfigured message types are untouched
+    assert!(!a.should_drop(MessageType::Join));
+    assert!(a.delay(MessageType::Join).is_none());
+  }
+
+  #[test]
+  fn test_lossy_network_drop_all() {
+    let network = LossyNetwork::new(0).with_drop(MessageType::Leave, 1.0);
+    assert!((0..16).all(|_| network.should_drop(MessageType::Leave)));
+  }
+}
@@ -0,0 +1,53 @@
+use memberlist_core::{transport::Id, CheapClone};
+
+/// Implemented to report the application health of the local node to the
+/// members pinging it, on top of the liveness the failure detector already
+/// provides.
+///
+/// The health is a single application defined byte, e.g. `0` for ready and
+/// the other values for the reasons not to route any work to the node. It is
+/// carried in the payload of the ping acks, so no extra message is sent, and
+/// the peers surface the last one reported in
+/// [`Member::app_health`](crate::types::Member::app_health). It trails the
+/// coordinate of the ack, so no health is reported when
+/// [`Options::disable_coordinates`](crate::Options::disable_coordinates) is set.
+#[auto_impl::auto_impl(Box, Arc)]
+pub trait HealthDelegate: Send + Sync + 'static {
+  /// The id type of the delegate
+  type Id: Id;
+  /// The address type of the delegate
+  type Address: CheapClone + Send + Sync + 'static;
+
+  /// Returns the health reported in the next ping ack, `None` to report
+  /// none. Invoked on every ping received, so it must be cheap.
+  fn app_health(&self) -> Option<u8> {
+    None
+  }
+}
+
+/// Noop implementation of `HealthDelegate`.
+#[derive(Debug)]
+pub struct NoopHealthDelegate<I, A>(std::marker::PhantomData<(I, A)>);
+
+impl<I, A> Default for NoopHealthDelegate<I, A> {
+  fn default() -> Self {
+    Self(Default::default())
+  }
+}
+
+impl<I, A> Clone for NoopHealthDelegate<I, A> {
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+
+impl<I, A> Copy for NoopHealthDelegate<I, A> {}
+
+impl<I, A> HealthDelegate for NoopHealthDelegate<I, A>
+where
+  I: Id,
+  A: CheapClone + Send + Sync + 'static,
+{
+  type Id = I;
+  type Address = A;
+}
@@ -0,0 +1,66 @@
+use memberlist_core::{transport::Id, CheapClone};
+
+use crate::types::{SerfMessage, SerfMessageRef};
+
+/// What [`OutboundDelegate::before_broadcast`] decides to do with a message.
+#[derive(Debug, Clone)]
+pub enum Decision<I, A> {
+  /// Queues the message as is.
+  Keep,
+  /// Drops the message, it is never gossiped.
+  Drop,
+  /// Queues the given message instead.
+  Replace(Box<SerfMessage<I, A>>),
+}
+
+/// Implemented to veto or rewrite the messages originated by the local node
+/// before they are queued for gossip, e.g. to redact payloads or to add labels.
+///
+/// The messages relayed on behalf of other nodes are not passed to the
+/// delegate. The local node always handles the original message, and the size
+/// limits are checked against both the original and the replacement.
+#[auto_impl::auto_impl(Box, Arc)]
+pub trait OutboundDelegate: Send + Sync + 'static {
+  /// The id type of the delegate
+  type Id: Id;
+  /// The address type of the delegate
+  type Address: CheapClone + Send + Sync + 'static;
+
+  /// Invoked before a message is queued for gossip.
+  fn before_broadcast(
+    &self,
+    msg: &SerfMessageRef<'_, Self::Id, Self::Address>,
+  ) -> Decision<Self::Id, Self::Address>;
+}
+
+/// Noop implementation of `OutboundDelegate`.
+#[derive(Debug)]
+pub struct NoopOutboundDelegate<I, A>(std::marker::PhantomData<(I, A)>);
+
+impl<I, A> Default for NoopOutboundDelegate<I, A> {
+  fn default() -> Self {
+    Self(Default::default())
+  }
+}
+
+impl<I, A> Clone for NoopOutboundDelegate<I, A> {
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+
+impl<I, A> Copy for NoopOutboundDelegate<I, A> {}
+
+impl<I, A> OutboundDelegate for NoopOutboundDelegate<I, A>
+where
+  I: Id,
+  A: CheapClone + Send + Sync + 'static,
+{
+  type Id = I;
+  type Address = A;
+
+  #[inline]
+  fn before_broadcast(&self, _msg: &SerfMessageRef<'_, I, A>) -> Decision<I, A> {
+    Decision::Keep
+  }
+}
@@ -1,10 +1,51 @@
-use std::time::Duration;
+use std::{future::Future, time::Duration};
 
 use memberlist_core::{transport::Id, CheapClone};
 
 use crate::types::Member;
 
-/// Implemented to allow overriding the reconnect timeout for individual members.
+/// Why a member is about to be reaped, see [`ReconnectDelegate::on_reap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ReapReason {
+  /// The member failed and could not be reconnected to before the
+  /// reconnect timeout.
+  Failed,
+  /// The member gracefully left and its tombstone timed out.
+  Left,
+}
+
+impl ReapReason {
+  /// Returns the string representation of the reason.
+  #[inline]
+  pub const fn as_str(&self) -> &'static str {
+    match self {
+      Self::Failed => "failed",
+      Self::Left => "left",
+    }
+  }
+}
+
+impl core::fmt::Display for ReapReason {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+/// Whether a member is reaped, see [`ReconnectDelegate::on_reap`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReapDecision {
+  /// Erase the member now.
+  #[default]
+  Reap,
+  /// Keep the member until the next reap interval, at most
+  /// [`Options::max_reap_vetoes`](crate::Options::max_reap_vetoes) times.
+  Delay,
+}
+
+/// Implemented to allow overriding the reconnect timeout or the address of
+/// individual members.
 #[auto_impl::auto_impl(Box, Arc)]
 pub trait ReconnectDelegate: Send + Sync + 'static {
   /// The id type of the delegate
@@ -18,6 +59,31 @@ pub trait ReconnectDelegate: Send + Sync + 'static {
     member: &Member<Self::Id, Self::Address>,
     timeout: Duration,
   ) -> Duration;
+
+  /// Returns the address to dial when attempting to reconnect to the given
+  /// failed member, e.g. a fresh one looked up for a node whose IP was
+  /// reassigned by DHCP. `None` dials the last known address of the member.
+  fn reconnect_address(
+    &self,
+    _member: &Member<Self::Id, Self::Address>,
+  ) -> impl Future<Output = Option<Self::Address>> + Send {
+    async { None }
+  }
+
+  /// Invoked before the member is erased for good, e.g. to archive the state
+  /// the application keeps about it.
+  ///
+  /// Returning [`ReapDecision::Delay`] keeps a member still referenced by the
+  /// application until the next reap interval. Once it has been delayed
+  /// [`Options::max_reap_vetoes`](crate::Options::max_reap_vetoes) times, the
+  /// member is reaped whatever the decision.
+  fn on_reap(
+    &self,
+    _member: &Member<Self::Id, Self::Address>,
+    _reason: ReapReason,
+  ) -> impl Future<Output = ReapDecision> + Send {
+    async { ReapDecision::Reap }
+  }
 }
 
 /// Noop implementation of `ReconnectDelegate`.
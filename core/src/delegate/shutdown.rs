@@ -0,0 +1,87 @@
+use std::future::Future;
+
+use memberlist_core::{transport::Id, CheapClone};
+
+/// The well-defined points of the leave/shutdown sequence at which
+/// [`ShutdownDelegate::on_shutdown`] is invoked.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum ShutdownPhase {
+  /// Invoked by [`Serf::leave`](crate::Serf::leave) right before the leave
+  /// intent is broadcast to the cluster.
+  BeforeLeaveBroadcast,
+  /// Invoked by [`Serf::leave`](crate::Serf::leave) once the leave has either
+  /// been confirmed by the memberlist or the broadcast timeout has elapsed.
+  LeaveConfirmed,
+  /// Invoked by [`Serf::shutdown`](crate::Serf::shutdown) right after the memberlist
+  /// and its associated network resources have been shut down.
+  MemberlistShutdown,
+  /// Invoked by [`Serf::shutdown`](crate::Serf::shutdown) after the snapshotter has
+  /// flushed its pending records to disk. If no snapshot is configured, this is
+  /// invoked right after [`ShutdownPhase::MemberlistShutdown`].
+  SnapshotFlushed,
+}
+
+impl ShutdownPhase {
+  /// Returns the string representation of the shutdown phase
+  #[inline]
+  pub const fn as_str(&self) -> &'static str {
+    match self {
+      Self::BeforeLeaveBroadcast => "before-leave-broadcast",
+      Self::LeaveConfirmed => "leave-confirmed",
+      Self::MemberlistShutdown => "memberlist-shutdown",
+      Self::SnapshotFlushed => "snapshot-flushed",
+    }
+  }
+}
+
+impl core::fmt::Display for ShutdownPhase {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+/// Implemented to allow embedding applications to order their own teardown
+/// relative to the gossip teardown.
+///
+/// The returned future is awaited before [`Serf`](crate::Serf) moves on to the
+/// next step, so long running work here delays the leave/shutdown.
+#[auto_impl::auto_impl(Box, Arc)]
+pub trait ShutdownDelegate: Send + Sync + 'static {
+  /// The id type of the delegate
+  type Id: Id;
+  /// The address type of the delegate
+  type Address: CheapClone + Send + Sync + 'static;
+
+  /// Invoked when the leave/shutdown sequence reaches the given phase.
+  fn on_shutdown(&self, phase: ShutdownPhase) -> impl Future<Output = ()> + Send;
+}
+
+/// Noop implementation of `ShutdownDelegate`.
+#[derive(Debug)]
+pub struct NoopShutdownDelegate<I, A>(std::marker::PhantomData<(I, A)>);
+
+impl<I, A> Default for NoopShutdownDelegate<I, A> {
+  fn default() -> Self {
+    Self(Default::default())
+  }
+}
+
+impl<I, A> Clone for NoopShutdownDelegate<I, A> {
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+
+impl<I, A> Copy for NoopShutdownDelegate<I, A> {}
+
+impl<I, A> ShutdownDelegate for NoopShutdownDelegate<I, A>
+where
+  I: Id,
+  A: CheapClone + Send + Sync + 'static,
+{
+  type Id = I;
+  type Address = A;
+
+  async fn on_shutdown(&self, _phase: ShutdownPhase) {}
+}
@@ -0,0 +1,60 @@
+use crate::{types::LamportTime, SnapshotError};
+
+/// The progress of a snapshot compaction, see
+/// [`SnapshotDelegate::on_snapshot_compact`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub enum SnapshotCompaction {
+  /// The snapshot is about to be rewritten.
+  Started,
+  /// The snapshot was rewritten, with its new size in bytes.
+  Finished(u64),
+  /// The snapshot could not be rewritten, the error is reported to
+  /// [`SnapshotDelegate::on_snapshot_error`].
+  Failed,
+}
+
+/// The state restored from the snapshot when [`Serf`](crate::Serf) starts,
+/// see [`SnapshotDelegate::on_snapshot_replay_complete`].
+#[viewit::viewit(
+  vis_all = "pub(crate)",
+  getters(vis_all = "pub", style = "move"),
+  setters(skip)
+)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct SnapshotReplay {
+  /// The number of members alive when the snapshot was last written.
+  #[viewit(getter(
+    const,
+    attrs(doc = "Returns the number of the restored alive members.")
+  ))]
+  alive_nodes: usize,
+  /// The member clock restored from the snapshot.
+  #[viewit(getter(const, attrs(doc = "Returns the restored member clock.")))]
+  clock: LamportTime,
+  /// The user event clock restored from the snapshot.
+  #[viewit(getter(const, attrs(doc = "Returns the restored user event clock.")))]
+  event_clock: LamportTime,
+  /// The query clock restored from the snapshot.
+  #[viewit(getter(const, attrs(doc = "Returns the restored query clock.")))]
+  query_clock: LamportTime,
+}
+
+/// Notified of the lifecycle of the snapshot, see
+/// [`Options::snapshot_delegate`](crate::Options::snapshot_delegate), e.g. to
+/// pause heavy work during the compactions or to alert on the write failures.
+///
+/// The hooks are invoked by the snapshotter itself, so they must not block.
+#[auto_impl::auto_impl(Box, Arc)]
+pub trait SnapshotDelegate: Send + Sync + 'static {
+  /// Invoked when a compaction starts and when it is over.
+  fn on_snapshot_compact(&self, _compaction: SnapshotCompaction) {}
+
+  /// Invoked once the snapshot has been replayed, before the node rejoins
+  /// the restored members.
+  fn on_snapshot_replay_complete(&self, _replay: &SnapshotReplay) {}
+
+  /// Invoked when the snapshot cannot be replayed, written, flushed or
+  /// compacted.
+  fn on_snapshot_error(&self, _err: &SnapshotError) {}
+}
@@ -1,3 +1,5 @@
+use std::net::{IpAddr, SocketAddr};
+
 use memberlist_core::{
   bytes::Bytes,
   transport::{Id, Node, Transformable},
@@ -11,7 +13,7 @@ use ruserf_types::{
 
 use crate::{
   coordinate::{Coordinate, CoordinateTransformError},
-  types::{AsMessageRef, Filter, SerfMessage, Tags, UnknownMessageType},
+  types::{scope, AsMessageRef, Filter, SerfMessage, Tags, UnknownMessageType},
 };
 
 /// A delegate for encoding and decoding.
@@ -61,6 +63,27 @@ pub trait TransformDelegate: Send + Sync + 'static {
   /// Decodes the address from the given bytes, returning the number of bytes consumed and the address.
   fn decode_address(bytes: &[u8]) -> Result<(usize, Self::Address), Self::Error>;
 
+  /// Returns the IP of the address, if it has one.
+  ///
+  /// Used to apply the IPv6 scope (zone) ids and to rank the addresses, see
+  /// [`Options::link_local_scope_id`](crate::Options::link_local_scope_id) and
+  /// [`Options::prefer_global_addresses`](crate::Options::prefer_global_addresses).
+  /// The addresses without an IP are left untouched.
+  fn address_ip(_address: &Self::Address) -> Option<IpAddr> {
+    None
+  }
+
+  /// Returns the non-zero IPv6 scope (zone) id of the address, if any.
+  fn address_scope_id(_address: &Self::Address) -> Option<u32> {
+    None
+  }
+
+  /// Returns the address with its IPv6 scope (zone) id replaced by `scope_id`,
+  /// or `None` if the address cannot carry one.
+  fn with_address_scope_id(_address: &Self::Address, _scope_id: u32) -> Option<Self::Address> {
+    None
+  }
+
   /// Encoded length of the coordinate.
   fn coordinate_encoded_len(coordinate: &Coordinate) -> usize;
 
@@ -100,6 +123,22 @@ pub trait TransformDelegate: Send + Sync + 'static {
     ty: MessageType,
     bytes: impl AsRef<[u8]>,
   ) -> Result<(usize, SerfMessage<Self::Id, Self::Address>), Self::Error>;
+
+  /// Decodes the message from the given [`Bytes`], returning the number of bytes consumed and the message.
+  ///
+  /// Unlike [`TransformDelegate::decode_message`], implementations are allowed to make the
+  /// payloads of the returned message share the memory of `bytes` instead of copying them, so
+  /// that the hot gossip path does not copy the payloads of the user events and the queries.
+  /// The message itself is still owned, and the fields which are not payloads, e.g. the ids
+  /// and the addresses, are decoded as usual.
+  ///
+  /// The default implementation falls back to [`TransformDelegate::decode_message`].
+  fn decode_message_from_bytes(
+    ty: MessageType,
+    bytes: &Bytes,
+  ) -> Result<(usize, SerfMessage<Self::Id, Self::Address>), Self::Error> {
+    Self::decode_message(ty, bytes)
+  }
 }
 
 /// The error type for the LPE transformation.
@@ -226,6 +265,28 @@ where
     Transformable::decode(bytes).map_err(Self::Error::Address)
   }
 
+  fn address_ip(address: &Self::Address) -> Option<IpAddr> {
+    scope::as_socket_addr(address).map(SocketAddr::ip)
+  }
+
+  fn address_scope_id(address: &Self::Address) -> Option<u32> {
+    match scope::as_socket_addr(address)? {
+      SocketAddr::V6(addr) if addr.scope_id() != 0 => Some(addr.scope_id()),
+      _ => None,
+    }
+  }
+
+  fn with_address_scope_id(address: &Self::Address, scope_id: u32) -> Option<Self::Address> {
+    match scope::as_socket_addr(address)? {
+      SocketAddr::V6(addr) => {
+        let mut addr = *addr;
+        addr.set_scope_id(scope_id);
+        scope::from_socket_addr(SocketAddr::V6(addr))
+      }
+      SocketAddr::V4(_) => None,
+    }
+  }
+
   fn coordinate_encoded_len(coordinate: &Coordinate) -> usize {
     Transformable::encoded_len(coordinate)
   }
@@ -301,4 +362,22 @@ where
       _ => unreachable!(),
     }
   }
+
+  fn decode_message_from_bytes(
+    ty: MessageType,
+    bytes: &Bytes,
+  ) -> Result<(usize, SerfMessage<Self::Id, Self::Address>), Self::Error> {
+    match ty {
+      MessageType::UserEvent => UserEventMessage::decode_from_bytes(bytes)
+        .map(|(n, m)| (n, SerfMessage::UserEvent(m)))
+        .map_err(|e| Self::Error::Message(e.into())),
+      MessageType::Query => QueryMessage::decode_from_bytes(bytes)
+        .map(|(n, m)| (n, SerfMessage::Query(m)))
+        .map_err(|e| Self::Error::Message(e.into())),
+      MessageType::QueryResponse => QueryResponseMessage::decode_from_bytes(bytes)
+        .map(|(n, m)| (n, SerfMessage::QueryResponse(m)))
+        .map_err(|e| Self::Error::Message(e.into())),
+      ty => Self::decode_message(ty, bytes),
+    }
+  }
 }
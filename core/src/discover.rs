@@ -0,0 +1,337 @@
+use std::{
+  collections::HashMap,
+  fmt::Write as _,
+  net::{IpAddr, SocketAddr},
+  time::Duration,
+};
+
+use futures::FutureExt;
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use memberlist_core::{
+  agnostic_lite::RuntimeLite,
+  tracing,
+  transport::{AddressResolver, MaybeResolvedAddress, Node, Transport},
+  CheapClone,
+};
+use smol_str::SmolStr;
+
+use crate::{
+  delegate::{Delegate, TransformDelegate},
+  types::{scope, MemberStatus},
+  Serf,
+};
+
+/// The TXT property carrying the encoded id of the node.
+const ID_PROPERTY: &str = "id";
+
+/// Errors returned by [`Serf::discover`].
+#[derive(Debug, thiserror::Error)]
+pub enum DiscoverError {
+  /// Returned when the mDNS daemon fails.
+  #[error("ruserf: mdns: {0}")]
+  Mdns(#[from] mdns_sd::Error),
+  /// Returned when the transport does not use socket addresses.
+  #[error("ruserf: mdns discovery requires a transport with socket addresses")]
+  UnsupportedAddress,
+  /// Returned when the local id cannot be encoded.
+  #[error("ruserf: failed to encode the local id: {0}")]
+  Id(String),
+}
+
+/// Configures the mDNS discovery, see [`Serf::discover`].
+///
+/// The local node is advertised as an instance of `_<service>._udp.local.`,
+/// and the instances found by browsing the same service are joined. Nodes
+/// which could not be joined are retried every `retry_interval` until they
+/// show up in the members or disappear from mDNS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MdnsDiscovery {
+  service: SmolStr,
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  retry_interval: Duration,
+}
+
+impl Default for MdnsDiscovery {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl MdnsDiscovery {
+  /// Returns a new configuration, advertising the `ruserf` service and
+  /// retrying the failed joins every 30 seconds.
+  #[inline]
+  pub const fn new() -> Self {
+    Self {
+      service: SmolStr::new_inline("ruserf"),
+      retry_interval: Duration::from_secs(30),
+    }
+  }
+
+  /// Sets the service name, usually the name of the cluster. mDNS limits it
+  /// to 15 characters.
+  #[inline]
+  pub fn with_service(mut self, service: impl Into<SmolStr>) -> Self {
+    self.service = service.into();
+    self
+  }
+
+  /// Sets how often the discovered nodes which failed to join are retried.
+  #[inline]
+  pub const fn with_retry_interval(mut self, interval: Duration) -> Self {
+    self.retry_interval = interval;
+    self
+  }
+
+  /// Returns the service name.
+  #[inline]
+  pub fn service(&self) -> &str {
+    &self.service
+  }
+
+  /// Returns how often the discovered nodes which failed to join are retried.
+  #[inline]
+  pub const fn retry_interval(&self) -> Duration {
+    self.retry_interval
+  }
+
+  /// Returns the fully qualified mDNS service type.
+  #[inline]
+  pub fn service_type(&self) -> String {
+    format!("_{}._udp.local.", self.service)
+  }
+}
+
+/// Advertises the local node over mDNS and joins the discovered nodes,
+/// returned by [`Serf::discover`].
+///
+/// The mDNS daemon runs on a dedicated thread. Dropping the handle
+/// unregisters the local node and stops the discovery, which also stops
+/// when [`Serf`] shuts down.
+pub struct Discovery {
+  daemon: ServiceDaemon,
+  fullname: String,
+  stop_tx: async_channel::Sender<()>,
+}
+
+impl Discovery {
+  /// Returns the fully qualified mDNS instance name of the local node.
+  #[inline]
+  pub fn fullname(&self) -> &str {
+    &self.fullname
+  }
+}
+
+impl Drop for Discovery {
+  fn drop(&mut self) {
+    self.stop_tx.close();
+    if let Err(e) = self.daemon.unregister(&self.fullname) {
+      tracing::debug!(err=%e, "ruserf: failed to unregister the mdns service");
+    }
+    if let Err(e) = self.daemon.shutdown() {
+      tracing::debug!(err=%e, "ruserf: failed to shutdown the mdns daemon");
+    }
+  }
+}
+
+impl<T, D> Serf<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  /// Advertises the local node over mDNS and joins the other nodes of the
+  /// same service, for LAN clusters without static seeds.
+  ///
+  /// Only available for the transports using [`SocketAddr`] addresses.
+  pub fn discover(&self, opts: MdnsDiscovery) -> Result<Discovery, DiscoverError> {
+    let node = self.advertise_node();
+    let addr = *scope::as_socket_addr(node.address()).ok_or(DiscoverError::UnsupportedAddress)?;
+    // Make sure the discovered addresses can be converted back
+    scope::from_socket_addr::<<T::Resolver as AddressResolver>::ResolvedAddress>(addr)
+      .ok_or(DiscoverError::UnsupportedAddress)?;
+
+    let id = node.id();
+    let mut encoded_id = vec![0; <D as TransformDelegate>::id_encoded_len(id)];
+    <D as TransformDelegate>::encode_id(id, &mut encoded_id)
+      .map_err(|e| DiscoverError::Id(e.to_string()))?;
+    let encoded_id = encode_hex(&encoded_id);
+
+    let daemon = ServiceDaemon::new()?;
+    let service_type = opts.service_type();
+    let instance = id.to_string();
+    let host = format!("{}.local.", instance.replace('.', "-"));
+    let properties = [(ID_PROPERTY, encoded_id.as_str())];
+    let info = if addr.ip().is_unspecified() {
+      ServiceInfo::new(
+        &service_type,
+        &instance,
+        &host,
+        "",
+        addr.port(),
+        &properties[..],
+      )?
+      .enable_addr_auto()
+    } else {
+      ServiceInfo::new(
+        &service_type,
+        &instance,
+        &host,
+        addr.ip(),
+        addr.port(),
+        &properties[..],
+      )?
+    };
+    let fullname = info.get_fullname().to_string();
+    daemon.register(info)?;
+    let discovered = daemon.browse(&service_type)?;
+
+    let (stop_tx, stop_rx) = async_channel::bounded(1);
+    let this = self.clone();
+    let retry_interval = opts.retry_interval;
+    <T::Runtime as RuntimeLite>::spawn_detach(async move {
+      let shutdown_rx = this.inner.shutdown_rx.clone();
+      // The nodes not joined yet, by mDNS instance name
+      let mut pending = HashMap::new();
+      loop {
+        futures::select! {
+          event = discovered.recv_async().fuse() => {
+            match event {
+              Ok(ServiceEvent::ServiceResolved(info)) => {
+                let Some(nodes) = this.discovered_nodes(&info) else {
+                  continue;
+                };
+                if nodes.first().is_some_and(|n| n.id().eq(this.local_id())) {
+                  continue;
+                }
+                pending.insert(info.get_fullname().to_string(), nodes);
+              }
+              Ok(ServiceEvent::ServiceRemoved(_, fullname)) => {
+                pending.remove(&fullname);
+                continue;
+              }
+              Ok(_) => continue,
+              Err(_) => break,
+            }
+          }
+          _ = this.inner.timer.sleep::<T::Runtime>(retry_interval).fuse() => {}
+          _ = stop_rx.recv().fuse() => break,
+          _ = shutdown_rx.recv().fuse() => break,
+        }
+
+        this.join_discovered(&mut pending).await;
+      }
+
+      tracing::debug!("ruserf: mdns discovery exits");
+    });
+
+    tracing::info!(service = %service_type, "ruserf: mdns discovery started");
+    Ok(Discovery {
+      daemon,
+      fullname,
+      stop_tx,
+    })
+  }
+
+  /// Returns the node behind a resolved mDNS instance, one per address.
+  fn discovered_nodes(
+    &self,
+    info: &ServiceInfo,
+  ) -> Option<Vec<Node<T::Id, MaybeResolvedAddress<T>>>> {
+    let id = decode_hex(info.get_property_val_str(ID_PROPERTY)?)?;
+    let id = match <D as TransformDelegate>::decode_id(&id) {
+      Ok((_, id)) => id,
+      Err(e) => {
+        tracing::warn!(err=%e, "ruserf: failed to decode the id of a discovered node");
+        return None;
+      }
+    };
+
+    let mut addrs = info
+      .get_addresses()
+      .iter()
+      .copied()
+      .collect::<Vec<IpAddr>>();
+    // Prefer the IPv4 addresses, the IPv6 ones are likely link-local
+    addrs.sort_by_key(|ip| ip.is_ipv6());
+    let nodes = addrs
+      .into_iter()
+      .filter_map(|ip| scope::from_socket_addr(SocketAddr::new(ip, info.get_port())))
+      .map(|addr| Node::new(id.cheap_clone(), MaybeResolvedAddress::Resolved(addr)))
+      .collect::<Vec<_>>();
+    (!nodes.is_empty()).then_some(nodes)
+  }
+
+  /// Joins the pending discovered nodes, keeping the ones which failed.
+  async fn join_discovered(
+    &self,
+    pending: &mut HashMap<String, Vec<Node<T::Id, MaybeResolvedAddress<T>>>>,
+  ) {
+    {
+      let members = self.inner.members.read().await;
+      pending.retain(|_, nodes| {
+        !members
+          .states
+          .get(nodes[0].id())
+          .is_some_and(|m| m.member.status == MemberStatus::Alive)
+      });
+    }
+
+    for nodes in pending.values() {
+      for node in nodes {
+        match self.join(node.cheap_clone(), true).await {
+          Ok(_) => {
+            tracing::info!("ruserf: joined discovered node {}", node);
+            break;
+          }
+          Err(e) => {
+            tracing::debug!(err=%e, "ruserf: failed to join discovered node {}", node);
+          }
+        }
+      }
+    }
+  }
+}
+
+fn encode_hex(src: &[u8]) -> String {
+  src
+    .iter()
+    .fold(String::with_capacity(src.len() * 2), |mut s, b| {
+      let _ = write!(s, "{b:02x}");
+      s
+    })
+}
+
+fn decode_hex(src: &str) -> Option<Vec<u8>> {
+  if src.len() % 2 != 0 {
+    return None;
+  }
+
+  (0..src.len())
+    .step_by(2)
+    .map(|i| u8::from_str_radix(src.get(i..i + 2)?, 16).ok())
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_hex() {
+    let src = [0u8, 1, 0xab, 0xff];
+    assert_eq!(encode_hex(&src), "0001abff");
+    assert_eq!(decode_hex("0001abff").unwrap(), src);
+    assert!(decode_hex("abc").is_none());
+    assert!(decode_hex("zz").is_none());
+  }
+
+  #[test]
+  fn test_service_type() {
+    assert_eq!(MdnsDiscovery::new().service_type(), "_ruserf._udp.local.");
+    assert_eq!(
+      MdnsDiscovery::new().with_service("prod").service_type(),
+      "_prod._udp.local."
+    );
+  }
+}
@@ -0,0 +1,193 @@
+use std::fmt::Display;
+
+use smol_str::SmolStr;
+
+use crate::types::{Member, MemberStatus};
+
+/// How the leader is picked among the eligible members, see [`ElectionOptions`].
+///
+/// Member ids are compared by their string representation, so every node
+/// agrees on the same leader once it has seen the same set of alive members.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ElectionStrategy {
+  /// The member with the lowest id is the leader.
+  #[default]
+  Lexicographic,
+  /// The member with the highest priority is the leader, ties are broken by
+  /// the highest id. The priority is read as an unsigned integer from the
+  /// given tag, members without a valid priority have priority `0`.
+  Bully {
+    /// The tag holding the priority of the member.
+    priority_tag: SmolStr,
+  },
+}
+
+impl ElectionStrategy {
+  /// Returns the string representation of the election strategy
+  #[inline]
+  pub const fn as_str(&self) -> &'static str {
+    match self {
+      Self::Lexicographic => "lexicographic",
+      Self::Bully { .. } => "bully",
+    }
+  }
+}
+
+impl core::fmt::Display for ElectionStrategy {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+/// Enables the leader election over the alive members, see
+/// [`Options::election`](crate::Options::election).
+///
+/// The leader is re-evaluated locally whenever the membership changes, no
+/// extra messages are exchanged. A [`LeaderChanged`](crate::event::Event::LeaderChanged)
+/// event is emitted when the outcome changes.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ElectionOptions {
+  strategy: ElectionStrategy,
+  tag: Option<(SmolStr, SmolStr)>,
+}
+
+impl ElectionOptions {
+  /// Returns a new election configuration using the given strategy.
+  #[inline]
+  pub const fn new(strategy: ElectionStrategy) -> Self {
+    Self {
+      strategy,
+      tag: None,
+    }
+  }
+
+  /// Only members which have the tag `key` set to `value` are eligible.
+  #[inline]
+  pub fn with_tag(mut self, key: impl Into<SmolStr>, value: impl Into<SmolStr>) -> Self {
+    self.tag = Some((key.into(), value.into()));
+    self
+  }
+
+  /// Returns the election strategy.
+  #[inline]
+  pub const fn strategy(&self) -> &ElectionStrategy {
+    &self.strategy
+  }
+
+  /// Returns the tag required for a member to be eligible, if any.
+  #[inline]
+  pub fn tag(&self) -> Option<(&SmolStr, &SmolStr)> {
+    self.tag.as_ref().map(|(k, v)| (k, v))
+  }
+
+  fn is_eligible<I, A>(&self, member: &Member<I, A>) -> bool {
+    if *member.status() != MemberStatus::Alive {
+      return false;
+    }
+
+    match &self.tag {
+      Some((key, value)) => member.tags().get(key) == Some(value),
+      None => true,
+    }
+  }
+
+  fn priority<I, A>(&self, member: &Member<I, A>) -> u64 {
+    match &self.strategy {
+      ElectionStrategy::Lexicographic => 0,
+      ElectionStrategy::Bully { priority_tag } => member
+        .tags()
+        .get(priority_tag)
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(0),
+    }
+  }
+}
+
+/// Returns the leader among the given members, or `None` if no member is eligible.
+pub(crate) fn elect<'a, I, A>(
+  opts: &ElectionOptions,
+  members: impl IntoIterator<Item = &'a Member<I, A>>,
+) -> Option<&'a Member<I, A>>
+where
+  I: Display + 'a,
+  A: 'a,
+{
+  members
+    .into_iter()
+    .filter(|m| opts.is_eligible(m))
+    .map(|m| (opts.priority(m), m.node().id().to_string(), m))
+    .reduce(|best, candidate| {
+      let better = match opts.strategy {
+        ElectionStrategy::Lexicographic => candidate.1 < best.1,
+        ElectionStrategy::Bully { .. } => (candidate.0, &candidate.1) > (best.0, &best.1),
+      };
+      if better {
+        candidate
+      } else {
+        best
+      }
+    })
+    .map(|(_, _, m)| m)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::SocketAddr;
+
+  use memberlist_core::types::Node;
+
+  use super::*;
+  use crate::types::Tags;
+
+  fn member(id: &str, status: MemberStatus, tags: &[(&str, &str)]) -> Member<SmolStr, SocketAddr> {
+    let tags = tags
+      .iter()
+      .map(|(k, v)| (SmolStr::new(k), SmolStr::new(v)))
+      .collect::<Tags>();
+    Member::new(
+      Node::new(id.into(), "127.0.0.1:7946".parse().unwrap()),
+      tags,
+      status,
+    )
+  }
+
+  #[test]
+  fn test_elect_lexicographic() {
+    let opts = ElectionOptions::new(ElectionStrategy::Lexicographic);
+    let members = [
+      member("b", MemberStatus::Alive, &[]),
+      member("a", MemberStatus::Failed, &[]),
+      member("c", MemberStatus::Alive, &[]),
+    ];
+    assert_eq!(elect(&opts, &members).unwrap().node().id(), "b");
+    assert!(elect(&opts, &members[1..2]).is_none());
+  }
+
+  #[test]
+  fn test_elect_bully() {
+    let opts = ElectionOptions::new(ElectionStrategy::Bully {
+      priority_tag: "priority".into(),
+    });
+    let members = [
+      member("a", MemberStatus::Alive, &[("priority", "5")]),
+      member("b", MemberStatus::Alive, &[("priority", "5")]),
+      member("c", MemberStatus::Alive, &[("priority", "bad")]),
+      member("d", MemberStatus::Leaving, &[("priority", "9")]),
+    ];
+    assert_eq!(elect(&opts, &members).unwrap().node().id(), "b");
+  }
+
+  #[test]
+  fn test_elect_tag_filter() {
+    let opts = ElectionOptions::new(ElectionStrategy::Lexicographic).with_tag("role", "server");
+    let members = [
+      member("a", MemberStatus::Alive, &[("role", "client")]),
+      member("b", MemberStatus::Alive, &[("role", "server")]),
+      member("c", MemberStatus::Alive, &[]),
+    ];
+    assert_eq!(elect(&opts, &members).unwrap().node().id(), "b");
+  }
+}
@@ -9,12 +9,103 @@ use smol_str::SmolStr;
 
 use crate::{
   delegate::{Delegate, MergeDelegate, TransformDelegate},
-  serf::{SerfDelegate, SerfState},
-  types::Member,
+  serf::{SerfDelegate, SerfState, ShutdownReport},
+  types::{Member, ProtocolVersion},
 };
 
 pub use crate::snapshot::SnapshotError;
 
+/// The stable classification of an error, see [`Error::kind`].
+///
+/// Unlike the error messages, the kinds and their [`as_str`](ErrorKind::as_str)
+/// codes do not change between the releases, so the callers can decide how
+/// to handle an error programmatically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+  /// A user event, query, response, tags or metadata exceeds a size limit.
+  TooLarge,
+  /// A deadline passed before the operation completed.
+  Timeout,
+  /// The operation is not allowed in the current state of the local node,
+  /// e.g. while the gossip is paused.
+  InvalidState,
+  /// The local node left the cluster or is shut down.
+  Shutdown,
+  /// The options of the local node are invalid.
+  Misconfigured,
+  /// The arguments of the operation are invalid.
+  InvalidInput,
+  /// The operation lost a race with another member, e.g. a lock is held by
+  /// another member or a tag was changed concurrently.
+  Conflict,
+  /// The operation was throttled, e.g. an identical query was just sent.
+  Throttled,
+  /// A member runs an incompatible version or belongs to another cluster.
+  Incompatible,
+  /// The operation is not supported with the current options, e.g. the
+  /// coordinates are disabled.
+  Unsupported,
+  /// A message could not be delivered, or no answer was received from a
+  /// member.
+  Network,
+  /// A message or payload could not be encoded, decoded or compressed.
+  Encoding,
+  /// A delegate refused the operation.
+  Rejected,
+  /// A snapshot or keyring file could not be read or written.
+  Io,
+  /// Any other error.
+  Other,
+}
+
+impl ErrorKind {
+  /// Returns the stable code of the kind.
+  #[inline]
+  pub const fn as_str(&self) -> &'static str {
+    match self {
+      Self::TooLarge => "too_large",
+      Self::Timeout => "timeout",
+      Self::InvalidState => "invalid_state",
+      Self::Shutdown => "shutdown",
+      Self::Misconfigured => "misconfigured",
+      Self::InvalidInput => "invalid_input",
+      Self::Conflict => "conflict",
+      Self::Throttled => "throttled",
+      Self::Incompatible => "incompatible",
+      Self::Unsupported => "unsupported",
+      Self::Network => "network",
+      Self::Encoding => "encoding",
+      Self::Rejected => "rejected",
+      Self::Io => "io",
+      Self::Other => "other",
+    }
+  }
+
+  /// Returns `true` if retrying the same operation later may succeed, e.g.
+  /// a join which timed out or a lock held by another member.
+  #[inline]
+  pub const fn is_retryable(&self) -> bool {
+    matches!(
+      self,
+      Self::Timeout | Self::Conflict | Self::Throttled | Self::Network
+    )
+  }
+
+  /// Returns `true` if the local node cannot be used anymore, it has to be
+  /// created again.
+  #[inline]
+  pub const fn is_fatal(&self) -> bool {
+    matches!(self, Self::Shutdown | Self::Misconfigured)
+  }
+}
+
+impl core::fmt::Display for ErrorKind {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
 /// Error trait for [`Delegate`]
 #[derive(thiserror::Error)]
 pub enum SerfDelegateError<D: Delegate> {
@@ -57,6 +148,16 @@ impl<D: Delegate> SerfDelegateError<D> {
   pub const fn serf(err: crate::error::SerfError) -> Self {
     Self::Serf(err)
   }
+
+  /// Returns the stable classification of the error.
+  #[inline]
+  pub fn kind(&self) -> ErrorKind {
+    match self {
+      Self::Serf(e) => e.kind(),
+      Self::TransformDelegate(_) => ErrorKind::Encoding,
+      Self::MergeDelegate(_) => ErrorKind::Rejected,
+    }
+  }
 }
 
 impl<T, D> From<MemberlistDelegateError<SerfDelegate<T, D>>> for SerfDelegateError<D>
@@ -159,6 +260,39 @@ where
   D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
   T: Transport,
 {
+  /// Returns the stable classification of the error, to decide how to
+  /// handle it rather than matching on its message.
+  pub fn kind(&self) -> ErrorKind {
+    match self {
+      Self::Memberlist(e) => match e {
+        MemberlistError::NotRunning => ErrorKind::Shutdown,
+        MemberlistError::UpdateTimeout | MemberlistError::LeaveTimeout => ErrorKind::Timeout,
+        MemberlistError::Lost(_)
+        | MemberlistError::UnexpectedMessage { .. }
+        | MemberlistError::SequenceNumberMismatch { .. }
+        | MemberlistError::Remote(_) => ErrorKind::Network,
+        MemberlistError::Other(_) => ErrorKind::Other,
+      },
+      Self::Serf(e) => e.kind(),
+      Self::Transport(_) | Self::Relay(_) => ErrorKind::Network,
+      Self::Delegate(e) => e.kind(),
+    }
+  }
+
+  /// Returns `true` if retrying the same operation later may succeed, see
+  /// [`ErrorKind::is_retryable`].
+  #[inline]
+  pub fn is_retryable(&self) -> bool {
+    self.kind().is_retryable()
+  }
+
+  /// Returns `true` if the local node cannot be used anymore, see
+  /// [`ErrorKind::is_fatal`].
+  #[inline]
+  pub fn is_fatal(&self) -> bool {
+    self.kind().is_fatal()
+  }
+
   /// Create error from a transform error
   #[inline]
   pub fn transform_delegate(err: <D as TransformDelegate>::Error) -> Self {
@@ -183,6 +317,12 @@ where
     Self::Serf(SerfError::QueryTimeout)
   }
 
+  /// Create a paused error
+  #[inline]
+  pub const fn paused() -> Self {
+    Self::Serf(SerfError::Paused)
+  }
+
   /// Create a query already response error
   #[inline]
   pub const fn query_already_responsed() -> Self {
@@ -219,12 +359,24 @@ where
     Self::Serf(SerfError::TagsTooLarge(size))
   }
 
+  /// Create an app meta too large error
+  #[inline]
+  pub const fn app_meta_too_large(size: usize) -> Self {
+    Self::Serf(SerfError::AppMetaTooLarge(size))
+  }
+
   /// Create a query too large error
   #[inline]
   pub const fn query_too_large(size: usize) -> Self {
     Self::Serf(SerfError::QueryTooLarge(size))
   }
 
+  /// Create a query suppressed error
+  #[inline]
+  pub const fn query_suppressed(name: SmolStr) -> Self {
+    Self::Serf(SerfError::QuerySuppressed(name))
+  }
+
   /// Create a user event limit too large error
   #[inline]
   pub const fn user_event_limit_too_large(size: usize) -> Self {
@@ -255,12 +407,32 @@ where
     Self::Serf(SerfError::RemovalBroadcastTimeout)
   }
 
+  /// Create a shutdown timeout error
+  #[inline]
+  pub fn shutdown_timeout(report: ShutdownReport) -> Self {
+    Self::Serf(SerfError::ShutdownTimeout(Box::new(report)))
+  }
+
   /// Create a snapshot error
   #[inline]
   pub const fn snapshot(err: SnapshotError) -> Self {
     Self::Serf(SerfError::Snapshot(err))
   }
 
+  /// Create a keyring file error
+  #[cfg(feature = "encryption")]
+  #[inline]
+  pub const fn keyring_file(err: std::io::Error) -> Self {
+    Self::Serf(SerfError::KeyringFile(err))
+  }
+
+  /// Create a key rotation error
+  #[cfg(feature = "encryption")]
+  #[inline]
+  pub const fn key_rotation(stage: crate::key_manager::RotationStage) -> Self {
+    Self::Serf(SerfError::KeyRotation(stage))
+  }
+
   /// Create a memberlist error
   #[inline]
   pub const fn memberlist(
@@ -281,11 +453,68 @@ where
     Self::Serf(SerfError::BadJoinStatus(status))
   }
 
+  /// Create a join timeout error
+  #[inline]
+  pub const fn join_timeout() -> Self {
+    Self::Serf(SerfError::JoinTimeout)
+  }
+
   /// Create a coordinates disabled error
   #[inline]
   pub const fn coordinates_disabled() -> Self {
     Self::Serf(SerfError::CoordinatesDisabled)
   }
+
+  /// Create an error for a feature needing a protocol version some members do not speak
+  #[inline]
+  pub const fn protocol_unsupported(vsn: ProtocolVersion) -> Self {
+    Self::Serf(SerfError::ProtocolUnsupported(vsn))
+  }
+
+  /// Create a compression error
+  #[inline]
+  pub const fn compression(err: std::io::Error) -> Self {
+    Self::Serf(SerfError::Compression(err))
+  }
+
+  /// Create a typed payload error
+  #[cfg(feature = "serde")]
+  #[inline]
+  pub const fn typed_payload(err: serde_json::Error) -> Self {
+    Self::Serf(SerfError::TypedPayload(err))
+  }
+
+  /// Create an invalid options error
+  #[inline]
+  pub const fn invalid_options(reason: &'static str) -> Self {
+    Self::Serf(SerfError::InvalidOptions(reason))
+  }
+
+  /// Create an invalid reloadable options error
+  #[inline]
+  pub const fn invalid_reloadable_options(reason: &'static str) -> Self {
+    Self::Serf(SerfError::InvalidReloadableOptions(reason))
+  }
+
+  /// Create a tag mismatch error
+  #[inline]
+  pub const fn tag_mismatch(
+    key: SmolStr,
+    expected: Option<SmolStr>,
+    actual: Option<SmolStr>,
+  ) -> Self {
+    Self::Serf(SerfError::TagMismatch {
+      key,
+      expected,
+      actual,
+    })
+  }
+
+  /// Create a lock not acquired error
+  #[inline]
+  pub const fn lock_not_acquired(name: SmolStr) -> Self {
+    Self::Serf(SerfError::LockNotAcquired(name))
+  }
 }
 
 /// [`Serf`](crate::Serf) error.
@@ -303,15 +532,25 @@ pub enum SerfError {
   /// Returned when the leave status is bad.
   #[error("ruserf: leave called on {0} statues")]
   BadLeaveStatus(SerfState),
+  /// Returned when a node could not be joined before the join deadline.
+  #[error("ruserf: join deadline exceeded")]
+  JoinTimeout,
   /// Returned when the encoded user event exceeds the sane limit after encoding.
   #[error("ruserf: user event exceeds sane limit of {0} bytes after encoding")]
   RawUserEventTooLarge(usize),
   /// Returned when the query size exceeds the configured limit.
   #[error("ruserf: query exceeds limit of {0} bytes")]
   QueryTooLarge(usize),
+  /// Returned when an identical query was sent within the
+  /// [`Options::query_suppression_window`](crate::Options::query_suppression_window).
+  #[error("ruserf: query {0} suppressed, an identical query was just sent")]
+  QuerySuppressed(SmolStr),
   /// Returned when the query is timeout.
   #[error("ruserf: query response is past the deadline")]
   QueryTimeout,
+  /// Returned when broadcasting while the gossip is paused.
+  #[error("ruserf: gossip is paused")]
+  Paused,
   /// Returned when the query response is too large.
   #[error("ruserf: query response ({got} bytes) exceeds limit of {limit} bytes")]
   QueryResponseTooLarge {
@@ -329,6 +568,27 @@ pub enum SerfError {
   /// Returned when the tags too large.
   #[error("ruserf: encoded length of tags exceeds limit of {0} bytes")]
   TagsTooLarge(usize),
+  /// Returned when the application metadata does not fit in the meta beside the tags.
+  #[error("ruserf: encoded length of tags and app meta exceeds limit of {0} bytes")]
+  AppMetaTooLarge(usize),
+  /// Returned when a member speaks a protocol or delegate version outside of the supported range.
+  #[error("ruserf: member {id} speaks incompatible versions {versions}")]
+  IncompatibleVersion {
+    /// The id of the member.
+    id: SmolStr,
+    /// The versions advertised by the member.
+    versions: SmolStr,
+  },
+  /// Returned when a member advertises the hash of another cluster name, see
+  /// [`Options::cluster_name`](crate::Options::cluster_name).
+  #[error("ruserf: member {id} belongs to another cluster")]
+  ClusterMismatch {
+    /// The id of the member.
+    id: SmolStr,
+    /// The hash of the cluster name advertised by the member, `None` if it
+    /// advertises no cluster.
+    cluster: Option<SmolStr>,
+  },
   /// Returned when the relayed response is too large.
   #[error("ruserf: relayed response exceeds limit of {0} bytes")]
   RelayedResponseTooLarge(usize),
@@ -338,17 +598,116 @@ pub enum SerfError {
   /// Returned when the coordinates are disabled.
   #[error("ruserf: coordinates are disabled")]
   CoordinatesDisabled,
+  /// Returned when a feature needs a protocol version some members do not speak.
+  #[error("ruserf: not every member speaks protocol version {0}")]
+  ProtocolUnsupported(ProtocolVersion),
+  /// Returned when the current value of a tag does not match the expected one.
+  #[error("ruserf: tag {key} is {actual:?}, expected {expected:?}")]
+  TagMismatch {
+    /// The key of the tag.
+    key: SmolStr,
+    /// The expected value of the tag.
+    expected: Option<SmolStr>,
+    /// The actual value of the tag.
+    actual: Option<SmolStr>,
+  },
+  /// Returned when failed to compress a payload.
+  #[error("ruserf: failed to compress payload: {0}")]
+  Compression(std::io::Error),
+  /// Returned when the payload of a typed user event cannot be encoded.
+  #[cfg(feature = "serde")]
+  #[error("ruserf: failed to encode typed user event payload: {0}")]
+  TypedPayload(serde_json::Error),
+  /// Returned when the options passed to create a [`Serf`](crate::Serf) are invalid.
+  #[error("ruserf: invalid options: {0}")]
+  InvalidOptions(&'static str),
+  /// Returned when the options passed to [`Serf::reload`](crate::Serf::reload) are invalid.
+  #[error("ruserf: invalid reloadable options: {0}")]
+  InvalidReloadableOptions(&'static str),
+  /// Returned when a majority of the members did not grant the lock.
+  #[error("ruserf: failed to acquire lock {0}")]
+  LockNotAcquired(SmolStr),
   /// Returned when snapshot error.
   #[error("ruserf: {0}")]
   Snapshot(#[from] SnapshotError),
+  /// Returned when the keyring file cannot be loaded.
+  #[cfg(feature = "encryption")]
+  #[error("ruserf: failed to load keyring file: {0}")]
+  KeyringFile(std::io::Error),
+  /// Returned when a key rotation did not complete, see
+  /// [`KeyManager::rotate_keys`](crate::key_manager::KeyManager::rotate_keys).
+  #[cfg(feature = "encryption")]
+  #[error("ruserf: key rotation failed at the {0} stage")]
+  KeyRotation(crate::key_manager::RotationStage),
   /// Returned when timed out broadcasting node removal.
   #[error("ruserf: timed out broadcasting node removal")]
   RemovalBroadcastTimeout,
+  /// Returned when [`Serf::shutdown`](crate::Serf::shutdown) did not complete
+  /// before the [`Options::shutdown_timeout`](crate::Options::shutdown_timeout),
+  /// with what was torn down until then.
+  #[error("ruserf: shutdown deadline exceeded")]
+  ShutdownTimeout(Box<ShutdownReport>),
   /// Returned when the timed out broadcasting channel closed.
   #[error("ruserf: timed out broadcasting channel closed")]
   BroadcastChannelClosed,
 }
 
+impl SerfError {
+  /// Returns the stable classification of the error.
+  pub fn kind(&self) -> ErrorKind {
+    match self {
+      Self::UserEventLimitTooLarge(_)
+      | Self::UserEventTooLarge(_)
+      | Self::RawUserEventTooLarge(_)
+      | Self::QueryTooLarge(_)
+      | Self::QueryResponseTooLarge { .. }
+      | Self::FailTruncateResponse
+      | Self::TagsTooLarge(_)
+      | Self::AppMetaTooLarge(_)
+      | Self::RelayedResponseTooLarge(_) => ErrorKind::TooLarge,
+      Self::BadJoinStatus(state) | Self::BadLeaveStatus(state) => match state {
+        SerfState::Left | SerfState::Shutdown => ErrorKind::Shutdown,
+        SerfState::Alive | SerfState::Leaving => ErrorKind::InvalidState,
+      },
+      Self::Paused | Self::QueryAlreadyResponsed => ErrorKind::InvalidState,
+      Self::JoinTimeout
+      | Self::QueryTimeout
+      | Self::RemovalBroadcastTimeout
+      | Self::ShutdownTimeout(_) => ErrorKind::Timeout,
+      Self::QuerySuppressed(_) => ErrorKind::Throttled,
+      Self::IncompatibleVersion { .. } | Self::ClusterMismatch { .. } => ErrorKind::Incompatible,
+      Self::QueryResponseDeliveryFailed => ErrorKind::Network,
+      Self::CoordinatesDisabled | Self::ProtocolUnsupported(_) => ErrorKind::Unsupported,
+      Self::TagMismatch { .. } | Self::LockNotAcquired(_) => ErrorKind::Conflict,
+      Self::Compression(_) => ErrorKind::Encoding,
+      #[cfg(feature = "serde")]
+      Self::TypedPayload(_) => ErrorKind::Encoding,
+      Self::InvalidOptions(_) => ErrorKind::Misconfigured,
+      Self::InvalidReloadableOptions(_) => ErrorKind::InvalidInput,
+      Self::Snapshot(_) => ErrorKind::Io,
+      #[cfg(feature = "encryption")]
+      Self::KeyringFile(_) => ErrorKind::Io,
+      #[cfg(feature = "encryption")]
+      Self::KeyRotation(_) => ErrorKind::Network,
+      Self::BroadcastChannelClosed => ErrorKind::Shutdown,
+    }
+  }
+
+  /// Returns `true` if retrying the same operation later may succeed, see
+  /// [`ErrorKind::is_retryable`].
+  #[inline]
+  pub fn is_retryable(&self) -> bool {
+    self.kind().is_retryable()
+  }
+
+  /// Returns `true` if the local node cannot be used anymore, see
+  /// [`ErrorKind::is_fatal`].
+  #[inline]
+  pub fn is_fatal(&self) -> bool {
+    self.kind().is_fatal()
+  }
+}
+
 /// Error type for [`Memberlist`](memberlist_core::Memberlist).
 #[derive(Debug, thiserror::Error)]
 pub enum MemberlistError<I, A> {
@@ -390,16 +749,49 @@ pub enum MemberlistError<I, A> {
 }
 
 /// Relay error from remote nodes.
-pub struct RelayError<T, D>(
+pub struct RelayError<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
   #[allow(clippy::type_complexity)]
-  TinyVec<(
-    Member<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
-    memberlist_core::error::Error<T, SerfDelegate<T, D>>,
-  )>,
-)
+  pub(crate) failures: Box<
+    TinyVec<(
+      Member<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+      memberlist_core::error::Error<T, SerfDelegate<T, D>>,
+    )>,
+  >,
+  pub(crate) skipped:
+    Box<TinyVec<Member<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>>>,
+}
+
+impl<T, D> RelayError<T, D>
 where
   D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
-  T: Transport;
+  T: Transport,
+{
+  /// Returns the peers the response could not be relayed through, with the
+  /// reason.
+  #[inline]
+  #[allow(clippy::type_complexity)]
+  pub fn failures(
+    &self,
+  ) -> &TinyVec<(
+    Member<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+    memberlist_core::error::Error<T, SerfDelegate<T, D>>,
+  )> {
+    &self.failures
+  }
+
+  /// Returns the peers skipped because the previous relays through them
+  /// failed, see [`Options::relay_circuit_breaker`](crate::Options::relay_circuit_breaker).
+  #[inline]
+  pub fn skipped(
+    &self,
+  ) -> &TinyVec<Member<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>> {
+    &self.skipped
+  }
+}
 
 impl<T, D>
   From<
@@ -418,7 +810,10 @@ where
       memberlist_core::error::Error<T, SerfDelegate<T, D>>,
     )>,
   ) -> Self {
-    Self(value)
+    Self {
+      failures: Box::new(value),
+      skipped: Box::default(),
+    }
   }
 }
 
@@ -430,7 +825,7 @@ where
   fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
     writeln!(f, "relay errors:")?;
 
-    for (member, err) in self.0.iter() {
+    for (member, err) in self.failures.iter() {
       writeln!(
         f,
         "\tfailed to send relay response to {}: {}",
@@ -438,6 +833,13 @@ where
         err
       )?;
     }
+    for member in self.skipped.iter() {
+      writeln!(
+        f,
+        "\tskipped relaying through {} after repeated failures",
+        member.node().id()
+      )?;
+    }
     Ok(())
   }
 }
@@ -542,3 +944,28 @@ where
   T: Transport,
 {
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_serf_error_kind() {
+    assert_eq!(SerfError::QueryTooLarge(2048).kind(), ErrorKind::TooLarge);
+    assert!(!SerfError::QueryTooLarge(2048).is_retryable());
+
+    assert!(SerfError::JoinTimeout.is_retryable());
+    assert!(SerfError::LockNotAcquired(SmolStr::new("lock")).is_retryable());
+    assert!(SerfError::QuerySuppressed(SmolStr::new("query")).is_retryable());
+
+    assert_eq!(
+      SerfError::BadJoinStatus(SerfState::Leaving).kind(),
+      ErrorKind::InvalidState
+    );
+    assert!(SerfError::BadJoinStatus(SerfState::Shutdown).is_fatal());
+    assert!(SerfError::InvalidOptions("bad").is_fatal());
+    assert!(!SerfError::InvalidReloadableOptions("bad").is_fatal());
+
+    assert_eq!(ErrorKind::Timeout.to_string(), "timeout");
+  }
+}
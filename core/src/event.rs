@@ -1,13 +1,30 @@
-use std::{pin::Pin, sync::Arc, task::Poll, time::Duration};
+use std::{pin::Pin, sync::Arc, task::Poll};
 
 use crate::delegate::TransformDelegate;
 
 use self::error::Error;
 
-use super::{delegate::Delegate, types::Epoch, *};
+use super::{clock::Deadline, delegate::Delegate, serf::CorrelationId, *};
+
+mod backpressure;
+pub(crate) use backpressure::backpressured_event;
+pub use backpressure::EventBackpressure;
 
 mod crate_event;
 
+mod sink;
+pub(crate) use sink::sunk_event;
+pub use sink::{EventRecord, EventRecordKind, EventSink, JsonLinesSink};
+
+mod stream;
+pub use stream::*;
+
+#[cfg(feature = "serde")]
+mod typed;
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+pub use typed::*;
+
 use async_channel::Sender;
 pub use async_channel::{RecvError, TryRecvError};
 
@@ -16,6 +33,7 @@ pub(crate) use crate_event::*;
 use futures::Stream;
 use memberlist_core::{
   bytes::{BufMut, Bytes, BytesMut},
+  tracing::{self, Instrument},
   transport::{AddressResolver, Transport},
   types::TinyVec,
   CheapClone,
@@ -30,8 +48,7 @@ where
   D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
   T: Transport,
 {
-  pub(crate) query_timeout: Duration,
-  pub(crate) span: Mutex<Option<Epoch>>,
+  pub(crate) span: Mutex<Option<Deadline>>,
   pub(crate) this: Serf<T, D>,
 }
 
@@ -42,9 +59,9 @@ where
 {
   fn check_response_size(&self, resp: &[u8]) -> Result<(), Error<T, D>> {
     let resp_len = resp.len();
-    if resp_len > self.this.inner.opts.query_response_size_limit {
+    if resp_len > self.this.inner.reloadable.load().query_response_size_limit {
       Err(Error::query_response_too_large(
-        self.this.inner.opts.query_response_size_limit,
+        self.this.inner.reloadable.load().query_response_size_limit,
         resp_len,
       ))
     } else {
@@ -54,7 +71,7 @@ where
 
   async fn respond_with_message_and_response(
     &self,
-    respond_to: &<T::Resolver as AddressResolver>::ResolvedAddress,
+    respond_to: &Node<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
     relay_factor: u8,
     raw: Bytes,
     resp: QueryResponseMessage<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
@@ -63,37 +80,61 @@ where
 
     let mut mu = self.span.lock().await;
 
-    if let Some(span) = *mu {
+    if let Some(deadline) = mu.as_ref() {
       // Ensure we aren't past our response deadline
-      if span.elapsed() > self.query_timeout {
+      if deadline.is_expired() {
         return Err(Error::query_timeout());
       }
 
-      // Send the response directly to the originator
-      self.this.inner.memberlist.send(respond_to, raw).await?;
+      // Share the correlation id of the query handled on this node
+      let span = tracing::debug_span!(
+        "ruserf.respond",
+        correlation_id = %CorrelationId::query(resp.ltime, resp.id),
+        to = %respond_to.address(),
+        relay_factor,
+      );
+      let relayed = async {
+        // Send the response directly to the originator, or the member collecting the responses
+        self
+          .this
+          .inner
+          .memberlist
+          .send(respond_to.address(), raw.clone())
+          .await?;
+        self.this.inner.bandwidth.sent(&raw);
 
-      // Relay the response through up to relayFactor other nodes
-      self
-        .this
-        .relay_response(relay_factor, resp.from.cheap_clone(), resp)
-        .await?;
+        // Relay the response through up to relayFactor other nodes
+        Ok::<_, Error<T, D>>(
+          self
+            .this
+            .relay_response(relay_factor, respond_to.cheap_clone(), resp)
+            .await,
+        )
+      }
+      .instrument(span)
+      .await?;
 
-      // Clear the deadline, responses sent
+      // Clear the deadline, the response reached the originator even if some
+      // of the relays failed
       *mu = None;
-      Ok(())
+      relayed
     } else {
       Err(Error::query_already_responsed())
     }
   }
 
-  async fn respond(
+  fn encode_response(
     &self,
-    respond_to: &<T::Resolver as AddressResolver>::ResolvedAddress,
     id: u32,
     ltime: LamportTime,
-    relay_factor: u8,
     msg: Bytes,
-  ) -> Result<(), Error<T, D>> {
+  ) -> Result<
+    (
+      Bytes,
+      QueryResponseMessage<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+    ),
+    Error<T, D>,
+  > {
     let resp = QueryResponseMessage {
       ltime,
       id,
@@ -111,10 +152,66 @@ where
       len, expected_encoded_len,
       "expected encoded len {expected_encoded_len} is not match the actual encoded len {len}"
     );
+    Ok((buf.freeze(), resp))
+  }
+
+  async fn respond(
+    &self,
+    respond_to: &Node<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+    id: u32,
+    ltime: LamportTime,
+    relay_factor: u8,
+    msg: Bytes,
+  ) -> Result<(), Error<T, D>> {
+    let (raw, resp) = self.encode_response(id, ltime, msg)?;
     self
-      .respond_with_message_and_response(respond_to, relay_factor, buf.freeze(), resp)
+      .respond_with_message_and_response(respond_to, relay_factor, raw, resp)
       .await
   }
+
+  async fn respond_lossy<F>(
+    &self,
+    respond_to: &Node<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+    id: u32,
+    ltime: LamportTime,
+    relay_factor: u8,
+    mut msg: Bytes,
+    mut truncate: F,
+  ) -> Result<(), Error<T, D>>
+  where
+    F: FnMut(Bytes, usize) -> Option<Bytes>,
+  {
+    let limit = self.this.inner.reloadable.load().query_response_size_limit;
+    let actual = msg.len();
+    loop {
+      let (raw, resp) = self.encode_response(id, ltime, msg.clone())?;
+      let excess = raw.len().saturating_sub(limit);
+      if excess == 0 {
+        if resp.payload.len() < actual {
+          tracing::warn!(
+            "ruserf: truncated query response, sending {} of {} bytes",
+            resp.payload.len(),
+            actual
+          );
+        }
+        return self
+          .respond_with_message_and_response(respond_to, relay_factor, raw, resp)
+          .await;
+      }
+
+      // Give up once the callback stops making progress
+      match truncate(msg, excess) {
+        Some(shorter) if shorter.len() < resp.payload.len() => msg = shorter,
+        _ => return Err(Error::fail_truncate_response()),
+      }
+    }
+  }
+}
+
+/// Cuts `excess` bytes off the tail of the payload, which is always enough
+/// to fit since the length prefix of the payload can only shrink.
+fn truncate_tail(payload: Bytes, excess: usize) -> Option<Bytes> {
+  (!payload.is_empty()).then(|| payload.slice(..payload.len().saturating_sub(excess)))
 }
 
 /// Query event
@@ -131,6 +228,9 @@ where
   pub(crate) id: u32,
   /// source node
   pub(crate) from: Node<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+  /// The node the responses are sent to, the source node unless the query
+  /// names a member collecting them
+  pub(crate) respond_to: Node<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
   /// Number of duplicate responses to relay back to sender
   pub(crate) relay_factor: u8,
 }
@@ -169,6 +269,16 @@ where
   pub const fn from(&self) -> &Node<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress> {
     &self.from
   }
+
+  /// Returns the node the responses are sent to, which differs from the
+  /// source node if the requester set
+  /// [`QueryParam::with_respond_via`](crate::QueryParam::with_respond_via).
+  #[inline]
+  pub const fn respond_to(
+    &self,
+  ) -> &Node<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress> {
+    &self.respond_to
+  }
 }
 
 impl<D, T> PartialEq for QueryEvent<T, D>
@@ -179,6 +289,7 @@ where
   fn eq(&self, other: &Self) -> bool {
     self.id == other.id
       && self.from == other.from
+      && self.respond_to == other.respond_to
       && self.relay_factor == other.relay_factor
       && self.ltime == other.ltime
       && self.name == other.name
@@ -209,6 +320,7 @@ where
       ctx: self.ctx.clone(),
       id: self.id,
       from: self.from.clone(),
+      respond_to: self.respond_to.clone(),
       relay_factor: self.relay_factor,
     }
   }
@@ -256,7 +368,7 @@ where
   ) -> Result<(), Error<T, D>> {
     self
       .ctx
-      .respond_with_message_and_response(self.from.address(), self.relay_factor, raw, resp)
+      .respond_with_message_and_response(&self.respond_to, self.relay_factor, raw, resp)
       .await
   }
 
@@ -265,7 +377,7 @@ where
     self
       .ctx
       .respond(
-        self.from().address(),
+        &self.respond_to,
         self.id,
         self.ltime,
         self.relay_factor,
@@ -273,6 +385,168 @@ where
       )
       .await
   }
+
+  /// Used to send a response to the user query, cutting the tail of the
+  /// payload off if the response exceeds
+  /// [`Options::query_response_size_limit`](crate::Options::query_response_size_limit),
+  /// instead of failing with a `QueryResponseTooLarge` error.
+  pub async fn respond_lossy(&self, msg: Bytes) -> Result<(), Error<T, D>> {
+    self.respond_lossy_with(msg, truncate_tail).await
+  }
+
+  /// Like [`QueryEvent::respond_lossy`], but the payload is shortened by
+  /// `truncate`, e.g. to drop whole records rather than cutting one in half.
+  ///
+  /// `truncate` is called with the payload and the number of bytes the response
+  /// exceeds the limit by, until the response fits. It returns a shorter
+  /// payload, or `None` to give up, in which case a `FailTruncateResponse`
+  /// error is returned.
+  pub async fn respond_lossy_with<F>(&self, msg: Bytes, truncate: F) -> Result<(), Error<T, D>>
+  where
+    F: FnMut(Bytes, usize) -> Option<Bytes>,
+  {
+    self
+      .ctx
+      .respond_lossy(
+        &self.respond_to,
+        self.id,
+        self.ltime,
+        self.relay_factor,
+        msg,
+        truncate,
+      )
+      .await
+  }
+
+  /// Returns a handle which can be used to respond to the query without
+  /// holding on to the whole event.
+  ///
+  /// The handle is cheap to clone and stays valid after the event has been
+  /// handled, so the query can be answered from another task until its
+  /// deadline. Only the first response is sent.
+  pub fn responder(&self) -> QueryResponder<T, D> {
+    QueryResponder {
+      ctx: self.ctx.clone(),
+      id: self.id,
+      ltime: self.ltime,
+      from: self.from.clone(),
+      respond_to: self.respond_to.clone(),
+      relay_factor: self.relay_factor,
+    }
+  }
+}
+
+/// A handle to respond to a [`QueryEvent`] asynchronously,
+/// see [`QueryEvent::responder`].
+pub struct QueryResponder<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  ctx: Arc<QueryContext<T, D>>,
+  id: u32,
+  ltime: LamportTime,
+  from: Node<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+  respond_to: Node<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+  relay_factor: u8,
+}
+
+impl<D, T> Clone for QueryResponder<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  fn clone(&self) -> Self {
+    Self {
+      ctx: self.ctx.clone(),
+      id: self.id,
+      ltime: self.ltime,
+      from: self.from.clone(),
+      respond_to: self.respond_to.clone(),
+      relay_factor: self.relay_factor,
+    }
+  }
+}
+
+impl<D, T> QueryResponder<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  /// Returns the id of the query
+  #[inline]
+  pub const fn id(&self) -> u32 {
+    self.id
+  }
+
+  /// Returns the lamport time of the query
+  #[inline]
+  pub const fn lamport_time(&self) -> LamportTime {
+    self.ltime
+  }
+
+  /// Returns the source node of the query
+  #[inline]
+  pub const fn from(&self) -> &Node<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress> {
+    &self.from
+  }
+
+  /// Returns the node the response is sent to, see [`QueryEvent::respond_to`].
+  #[inline]
+  pub const fn respond_to(
+    &self,
+  ) -> &Node<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress> {
+    &self.respond_to
+  }
+
+  /// Returns `true` if a response can still be sent, i.e. the query has not
+  /// been responded to yet and its deadline has not passed.
+  pub async fn is_pending(&self) -> bool {
+    self
+      .ctx
+      .span
+      .lock()
+      .await
+      .as_ref()
+      .is_some_and(|deadline| !deadline.is_expired())
+  }
+
+  /// Used to send a response to the user query
+  pub async fn respond(&self, msg: Bytes) -> Result<(), Error<T, D>> {
+    self
+      .ctx
+      .respond(
+        &self.respond_to,
+        self.id,
+        self.ltime,
+        self.relay_factor,
+        msg,
+      )
+      .await
+  }
+
+  /// Used to send a response to the user query, see [`QueryEvent::respond_lossy`].
+  pub async fn respond_lossy(&self, msg: Bytes) -> Result<(), Error<T, D>> {
+    self.respond_lossy_with(msg, truncate_tail).await
+  }
+
+  /// Used to send a response to the user query, see [`QueryEvent::respond_lossy_with`].
+  pub async fn respond_lossy_with<F>(&self, msg: Bytes, truncate: F) -> Result<(), Error<T, D>>
+  where
+    F: FnMut(Bytes, usize) -> Option<Bytes>,
+  {
+    self
+      .ctx
+      .respond_lossy(
+        &self.respond_to,
+        self.id,
+        self.ltime,
+        self.relay_factor,
+        msg,
+        truncate,
+      )
+      .await
+  }
 }
 
 /// The event type for member event
@@ -295,6 +569,10 @@ pub enum MemberEventType {
   /// Reap event
   #[cfg_attr(feature = "serde", serde(rename = "member-reap"))]
   Reap,
+  /// Flap event, the member failed and rejoined too often, see
+  /// [`Options::flap_threshold`](crate::Options::flap_threshold)
+  #[cfg_attr(feature = "serde", serde(rename = "member-flap"))]
+  Flap,
 }
 
 impl MemberEventType {
@@ -307,6 +585,7 @@ impl MemberEventType {
       Self::Failed => "member-failed",
       Self::Update => "member-update",
       Self::Reap => "member-reap",
+      Self::Flap => "member-flap",
     }
   }
 }
@@ -319,6 +598,7 @@ impl core::fmt::Display for MemberEventType {
       Self::Failed => write!(f, "member-failed"),
       Self::Update => write!(f, "member-update"),
       Self::Reap => write!(f, "member-reap"),
+      Self::Flap => write!(f, "member-flap"),
     }
   }
 }
@@ -381,6 +661,199 @@ impl<I, A> From<MemberEvent<I, A>> for (MemberEventType, Arc<TinyVec<Member<I, A
   }
 }
 
+/// ClusterFormedEvent is emitted once the number of alive members reaches
+/// [`Options::bootstrap_expect`](crate::Options::bootstrap_expect).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClusterFormedEvent {
+  pub(crate) expect: usize,
+  pub(crate) alive: usize,
+}
+
+impl CheapClone for ClusterFormedEvent {}
+
+impl core::fmt::Display for ClusterFormedEvent {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "cluster-formed")
+  }
+}
+
+impl ClusterFormedEvent {
+  /// Returns the number of alive members which was expected
+  #[inline]
+  pub const fn expect(&self) -> usize {
+    self.expect
+  }
+
+  /// Returns the number of alive members when the cluster was formed
+  #[inline]
+  pub const fn alive(&self) -> usize {
+    self.alive
+  }
+}
+
+/// LeaderChangedEvent is emitted when the leader elected among the alive
+/// members changes, see [`Options::election`](crate::Options::election).
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeaderChangedEvent<I, A> {
+  pub(crate) previous: Option<Member<I, A>>,
+  pub(crate) current: Option<Member<I, A>>,
+}
+
+impl<I: CheapClone, A: CheapClone> CheapClone for LeaderChangedEvent<I, A> {
+  fn cheap_clone(&self) -> Self {
+    Self {
+      previous: self.previous.as_ref().map(CheapClone::cheap_clone),
+      current: self.current.as_ref().map(CheapClone::cheap_clone),
+    }
+  }
+}
+
+impl<I, A> core::fmt::Display for LeaderChangedEvent<I, A> {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "leader-changed")
+  }
+}
+
+impl<I, A> LeaderChangedEvent<I, A> {
+  /// Returns the previous leader, `None` if there was no leader
+  #[inline]
+  pub const fn previous(&self) -> Option<&Member<I, A>> {
+    self.previous.as_ref()
+  }
+
+  /// Returns the current leader, `None` if no member is eligible anymore
+  #[inline]
+  pub const fn current(&self) -> Option<&Member<I, A>> {
+    self.current.as_ref()
+  }
+}
+
+/// PartitionEvent is emitted when the local node suspects a network
+/// partition, and once it heals, see
+/// [`Options::partition_detection`](crate::Options::partition_detection).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PartitionEvent {
+  pub(crate) partitioned: bool,
+  pub(crate) peak: usize,
+  pub(crate) alive: usize,
+  pub(crate) failed: usize,
+}
+
+impl CheapClone for PartitionEvent {}
+
+impl core::fmt::Display for PartitionEvent {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    if self.partitioned {
+      write!(f, "partition")
+    } else {
+      write!(f, "partition-healed")
+    }
+  }
+}
+
+impl PartitionEvent {
+  /// Returns `true` if a partition is suspected, `false` once it healed
+  #[inline]
+  pub const fn partitioned(&self) -> bool {
+    self.partitioned
+  }
+
+  /// Returns the peak number of alive members
+  #[inline]
+  pub const fn peak(&self) -> usize {
+    self.peak
+  }
+
+  /// Returns the number of alive members when the event was emitted
+  #[inline]
+  pub const fn alive(&self) -> usize {
+    self.alive
+  }
+
+  /// Returns the number of members which failed within the detection window
+  #[inline]
+  pub const fn failed(&self) -> usize {
+    self.failed
+  }
+}
+
+/// ClusterMismatchEvent is emitted when a node is refused because it
+/// advertises another cluster name, see
+/// [`Options::cluster_name`](crate::Options::cluster_name).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClusterMismatchEvent<I, A> {
+  pub(crate) node: Node<I, A>,
+  pub(crate) cluster: Option<SmolStr>,
+}
+
+impl<I: CheapClone, A: CheapClone> CheapClone for ClusterMismatchEvent<I, A> {
+  fn cheap_clone(&self) -> Self {
+    Self {
+      node: self.node.cheap_clone(),
+      cluster: self.cluster.clone(),
+    }
+  }
+}
+
+impl<I, A> core::fmt::Display for ClusterMismatchEvent<I, A> {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "cluster-mismatch")
+  }
+}
+
+impl<I, A> ClusterMismatchEvent<I, A> {
+  /// Returns the refused node
+  #[inline]
+  pub const fn node(&self) -> &Node<I, A> {
+    &self.node
+  }
+
+  /// Returns the hash of the cluster name advertised by the refused node,
+  /// `None` if it advertises no cluster
+  #[inline]
+  pub const fn cluster(&self) -> Option<&SmolStr> {
+    self.cluster.as_ref()
+  }
+}
+
+/// InvalidUserEvent is emitted instead of a user event whose payload was
+/// rejected by the validator registered for its name, see
+/// [`Serf::register_user_event_validator`](crate::Serf::register_user_event_validator).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidUserEvent {
+  pub(crate) event: UserEventMessage,
+  pub(crate) reason: SmolStr,
+}
+
+impl CheapClone for InvalidUserEvent {
+  fn cheap_clone(&self) -> Self {
+    Self {
+      event: self.event.cheap_clone(),
+      reason: self.reason.clone(),
+    }
+  }
+}
+
+impl core::fmt::Display for InvalidUserEvent {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "invalid-user-event")
+  }
+}
+
+impl InvalidUserEvent {
+  /// Returns the rejected user event
+  #[inline]
+  pub const fn event(&self) -> &UserEventMessage {
+    &self.event
+  }
+
+  /// Returns why the validator rejected the payload
+  #[inline]
+  pub const fn reason(&self) -> &SmolStr {
+    &self.reason
+  }
+}
+
 /// The event produced by the Serf instance.
 #[derive(derive_more::From)]
 pub enum Event<T, D>
@@ -394,6 +867,16 @@ where
   User(UserEventMessage),
   /// Query events
   Query(QueryEvent<T, D>),
+  /// The cluster has reached the expected number of alive members
+  ClusterFormed(ClusterFormedEvent),
+  /// The elected leader has changed
+  LeaderChanged(LeaderChangedEvent<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>),
+  /// A network partition is suspected, or healed
+  Partition(PartitionEvent),
+  /// A node of another cluster was refused
+  ClusterMismatch(ClusterMismatchEvent<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>),
+  /// A user event was rejected by the validator of its name
+  InvalidUser(InvalidUserEvent),
 }
 
 impl<D, T> Clone for Event<T, D>
@@ -406,6 +889,11 @@ where
       Self::Member(e) => Self::Member(e.cheap_clone()),
       Self::User(e) => Self::User(e.cheap_clone()),
       Self::Query(e) => Self::Query(e.clone()),
+      Self::ClusterFormed(e) => Self::ClusterFormed(e.cheap_clone()),
+      Self::LeaderChanged(e) => Self::LeaderChanged(e.cheap_clone()),
+      Self::Partition(e) => Self::Partition(e.cheap_clone()),
+      Self::ClusterMismatch(e) => Self::ClusterMismatch(e.cheap_clone()),
+      Self::InvalidUser(e) => Self::InvalidUser(e.cheap_clone()),
     }
   }
 }
@@ -472,6 +960,11 @@ where
         Ok(CrateEvent::Member(e)) => return Ok(Event::Member(e)),
         Ok(CrateEvent::User(e)) => return Ok(Event::User(e)),
         Ok(CrateEvent::Query(e)) => return Ok(Event::Query(e)),
+        Ok(CrateEvent::ClusterFormed(e)) => return Ok(Event::ClusterFormed(e)),
+        Ok(CrateEvent::LeaderChanged(e)) => return Ok(Event::LeaderChanged(e)),
+        Ok(CrateEvent::Partition(e)) => return Ok(Event::Partition(e)),
+        Ok(CrateEvent::ClusterMismatch(e)) => return Ok(Event::ClusterMismatch(e)),
+        Ok(CrateEvent::InvalidUser(e)) => return Ok(Event::InvalidUser(e)),
         Err(e) => return Err(e),
       }
     }
@@ -488,6 +981,11 @@ where
         Ok(CrateEvent::Member(e)) => return Ok(Event::Member(e)),
         Ok(CrateEvent::User(e)) => return Ok(Event::User(e)),
         Ok(CrateEvent::Query(e)) => return Ok(Event::Query(e)),
+        Ok(CrateEvent::ClusterFormed(e)) => return Ok(Event::ClusterFormed(e)),
+        Ok(CrateEvent::LeaderChanged(e)) => return Ok(Event::LeaderChanged(e)),
+        Ok(CrateEvent::Partition(e)) => return Ok(Event::Partition(e)),
+        Ok(CrateEvent::ClusterMismatch(e)) => return Ok(Event::ClusterMismatch(e)),
+        Ok(CrateEvent::InvalidUser(e)) => return Ok(Event::InvalidUser(e)),
         Err(e) => return Err(e),
       }
     }
@@ -507,6 +1005,12 @@ where
   pub fn len(&self) -> usize {
     self.rx.len()
   }
+
+  /// Converts the subscriber into a [`Stream`] which can be split into
+  /// streams of a single kind of events.
+  pub fn into_stream(self) -> EventStream<T, D> {
+    EventStream::from(self)
+  }
 }
 
 impl<T, D> Stream for EventSubscriber<T, D>
@@ -517,15 +1021,6 @@ where
   type Item = Event<T, D>;
 
   fn poll_next(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Option<Self::Item>> {
-    match <async_channel::Receiver<CrateEvent<T, D>> as Stream>::poll_next(self.project().rx, cx) {
-      Poll::Ready(Some(event)) => match event {
-        CrateEvent::Member(e) => Poll::Ready(Some(Event::Member(e))),
-        CrateEvent::User(e) => Poll::Ready(Some(Event::User(e))),
-        CrateEvent::Query(e) => Poll::Ready(Some(Event::Query(e))),
-        CrateEvent::InternalQuery { .. } => Poll::Pending,
-      },
-      Poll::Ready(None) => Poll::Ready(None),
-      Poll::Pending => Poll::Pending,
-    }
+    poll_event(self.project().rx, cx)
   }
 }
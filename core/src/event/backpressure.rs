@@ -0,0 +1,108 @@
+use async_channel::{unbounded, Receiver, Sender, TrySendError};
+use memberlist_core::{
+  agnostic_lite::RuntimeLite,
+  tracing,
+  transport::{AddressResolver, Transport},
+};
+
+use super::*;
+
+/// Controls what happens when the event channel handed to [`Serf`] is full.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum EventBackpressure {
+  /// Wait until the subscriber makes room for the event. A slow subscriber
+  /// will stall the gossip processing which produces the events.
+  #[default]
+  Block,
+  /// Evict the oldest pending event from the channel to make room for the new one.
+  DropOldest,
+  /// Discard the new event and keep the pending ones.
+  DropNewest,
+  /// Never drop an event, pending events which do not fit in the channel are
+  /// buffered in an unbounded queue until the subscriber catches up.
+  Lossless,
+}
+
+impl EventBackpressure {
+  /// Returns the string representation of the backpressure policy
+  #[inline]
+  pub const fn as_str(&self) -> &'static str {
+    match self {
+      Self::Block => "block",
+      Self::DropOldest => "drop-oldest",
+      Self::DropNewest => "drop-newest",
+      Self::Lossless => "lossless",
+    }
+  }
+}
+
+impl core::fmt::Display for EventBackpressure {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+/// Returns an event channel which applies the given backpressure policy
+/// before handing the events to `out_tx`.
+pub(crate) fn backpressured_event<T, D>(
+  out_tx: Sender<CrateEvent<T, D>>,
+  policy: EventBackpressure,
+  #[cfg(feature = "metrics")] metric_labels: std::sync::Arc<memberlist_core::types::MetricLabels>,
+) -> Sender<CrateEvent<T, D>>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  // Blocking is the natural behavior of the channel, no need to spawn anything
+  if policy == EventBackpressure::Block {
+    return out_tx;
+  }
+
+  let (in_tx, in_rx) = unbounded();
+  <T::Runtime as RuntimeLite>::spawn_detach(backpressure_loop(
+    in_rx,
+    out_tx,
+    policy,
+    #[cfg(feature = "metrics")]
+    metric_labels,
+  ));
+  in_tx
+}
+
+async fn backpressure_loop<T, D>(
+  in_rx: Receiver<CrateEvent<T, D>>,
+  out_tx: Sender<CrateEvent<T, D>>,
+  policy: EventBackpressure,
+  #[cfg(feature = "metrics")] metric_labels: std::sync::Arc<memberlist_core::types::MetricLabels>,
+) where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  while let Ok(ev) = in_rx.recv().await {
+    let dropped = match policy {
+      EventBackpressure::Block | EventBackpressure::Lossless => {
+        if out_tx.send(ev).await.is_err() {
+          return;
+        }
+        false
+      }
+      EventBackpressure::DropNewest => match out_tx.try_send(ev) {
+        Ok(()) => false,
+        Err(TrySendError::Full(_)) => true,
+        Err(TrySendError::Closed(_)) => return,
+      },
+      EventBackpressure::DropOldest => match out_tx.force_send(ev) {
+        Ok(displaced) => displaced.is_some(),
+        Err(_) => return,
+      },
+    };
+
+    if dropped {
+      tracing::debug!(policy=%policy, "ruserf: event channel is full, dropped an event");
+      #[cfg(feature = "metrics")]
+      metrics::counter!("ruserf.events.dropped", metric_labels.iter()).increment(1);
+    }
+  }
+}
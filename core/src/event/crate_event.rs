@@ -1,5 +1,7 @@
 use ruserf_types::QueryMessage;
 
+use crate::app_query::APP_QUERY_PREFIX;
+
 use super::*;
 
 pub(crate) trait QueryMessageExt {
@@ -17,6 +19,8 @@ impl<I, A> QueryMessageExt for QueryMessage<I, A> {
       INTERNAL_CONFLICT => {
         return Some(T::decode_id(&self.payload).map(|(_, id)| InternalQueryEvent::Conflict(id)));
       }
+      INTERNAL_ACQUIRE_LOCK => InternalQueryEvent::AcquireLock,
+      INTERNAL_AUDIT => InternalQueryEvent::Audit,
       #[cfg(feature = "encryption")]
       INTERNAL_INSTALL_KEY => InternalQueryEvent::InstallKey,
       #[cfg(feature = "encryption")]
@@ -25,13 +29,16 @@ impl<I, A> QueryMessageExt for QueryMessage<I, A> {
       INTERNAL_REMOVE_KEY => InternalQueryEvent::RemoveKey,
       #[cfg(feature = "encryption")]
       INTERNAL_LIST_KEYS => InternalQueryEvent::ListKey,
+      name if name.starts_with(APP_QUERY_PREFIX) => InternalQueryEvent::App,
       _ => return None,
     }));
   }
 }
 
-const INTERNAL_PING: &str = "_ruserf_ping";
+pub(crate) const INTERNAL_PING: &str = "_ruserf_ping";
 const INTERNAL_CONFLICT: &str = "_ruserf_conflict";
+pub(crate) const INTERNAL_ACQUIRE_LOCK: &str = "_ruserf_acquire_lock";
+pub(crate) const INTERNAL_AUDIT: &str = "_ruserf_audit";
 #[cfg(feature = "encryption")]
 pub(crate) const INTERNAL_INSTALL_KEY: &str = "_ruserf_install_key";
 #[cfg(feature = "encryption")]
@@ -50,6 +57,11 @@ pub enum CrateEventType {
   User,
   Query,
   InternalQuery,
+  ClusterFormed,
+  LeaderChanged,
+  Partition,
+  ClusterMismatch,
+  InvalidUser,
 }
 
 pub(crate) enum CrateEvent<T, D>
@@ -64,6 +76,11 @@ where
     kind: InternalQueryEvent<T::Id>,
     query: QueryEvent<T, D>,
   },
+  ClusterFormed(ClusterFormedEvent),
+  LeaderChanged(LeaderChangedEvent<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>),
+  Partition(PartitionEvent),
+  ClusterMismatch(ClusterMismatchEvent<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>),
+  InvalidUser(InvalidUserEvent),
 }
 
 impl<D, T> Clone for CrateEvent<T, D>
@@ -80,6 +97,11 @@ where
         kind: kind.clone(),
         query: query.clone(),
       },
+      Self::ClusterFormed(e) => Self::ClusterFormed(*e),
+      Self::LeaderChanged(e) => Self::LeaderChanged(e.clone()),
+      Self::Partition(e) => Self::Partition(*e),
+      Self::ClusterMismatch(e) => Self::ClusterMismatch(e.clone()),
+      Self::InvalidUser(e) => Self::InvalidUser(e.clone()),
     }
   }
 }
@@ -98,6 +120,11 @@ where
       Self::User(_) => CrateEventType::User,
       Self::Query(_) => CrateEventType::Query,
       Self::InternalQuery { .. } => CrateEventType::InternalQuery,
+      Self::ClusterFormed(_) => CrateEventType::ClusterFormed,
+      Self::LeaderChanged(_) => CrateEventType::LeaderChanged,
+      Self::Partition(_) => CrateEventType::Partition,
+      Self::ClusterMismatch(_) => CrateEventType::ClusterMismatch,
+      Self::InvalidUser(_) => CrateEventType::InvalidUser,
     }
   }
 
@@ -137,6 +164,62 @@ where
   }
 }
 
+impl<D, T> From<ClusterFormedEvent> for CrateEvent<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  fn from(value: ClusterFormedEvent) -> Self {
+    Self::ClusterFormed(value)
+  }
+}
+
+impl<D, T> From<LeaderChangedEvent<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>>
+  for CrateEvent<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  fn from(
+    value: LeaderChangedEvent<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+  ) -> Self {
+    Self::LeaderChanged(value)
+  }
+}
+
+impl<D, T> From<PartitionEvent> for CrateEvent<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  fn from(value: PartitionEvent) -> Self {
+    Self::Partition(value)
+  }
+}
+
+impl<D, T> From<ClusterMismatchEvent<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>>
+  for CrateEvent<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  fn from(
+    value: ClusterMismatchEvent<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+  ) -> Self {
+    Self::ClusterMismatch(value)
+  }
+}
+
+impl<D, T> From<InvalidUserEvent> for CrateEvent<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  fn from(value: InvalidUserEvent) -> Self {
+    Self::InvalidUser(value)
+  }
+}
+
 impl<D, T> From<(InternalQueryEvent<T::Id>, QueryEvent<T, D>)> for CrateEvent<T, D>
 where
   D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
@@ -153,6 +236,8 @@ where
 pub enum InternalQueryEvent<I> {
   Ping,
   Conflict(I),
+  AcquireLock,
+  Audit,
   #[cfg(feature = "encryption")]
   InstallKey,
   #[cfg(feature = "encryption")]
@@ -161,6 +246,7 @@ pub enum InternalQueryEvent<I> {
   RemoveKey,
   #[cfg(feature = "encryption")]
   ListKey,
+  App,
 }
 
 impl<I: Clone> Clone for InternalQueryEvent<I> {
@@ -168,6 +254,8 @@ impl<I: Clone> Clone for InternalQueryEvent<I> {
     match self {
       Self::Ping => Self::Ping,
       Self::Conflict(e) => Self::Conflict(e.clone()),
+      Self::AcquireLock => Self::AcquireLock,
+      Self::Audit => Self::Audit,
       #[cfg(feature = "encryption")]
       Self::InstallKey => Self::InstallKey,
       #[cfg(feature = "encryption")]
@@ -176,6 +264,7 @@ impl<I: Clone> Clone for InternalQueryEvent<I> {
       Self::RemoveKey => Self::RemoveKey,
       #[cfg(feature = "encryption")]
       Self::ListKey => Self::ListKey,
+      Self::App => Self::App,
     }
   }
 }
@@ -186,6 +275,8 @@ impl<I> InternalQueryEvent<I> {
     match self {
       Self::Ping => INTERNAL_PING,
       Self::Conflict(_) => INTERNAL_CONFLICT,
+      Self::AcquireLock => INTERNAL_ACQUIRE_LOCK,
+      Self::Audit => INTERNAL_AUDIT,
       #[cfg(feature = "encryption")]
       Self::InstallKey => INTERNAL_INSTALL_KEY,
       #[cfg(feature = "encryption")]
@@ -194,6 +285,7 @@ impl<I> InternalQueryEvent<I> {
       Self::RemoveKey => INTERNAL_REMOVE_KEY,
       #[cfg(feature = "encryption")]
       Self::ListKey => INTERNAL_LIST_KEYS,
+      Self::App => APP_QUERY_PREFIX,
     }
   }
 }
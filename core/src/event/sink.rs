@@ -0,0 +1,418 @@
+use std::{
+  fmt::Write as _,
+  fs::{File, OpenOptions},
+  io::{self, BufWriter, Write},
+  path::{Path, PathBuf},
+  sync::Arc,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_channel::{bounded, Sender};
+use memberlist_core::{
+  agnostic_lite::RuntimeLite,
+  tracing,
+  transport::{AddressResolver, Transport},
+};
+use serde_json::{Map, Value};
+
+use super::*;
+
+/// The kind of an [`EventRecord`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventRecordKind {
+  /// A member event.
+  Member(MemberEventType),
+  /// A user event.
+  User,
+  /// A query.
+  Query,
+  /// A query handled by ruserf itself, e.g. the pings and the key management.
+  InternalQuery,
+}
+
+impl EventRecordKind {
+  /// Returns the string representation of the kind.
+  #[inline]
+  pub const fn as_str(&self) -> &'static str {
+    match self {
+      Self::Member(ty) => ty.as_str(),
+      Self::User => "user",
+      Self::Query => "query",
+      Self::InternalQuery => "internal-query",
+    }
+  }
+}
+
+impl core::fmt::Display for EventRecordKind {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+/// An event as recorded by an [`EventSink`].
+///
+/// The ids and the addresses of the nodes are rendered with their `Display`
+/// implementation, so the records do not depend on the transport.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventRecord {
+  timestamp: SystemTime,
+  kind: EventRecordKind,
+  name: SmolStr,
+  ltime: Option<LamportTime>,
+  source: Option<SmolStr>,
+  members: Vec<SmolStr>,
+  payload: Bytes,
+}
+
+impl EventRecord {
+  /// Returns the wall clock time the event was seen by the local node.
+  #[inline]
+  pub const fn timestamp(&self) -> SystemTime {
+    self.timestamp
+  }
+
+  /// Returns the kind of the event.
+  #[inline]
+  pub const fn kind(&self) -> EventRecordKind {
+    self.kind
+  }
+
+  /// Returns the name of the user event or the query, empty for the member
+  /// events.
+  #[inline]
+  pub const fn name(&self) -> &SmolStr {
+    &self.name
+  }
+
+  /// Returns the lamport time of the user event or the query.
+  #[inline]
+  pub const fn ltime(&self) -> Option<LamportTime> {
+    self.ltime
+  }
+
+  /// Returns the node which sent the query.
+  #[inline]
+  pub const fn source(&self) -> Option<&SmolStr> {
+    self.source.as_ref()
+  }
+
+  /// Returns the nodes of the member event.
+  #[inline]
+  pub fn members(&self) -> &[SmolStr] {
+    &self.members
+  }
+
+  /// Returns the payload of the user event or the query.
+  #[inline]
+  pub const fn payload(&self) -> &Bytes {
+    &self.payload
+  }
+
+  pub(crate) fn from_event<T, D>(event: &CrateEvent<T, D>) -> Option<Self>
+  where
+    D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+    T: Transport,
+  {
+    let record = Self {
+      timestamp: SystemTime::now(),
+      kind: EventRecordKind::User,
+      name: SmolStr::default(),
+      ltime: None,
+      source: None,
+      members: Vec::new(),
+      payload: Bytes::new(),
+    };
+
+    Some(match event {
+      CrateEvent::Member(e) => Self {
+        kind: EventRecordKind::Member(e.ty),
+        members: e
+          .members
+          .iter()
+          .map(|m| SmolStr::from(m.node().to_string()))
+          .collect(),
+        ..record
+      },
+      CrateEvent::User(e) => Self {
+        kind: EventRecordKind::User,
+        name: e.name().clone(),
+        ltime: Some(e.ltime()),
+        payload: e.payload().clone(),
+        ..record
+      },
+      CrateEvent::Query(q) => Self::from_query(EventRecordKind::Query, q, record),
+      CrateEvent::InternalQuery { query, .. } => {
+        Self::from_query(EventRecordKind::InternalQuery, query, record)
+      }
+      _ => return None,
+    })
+  }
+
+  fn from_query<T, D>(kind: EventRecordKind, query: &QueryEvent<T, D>, record: Self) -> Self
+  where
+    D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+    T: Transport,
+  {
+    Self {
+      kind,
+      name: query.name.clone(),
+      ltime: Some(query.ltime),
+      source: Some(SmolStr::from(query.from.to_string())),
+      payload: query.payload.clone(),
+      ..record
+    }
+  }
+
+  /// Returns the record as a single line of JSON, without the line feed.
+  ///
+  /// The timestamp is in milliseconds since the Unix epoch, and the payload
+  /// is hex encoded.
+  pub fn to_json_line(&self) -> String {
+    let mut obj = Map::new();
+    let timestamp = self
+      .timestamp
+      .duration_since(UNIX_EPOCH)
+      .unwrap_or(Duration::ZERO);
+    obj.insert(
+      "timestamp".into(),
+      Value::from(timestamp.as_millis() as u64),
+    );
+    obj.insert("kind".into(), Value::from(self.kind.as_str()));
+    if let Some(ltime) = self.ltime {
+      obj.insert("name".into(), Value::from(self.name.as_str()));
+      obj.insert("ltime".into(), Value::from(u64::from(ltime)));
+    }
+    if let Some(source) = &self.source {
+      obj.insert("source".into(), Value::from(source.as_str()));
+    }
+    if let EventRecordKind::Member(_) = self.kind {
+      obj.insert(
+        "members".into(),
+        self.members.iter().map(|m| m.as_str()).collect(),
+      );
+    } else {
+      let payload =
+        self
+          .payload
+          .iter()
+          .fold(String::with_capacity(self.payload.len() * 2), |mut s, b| {
+            let _ = write!(s, "{b:02x}");
+            s
+          });
+      obj.insert("payload".into(), Value::from(payload));
+    }
+    Value::Object(obj).to_string()
+  }
+}
+
+/// Records the member events, the user events and the queries seen by the
+/// local node, see [`Options::event_sink`](crate::Options::event_sink).
+///
+/// The events are recorded before the coalescing, and whether or not an
+/// event subscriber is set. The sink is called from a dedicated task, in the
+/// order of the events, so a slow sink delays the events handed to the
+/// subscriber but not the gossip.
+#[auto_impl::auto_impl(Box, Arc)]
+pub trait EventSink: Send + Sync + 'static {
+  /// Records an event. The errors are logged and the event is still
+  /// delivered.
+  fn record(&self, record: &EventRecord) -> io::Result<()>;
+}
+
+struct JsonLinesFile {
+  writer: BufWriter<File>,
+  size: u64,
+}
+
+/// An [`EventSink`] appending the records to a file, one JSON object per
+/// line, see [`EventRecord::to_json_line`].
+///
+/// Once the file grows past `max_size`, it is rotated: `events.jsonl`
+/// becomes `events.jsonl.1`, `events.jsonl.1` becomes `events.jsonl.2` and
+/// so on, and the files past `max_files` are removed.
+pub struct JsonLinesSink {
+  path: PathBuf,
+  max_size: u64,
+  max_files: usize,
+  file: parking_lot::Mutex<JsonLinesFile>,
+}
+
+impl JsonLinesSink {
+  /// Opens the file at `path` for appending, rotating it past 64 MiB and
+  /// keeping 4 rotated files.
+  pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+    let path = path.into();
+    let file = Self::open_file(&path)?;
+    Ok(Self {
+      path,
+      max_size: 64 * 1024 * 1024,
+      max_files: 4,
+      file: parking_lot::Mutex::new(file),
+    })
+  }
+
+  /// Sets the size past which the file is rotated.
+  pub fn with_max_size(mut self, max_size: u64) -> Self {
+    self.max_size = max_size;
+    self
+  }
+
+  /// Sets the number of rotated files to keep, `0` truncates the file
+  /// instead.
+  pub fn with_max_files(mut self, max_files: usize) -> Self {
+    self.max_files = max_files;
+    self
+  }
+
+  /// Returns the path of the current file.
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  fn open_file(path: &Path) -> io::Result<JsonLinesFile> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok(JsonLinesFile {
+      writer: BufWriter::new(file),
+      size,
+    })
+  }
+
+  fn rotated_path(&self, idx: usize) -> PathBuf {
+    let mut path = self.path.clone().into_os_string();
+    path.push(format!(".{idx}"));
+    PathBuf::from(path)
+  }
+
+  fn rotate(&self, file: &mut JsonLinesFile) -> io::Result<()> {
+    file.writer.flush()?;
+    if self.max_files == 0 {
+      std::fs::remove_file(&self.path)?;
+    } else {
+      for idx in (1..self.max_files).rev() {
+        let from = self.rotated_path(idx);
+        if from.exists() {
+          std::fs::rename(from, self.rotated_path(idx + 1))?;
+        }
+      }
+      std::fs::rename(&self.path, self.rotated_path(1))?;
+    }
+    *file = Self::open_file(&self.path)?;
+    Ok(())
+  }
+}
+
+impl EventSink for JsonLinesSink {
+  fn record(&self, record: &EventRecord) -> io::Result<()> {
+    let mut line = record.to_json_line();
+    line.push('\n');
+
+    let mut file = self.file.lock();
+    if file.size > 0 && file.size + line.len() as u64 > self.max_size {
+      self.rotate(&mut file)?;
+    }
+    file.writer.write_all(line.as_bytes())?;
+    // Flushed right away, the file is meant to survive a crash
+    file.writer.flush()?;
+    file.size += line.len() as u64;
+    Ok(())
+  }
+}
+
+/// Returns an event channel which records the events to `sink` before
+/// handing them to `out_tx`.
+pub(crate) fn sunk_event<T, D>(
+  out_tx: Sender<CrateEvent<T, D>>,
+  sink: Arc<dyn EventSink>,
+) -> Sender<CrateEvent<T, D>>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  let (in_tx, in_rx) = bounded(1024);
+  <T::Runtime as RuntimeLite>::spawn_detach(async move {
+    while let Ok(ev) = in_rx.recv().await {
+      if let Some(record) = EventRecord::from_event(&ev) {
+        if let Err(e) = sink.record(&record) {
+          tracing::warn!(err=%e, kind=%record.kind, "ruserf: failed to record an event");
+        }
+      }
+
+      if out_tx.send(ev).await.is_err() {
+        return;
+      }
+    }
+  });
+  in_tx
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn user_record(name: &str) -> EventRecord {
+    EventRecord {
+      timestamp: UNIX_EPOCH + Duration::from_millis(1500),
+      kind: EventRecordKind::User,
+      name: name.into(),
+      ltime: Some(LamportTime::new(7)),
+      source: None,
+      members: Vec::new(),
+      payload: Bytes::from_static(&[0xab, 0x01]),
+    }
+  }
+
+  #[test]
+  fn test_event_record_json() {
+    assert_eq!(
+      user_record("deploy").to_json_line(),
+      r#"{"kind":"user","ltime":7,"name":"deploy","payload":"ab01","timestamp":1500}"#
+    );
+
+    let record = EventRecord {
+      kind: EventRecordKind::Member(MemberEventType::Join),
+      name: SmolStr::default(),
+      ltime: None,
+      members: vec!["a".into(), "b".into()],
+      payload: Bytes::new(),
+      ..user_record("")
+    };
+    assert_eq!(
+      record.to_json_line(),
+      r#"{"kind":"member-join","members":["a","b"],"timestamp":1500}"#
+    );
+  }
+
+  #[test]
+  fn test_json_lines_sink_rotation() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.jsonl");
+    let line_len = user_record("a").to_json_line().len() as u64 + 1;
+    let sink = JsonLinesSink::open(&path)
+      .unwrap()
+      .with_max_size(line_len * 2)
+      .with_max_files(2);
+
+    for name in ["a", "b", "c", "d", "e", "f", "g"] {
+      sink.record(&user_record(name)).unwrap();
+    }
+
+    let names = |path: PathBuf| {
+      std::fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str::<Value>(l).unwrap()["name"].clone())
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(names(path.clone()), ["g"]);
+    assert_eq!(names(sink.rotated_path(1)), ["e", "f"]);
+    assert_eq!(names(sink.rotated_path(2)), ["c", "d"]);
+    assert!(!sink.rotated_path(3).exists());
+
+    // Appends to the existing file when reopened
+    drop(sink);
+    let sink = JsonLinesSink::open(&path).unwrap();
+    sink.record(&user_record("h")).unwrap();
+    assert_eq!(names(path), ["g", "h"]);
+  }
+}
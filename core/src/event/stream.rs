@@ -0,0 +1,178 @@
+use futures::{ready, stream::FusedStream};
+
+use super::*;
+
+/// Polls the next event of the channel, skipping the internal queries.
+pub(super) fn poll_event<T, D>(
+  mut rx: Pin<&mut async_channel::Receiver<CrateEvent<T, D>>>,
+  cx: &mut std::task::Context<'_>,
+) -> Poll<Option<Event<T, D>>>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  loop {
+    let event = match ready!(rx.as_mut().poll_next(cx)) {
+      Some(CrateEvent::InternalQuery { .. }) => continue,
+      Some(CrateEvent::Member(e)) => Event::Member(e),
+      Some(CrateEvent::User(e)) => Event::User(e),
+      Some(CrateEvent::Query(e)) => Event::Query(e),
+      Some(CrateEvent::ClusterFormed(e)) => Event::ClusterFormed(e),
+      Some(CrateEvent::LeaderChanged(e)) => Event::LeaderChanged(e),
+      Some(CrateEvent::Partition(e)) => Event::Partition(e),
+      Some(CrateEvent::ClusterMismatch(e)) => Event::ClusterMismatch(e),
+      Some(CrateEvent::InvalidUser(e)) => Event::InvalidUser(e),
+      None => return Poll::Ready(None),
+    };
+    return Poll::Ready(Some(event));
+  }
+}
+
+/// A [`Stream`] of the events of a Serf instance, see [`EventSubscriber::into_stream`].
+///
+/// The stream ends once the Serf instance is shut down and all the pending
+/// events have been received. Unlike [`EventSubscriber`], the stream is
+/// [`Unpin`], so it can be polled with [`StreamExt::next`](futures::StreamExt::next) directly.
+#[derive(Debug)]
+pub struct EventStream<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  rx: Pin<Box<async_channel::Receiver<CrateEvent<T, D>>>>,
+}
+
+impl<T, D> EventStream<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  /// Returns a stream of the member events only.
+  pub fn member_events(self) -> MemberEventStream<T, D> {
+    SelectEvents::new(self, |e| match e {
+      Event::Member(e) => Some(e),
+      _ => None,
+    })
+  }
+
+  /// Returns a stream of the user events only.
+  pub fn user_events(self) -> UserEventStream<T, D> {
+    SelectEvents::new(self, |e| match e {
+      Event::User(e) => Some(e),
+      _ => None,
+    })
+  }
+
+  /// Returns a stream of the queries only.
+  pub fn queries(self) -> QueryStream<T, D> {
+    SelectEvents::new(self, |e| match e {
+      Event::Query(e) => Some(e),
+      _ => None,
+    })
+  }
+}
+
+impl<T, D> From<EventSubscriber<T, D>> for EventStream<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  fn from(subscriber: EventSubscriber<T, D>) -> Self {
+    Self {
+      rx: Box::pin(subscriber.rx),
+    }
+  }
+}
+
+impl<T, D> Stream for EventStream<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  type Item = Event<T, D>;
+
+  fn poll_next(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Option<Self::Item>> {
+    poll_event(self.get_mut().rx.as_mut(), cx)
+  }
+}
+
+impl<T, D> FusedStream for EventStream<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  fn is_terminated(&self) -> bool {
+    self.rx.is_terminated()
+  }
+}
+
+/// The stream of a single kind of events, see [`EventStream::member_events`],
+/// [`EventStream::user_events`] and [`EventStream::queries`].
+#[pin_project::pin_project]
+pub struct SelectEvents<T, D, O>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  #[pin]
+  inner: EventStream<T, D>,
+  select: fn(Event<T, D>) -> Option<O>,
+}
+
+/// The stream of the member events.
+pub type MemberEventStream<T, D> = SelectEvents<
+  T,
+  D,
+  MemberEvent<
+    <T as Transport>::Id,
+    <<T as Transport>::Resolver as AddressResolver>::ResolvedAddress,
+  >,
+>;
+
+/// The stream of the user events.
+pub type UserEventStream<T, D> = SelectEvents<T, D, UserEventMessage>;
+
+/// The stream of the queries.
+pub type QueryStream<T, D> = SelectEvents<T, D, QueryEvent<T, D>>;
+
+impl<T, D, O> SelectEvents<T, D, O>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  fn new(inner: EventStream<T, D>, select: fn(Event<T, D>) -> Option<O>) -> Self {
+    Self { inner, select }
+  }
+}
+
+impl<T, D, O> Stream for SelectEvents<T, D, O>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  type Item = O;
+
+  fn poll_next(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Option<Self::Item>> {
+    let mut this = self.project();
+    loop {
+      match ready!(this.inner.as_mut().poll_next(cx)) {
+        Some(event) => {
+          if let Some(event) = (this.select)(event) {
+            return Poll::Ready(Some(event));
+          }
+        }
+        None => return Poll::Ready(None),
+      }
+    }
+  }
+}
+
+impl<T, D, O> FusedStream for SelectEvents<T, D, O>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  fn is_terminated(&self) -> bool {
+    self.inner.is_terminated()
+  }
+}
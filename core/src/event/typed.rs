@@ -0,0 +1,174 @@
+use std::marker::PhantomData;
+
+use futures::{ready, stream::FusedStream};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::*;
+
+/// A user event whose payload was decoded, see
+/// [`EventStream::typed_user_events`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypedUserEvent<E> {
+  ltime: LamportTime,
+  name: SmolStr,
+  value: E,
+}
+
+impl<E> TypedUserEvent<E> {
+  /// Returns the lamport time of the event.
+  #[inline]
+  pub const fn ltime(&self) -> LamportTime {
+    self.ltime
+  }
+
+  /// Returns the name of the event.
+  #[inline]
+  pub const fn name(&self) -> &SmolStr {
+    &self.name
+  }
+
+  /// Returns the decoded payload.
+  #[inline]
+  pub const fn value(&self) -> &E {
+    &self.value
+  }
+
+  /// Consumes the event, returning the decoded payload.
+  #[inline]
+  pub fn into_value(self) -> E {
+    self.value
+  }
+}
+
+/// A user event whose payload could not be decoded, see
+/// [`EventStream::typed_user_events`].
+#[derive(Debug)]
+pub struct MalformedUserEvent {
+  event: UserEventMessage,
+  error: serde_json::Error,
+}
+
+impl MalformedUserEvent {
+  /// Returns the event as received.
+  #[inline]
+  pub const fn event(&self) -> &UserEventMessage {
+    &self.event
+  }
+
+  /// Returns why the payload could not be decoded.
+  #[inline]
+  pub const fn error(&self) -> &serde_json::Error {
+    &self.error
+  }
+}
+
+impl core::fmt::Display for MalformedUserEvent {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(
+      f,
+      "malformed payload of user event {}: {}",
+      self.event.name(),
+      self.error
+    )
+  }
+}
+
+impl std::error::Error for MalformedUserEvent {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    Some(&self.error)
+  }
+}
+
+/// The stream of the user events of a single name, with their payload decoded
+/// from JSON, see [`EventStream::typed_user_events`].
+#[pin_project::pin_project]
+pub struct TypedUserEventStream<T, D, E>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  #[pin]
+  inner: UserEventStream<T, D>,
+  name: SmolStr,
+  _e: PhantomData<fn() -> E>,
+}
+
+impl<T, D> EventStream<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  /// Returns a stream of the user events named `name`, with their payload
+  /// decoded as sent by [`Serf::typed_user_event`].
+  ///
+  /// The events whose payload cannot be decoded into `E` are yielded as a
+  /// [`MalformedUserEvent`] rather than skipped.
+  pub fn typed_user_events<E: DeserializeOwned>(
+    self,
+    name: impl Into<SmolStr>,
+  ) -> TypedUserEventStream<T, D, E> {
+    TypedUserEventStream {
+      inner: self.user_events(),
+      name: name.into(),
+      _e: PhantomData,
+    }
+  }
+}
+
+impl<T, D, E> Stream for TypedUserEventStream<T, D, E>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+  E: DeserializeOwned,
+{
+  type Item = Result<TypedUserEvent<E>, MalformedUserEvent>;
+
+  fn poll_next(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Option<Self::Item>> {
+    let mut this = self.project();
+    loop {
+      let Some(event) = ready!(this.inner.as_mut().poll_next(cx)) else {
+        return Poll::Ready(None);
+      };
+      if event.name().ne(this.name) {
+        continue;
+      }
+
+      return Poll::Ready(Some(match serde_json::from_slice::<E>(event.payload()) {
+        Ok(value) => Ok(TypedUserEvent {
+          ltime: event.ltime(),
+          name: event.name().clone(),
+          value,
+        }),
+        Err(error) => Err(MalformedUserEvent { event, error }),
+      }));
+    }
+  }
+}
+
+impl<T, D, E> FusedStream for TypedUserEventStream<T, D, E>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+  E: DeserializeOwned,
+{
+  fn is_terminated(&self) -> bool {
+    self.inner.is_terminated()
+  }
+}
+
+impl<T, D> Serf<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  /// Broadcasts a user event whose payload is `event` encoded as JSON, to be
+  /// received with [`EventStream::typed_user_events`].
+  pub async fn typed_user_event<E: Serialize + ?Sized>(
+    &self,
+    name: impl Into<SmolStr>,
+    event: &E,
+  ) -> Result<(), Error<T, D>> {
+    let payload = serde_json::to_vec(event).map_err(Error::typed_payload)?;
+    self.user_event(name, payload, false).await
+  }
+}
@@ -0,0 +1,179 @@
+use std::{
+  io::{self, BufRead, BufReader, Write},
+  net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+  thread::JoinHandle,
+  time::Duration,
+};
+
+pub use metrics_exporter_prometheus::{BuildError, PrometheusBuilder, PrometheusHandle};
+
+/// How long a scrape may take to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Errors returned by [`PrometheusExporter::install`].
+#[derive(Debug, thiserror::Error)]
+pub enum ExporterError {
+  /// Returned when the Prometheus recorder cannot be installed.
+  #[error("ruserf: failed to install the prometheus recorder: {0}")]
+  Build(#[from] BuildError),
+  /// Returned when the HTTP listener cannot be bound.
+  #[error("ruserf: failed to bind the metrics listener: {0}")]
+  Io(#[from] io::Error),
+}
+
+/// Serves the metrics in the Prometheus text format over HTTP, at `/metrics`.
+///
+/// Besides the `ruserf.*` counters and histograms, the reaper reports the
+/// number of members by status in the `ruserf.members` gauge, and the
+/// awareness score of the local node in the `ruserf.health.score` gauge, on
+/// every reap interval.
+///
+/// The listener runs on a dedicated thread, so it does not depend on the
+/// async runtime. Dropping the exporter stops the listener.
+pub struct PrometheusExporter {
+  handle: PrometheusHandle,
+  local_addr: SocketAddr,
+  shutdown: Arc<AtomicBool>,
+  thread: Option<JoinHandle<()>>,
+}
+
+impl PrometheusExporter {
+  /// Installs a Prometheus recorder as the global [`metrics`] recorder, and serves it on `addr`.
+  pub fn install(addr: impl ToSocketAddrs) -> Result<Self, ExporterError> {
+    let handle = PrometheusBuilder::new().install_recorder()?;
+    Self::serve(handle, addr).map_err(Into::into)
+  }
+
+  /// Serves the metrics of a recorder built with a custom [`PrometheusBuilder`] on `addr`.
+  pub fn serve(handle: PrometheusHandle, addr: impl ToSocketAddrs) -> io::Result<Self> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    let shutdown = Arc::new(AtomicBool::new(false));
+
+    let thread = {
+      let handle = handle.clone();
+      let shutdown = shutdown.clone();
+      std::thread::Builder::new()
+        .name("ruserf-metrics".into())
+        .spawn(move || {
+          for stream in listener.incoming() {
+            if shutdown.load(Ordering::Acquire) {
+              break;
+            }
+
+            let Ok(stream) = stream else { continue };
+            if let Err(e) = respond(stream, &handle) {
+              memberlist_core::tracing::debug!(err=%e, "ruserf: failed to serve the metrics");
+            }
+          }
+        })?
+    };
+
+    Ok(Self {
+      handle,
+      local_addr,
+      shutdown,
+      thread: Some(thread),
+    })
+  }
+
+  /// Returns the address the listener is bound to.
+  #[inline]
+  pub const fn local_addr(&self) -> SocketAddr {
+    self.local_addr
+  }
+
+  /// Returns the handle of the Prometheus recorder.
+  #[inline]
+  pub const fn handle(&self) -> &PrometheusHandle {
+    &self.handle
+  }
+
+  /// Returns the metrics in the Prometheus text format.
+  #[inline]
+  pub fn render(&self) -> String {
+    self.handle.render()
+  }
+}
+
+impl Drop for PrometheusExporter {
+  fn drop(&mut self) {
+    self.shutdown.store(true, Ordering::Release);
+    // Wake up the listener blocked on accepting a connection
+    let _ = TcpStream::connect(self.local_addr);
+    if let Some(thread) = self.thread.take() {
+      let _ = thread.join();
+    }
+  }
+}
+
+/// Answers a single HTTP request, the connection is closed afterwards.
+fn respond(stream: TcpStream, handle: &PrometheusHandle) -> io::Result<()> {
+  stream.set_read_timeout(Some(READ_TIMEOUT))?;
+  let mut reader = BufReader::new(stream);
+  let mut request_line = String::new();
+  reader.read_line(&mut request_line)?;
+
+  // Drain the headers, the request has no body
+  let mut line = String::new();
+  loop {
+    line.clear();
+    if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+      break;
+    }
+  }
+
+  let mut stream = reader.into_inner();
+  let mut parts = request_line.split_whitespace();
+  let (status, body) = match (parts.next(), parts.next()) {
+    (Some("GET"), Some("/metrics" | "/")) => ("200 OK", handle.render()),
+    (Some("GET"), _) => ("404 Not Found", String::new()),
+    _ => ("405 Method Not Allowed", String::new()),
+  };
+
+  write!(
+    stream,
+    "HTTP/1.1 {status}\r\nContent-Type: {CONTENT_TYPE}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+    body.len()
+  )?;
+  stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Read;
+
+  use super::*;
+
+  fn get(addr: SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut resp = String::new();
+    stream.read_to_string(&mut resp).unwrap();
+    resp
+  }
+
+  #[test]
+  fn test_serve_metrics() {
+    let recorder = PrometheusBuilder::new().build_recorder();
+    let handle = recorder.handle();
+    metrics::with_local_recorder(&recorder, || {
+      metrics::counter!("ruserf.member.join").increment(3);
+      metrics::gauge!("ruserf.members", "status" => "alive").set(2.0);
+    });
+
+    let exporter = PrometheusExporter::serve(handle, "127.0.0.1:0").unwrap();
+    let resp = get(exporter.local_addr(), "/metrics");
+    assert!(resp.starts_with("HTTP/1.1 200 OK"));
+    assert!(resp.contains("ruserf_member_join 3"));
+    assert!(resp.contains("ruserf_members{status=\"alive\"} 2"));
+
+    assert!(get(exporter.local_addr(), "/foo").starts_with("HTTP/1.1 404"));
+  }
+}
@@ -0,0 +1,319 @@
+use indexmap::IndexMap;
+use memberlist_core::{
+  bytes::{BufMut, Bytes, BytesMut},
+  tracing,
+  types::TinyVec,
+};
+use smol_str::SmolStr;
+
+use crate::types::{LamportTime, UserEventMessage};
+
+/// The prefix of the user event names which carry a fragment of a larger
+/// user event, the name of the original event follows the prefix.
+///
+/// User events with this prefix are held until all the fragments of the
+/// original event arrived, they are never delivered to the event subscriber.
+pub(crate) const FRAGMENT_EVENT_PREFIX: &str = "_ruserf_frag/";
+
+/// The nonce (8 bytes), the index and the number of fragments (2 bytes each)
+/// and the flags (1 byte) precede the chunk of the original payload.
+pub(crate) const FRAGMENT_HEADER_LEN: usize = 13;
+
+const COALESCE_FLAG: u8 = 1 << 0;
+const COMPRESSED_FLAG: u8 = 1 << 1;
+
+/// Splits a payload into the payloads of the fragment events, each one
+/// carrying at most `chunk_size` bytes of the original payload.
+///
+/// Returns `None` if the payload needs more fragments than can be numbered.
+pub(crate) fn split(
+  payload: &Bytes,
+  chunk_size: usize,
+  cc: bool,
+  compressed: bool,
+) -> Option<Vec<Bytes>> {
+  debug_assert!(chunk_size > 0);
+  let count = u16::try_from(payload.len().div_ceil(chunk_size)).ok()?;
+  let nonce = rand::random::<u64>();
+  let mut flags = 0;
+  if cc {
+    flags |= COALESCE_FLAG;
+  }
+  if compressed {
+    flags |= COMPRESSED_FLAG;
+  }
+
+  Some(
+    payload
+      .chunks(chunk_size)
+      .enumerate()
+      .map(|(idx, chunk)| {
+        let mut buf = BytesMut::with_capacity(FRAGMENT_HEADER_LEN + chunk.len());
+        buf.put_u64(nonce);
+        buf.put_u16(idx as u16);
+        buf.put_u16(count);
+        buf.put_u8(flags);
+        buf.put_slice(chunk);
+        buf.freeze()
+      })
+      .collect(),
+  )
+}
+
+/// The fragments received so far of a user event.
+struct Partial {
+  chunks: Vec<Option<Bytes>>,
+  missing: usize,
+  size: usize,
+  flags: u8,
+  distribution: TinyVec<(SmolStr, SmolStr)>,
+}
+
+/// Reassembles the fragmented user events.
+///
+/// The events are keyed by their Lamport time, name and nonce. At most
+/// `max_pending` events are reassembled at once, the oldest one is dropped
+/// to make room for a new one. The events whose payload would exceed
+/// `max_size` bytes are dropped.
+pub(crate) struct FragmentBuffer {
+  pending: IndexMap<(LamportTime, SmolStr, u64), Partial>,
+  max_pending: usize,
+  max_size: usize,
+}
+
+impl FragmentBuffer {
+  pub(crate) fn new(max_pending: usize, max_size: usize) -> Self {
+    Self {
+      pending: IndexMap::new(),
+      max_pending: max_pending.max(1),
+      max_size,
+    }
+  }
+
+  /// Adds a fragment of the user event `name`, returns the original event
+  /// once all of its fragments arrived.
+  pub(crate) fn insert(
+    &mut self,
+    ltime: LamportTime,
+    name: &str,
+    payload: &Bytes,
+    distribution: &TinyVec<(SmolStr, SmolStr)>,
+  ) -> Option<UserEventMessage> {
+    if payload.len() < FRAGMENT_HEADER_LEN {
+      tracing::warn!("ruserf: received truncated fragment of user event {}", name);
+      return None;
+    }
+
+    let nonce = u64::from_be_bytes(payload[..8].try_into().unwrap());
+    let idx = u16::from_be_bytes([payload[8], payload[9]]) as usize;
+    let count = u16::from_be_bytes([payload[10], payload[11]]) as usize;
+    let flags = payload[12];
+    let chunk = payload.slice(FRAGMENT_HEADER_LEN..);
+    if idx >= count {
+      tracing::warn!(
+        "ruserf: received fragment {} of {} of user event {}",
+        idx,
+        count,
+        name
+      );
+      return None;
+    }
+
+    // All the chunks but the last one are full, so they tell how many
+    // fragments an event of the maximum size needs
+    let max_count = if idx + 1 < count {
+      self.max_size.div_ceil(chunk.len().max(1))
+    } else {
+      self.max_size
+    };
+    if count > max_count {
+      tracing::warn!(
+        "ruserf: dropped user event {} of {} fragments, exceeding the size limit of {} bytes",
+        name,
+        count,
+        self.max_size
+      );
+      return None;
+    }
+
+    let key = (ltime, SmolStr::new(name), nonce);
+    if !self.pending.contains_key(&key) {
+      if self.pending.len() >= self.max_pending {
+        if let Some(((ltime, name, _), _)) = self.pending.shift_remove_index(0) {
+          tracing::debug!(
+            "ruserf: dropped incomplete user event {} from time {}",
+            name,
+            ltime
+          );
+        }
+      }
+      self.pending.insert(
+        key.clone(),
+        Partial {
+          chunks: vec![None; count],
+          missing: count,
+          size: 0,
+          flags,
+          distribution: distribution.clone(),
+        },
+      );
+    }
+
+    let partial = self.pending.get_mut(&key)?;
+    if partial.chunks.len() != count {
+      tracing::warn!(
+        "ruserf: received fragments of user event {} with mismatched counts",
+        name
+      );
+      return None;
+    }
+    if partial.chunks[idx].is_none() {
+      partial.size += chunk.len();
+      partial.chunks[idx] = Some(chunk);
+      partial.missing -= 1;
+    }
+    if partial.size > self.max_size {
+      tracing::warn!(
+        "ruserf: dropped user event {}, exceeding the size limit of {} bytes",
+        name,
+        self.max_size
+      );
+      self.pending.shift_remove(&key);
+      return None;
+    }
+    if partial.missing > 0 {
+      return None;
+    }
+
+    let (_, partial) = self.pending.shift_remove_entry(&key)?;
+    let mut buf = BytesMut::with_capacity(partial.size);
+    for chunk in partial.chunks.into_iter().flatten() {
+      buf.put_slice(&chunk);
+    }
+    Some(UserEventMessage {
+      ltime,
+      name: key.1,
+      payload: buf.freeze(),
+      cc: partial.flags & COALESCE_FLAG != 0,
+      compressed: partial.flags & COMPRESSED_FLAG != 0,
+      distribution: partial.distribution,
+    })
+  }
+
+  /// Returns the number of events waiting for fragments.
+  #[cfg(test)]
+  fn len(&self) -> usize {
+    self.pending.len()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_fragment_reassemble() {
+    let payload = Bytes::from((0..=255u8).cycle().take(1000).collect::<Vec<_>>());
+    let fragments = split(&payload, 300, true, false).unwrap();
+    assert_eq!(fragments.len(), 4);
+
+    let mut buf = FragmentBuffer::new(4, 1000);
+    let ltime = LamportTime::new(7);
+    // Out of order and duplicated
+    for idx in [3, 1, 1, 0] {
+      assert!(buf
+        .insert(ltime, "foo", &fragments[idx], &TinyVec::new())
+        .is_none());
+    }
+    assert_eq!(buf.len(), 1);
+
+    let ev = buf
+      .insert(ltime, "foo", &fragments[2], &TinyVec::new())
+      .unwrap();
+    assert_eq!(ev.ltime, ltime);
+    assert_eq!(ev.name, "foo");
+    assert_eq!(ev.payload, payload);
+    assert!(ev.cc);
+    assert!(!ev.compressed);
+    assert_eq!(buf.len(), 0);
+  }
+
+  #[test]
+  fn test_fragment_eviction() {
+    let payload = Bytes::from(vec![1; 100]);
+    let mut buf = FragmentBuffer::new(2, 1000);
+    let first = split(&payload, 60, false, false).unwrap();
+    for ltime in 1..=3 {
+      let fragments = split(&payload, 60, false, false).unwrap();
+      let fragments = if ltime == 1 { &first } else { &fragments };
+      assert!(buf
+        .insert(
+          LamportTime::new(ltime),
+          "foo",
+          &fragments[0],
+          &TinyVec::new()
+        )
+        .is_none());
+    }
+    assert_eq!(buf.len(), 2);
+
+    // The oldest event was dropped, so it starts over
+    assert!(buf
+      .insert(LamportTime::new(1), "foo", &first[1], &TinyVec::new())
+      .is_none());
+    assert_eq!(buf.len(), 2);
+
+    // Malformed fragments are ignored
+    assert!(buf
+      .insert(
+        LamportTime::new(4),
+        "foo",
+        &Bytes::from_static(b"bar"),
+        &TinyVec::new()
+      )
+      .is_none());
+  }
+
+  #[test]
+  fn test_fragment_size_limit() {
+    let payload = Bytes::from(vec![1; 1000]);
+    let fragments = split(&payload, 100, false, false).unwrap();
+    let ltime = LamportTime::new(1);
+
+    // Too many fragments for the limit
+    let mut buf = FragmentBuffer::new(4, 500);
+    for fragment in &fragments[..9] {
+      assert!(buf
+        .insert(ltime, "foo", fragment, &TinyVec::new())
+        .is_none());
+    }
+    assert_eq!(buf.len(), 0);
+
+    // The last fragment only tells the number of fragments
+    let mut buf = FragmentBuffer::new(4, 5);
+    assert!(buf
+      .insert(ltime, "foo", &fragments[9], &TinyVec::new())
+      .is_none());
+    assert_eq!(buf.len(), 0);
+
+    // A sender lying about the number of fragments is caught by the size
+    let forge = |idx: u16, len: usize| {
+      let mut buf = BytesMut::with_capacity(FRAGMENT_HEADER_LEN + len);
+      buf.put_u64(1);
+      buf.put_u16(idx);
+      buf.put_u16(2);
+      buf.put_u8(0);
+      buf.put_slice(&vec![1; len]);
+      buf.freeze()
+    };
+    let mut buf = FragmentBuffer::new(4, 150);
+    assert!(buf
+      .insert(ltime, "foo", &forge(0, 100), &TinyVec::new())
+      .is_none());
+    assert_eq!(buf.len(), 1);
+    assert!(buf
+      .insert(ltime, "foo", &forge(1, 100), &TinyVec::new())
+      .is_none());
+    assert_eq!(buf.len(), 0);
+  }
+}
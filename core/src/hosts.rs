@@ -0,0 +1,393 @@
+use std::{
+  collections::BTreeMap,
+  fmt::{Display, Write as _},
+  io,
+  net::IpAddr,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+
+use async_channel::{bounded, Sender};
+use memberlist_core::{
+  agnostic_lite::RuntimeLite,
+  tracing,
+  transport::{AddressResolver, Transport},
+};
+use smol_str::SmolStr;
+
+use crate::{
+  delegate::Delegate,
+  event::{CrateEvent, MemberEvent, MemberEventType},
+  types::scope,
+};
+
+/// The format of the file written by the [`HostsExport`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum HostsFormat {
+  /// The `/etc/hosts` format, one `<ip> <name> <name>.<domain>` line per
+  /// member.
+  #[default]
+  Hosts,
+  /// A DNS zone file for the domain, one `A` or `AAAA` record per member.
+  Zone,
+}
+
+/// Exports the addresses of the alive members by name, see
+/// [`Options::hosts`](crate::Options::hosts).
+///
+/// The table is updated on the member events and can be queried with
+/// [`Serf::hosts`](crate::Serf::hosts), and is optionally written to a file
+/// on every change. Only the members whose address is a socket address are
+/// exported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HostsExport {
+  domain: SmolStr,
+  tag: Option<SmolStr>,
+  path: Option<PathBuf>,
+  format: HostsFormat,
+}
+
+impl Default for HostsExport {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl HostsExport {
+  /// Returns a new configuration for the `serf` domain, only kept in memory.
+  #[inline]
+  pub fn new() -> Self {
+    Self {
+      domain: SmolStr::new_inline("serf"),
+      tag: None,
+      path: None,
+      format: HostsFormat::Hosts,
+    }
+  }
+
+  /// Sets the domain the members are named under.
+  #[inline]
+  pub fn with_domain(mut self, domain: impl Into<SmolStr>) -> Self {
+    self.domain = domain.into();
+    self
+  }
+
+  /// Sets the tag whose value also names the member under the domain, e.g.
+  /// `role` resolves `web.<domain>` to every member tagged `role=web`.
+  #[inline]
+  pub fn with_tag(mut self, tag: Option<SmolStr>) -> Self {
+    self.tag = tag;
+    self
+  }
+
+  /// Sets the file the table is written to on every change.
+  #[inline]
+  pub fn with_path(mut self, path: Option<PathBuf>) -> Self {
+    self.path = path;
+    self
+  }
+
+  /// Sets the format of the file.
+  #[inline]
+  pub const fn with_format(mut self, format: HostsFormat) -> Self {
+    self.format = format;
+    self
+  }
+
+  /// Returns the domain the members are named under.
+  #[inline]
+  pub const fn domain(&self) -> &SmolStr {
+    &self.domain
+  }
+
+  /// Returns the tag whose value also names the member, if any.
+  #[inline]
+  pub const fn tag(&self) -> Option<&SmolStr> {
+    self.tag.as_ref()
+  }
+
+  /// Returns the file the table is written to, if any.
+  #[inline]
+  pub fn path(&self) -> Option<&Path> {
+    self.path.as_deref()
+  }
+
+  /// Returns the format of the file.
+  #[inline]
+  pub const fn format(&self) -> HostsFormat {
+    self.format
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Host {
+  ip: IpAddr,
+  alias: Option<SmolStr>,
+}
+
+#[derive(Debug)]
+struct HostsInner {
+  export: HostsExport,
+  hosts: BTreeMap<SmolStr, Host>,
+}
+
+/// The addresses of the alive members by name, see [`HostsExport`].
+#[derive(Debug, Clone)]
+pub struct HostsTable(Arc<parking_lot::RwLock<HostsInner>>);
+
+impl HostsTable {
+  pub(crate) fn new(export: HostsExport) -> Self {
+    Self(Arc::new(parking_lot::RwLock::new(HostsInner {
+      export,
+      hosts: BTreeMap::new(),
+    })))
+  }
+
+  /// Returns the addresses of `name`, which is either the name of a member,
+  /// optionally followed by the domain, or the value of the naming tag
+  /// followed by the domain.
+  pub fn resolve(&self, name: &str) -> Vec<IpAddr> {
+    let inner = self.0.read();
+    let domain = inner.export.domain.as_str();
+    if let Some(host) = inner.hosts.get(name) {
+      return vec![host.ip];
+    }
+
+    let Some(name) = name
+      .strip_suffix(domain)
+      .and_then(|name| name.strip_suffix('.'))
+    else {
+      return Vec::new();
+    };
+    if let Some(host) = inner.hosts.get(name) {
+      return vec![host.ip];
+    }
+    inner
+      .hosts
+      .values()
+      .filter(|h| h.alias.as_deref() == Some(name))
+      .map(|h| h.ip)
+      .collect()
+  }
+
+  /// Returns the names of the members with their address, sorted by name.
+  pub fn entries(&self) -> Vec<(SmolStr, IpAddr)> {
+    self
+      .0
+      .read()
+      .hosts
+      .iter()
+      .map(|(name, h)| (name.clone(), h.ip))
+      .collect()
+  }
+
+  /// Returns the table in the `/etc/hosts` format.
+  pub fn to_hosts_file(&self) -> String {
+    let inner = self.0.read();
+    let domain = &inner.export.domain;
+    let mut out = String::new();
+    for (name, h) in inner.hosts.iter() {
+      let _ = write!(out, "{} {name} {name}.{domain}", h.ip);
+      if let Some(alias) = &h.alias {
+        let _ = write!(out, " {alias}.{domain}");
+      }
+      out.push('\n');
+    }
+    out
+  }
+
+  /// Returns the table as a DNS zone file for the domain.
+  pub fn to_zone_file(&self) -> String {
+    let inner = self.0.read();
+    let mut out = format!("$ORIGIN {}.\n", inner.export.domain);
+    for (name, h) in inner.hosts.iter() {
+      let ty = if h.ip.is_ipv4() { "A" } else { "AAAA" };
+      let _ = writeln!(out, "{name} IN {ty} {}", h.ip);
+      if let Some(alias) = &h.alias {
+        let _ = writeln!(out, "{alias} IN {ty} {}", h.ip);
+      }
+    }
+    out
+  }
+
+  /// Applies a member event, returns `true` if the table changed.
+  pub(crate) fn apply<I, A>(&self, event: &MemberEvent<I, A>) -> bool
+  where
+    I: Display,
+    A: 'static,
+  {
+    let mut inner = self.0.write();
+    let mut changed = false;
+    for m in event.members.iter() {
+      let name = SmolStr::from(m.node().id().to_string());
+      match event.ty {
+        MemberEventType::Join | MemberEventType::Update => {
+          let Some(addr) = scope::as_socket_addr(m.node().address()) else {
+            continue;
+          };
+          let host = Host {
+            ip: addr.ip(),
+            alias: inner
+              .export
+              .tag
+              .as_ref()
+              .and_then(|tag| m.tags().get(tag).cloned()),
+          };
+          changed |= inner.hosts.insert(name, host.clone()) != Some(host);
+        }
+        MemberEventType::Leave | MemberEventType::Failed | MemberEventType::Reap => {
+          changed |= inner.hosts.remove(&name).is_some();
+        }
+        MemberEventType::Flap => {}
+      }
+    }
+    changed
+  }
+
+  /// Writes the table to the file, if any. The file is replaced at once, so
+  /// the readers never see a partial table.
+  pub(crate) fn write(&self) -> io::Result<()> {
+    let (path, format) = {
+      let inner = self.0.read();
+      let Some(path) = inner.export.path.clone() else {
+        return Ok(());
+      };
+      (path, inner.export.format)
+    };
+    let content = match format {
+      HostsFormat::Hosts => self.to_hosts_file(),
+      HostsFormat::Zone => self.to_zone_file(),
+    };
+
+    let mut tmp = path.clone().into_os_string();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, content)?;
+    std::fs::rename(tmp, path)
+  }
+}
+
+/// Returns an event channel which applies the member events to `table`
+/// before handing them to `out_tx`.
+pub(crate) fn hosts_event<T, D>(
+  out_tx: Sender<CrateEvent<T, D>>,
+  table: HostsTable,
+) -> Sender<CrateEvent<T, D>>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  let (in_tx, in_rx) = bounded(1024);
+  <T::Runtime as RuntimeLite>::spawn_detach(async move {
+    while let Ok(ev) = in_rx.recv().await {
+      if let CrateEvent::Member(e) = &ev {
+        if table.apply(e) {
+          if let Err(e) = table.write() {
+            tracing::warn!(err=%e, "ruserf: failed to write the hosts file");
+          }
+        }
+      }
+
+      if out_tx.send(ev).await.is_err() {
+        return;
+      }
+    }
+  });
+  in_tx
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::SocketAddr;
+
+  use memberlist_core::{transport::Node, types::TinyVec};
+
+  use super::*;
+  use crate::types::{Member, MemberStatus, Tags};
+
+  fn event(
+    ty: MemberEventType,
+    members: &[(&str, &str, Option<&str>)],
+  ) -> MemberEvent<SmolStr, SocketAddr> {
+    MemberEvent {
+      ty,
+      members: Arc::new(
+        members
+          .iter()
+          .map(|(name, addr, role)| {
+            let tags = role
+              .map(|role| Tags::from_iter([(SmolStr::new("role"), SmolStr::new(role))]))
+              .unwrap_or_default();
+            Member::new(
+              Node::new(SmolStr::new(name), addr.parse().unwrap()),
+              tags,
+              MemberStatus::Alive,
+            )
+          })
+          .collect::<TinyVec<_>>(),
+      ),
+    }
+  }
+
+  #[test]
+  fn test_hosts_table() {
+    let table = HostsTable::new(
+      HostsExport::new()
+        .with_domain("dc1.serf")
+        .with_tag(Some("role".into())),
+    );
+
+    assert!(table.apply(&event(
+      MemberEventType::Join,
+      &[
+        ("a", "10.0.0.1:7946", Some("web")),
+        ("b", "10.0.0.2:7946", Some("web")),
+        ("c", "[fd00::3]:7946", None),
+      ],
+    )));
+    assert!(!table.apply(&event(
+      MemberEventType::Join,
+      &[("a", "10.0.0.1:7946", Some("web"))],
+    )));
+
+    assert_eq!(table.resolve("a"), ["10.0.0.1".parse::<IpAddr>().unwrap()]);
+    assert_eq!(
+      table.resolve("c.dc1.serf"),
+      ["fd00::3".parse::<IpAddr>().unwrap()]
+    );
+    assert_eq!(table.resolve("web.dc1.serf").len(), 2);
+    assert!(table.resolve("web").is_empty());
+    assert_eq!(
+      table.to_hosts_file(),
+      "10.0.0.1 a a.dc1.serf web.dc1.serf\n10.0.0.2 b b.dc1.serf web.dc1.serf\nfd00::3 c c.dc1.serf\n"
+    );
+
+    assert!(table.apply(&event(
+      MemberEventType::Failed,
+      &[("b", "10.0.0.2:7946", Some("web"))],
+    )));
+    assert_eq!(table.resolve("web.dc1.serf").len(), 1);
+    assert_eq!(
+      table.to_zone_file(),
+      "$ORIGIN dc1.serf.\na IN A 10.0.0.1\nweb IN A 10.0.0.1\nc IN AAAA fd00::3\n"
+    );
+  }
+
+  #[test]
+  fn test_hosts_table_write() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("hosts");
+    let table = HostsTable::new(HostsExport::new().with_path(Some(path.clone())));
+    table.apply(&event(
+      MemberEventType::Join,
+      &[("a", "10.0.0.1:7946", None)],
+    ));
+    table.write().unwrap();
+    assert_eq!(
+      std::fs::read_to_string(path).unwrap(),
+      "10.0.0.1 a a.serf\n"
+    );
+  }
+}
@@ -12,6 +12,9 @@ use memberlist_core::{
 };
 use smol_str::SmolStr;
 
+mod keyring_file;
+pub(crate) use keyring_file::{load_keyring_file, write_keyring_file};
+
 use crate::event::{
   InternalQueryEvent, INTERNAL_INSTALL_KEY, INTERNAL_LIST_KEYS, INTERNAL_REMOVE_KEY,
   INTERNAL_USE_KEY,
@@ -31,7 +34,7 @@ use super::{
   getters(style = "move", vis_all = "pub"),
   setters(skip)
 )]
-#[derive(Default, Debug)]
+#[derive(Debug)]
 pub struct KeyResponse<I> {
   /// Map of node id to response message
   #[viewit(getter(
@@ -73,6 +76,19 @@ pub struct KeyResponse<I> {
   primary_keys: HashMap<SecretKey, usize>,
 }
 
+impl<I> Default for KeyResponse<I> {
+  fn default() -> Self {
+    Self {
+      messages: HashMap::new(),
+      num_nodes: 0,
+      num_resp: 0,
+      num_err: 0,
+      keys: HashMap::new(),
+      primary_keys: HashMap::new(),
+    }
+  }
+}
+
 /// KeyRequestOptions is used to contain optional parameters for a keyring operation
 pub struct KeyRequestOptions {
   /// The number of duplicate query responses to send by relaying through
@@ -80,6 +96,41 @@ pub struct KeyRequestOptions {
   pub relay_factor: u8,
 }
 
+/// The stages of [`KeyManager::rotate_keys`], in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RotationStage {
+  /// The new key is installed on all the members.
+  Install,
+  /// The members are checked to all have the new key installed.
+  VerifyInstall,
+  /// The new key becomes the primary key of all the members.
+  Use,
+  /// The members are checked to all use the new key as the primary key.
+  VerifyUse,
+  /// The previous primary keys are removed from all the members.
+  RemoveOld,
+}
+
+impl RotationStage {
+  /// Returns the name of the stage.
+  #[inline]
+  pub const fn as_str(&self) -> &'static str {
+    match self {
+      Self::Install => "install",
+      Self::VerifyInstall => "verify install",
+      Self::Use => "use",
+      Self::VerifyUse => "verify use",
+      Self::RemoveOld => "remove old",
+    }
+  }
+}
+
+impl core::fmt::Display for RotationStage {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
 /// `KeyManager` encapsulates all functionality within Serf for handling
 /// encryption keyring changes across a cluster.
 pub struct KeyManager<T, D>
@@ -173,6 +224,161 @@ where
   /// cases, it's important to verify this information and remove unneeded keys.
   pub async fn list_keys(&self) -> Result<KeyResponse<T::Id>, Error<T, D>> {
     let _mu = self.l.read().await;
+    self.list_keys_locked().await
+  }
+
+  /// Rotates the primary key of the cluster to `new_key`: installs it on all
+  /// the members, makes it their primary key, then removes the previous
+  /// primary keys, checking with a key list that every member took each
+  /// step before moving on.
+  ///
+  /// `on_progress` is called with the response of every completed stage.
+  /// If the install or the use does not reach every member, or one of their
+  /// requests fails, the members are rolled back to the previous primary key
+  /// and the new key is removed. A failure to remove the previous keys is not rolled back, as the new key
+  /// is already in use, the removal can be retried with
+  /// [`remove_key`](Self::remove_key).
+  ///
+  /// Returns the response of the last stage, the key list checking the use
+  /// when `new_key` was already the only primary key.
+  pub async fn rotate_keys(
+    &self,
+    new_key: SecretKey,
+    opts: Option<KeyRequestOptions>,
+    mut on_progress: impl FnMut(RotationStage, &KeyResponse<T::Id>),
+  ) -> Result<KeyResponse<T::Id>, Error<T, D>> {
+    let _mu = self.l.write().await;
+    let relay_factor = opts.map(|opts| opts.relay_factor);
+
+    let before = self.list_keys_locked().await?;
+    let old_keys = before
+      .primary_keys
+      .keys()
+      .filter(|k| **k != new_key)
+      .copied()
+      .collect::<Vec<_>>();
+
+    // Install, then make sure every member has the new key before using it
+    let stages = [
+      (
+        RotationStage::Install,
+        INTERNAL_INSTALL_KEY,
+        InternalQueryEvent::InstallKey,
+      ),
+      (
+        RotationStage::Use,
+        INTERNAL_USE_KEY,
+        InternalQueryEvent::UseKey,
+      ),
+    ];
+    let mut resp = KeyResponse::default();
+    for (stage, ty, event) in stages {
+      let staged = match self
+        .key_request_with(new_key, ty, relay_factor, event)
+        .await
+      {
+        Ok(staged) if Self::complete(&staged) => staged,
+        Ok(_) => {
+          let err = Error::key_rotation(stage);
+          return Err(self.rollback(new_key, &old_keys, relay_factor, err).await);
+        }
+        Err(e) => return Err(self.rollback(new_key, &old_keys, relay_factor, e).await),
+      };
+      on_progress(stage, &staged);
+
+      let verify = match stage {
+        RotationStage::Install => RotationStage::VerifyInstall,
+        _ => RotationStage::VerifyUse,
+      };
+      resp = match self.list_keys_locked().await {
+        Ok(resp) => resp,
+        Err(e) => return Err(self.rollback(new_key, &old_keys, relay_factor, e).await),
+      };
+      let keys = match verify {
+        RotationStage::VerifyInstall => &resp.keys,
+        _ => &resp.primary_keys,
+      };
+      if !Self::complete(&resp) || keys.get(&new_key) != Some(&resp.num_nodes) {
+        let err = Error::key_rotation(verify);
+        return Err(self.rollback(new_key, &old_keys, relay_factor, err).await);
+      }
+      on_progress(verify, &resp);
+    }
+
+    if old_keys.is_empty() {
+      return Ok(resp);
+    }
+    for old in old_keys {
+      resp = self
+        .key_request_with(
+          old,
+          INTERNAL_REMOVE_KEY,
+          relay_factor,
+          InternalQueryEvent::RemoveKey,
+        )
+        .await?;
+      if !Self::complete(&resp) {
+        return Err(Error::key_rotation(RotationStage::RemoveOld));
+      }
+    }
+    on_progress(RotationStage::RemoveOld, &resp);
+    Ok(resp)
+  }
+
+  /// Restores the previous primary key and removes the new key, as far as
+  /// the members still respond, then returns `err`.
+  async fn rollback(
+    &self,
+    new_key: SecretKey,
+    old_keys: &[SecretKey],
+    relay_factor: Option<u8>,
+    err: Error<T, D>,
+  ) -> Error<T, D> {
+    tracing::warn!(err=%err, "ruserf: key rotation did not reach every member, rolling back");
+    if let Some(old) = old_keys.first() {
+      if let Err(e) = self
+        .key_request_with(
+          *old,
+          INTERNAL_USE_KEY,
+          relay_factor,
+          InternalQueryEvent::UseKey,
+        )
+        .await
+      {
+        tracing::error!(err=%e, "ruserf: failed to restore the previous primary key");
+      }
+    }
+    if let Err(e) = self
+      .key_request_with(
+        new_key,
+        INTERNAL_REMOVE_KEY,
+        relay_factor,
+        InternalQueryEvent::RemoveKey,
+      )
+      .await
+    {
+      tracing::error!(err=%e, "ruserf: failed to remove the new key");
+    }
+    err
+  }
+
+  /// Returns whether every member responded without an error.
+  fn complete(resp: &KeyResponse<T::Id>) -> bool {
+    resp.num_err == 0 && resp.num_resp == resp.num_nodes
+  }
+
+  async fn key_request_with(
+    &self,
+    key: SecretKey,
+    ty: &str,
+    relay_factor: Option<u8>,
+    event: InternalQueryEvent<T::Id>,
+  ) -> Result<KeyResponse<T::Id>, Error<T, D>> {
+    let opts = relay_factor.map(|relay_factor| KeyRequestOptions { relay_factor });
+    self.handle_key_request(Some(key), ty, opts, event).await
+  }
+
+  async fn list_keys_locked(&self) -> Result<KeyResponse<T::Id>, Error<T, D>> {
     self
       .handle_key_request(None, INTERNAL_LIST_KEYS, None, InternalQueryEvent::ListKey)
       .await
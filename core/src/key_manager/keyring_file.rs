@@ -0,0 +1,197 @@
+use std::{
+  fs::{self, OpenOptions},
+  io::{self, Write},
+  path::Path,
+};
+
+use base64::{engine::general_purpose, Engine as _};
+use memberlist_core::{
+  tracing,
+  types::{SecretKey, SecretKeyring},
+};
+
+/// Reads the base64 encoded keys from the keyring file, the first one is the
+/// primary key. Returns `None` if the file does not exist.
+pub(crate) fn read_keyring_file(path: &Path) -> io::Result<Option<Vec<SecretKey>>> {
+  let data = match fs::read(path) {
+    Ok(data) => data,
+    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+    Err(e) => return Err(e),
+  };
+
+  // The keys must be as secret as the file holding them
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path)?.permissions().mode();
+    if mode & 0o077 != 0 {
+      return Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!(
+          "keyring file {} is accessible by other users (mode {:o})",
+          path.display(),
+          mode & 0o777
+        ),
+      ));
+    }
+  }
+
+  let encoded: Vec<String> =
+    serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+  if encoded.is_empty() {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidData,
+      format!("keyring file {} has no keys", path.display()),
+    ));
+  }
+
+  encoded
+    .iter()
+    .map(|k| {
+      let raw = general_purpose::STANDARD
+        .decode(k)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+      SecretKey::try_from(raw.as_slice()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    })
+    .collect::<io::Result<Vec<_>>>()
+    .map(Some)
+}
+
+/// Installs the keys of the keyring file into the keyring, replacing the ones
+/// the transport was configured with. If the file does not exist yet, the
+/// keyring is saved to it instead.
+pub(crate) async fn load_keyring_file(path: &Path, keyring: &SecretKeyring) -> io::Result<()> {
+  let Some(keys) = read_keyring_file(path)? else {
+    return write_keyring_file(path, keyring).await;
+  };
+
+  for key in keys.iter() {
+    keyring.insert(*key).await;
+  }
+  keyring.use_key(&keys[0]).await.map_err(io::Error::other)?;
+  for key in keyring.keys().await {
+    if !keys.contains(&key) {
+      keyring.remove(&key).await.map_err(io::Error::other)?;
+    }
+  }
+
+  tracing::info!(
+    "ruserf: loaded {} keys from keyring file {}",
+    keys.len(),
+    path.display()
+  );
+  Ok(())
+}
+
+/// Saves the keyring to the keyring file, the primary key first.
+///
+/// The keys are written to a temporary file next to the keyring file, which
+/// then replaces it, so a crash never leaves a truncated keyring behind.
+pub(crate) async fn write_keyring_file(path: &Path, keyring: &SecretKeyring) -> io::Result<()> {
+  let encoded_keys = keyring
+    .keys()
+    .await
+    .map(|k| general_purpose::STANDARD.encode(k))
+    .collect::<Vec<_>>();
+
+  let mut tmp_path = path.as_os_str().to_owned();
+  tmp_path.push(".tmp");
+
+  let mut opts = OpenOptions::new();
+  opts.truncate(true).write(true).create(true);
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::OpenOptionsExt;
+    opts.mode(0o600);
+  }
+  // TODO: I don't know how to set permissions on windows
+  // need helps :)
+
+  let mut file = opts.open(&tmp_path)?;
+  serde_json::to_writer_pretty(&mut file, &encoded_keys).map_err(io::Error::other)?;
+  file.flush()?;
+  file.sync_all()?;
+  drop(file);
+  fs::rename(&tmp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const KEY1: &str = "T9jncgl9mbLus+baTTa7q7nPSUrXwbDi2dhbtqir37s=";
+  const KEY2: &str = "HvY8ubRZMgafUOWvrOadwOckVa1wN3QWAo46FVKbVN8=";
+
+  fn key(encoded: &str) -> SecretKey {
+    SecretKey::try_from(
+      general_purpose::STANDARD
+        .decode(encoded)
+        .unwrap()
+        .as_slice(),
+    )
+    .unwrap()
+  }
+
+  fn write_private(path: &Path, contents: &str) {
+    fs::write(path, contents).unwrap();
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      fs::set_permissions(path, fs::Permissions::from_mode(0o600)).unwrap();
+    }
+  }
+
+  #[tokio::test]
+  async fn test_keyring_file_round_trip() {
+    let td = tempfile::tempdir().unwrap();
+    let path = td.path().join("keyring.json");
+    assert!(read_keyring_file(&path).unwrap().is_none());
+
+    let keyring = SecretKeyring::with_keys(key(KEY1), [key(KEY2)].into_iter());
+    load_keyring_file(&path, &keyring).await.unwrap();
+    assert_eq!(
+      read_keyring_file(&path).unwrap().unwrap(),
+      vec![key(KEY1), key(KEY2)]
+    );
+    assert!(!td.path().join("keyring.json.tmp").exists());
+
+    // The file replaces the keys of the transport
+    write_private(&path, &format!("[\"{KEY2}\"]"));
+    let keyring = SecretKeyring::new(key(KEY1));
+    load_keyring_file(&path, &keyring).await.unwrap();
+    assert_eq!(keyring.primary_key().await, key(KEY2));
+    assert_eq!(keyring.keys().await.count(), 1);
+  }
+
+  #[tokio::test]
+  async fn test_keyring_file_invalid() {
+    let td = tempfile::tempdir().unwrap();
+    let path = td.path().join("keyring.json");
+
+    write_private(&path, "[]");
+    assert_eq!(
+      read_keyring_file(&path).unwrap_err().kind(),
+      io::ErrorKind::InvalidData
+    );
+
+    write_private(&path, "[\"not a key\"]");
+    assert_eq!(
+      read_keyring_file(&path).unwrap_err().kind(),
+      io::ErrorKind::InvalidData
+    );
+
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+
+      write_private(&path, &format!("[\"{KEY1}\"]"));
+      fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+      let err = read_keyring_file(&path).unwrap_err();
+      assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+      fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+      assert_eq!(read_keyring_file(&path).unwrap().unwrap(), vec![key(KEY1)]);
+    }
+  }
+}
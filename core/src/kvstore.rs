@@ -0,0 +1,184 @@
+use std::{collections::HashMap, pin::Pin, task::Poll};
+
+use async_channel::{Receiver, RecvError, Sender, TryRecvError};
+use futures::Stream;
+use memberlist_core::bytes::Bytes;
+use smol_str::SmolStr;
+
+use crate::types::LamportTime;
+
+/// The prefix of the user event names which carry the key/value entries.
+///
+/// User events with this prefix are applied to the store instead of being
+/// delivered to the event subscriber.
+pub const KV_EVENT_PREFIX: &str = "_ruserf_kv/";
+
+/// An entry of the gossip-replicated key/value store, see [`Serf::kv_put`](crate::Serf::kv_put).
+#[viewit::viewit(getters(style = "ref"), setters(skip))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KvEntry {
+  /// The key of the entry
+  #[viewit(getter(const, attrs(doc = "Returns the key of the entry")))]
+  key: SmolStr,
+  /// The value of the entry
+  #[viewit(getter(const, attrs(doc = "Returns the value of the entry")))]
+  value: Bytes,
+  /// The Lamport time of the write
+  #[viewit(getter(
+    const,
+    style = "move",
+    attrs(doc = "Returns the Lamport time of the write")
+  ))]
+  ltime: LamportTime,
+}
+
+impl KvEntry {
+  /// Returns `true` if this write wins over the `other` one. The later write
+  /// wins, concurrent writes are ordered by value so every node converges.
+  fn supersedes(&self, other: &Self) -> bool {
+    (self.ltime, &self.value) > (other.ltime, &other.value)
+  }
+}
+
+/// Receives the updates of the entries whose key starts with the watched prefix,
+/// see [`Serf::kv_watch`](crate::Serf::kv_watch).
+#[pin_project::pin_project]
+#[derive(Debug)]
+pub struct KvWatcher {
+  #[pin]
+  rx: Receiver<KvEntry>,
+}
+
+impl KvWatcher {
+  /// Receives the next update, returns an error once the Serf instance is dropped.
+  pub async fn recv(&self) -> Result<KvEntry, RecvError> {
+    self.rx.recv().await
+  }
+
+  /// Tries to receive the next update without waiting.
+  pub fn try_recv(&self) -> Result<KvEntry, TryRecvError> {
+    self.rx.try_recv()
+  }
+}
+
+impl Stream for KvWatcher {
+  type Item = KvEntry;
+
+  fn poll_next(self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Option<Self::Item>> {
+    self.project().rx.poll_next(cx)
+  }
+}
+
+/// The local replica of the key/value store.
+pub(crate) struct KvStore {
+  entries: HashMap<SmolStr, KvEntry>,
+  max_entries: usize,
+  watchers: Vec<(SmolStr, Sender<KvEntry>)>,
+}
+
+impl KvStore {
+  pub(crate) fn new(max_entries: usize) -> Self {
+    Self {
+      entries: HashMap::new(),
+      max_entries,
+      watchers: Vec::new(),
+    }
+  }
+
+  pub(crate) fn get(&self, key: &str) -> Option<KvEntry> {
+    self.entries.get(key).cloned()
+  }
+
+  pub(crate) fn watch(&mut self, prefix: SmolStr) -> KvWatcher {
+    let (tx, rx) = async_channel::unbounded();
+    self.watchers.push((prefix, tx));
+    KvWatcher { rx }
+  }
+
+  /// Applies a write, returns `false` if it lost against the stored entry.
+  pub(crate) fn apply(&mut self, key: &str, value: Bytes, ltime: LamportTime) -> bool {
+    let entry = KvEntry {
+      key: SmolStr::new(key),
+      value,
+      ltime,
+    };
+
+    match self.entries.get(key) {
+      Some(current) if !entry.supersedes(current) => return false,
+      Some(_) => {}
+      None => {
+        if self.max_entries == 0 {
+          return false;
+        }
+
+        // Make room by evicting the least recently written entry
+        if self.entries.len() >= self.max_entries {
+          let oldest = self
+            .entries
+            .values()
+            .min_by(|a, b| (a.ltime, &a.key).cmp(&(b.ltime, &b.key)))
+            .map(|e| e.key.clone());
+          if let Some(oldest) = oldest {
+            self.entries.remove(&oldest);
+          }
+        }
+      }
+    }
+
+    self.watchers.retain(|(prefix, tx)| {
+      if !entry.key.starts_with(prefix.as_str()) {
+        return !tx.is_closed();
+      }
+      tx.try_send(entry.clone()).is_ok()
+    });
+    self.entries.insert(entry.key.clone(), entry);
+    true
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_last_writer_wins() {
+    let mut store = KvStore::new(8);
+    assert!(store.apply("a", Bytes::from_static(b"1"), 2.into()));
+    assert!(!store.apply("a", Bytes::from_static(b"0"), 1.into()));
+    assert_eq!(store.get("a").unwrap().value().as_ref(), b"1");
+
+    // concurrent writes resolve the same way on every node
+    assert!(store.apply("a", Bytes::from_static(b"2"), 2.into()));
+    assert!(!store.apply("a", Bytes::from_static(b"1"), 2.into()));
+    assert_eq!(store.get("a").unwrap().value().as_ref(), b"2");
+  }
+
+  #[test]
+  fn test_bounded() {
+    let mut store = KvStore::new(2);
+    assert!(store.apply("a", Bytes::new(), 1.into()));
+    assert!(store.apply("b", Bytes::new(), 3.into()));
+    assert!(store.apply("c", Bytes::new(), 2.into()));
+    assert!(store.get("a").is_none());
+    assert!(store.get("b").is_some());
+    assert!(store.get("c").is_some());
+
+    assert!(!KvStore::new(0).apply("a", Bytes::new(), 1.into()));
+  }
+
+  #[test]
+  fn test_watch() {
+    let mut store = KvStore::new(8);
+    let watcher = store.watch("svc/".into());
+    store.apply("svc/web", Bytes::from_static(b"1"), 1.into());
+    store.apply("node/a", Bytes::from_static(b"1"), 1.into());
+
+    let entry = watcher.try_recv().unwrap();
+    assert_eq!(entry.key(), "svc/web");
+    assert!(watcher.try_recv().is_err());
+
+    drop(watcher);
+    store.apply("svc/db", Bytes::from_static(b"1"), 2.into());
+    assert!(store.watchers.is_empty());
+  }
+}
@@ -6,16 +6,91 @@
 #![cfg_attr(docsrs, feature(doc_cfg))]
 #![cfg_attr(docsrs, allow(unused_attributes))]
 
+mod addresses;
+
+/// Periodic anti-entropy audit of the member map.
+pub mod anti_entropy;
+
+mod app_meta;
+
+/// Application queries answered by registered handlers.
+pub mod app_query;
+
 pub(crate) mod broadcast;
 
+mod cluster;
+
 mod coalesce;
 
+/// Clocks driving the background tasks.
+pub mod clock;
+
+/// User event, query payload, push/pull state and snapshot compression.
+pub mod compression;
+
+/// Resolution of the node name conflicts.
+pub mod conflict;
+
 /// Coordinate.
 pub mod coordinate;
 
+/// mDNS peer discovery.
+#[cfg(feature = "mdns")]
+#[cfg_attr(docsrs, doc(cfg(feature = "mdns")))]
+pub mod discover;
+
+/// Leader election over the alive members.
+pub mod election;
+
 /// Events for [`Serf`]
 pub mod event;
 
+/// Prometheus exposition of the metrics.
+#[cfg(feature = "metrics-exporter")]
+#[cfg_attr(docsrs, doc(cfg(feature = "metrics-exporter")))]
+pub mod exporter;
+
+/// Export of the member addresses by name.
+pub mod hosts;
+
+/// Gossip-replicated key/value store.
+pub mod kvstore;
+
+/// Scheduling of the reaper and the reconnector.
+pub mod maintenance;
+
+/// In-memory transport to simulate large clusters in tests.
+#[cfg(feature = "test-util")]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+pub mod memory;
+
+/// Best-effort distributed lock.
+pub mod lock;
+
+/// Filtering and pagination of the member list.
+pub mod member_filter;
+
+/// Detection of the likely network partitions.
+pub mod partition;
+
+/// Pinging members for their coordinate and load.
+pub mod ping;
+
+/// Rate limiting of the incoming queries.
+pub mod rate_limit;
+
+/// Circuit breaking of the query response relays.
+pub mod relay;
+
+/// Segments scoping the user events to a subset of the members.
+pub mod segment;
+
+/// Health of the background tasks.
+pub mod task;
+
+/// Validation of the user event payloads by name.
+pub mod validator;
+
 /// Errors for `ruserf`.
 pub mod error;
 
@@ -33,12 +108,18 @@ pub mod types;
 #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
 pub mod key_manager;
 
+mod fragment;
+
+mod secure;
+
 mod serf;
 pub use serf::*;
 
 mod snapshot;
 pub use snapshot::*;
 
+mod version;
+
 fn invalid_data_io_error<E: std::error::Error + Send + Sync + 'static>(e: E) -> std::io::Error {
   std::io::Error::new(std::io::ErrorKind::InvalidData, e)
 }
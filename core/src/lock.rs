@@ -0,0 +1,344 @@
+use std::{collections::HashMap, time::Duration};
+
+use async_channel::Sender;
+use futures::FutureExt;
+use memberlist_core::{
+  agnostic_lite::RuntimeLite,
+  bytes::{BufMut, Bytes, BytesMut},
+  tracing,
+  transport::{AddressResolver, Transport},
+};
+use smol_str::SmolStr;
+
+use crate::{
+  delegate::{Delegate, TransformDelegate},
+  error::Error,
+  event::{InternalQueryEvent, INTERNAL_ACQUIRE_LOCK},
+  types::{Epoch, MemberStatus},
+  Serf,
+};
+
+/// The prefix of the user event names which renew or release a lock lease.
+pub(crate) const LOCK_EVENT_PREFIX: &str = "_ruserf_lock/";
+const RENEW: &str = "renew/";
+const RELEASE: &str = "release/";
+
+struct Grant<I> {
+  holder: I,
+  granted_at: Epoch,
+  ttl: Duration,
+}
+
+impl<I> Grant<I> {
+  fn is_expired(&self) -> bool {
+    self.granted_at.elapsed() > self.ttl
+  }
+}
+
+/// The lock leases granted by the local node.
+pub(crate) struct LockTable<I> {
+  grants: HashMap<SmolStr, Grant<I>>,
+}
+
+impl<I> Default for LockTable<I> {
+  fn default() -> Self {
+    Self {
+      grants: HashMap::new(),
+    }
+  }
+}
+
+impl<I: Eq> LockTable<I> {
+  /// Grants or renews the lease of the lock, returns `false` if another
+  /// member holds an unexpired lease.
+  pub(crate) fn grant(&mut self, name: &str, holder: I, ttl: Duration) -> bool {
+    match self.grants.get(name) {
+      Some(grant) if grant.holder != holder && !grant.is_expired() => false,
+      _ => {
+        self.grants.insert(
+          SmolStr::new(name),
+          Grant {
+            holder,
+            granted_at: Epoch::now(),
+            ttl,
+          },
+        );
+        true
+      }
+    }
+  }
+
+  /// Releases the lease of the lock if it is held by `holder`.
+  pub(crate) fn release(&mut self, name: &str, holder: &I) {
+    if self.grants.get(name).is_some_and(|g| g.holder.eq(holder)) {
+      self.grants.remove(name);
+    }
+  }
+
+  /// Releases all the leases held by a member which is gone.
+  pub(crate) fn release_member(&mut self, holder: &I) {
+    self.grants.retain(|_, g| g.holder.ne(holder));
+  }
+}
+
+/// Encodes the payload of the acquire query.
+pub(crate) fn encode_acquire(name: &str, ttl: Duration) -> Bytes {
+  let mut buf = BytesMut::with_capacity(8 + name.len());
+  buf.put_u64(ttl.as_millis() as u64);
+  buf.put_slice(name.as_bytes());
+  buf.freeze()
+}
+
+/// Decodes the payload of the acquire query.
+pub(crate) fn decode_acquire(src: &[u8]) -> Option<(&str, Duration)> {
+  if src.len() < 8 {
+    return None;
+  }
+  let (ttl, name) = src.split_at(8);
+  let ttl = u64::from_be_bytes(ttl.try_into().ok()?);
+  Some((core::str::from_utf8(name).ok()?, Duration::from_millis(ttl)))
+}
+
+/// A lease operation carried by a user event.
+pub(crate) enum LeaseUpdate<'a> {
+  Renew(&'a str),
+  Release(&'a str),
+}
+
+impl<'a> LeaseUpdate<'a> {
+  /// Parses the name of a user event without the [`LOCK_EVENT_PREFIX`].
+  pub(crate) fn parse(name: &'a str) -> Option<Self> {
+    if let Some(name) = name.strip_prefix(RENEW) {
+      Some(Self::Renew(name))
+    } else {
+      name.strip_prefix(RELEASE).map(Self::Release)
+    }
+  }
+}
+
+/// A best-effort distributed mutex, see [`Serf::lock`].
+///
+/// The lease is renewed in the background for as long as the lock is held.
+/// Dropping the lock stops the renewals and the lease expires after its ttl,
+/// use [`release`](DistributedLock::release) to free it right away.
+pub struct DistributedLock<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  serf: Serf<T, D>,
+  name: SmolStr,
+  ttl: Duration,
+  _stop_tx: Sender<()>,
+}
+
+impl<T, D> DistributedLock<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  pub(crate) async fn acquire(
+    serf: Serf<T, D>,
+    name: SmolStr,
+    ttl: Duration,
+  ) -> Result<Self, Error<T, D>> {
+    if ttl.is_zero() {
+      return Err(Error::lock_not_acquired(name));
+    }
+
+    let alive = serf
+      .inner
+      .members
+      .read()
+      .await
+      .states
+      .values()
+      .filter(|m| m.member.status == MemberStatus::Alive)
+      .count();
+    let majority = alive / 2 + 1;
+
+    let ty = InternalQueryEvent::AcquireLock;
+    let resp = serf
+      .internal_query(
+        SmolStr::new(INTERNAL_ACQUIRE_LOCK),
+        encode_acquire(&name, ttl),
+        None,
+        ty,
+      )
+      .await?;
+
+    let mut granted = 0usize;
+    let mut denied = 0usize;
+    let resp_rx = resp.response_rx();
+    while let Ok(r) = resp_rx.recv().await {
+      if r.payload().first() == Some(&1) {
+        granted += 1;
+      } else {
+        denied += 1;
+      }
+
+      // Stop waiting once the outcome is decided
+      if granted >= majority || denied > alive - majority {
+        break;
+      }
+    }
+
+    if granted < majority {
+      tracing::debug!(
+        "ruserf: failed to acquire lock {} [{} / {}]",
+        name,
+        granted,
+        alive
+      );
+      // Give back the leases which were granted
+      if let Err(e) = serf.lease_event(RELEASE, &name, Duration::ZERO).await {
+        tracing::warn!(err=%e, "ruserf: failed to release lock {}", name);
+      }
+      return Err(Error::lock_not_acquired(name));
+    }
+
+    let (stop_tx, stop_rx) = async_channel::bounded::<()>(1);
+    let this = serf.clone();
+    let lock = name.clone();
+    <T::Runtime as RuntimeLite>::spawn_detach(async move {
+      loop {
+        futures::select! {
+          _ = <T::Runtime as RuntimeLite>::sleep(ttl / 3).fuse() => {
+            if let Err(e) = this.lease_event(RENEW, &lock, ttl).await {
+              tracing::warn!(err=%e, "ruserf: failed to renew lock {}", lock);
+            }
+          }
+          _ = stop_rx.recv().fuse() => return,
+        }
+      }
+    });
+
+    Ok(Self {
+      serf,
+      name,
+      ttl,
+      _stop_tx: stop_tx,
+    })
+  }
+
+  /// Returns the name of the lock.
+  #[inline]
+  pub fn name(&self) -> &SmolStr {
+    &self.name
+  }
+
+  /// Returns the ttl of the lease.
+  #[inline]
+  pub const fn ttl(&self) -> Duration {
+    self.ttl
+  }
+
+  /// Stops renewing the lease and asks the other members to release it.
+  pub async fn release(self) -> Result<(), Error<T, D>> {
+    self
+      .serf
+      .lease_event(RELEASE, &self.name, Duration::ZERO)
+      .await
+  }
+}
+
+impl<T, D> Serf<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  /// Broadcasts a lease renewal or release of the local node.
+  async fn lease_event(&self, op: &str, name: &str, ttl: Duration) -> Result<(), Error<T, D>> {
+    let id = self.local_id();
+    let id_len = <D as TransformDelegate>::id_encoded_len(id);
+    let mut payload = BytesMut::with_capacity(8 + id_len);
+    payload.put_u64(ttl.as_millis() as u64);
+    payload.resize(8 + id_len, 0);
+    <D as TransformDelegate>::encode_id(id, &mut payload[8..])
+      .map_err(Error::transform_delegate)?;
+
+    self
+      .user_event(
+        format!("{LOCK_EVENT_PREFIX}{op}{name}"),
+        payload.freeze(),
+        false,
+      )
+      .await
+  }
+
+  /// Applies a lease renewal or release carried by a user event.
+  pub(crate) fn handle_lease_event(&self, name: &str, payload: &[u8]) {
+    let Some(update) = LeaseUpdate::parse(name) else {
+      tracing::warn!("ruserf: unknown lock event {}", name);
+      return;
+    };
+
+    if payload.len() < 8 {
+      tracing::warn!("ruserf: invalid lock event payload");
+      return;
+    }
+    let (ttl, id) = payload.split_at(8);
+    let ttl = Duration::from_millis(u64::from_be_bytes(ttl.try_into().unwrap()));
+    let holder = match <D as TransformDelegate>::decode_id(id) {
+      Ok((_, id)) => id,
+      Err(e) => {
+        tracing::warn!(err=%e, "ruserf: failed to decode lock holder");
+        return;
+      }
+    };
+
+    let mut locks = self.inner.locks.lock();
+    match update {
+      LeaseUpdate::Renew(name) => {
+        if !locks.grant(name, holder, ttl) {
+          tracing::debug!(
+            "ruserf: refused to renew lock {} held by another member",
+            name
+          );
+        }
+      }
+      LeaseUpdate::Release(name) => locks.release(name, &holder),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_lock_table() {
+    let ttl = Duration::from_secs(10);
+    let mut table = LockTable::default();
+    assert!(table.grant("a", 1, ttl));
+    // renewals of the holder are granted
+    assert!(table.grant("a", 1, ttl));
+    assert!(!table.grant("a", 2, ttl));
+    assert!(table.grant("b", 2, ttl));
+
+    // only the holder can release the lock
+    table.release("a", &2);
+    assert!(!table.grant("a", 2, ttl));
+    table.release("a", &1);
+    assert!(table.grant("a", 2, ttl));
+
+    table.release_member(&2);
+    assert!(table.grant("a", 1, ttl));
+    assert!(table.grant("b", 1, ttl));
+
+    // expired leases can be taken over
+    assert!(table.grant("c", 1, Duration::ZERO));
+    std::thread::sleep(Duration::from_millis(1));
+    assert!(table.grant("c", 2, ttl));
+  }
+
+  #[test]
+  fn test_acquire_payload() {
+    let payload = encode_acquire("db/primary", Duration::from_millis(1500));
+    assert_eq!(
+      decode_acquire(&payload),
+      Some(("db/primary", Duration::from_millis(1500)))
+    );
+    assert!(decode_acquire(&payload[..4]).is_none());
+  }
+}
@@ -0,0 +1,210 @@
+use std::{future::Future, time::Duration};
+
+use async_channel::{unbounded, Receiver, Sender};
+use futures::{
+  future::{BoxFuture, Either},
+  FutureExt,
+};
+use memberlist_core::agnostic_lite::RuntimeLite;
+
+use crate::{
+  clock::Timer,
+  types::{MemberStatus, Members},
+};
+
+/// A background maintenance task of [`Serf`](crate::Serf), see
+/// [`MaintenanceScheduler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MaintenanceTask {
+  /// Reaps the failed and the left members past their timeout, and the
+  /// expired intents.
+  Reap,
+  /// Attempts to reconnect to a random failed member.
+  Reconnect,
+}
+
+/// The membership a [`MaintenanceScheduler`] bases the next round on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MaintenanceState {
+  alive: usize,
+  failed: usize,
+  left: usize,
+  interval: Duration,
+}
+
+impl MaintenanceState {
+  /// Returns the number of the alive members.
+  #[inline]
+  pub const fn alive(&self) -> usize {
+    self.alive
+  }
+
+  /// Returns the number of the failed members.
+  #[inline]
+  pub const fn failed(&self) -> usize {
+    self.failed
+  }
+
+  /// Returns the number of the left members.
+  #[inline]
+  pub const fn left(&self) -> usize {
+    self.left
+  }
+
+  /// Returns the configured interval of the task, i.e. the
+  /// [`reap_interval`](crate::Options::reap_interval) or the
+  /// [`reconnect_interval`](crate::Options::reconnect_interval).
+  #[inline]
+  pub const fn interval(&self) -> Duration {
+    self.interval
+  }
+
+  pub(crate) fn new<I, A>(members: &Members<I, A>, interval: Duration) -> Self {
+    let failed = members.failed_members.len();
+    let left = members.left_members.len();
+    let alive = members
+      .states
+      .values()
+      .filter(|m| m.member.status == MemberStatus::Alive)
+      .count();
+    Self {
+      alive,
+      failed,
+      left,
+      interval,
+    }
+  }
+}
+
+/// When the next round of a [`MaintenanceTask`] runs.
+pub enum Schedule {
+  /// After the given delay, measured with the
+  /// [`Options::clock`](crate::Options::clock).
+  After(Duration),
+  /// Once the future completes.
+  When(BoxFuture<'static, ()>),
+}
+
+/// Schedules the rounds of the reaper and the reconnector, see
+/// [`Options::maintenance_scheduler`](crate::Options::maintenance_scheduler).
+///
+/// By default, the rounds run at the fixed
+/// [`reap_interval`](crate::Options::reap_interval) and
+/// [`reconnect_interval`](crate::Options::reconnect_interval). A scheduler
+/// can adapt them to the membership, e.g. reconnect less often when many
+/// members are down, or leave them to the test driving a simulation.
+#[auto_impl::auto_impl(Box, Arc)]
+pub trait MaintenanceScheduler: Send + Sync + 'static {
+  /// Returns when the next round of `task` runs. Called once the previous
+  /// round is done.
+  fn schedule(&self, task: MaintenanceTask, state: &MaintenanceState) -> Schedule;
+}
+
+/// A [`MaintenanceScheduler`] whose rounds only run when
+/// [`ManualScheduler::trigger`] is called, so simulations can drive the
+/// maintenance step by step.
+#[derive(Debug, Clone)]
+pub struct ManualScheduler {
+  reap: (Sender<()>, Receiver<()>),
+  reconnect: (Sender<()>, Receiver<()>),
+}
+
+impl Default for ManualScheduler {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl ManualScheduler {
+  /// Returns a scheduler with no round triggered.
+  #[inline]
+  pub fn new() -> Self {
+    Self {
+      reap: unbounded(),
+      reconnect: unbounded(),
+    }
+  }
+
+  /// Runs a round of `task`. The rounds triggered while the previous one is
+  /// still running are queued.
+  pub fn trigger(&self, task: MaintenanceTask) {
+    let _ = self.channel(task).0.try_send(());
+  }
+
+  fn channel(&self, task: MaintenanceTask) -> &(Sender<()>, Receiver<()>) {
+    match task {
+      MaintenanceTask::Reap => &self.reap,
+      MaintenanceTask::Reconnect => &self.reconnect,
+    }
+  }
+}
+
+impl MaintenanceScheduler for ManualScheduler {
+  fn schedule(&self, task: MaintenanceTask, _state: &MaintenanceState) -> Schedule {
+    let rx = self.channel(task).1.clone();
+    Schedule::When(
+      async move {
+        if rx.recv().await.is_err() {
+          std::future::pending::<()>().await;
+        }
+      }
+      .boxed(),
+    )
+  }
+}
+
+/// Returns a future which completes once the next round of `task` is due.
+pub(crate) fn next_round<R: RuntimeLite>(
+  scheduler: Option<&dyn MaintenanceScheduler>,
+  timer: &Timer,
+  task: MaintenanceTask,
+  state: &MaintenanceState,
+) -> impl Future<Output = ()> + Send + 'static {
+  let schedule = match scheduler {
+    Some(s) => s.schedule(task, state),
+    None => Schedule::After(state.interval),
+  };
+  match schedule {
+    Schedule::After(delay) => Either::Left(timer.sleep::<R>(delay)),
+    Schedule::When(fut) => Either::Right(fut),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn test_manual_scheduler() {
+    let scheduler = ManualScheduler::new();
+    let state = MaintenanceState {
+      alive: 3,
+      failed: 1,
+      left: 0,
+      interval: Duration::from_secs(15),
+    };
+
+    let Schedule::When(reap) = scheduler.schedule(MaintenanceTask::Reap, &state) else {
+      panic!("expected a manual schedule");
+    };
+    let Schedule::When(reconnect) = scheduler.schedule(MaintenanceTask::Reconnect, &state) else {
+      panic!("expected a manual schedule");
+    };
+    futures::pin_mut!(reap, reconnect);
+    assert!(futures::poll!(reap.as_mut()).is_pending());
+
+    scheduler.trigger(MaintenanceTask::Reap);
+    assert!(futures::poll!(reap.as_mut()).is_ready());
+    assert!(futures::poll!(reconnect.as_mut()).is_pending());
+
+    // Triggered ahead of the next round
+    scheduler.trigger(MaintenanceTask::Reconnect);
+    scheduler.trigger(MaintenanceTask::Reconnect);
+    assert!(futures::poll!(reconnect.as_mut()).is_ready());
+    let Schedule::When(next) = scheduler.schedule(MaintenanceTask::Reconnect, &state) else {
+      panic!("expected a manual schedule");
+    };
+    futures::pin_mut!(next);
+    assert!(futures::poll!(next).is_ready());
+  }
+}
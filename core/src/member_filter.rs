@@ -0,0 +1,338 @@
+use std::{fmt::Display, ops::BitOr};
+
+use memberlist_core::{
+  transport::{AddressResolver, Transport},
+  types::OneOrMore,
+  CheapClone,
+};
+use regex::Regex;
+use smol_str::SmolStr;
+
+use crate::{
+  delegate::Delegate,
+  types::{Member, MemberStatus},
+  Serf,
+};
+
+/// A set of [`MemberStatus`]es, see [`MemberFilter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MemberStatusMask(u8);
+
+impl MemberStatusMask {
+  /// Matches no status.
+  pub const EMPTY: Self = Self(0);
+  /// Matches the [`MemberStatus::Alive`] status.
+  pub const ALIVE: Self = Self::of(MemberStatus::Alive);
+  /// Matches the [`MemberStatus::Leaving`] status.
+  pub const LEAVING: Self = Self::of(MemberStatus::Leaving);
+  /// Matches the [`MemberStatus::Left`] status.
+  pub const LEFT: Self = Self::of(MemberStatus::Left);
+  /// Matches the [`MemberStatus::Failed`] status.
+  pub const FAILED: Self = Self::of(MemberStatus::Failed);
+  /// Matches every status.
+  pub const ALL: Self = Self(0b11111);
+
+  /// Returns a mask matching only the given status.
+  #[inline]
+  pub const fn of(status: MemberStatus) -> Self {
+    Self(1 << status as u8)
+  }
+
+  /// Returns `true` if the mask matches the given status.
+  #[inline]
+  pub const fn contains(&self, status: MemberStatus) -> bool {
+    self.0 & Self::of(status).0 != 0
+  }
+}
+
+impl Default for MemberStatusMask {
+  fn default() -> Self {
+    Self::ALL
+  }
+}
+
+impl From<MemberStatus> for MemberStatusMask {
+  fn from(status: MemberStatus) -> Self {
+    Self::of(status)
+  }
+}
+
+impl BitOr for MemberStatusMask {
+  type Output = Self;
+
+  fn bitor(self, rhs: Self) -> Self {
+    Self(self.0 | rhs.0)
+  }
+}
+
+impl BitOr<MemberStatus> for MemberStatusMask {
+  type Output = Self;
+
+  fn bitor(self, rhs: MemberStatus) -> Self {
+    self | Self::of(rhs)
+  }
+}
+
+/// Selects the members returned by [`Serf::members_filtered`] and [`Serf::members_paged`].
+///
+/// A member matches if its status is in the status mask, every tag filter
+/// matches and its id matches the name glob. The default filter matches every member.
+///
+/// The members matching the tag filters are looked up through an index of
+/// the tags of the known members, so filtering by tag does not scan the
+/// whole member map.
+#[derive(Debug, Clone, Default)]
+pub struct MemberFilter {
+  status: MemberStatusMask,
+  tags: Vec<(SmolStr, Regex)>,
+  name: Option<SmolStr>,
+}
+
+impl MemberFilter {
+  /// Returns a filter matching the members with one of the given statuses.
+  #[inline]
+  pub fn new(status: impl Into<MemberStatusMask>) -> Self {
+    Self {
+      status: status.into(),
+      tags: Vec::new(),
+      name: None,
+    }
+  }
+
+  /// Only matches the members whose tag `key` matches the regular expression
+  /// `pattern` in its entirety.
+  pub fn with_tag(
+    mut self,
+    key: impl Into<SmolStr>,
+    pattern: impl AsRef<str>,
+  ) -> Result<Self, regex::Error> {
+    let re = Regex::new(&format!("^(?:{})$", pattern.as_ref()))?;
+    self.tags.push((key.into(), re));
+    Ok(self)
+  }
+
+  /// Only matches the members whose id matches the glob, where `*` matches
+  /// any sequence of characters and `?` matches a single character.
+  #[inline]
+  pub fn with_name(mut self, glob: impl Into<SmolStr>) -> Self {
+    self.name = Some(glob.into());
+    self
+  }
+
+  /// Returns the status mask.
+  #[inline]
+  pub const fn status(&self) -> MemberStatusMask {
+    self.status
+  }
+
+  /// Returns the name glob, if any.
+  #[inline]
+  pub fn name(&self) -> Option<&SmolStr> {
+    self.name.as_ref()
+  }
+
+  /// Returns the tag keys and the regular expressions their values must match.
+  pub(crate) fn tags(&self) -> impl Iterator<Item = (&str, &Regex)> {
+    self.tags.iter().map(|(key, re)| (key.as_str(), re))
+  }
+
+  /// Returns `true` if the member matches the filter.
+  pub fn matches<I: Display, A>(&self, member: &Member<I, A>) -> bool {
+    self.status.contains(*member.status())
+      && self.tags.iter().all(|(key, re)| {
+        member
+          .tags()
+          .get(key)
+          .is_some_and(|value| re.is_match(value))
+      })
+      && self.name.as_ref().map_or(true, |glob| {
+        glob_match(glob, &member.node().id().to_string())
+      })
+  }
+}
+
+/// Matches `name` against a glob supporting the `*` and `?` wildcards.
+fn glob_match(glob: &str, name: &str) -> bool {
+  let glob = glob.chars().collect::<Vec<_>>();
+  let name = name.chars().collect::<Vec<_>>();
+  let (mut g, mut n) = (0, 0);
+  // The position of the last `*` and the name position it was tried at
+  let mut backtrack = None;
+
+  while n < name.len() {
+    match glob.get(g) {
+      Some('*') => {
+        backtrack = Some((g, n));
+        g += 1;
+      }
+      Some(&c) if c == '?' || c == name[n] => {
+        g += 1;
+        n += 1;
+      }
+      _ => match backtrack {
+        // Let the last `*` consume one more character
+        Some((star, at)) => {
+          backtrack = Some((star, at + 1));
+          g = star + 1;
+          n = at + 1;
+        }
+        None => return false,
+      },
+    }
+  }
+
+  glob[g..].iter().all(|&c| c == '*')
+}
+
+/// Iterates over the members matching a [`MemberFilter`] page by page, see [`Serf::members_paged`].
+///
+/// Members are ordered by the string representation of their ids. Each page
+/// reflects the membership at the time it is fetched, so members joining or
+/// leaving between two pages may be missed.
+pub struct MemberPages<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  serf: Serf<T, D>,
+  filter: MemberFilter,
+  page_size: usize,
+  cursor: Option<String>,
+  done: bool,
+}
+
+impl<T, D> MemberPages<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  pub(crate) fn new(serf: Serf<T, D>, filter: MemberFilter, page_size: usize) -> Self {
+    Self {
+      serf,
+      filter,
+      page_size: page_size.max(1),
+      cursor: None,
+      done: false,
+    }
+  }
+
+  /// Returns the next page of members, or `None` once all the matching
+  /// members have been returned.
+  pub async fn next_page(
+    &mut self,
+  ) -> Option<OneOrMore<Member<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>>> {
+    if self.done {
+      return None;
+    }
+
+    let members = self.serf.inner.members.read().await;
+    let mut matched = members
+      .states_tagged(self.filter.tags())
+      .filter(|s| self.filter.matches(&s.member))
+      .map(|s| (s.member.node().id().to_string(), &s.member))
+      .filter(|(id, _)| self.cursor.as_ref().map_or(true, |cursor| id > cursor))
+      .collect::<Vec<_>>();
+
+    // Only sort the members of this page
+    if matched.len() > self.page_size {
+      matched.select_nth_unstable_by(self.page_size, |a, b| a.0.cmp(&b.0));
+      matched.truncate(self.page_size);
+    } else {
+      self.done = true;
+    }
+    matched.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+    let (cursor, _) = matched.last()?;
+    self.cursor = Some(cursor.clone());
+    Some(matched.into_iter().map(|(_, m)| m.cheap_clone()).collect())
+  }
+}
+
+impl<T, D> Serf<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  /// Returns a point-in-time snapshot of the members matching the filter,
+  /// only the matching members are cloned.
+  pub async fn members_filtered(
+    &self,
+    filter: &MemberFilter,
+  ) -> OneOrMore<Member<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>> {
+    self
+      .inner
+      .members
+      .read()
+      .await
+      .states_tagged(filter.tags())
+      .filter(|s| filter.matches(&s.member))
+      .map(|s| s.member.cheap_clone())
+      .collect()
+  }
+
+  /// Returns an iterator over the members matching the filter, fetching at
+  /// most `page_size` members at a time.
+  pub fn members_paged(&self, filter: MemberFilter, page_size: usize) -> MemberPages<T, D> {
+    MemberPages::new(self.clone(), filter, page_size)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::SocketAddr;
+
+  use memberlist_core::types::Node;
+
+  use super::*;
+  use crate::types::Tags;
+
+  fn member(id: &str, status: MemberStatus, tags: &[(&str, &str)]) -> Member<SmolStr, SocketAddr> {
+    let tags = tags
+      .iter()
+      .map(|(k, v)| (SmolStr::new(k), SmolStr::new(v)))
+      .collect::<Tags>();
+    Member::new(
+      Node::new(id.into(), "127.0.0.1:7946".parse().unwrap()),
+      tags,
+      status,
+    )
+  }
+
+  #[test]
+  fn test_status_mask() {
+    let mask = MemberStatusMask::ALIVE | MemberStatus::Failed;
+    assert!(mask.contains(MemberStatus::Alive));
+    assert!(mask.contains(MemberStatus::Failed));
+    assert!(!mask.contains(MemberStatus::Left));
+    assert!(!MemberStatusMask::EMPTY.contains(MemberStatus::None));
+    assert!(MemberStatusMask::ALL.contains(MemberStatus::None));
+  }
+
+  #[test]
+  fn test_glob_match() {
+    assert!(glob_match("web-*", "web-1"));
+    assert!(glob_match("web-?", "web-1"));
+    assert!(!glob_match("web-?", "web-12"));
+    assert!(glob_match("*-db-*", "eu-db-primary"));
+    assert!(glob_match("*", ""));
+    assert!(glob_match("a*b*c", "aXbYbZc"));
+    assert!(!glob_match("a*b*c", "aXbYbZ"));
+  }
+
+  #[test]
+  fn test_member_filter() {
+    let filter = MemberFilter::new(MemberStatus::Alive)
+      .with_tag("role", "web|api")
+      .unwrap()
+      .with_name("node-*");
+    assert!(filter.matches(&member("node-1", MemberStatus::Alive, &[("role", "web")])));
+    assert!(!filter.matches(&member("node-1", MemberStatus::Failed, &[("role", "web")])));
+    // tag patterns match the whole value
+    assert!(!filter.matches(&member("node-1", MemberStatus::Alive, &[("role", "webby")])));
+    assert!(!filter.matches(&member("node-1", MemberStatus::Alive, &[])));
+    assert!(!filter.matches(&member("db-1", MemberStatus::Alive, &[("role", "api")])));
+
+    assert!(MemberFilter::default().matches(&member("x", MemberStatus::Left, &[])));
+    assert!(MemberFilter::default().with_tag("role", "(").is_err());
+  }
+}
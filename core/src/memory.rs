@@ -0,0 +1,651 @@
+use std::{
+  borrow::Cow,
+  collections::HashMap,
+  marker::PhantomData,
+  net::{Ipv4Addr, SocketAddr},
+  sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc,
+  },
+  time::{Duration, Instant},
+};
+
+use async_channel::{Receiver, Sender};
+use memberlist_core::{
+  agnostic_lite::RuntimeLite,
+  transport::{
+    packet_stream, promised_stream, resolver::socket_addr::SocketAddrResolver, Id, Lpe,
+    MaybeResolvedAddress, PacketProducer, PacketSubscriber, StreamProducer, StreamSubscriber,
+    TimeoutableReadStream, TimeoutableWriteStream, Transport, TransportError, Wire,
+  },
+  types::{Message, OneOrMore, Packet, TinyVec},
+};
+use parking_lot::RwLock;
+
+use crate::{error::Error, DefaultDelegate, Options, Serf};
+
+#[cfg(feature = "encryption")]
+use memberlist_core::types::SecretKeyring;
+
+/// The port of the addresses allocated by a [`MemoryNetwork`].
+const PORT: u16 = 7946;
+
+/// The default maximum size of a packet.
+const DEFAULT_MAX_PAYLOAD_SIZE: usize = 1400;
+
+/// The id and the address of a message passed over a [`MemoryTransport`].
+type Msg<I> = Message<I, SocketAddr>;
+
+/// Errors returned by the [`MemoryTransport`].
+#[derive(Debug, thiserror::Error)]
+pub enum MemoryTransportError {
+  /// Returned when another transport of the network is bound to the address.
+  #[error("ruserf: address {0} is already in use")]
+  AddressInUse(SocketAddr),
+  /// Returned when the remote node is not on the network, or the link to it drops everything.
+  #[error("ruserf: address {0} is unreachable")]
+  Unreachable(SocketAddr),
+  /// Returned when the other side of a stream has gone away.
+  #[error("ruserf: connection closed")]
+  ConnectionClosed,
+  /// Returned when the deadline of a stream is exceeded.
+  #[error("ruserf: stream deadline exceeded")]
+  Timeout,
+  /// Custom error.
+  #[error("ruserf: {0}")]
+  Custom(Cow<'static, str>),
+}
+
+impl TransportError for MemoryTransportError {
+  fn is_remote_failure(&self) -> bool {
+    matches!(
+      self,
+      Self::Unreachable(_) | Self::ConnectionClosed | Self::Timeout
+    )
+  }
+
+  fn custom(err: Cow<'static, str>) -> Self {
+    Self::Custom(err)
+  }
+}
+
+/// The conditions of the link from one node to another.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Link {
+  latency: Duration,
+  loss: f64,
+}
+
+impl Link {
+  /// Returns a link without latency or loss.
+  #[inline]
+  pub const fn new() -> Self {
+    Self {
+      latency: Duration::ZERO,
+      loss: 0.0,
+    }
+  }
+
+  /// Sets how long a packet or a stream message takes to arrive.
+  #[inline]
+  pub const fn with_latency(mut self, latency: Duration) -> Self {
+    self.latency = latency;
+    self
+  }
+
+  /// Sets the probability, in `[0, 1]`, that a packet is dropped.
+  ///
+  /// Streams are reliable, but a link with a loss of `1` refuses to dial.
+  #[inline]
+  pub const fn with_loss(mut self, loss: f64) -> Self {
+    self.loss = loss;
+    self
+  }
+
+  /// Returns how long a packet or a stream message takes to arrive.
+  #[inline]
+  pub const fn latency(&self) -> Duration {
+    self.latency
+  }
+
+  /// Returns the probability that a packet is dropped.
+  #[inline]
+  pub const fn loss(&self) -> f64 {
+    self.loss
+  }
+
+  #[inline]
+  fn is_down(&self) -> bool {
+    self.loss >= 1.0
+  }
+
+  #[inline]
+  fn drops(&self) -> bool {
+    self.loss > 0.0 && rand::random::<f64>() < self.loss
+  }
+}
+
+struct Endpoint<I> {
+  packet: PacketProducer<I, SocketAddr>,
+  stream: StreamProducer<SocketAddr, MemoryStream<I>>,
+}
+
+impl<I: Clone> Clone for Endpoint<I> {
+  fn clone(&self) -> Self {
+    Self {
+      packet: self.packet.clone(),
+      stream: self.stream.clone(),
+    }
+  }
+}
+
+struct NetworkInner<I> {
+  endpoints: RwLock<HashMap<SocketAddr, Endpoint<I>>>,
+  links: RwLock<HashMap<(SocketAddr, SocketAddr), Link>>,
+  default_link: RwLock<Link>,
+  next_addr: AtomicU32,
+}
+
+/// An in-process network connecting the [`MemoryTransport`]s built with it.
+///
+/// Every transport gets a fake address, and the latency and loss of the link
+/// between any two of them can be set, e.g. to partition the cluster. Cloning
+/// the network is cheap, all clones share the same nodes and links.
+pub struct MemoryNetwork<I> {
+  inner: Arc<NetworkInner<I>>,
+}
+
+impl<I> Clone for MemoryNetwork<I> {
+  fn clone(&self) -> Self {
+    Self {
+      inner: self.inner.clone(),
+    }
+  }
+}
+
+impl<I> core::fmt::Debug for MemoryNetwork<I> {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_struct("MemoryNetwork")
+      .field("nodes", &self.inner.endpoints.read().len())
+      .field("default_link", &*self.inner.default_link.read())
+      .finish()
+  }
+}
+
+impl<I> Default for MemoryNetwork<I> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<I> MemoryNetwork<I> {
+  /// Returns an empty network, whose links have no latency or loss.
+  pub fn new() -> Self {
+    Self {
+      inner: Arc::new(NetworkInner {
+        endpoints: RwLock::new(HashMap::new()),
+        links: RwLock::new(HashMap::new()),
+        default_link: RwLock::new(Link::new()),
+        next_addr: AtomicU32::new(1),
+      }),
+    }
+  }
+
+  /// Returns a fresh address on the network, in the `10.0.0.0/8` range.
+  pub fn allocate_address(&self) -> SocketAddr {
+    let n = self.inner.next_addr.fetch_add(1, Ordering::Relaxed);
+    let [_, b, c, d] = n.to_be_bytes();
+    SocketAddr::new(Ipv4Addr::new(10, b, c, d).into(), PORT)
+  }
+
+  /// Returns the number of transports bound to the network.
+  pub fn num_nodes(&self) -> usize {
+    self.inner.endpoints.read().len()
+  }
+
+  /// Sets the link used between the nodes which have no specific one.
+  pub fn set_default_link(&self, link: Link) {
+    *self.inner.default_link.write() = link;
+  }
+
+  /// Sets the link from `from` to `to`. The link in the other direction is left as is.
+  pub fn set_link(&self, from: SocketAddr, to: SocketAddr, link: Link) {
+    self.inner.links.write().insert((from, to), link);
+  }
+
+  /// Removes the link from `from` to `to`, so the default link is used again.
+  pub fn clear_link(&self, from: SocketAddr, to: SocketAddr) {
+    self.inner.links.write().remove(&(from, to));
+  }
+
+  /// Returns the link from `from` to `to`.
+  pub fn link(&self, from: SocketAddr, to: SocketAddr) -> Link {
+    self
+      .inner
+      .links
+      .read()
+      .get(&(from, to))
+      .copied()
+      .unwrap_or_else(|| *self.inner.default_link.read())
+  }
+
+  /// Cuts the links between the two groups of nodes, in both directions.
+  pub fn partition(&self, left: &[SocketAddr], right: &[SocketAddr]) {
+    let down = Link::new().with_loss(1.0);
+    let mut links = self.inner.links.write();
+    for l in left {
+      for r in right {
+        links.insert((*l, *r), down);
+        links.insert((*r, *l), down);
+      }
+    }
+  }
+
+  /// Removes all the links set on the network, so the default link is used everywhere.
+  pub fn heal(&self) {
+    self.inner.links.write().clear();
+  }
+}
+
+impl<I: Id> MemoryNetwork<I> {
+  fn endpoint(&self, addr: &SocketAddr) -> Option<Endpoint<I>> {
+    self.inner.endpoints.read().get(addr).cloned()
+  }
+
+  /// Creates `n` [`Serf`]s on the network and joins all of them to the first one.
+  ///
+  /// The ids are built by `id` from the index of the node.
+  pub async fn spawn_serfs<R: RuntimeLite>(
+    &self,
+    n: usize,
+    opts: Options,
+    mut id: impl FnMut(usize) -> I,
+  ) -> Result<
+    Vec<Serf<MemoryTransport<I, R>>>,
+    Error<MemoryTransport<I, R>, DefaultDelegate<MemoryTransport<I, R>>>,
+  > {
+    let mut serfs = Vec::with_capacity(n);
+    for idx in 0..n {
+      let transport_opts = MemoryTransportOptions::new(id(idx), self.clone());
+      serfs.push(Serf::new(transport_opts, opts.clone()).await?);
+    }
+
+    if let Some((first, rest)) = serfs.split_first() {
+      let node = first
+        .advertise_node()
+        .map_address(MaybeResolvedAddress::resolved);
+      // One at a time, so the first node is not flooded with push/pull requests
+      for s in rest {
+        s.join(node.clone(), false).await?;
+      }
+    }
+    Ok(serfs)
+  }
+}
+
+/// The options used to create a [`MemoryTransport`].
+pub struct MemoryTransportOptions<I> {
+  id: I,
+  network: MemoryNetwork<I>,
+  address: Option<SocketAddr>,
+  max_payload_size: usize,
+}
+
+impl<I> MemoryTransportOptions<I> {
+  /// Returns the options of a transport with the given id on the network.
+  ///
+  /// The network allocates the address of the transport, unless one is set.
+  pub fn new(id: I, network: MemoryNetwork<I>) -> Self {
+    Self {
+      id,
+      network,
+      address: None,
+      max_payload_size: DEFAULT_MAX_PAYLOAD_SIZE,
+    }
+  }
+
+  /// Sets the address of the transport.
+  pub fn with_address(mut self, address: SocketAddr) -> Self {
+    self.address = Some(address);
+    self
+  }
+
+  /// Sets the maximum size of a packet.
+  pub fn with_max_payload_size(mut self, size: usize) -> Self {
+    self.max_payload_size = size;
+    self
+  }
+
+  /// Returns the id of the transport.
+  #[inline]
+  pub const fn id(&self) -> &I {
+    &self.id
+  }
+
+  /// Returns the network the transport joins.
+  #[inline]
+  pub const fn network(&self) -> &MemoryNetwork<I> {
+    &self.network
+  }
+
+  /// Returns the address of the transport, if set.
+  #[inline]
+  pub const fn address(&self) -> Option<SocketAddr> {
+    self.address
+  }
+
+  /// Returns the maximum size of a packet.
+  #[inline]
+  pub const fn max_payload_size(&self) -> usize {
+    self.max_payload_size
+  }
+}
+
+/// One side of a promised connection between two [`MemoryTransport`]s.
+pub struct MemoryStream<I> {
+  tx: Sender<Msg<I>>,
+  rx: Receiver<Msg<I>>,
+  latency: Duration,
+  read_deadline: Option<Instant>,
+  write_deadline: Option<Instant>,
+}
+
+impl<I> MemoryStream<I> {
+  fn pair(latency: Duration) -> (Self, Self) {
+    let (ltx, rrx) = async_channel::unbounded();
+    let (rtx, lrx) = async_channel::unbounded();
+    (
+      Self {
+        tx: ltx,
+        rx: lrx,
+        latency,
+        read_deadline: None,
+        write_deadline: None,
+      },
+      Self {
+        tx: rtx,
+        rx: rrx,
+        latency,
+        read_deadline: None,
+        write_deadline: None,
+      },
+    )
+  }
+}
+
+// The stream is never pinned in place, the channels are only polled through `recv`
+impl<I> Unpin for MemoryStream<I> {}
+
+impl<I> Drop for MemoryStream<I> {
+  fn drop(&mut self) {
+    self.tx.close();
+  }
+}
+
+impl<I: Send + Sync + 'static> TimeoutableReadStream for MemoryStream<I> {
+  fn set_read_deadline(&mut self, deadline: Option<Instant>) {
+    self.read_deadline = deadline;
+  }
+
+  fn read_deadline(&self) -> Option<Instant> {
+    self.read_deadline
+  }
+}
+
+impl<I: Send + Sync + 'static> TimeoutableWriteStream for MemoryStream<I> {
+  fn set_write_deadline(&mut self, deadline: Option<Instant>) {
+    self.write_deadline = deadline;
+  }
+
+  fn write_deadline(&self) -> Option<Instant> {
+    self.write_deadline
+  }
+}
+
+/// A [`Transport`] passing the messages in-process, over a [`MemoryNetwork`].
+///
+/// It spins up thousands of nodes in a single test without touching the
+/// sockets of the host, and the links of the network control the latency and
+/// the loss between any two nodes.
+pub struct MemoryTransport<I, R> {
+  id: I,
+  addr: SocketAddr,
+  network: MemoryNetwork<I>,
+  max_payload_size: usize,
+  packet_rx: PacketSubscriber<I, SocketAddr>,
+  stream_rx: StreamSubscriber<SocketAddr, MemoryStream<I>>,
+  shutdown: AtomicBool,
+  _runtime: PhantomData<R>,
+}
+
+impl<I: core::fmt::Debug, R> core::fmt::Debug for MemoryTransport<I, R> {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_struct("MemoryTransport")
+      .field("id", &self.id)
+      .field("addr", &self.addr)
+      .finish()
+  }
+}
+
+impl<I, R> MemoryTransport<I, R> {
+  /// Returns the network the transport is bound to.
+  #[inline]
+  pub const fn network(&self) -> &MemoryNetwork<I> {
+    &self.network
+  }
+}
+
+impl<I, R> Transport for MemoryTransport<I, R>
+where
+  I: Id,
+  R: RuntimeLite,
+{
+  type Error = MemoryTransportError;
+  type Id = I;
+  type Resolver = SocketAddrResolver<R>;
+  type Stream = MemoryStream<I>;
+  type Wire = Lpe<I, SocketAddr>;
+  type Runtime = R;
+  type Options = MemoryTransportOptions<I>;
+
+  async fn new(opts: Self::Options) -> Result<Self, Self::Error> {
+    let MemoryTransportOptions {
+      id,
+      network,
+      address,
+      max_payload_size,
+    } = opts;
+    let addr = address.unwrap_or_else(|| network.allocate_address());
+
+    let (packet_tx, packet_rx) = packet_stream::<Self>();
+    let (stream_tx, stream_rx) = promised_stream::<Self>();
+    {
+      let mut endpoints = network.inner.endpoints.write();
+      if endpoints.contains_key(&addr) {
+        return Err(MemoryTransportError::AddressInUse(addr));
+      }
+      endpoints.insert(
+        addr,
+        Endpoint {
+          packet: packet_tx,
+          stream: stream_tx,
+        },
+      );
+    }
+
+    Ok(Self {
+      id,
+      addr,
+      network,
+      max_payload_size,
+      packet_rx,
+      stream_rx,
+      shutdown: AtomicBool::new(false),
+      _runtime: PhantomData,
+    })
+  }
+
+  async fn resolve(&self, addr: &SocketAddr) -> Result<SocketAddr, Self::Error> {
+    Ok(*addr)
+  }
+
+  fn local_id(&self) -> &Self::Id {
+    &self.id
+  }
+
+  fn local_address(&self) -> &SocketAddr {
+    &self.addr
+  }
+
+  fn advertise_address(&self) -> &SocketAddr {
+    &self.addr
+  }
+
+  #[cfg(feature = "encryption")]
+  fn keyring(&self) -> Option<&SecretKeyring> {
+    None
+  }
+
+  #[cfg(feature = "encryption")]
+  fn encryption_enabled(&self) -> bool {
+    false
+  }
+
+  fn max_payload_size(&self) -> usize {
+    self.max_payload_size
+  }
+
+  fn packets_header_overhead(&self) -> usize {
+    0
+  }
+
+  fn packet_overhead(&self) -> usize {
+    0
+  }
+
+  fn blocked_address(&self, _addr: &SocketAddr) -> Result<(), Self::Error> {
+    Ok(())
+  }
+
+  async fn read_message(
+    &self,
+    _from: &SocketAddr,
+    conn: &mut Self::Stream,
+  ) -> Result<(usize, Msg<I>), Self::Error> {
+    let msg = match conn.read_deadline {
+      Some(deadline) => R::timeout_at(deadline, conn.rx.recv())
+        .await
+        .map_err(|_| MemoryTransportError::Timeout)?,
+      None => conn.rx.recv().await,
+    }
+    .map_err(|_| MemoryTransportError::ConnectionClosed)?;
+    Ok((Self::Wire::encoded_len(&msg), msg))
+  }
+
+  async fn send_message(&self, conn: &mut Self::Stream, msg: Msg<I>) -> Result<usize, Self::Error> {
+    if conn.write_deadline.is_some_and(|d| Instant::now() >= d) {
+      return Err(MemoryTransportError::Timeout);
+    }
+    if !conn.latency.is_zero() {
+      R::sleep(conn.latency).await;
+    }
+
+    let len = Self::Wire::encoded_len(&msg);
+    conn
+      .tx
+      .try_send(msg)
+      .map_err(|_| MemoryTransportError::ConnectionClosed)?;
+    Ok(len)
+  }
+
+  async fn send_packet(
+    &self,
+    addr: &SocketAddr,
+    packet: Msg<I>,
+  ) -> Result<(usize, Instant), Self::Error> {
+    self
+      .send_packets(addr, core::iter::once(packet).collect())
+      .await
+  }
+
+  async fn send_packets(
+    &self,
+    addr: &SocketAddr,
+    packets: TinyVec<Msg<I>>,
+  ) -> Result<(usize, Instant), Self::Error> {
+    let sent = Instant::now();
+    let len = packets.iter().map(Self::Wire::encoded_len).sum();
+
+    // Like UDP, packets to an unknown address or over a lossy link vanish silently
+    let link = self.network.link(self.addr, *addr);
+    let Some(endpoint) = self.network.endpoint(addr) else {
+      return Ok((len, sent));
+    };
+    if link.drops() {
+      return Ok((len, sent));
+    }
+
+    let packet = Packet::new(
+      packets.into_iter().collect::<OneOrMore<_>>(),
+      self.addr,
+      sent,
+    );
+    if link.latency().is_zero() {
+      let _ = endpoint.packet.try_send(packet);
+    } else {
+      R::spawn_detach(async move {
+        R::sleep(link.latency()).await;
+        let _ = endpoint.packet.send(packet).await;
+      });
+    }
+    Ok((len, sent))
+  }
+
+  async fn dial_with_deadline(
+    &self,
+    addr: &SocketAddr,
+    deadline: Instant,
+  ) -> Result<Self::Stream, Self::Error> {
+    let link = self.network.link(self.addr, *addr);
+    let endpoint = self
+      .network
+      .endpoint(addr)
+      .filter(|_| !link.is_down())
+      .ok_or(MemoryTransportError::Unreachable(*addr))?;
+
+    let (local, remote) = MemoryStream::pair(link.latency());
+    R::timeout_at(deadline, endpoint.stream.send(self.addr, remote))
+      .await
+      .map_err(|_| MemoryTransportError::Timeout)?
+      .map_err(|_| MemoryTransportError::Unreachable(*addr))?;
+    Ok(local)
+  }
+
+  async fn cache_stream(
+    &self,
+    _addr: &SocketAddr,
+    _stream: Self::Stream,
+  ) -> Result<(), Self::Error> {
+    Ok(())
+  }
+
+  fn packet(&self) -> PacketSubscriber<I, SocketAddr> {
+    self.packet_rx.clone()
+  }
+
+  fn stream(&self) -> StreamSubscriber<SocketAddr, Self::Stream> {
+    self.stream_rx.clone()
+  }
+
+  async fn shutdown(&self) -> Result<(), Self::Error> {
+    if self.shutdown.swap(true, Ordering::AcqRel) {
+      return Ok(());
+    }
+
+    if let Some(endpoint) = self.network.inner.endpoints.write().remove(&self.addr) {
+      endpoint.packet.close();
+      endpoint.stream.close();
+    }
+    Ok(())
+  }
+}
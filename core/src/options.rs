@@ -4,7 +4,23 @@ use arc_swap::ArcSwap;
 pub use memberlist_core::Options as MemberlistOptions;
 use smol_str::SmolStr;
 
-use super::types::{DelegateVersion, ProtocolVersion, Tags};
+#[cfg(any(test, feature = "test"))]
+use super::delegate::MessageDropper;
+use super::{
+  anti_entropy::AntiEntropyAudit,
+  clock::Clock,
+  compression::Compressor,
+  conflict::ConflictResolution,
+  delegate::SnapshotDelegate,
+  election::ElectionOptions,
+  event::{EventBackpressure, EventSink},
+  hosts::HostsExport,
+  maintenance::MaintenanceScheduler,
+  partition::PartitionDetection,
+  rate_limit::RateLimit,
+  relay::RelayCircuitBreaker,
+  types::{DelegateVersion, ProtocolVersion, Tags, TombstoneEviction},
+};
 
 fn tags(tags: &Arc<ArcSwap<Tags>>) -> Arc<Tags> {
   tags.load().clone()
@@ -78,6 +94,19 @@ pub struct Options {
   )]
   leave_propagate_delay: Duration,
 
+  /// The overall deadline of [`Serf::shutdown`](crate::Serf::shutdown), raced
+  /// by every phase of the teardown, including the shutdown of the memberlist
+  /// and the [`ShutdownDelegate`](crate::delegate::ShutdownDelegate) callbacks.
+  /// The phases not completed at the deadline are abandoned, the background
+  /// tasks are detached, and the shutdown fails with a timeout error. `None`
+  /// waits for every phase.
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  #[viewit(
+    getter(const, attrs(doc = "Returns the overall deadline of the shutdown.")),
+    setter(attrs(doc = "Sets the overall deadline of the shutdown."))
+  )]
+  shutdown_timeout: Option<Duration>,
+
   /// The settings below relate to Serf's event coalescence feature. Serf
   /// is able to coalesce multiple events into single events in order to
   /// reduce the amount of noise that is sent along the event channel. For example
@@ -139,6 +168,17 @@ pub struct Options {
   )]
   user_quiescent_period: Duration,
 
+  /// Groups the member events of the same type dispatched within this
+  /// window into a single event, e.g. when hundreds of nodes join at once.
+  /// Unlike the coalescence, no event is dropped and their order is kept,
+  /// so a few milliseconds are enough. Disabled if zero, the default.
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  #[viewit(
+    getter(const, attrs(doc = "Returns the member event batching window.")),
+    setter(attrs(doc = "Sets the member event batching window."))
+  )]
+  member_event_batch_window: Duration,
+
   /// The interval when the reaper runs. If this is not
   /// set (it is zero), it will be set to a reasonable default.
   #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
@@ -194,6 +234,44 @@ pub struct Options {
   )]
   tombstone_timeout: Duration,
 
+  /// The maximum number of members which gracefully left kept as tombstones,
+  /// the extra ones are reaped according to the `tombstone_eviction` strategy
+  /// on the next reap interval. `0` keeps every tombstone until its timeout.
+  #[viewit(
+    getter(
+      const,
+      attrs(
+        doc = "Returns the maximum number of members which gracefully left kept as tombstones."
+      )
+    ),
+    setter(attrs(
+      doc = "Sets the maximum number of members which gracefully left kept as tombstones."
+    ))
+  )]
+  max_left_members: usize,
+
+  /// Which tombstone is reaped first once `max_left_members` is exceeded.
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns which tombstone is reaped first once the maximum is exceeded.")
+    ),
+    setter(attrs(doc = "Sets which tombstone is reaped first once the maximum is exceeded."))
+  )]
+  tombstone_eviction: TombstoneEviction,
+
+  /// The number of times a member can be kept past its reap by
+  /// [`ReconnectDelegate::on_reap`](crate::delegate::ReconnectDelegate::on_reap),
+  /// once per reap interval, before it is reaped anyway.
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns the number of times the reap of a member can be delayed.")
+    ),
+    setter(attrs(doc = "Sets the number of times the reap of a member can be delayed."))
+  )]
+  max_reap_vetoes: u32,
+
   /// The amount of time less than which we consider a node
   /// being failed and rejoining looks like a flap for telemetry purposes.
   /// This should be set less than a typical reboot time, but large enough
@@ -213,6 +291,34 @@ pub struct Options {
   )]
   flap_timeout: Duration,
 
+  /// A member which failed and rejoined within the `flap_timeout` more than
+  /// `flap_threshold` times within the `flap_window` is reported with a
+  /// [`MemberEventType::Flap`](crate::event::MemberEventType::Flap) event.
+  /// `0` disables the flap events.
+  #[viewit(
+    getter(
+      const,
+      attrs(
+        doc = "Returns how many flaps within the flap window are tolerated before a flap event is emitted."
+      )
+    ),
+    setter(attrs(
+      doc = "Sets how many flaps within the flap window are tolerated before a flap event is emitted."
+    ))
+  )]
+  flap_threshold: usize,
+
+  /// The sliding window over which the flaps of a member are counted, see `flap_threshold`.
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns the sliding window over which the flaps of a member are counted.")
+    ),
+    setter(attrs(doc = "Sets the sliding window over which the flaps of a member are counted."))
+  )]
+  flap_window: Duration,
+
   /// The interval at which we check the message
   /// queue to apply the warning and max depth.
   #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
@@ -265,6 +371,21 @@ pub struct Options {
   )]
   min_queue_depth: usize,
 
+  /// Freezes the number of members the three broadcast queues are scaled by.
+  /// By default, each broadcast is retransmitted `retransmit_mult * ceil(log10(n + 1))`
+  /// times, and the dynamic queue depth is `2 * n`, with `n` the current
+  /// number of members. Defaults to `None`, see
+  /// [`Serf::freeze_broadcast_scaling`](crate::Serf::freeze_broadcast_scaling)
+  /// to freeze them at runtime.
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns the number of members the broadcast queues are frozen at, if any.")
+    ),
+    setter(attrs(doc = "Sets the number of members the broadcast queues are frozen at."))
+  )]
+  frozen_broadcast_scale: Option<usize>,
+
   /// Used to determine how long we store recent
   /// join and leave intents. This is used to guard against the case where
   /// Serf broadcasts an intent that arrives before the Memberlist event.
@@ -280,24 +401,55 @@ pub struct Options {
   )]
   recent_intent_timeout: Duration,
 
+  /// The maximum number of recent join and leave intents buffered for the
+  /// unknown nodes. Once full, the least recently updated intent is evicted
+  /// and counted in the `ruserf.intents.evicted` metric, a sign that the
+  /// buffer is too small for the cluster to converge. `0` keeps every intent
+  /// until its timeout.
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns the maximum number of recent join and leave intents buffered.")
+    ),
+    setter(attrs(doc = "Sets the maximum number of recent join and leave intents buffered."))
+  )]
+  max_recent_intents: usize,
+
   /// Used to control how many events are buffered.
   /// This is used to prevent re-delivery of events to a client. The buffer
   /// must be large enough to handle all "recent" events, since Serf will
   /// not deliver messages that are older than the oldest entry in the buffer.
   /// Thus if a client is generating too many events, it's possible that the
   /// buffer gets overrun and messages are not delivered.
+  ///
+  /// Must be greater than zero. The events dropped for being older than the
+  /// buffer are counted by [`Stats::old_events`](crate::Stats::get_old_events).
   #[viewit(
     getter(const, attrs(doc = "Returns how many events are buffered.")),
     setter(attrs(doc = "Sets how many events are buffered."))
   )]
   event_buffer_size: usize,
 
+  /// Controls what happens to new events when the event channel handed to
+  /// Serf is full. By default, Serf waits for the subscriber to make room.
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns the policy applied when the event channel is full.")
+    ),
+    setter(attrs(doc = "Sets the policy applied when the event channel is full."))
+  )]
+  event_backpressure: EventBackpressure,
+
   /// used to control how many queries are buffered.
   /// This is used to prevent re-delivery of queries to a client. The buffer
   /// must be large enough to handle all "recent" events, since Serf will not
   /// deliver queries older than the oldest entry in the buffer.
   /// Thus if a client is generating too many queries, it's possible that the
   /// buffer gets overrun and messages are not delivered.
+  ///
+  /// Must be greater than zero. The queries dropped for being older than the
+  /// buffer are counted by [`Stats::old_queries`](crate::Stats::get_old_queries).
   #[viewit(
     getter(const, attrs(doc = "Returns how many queries are buffered.")),
     setter(attrs(doc = "Sets how many queries are buffered."))
@@ -398,6 +550,301 @@ pub struct Options {
   )]
   rejoin_after_leave: bool,
 
+  /// If `true`, the snapshot is encrypted at rest with the primary key of the
+  /// keyring, which requires the transport to have encryption enabled. On replay
+  /// the records are decrypted with any key of the keyring, and the snapshot is
+  /// rewritten once the primary key changes.
+  #[cfg(feature = "encryption")]
+  #[viewit(
+    getter(
+      const,
+      attrs(
+        doc = "Returns if the snapshot is encrypted at rest.",
+        cfg(feature = "encryption")
+      )
+    ),
+    setter(attrs(
+      doc = "Sets if the snapshot is encrypted at rest.",
+      cfg(feature = "encryption")
+    ))
+  )]
+  snapshot_encryption: bool,
+
+  /// If provided, each compaction writes the snapshot as a single
+  /// compressed segment, the records appended afterwards are left as is
+  /// until the next compaction folds them into a new segment. Shrinks the
+  /// snapshots of the long-lived nodes, which are dominated by the member
+  /// records. The segments are detected when the snapshot is replayed, so
+  /// the compression can be enabled, changed or disabled across restarts as
+  /// long as the algorithm of the existing segment is still compiled in.
+  #[cfg_attr(feature = "serde", serde(skip))]
+  #[viewit(
+    getter(
+      style = "ref",
+      result(
+        converter(fn = "Option::as_ref"),
+        type = "Option<&Arc<dyn Compressor>>"
+      ),
+      attrs(doc = "Returns the compressor of the snapshot segments.")
+    ),
+    setter(attrs(doc = "Sets the compressor of the snapshot segments."))
+  )]
+  snapshot_compressor: Option<Arc<dyn Compressor>>,
+
+  /// How often the Lamport clocks are checkpointed to the snapshot, on top of
+  /// the writes driven by the events. The clocks witnessed in between, e.g.
+  /// from the gossip which delivers no event, are lost on a crash. Defaults
+  /// to 500ms.
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns how often the Lamport clocks are checkpointed to the snapshot.")
+    ),
+    setter(attrs(doc = "Sets how often the Lamport clocks are checkpointed to the snapshot."))
+  )]
+  snapshot_clock_interval: Duration,
+
+  /// The margin added to the Lamport clocks restored from the snapshot on
+  /// restart. A snapshot is stale by up to [`snapshot_clock_interval`](Options::snapshot_clock_interval),
+  /// so the clocks are bumped past the times the node may have used before it
+  /// stopped, instead of reusing them. Defaults to `0`.
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns the margin added to the Lamport clocks restored from the snapshot.")
+    ),
+    setter(attrs(
+      doc = "Sets the margin added to the Lamport clocks restored from the snapshot."
+    ))
+  )]
+  clock_skew_recovery: u64,
+
+  /// The IPv6 scope (zone) id applied to link-local addresses which were
+  /// learned without one, e.g. when replaying the snapshot or reconnecting
+  /// to failed members. Link-local addresses are unusable without a zone, so
+  /// this must be set in link-local-only environments. The zones are read and
+  /// applied through the [`TransformDelegate`](crate::delegate::TransformDelegate).
+  #[viewit(
+    getter(
+      const,
+      attrs(
+        doc = "Returns the IPv6 scope (zone) id applied to link-local addresses which were learned without one."
+      )
+    ),
+    setter(attrs(
+      doc = "Sets the IPv6 scope (zone) id applied to link-local addresses which were learned without one."
+    ))
+  )]
+  link_local_scope_id: Option<u32>,
+
+  /// Controls if Serf will try previously known nodes with global addresses
+  /// before the ones with loopback, link-local or private addresses when
+  /// re-joining the cluster from the snapshot.
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns if Serf will prefer global addresses when re-joining the cluster.")
+    ),
+    setter(attrs(doc = "Sets if Serf will prefer global addresses when re-joining the cluster."))
+  )]
+  prefer_global_addresses: bool,
+
+  /// The number of alive members this node expects to see before the cluster
+  /// is considered formed. Once reached, a [`ClusterFormed`](crate::event::Event::ClusterFormed)
+  /// event is emitted, see [`Serf::is_cluster_formed`](crate::Serf::is_cluster_formed).
+  ///
+  /// The local node counts towards the expectation. `None` disables the
+  /// expectation and the cluster is considered formed right away.
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns the number of alive members expected before the cluster is formed.")
+    ),
+    setter(attrs(
+      doc = "Sets the number of alive members expected before the cluster is formed."
+    ))
+  )]
+  bootstrap_expect: Option<usize>,
+
+  /// Enables the leader election over the alive members. The current leader
+  /// is returned by [`Serf::current_leader`](crate::Serf::current_leader) and
+  /// a [`LeaderChanged`](crate::event::Event::LeaderChanged) event is emitted
+  /// whenever it changes. `None` disables the election.
+  #[viewit(
+    getter(
+      style = "ref",
+      result(converter(fn = "Option::as_ref"), type = "Option<&ElectionOptions>"),
+      attrs(doc = "Returns the leader election options.")
+    ),
+    setter(attrs(doc = "Sets the leader election options."))
+  )]
+  election: Option<ElectionOptions>,
+
+  /// Enables the detection of the likely network partitions. A
+  /// [`Partition`](crate::event::Event::Partition) event is emitted when a
+  /// burst of failures suggests the local node was cut off from most of the
+  /// cluster, so the application can stop accepting writes, and again once
+  /// it heals. `None` disables the detection.
+  #[viewit(
+    getter(
+      style = "ref",
+      result(converter(fn = "Option::as_ref"), type = "Option<&PartitionDetection>"),
+      attrs(doc = "Returns the partition detection options.")
+    ),
+    setter(attrs(doc = "Sets the partition detection options."))
+  )]
+  partition_detection: Option<PartitionDetection>,
+
+  /// Enables the periodic anti-entropy audit of the member map. The digest
+  /// of the member map is compared with the one of a random alive member,
+  /// and a push/pull reconciles them right away when they diverge. `None`
+  /// disables the audit.
+  #[viewit(
+    getter(
+      style = "ref",
+      result(converter(fn = "Option::as_ref"), type = "Option<&AntiEntropyAudit>"),
+      attrs(doc = "Returns the anti-entropy audit options.")
+    ),
+    setter(attrs(doc = "Sets the anti-entropy audit options."))
+  )]
+  #[cfg_attr(feature = "serde", serde(default))]
+  anti_entropy_audit: Option<AntiEntropyAudit>,
+
+  /// Exports the addresses of the alive members by name, in memory and
+  /// optionally to a hosts or DNS zone file, see
+  /// [`Serf::hosts`](crate::Serf::hosts). `None` disables the export.
+  #[viewit(
+    getter(
+      style = "ref",
+      result(converter(fn = "Option::as_ref"), type = "Option<&HostsExport>"),
+      attrs(doc = "Returns the options of the member address export.")
+    ),
+    setter(attrs(doc = "Sets the options of the member address export."))
+  )]
+  #[cfg_attr(feature = "serde", serde(default))]
+  hosts: Option<HostsExport>,
+
+  /// The maximum number of entries kept by the key/value store, see
+  /// [`Serf::kv_put`](crate::Serf::kv_put). Once reached, the least recently
+  /// written entry is evicted to make room for a new key. `0` disables the store.
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns the maximum number of entries kept by the key/value store.")
+    ),
+    setter(attrs(doc = "Sets the maximum number of entries kept by the key/value store."))
+  )]
+  kv_max_entries: usize,
+
+  /// Limits the queries accepted from each source node, the excess queries
+  /// are dropped and not rebroadcast. `None` disables the limit.
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns the rate limit of the queries accepted from each source node.")
+    ),
+    setter(attrs(doc = "Sets the rate limit of the queries accepted from each source node."))
+  )]
+  query_rate_limit: Option<RateLimit>,
+
+  /// Limits the queries accepted for each query name, whichever node sent
+  /// them. `None` disables the limit.
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns the rate limit of the queries accepted for each query name.")
+    ),
+    setter(attrs(doc = "Sets the rate limit of the queries accepted for each query name."))
+  )]
+  query_name_rate_limit: Option<RateLimit>,
+
+  /// Drops the queries identical in name and payload to one received from
+  /// the same node less than this long ago, without rebroadcasting them.
+  /// Protects the query broadcast queue from the clients resubmitting a query
+  /// in a tight loop. The local clients get
+  /// [`SerfError::QuerySuppressed`](crate::error::SerfError::QuerySuppressed)
+  /// instead. `None` disables the suppression.
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns how long the identical queries of a node are suppressed.")
+    ),
+    setter(attrs(doc = "Sets how long the identical queries of a node are suppressed."))
+  )]
+  query_suppression_window: Option<Duration>,
+
+  /// Skips relaying the query responses through the peers which
+  /// consistently fail to receive them, rather than wasting bandwidth on dead
+  /// relay paths. The skipped peers are reported by the
+  /// [`RelayError`](crate::error::RelayError) of the response. `None` always
+  /// relays through randomly chosen alive peers.
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns the circuit breaker of the query response relays.")
+    ),
+    setter(attrs(doc = "Sets the circuit breaker of the query response relays."))
+  )]
+  relay_circuit_breaker: Option<RelayCircuitBreaker>,
+
+  /// Answers the repeated identical ping and key listing internal queries
+  /// from a cache for this long, rather than computing the response again
+  /// for each of them during a storm. The cache is keyed by the query name
+  /// and payload, the key listings are dropped as soon as the keyring
+  /// changes. `None` disables the cache.
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns how long the responses to the idempotent internal queries are cached.")
+    ),
+    setter(attrs(
+      doc = "Sets how long the responses to the idempotent internal queries are cached."
+    ))
+  )]
+  internal_query_cache_ttl: Option<Duration>,
+
+  /// The maximum number of nodes dialed at the same time by
+  /// [`Serf::join_many`](crate::Serf::join_many). `0` dials all the nodes at once.
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns the maximum number of nodes dialed at the same time when joining.")
+    ),
+    setter(attrs(doc = "Sets the maximum number of nodes dialed at the same time when joining."))
+  )]
+  join_parallelism: usize,
+
+  /// The overall deadline of [`Serf::join_many`](crate::Serf::join_many), the
+  /// nodes not joined in time are reported with a timeout error. `None` waits
+  /// for every node.
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns the overall deadline of joining many nodes.")
+    ),
+    setter(attrs(doc = "Sets the overall deadline of joining many nodes."))
+  )]
+  join_timeout: Option<Duration>,
+
+  /// Makes [`Serf::join`](crate::Serf::join) wait until the members the
+  /// joined node reported in the push/pull of the join are all known
+  /// locally, so the member list is complete once it returns. The join fails
+  /// with a timeout error if they are not known in time. `None` returns as
+  /// soon as the node is joined.
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns how long a join waits for the member list to be synced.")
+    ),
+    setter(attrs(doc = "Sets how long a join waits for the member list to be synced."))
+  )]
+  join_sync_timeout: Option<Duration>,
+
   /// Controls if Serf will actively attempt
   /// to resolve a name conflict. Since each Serf member must have a unique
   /// name, a cluster can run into issues if multiple nodes claim the same
@@ -415,6 +862,18 @@ pub struct Options {
   )]
   enable_id_conflict_resolution: bool,
 
+  /// What the local node does when it loses the vote of a name conflict,
+  /// only used if `enable_id_conflict_resolution` is set.
+  #[viewit(
+    getter(
+      const,
+      style = "ref",
+      attrs(doc = "Returns what the local node does when it loses a name conflict vote.")
+    ),
+    setter(attrs(doc = "Sets what the local node does when it loses a name conflict vote."))
+  )]
+  conflict_resolution: ConflictResolution,
+
   /// Controls if Serf will maintain an estimate of this
   /// node's network coordinate internally. A network coordinate is useful
   /// for estimating the network distance (i.e. round trip time) between
@@ -432,6 +891,34 @@ pub struct Options {
   )]
   disable_coordinates: bool,
 
+  /// If provided, the network coordinate of the local node and the ones
+  /// cached for the members are persisted to this file every
+  /// `coordinate_cache_interval` and on shutdown, and loaded on start, so the
+  /// round trip time estimates are useful right after a restart instead of
+  /// converging again. Ignored if the coordinates are disabled.
+  #[viewit(
+    getter(
+      const,
+      style = "ref",
+      result(converter(fn = "Option::as_ref"), type = "Option<&PathBuf>"),
+      attrs(doc = "Returns the path to the file the network coordinates are persisted to.")
+    ),
+    setter(attrs(doc = "Sets the path to the file the network coordinates are persisted to."))
+  )]
+  coordinate_cache_path: Option<PathBuf>,
+
+  /// How often the network coordinates are persisted to the
+  /// `coordinate_cache_path`. Defaults to 60s.
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns how often the network coordinates are persisted.")
+    ),
+    setter(attrs(doc = "Sets how often the network coordinates are persisted."))
+  )]
+  coordinate_cache_interval: Duration,
+
   /// Provides the location of a writable file where Serf can
   /// persist changes to the encryption keyring.
   #[cfg(feature = "encryption")]
@@ -441,12 +928,12 @@ pub struct Options {
       style = "ref",
       result(converter(fn = "Option::as_ref"), type = "Option<&PathBuf>"),
       attrs(
-        doc = "Returns the location of a writable file where Serf can persist changes to the encryption keyring.",
+        doc = "Returns the location of a writable file where Serf can persist changes to the encryption keyring. The keys in the file are loaded at startup, replacing the ones of the transport.",
         cfg(feature = "encryption")
       )
     ),
     setter(attrs(
-      doc = "Sets the location of a writable file where Serf can persist changes to the encryption keyring.",
+      doc = "Sets the location of a writable file where Serf can persist changes to the encryption keyring. The keys in the file are loaded at startup, replacing the ones of the transport.",
       cfg(feature = "encryption")
     ))
   )]
@@ -466,6 +953,267 @@ pub struct Options {
     ))
   )]
   max_user_event_size: usize,
+
+  /// User events whose `name` + `payload` exceed [`max_user_event_size`](Options::max_user_event_size),
+  /// but not this limit, are split into fragments which are gossiped
+  /// independently and reassembled by the receiving nodes. The events are
+  /// only fragmented once every member speaks [`ProtocolVersion::V2`] or
+  /// newer, the older ones would deliver the fragments as user events of
+  /// their own.
+  ///
+  /// The receiving nodes drop the fragmented events exceeding their own
+  /// limit, so every node must be configured with the same one.
+  ///
+  /// Defaults to `0`, fragmentation disabled.
+  #[viewit(
+    getter(
+      const,
+      attrs(
+        doc = "Returns the maximum byte size of user event `name` + `payload` which is split into fragments."
+      )
+    ),
+    setter(attrs(
+      doc = "Sets the maximum byte size of user event `name` + `payload` which is split into fragments."
+    ))
+  )]
+  max_fragmented_user_event_size: usize,
+
+  /// The maximum number of fragmented user events reassembled at once, the
+  /// oldest incomplete event is dropped to make room for a new one.
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns the maximum number of fragmented user events reassembled at once.")
+    ),
+    setter(attrs(doc = "Sets the maximum number of fragmented user events reassembled at once."))
+  )]
+  fragment_buffer_size: usize,
+
+  /// If provided, user event and query payloads of at least
+  /// `compression_threshold` bytes are compressed before being gossiped,
+  /// the size limits are applied to the compressed payloads. The payloads
+  /// are only compressed once every member speaks
+  /// [`ProtocolVersion::V2`] or newer, the older ones would deliver them
+  /// as is.
+  ///
+  /// The receiving nodes must be configured with the same compressor
+  /// to inflate the payloads, the others still gossip them.
+  #[cfg_attr(feature = "serde", serde(skip))]
+  #[viewit(
+    getter(
+      style = "ref",
+      result(
+        converter(fn = "Option::as_ref"),
+        type = "Option<&Arc<dyn Compressor>>"
+      ),
+      attrs(doc = "Returns the compressor of the user event and query payloads.")
+    ),
+    setter(attrs(doc = "Sets the compressor of the user event and query payloads."))
+  )]
+  compressor: Option<Arc<dyn Compressor>>,
+
+  /// The minimum size in bytes of a payload to be compressed.
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns the minimum size in bytes of a payload to be compressed.")
+    ),
+    setter(attrs(doc = "Sets the minimum size in bytes of a payload to be compressed."))
+  )]
+  compression_threshold: usize,
+
+  /// The maximum size in bytes of a compressed user event or query payload
+  /// once inflated. The payloads inflating to more are not delivered, so a
+  /// forged payload cannot exhaust the memory.
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns the maximum size in bytes of an inflated payload.")
+    ),
+    setter(attrs(doc = "Sets the maximum size in bytes of an inflated payload."))
+  )]
+  max_decompressed_size: usize,
+
+  /// The size in bytes of the local state exchanged during a push/pull sync
+  /// above which a warning is logged, as large states slow down every sync.
+  /// `0` disables the warning.
+  #[viewit(
+    getter(
+      const,
+      attrs(
+        doc = "Returns the size in bytes of the push/pull state above which a warning is logged."
+      )
+    ),
+    setter(attrs(
+      doc = "Sets the size in bytes of the push/pull state above which a warning is logged."
+    ))
+  )]
+  push_pull_size_warning: usize,
+
+  /// If `true`, the periodic push/pull syncs only exchange the member state,
+  /// the recent user events are only sent when joining. This saves bandwidth
+  /// in large clusters, at the cost of not repairing user events lost by the gossip.
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns whether the periodic push/pull syncs omit the user events.")
+    ),
+    setter(attrs(doc = "Sets whether the periodic push/pull syncs omit the user events."))
+  )]
+  incremental_push_pull: bool,
+
+  /// If `true`, the push/pull state is compressed with LZ4 once every member
+  /// which did not leave speaks [`ProtocolVersion::V2`] or newer, which needs
+  /// the `lz4` feature. It is sent as is while a member speaks an older
+  /// protocol, during a join, as the joining node is not known yet, or when
+  /// the compression saves no space.
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns whether the push/pull state is compressed.")
+    ),
+    setter(attrs(doc = "Sets whether the push/pull state is compressed."))
+  )]
+  push_pull_compression: bool,
+
+  /// The segment of the local node, advertised in the
+  /// [`SEGMENT_TAG`](crate::segment::SEGMENT_TAG) tag.
+  ///
+  /// The user events sent by a member of a segment are only delivered to the
+  /// members of the same segment, and the push/pull syncs only replay them
+  /// to those. The other members still relay them, and the failure
+  /// detection, the membership and the queries span all the segments. The
+  /// user events of the members without a segment are delivered to every
+  /// member. The segment counts towards the size limit of the user event
+  /// names, and must not contain a `/`.
+  #[viewit(
+    getter(
+      const,
+      style = "ref",
+      result(converter(fn = "Option::as_ref"), type = "Option<&SmolStr>"),
+      attrs(doc = "Returns the segment of the local node, if any.")
+    ),
+    setter(attrs(doc = "Sets the segment of the local node."))
+  )]
+  #[cfg_attr(feature = "serde", serde(default))]
+  segment: Option<SmolStr>,
+
+  /// The name of the cluster of the local node. Its hash is advertised in
+  /// the meta of the node, so it is part of the join and push/pull
+  /// messages, and the nodes of another cluster, or of no cluster while the
+  /// local one is named, are refused with a
+  /// [`ClusterMismatch`](crate::event::Event::ClusterMismatch) event. This
+  /// prevents the accidental joins across environments sharing a network.
+  #[viewit(
+    getter(
+      const,
+      style = "ref",
+      result(converter(fn = "Option::as_ref"), type = "Option<&SmolStr>"),
+      attrs(doc = "Returns the name of the cluster of the local node, if any.")
+    ),
+    setter(attrs(doc = "Sets the name of the cluster of the local node."))
+  )]
+  #[cfg_attr(feature = "serde", serde(default))]
+  cluster_name: Option<SmolStr>,
+
+  /// The clock of the reaper, the reconnector, the event coalescers, the
+  /// query deadlines, and the waits of the leave, the join and the removal
+  /// of a member. If not provided, the timers of the runtime are used.
+  ///
+  /// A [`ManualClock`](crate::clock::ManualClock) lets tests advance these
+  /// timers without waiting.
+  #[cfg_attr(feature = "serde", serde(skip))]
+  #[viewit(
+    getter(
+      style = "ref",
+      result(converter(fn = "Option::as_ref"), type = "Option<&Arc<dyn Clock>>"),
+      attrs(doc = "Returns the clock of the background tasks.")
+    ),
+    setter(attrs(doc = "Sets the clock of the background tasks."))
+  )]
+  clock: Option<Arc<dyn Clock>>,
+
+  /// Schedules the rounds of the reaper and the reconnector. If not
+  /// provided, they run at the `reap_interval` and the `reconnect_interval`.
+  #[cfg_attr(feature = "serde", serde(skip))]
+  #[viewit(
+    getter(
+      style = "ref",
+      result(
+        converter(fn = "Option::as_ref"),
+        type = "Option<&Arc<dyn MaintenanceScheduler>>"
+      ),
+      attrs(doc = "Returns the scheduler of the reaper and the reconnector.")
+    ),
+    setter(attrs(doc = "Sets the scheduler of the reaper and the reconnector."))
+  )]
+  maintenance_scheduler: Option<Arc<dyn MaintenanceScheduler>>,
+
+  /// Records the member events, the user events and the queries seen by the
+  /// local node, e.g. to a [`JsonLinesSink`](crate::event::JsonLinesSink)
+  /// for an audit trail of the cluster.
+  #[cfg_attr(feature = "serde", serde(skip))]
+  #[viewit(
+    getter(
+      style = "ref",
+      result(converter(fn = "Option::as_ref"), type = "Option<&Arc<dyn EventSink>>"),
+      attrs(doc = "Returns the sink recording the events.")
+    ),
+    setter(attrs(doc = "Sets the sink recording the events."))
+  )]
+  event_sink: Option<Arc<dyn EventSink>>,
+
+  /// Notified of the compactions, the replay and the failures of the
+  /// snapshot, see [`snapshot_path`](Options::snapshot_path).
+  #[cfg_attr(feature = "serde", serde(skip))]
+  #[viewit(
+    getter(
+      style = "ref",
+      result(
+        converter(fn = "Option::as_ref"),
+        type = "Option<&Arc<dyn SnapshotDelegate>>"
+      ),
+      attrs(doc = "Returns the delegate notified of the snapshot lifecycle.")
+    ),
+    setter(attrs(doc = "Sets the delegate notified of the snapshot lifecycle."))
+  )]
+  snapshot_delegate: Option<Arc<dyn SnapshotDelegate>>,
+
+  /// Seeds the random choices of the local node: the query ids, the members
+  /// the query responses are relayed through, the failed member to reconnect
+  /// to, the peer of the anti-entropy audit and the order the snapshot
+  /// members are rejoined in. With a seed, a simulation replays the same
+  /// choices on every run. The nodes of a cluster must use distinct seeds,
+  /// or their queries get the same ids. If not provided, the generator is
+  /// seeded from the entropy of the system.
+  #[viewit(
+    getter(const, attrs(doc = "Returns the seed of the random choices, if any.")),
+    setter(attrs(doc = "Sets the seed of the random choices."))
+  )]
+  rng_seed: Option<u64>,
+
+  /// Injects faults into the incoming gossip messages to simulate a lossy
+  /// network, only available in test builds.
+  #[cfg(any(test, feature = "test"))]
+  #[cfg_attr(feature = "serde", serde(skip))]
+  #[viewit(
+    getter(
+      style = "ref",
+      result(
+        converter(fn = "Option::as_ref"),
+        type = "Option<&Arc<dyn MessageDropper>>"
+      ),
+      attrs(
+        doc = "Returns the fault injector of the incoming gossip messages.",
+        cfg(any(test, feature = "test"))
+      )
+    ),
+    setter(attrs(
+      doc = "Sets the fault injector of the incoming gossip messages.",
+      cfg(any(test, feature = "test"))
+    ))
+  )]
+  message_dropper: Option<Arc<dyn MessageDropper>>,
 }
 
 impl Default for Options {
@@ -482,7 +1230,22 @@ impl Clone for Options {
       memberlist_options: self.memberlist_options.clone(),
       keyring_file: self.keyring_file.clone(),
       snapshot_path: self.snapshot_path.clone(),
+      coordinate_cache_path: self.coordinate_cache_path.clone(),
       tags: self.tags.clone(),
+      compressor: self.compressor.clone(),
+      snapshot_compressor: self.snapshot_compressor.clone(),
+      election: self.election.clone(),
+      partition_detection: self.partition_detection,
+      hosts: self.hosts.clone(),
+      conflict_resolution: self.conflict_resolution.clone(),
+      clock: self.clock.clone(),
+      maintenance_scheduler: self.maintenance_scheduler.clone(),
+      segment: self.segment.clone(),
+      cluster_name: self.cluster_name.clone(),
+      event_sink: self.event_sink.clone(),
+      snapshot_delegate: self.snapshot_delegate.clone(),
+      #[cfg(any(test, feature = "test"))]
+      message_dropper: self.message_dropper.clone(),
       ..*self
     }
   }
@@ -498,21 +1261,31 @@ impl Options {
       delegate_version: DelegateVersion::V1,
       broadcast_timeout: Duration::from_secs(5),
       leave_propagate_delay: Duration::from_secs(1),
+      shutdown_timeout: None,
       coalesce_period: Duration::ZERO,
       quiescent_period: Duration::ZERO,
       user_coalesce_period: Duration::ZERO,
       user_quiescent_period: Duration::ZERO,
+      member_event_batch_window: Duration::ZERO,
       reap_interval: Duration::from_secs(15),
       reconnect_interval: Duration::from_secs(30),
       reconnect_timeout: Duration::from_secs(3600 * 24),
       tombstone_timeout: Duration::from_secs(3600 * 24),
+      max_left_members: 0,
+      tombstone_eviction: TombstoneEviction::Oldest,
+      max_reap_vetoes: 3,
       flap_timeout: Duration::from_secs(60),
+      flap_threshold: 0,
+      flap_window: Duration::from_secs(600),
       queue_check_interval: Duration::from_secs(30),
       queue_depth_warning: 128,
       max_queue_depth: 4096,
       min_queue_depth: 0,
+      frozen_broadcast_scale: None,
       recent_intent_timeout: Duration::from_secs(60 * 5),
+      max_recent_intents: 0,
       event_buffer_size: 512,
+      event_backpressure: EventBackpressure::Block,
       query_buffer_size: 512,
       query_timeout_mult: 16,
       query_response_size_limit: 1024,
@@ -520,10 +1293,51 @@ impl Options {
       memberlist_options: MemberlistOptions::lan(),
       snapshot_path: None,
       rejoin_after_leave: false,
+      #[cfg(feature = "encryption")]
+      snapshot_encryption: false,
+      snapshot_compressor: None,
+      snapshot_clock_interval: Duration::from_millis(500),
+      clock_skew_recovery: 0,
+      link_local_scope_id: None,
+      prefer_global_addresses: false,
+      bootstrap_expect: None,
+      election: None,
+      partition_detection: None,
+      anti_entropy_audit: None,
+      hosts: None,
+      kv_max_entries: 1024,
+      query_rate_limit: None,
+      query_name_rate_limit: None,
+      query_suppression_window: None,
+      relay_circuit_breaker: None,
+      internal_query_cache_ttl: None,
+      join_parallelism: 16,
+      join_sync_timeout: None,
+      join_timeout: None,
       enable_id_conflict_resolution: true,
+      conflict_resolution: ConflictResolution::Shutdown,
       disable_coordinates: false,
+      coordinate_cache_path: None,
+      coordinate_cache_interval: Duration::from_secs(60),
       keyring_file: None,
       max_user_event_size: 512,
+      max_fragmented_user_event_size: 0,
+      fragment_buffer_size: 64,
+      compressor: None,
+      compression_threshold: 256,
+      max_decompressed_size: 1024 * 1024,
+      push_pull_size_warning: 64 * 1024,
+      incremental_push_pull: false,
+      push_pull_compression: false,
+      segment: None,
+      cluster_name: None,
+      clock: None,
+      maintenance_scheduler: None,
+      event_sink: None,
+      snapshot_delegate: None,
+      rng_seed: None,
+      #[cfg(any(test, feature = "test"))]
+      message_dropper: None,
     }
   }
 
@@ -539,13 +1353,46 @@ impl Options {
     self
   }
 
+  /// Returns the subset of the options which can be changed at runtime.
   #[inline]
-  pub(crate) fn queue_opts(&self) -> QueueOptions {
-    QueueOptions {
+  pub fn reloadable(&self) -> ReloadableOptions {
+    ReloadableOptions {
+      queue_depth_warning: self.queue_depth_warning,
       max_queue_depth: self.max_queue_depth,
       min_queue_depth: self.min_queue_depth,
+      coalesce_period: self.coalesce_period,
+      quiescent_period: self.quiescent_period,
+      user_coalesce_period: self.user_coalesce_period,
+      user_quiescent_period: self.user_quiescent_period,
+      reap_interval: self.reap_interval,
+      reconnect_interval: self.reconnect_interval,
+      query_timeout_mult: self.query_timeout_mult,
+      query_response_size_limit: self.query_response_size_limit,
+      query_size_limit: self.query_size_limit,
+    }
+  }
+
+  /// Checks the options Serf cannot be created with.
+  pub(crate) fn validate(&self) -> Result<(), &'static str> {
+    if self.event_buffer_size == 0 {
+      return Err("event buffer size must be greater than zero");
+    }
+
+    if self.query_buffer_size == 0 {
+      return Err("query buffer size must be greater than zero");
+    }
+
+    if self.protocol_version as u8 > crate::version::PROTOCOL_MAX as u8 {
+      return Err("protocol version 2 needs the lz4 feature");
+    }
+
+    Ok(())
+  }
+
+  #[inline]
+  pub(crate) fn queue_opts(&self) -> QueueOptions {
+    QueueOptions {
       check_interval: self.queue_check_interval,
-      depth_warning: self.queue_depth_warning,
       #[cfg(feature = "metrics")]
       metric_labels: self.memberlist_options.metric_labels().clone(),
     }
@@ -554,14 +1401,158 @@ impl Options {
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub(crate) struct QueueOptions {
-  pub(crate) max_queue_depth: usize,
-  pub(crate) min_queue_depth: usize,
   pub(crate) check_interval: Duration,
-  pub(crate) depth_warning: usize,
   #[cfg(feature = "metrics")]
   pub(crate) metric_labels: Arc<memberlist_core::types::MetricLabels>,
 }
 
+/// The subset of [`Options`] which can be changed on a running
+/// [`Serf`](crate::Serf) through [`Serf::reload`](crate::Serf::reload).
+///
+/// See the fields with the same name on [`Options`] for their meaning.
+/// The log level is not part of Serf's options, it is controlled by the
+/// application's `tracing` subscriber (e.g. with `tracing_subscriber::reload`).
+#[viewit::viewit(getters(vis_all = "pub"), setters(vis_all = "pub", prefix = "with"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReloadableOptions {
+  /// The number of queued broadcasts at which a warning is logged.
+  #[viewit(
+    getter(const, attrs(doc = "Returns the queue depth warning.")),
+    setter(attrs(doc = "Sets the queue depth warning."))
+  )]
+  queue_depth_warning: usize,
+
+  /// The maximum number of queued broadcasts.
+  #[viewit(
+    getter(const, attrs(doc = "Returns the maximum queue depth.")),
+    setter(attrs(doc = "Sets the maximum queue depth."))
+  )]
+  max_queue_depth: usize,
+
+  /// The minimum number of queued broadcasts, scaled with the cluster size.
+  #[viewit(
+    getter(const, attrs(doc = "Returns the minimum queue depth.")),
+    setter(attrs(doc = "Sets the minimum queue depth."))
+  )]
+  min_queue_depth: usize,
+
+  /// The member event coalesce period.
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  #[viewit(
+    getter(const, attrs(doc = "Returns the coalesce period.")),
+    setter(attrs(doc = "Sets the coalesce period."))
+  )]
+  coalesce_period: Duration,
+
+  /// The member event quiescent period.
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  #[viewit(
+    getter(const, attrs(doc = "Returns the quiescent period.")),
+    setter(attrs(doc = "Sets the quiescent period."))
+  )]
+  quiescent_period: Duration,
+
+  /// The user event coalesce period.
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  #[viewit(
+    getter(const, attrs(doc = "Returns the user event coalesce period.")),
+    setter(attrs(doc = "Sets the user event coalesce period."))
+  )]
+  user_coalesce_period: Duration,
+
+  /// The user event quiescent period.
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  #[viewit(
+    getter(const, attrs(doc = "Returns the user quiescent period.")),
+    setter(attrs(doc = "Sets the user quiescent period."))
+  )]
+  user_quiescent_period: Duration,
+
+  /// The interval when the reaper runs.
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  #[viewit(
+    getter(const, attrs(doc = "Returns the interval when the reaper runs.")),
+    setter(attrs(doc = "Sets the interval when the reaper runs."))
+  )]
+  reap_interval: Duration,
+
+  /// The interval when the failed nodes are tried to reconnect.
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  #[viewit(
+    getter(const, attrs(doc = "Returns the reconnect interval.")),
+    setter(attrs(doc = "Sets the reconnect interval."))
+  )]
+  reconnect_interval: Duration,
+
+  /// The default timeout multipler for a query.
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns the default timeout multipler for a query.")
+    ),
+    setter(attrs(doc = "Sets the default timeout multipler for a query."))
+  )]
+  query_timeout_mult: usize,
+
+  /// The limit of the query response size.
+  #[viewit(
+    getter(const, attrs(doc = "Returns the query response size limit.")),
+    setter(attrs(doc = "Sets the query response size limit."))
+  )]
+  query_response_size_limit: usize,
+
+  /// The limit of the query size.
+  #[viewit(
+    getter(const, attrs(doc = "Returns the query size limit.")),
+    setter(attrs(doc = "Sets the query size limit."))
+  )]
+  query_size_limit: usize,
+}
+
+impl From<&Options> for ReloadableOptions {
+  fn from(opts: &Options) -> Self {
+    opts.reloadable()
+  }
+}
+
+impl ReloadableOptions {
+  /// Checks if `self` can replace the `current` options of a running Serf.
+  pub(crate) fn validate(&self, current: &Self) -> Result<(), &'static str> {
+    // The coalescers are only spawned when the instance is created
+    let coalescing = |c: Duration, q: Duration| c > Duration::ZERO && q > Duration::ZERO;
+    if coalescing(self.coalesce_period, self.quiescent_period)
+      != coalescing(current.coalesce_period, current.quiescent_period)
+    {
+      return Err("member event coalescing cannot be enabled or disabled at runtime");
+    }
+
+    if coalescing(self.user_coalesce_period, self.user_quiescent_period)
+      != coalescing(current.user_coalesce_period, current.user_quiescent_period)
+    {
+      return Err("user event coalescing cannot be enabled or disabled at runtime");
+    }
+
+    if self.reap_interval.is_zero() {
+      return Err("reap interval must be greater than zero");
+    }
+
+    if self.reconnect_interval.is_zero() {
+      return Err("reconnect interval must be greater than zero");
+    }
+
+    if self.query_timeout_mult == 0 {
+      return Err("query timeout multiplier must be greater than zero");
+    }
+
+    if self.max_queue_depth == 0 && self.min_queue_depth == 0 {
+      return Err("either the max or the min queue depth must be greater than zero");
+    }
+
+    Ok(())
+  }
+}
+
 #[cfg(feature = "serde")]
 mod tags_serde {
   use std::sync::Arc;
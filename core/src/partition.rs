@@ -0,0 +1,200 @@
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{event::PartitionEvent, types::Epoch};
+
+/// Enables the detection of the likely network partitions, see
+/// [`Options::partition_detection`](crate::Options::partition_detection).
+///
+/// The local node remembers the peak number of alive members, seeded from
+/// the snapshot if any. A partition is suspected once more than
+/// `failure_ratio` of the peak failed within `window`, and healed once more
+/// than `1 - failure_ratio` of the peak is alive again. A graceful leave
+/// lowers the peak, so scaling the cluster down is not taken for a partition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PartitionDetection {
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  check_interval: Duration,
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  window: Duration,
+  failure_ratio: f64,
+}
+
+impl Default for PartitionDetection {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl PartitionDetection {
+  /// Returns a new configuration, checking every 5 seconds for more than
+  /// half of the members failing within a minute.
+  #[inline]
+  pub const fn new() -> Self {
+    Self {
+      check_interval: Duration::from_secs(5),
+      window: Duration::from_secs(60),
+      failure_ratio: 0.5,
+    }
+  }
+
+  /// Sets how often the membership is checked.
+  #[inline]
+  pub const fn with_check_interval(mut self, interval: Duration) -> Self {
+    self.check_interval = interval;
+    self
+  }
+
+  /// Sets the window the failures are counted over.
+  #[inline]
+  pub const fn with_window(mut self, window: Duration) -> Self {
+    self.window = window;
+    self
+  }
+
+  /// Sets the share of the peak members which must fail within the window,
+  /// clamped to `0.0..=1.0`.
+  #[inline]
+  pub fn with_failure_ratio(mut self, ratio: f64) -> Self {
+    self.failure_ratio = ratio.clamp(0.0, 1.0);
+    self
+  }
+
+  /// Returns how often the membership is checked.
+  #[inline]
+  pub const fn check_interval(&self) -> Duration {
+    self.check_interval
+  }
+
+  /// Returns the window the failures are counted over.
+  #[inline]
+  pub const fn window(&self) -> Duration {
+    self.window
+  }
+
+  /// Returns the share of the peak members which must fail within the window.
+  #[inline]
+  pub const fn failure_ratio(&self) -> f64 {
+    self.failure_ratio
+  }
+}
+
+/// Tracks the peak of the alive members and the recent failures.
+pub(crate) struct PartitionDetector {
+  opts: PartitionDetection,
+  peak: usize,
+  failures: VecDeque<Epoch>,
+  partitioned: bool,
+}
+
+impl PartitionDetector {
+  pub(crate) fn new(opts: PartitionDetection, peak: usize) -> Self {
+    Self {
+      opts,
+      peak,
+      failures: VecDeque::new(),
+      partitioned: false,
+    }
+  }
+
+  pub(crate) fn is_partitioned(&self) -> bool {
+    self.partitioned
+  }
+
+  pub(crate) fn record_failure(&mut self, at: Epoch) {
+    self.failures.push_back(at);
+  }
+
+  pub(crate) fn record_leave(&mut self) {
+    self.peak = self.peak.saturating_sub(1);
+  }
+
+  /// Returns the event to emit if the verdict changed.
+  pub(crate) fn check(&mut self, alive: usize, now: Epoch) -> Option<PartitionEvent> {
+    self.peak = self.peak.max(alive);
+    while let Some(at) = self.failures.front() {
+      if now - *at <= self.opts.window {
+        break;
+      }
+      self.failures.pop_front();
+    }
+
+    let peak = self.peak as f64;
+    let failed = self.failures.len();
+    let partitioned = if self.partitioned {
+      alive as f64 <= peak * (1.0 - self.opts.failure_ratio)
+    } else {
+      failed as f64 > peak * self.opts.failure_ratio
+    };
+    if partitioned == self.partitioned {
+      return None;
+    }
+
+    self.partitioned = partitioned;
+    if !partitioned {
+      // The failures which led to the partition are not a new burst
+      self.failures.clear();
+    }
+    Some(PartitionEvent {
+      partitioned,
+      peak: self.peak,
+      alive,
+      failed,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_partition_detector() {
+    let opts = PartitionDetection::new().with_window(Duration::from_secs(10));
+    let start = Epoch::now();
+    let mut detector = PartitionDetector::new(opts, 4);
+
+    // The peak follows the alive members
+    assert!(detector.check(10, start).is_none());
+
+    // Half of the members failing is not a partition yet
+    for _ in 0..5 {
+      detector.record_failure(start);
+    }
+    assert!(detector.check(5, start).is_none());
+    detector.record_failure(start);
+    let event = detector.check(4, start).unwrap();
+    assert!(event.partitioned());
+    assert_eq!(event.peak(), 10);
+    assert_eq!(event.failed(), 6);
+    assert!(detector.is_partitioned());
+
+    // Healed once most of the members are back
+    assert!(detector.check(5, start + Duration::from_secs(20)).is_none());
+    let event = detector.check(6, start + Duration::from_secs(20)).unwrap();
+    assert!(!event.partitioned());
+    assert!(!detector.is_partitioned());
+  }
+
+  #[test]
+  fn test_partition_detector_window() {
+    let opts = PartitionDetection::new().with_window(Duration::from_secs(10));
+    let start = Epoch::now();
+    let mut detector = PartitionDetector::new(opts, 4);
+
+    // Failures spread over longer than the window are not a burst
+    for i in 0..3 {
+      detector.record_failure(start + Duration::from_secs(i * 20));
+    }
+    assert!(detector.check(1, start + Duration::from_secs(40)).is_none());
+
+    // The graceful leaves lower the peak
+    let mut detector = PartitionDetector::new(opts, 4);
+    detector.record_leave();
+    detector.record_leave();
+    detector.record_failure(start);
+    assert!(detector.check(1, start).is_none());
+    detector.record_failure(start);
+    assert!(detector.check(0, start).unwrap().partitioned());
+  }
+}
@@ -0,0 +1,203 @@
+use std::time::Duration;
+
+use memberlist_core::{
+  bytes::{Buf, BufMut, Bytes, BytesMut},
+  tracing,
+  transport::{AddressResolver, Transport},
+  types::TinyVec,
+  CheapClone,
+};
+use ruserf_types::Transformable;
+use smol_str::SmolStr;
+
+use crate::{
+  coordinate::Coordinate,
+  delegate::Delegate,
+  error::Error,
+  event::{InternalQueryEvent, INTERNAL_PING},
+  types::{Epoch, Filter},
+  Serf,
+};
+
+/// uptime (u64 millis) + intent, event and query queue depths (u32 each)
+const FIXED_LEN: usize = 8 + 3 * 4;
+
+/// The state reported by a member answering [`Serf::ping_member`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PingInfo {
+  coordinate: Option<Coordinate>,
+  uptime: Duration,
+  intent_queue: usize,
+  event_queue: usize,
+  query_queue: usize,
+  rtt: Duration,
+}
+
+impl PingInfo {
+  /// Returns the network coordinate of the member, or `None` if it has
+  /// coordinates disabled.
+  #[inline]
+  pub const fn coordinate(&self) -> Option<&Coordinate> {
+    self.coordinate.as_ref()
+  }
+
+  /// Returns how long the member has been running.
+  #[inline]
+  pub const fn uptime(&self) -> Duration {
+    self.uptime
+  }
+
+  /// Returns the number of intents queued for broadcast by the member.
+  #[inline]
+  pub const fn intent_queue(&self) -> usize {
+    self.intent_queue
+  }
+
+  /// Returns the number of user events queued for broadcast by the member.
+  #[inline]
+  pub const fn event_queue(&self) -> usize {
+    self.event_queue
+  }
+
+  /// Returns the number of queries queued for broadcast by the member.
+  #[inline]
+  pub const fn query_queue(&self) -> usize {
+    self.query_queue
+  }
+
+  /// Returns the time between sending the ping and receiving the answer.
+  #[inline]
+  pub const fn rtt(&self) -> Duration {
+    self.rtt
+  }
+
+  fn encode(&self) -> Bytes {
+    let coord_len = self.coordinate.as_ref().map_or(0, |c| c.encoded_len());
+    let mut buf = BytesMut::with_capacity(FIXED_LEN + coord_len);
+    buf.put_u64(self.uptime.as_millis() as u64);
+    for depth in [self.intent_queue, self.event_queue, self.query_queue] {
+      buf.put_u32(depth.min(u32::MAX as usize) as u32);
+    }
+    if let Some(coord) = &self.coordinate {
+      let offset = buf.len();
+      buf.resize(offset + coord_len, 0);
+      // The buffer is sized to the encoded length
+      coord.encode(&mut buf[offset..]).unwrap();
+    }
+    buf.freeze()
+  }
+
+  fn decode(mut src: &[u8], rtt: Duration) -> Option<Self> {
+    if src.len() < FIXED_LEN {
+      return None;
+    }
+
+    let uptime = Duration::from_millis(src.get_u64());
+    let intent_queue = src.get_u32() as usize;
+    let event_queue = src.get_u32() as usize;
+    let query_queue = src.get_u32() as usize;
+    let coordinate = if src.is_empty() {
+      None
+    } else {
+      Some(Coordinate::decode(src).ok()?.1)
+    };
+
+    Some(Self {
+      coordinate,
+      uptime,
+      intent_queue,
+      event_queue,
+      query_queue,
+      rtt,
+    })
+  }
+}
+
+impl<T, D> Serf<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  /// Asks a member for its coordinate, uptime and queue depths.
+  ///
+  /// Returns an error if the member does not answer before the default
+  /// query timeout.
+  pub async fn ping_member(&self, id: T::Id) -> Result<PingInfo, Error<T, D>> {
+    let mut params = self.default_query_param().await;
+    params
+      .filters
+      .push(Filter::Id(TinyVec::from(id.cheap_clone())));
+
+    let start = Epoch::now();
+    let resp = self
+      .internal_query(
+        SmolStr::new(INTERNAL_PING),
+        Bytes::new(),
+        Some(params),
+        InternalQueryEvent::Ping,
+      )
+      .await?;
+
+    let resp_rx = resp.response_rx();
+    while let Ok(r) = resp_rx.recv().await {
+      if r.from().id().ne(&id) {
+        continue;
+      }
+
+      match PingInfo::decode(r.payload(), start.elapsed()) {
+        Some(info) => return Ok(info),
+        None => tracing::warn!("ruserf: invalid ping response from {}", id),
+      }
+    }
+
+    Err(Error::query_timeout())
+  }
+
+  /// Encodes the answer of the local node to a ping query.
+  pub(crate) async fn ping_payload(&self) -> Bytes {
+    let stats = self.stats().await;
+    PingInfo {
+      coordinate: self
+        .inner
+        .coord_core
+        .as_ref()
+        .map(|cc| cc.client.get_coordinate()),
+      uptime: self.inner.started_at.elapsed(),
+      intent_queue: stats.get_intent_queue(),
+      event_queue: stats.get_event_queue(),
+      query_queue: stats.get_query_queue(),
+      rtt: Duration::ZERO,
+    }
+    .encode()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_ping_info_payload() {
+    let mut info = PingInfo {
+      coordinate: None,
+      uptime: Duration::from_secs(90),
+      intent_queue: 1,
+      event_queue: 2,
+      query_queue: 3,
+      rtt: Duration::from_millis(5),
+    };
+    assert_eq!(
+      PingInfo::decode(&info.encode(), Duration::from_millis(5)),
+      Some(info.clone())
+    );
+
+    info.coordinate = Some(Coordinate::new());
+    assert_eq!(
+      PingInfo::decode(&info.encode(), Duration::from_millis(5)),
+      Some(info.clone())
+    );
+
+    assert!(PingInfo::decode(&info.encode()[..FIXED_LEN - 1], Duration::ZERO).is_none());
+    assert!(PingInfo::decode(&info.encode()[..FIXED_LEN + 2], Duration::ZERO).is_none());
+  }
+}
@@ -0,0 +1,168 @@
+use std::{collections::HashMap, hash::Hash, time::Duration};
+
+use smol_str::SmolStr;
+
+use crate::types::Epoch;
+
+/// The number of buckets above which the idle buckets are evicted.
+const MAX_IDLE_BUCKETS: usize = 1024;
+
+/// A token bucket limit, see [`Options::query_rate_limit`](crate::Options::query_rate_limit)
+/// and [`Options::query_name_rate_limit`](crate::Options::query_name_rate_limit).
+///
+/// Up to `burst` queries are accepted at once, then the bucket is refilled
+/// with `rate` queries per second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RateLimit {
+  rate: f64,
+  burst: u32,
+}
+
+impl RateLimit {
+  /// Returns a limit accepting `rate` queries per second, with bursts of up to `burst` queries.
+  #[inline]
+  pub const fn new(rate: f64, burst: u32) -> Self {
+    Self { rate, burst }
+  }
+
+  /// Returns the number of queries per second refilled in the bucket.
+  #[inline]
+  pub const fn rate(&self) -> f64 {
+    self.rate
+  }
+
+  /// Returns the maximum number of queries accepted at once.
+  #[inline]
+  pub const fn burst(&self) -> u32 {
+    self.burst
+  }
+
+  /// Returns how long an empty bucket takes to be full again.
+  fn refill_time(&self) -> Duration {
+    if self.rate > 0.0 {
+      Duration::from_secs_f64(self.burst as f64 / self.rate)
+    } else {
+      Duration::MAX
+    }
+  }
+}
+
+struct Bucket {
+  tokens: f64,
+  last: Epoch,
+}
+
+/// Token buckets sharing the same limit, one per key.
+pub(crate) struct Buckets<K> {
+  limit: RateLimit,
+  buckets: HashMap<K, Bucket>,
+}
+
+impl<K: Eq + Hash> Buckets<K> {
+  pub(crate) fn new(limit: RateLimit) -> Self {
+    Self {
+      limit,
+      buckets: HashMap::new(),
+    }
+  }
+
+  /// Takes a token from the bucket of the key, returns `false` if the bucket is empty.
+  pub(crate) fn take(&mut self, key: K, now: Epoch) -> bool {
+    if self.buckets.len() >= MAX_IDLE_BUCKETS {
+      // The buckets which would be full again are the same as new ones
+      let refill_time = self.limit.refill_time();
+      self.buckets.retain(|_, b| now - b.last < refill_time);
+    }
+
+    let limit = self.limit;
+    let bucket = self.buckets.entry(key).or_insert(Bucket {
+      tokens: limit.burst as f64,
+      last: now,
+    });
+    let elapsed = (now - bucket.last).as_secs_f64();
+    bucket.tokens = (bucket.tokens + elapsed * limit.rate).min(limit.burst as f64);
+    bucket.last = now;
+
+    if bucket.tokens < 1.0 {
+      return false;
+    }
+    bucket.tokens -= 1.0;
+    true
+  }
+}
+
+/// Limits the incoming queries per source node and per query name.
+pub(crate) struct QueryLimiter<I> {
+  nodes: Option<Buckets<I>>,
+  names: Option<Buckets<SmolStr>>,
+}
+
+impl<I: Eq + Hash> QueryLimiter<I> {
+  pub(crate) fn new(per_node: Option<RateLimit>, per_name: Option<RateLimit>) -> Self {
+    Self {
+      nodes: per_node.map(Buckets::new),
+      names: per_name.map(Buckets::new),
+    }
+  }
+
+  /// Returns `true` if the query of the given source node and name is accepted.
+  pub(crate) fn allow(&mut self, from: &I, name: &SmolStr) -> bool
+  where
+    I: Clone,
+  {
+    let now = Epoch::now();
+    // A query rejected for its source does not use up a token of its name
+    self
+      .nodes
+      .as_mut()
+      .map_or(true, |b| b.take(from.clone(), now))
+      && self
+        .names
+        .as_mut()
+        .map_or(true, |b| b.take(name.clone(), now))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_buckets() {
+    let mut buckets = Buckets::new(RateLimit::new(2.0, 3));
+    let start = Epoch::now();
+    assert!((0..3).all(|_| buckets.take("a", start)));
+    assert!(!buckets.take("a", start));
+    // the other keys have their own bucket
+    assert!(buckets.take("b", start));
+
+    // 2 tokens are refilled per second
+    let later = start + Duration::from_millis(500);
+    assert!(buckets.take("a", later));
+    assert!(!buckets.take("a", later));
+
+    // the bucket never holds more than the burst
+    let much_later = start + Duration::from_secs(60);
+    assert!((0..3).all(|_| buckets.take("a", much_later)));
+    assert!(!buckets.take("a", much_later));
+  }
+
+  #[test]
+  fn test_query_limiter() {
+    let mut limiter = QueryLimiter::new(Some(RateLimit::new(0.0, 2)), None);
+    let name = SmolStr::new("foo");
+    assert!(limiter.allow(&1, &name));
+    assert!(limiter.allow(&1, &name));
+    assert!(!limiter.allow(&1, &name));
+    assert!(limiter.allow(&2, &name));
+
+    let mut limiter = QueryLimiter::new(None, Some(RateLimit::new(0.0, 1)));
+    assert!(limiter.allow(&1, &name));
+    assert!(!limiter.allow(&2, &name));
+    assert!(limiter.allow(&2, &SmolStr::new("bar")));
+
+    let mut limiter = QueryLimiter::<u32>::new(None, None);
+    assert!((0..16).all(|_| limiter.allow(&1, &name)));
+  }
+}
@@ -0,0 +1,151 @@
+use std::{collections::HashMap, hash::Hash, time::Duration};
+
+use crate::types::Epoch;
+
+/// The number of tracked peers above which the closed circuits are evicted.
+const MAX_IDLE_CIRCUITS: usize = 1024;
+
+/// Skips relaying the query responses through the peers which consistently
+/// fail, see [`Options::relay_circuit_breaker`](crate::Options::relay_circuit_breaker).
+///
+/// Once the relays through a peer failed `failure_threshold` times in a row,
+/// the peer is skipped for `cooldown`. After the cooldown a single relay is
+/// attempted again, which closes the circuit if it succeeds and skips the
+/// peer for another cooldown otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RelayCircuitBreaker {
+  failure_threshold: u32,
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  cooldown: Duration,
+}
+
+impl Default for RelayCircuitBreaker {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl RelayCircuitBreaker {
+  /// Returns a new configuration, skipping a peer for 30 seconds after 3
+  /// consecutive failures.
+  #[inline]
+  pub const fn new() -> Self {
+    Self {
+      failure_threshold: 3,
+      cooldown: Duration::from_secs(30),
+    }
+  }
+
+  /// Sets the number of consecutive failures after which a peer is skipped,
+  /// at least 1.
+  #[inline]
+  pub const fn with_failure_threshold(mut self, threshold: u32) -> Self {
+    self.failure_threshold = if threshold == 0 { 1 } else { threshold };
+    self
+  }
+
+  /// Sets how long a failing peer is skipped.
+  #[inline]
+  pub const fn with_cooldown(mut self, cooldown: Duration) -> Self {
+    self.cooldown = cooldown;
+    self
+  }
+
+  /// Returns the number of consecutive failures after which a peer is skipped.
+  #[inline]
+  pub const fn failure_threshold(&self) -> u32 {
+    self.failure_threshold
+  }
+
+  /// Returns how long a failing peer is skipped.
+  #[inline]
+  pub const fn cooldown(&self) -> Duration {
+    self.cooldown
+  }
+}
+
+struct Circuit {
+  failures: u32,
+  open_until: Option<Epoch>,
+}
+
+/// The circuits of the peers the query responses were relayed through.
+pub(crate) struct RelayCircuits<I> {
+  opts: RelayCircuitBreaker,
+  circuits: HashMap<I, Circuit>,
+}
+
+impl<I: Eq + Hash> RelayCircuits<I> {
+  pub(crate) fn new(opts: RelayCircuitBreaker) -> Self {
+    Self {
+      opts,
+      circuits: HashMap::new(),
+    }
+  }
+
+  /// Returns `true` if the responses may be relayed through the peer.
+  pub(crate) fn allow(&self, id: &I, now: Epoch) -> bool {
+    self
+      .circuits
+      .get(id)
+      .and_then(|c| c.open_until)
+      .map_or(true, |until| now >= until)
+  }
+
+  pub(crate) fn record_success(&mut self, id: &I) {
+    self.circuits.remove(id);
+  }
+
+  pub(crate) fn record_failure(&mut self, id: I, now: Epoch) {
+    if self.circuits.len() >= MAX_IDLE_CIRCUITS {
+      // The peers which are not skipped would be relayed through anyway
+      self
+        .circuits
+        .retain(|_, c| c.open_until.is_some_and(|until| now < until));
+    }
+
+    let opts = self.opts;
+    let circuit = self.circuits.entry(id).or_insert(Circuit {
+      failures: 0,
+      open_until: None,
+    });
+    circuit.failures = circuit.failures.saturating_add(1);
+    if circuit.failures >= opts.failure_threshold {
+      circuit.open_until = Some(now + opts.cooldown);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_relay_circuits() {
+    let opts = RelayCircuitBreaker::new()
+      .with_failure_threshold(2)
+      .with_cooldown(Duration::from_secs(10));
+    let mut circuits = RelayCircuits::new(opts);
+    let now = Epoch::now();
+
+    circuits.record_failure(1, now);
+    assert!(circuits.allow(&1, now));
+    circuits.record_failure(1, now);
+    assert!(!circuits.allow(&1, now));
+    // The other peers have their own circuit
+    assert!(circuits.allow(&2, now));
+
+    // A single relay is attempted after the cooldown
+    let later = now + Duration::from_secs(10);
+    assert!(circuits.allow(&1, later));
+    circuits.record_failure(1, later);
+    assert!(!circuits.allow(&1, later + Duration::from_secs(5)));
+
+    // A success closes the circuit
+    let much_later = later + Duration::from_secs(10);
+    circuits.record_success(&1);
+    circuits.record_failure(1, much_later);
+    assert!(circuits.allow(&1, much_later));
+  }
+}
@@ -0,0 +1,20 @@
+use smol_str::SmolStr;
+
+use crate::types::Tags;
+
+/// The reserved tag advertised by the members which encrypt the gossip they send.
+pub(crate) const SECURE_TAG: &str = "_ruserf_sec";
+
+/// Adds the secure tag to the tags advertised in the meta of the local node,
+/// if it encrypts its gossip.
+pub(crate) fn advertise(tags: &mut Tags, encrypted: bool) {
+  if encrypted {
+    tags.insert(SmolStr::new(SECURE_TAG), SmolStr::new("1"));
+  }
+}
+
+/// Removes the secure tag from the decoded tags of a member, and returns
+/// whether it encrypts its gossip.
+pub(crate) fn split(tags: &mut Tags) -> bool {
+  tags.shift_remove(SECURE_TAG).is_some()
+}
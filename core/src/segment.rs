@@ -0,0 +1,63 @@
+use memberlist_core::types::OneOrMore;
+use smol_str::{format_smolstr, SmolStr};
+
+use crate::types::{Tags, UserEvents};
+
+/// The tag advertised by the members of a segment, see
+/// [`Options::segment`](crate::Options::segment).
+///
+/// Like the [`PAUSED_TAG`](crate::PAUSED_TAG), it stays in the tags of the
+/// member, so the queries can target a segment with a tag filter.
+pub const SEGMENT_TAG: &str = "_ruserf_segment";
+
+/// The prefix of the names of the user events restricted to a segment. It is
+/// followed by the segment, a `/` and the name of the event.
+pub(crate) const SEGMENT_EVENT_PREFIX: &str = "_ruserf_seg/";
+
+/// Adds the segment tag to the tags advertised in the meta of the local
+/// node, if it belongs to a segment.
+pub(crate) fn advertise(tags: &mut Tags, segment: Option<&SmolStr>) {
+  if let Some(segment) = segment {
+    tags.insert(SmolStr::new(SEGMENT_TAG), segment.clone());
+  }
+}
+
+/// Returns the name of a user event restricted to `segment`.
+pub(crate) fn event_name(segment: &str, name: &str) -> SmolStr {
+  format_smolstr!("{SEGMENT_EVENT_PREFIX}{segment}/{name}")
+}
+
+/// Returns the segment a user event is restricted to and its original name,
+/// or `None` if the event is not restricted.
+pub(crate) fn split_event_name(name: &str) -> Option<(&str, &str)> {
+  name.strip_prefix(SEGMENT_EVENT_PREFIX)?.split_once('/')
+}
+
+/// Returns the buffered events which are not restricted to another segment
+/// than `segment`, or `None` if there are none left.
+pub(crate) fn retain_events(buffered: &UserEvents, segment: &str) -> Option<UserEvents> {
+  let mut events = OneOrMore::new();
+  for e in buffered.events.iter() {
+    if !matches!(split_event_name(&e.name), Some((s, _)) if s != segment) {
+      events.push(e.clone());
+    }
+  }
+
+  (!events.is_empty()).then(|| UserEvents {
+    ltime: buffered.ltime,
+    events,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_segment_event_name() {
+    let name = event_name("us-east-1a", "deploy/web");
+    assert_eq!(name, "_ruserf_seg/us-east-1a/deploy/web");
+    assert_eq!(split_event_name(&name), Some(("us-east-1a", "deploy/web")));
+    assert_eq!(split_event_name("deploy/web"), None);
+  }
+}
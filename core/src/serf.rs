@@ -1,31 +1,64 @@
 use std::{
-  collections::HashMap,
-  sync::{atomic::AtomicBool, Arc},
+  collections::{HashMap, HashSet, VecDeque},
+  sync::{
+    atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    Arc,
+  },
 };
 
+use arc_swap::ArcSwap;
 use async_lock::{Mutex, RwLock};
 use atomic_refcell::AtomicRefCell;
 use futures::stream::FuturesUnordered;
 use memberlist_core::{
   agnostic_lite::{AsyncSpawner, RuntimeLite},
-  queue::TransmitLimitedQueue,
+  queue::{NodeCalculator, TransmitLimitedQueue},
   transport::{AddressResolver, Transport},
   types::MediumVec,
   Memberlist,
 };
+use rand::rngs::StdRng;
 
 use super::{
+  anti_entropy::AntiEntropyAudit,
+  app_query::AppQueryHandlers,
   broadcast::SerfBroadcast,
+  clock::Timer,
   coordinate::{Coordinate, CoordinateClient},
   delegate::{CompositeDelegate, Delegate},
   event::CrateEvent,
-  snapshot::SnapshotHandle,
-  types::{LamportClock, LamportTime, Members, UserEvents},
-  Options,
+  fragment::FragmentBuffer,
+  hosts::HostsTable,
+  kvstore::KvStore,
+  lock::LockTable,
+  partition::PartitionDetector,
+  rate_limit::QueryLimiter,
+  relay::RelayCircuits,
+  snapshot::{RecentEvents, SnapshotHandle},
+  task::TaskRegistry,
+  types::{Epoch, LamportClock, LamportTime, Member, Members, QueryResponseMessage, UserEvents},
+  validator::UserEventValidators,
+  Options, ReloadableOptions,
 };
 
 mod api;
+pub use api::{Health, IntentAges, PeerHealth, ShutdownReport, PAUSED_TAG};
+
+mod bandwidth;
+use bandwidth::{Bandwidth, BandwidthCounters};
+
+mod response_cache;
+use response_cache::ResponseCache;
+
+mod shutdown;
+use shutdown::ShutdownNotifier;
+pub use shutdown::ShutdownSignal;
+
+mod query_suppression;
+use query_suppression::QuerySuppressor;
+
 pub(crate) mod base;
+mod coordinate_cache;
 
 mod delegate;
 pub(crate) use delegate::*;
@@ -41,6 +74,9 @@ pub(crate) const SNAPSHOT_SIZE_LIMIT: u64 = 128 * 1024;
 /// Maximum 9KB for event name and payload
 const USER_EVENT_SIZE_LIMIT: usize = 9 * 1024;
 
+/// Maximum number of responses kept for the queries not registered yet
+const MAX_EARLY_RESPONSES: usize = 256;
+
 /// Exports the default delegate type
 pub type DefaultDelegate<T> = CompositeDelegate<
   <T as Transport>::Id,
@@ -63,6 +99,9 @@ pub(crate) struct Queries {
 #[derive(Default)]
 pub(crate) struct QueryCore<I, A> {
   responses: HashMap<LamportTime, QueryResponse<I, A>>,
+  /// The responses which arrived before their query was registered, e.g.
+  /// before [`Serf::collect_query_responses`] was called, oldest first.
+  early: VecDeque<QueryResponseMessage<I, A>>,
   min_time: LamportTime,
   buffer: Vec<Option<Queries>>,
 }
@@ -71,6 +110,19 @@ pub(crate) struct QueryCore<I, A> {
 pub(crate) struct EventCore {
   min_time: LamportTime,
   buffer: Vec<Option<UserEvents>>,
+  /// The user events seen before a restart, restored from the snapshot.
+  restored: RecentEvents,
+  /// The user events which could not be inflated, only rebroadcast once.
+  undecodable: RecentEvents,
+}
+
+/// The members a running join waits for, see [`Options::join_sync_timeout`].
+pub(crate) struct JoinSync<I> {
+  /// The members reported by the joined node which are not known yet,
+  /// `None` until its push/pull arrived.
+  missing: Option<HashSet<I>>,
+  /// Closed once all the members are known.
+  synced: async_channel::Sender<()>,
 }
 
 /// The state of the Serf instance.
@@ -104,30 +156,66 @@ impl core::fmt::Display for SerfState {
   }
 }
 
-struct NumMembers<I, A>(Arc<RwLock<Members<I, A>>>);
+/// The number of members the broadcast queues scale their retransmits and
+/// their depth by, see [`Serf::freeze_broadcast_scaling`].
+struct NumMembers<I, A> {
+  members: Arc<RwLock<Members<I, A>>>,
+  /// The frozen number of members, `0` if it follows the cluster.
+  frozen: Arc<AtomicUsize>,
+}
 
 impl<I, A> Clone for NumMembers<I, A> {
   fn clone(&self) -> Self {
-    Self(self.0.clone())
+    Self {
+      members: self.members.clone(),
+      frozen: self.frozen.clone(),
+    }
   }
 }
 
-impl<I, A> From<Arc<RwLock<Members<I, A>>>> for NumMembers<I, A> {
-  fn from(value: Arc<RwLock<Members<I, A>>>) -> Self {
-    Self(value)
+impl<I, A> NumMembers<I, A> {
+  fn new(members: Arc<RwLock<Members<I, A>>>, frozen: Option<usize>) -> Self {
+    Self {
+      members,
+      frozen: Arc::new(AtomicUsize::new(frozen.map_or(0, |n| n.max(1)))),
+    }
+  }
+
+  fn freeze(&self, members: usize) {
+    self.frozen.store(members.max(1), Ordering::Relaxed);
+  }
+
+  fn unfreeze(&self) {
+    self.frozen.store(0, Ordering::Relaxed);
+  }
+
+  fn frozen(&self) -> Option<usize> {
+    match self.frozen.load(Ordering::Relaxed) {
+      0 => None,
+      n => Some(n),
+    }
   }
 }
 
-impl<I, A> memberlist_core::queue::NodeCalculator for NumMembers<I, A>
+impl<I, A> NodeCalculator for NumMembers<I, A>
 where
   I: Send + Sync + 'static,
   A: Send + Sync + 'static,
 {
   async fn num_nodes(&self) -> usize {
-    self.0.read().await.states.len()
+    match self.frozen() {
+      Some(n) => n,
+      None => self.members.read().await.states.len(),
+    }
   }
 }
 
+/// Returns how many times a broadcast is retransmitted in a cluster of `n`
+/// members, as the broadcast queues of memberlist compute it.
+fn retransmit_limit(retransmit_mult: usize, n: usize) -> usize {
+  retransmit_mult * ((n + 1) as f64).log10().ceil() as usize
+}
+
 pub(crate) struct SerfCore<T, D = DefaultDelegate<T>>
 where
   D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
@@ -155,12 +243,55 @@ where
       NumMembers<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
     >,
   >,
+  /// The number of members the broadcast queues are scaled by.
+  num_members: NumMembers<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
 
   pub(crate) memberlist: Memberlist<T, SerfDelegate<T, D>>,
   pub(crate) members:
     Arc<RwLock<Members<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>>>,
   event_tx: async_channel::Sender<CrateEvent<T, D>>,
   pub(crate) event_join_ignore: AtomicBool,
+  /// Set once the number of alive members reaches the bootstrap expectation.
+  cluster_formed: AtomicBool,
+  /// The user events older than the event buffer, see [`Stats::old_events`].
+  old_events: AtomicU64,
+  /// The queries older than the query buffer, see [`Stats::old_queries`].
+  old_queries: AtomicU64,
+  /// The local replica of the key/value store.
+  kv: parking_lot::Mutex<KvStore>,
+  /// The fragmented user events being reassembled.
+  fragments: parking_lot::Mutex<FragmentBuffer>,
+  /// When this node was started, see [`Serf::ping_member`].
+  pub(crate) started_at: Epoch,
+  /// The bytes sent and received by message type, see [`Serf::stats`].
+  pub(crate) bandwidth: BandwidthCounters,
+  /// The clock of the background tasks, see [`Options::clock`].
+  pub(crate) timer: Timer,
+  /// Draws the random choices, see [`Options::rng_seed`].
+  pub(crate) rng: parking_lot::Mutex<StdRng>,
+  /// Limits the incoming queries, see [`Options::query_rate_limit`].
+  pub(crate) query_limiter: parking_lot::Mutex<QueryLimiter<T::Id>>,
+  /// Drops the repeated identical queries, see [`Options::query_suppression_window`].
+  pub(crate) query_suppressor: Option<parking_lot::Mutex<QuerySuppressor<T::Id>>>,
+  /// The peers the query responses are relayed through, see [`Options::relay_circuit_breaker`].
+  pub(crate) relay_circuits: Option<parking_lot::Mutex<RelayCircuits<T::Id>>>,
+  /// The responses to the idempotent internal queries, see [`Options::internal_query_cache_ttl`].
+  pub(crate) response_cache: Option<parking_lot::Mutex<ResponseCache>>,
+  /// The lock leases granted by this node.
+  pub(crate) locks: parking_lot::Mutex<LockTable<T::Id>>,
+  /// The handlers of the application queries, see [`Serf::register_query_handler`].
+  pub(crate) app_queries: AppQueryHandlers,
+  /// The long-lived background tasks, see [`Serf::task_health`].
+  pub(crate) tasks: TaskRegistry,
+  /// The validators of the user event payloads, see [`Serf::register_user_event_validator`].
+  pub(crate) user_event_validators: UserEventValidators,
+  /// The leader elected among the alive members, see [`Options::election`].
+  leader:
+    parking_lot::Mutex<Option<Member<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>>>,
+  /// Detects the likely network partitions, see [`Options::partition_detection`].
+  partition: Option<Arc<parking_lot::Mutex<PartitionDetector>>>,
+  /// The addresses of the alive members, see [`Options::hosts`].
+  hosts: Option<HostsTable>,
 
   pub(crate) event_core: RwLock<EventCore>,
   query_core: Arc<RwLock<QueryCore<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>>>,
@@ -168,16 +299,27 @@ where
     FuturesUnordered<<<T::Runtime as RuntimeLite>::Spawner as AsyncSpawner>::JoinHandle<()>>,
   >,
   pub(crate) opts: Options,
+  /// The options which can be changed at runtime, see [`Serf::reload`].
+  pub(crate) reloadable: Arc<ArcSwap<ReloadableOptions>>,
 
   state: parking_lot::Mutex<SerfState>,
 
   join_lock: Mutex<()>,
+  /// The running joins waiting for the members reported by the joined
+  /// nodes, keyed by the id of the joined node.
+  pub(crate) join_sync: parking_lot::Mutex<HashMap<T::Id, JoinSync<T::Id>>>,
+  /// Serializes the updates of the local tags, and counts how many
+  /// updates have been applied so far.
+  tags_version: Mutex<u64>,
 
   snapshot: Option<SnapshotHandle>,
   #[cfg(feature = "encryption")]
   key_manager: crate::key_manager::KeyManager<T, D>,
   shutdown_tx: async_channel::Sender<()>,
-  shutdown_rx: async_channel::Receiver<()>,
+  pub(crate) shutdown_rx: async_channel::Receiver<()>,
+  /// Tells the embedders when the shutdown begins and completes, see
+  /// [`Serf::shutdown_signal`].
+  shutdown_notifier: ShutdownNotifier,
 
   pub(crate) coord_core: Option<Arc<CoordCore<T::Id>>>,
 }
@@ -1,20 +1,35 @@
-use std::sync::atomic::Ordering;
+use std::{collections::HashSet, sync::atomic::Ordering, time::Duration};
 
 use futures::{FutureExt, StreamExt};
 use memberlist_core::{
-  bytes::{BufMut, Bytes, BytesMut},
+  bytes::Bytes,
   tracing,
   transport::{MaybeResolvedAddress, Node},
-  types::{Meta, OneOrMore, SmallVec},
+  types::{Meta, OneOrMore, SmallVec, TinyVec},
   CheapClone,
 };
 use smol_str::SmolStr;
 
 use crate::{
-  delegate::TransformDelegate,
+  addresses::{self, ADDRESSES_TAG},
+  app_meta, cluster,
+  compression::compress_payload,
+  conflict,
+  delegate::{ShutdownPhase, TransformDelegate},
   error::{Error, JoinError},
   event::EventProducer,
-  types::{LeaveMessage, Member, MessageType, SerfMessage, Tags, UserEventMessage},
+  fragment::{self, FRAGMENT_EVENT_PREFIX, FRAGMENT_HEADER_LEN},
+  hosts::HostsTable,
+  kvstore::{KvEntry, KvWatcher, KV_EVENT_PREFIX},
+  lock::{DistributedLock, LOCK_EVENT_PREFIX},
+  secure, segment,
+  task::TaskHealth,
+  types::{
+    AsMessageRef, ClusterState, DelegateVersion, DepartedMember, ExportedMember, LamportTime,
+    LeaveMessage, Member, MemberInfo, MemberState, MemberStatus, ProtocolVersion, SerfMessage,
+    Tags, UserEventMessage,
+  },
+  version::Versions,
 };
 
 use super::*;
@@ -28,15 +43,7 @@ where
     transport: T::Options,
     opts: Options,
   ) -> Result<Self, Error<T, DefaultDelegate<T>>> {
-    Self::new_in(
-      None,
-      None,
-      transport,
-      opts,
-      #[cfg(any(test, feature = "test"))]
-      None,
-    )
-    .await
+    Self::new_in(None, None, transport, opts).await
   }
 
   /// Creates a new Serf instance with the given transport and options.
@@ -45,18 +52,16 @@ where
     opts: Options,
     ev: EventProducer<T, DefaultDelegate<T>>,
   ) -> Result<Self, Error<T, DefaultDelegate<T>>> {
-    Self::new_in(
-      Some(ev.tx),
-      None,
-      transport,
-      opts,
-      #[cfg(any(test, feature = "test"))]
-      None,
-    )
-    .await
+    Self::new_in(Some(ev.tx), None, transport, opts).await
   }
 }
 
+/// The tag advertised by the members whose gossip participation is paused,
+/// see [`Serf::pause`].
+///
+/// Unlike the other reserved tags, it stays in the tags of the member.
+pub const PAUSED_TAG: &str = "_ruserf_paused";
+
 impl<T, D> Serf<T, D>
 where
   D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
@@ -68,15 +73,7 @@ where
     opts: Options,
     delegate: D,
   ) -> Result<Self, Error<T, D>> {
-    Self::new_in(
-      None,
-      Some(delegate),
-      transport,
-      opts,
-      #[cfg(any(test, feature = "test"))]
-      None,
-    )
-    .await
+    Self::new_in(None, Some(delegate), transport, opts).await
   }
 
   /// Creates a new Serf instance with the given transport, options, event sender, and delegate.
@@ -86,15 +83,7 @@ where
     ev: EventProducer<T, D>,
     delegate: D,
   ) -> Result<Self, Error<T, D>> {
-    Self::new_in(
-      Some(ev.tx),
-      Some(delegate),
-      transport,
-      opts,
-      #[cfg(any(test, feature = "test"))]
-      None,
-    )
-    .await
+    Self::new_in(Some(ev.tx), Some(delegate), transport, opts).await
   }
 
   /// Returns the local node's ID
@@ -120,6 +109,29 @@ where
     self.inner.memberlist.encryption_enabled()
   }
 
+  /// Returns the alive members which do not encrypt the gossip they send,
+  /// see [`Member::secure`]. Once it is empty, a keyring rollout has reached
+  /// every member, and the encryption can be enforced.
+  ///
+  /// The members running a version which does not advertise its encryption
+  /// state are always returned.
+  #[cfg(feature = "encryption")]
+  #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+  pub async fn unencrypted_members(
+    &self,
+  ) -> OneOrMore<Member<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>> {
+    self
+      .inner
+      .members
+      .read()
+      .await
+      .states
+      .values()
+      .filter(|s| s.member.status == MemberStatus::Alive && !s.member.secure)
+      .map(|s| s.member.cheap_clone())
+      .collect()
+  }
+
   /// Returns a receiver that can be used to wait for
   /// Serf to shutdown.
   #[inline]
@@ -127,12 +139,75 @@ where
     self.inner.shutdown_rx.clone()
   }
 
+  /// Returns a handle which resolves when Serf begins and completes its
+  /// shutdown, so the tasks of the embedder can follow its lifecycle without
+  /// polling [`Serf::state`].
+  ///
+  /// The shutdown begins once [`Serf::shutdown`] is called, and completes
+  /// once it returns, after the background tasks were joined or abandoned.
+  #[inline]
+  pub fn shutdown_signal(&self) -> ShutdownSignal {
+    self.inner.shutdown_notifier.signal()
+  }
+
+  /// Returns the options which are currently in effect among the ones
+  /// which can be changed at runtime.
+  #[inline]
+  pub fn reloadable_options(&self) -> ReloadableOptions {
+    **self.inner.reloadable.load()
+  }
+
+  /// Changes a subset of the options on the running instance.
+  ///
+  /// The new options are validated against the ones in effect and swapped
+  /// atomically, the background tasks pick them up on their next round.
+  pub fn reload(&self, opts: ReloadableOptions) -> Result<(), Error<T, D>> {
+    let current = self.inner.reloadable.load();
+    opts
+      .validate(&current)
+      .map_err(Error::invalid_reloadable_options)?;
+    self.inner.reloadable.store(Arc::new(opts));
+    tracing::info!(options=?opts, "ruserf: reloaded options");
+    Ok(())
+  }
+
+  /// Returns `true` once the number of alive members has reached
+  /// [`Options::bootstrap_expect`]. Always `true` if no expectation is configured.
+  #[inline]
+  pub fn is_cluster_formed(&self) -> bool {
+    self.inner.cluster_formed.load(Ordering::Acquire)
+  }
+
+  /// Returns the leader elected among the alive members, or `None` if the
+  /// election is disabled or no member is eligible, see [`Options::election`].
+  #[inline]
+  pub fn current_leader(
+    &self,
+  ) -> Option<Member<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>> {
+    self.inner.leader.lock().clone()
+  }
+
   /// The current state of this Serf instance.
   #[inline]
   pub fn state(&self) -> SerfState {
     *self.inner.state.lock()
   }
 
+  /// Returns the protocol version spoken by this Serf instance.
+  ///
+  /// The members speaking a version outside of
+  /// [`ProtocolVersion::MIN`]..=[`ProtocolVersion::MAX`] are refused.
+  #[inline]
+  pub fn protocol_version(&self) -> ProtocolVersion {
+    self.inner.opts.protocol_version
+  }
+
+  /// Returns the delegate version spoken by this Serf instance.
+  #[inline]
+  pub fn delegate_version(&self) -> DelegateVersion {
+    self.inner.opts.delegate_version
+  }
+
   /// Returns a point-in-time snapshot of the members of this cluster.
   #[inline]
   pub async fn members(
@@ -149,16 +224,277 @@ where
       .collect()
   }
 
+  /// Returns a point-in-time snapshot of the members of this cluster, with
+  /// when each was first seen and last changed status.
+  pub async fn members_info(
+    &self,
+  ) -> Vec<MemberInfo<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>> {
+    let now = self.inner.timer.now();
+    let members = self.inner.members.read().await;
+    members
+      .states
+      .iter()
+      .map(|(id, s)| MemberInfo::from_state(s, members.timeline.get(id), now))
+      .collect()
+  }
+
+  /// Returns the members which gracefully left and are still kept as tombstones,
+  /// with the time they left.
+  pub async fn left_members(
+    &self,
+  ) -> Vec<DepartedMember<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>> {
+    let now = self.inner.timer.now();
+    self
+      .inner
+      .members
+      .read()
+      .await
+      .left_members
+      .iter()
+      .map(|s| DepartedMember::from_state(s, now))
+      .collect()
+  }
+
+  /// Returns the failed members which are still reconnected to, with the time they failed.
+  pub async fn failed_members(
+    &self,
+  ) -> Vec<DepartedMember<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>> {
+    let now = self.inner.timer.now();
+    self
+      .inner
+      .members
+      .read()
+      .await
+      .failed_members
+      .iter()
+      .map(|s| DepartedMember::from_state(s, now))
+      .collect()
+  }
+
+  /// Exports the Lamport clocks, the members and the buffered user events, to
+  /// seed a standby instance with [`Serf::import_state`].
+  pub async fn export_state(
+    &self,
+  ) -> ClusterState<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress> {
+    let members = self.inner.members.read().await;
+    let events = self.inner.event_core.read().await;
+    ClusterState {
+      ltime: self.inner.clock.time(),
+      event_ltime: self.inner.event_clock.time(),
+      query_ltime: self.inner.query_clock.time(),
+      members: members
+        .states
+        .values()
+        .map(|s| ExportedMember {
+          member: s.member.cheap_clone(),
+          status_time: s.status_time,
+        })
+        .collect(),
+      events: events.buffer.iter().flatten().cloned().collect(),
+    }
+  }
+
+  /// Seeds this instance with the state exported by [`Serf::export_state`],
+  /// e.g. to hand over from an agent to its upgraded replacement.
+  ///
+  /// The clocks continue from the exported times, and the user events and
+  /// queries the exporting instance already saw are not delivered again. The
+  /// members unknown to this instance are added as failed, or left, members,
+  /// so the reconnector joins them without an explicit [`Serf::join`]. The
+  /// local member and the members already known are left untouched.
+  pub async fn import_state(
+    &self,
+    state: ClusterState<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+  ) {
+    // We subtract 1 since no message with that clock has been sent yet
+    if state.ltime > LamportTime::ZERO {
+      self.inner.clock.witness(state.ltime - LamportTime::new(1));
+    }
+    if state.event_ltime > LamportTime::ZERO {
+      self
+        .inner
+        .event_clock
+        .witness(state.event_ltime - LamportTime::new(1));
+    }
+    if state.query_ltime > LamportTime::ZERO {
+      self
+        .inner
+        .query_clock
+        .witness(state.query_ltime - LamportTime::new(1));
+    }
+
+    {
+      let mut qc = self.inner.query_core.write().await;
+      qc.min_time = qc.min_time.max(state.query_ltime + LamportTime::new(1));
+    }
+
+    {
+      // The buffered events are told apart from the new ones, so only the
+      // older ones are rejected by their lamport time
+      let mut ec = self.inner.event_core.write().await;
+      let min_time = state
+        .events
+        .iter()
+        .map(|e| e.ltime)
+        .min()
+        .unwrap_or(state.event_ltime + LamportTime::new(1));
+      ec.min_time = ec.min_time.max(min_time);
+      let bltime = LamportTime::new(ec.buffer.len() as u64);
+      for events in state.events {
+        let idx = u64::from(events.ltime % bltime) as usize;
+        if ec.buffer[idx]
+          .as_ref()
+          .map_or(true, |seen| seen.ltime < events.ltime)
+        {
+          ec.buffer[idx] = Some(events);
+        }
+      }
+    }
+
+    let now = self.inner.timer.now();
+    let local_id = self.inner.memberlist.local_id();
+    let mut members = self.inner.members.write().await;
+    for ExportedMember {
+      member,
+      status_time,
+    } in state.members
+    {
+      let id = member.node().id();
+      if id == local_id || members.states.contains_key(id) {
+        continue;
+      }
+
+      let left = matches!(member.status(), MemberStatus::Leaving | MemberStatus::Left);
+      let member = member.with_status(if left {
+        MemberStatus::Left
+      } else {
+        MemberStatus::Failed
+      });
+      let ms = MemberState {
+        member,
+        status_time,
+        leave_time: Some(now),
+      };
+      if left {
+        members.left_members.push(ms.clone());
+      } else {
+        members.failed_members.push(ms.clone());
+      }
+      members
+        .states
+        .insert(ms.member.node().id().cheap_clone(), ms);
+    }
+  }
+
+  /// Returns the awareness score of the local node, and the peers which are
+  /// failed or recently flapped.
+  ///
+  /// The score rises when the local node misses its own probe deadlines, so a
+  /// rising score while peers flap points at a degraded local node, e.g. CPU
+  /// starvation, rather than at the peers. The flaps are only counted when
+  /// [`Options::flap_threshold`] is set.
+  pub async fn health(&self) -> Health<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress> {
+    let now = self.inner.timer.now();
+    let window = self.inner.opts.flap_window;
+    let members = self.inner.members.read().await;
+    let peers = members
+      .states
+      .values()
+      .filter(|ms| ms.member.node.id().ne(self.local_id()))
+      .filter_map(|ms| {
+        let flaps = members.flaps.get(ms.member.node.id()).map_or(0, |flaps| {
+          flaps.iter().filter(|t| now - **t <= window).count()
+        });
+        (ms.member.status == MemberStatus::Failed || flaps > 0).then(|| PeerHealth {
+          node: ms.member.node.cheap_clone(),
+          status: ms.member.status,
+          flaps,
+        })
+      })
+      .collect();
+
+    Health {
+      score: self.inner.memberlist.health_score(),
+      max_score: self
+        .inner
+        .opts
+        .memberlist_options
+        .awareness_max_multiplier(),
+      peers,
+    }
+  }
+
+  /// Returns the health of the long-lived background tasks, e.g. the reaper,
+  /// the reconnector, the snapshotter, the queries handler and the
+  /// coalescers, to tell a stuck background loop apart from an idle one.
+  ///
+  /// Every task runs in a `ruserf.task` tracing span whose `task` field is
+  /// its [`TaskHealth::name`].
+  pub fn task_health(&self) -> Vec<TaskHealth> {
+    self.inner.tasks.health()
+  }
+
+  /// Freezes the number of members the broadcast queues scale their
+  /// retransmits and their depth by at the current number of members, e.g.
+  /// while a large part of the cluster restarts. Returns the frozen number.
+  ///
+  /// See [`Options::frozen_broadcast_scale`](crate::Options::frozen_broadcast_scale).
+  pub async fn freeze_broadcast_scaling(&self) -> usize {
+    let n = self.inner.members.read().await.states.len().max(1);
+    self.inner.num_members.freeze(n);
+    n
+  }
+
+  /// Scales the broadcast queues with the current number of members again.
+  pub fn unfreeze_broadcast_scaling(&self) {
+    self.inner.num_members.unfreeze();
+  }
+
   /// Used to provide operator debugging information
   #[inline]
   pub async fn stats(&self) -> Stats {
-    let (num_members, num_failed, num_left, health_score) = {
+    let (
+      num_members,
+      num_alive,
+      num_leaving,
+      num_failed,
+      num_left,
+      health_score,
+      intent_ages,
+      last_status_change,
+    ) = {
       let members = self.inner.members.read().await;
       let num_members = members.states.len();
+      let count = |status| {
+        members
+          .states
+          .values()
+          .filter(|s| s.member.status == status)
+          .count()
+      };
+      let num_alive = count(MemberStatus::Alive);
+      let num_leaving = count(MemberStatus::Leaving);
       let num_failed = members.failed_members.len();
       let num_left = members.left_members.len();
       let health_score = self.inner.memberlist.health_score();
-      (num_members, num_failed, num_left, health_score)
+      let now = self.inner.timer.now();
+      let intent_ages = IntentAges::new(
+        members
+          .recent_intents
+          .values()
+          .map(|intent| (now - intent.wall_time, intent.referenced)),
+      );
+      let last_status_change = members.last_status_change(now);
+      (
+        num_members,
+        num_alive,
+        num_leaving,
+        num_failed,
+        num_left,
+        health_score,
+        intent_ages,
+        last_status_change,
+      )
     };
 
     #[cfg(not(feature = "encryption"))]
@@ -166,23 +502,42 @@ where
     #[cfg(feature = "encryption")]
     let encrypted = self.inner.memberlist.encryption_enabled();
 
+    let broadcast_retransmits = retransmit_limit(
+      self.inner.opts.memberlist_options.retransmit_mult(),
+      self.inner.num_members.num_nodes().await,
+    );
+
     Stats {
       members: num_members,
+      alive: num_alive,
+      leaving: num_leaving,
       failed: num_failed,
       left: num_left,
       health_score,
+      last_status_change_ms: last_status_change.map(|t| t.as_millis() as u64),
       member_time: self.inner.clock.time().into(),
       event_time: self.inner.event_clock.time().into(),
       query_time: self.inner.query_clock.time().into(),
       intent_queue: self.inner.broadcasts.num_queued().await,
+      intent_ages,
       event_queue: self.inner.event_broadcasts.num_queued().await,
       query_queue: self.inner.query_broadcasts.num_queued().await,
+      broadcast_retransmits,
+      broadcast_scale_frozen: self.inner.num_members.frozen().is_some(),
       encrypted,
       coordinate_resets: self
         .inner
         .coord_core
         .as_ref()
         .map(|coord| coord.client.stats().resets),
+      coordinate_cache: self
+        .inner
+        .coord_core
+        .as_ref()
+        .map(|coord| coord.cache.read().len()),
+      bandwidth: self.inner.bandwidth.snapshot(),
+      old_events: self.inner.old_events.load(Ordering::Relaxed),
+      old_queries: self.inner.old_queries.load(Ordering::Relaxed),
     }
   }
 
@@ -223,14 +578,231 @@ where
   /// the cluster. Blocks until a the message is broadcast out.
   #[inline]
   pub async fn set_tags(&self, tags: Tags) -> Result<(), Error<T, D>> {
-    // Check that the meta data length is okay
-    let tags_encoded_len = <D as TransformDelegate>::tags_encoded_len(&tags);
+    let mut version = self.inner.tags_version.lock().await;
+    self.store_tags(tags, &mut version)?;
+    drop(version);
+
+    self.broadcast_tags().await
+  }
+
+  /// Sets the tag `key` of the local node to `new`, only if its current value
+  /// is `expected_old` (`None` means the tag must not be set).
+  ///
+  /// This allows multiple controllers driving the tags of the same node to avoid
+  /// lost updates. On success, returns the new [`tags_version`](Serf::tags_version).
+  /// Blocks until the message is broadcast out.
+  pub async fn set_tag_if(
+    &self,
+    key: impl Into<SmolStr>,
+    expected_old: Option<&str>,
+    new: impl Into<SmolStr>,
+  ) -> Result<u64, Error<T, D>> {
+    let key = key.into();
+    let mut version = self.inner.tags_version.lock().await;
+    let mut tags = Tags::clone(&self.inner.opts.tags.load());
+    let actual = tags.get(&key);
+    if actual.map(SmolStr::as_str) != expected_old {
+      return Err(Error::tag_mismatch(
+        key,
+        expected_old.map(SmolStr::new),
+        actual.cloned(),
+      ));
+    }
+
+    tags.insert(key, new.into());
+    self.store_tags(tags, &mut version)?;
+    let version = *version;
+
+    self.broadcast_tags().await.map(|_| version)
+  }
+
+  /// Sets the opaque application metadata of the local node, carried in
+  /// the meta of the node beside its tags, and returned by
+  /// [`Member::app_meta`]. An empty metadata removes it.
+  ///
+  /// The tags and the metadata share the meta size limit of memberlist,
+  /// an error is returned if they do not fit. Blocks until the message is
+  /// broadcast out.
+  pub async fn set_app_meta(&self, app_meta: impl Into<Bytes>) -> Result<(), Error<T, D>> {
+    let app_meta = app_meta.into();
+    // Serialize with the tag updates, which check the size of the app meta
+    let version = self.inner.tags_version.lock().await;
+    let tags_encoded_len = <D as TransformDelegate>::tags_encoded_len(
+      &self.advertised_tags(&self.inner.opts.tags.load()),
+    );
+    let encoded_len = tags_encoded_len + app_meta::encoded_len(&app_meta);
+    if encoded_len > Meta::MAX_SIZE {
+      return Err(Error::app_meta_too_large(encoded_len));
+    }
+    if let Some(d) = self.inner.memberlist.delegate() {
+      d.set_app_meta(app_meta);
+    }
+    drop(version);
+
+    self.broadcast_tags().await
+  }
+
+  /// Advertises additional addresses of the local node in order of
+  /// preference, e.g. its public address beside the private one of its node.
+  /// The members which cannot reach the node at the address of its node try
+  /// these when they reconnect to it, see [`Member::addresses`].
+  ///
+  /// The addresses count towards the size limit of the tags, an empty list
+  /// stops advertising them. Blocks until the tags are broadcast out.
+  pub async fn set_advertise_addresses(
+    &self,
+    addrs: impl IntoIterator<Item = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  ) -> Result<(), Error<T, D>> {
+    let addrs = addrs.into_iter().collect::<Vec<_>>();
+    let encoded = addresses::encode::<D>(&addrs).map_err(Error::transform_delegate)?;
+
+    // Serialize with the tag updates, which check the size of the tags
+    let version = self.inner.tags_version.lock().await;
+    let mut tags = self.advertised_tags(&self.inner.opts.tags.load());
+    tags.shift_remove(ADDRESSES_TAG);
+    addresses::advertise(&mut tags, &encoded);
+    let app_meta_len = self
+      .inner
+      .memberlist
+      .delegate()
+      .map(|d| app_meta::encoded_len(&d.app_meta()))
+      .unwrap_or_default();
+    let encoded_len = <D as TransformDelegate>::tags_encoded_len(&tags) + app_meta_len;
+    if encoded_len > Meta::MAX_SIZE {
+      return Err(Error::tags_too_large(encoded_len));
+    }
+    if let Some(d) = self.inner.memberlist.delegate() {
+      d.set_addresses(encoded);
+    }
+    drop(version);
+
+    self.broadcast_tags().await
+  }
+
+  /// Returns the version of the local tags, which is incremented on every
+  /// successful [`set_tags`](Serf::set_tags) or [`set_tag_if`](Serf::set_tag_if).
+  pub async fn tags_version(&self) -> u64 {
+    *self.inner.tags_version.lock().await
+  }
+
+  /// Returns the tags advertised in the meta of the local node.
+  fn advertised_tags(&self, tags: &Tags) -> Tags {
+    let mut advertised = Versions::local(&self.inner.opts).advertise(tags);
+    #[cfg(feature = "encryption")]
+    secure::advertise(&mut advertised, self.inner.memberlist.encryption_enabled());
+    conflict::advertise(
+      &mut advertised,
+      self.inner.memberlist.delegate().and_then(|d| d.started()),
+    );
+    segment::advertise(&mut advertised, self.inner.opts.segment.as_ref());
+    cluster::advertise(
+      &mut advertised,
+      self.inner.memberlist.delegate().and_then(|d| d.cluster()),
+    );
+    if let Some(d) = self.inner.memberlist.delegate() {
+      addresses::advertise(&mut advertised, &d.addresses());
+    }
+    if self.is_paused() {
+      advertised.insert(SmolStr::new(PAUSED_TAG), SmolStr::new("1"));
+    }
+    advertised
+  }
+
+  /// Pauses the gossip participation of the local node for a maintenance
+  /// window, without leaving the cluster.
+  ///
+  /// The node keeps answering the probes, so it stays alive and visible,
+  /// and advertises the [`PAUSED_TAG`] tag. It no longer gossips the
+  /// messages it receives, ignores the state of the push/pull syncs, and
+  /// sending user events or queries fails. Leaving still works. Blocks
+  /// until the tag is broadcast out.
+  pub async fn pause(&self) -> Result<(), Error<T, D>> {
+    let Some(d) = self.inner.memberlist.delegate() else {
+      return Ok(());
+    };
+
+    // Serialize with the tag updates, the tag takes its share of the meta
+    let version = self.inner.tags_version.lock().await;
+    if d.set_paused(true) {
+      return Ok(());
+    }
+    let app_meta_len = app_meta::encoded_len(&d.app_meta());
+    let encoded_len = <D as TransformDelegate>::tags_encoded_len(
+      &self.advertised_tags(&self.inner.opts.tags.load()),
+    ) + app_meta_len;
+    if encoded_len > Meta::MAX_SIZE {
+      d.set_paused(false);
+      return Err(Error::tags_too_large(encoded_len));
+    }
+    drop(version);
+
+    tracing::info!("ruserf: gossip paused");
+    self.broadcast_tags().await
+  }
+
+  /// Resumes the gossip participation paused by [`Serf::pause`].
+  ///
+  /// The state missed meanwhile is repaired by the next push/pull sync.
+  /// Blocks until the removal of the tag is broadcast out.
+  pub async fn resume(&self) -> Result<(), Error<T, D>> {
+    let Some(d) = self.inner.memberlist.delegate() else {
+      return Ok(());
+    };
+
+    let version = self.inner.tags_version.lock().await;
+    if !d.set_paused(false) {
+      return Ok(());
+    }
+    drop(version);
+
+    tracing::info!("ruserf: gossip resumed");
+    self.broadcast_tags().await
+  }
+
+  /// Returns whether the gossip participation is paused, see [`Serf::pause`].
+  #[inline]
+  pub fn is_paused(&self) -> bool {
+    self.inner.memberlist.delegate().is_some_and(|d| d.paused())
+  }
+
+  /// Returns whether a likely network partition is ongoing, always `false`
+  /// without [`Options::partition_detection`].
+  #[inline]
+  pub fn is_partitioned(&self) -> bool {
+    self
+      .inner
+      .partition
+      .as_ref()
+      .is_some_and(|detector| detector.lock().is_partitioned())
+  }
+
+  /// Returns the addresses of the alive members by name, `None` without
+  /// [`Options::hosts`].
+  #[inline]
+  pub fn hosts(&self) -> Option<&HostsTable> {
+    self.inner.hosts.as_ref()
+  }
+
+  fn store_tags(&self, tags: Tags, version: &mut u64) -> Result<(), Error<T, D>> {
+    // Check that the meta data length is okay, the app meta takes its share
+    let app_meta_len = self
+      .inner
+      .memberlist
+      .delegate()
+      .map(|d| app_meta::encoded_len(&d.app_meta()))
+      .unwrap_or_default();
+    let tags_encoded_len =
+      <D as TransformDelegate>::tags_encoded_len(&self.advertised_tags(&tags)) + app_meta_len;
     if tags_encoded_len > Meta::MAX_SIZE {
       return Err(Error::tags_too_large(tags_encoded_len));
     }
     // update the config
     self.inner.opts.tags.store(Arc::new(tags));
+    *version += 1;
+    Ok(())
+  }
 
+  async fn broadcast_tags(&self) -> Result<(), Error<T, D>> {
     // trigger a memberlist update
     self
       .inner
@@ -250,10 +822,100 @@ where
     payload: impl Into<Bytes>,
     coalesce: bool,
   ) -> Result<(), Error<T, D>> {
-    let name: SmolStr = name.into();
-    let payload: Bytes = payload.into();
+    self
+      .user_event_in(
+        self.segmented_event_name(name.into()),
+        payload.into(),
+        coalesce,
+        TinyVec::new(),
+      )
+      .await
+  }
+
+  /// Used to broadcast a custom user event which is only delivered by the members
+  /// whose tags match every `(tag, regex)` pair of `distribution`, with the same
+  /// semantics as the tag filters of a query.
+  ///
+  /// The other members still buffer and rebroadcast the event, so it reaches the
+  /// whole cluster, but they do not deliver it to the application. The filters
+  /// count towards the size limit of the event. The events replayed by a push/pull
+  /// sync are delivered without their filters.
+  #[inline]
+  pub async fn user_event_with_distribution<K, V>(
+    &self,
+    name: impl Into<SmolStr>,
+    payload: impl Into<Bytes>,
+    coalesce: bool,
+    distribution: impl IntoIterator<Item = (K, V)>,
+  ) -> Result<(), Error<T, D>>
+  where
+    K: Into<SmolStr>,
+    V: Into<SmolStr>,
+  {
+    self
+      .user_event_in(
+        self.segmented_event_name(name.into()),
+        payload.into(),
+        coalesce,
+        distribution
+          .into_iter()
+          .map(|(tag, expr)| (tag.into(), expr.into()))
+          .collect(),
+      )
+      .await
+  }
+
+  /// Restricts the user events of the application to the segment of the
+  /// local node, if any.
+  fn segmented_event_name(&self, name: SmolStr) -> SmolStr {
+    match self.inner.opts.segment.as_deref() {
+      Some(s) => segment::event_name(s, &name),
+      None => name,
+    }
+  }
+
+  async fn user_event_in(
+    &self,
+    name: SmolStr,
+    mut payload: Bytes,
+    coalesce: bool,
+    distribution: TinyVec<(SmolStr, SmolStr)>,
+  ) -> Result<(), Error<T, D>> {
+    if self.is_paused() {
+      return Err(Error::paused());
+    }
+
+    let mut compressed = false;
+    if let Some(compressor) = self.compressor().await {
+      if let Some(c) = compress_payload(compressor, self.inner.opts.compression_threshold, &payload)
+        .map_err(Error::compression)?
+      {
+        payload = c;
+        compressed = true;
+      }
+    }
     let payload_size_before_encoding = name.len() + payload.len();
 
+    // The members of older protocol versions would deliver the fragments
+    // as events of their own
+    let fragment_limit = self.inner.opts.max_fragmented_user_event_size;
+    if payload_size_before_encoding > self.inner.opts.max_user_event_size
+      && fragment_limit > 0
+      && self
+        .inner
+        .members
+        .read()
+        .await
+        .all_speak(ProtocolVersion::V2)
+    {
+      if payload_size_before_encoding > fragment_limit {
+        return Err(Error::user_event_limit_too_large(fragment_limit));
+      }
+      return self
+        .fragmented_user_event(name, payload, coalesce, compressed, distribution)
+        .await;
+    }
+
     // Check size before encoding to prevent needless encoding and return early if it's over the specified limit.
     if payload_size_before_encoding > self.inner.opts.max_user_event_size {
       return Err(Error::user_event_limit_too_large(
@@ -271,6 +933,8 @@ where
       name: name.clone(),
       payload,
       cc: coalesce,
+      compressed,
+      distribution,
     };
 
     // Start broadcasting the event
@@ -286,34 +950,189 @@ where
       return Err(Error::raw_user_event_too_large(len));
     }
 
-    let mut raw = BytesMut::with_capacity(len + 1); // + 1 for message type byte
-    raw.put_u8(MessageType::UserEvent as u8);
-    raw.resize(len + 1, 0);
-
-    let actual_encoded_len = <D as TransformDelegate>::encode_message(&msg, &mut raw[1..])
-      .map_err(Error::transform_delegate)?;
-    debug_assert_eq!(
-      actual_encoded_len, len,
-      "expected encoded len {} mismatch the actual encoded len {}",
-      len, actual_encoded_len
-    );
+    let raw = self.encode_outbound(msg.as_message_ref())?;
+    // The delegate may have replaced the event with a larger one
+    if let Some(raw) = &raw {
+      let len = raw.len() - 1;
+      if len > self.inner.opts.max_user_event_size || len > USER_EVENT_SIZE_LIMIT {
+        return Err(Error::raw_user_event_too_large(len));
+      }
+    }
 
     self.inner.event_clock.increment();
 
     // Process update locally
     self.handle_user_event(msg).await;
 
-    self
+    if let Some(raw) = raw {
+      self
+        .inner
+        .event_broadcasts
+        .queue_broadcast(SerfBroadcast::new(raw, None))
+        .await;
+    }
+    Ok(())
+  }
+
+  /// Gossips a user event too large for a single message as fragments, see
+  /// [`Options::max_fragmented_user_event_size`].
+  async fn fragmented_user_event(
+    &self,
+    name: SmolStr,
+    payload: Bytes,
+    coalesce: bool,
+    compressed: bool,
+    distribution: TinyVec<(SmolStr, SmolStr)>,
+  ) -> Result<(), Error<T, D>> {
+    let limit = self
       .inner
-      .event_broadcasts
-      .queue_broadcast(SerfBroadcast {
-        msg: raw.freeze(),
-        notify_tx: None,
-      })
-      .await;
+      .opts
+      .max_user_event_size
+      .min(USER_EVENT_SIZE_LIMIT);
+    let ltime = self.inner.event_clock.time();
+    let fragment = |payload| UserEventMessage {
+      ltime,
+      name: SmolStr::from(format!("{FRAGMENT_EVENT_PREFIX}{name}")),
+      payload,
+      cc: false,
+      compressed: false,
+      distribution: distribution.clone(),
+    };
+
+    // The encoded size of a fragment without its chunk, with room for the
+    // length prefix of the payload to grow
+    let header = fragment(Bytes::from(vec![0; FRAGMENT_HEADER_LEN]));
+    let overhead = <D as TransformDelegate>::message_encoded_len(&header) + 8;
+    let chunks = limit
+      .checked_sub(overhead)
+      .filter(|size| *size > 0)
+      .and_then(|size| fragment::split(&payload, size, coalesce, compressed))
+      .ok_or(Error::user_event_limit_too_large(
+        self.inner.opts.max_user_event_size,
+      ))?;
+
+    let mut fragments = Vec::with_capacity(chunks.len());
+    for chunk in chunks {
+      let msg = fragment(chunk);
+      let len = <D as TransformDelegate>::message_encoded_len(&msg);
+      if len > limit {
+        return Err(Error::raw_user_event_too_large(len));
+      }
+
+      let raw = self.encode_outbound(msg.as_message_ref())?;
+      if let Some(raw) = &raw {
+        let len = raw.len() - 1;
+        if len > limit {
+          return Err(Error::raw_user_event_too_large(len));
+        }
+      }
+      fragments.push((msg, raw));
+    }
+
+    self.inner.event_clock.increment();
+
+    // The fragments are handled like the ones of the other nodes, so the
+    // event is delivered locally once the last one is processed
+    for (msg, raw) in fragments {
+      self.handle_user_event(msg).await;
+      if let Some(raw) = raw {
+        self
+          .inner
+          .event_broadcasts
+          .queue_broadcast(SerfBroadcast::new(raw, None))
+          .await;
+      }
+    }
     Ok(())
   }
 
+  /// Returns the buffered user events with a Lamport time of at least `since`,
+  /// oldest first, so an application component started late can catch up on
+  /// the recent events before consuming the event stream.
+  ///
+  /// Only the events still held in the buffer are replayed, see
+  /// [`Options::event_buffer_size`]. The key/value and lock events are not
+  /// returned, neither are the fragmented events, which are only buffered as
+  /// fragments. The tag filters of the routed events are not kept by the
+  /// buffer, so they are returned no matter which members they targeted.
+  pub async fn replay_user_events(&self, since: LamportTime) -> Vec<UserEventMessage> {
+    let mut events = self
+      .inner
+      .event_core
+      .read()
+      .await
+      .buffer
+      .iter()
+      .flatten()
+      .filter(|events| events.ltime >= since)
+      .flat_map(|events| {
+        events.events.iter().map(|e| UserEventMessage {
+          ltime: events.ltime,
+          name: e.name.clone(),
+          payload: e.payload.clone(),
+          cc: false,
+          compressed: false,
+          distribution: Default::default(),
+        })
+      })
+      .filter(|e| {
+        !e.name.starts_with(KV_EVENT_PREFIX)
+          && !e.name.starts_with(LOCK_EVENT_PREFIX)
+          && !e.name.starts_with(FRAGMENT_EVENT_PREFIX)
+      })
+      .collect::<Vec<_>>();
+    events.sort_by_key(|e| e.ltime);
+    events
+  }
+
+  /// Writes an entry to the gossip-replicated key/value store.
+  ///
+  /// The write is gossiped as a user event, so the key and value are subject
+  /// to the same size limits. Concurrent writes of the same key are resolved
+  /// by their Lamport time, the last writer wins. Nodes which join later only
+  /// learn the writes still held in their peers' user event buffers, see
+  /// [`Options::event_buffer_size`].
+  pub async fn kv_put(
+    &self,
+    key: impl AsRef<str>,
+    value: impl Into<Bytes>,
+  ) -> Result<(), Error<T, D>> {
+    let name = SmolStr::from(format!("{KV_EVENT_PREFIX}{}", key.as_ref()));
+    self.user_event(name, value, false).await
+  }
+
+  /// Acquires the best-effort distributed lock with the given name.
+  ///
+  /// An internal query asks every member for a lease of `ttl`, the lock is
+  /// acquired once a majority of the alive members granted it. The lease is
+  /// renewed with user events while the returned lock is held, and released
+  /// by the other members if this node fails or leaves.
+  ///
+  /// Membership changes and partitions can let two members hold the same lock,
+  /// do not rely on it for correctness.
+  pub async fn lock(
+    &self,
+    name: impl Into<SmolStr>,
+    ttl: Duration,
+  ) -> Result<DistributedLock<T, D>, Error<T, D>> {
+    DistributedLock::acquire(self.clone(), name.into(), ttl).await
+  }
+
+  /// Returns the entry of the key/value store for the given key, if any.
+  #[inline]
+  pub fn kv_get(&self, key: impl AsRef<str>) -> Option<KvEntry> {
+    self.inner.kv.lock().get(key.as_ref())
+  }
+
+  /// Watches the writes to the keys starting with the given prefix.
+  ///
+  /// Only the writes applied after this call are received, an empty prefix
+  /// watches the whole store.
+  #[inline]
+  pub fn kv_watch(&self, prefix: impl Into<SmolStr>) -> KvWatcher {
+    self.inner.kv.lock().watch(prefix.into())
+  }
+
   /// Used to broadcast a new query. The query must be fairly small,
   /// and an error will be returned if the size limit is exceeded. This is only
   /// available with protocol version 4 and newer. Query parameters are optional,
@@ -353,6 +1172,23 @@ where
       self.inner.event_join_ignore.store(true, Ordering::SeqCst);
     }
 
+    // Track the members reported by the joined node, the join waits for them
+    let join_id = node.id().cheap_clone();
+    let synced = self.inner.opts.join_sync_timeout.map(|timeout| {
+      let (tx, rx) = async_channel::bounded(1);
+      self.inner.join_sync.lock().insert(
+        join_id.cheap_clone(),
+        JoinSync {
+          missing: None,
+          synced: tx,
+        },
+      );
+      (timeout, rx)
+    });
+    scopeguard::defer!({
+      self.inner.join_sync.lock().remove(&join_id);
+    });
+
     // Have memberlist attempt to join
     match self.inner.memberlist.join(node).await {
       Ok(node) => {
@@ -367,6 +1203,9 @@ where
           self.inner.event_join_ignore.store(false, Ordering::SeqCst);
         }
 
+        if let Some((timeout, synced)) = synced {
+          self.wait_join_sync(&join_id, timeout, synced).await?;
+        }
         Ok(node)
       }
       Err(e) => {
@@ -411,60 +1250,123 @@ where
       self.inner.event_join_ignore.store(true, Ordering::SeqCst);
     }
 
-    // Have memberlist attempt to join
-    match self.inner.memberlist.join_many(existing).await {
-      Ok(joined) => {
-        // Start broadcasting the update
-        if let Err(e) = self.broadcast_join(self.inner.clock.time()).await {
-          self.inner.event_join_ignore.store(false, Ordering::SeqCst);
-          return Err(JoinError {
-            joined,
-            errors: Default::default(),
-            broadcast_error: Some(e),
-          });
-        }
-        self.inner.event_join_ignore.store(false, Ordering::SeqCst);
-        Ok(joined)
+    let (joined, errors) = self.dial_many(existing).await;
+
+    // Start broadcasting the update if we joined any nodes
+    let broadcast_error = if errors.is_empty() || !joined.is_empty() {
+      self.broadcast_join(self.inner.clock.time()).await.err()
+    } else {
+      None
+    };
+    self.inner.event_join_ignore.store(false, Ordering::SeqCst);
+
+    if errors.is_empty() && broadcast_error.is_none() {
+      Ok(joined)
+    } else {
+      Err(JoinError {
+        joined,
+        errors,
+        broadcast_error,
+      })
+    }
+  }
+
+  /// Waits until the members reported by the joined node `id` are all known
+  /// locally, see [`Options::join_sync_timeout`].
+  async fn wait_join_sync(
+    &self,
+    id: &T::Id,
+    timeout: Duration,
+    synced: async_channel::Receiver<()>,
+  ) -> Result<(), Error<T, D>> {
+    // The joined node did not send its state
+    if !self
+      .inner
+      .join_sync
+      .lock()
+      .get(id)
+      .is_some_and(|sync| sync.missing.is_some())
+    {
+      return Ok(());
+    }
+
+    futures::select! {
+      _ = synced.recv().fuse() => Ok(()),
+      _ = self.inner.timer.sleep::<T::Runtime>(timeout).fuse() => {
+        let missing = self
+          .inner
+          .join_sync
+          .lock()
+          .get(id)
+          .and_then(|sync| sync.missing.as_ref().map(HashSet::len))
+          .unwrap_or_default();
+        tracing::warn!("ruserf: join timed out with {} members not synced", missing);
+        Err(Error::join_timeout())
       }
-      Err(e) => {
-        let (joined, errors) = e.into();
-        // If we joined any nodes, broadcast the join message
-        if !joined.is_empty() {
-          // Start broadcasting the update
-          if let Err(e) = self.broadcast_join(self.inner.clock.time()).await {
-            self.inner.event_join_ignore.store(false, Ordering::SeqCst);
-            return Err(JoinError {
-              joined,
-              errors: errors
-                .into_iter()
-                .map(|(addr, err)| (addr, err.into()))
-                .collect(),
-              broadcast_error: Some(e),
-            });
-          }
+    }
+  }
 
-          self.inner.event_join_ignore.store(false, Ordering::SeqCst);
-          Err(JoinError {
-            joined,
-            errors: errors
-              .into_iter()
-              .map(|(addr, err)| (addr, err.into()))
-              .collect(),
-            broadcast_error: None,
-          })
-        } else {
-          self.inner.event_join_ignore.store(false, Ordering::SeqCst);
-          Err(JoinError {
-            joined,
-            errors: errors
-              .into_iter()
-              .map(|(addr, err)| (addr, err.into()))
-              .collect(),
-            broadcast_error: None,
-          })
+  /// Joins the nodes concurrently, at most [`Options::join_parallelism`] at a
+  /// time. The nodes not joined before the [`Options::join_timeout`] fail with
+  /// a timeout error.
+  async fn dial_many(
+    &self,
+    existing: impl Iterator<Item = Node<T::Id, MaybeResolvedAddress<T>>>,
+  ) -> (
+    SmallVec<Node<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>>,
+    HashMap<Node<T::Id, MaybeResolvedAddress<T>>, Error<T, D>>,
+  ) {
+    let nodes = existing.collect::<Vec<_>>();
+    let mut pending = nodes.iter().cloned().collect::<HashSet<_>>();
+    let parallelism = match self.inner.opts.join_parallelism {
+      0 => nodes.len().max(1),
+      n => n,
+    };
+
+    let memberlist = &self.inner.memberlist;
+    let mut dials = futures::stream::iter(nodes)
+      .map(|node| async move {
+        let res = memberlist.join(node.cheap_clone()).await;
+        (node, res)
+      })
+      .buffer_unordered(parallelism);
+
+    let timeout = self.inner.opts.join_timeout;
+    let timer = &self.inner.timer;
+    let deadline = async move {
+      match timeout {
+        Some(timeout) => timer.sleep::<T::Runtime>(timeout).await,
+        None => futures::future::pending().await,
+      }
+    }
+    .fuse();
+    futures::pin_mut!(deadline);
+
+    let mut joined = SmallVec::with_capacity(pending.len());
+    let mut errors = HashMap::new();
+    loop {
+      futures::select! {
+        res = dials.next() => match res {
+          Some((node, res)) => {
+            pending.remove(&node);
+            match res {
+              Ok(node) => joined.push(node),
+              Err(e) => {
+                tracing::debug!(err=%e, "ruserf: failed to join {}", node);
+                errors.insert(node, Error::from(e));
+              }
+            }
+          }
+          None => break,
+        },
+        _ = deadline => {
+          tracing::warn!("ruserf: join timed out with {} nodes pending", pending.len());
+          errors.extend(pending.drain().map(|node| (node, Error::join_timeout())));
+          break;
         }
       }
     }
+    (joined, errors)
   }
 
   /// Gracefully exits the cluster. It is safe to call this multiple
@@ -504,6 +1406,10 @@ where
 
     let msg = SerfMessage::Leave(msg);
 
+    self
+      .notify_shutdown_phase(ShutdownPhase::BeforeLeaveBroadcast)
+      .await;
+
     // Only broadcast the leave message if there is at least one
     // other node alive.
     if self.has_alive_members().await {
@@ -514,7 +1420,7 @@ where
         _ = notify_rx.recv().fuse() => {
           // We got a response, so we are done
         }
-        _ = <T::Runtime as RuntimeLite>::sleep(self.inner.opts.broadcast_timeout).fuse() => {
+        _ = self.inner.timer.sleep::<T::Runtime>(self.inner.opts.broadcast_timeout).fuse() => {
           tracing::warn!("ruserf: timeout while waiting for graceful leave");
         }
       }
@@ -530,12 +1436,20 @@ where
       tracing::warn!("ruserf: timeout waiting for leave broadcast: {}", e);
     }
 
+    self
+      .notify_shutdown_phase(ShutdownPhase::LeaveConfirmed)
+      .await;
+
     // Wait for the leave to propagate through the cluster. The broadcast
     // timeout is how long we wait for the message to go out from our own
     // queue, but this wait is for that message to propagate through the
     // cluster. In particular, we want to stay up long enough to service
     // any probes from other nodes before they learn about us leaving.
-    <T::Runtime as RuntimeLite>::sleep(self.inner.opts.leave_propagate_delay).await;
+    self
+      .inner
+      .timer
+      .sleep::<T::Runtime>(self.inner.opts.leave_propagate_delay)
+      .await;
 
     // Transition to Left only if we not already shutdown
     {
@@ -573,40 +1487,131 @@ where
   /// to Leave. Otherwise, other nodes in the cluster will detect this node's
   /// exit as a node failure.
   ///
-  /// It is safe to call this method multiple times.
-  pub async fn shutdown(&self) -> Result<(), Error<T, D>> {
-    {
+  /// Returns a [`ShutdownReport`] telling whether the node left first, what
+  /// was dropped and how long each phase took. If the teardown, including the
+  /// [`ShutdownDelegate`](crate::delegate::ShutdownDelegate) callbacks, does
+  /// not complete before [`Options::shutdown_timeout`], the remaining phases
+  /// are abandoned and [`SerfError::ShutdownTimeout`](crate::error::SerfError::ShutdownTimeout)
+  /// is returned with the report of what was torn down.
+  ///
+  /// It is safe to call this method multiple times, the later calls return
+  /// an empty report.
+  pub async fn shutdown(&self) -> Result<ShutdownReport, Error<T, D>> {
+    let left = {
       let mut s = self.inner.state.lock();
-      match *s {
-        SerfState::Shutdown => return Ok(()),
-        SerfState::Left => {}
+      let left = match *s {
+        SerfState::Shutdown => return Ok(ShutdownReport::default()),
+        SerfState::Left => true,
         _ => {
           tracing::warn!("ruserf: shutdown without a leave");
+          false
         }
-      }
+      };
 
       // Wait to close the shutdown channel until after we've shut down the
       // memberlist and its associated network resources, since the shutdown
       // channel signals that we are cleaned up outside of Serf.
       *s = SerfState::Shutdown;
+      left
+    };
+    self.inner.shutdown_notifier.start();
+
+    // Every phase races the same deadline
+    let sleep = self
+      .inner
+      .opts
+      .shutdown_timeout
+      .map(|timeout| self.inner.timer.sleep::<T::Runtime>(timeout));
+    let deadline = async move {
+      match sleep {
+        Some(sleep) => sleep.await,
+        None => futures::future::pending().await,
+      }
+    }
+    .fuse();
+    futures::pin_mut!(deadline);
+
+    // The broadcasts still queued are never sent
+    let mut report = ShutdownReport {
+      left,
+      unflushed_broadcasts: self.inner.broadcasts.num_queued().await
+        + self.inner.event_broadcasts.num_queued().await
+        + self.inner.query_broadcasts.num_queued().await,
+      ..Default::default()
+    };
+
+    let start = Epoch::now();
+    futures::select! {
+      res = self.inner.memberlist.shutdown().fuse() => if let Err(e) = res {
+        // Nothing more is torn down, do not leave the embedders waiting
+        self.inner.shutdown_notifier.complete();
+        return Err(e.into());
+      },
+      _ = deadline => return Err(self.shutdown_timed_out(report)),
+    }
+    report.memberlist_elapsed = start.elapsed();
+    futures::select! {
+      _ = self.notify_shutdown_phase(ShutdownPhase::MemberlistShutdown).fuse() => {},
+      _ = deadline => return Err(self.shutdown_timed_out(report)),
     }
-    self.inner.memberlist.shutdown().await?;
     self.inner.shutdown_tx.close();
 
     // Wait for the snapshoter to finish if we have one
+    let start = Epoch::now();
     if let Some(ref snap) = self.inner.snapshot {
-      snap.wait().await;
+      futures::select! {
+        synced = snap.wait().fuse() => report.snapshot_synced = Some(synced),
+        _ = deadline => return Err(self.shutdown_timed_out(report)),
+      }
+    }
+    report.snapshot_elapsed = start.elapsed();
+    futures::select! {
+      _ = self.notify_shutdown_phase(ShutdownPhase::SnapshotFlushed).fuse() => {},
+      _ = deadline => return Err(self.shutdown_timed_out(report)),
     }
 
+    let start = Epoch::now();
     loop {
       if let Ok(mut handles) = self.inner.handles.try_borrow_mut() {
         let mut futs = core::mem::take(&mut *handles);
-        while futs.next().await.is_some() {}
+        loop {
+          futures::select! {
+            res = futs.next() => match res {
+              Some(_) => report.tasks_joined += 1,
+              None => break,
+            },
+            _ = deadline => {
+              // Dropping the handles detaches the tasks
+              report.tasks_abandoned = futs.len();
+              report.tasks_elapsed = start.elapsed();
+              return Err(self.shutdown_timed_out(report));
+            },
+          }
+        }
         break;
       }
     }
+    report.tasks_elapsed = start.elapsed();
 
-    Ok(())
+    self.inner.shutdown_notifier.complete();
+    Ok(report)
+  }
+
+  /// Completes a shutdown abandoned at the deadline, the phases not reached
+  /// are skipped.
+  fn shutdown_timed_out(&self, mut report: ShutdownReport) -> Error<T, D> {
+    report.timed_out = true;
+    // The background tasks not joined yet are detached
+    if let Ok(handles) = self.inner.handles.try_borrow() {
+      report.tasks_abandoned += handles.len();
+    }
+    self.inner.shutdown_tx.close();
+    tracing::warn!(
+      "ruserf: shutdown timed out with {} background tasks running",
+      report.tasks_abandoned
+    );
+    self.inner.shutdown_notifier.complete();
+    Error::shutdown_timeout(report)
   }
 
   /// Returns the network coordinate of the local node.
@@ -635,21 +1640,250 @@ where
   }
 }
 
+/// The counters of the local node, see [`Serf::stats`].
 #[viewit::viewit(vis_all = "", getters(vis_all = "pub", prefix = "get"), setters(skip))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "async-graphql", derive(async_graphql::SimpleObject))]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Stats {
+  /// The number of known members, regardless of their status.
   members: usize,
+  /// The number of alive members.
+  alive: usize,
+  /// The number of members which are leaving.
+  leaving: usize,
+  /// The number of failed members, not reaped yet.
   failed: usize,
+  /// The number of members which left, not reaped yet.
   left: usize,
+  /// The health score of the local node, lower is healthier.
   health_score: usize,
+  /// How long ago a member last changed status, in milliseconds, see
+  /// [`Serf::members_info`].
+  #[cfg_attr(
+    feature = "serde",
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  last_status_change_ms: Option<u64>,
+  /// The time of the member clock.
   member_time: u64,
+  /// The time of the user event clock.
   event_time: u64,
+  /// The time of the query clock.
   query_time: u64,
+  /// The number of queued member intents.
   intent_queue: usize,
+  /// The intents buffered for the nodes not known yet, and how long they have
+  /// been waiting for their node to join.
+  #[cfg_attr(feature = "serde", serde(default))]
+  intent_ages: IntentAges,
+  /// The number of queued user events.
   event_queue: usize,
+  /// The number of queued queries.
   query_queue: usize,
+  /// How many times each broadcast is retransmitted, scaled by the number of
+  /// members.
+  #[cfg_attr(feature = "serde", serde(default))]
+  broadcast_retransmits: usize,
+  /// Whether the number of members the broadcasts are scaled by is frozen,
+  /// see [`Serf::freeze_broadcast_scaling`].
+  #[cfg_attr(feature = "serde", serde(default))]
+  broadcast_scale_frozen: bool,
+  /// Whether the gossip is encrypted.
   encrypted: bool,
+  /// The number of resets of the local coordinate, `None` if the coordinates are disabled.
   #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
   coordinate_resets: Option<usize>,
+  /// The number of cached coordinates of the other members, `None` if the
+  /// coordinates are disabled.
+  #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+  coordinate_cache: Option<usize>,
+  /// The bytes sent and received by message type.
+  #[cfg_attr(feature = "serde", serde(default))]
+  bandwidth: Bandwidth,
+  /// The number of user events dropped for being older than the event buffer.
+  #[cfg_attr(feature = "serde", serde(default))]
+  old_events: u64,
+  /// The number of queries dropped for being older than the query buffer.
+  #[cfg_attr(feature = "serde", serde(default))]
+  old_queries: u64,
+}
+
+/// The ages of the join and leave intents buffered for the nodes not known
+/// yet, see [`Stats::get_intent_ages`].
+///
+/// Growing percentiles mean the intents are not resolved as fast as the
+/// membership changes, see [`Options::max_recent_intents`](crate::Options::max_recent_intents).
+#[viewit::viewit(vis_all = "", getters(vis_all = "pub", prefix = "get"), setters(skip))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "async-graphql", derive(async_graphql::SimpleObject))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IntentAges {
+  /// The number of buffered intents.
+  #[viewit(getter(const, attrs(doc = "Returns the number of buffered intents.")))]
+  buffered: usize,
+  /// The number of buffered intents whose node was referenced by a query or
+  /// a relay, which are evicted last.
+  #[viewit(getter(
+    const,
+    attrs(
+      doc = "Returns the number of buffered intents whose node was referenced by a query or a relay."
+    )
+  ))]
+  referenced: usize,
+  /// The median age, in milliseconds.
+  #[viewit(getter(const, attrs(doc = "Returns the median age, in milliseconds.")))]
+  p50_ms: u64,
+  /// The 90th percentile of the ages, in milliseconds.
+  #[viewit(getter(
+    const,
+    attrs(doc = "Returns the 90th percentile of the ages, in milliseconds.")
+  ))]
+  p90_ms: u64,
+  /// The 99th percentile of the ages, in milliseconds.
+  #[viewit(getter(
+    const,
+    attrs(doc = "Returns the 99th percentile of the ages, in milliseconds.")
+  ))]
+  p99_ms: u64,
+}
+
+impl IntentAges {
+  /// Computes the percentiles of the ages of the intents, by nearest rank.
+  fn new(intents: impl Iterator<Item = (Duration, bool)>) -> Self {
+    let mut referenced = 0;
+    let mut ages = intents
+      .map(|(age, r)| {
+        referenced += r as usize;
+        age.as_millis() as u64
+      })
+      .collect::<Vec<_>>();
+    ages.sort_unstable();
+
+    let percentile = |p: usize| match ages.len() {
+      0 => 0,
+      n => ages[(p * n).div_ceil(100) - 1],
+    };
+    Self {
+      buffered: ages.len(),
+      referenced,
+      p50_ms: percentile(50),
+      p90_ms: percentile(90),
+      p99_ms: percentile(99),
+    }
+  }
+}
+
+/// What [`Serf::shutdown`] managed to tear down, and how long each phase took.
+#[viewit::viewit(vis_all = "", getters(vis_all = "pub", style = "move"), setters(skip))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ShutdownReport {
+  /// Whether the node left the cluster with [`Serf::leave`] before it was
+  /// shut down, otherwise the other members will see it fail.
+  #[viewit(getter(
+    const,
+    attrs(doc = "Returns whether the node left the cluster before it was shut down.")
+  ))]
+  left: bool,
+  /// The intents, user events and queries still queued for broadcast when the
+  /// memberlist was shut down, which were never sent.
+  #[viewit(getter(
+    const,
+    attrs(doc = "Returns the number of broadcasts dropped by the shutdown.")
+  ))]
+  unflushed_broadcasts: usize,
+  /// Whether the snapshot was flushed and synced to disk, `None` if there
+  /// is no snapshot or the shutdown timed out before it was.
+  #[viewit(getter(
+    const,
+    attrs(doc = "Returns whether the snapshot was synced to disk, if there is one.")
+  ))]
+  snapshot_synced: Option<bool>,
+  /// The number of background tasks which exited before the shutdown returned.
+  #[viewit(getter(const, attrs(doc = "Returns the number of background tasks joined.")))]
+  tasks_joined: usize,
+  /// The number of background tasks still running at the deadline, see
+  /// [`Options::shutdown_timeout`](crate::Options::shutdown_timeout).
+  #[viewit(getter(
+    const,
+    attrs(doc = "Returns the number of background tasks abandoned at the deadline.")
+  ))]
+  tasks_abandoned: usize,
+  /// Whether the shutdown deadline elapsed before the teardown completed, only
+  /// set in the report of [`SerfError::ShutdownTimeout`](crate::error::SerfError::ShutdownTimeout).
+  #[viewit(getter(const, attrs(doc = "Returns whether the shutdown deadline elapsed.")))]
+  timed_out: bool,
+  /// How long the shutdown of the memberlist took.
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  #[viewit(getter(
+    const,
+    attrs(doc = "Returns how long the shutdown of the memberlist took.")
+  ))]
+  memberlist_elapsed: Duration,
+  /// How long waiting for the snapshot flush took.
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  #[viewit(getter(
+    const,
+    attrs(doc = "Returns how long waiting for the snapshot flush took.")
+  ))]
+  snapshot_elapsed: Duration,
+  /// How long joining the background tasks took.
+  #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+  #[viewit(getter(
+    const,
+    attrs(doc = "Returns how long joining the background tasks took.")
+  ))]
+  tasks_elapsed: Duration,
+}
+
+/// The health of the local node as seen by the failure detector, see [`Serf::health`].
+#[viewit::viewit(vis_all = "", getters(vis_all = "pub", style = "ref"), setters(skip))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Health<I, A> {
+  /// The awareness score of the local node, from `0`, healthy, up to
+  /// `max_score - 1`. The probe timeouts are scaled by `score + 1`.
+  #[viewit(getter(
+    const,
+    style = "move",
+    attrs(doc = "Returns the awareness score of the local node, lower is healthier.")
+  ))]
+  score: usize,
+  /// The upper bound of the score, see the `awareness_max_multiplier` of the
+  /// memberlist options.
+  #[viewit(getter(
+    const,
+    style = "move",
+    attrs(doc = "Returns the upper bound of the awareness score.")
+  ))]
+  max_score: usize,
+  /// The peers which are failed or recently flapped.
+  #[viewit(getter(
+    style = "ref",
+    result(converter(fn = "Vec::as_slice"), type = "&[PeerHealth<I, A>]"),
+    attrs(doc = "Returns the peers which are failed or recently flapped.")
+  ))]
+  peers: Vec<PeerHealth<I, A>>,
+}
+
+/// A peer reported by [`Serf::health`].
+#[viewit::viewit(vis_all = "", getters(vis_all = "pub", style = "ref"), setters(skip))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PeerHealth<I, A> {
+  /// The peer.
+  #[viewit(getter(const, attrs(doc = "Returns the peer.")))]
+  node: Node<I, A>,
+  /// The status of the peer.
+  #[viewit(getter(const, style = "move", attrs(doc = "Returns the status of the peer.")))]
+  status: MemberStatus,
+  /// How many times the peer failed and rejoined within the flap window,
+  /// always `0` unless the flap events are enabled.
+  #[viewit(getter(
+    const,
+    style = "move",
+    attrs(doc = "Returns how many times the peer failed and rejoined within the flap window.")
+  ))]
+  flaps: usize,
 }
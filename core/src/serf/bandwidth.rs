@@ -0,0 +1,199 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{compression::COMPRESSED_PUSH_PULL_TAG, types::MessageType};
+
+/// The bytes sent and received for a type of message, see [`Bandwidth`].
+#[viewit::viewit(vis_all = "", getters(vis_all = "pub", prefix = "get"), setters(skip))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "async-graphql", derive(async_graphql::SimpleObject))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MessageBandwidth {
+  /// The number of bytes sent, including the retransmissions of the gossip.
+  sent: u64,
+  /// The number of bytes received.
+  received: u64,
+}
+
+/// The bytes sent and received by the local node since it started, by
+/// message type, see [`Serf::stats`](crate::Serf::stats).
+///
+/// Only the messages of ruserf are accounted, not the probes and the
+/// membership gossip of memberlist, nor the framing of the transport.
+#[viewit::viewit(vis_all = "", getters(vis_all = "pub", prefix = "get"), setters(skip))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "async-graphql", derive(async_graphql::SimpleObject))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bandwidth {
+  /// The leave intents.
+  leave: MessageBandwidth,
+  /// The join intents.
+  join: MessageBandwidth,
+  /// The push/pull syncs.
+  push_pull: MessageBandwidth,
+  /// The user events.
+  user_event: MessageBandwidth,
+  /// The queries.
+  query: MessageBandwidth,
+  /// The query responses, including the acks.
+  query_response: MessageBandwidth,
+  /// The name conflict responses.
+  conflict_response: MessageBandwidth,
+  /// The relayed query responses.
+  relay: MessageBandwidth,
+  /// The key requests, always empty without the `encryption` feature.
+  key_request: MessageBandwidth,
+  /// The key responses, always empty without the `encryption` feature.
+  key_response: MessageBandwidth,
+}
+
+/// The names of the slots, also the `message_type` label of the metrics.
+const SLOTS: [&str; 10] = [
+  "leave",
+  "join",
+  "push_pull",
+  "user_event",
+  "query",
+  "query_response",
+  "conflict_response",
+  "relay",
+  "key_request",
+  "key_response",
+];
+
+fn slot(ty: u8) -> Option<usize> {
+  if ty == COMPRESSED_PUSH_PULL_TAG {
+    return Some(2);
+  }
+
+  Some(match MessageType::try_from(ty).ok()? {
+    MessageType::Leave => 0,
+    MessageType::Join => 1,
+    MessageType::PushPull => 2,
+    MessageType::UserEvent => 3,
+    MessageType::Query => 4,
+    MessageType::QueryResponse => 5,
+    MessageType::ConflictResponse => 6,
+    MessageType::Relay => 7,
+    #[cfg(feature = "encryption")]
+    MessageType::KeyRequest => 8,
+    #[cfg(feature = "encryption")]
+    MessageType::KeyResponse => 9,
+    _ => return None,
+  })
+}
+
+/// Counts the bytes of the encoded messages, which start with their type.
+pub(crate) struct BandwidthCounters {
+  sent: [AtomicU64; SLOTS.len()],
+  received: [AtomicU64; SLOTS.len()],
+  #[cfg(feature = "metrics")]
+  metric_labels: std::sync::Arc<memberlist_core::types::MetricLabels>,
+}
+
+impl BandwidthCounters {
+  pub(crate) fn new(
+    #[cfg(feature = "metrics")] metric_labels: std::sync::Arc<memberlist_core::types::MetricLabels>,
+  ) -> Self {
+    Self {
+      sent: Default::default(),
+      received: Default::default(),
+      #[cfg(feature = "metrics")]
+      metric_labels,
+    }
+  }
+
+  pub(crate) fn sent(&self, msg: &[u8]) {
+    self.record(
+      &self.sent,
+      msg,
+      #[cfg(feature = "metrics")]
+      "ruserf.bandwidth.sent",
+    );
+  }
+
+  pub(crate) fn received(&self, msg: &[u8]) {
+    self.record(
+      &self.received,
+      msg,
+      #[cfg(feature = "metrics")]
+      "ruserf.bandwidth.received",
+    );
+  }
+
+  fn record(
+    &self,
+    counters: &[AtomicU64; SLOTS.len()],
+    msg: &[u8],
+    #[cfg(feature = "metrics")] name: &'static str,
+  ) {
+    let Some(idx) = msg.first().and_then(|ty| slot(*ty)) else {
+      return;
+    };
+    counters[idx].fetch_add(msg.len() as u64, Ordering::Relaxed);
+
+    #[cfg(feature = "metrics")]
+    {
+      let labels = self
+        .metric_labels
+        .iter()
+        .cloned()
+        .chain(std::iter::once(metrics::Label::new(
+          "message_type",
+          SLOTS[idx],
+        )))
+        .collect::<Vec<_>>();
+      metrics::counter!(name, labels).increment(msg.len() as u64);
+    }
+  }
+
+  pub(crate) fn snapshot(&self) -> Bandwidth {
+    let get = |idx: usize| MessageBandwidth {
+      sent: self.sent[idx].load(Ordering::Relaxed),
+      received: self.received[idx].load(Ordering::Relaxed),
+    };
+    Bandwidth {
+      leave: get(0),
+      join: get(1),
+      push_pull: get(2),
+      user_event: get(3),
+      query: get(4),
+      query_response: get(5),
+      conflict_response: get(6),
+      relay: get(7),
+      key_request: get(8),
+      key_response: get(9),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_bandwidth_counters() {
+    let counters = BandwidthCounters::new(
+      #[cfg(feature = "metrics")]
+      Default::default(),
+    );
+    counters.sent(&[MessageType::UserEvent as u8, 1, 2]);
+    counters.sent(&[MessageType::UserEvent as u8]);
+    counters.received(&[MessageType::PushPull as u8, 1, 2, 3]);
+    // Unknown and empty messages are ignored
+    counters.received(&[200, 1]);
+    counters.received(&[]);
+
+    let bandwidth = counters.snapshot();
+    assert_eq!(bandwidth.user_event.sent, 4);
+    assert_eq!(bandwidth.user_event.received, 0);
+    assert_eq!(bandwidth.push_pull.received, 4);
+    assert_eq!(
+      Bandwidth {
+        user_event: Default::default(),
+        push_pull: Default::default(),
+        ..bandwidth
+      },
+      Bandwidth::default()
+    );
+  }
+}
@@ -1,34 +1,64 @@
-use std::time::Duration;
+use std::{sync::atomic::Ordering, time::Duration};
 
 use futures::{FutureExt, StreamExt};
+use indexmap::{IndexMap, IndexSet};
 use memberlist_core::{
-  agnostic_lite::Detach,
   bytes::{BufMut, Bytes, BytesMut},
   delegate::EventDelegate,
-  tracing,
+  tracing::{self, Instrument},
   transport::{MaybeResolvedAddress, Node},
   types::{Meta, NodeState, OneOrMore, TinyVec},
   CheapClone,
 };
-use rand::{Rng, SeedableRng};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
 use smol_str::SmolStr;
 
 use crate::{
-  coalesce::{coalesced_event, MemberEventCoalescer, UserEventCoalescer},
+  addresses, app_meta,
+  app_query::AppQueryHandlers,
+  cluster,
+  coalesce::{coalesced_event, MemberEventBatcher, MemberEventCoalescer, UserEventCoalescer},
+  compression::{compress_payload, decompress_payload, Compressor},
+  conflict::{self, ConflictResolution},
   coordinate::CoordinateOptions,
-  delegate::TransformDelegate,
+  delegate::{
+    Decision, ReapDecision, ReapReason, ShutdownPhase, SnapshotReplay, TransformDelegate,
+  },
+  election::elect,
   error::Error,
-  event::{InternalQueryEvent, MemberEvent, MemberEventType, QueryContext, QueryEvent},
-  snapshot::{open_and_replay_snapshot, Snapshot},
+  event::{
+    backpressured_event, sunk_event, ClusterFormedEvent, InternalQueryEvent, InvalidUserEvent,
+    LeaderChangedEvent, MemberEvent, MemberEventType, QueryContext, QueryEvent,
+  },
+  fragment::FRAGMENT_EVENT_PREFIX,
+  hosts::{hosts_event, HostsTable},
+  kvstore::{KvStore, KV_EVENT_PREFIX},
+  lock::{LockTable, LOCK_EVENT_PREFIX},
+  maintenance::{next_round, MaintenanceScheduler, MaintenanceState, MaintenanceTask},
+  rate_limit::QueryLimiter,
+  secure, segment,
+  snapshot::{
+    notify_snapshot_error, open_and_replay_snapshot, trim_recent_events, EventDigest, RecentEvents,
+    Snapshot,
+  },
+  task::TaskHeartbeat,
   types::{
-    DelegateVersion, Epoch, JoinMessage, LeaveMessage, Member, MemberState, MemberStatus,
-    MemberlistDelegateVersion, MemberlistProtocolVersion, MessageType, NodeIntent, ProtocolVersion,
-    QueryFlag, QueryMessage, QueryResponseMessage, SerfMessage, UserEvent, UserEventMessage,
+    scope, AsMessageRef, Epoch, Filter, JoinMessage, LeaveMessage, Member, MemberState,
+    MemberStatus, MemberlistDelegateVersion, MemberlistProtocolVersion, MessageType, NodeIntent,
+    ProtocolVersion, QueryFlag, QueryMessage, QueryResponseMessage, SerfMessage, SerfMessageRef,
+    TombstoneEviction, UserEvent, UserEventMessage,
   },
-  QueueOptions,
+  version::Versions,
+  QueueOptions, ReloadableOptions,
+};
+
+use self::{
+  coordinate_cache::{self, CoordinatePersister},
+  internal_query::SerfQueries,
 };
 
-use self::internal_query::SerfQueries;
+#[cfg(feature = "encryption")]
+use crate::snapshot::{open_and_replay_encrypted_snapshot, SnapshotCipher, SnapshotError};
 
 use super::*;
 
@@ -42,71 +72,95 @@ where
   D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
   T: Transport,
 {
-  #[cfg(feature = "test")]
-  pub(crate) async fn with_message_dropper(
-    transport: T::Options,
-    opts: Options,
-    message_dropper: Box<dyn delegate::MessageDropper>,
-  ) -> Result<Self, Error<T, D>> {
-    Self::new_in(
-      None,
-      None,
-      transport,
-      opts,
-      #[cfg(feature = "test")]
-      Some(message_dropper),
-    )
-    .await
-  }
-
   pub(crate) async fn new_in(
     ev: Option<async_channel::Sender<CrateEvent<T, D>>>,
     delegate: Option<D>,
     transport: T::Options,
     opts: Options,
-    #[cfg(any(test, feature = "test"))] message_dropper: Option<Box<dyn delegate::MessageDropper>>,
   ) -> Result<Self, Error<T, D>> {
     if opts.max_user_event_size > USER_EVENT_SIZE_LIMIT {
       return Err(Error::user_event_limit_too_large(USER_EVENT_SIZE_LIMIT));
     }
 
+    opts.validate().map_err(Error::invalid_options)?;
+
+    let started = opts.conflict_resolution.started();
+    let cluster = opts.cluster_name.as_deref().map(cluster::hash);
+
     // Check that the meta data length is okay
     {
-      let tags = opts.tags.load();
-      if !tags.as_ref().is_empty() {
-        let len = <D as TransformDelegate>::tags_encoded_len(&tags);
-        if len > Meta::MAX_SIZE {
-          return Err(Error::tags_too_large(len));
-        }
+      let mut tags = Versions::local(&opts).advertise(&opts.tags.load());
+      // The encryption state of the transport is not known yet, assume the worst case
+      secure::advertise(&mut tags, true);
+      conflict::advertise(&mut tags, started);
+      cluster::advertise(&mut tags, cluster.as_ref());
+      let len = <D as TransformDelegate>::tags_encoded_len(&tags);
+      if len > Meta::MAX_SIZE {
+        return Err(Error::tags_too_large(len));
       }
     }
 
     let (shutdown_tx, shutdown_rx) = async_channel::bounded(1);
 
     let handles = FuturesUnordered::new();
+    let reloadable = Arc::new(ArcSwap::from_pointee(opts.reloadable()));
+    let timer = Timer::new(opts.clock.clone());
+    let tasks = TaskRegistry::default();
     let event_tx = ev.map(|mut event_tx| {
+      // Apply the backpressure policy right before the events reach the user
+      event_tx = backpressured_event(
+        event_tx,
+        opts.event_backpressure,
+        #[cfg(feature = "metrics")]
+        opts.memberlist_options.metric_labels().clone(),
+      );
+
+      // Check if member event batching is enabled, the batches are made of
+      // the events which made it through the coalescer, if any
+      if opts.member_event_batch_window > Duration::ZERO {
+        let window = opts.member_event_batch_window;
+        event_tx = coalesced_event(
+          event_tx,
+          shutdown_rx.clone(),
+          timer.clone(),
+          move || (window, window),
+          MemberEventBatcher::new(),
+          &tasks,
+        );
+      }
+
       // Check if serf member event coalescing is enabled
       if opts.coalesce_period > Duration::ZERO && opts.quiescent_period > Duration::ZERO {
         let c = MemberEventCoalescer::new();
 
+        let reloadable = reloadable.clone();
         event_tx = coalesced_event(
           event_tx,
           shutdown_rx.clone(),
-          opts.coalesce_period,
-          opts.quiescent_period,
+          timer.clone(),
+          move || {
+            let opts = reloadable.load();
+            (opts.coalesce_period, opts.quiescent_period)
+          },
           c,
+          &tasks,
         );
       }
 
       // Check if user event coalescing is enabled
       if opts.user_coalesce_period > Duration::ZERO && opts.user_quiescent_period > Duration::ZERO {
         let c = UserEventCoalescer::new();
+        let reloadable = reloadable.clone();
         event_tx = coalesced_event(
           event_tx,
           shutdown_rx.clone(),
-          opts.user_coalesce_period,
-          opts.user_quiescent_period,
+          timer.clone(),
+          move || {
+            let opts = reloadable.load();
+            (opts.user_coalesce_period, opts.user_quiescent_period)
+          },
           c,
+          &tasks,
         );
       }
 
@@ -116,52 +170,177 @@ where
     // Listen for internal Serf queries. This is setup before the snapshotter, since
     // we want to capture the query-time, but the internal listener does not passthrough
     // the queries
-    let (event_tx, handle) = SerfQueries::new(event_tx.clone(), shutdown_rx.clone());
+    let (event_tx, handle) = SerfQueries::new(event_tx.clone(), shutdown_rx.clone(), &tasks);
     handles.push(handle);
 
+    // Record the events before the internal queries are filtered out
+    let event_tx = match opts.event_sink.clone() {
+      Some(sink) => sunk_event(event_tx, sink),
+      None => event_tx,
+    };
+
+    let hosts = opts.hosts.clone().map(HostsTable::new);
+    let event_tx = match hosts.clone() {
+      Some(table) => hosts_event(event_tx, table),
+      None => event_tx,
+    };
+
     let clock = LamportClock::new();
     let event_clock = LamportClock::new();
     let query_clock = LamportClock::new();
     let mut event_min_time = LamportTime::ZERO;
     let mut query_min_time = LamportTime::ZERO;
 
+    // Create the underlying memberlist that will manage membership
+    // and failure detection for the Serf instance. The keyring is owned by
+    // the transport, so this happens before the snapshot is replayed.
+    let memberlist = Memberlist::with_delegate(
+      SerfDelegate::new(
+        delegate,
+        opts.tags.clone(),
+        Versions::local(&opts),
+        started,
+        opts.segment.clone(),
+        cluster,
+        #[cfg(any(test, feature = "test"))]
+        opts.message_dropper.clone(),
+      ),
+      transport,
+      opts.memberlist_options.clone(),
+    )
+    .await?;
+
+    // The keys persisted by a previous run take over the ones of the transport
+    #[cfg(feature = "encryption")]
+    if let Some(path) = opts.keyring_file.as_ref() {
+      let loaded = match memberlist.keyring() {
+        Some(keyring) => crate::key_manager::load_keyring_file(path, keyring).await,
+        None => {
+          tracing::warn!(
+            "ruserf: ignoring keyring file {}, encryption is not enabled on the transport",
+            path.display()
+          );
+          Ok(())
+        }
+      };
+      if let Err(e) = loaded {
+        let _ = memberlist.shutdown().await;
+        return Err(Error::keyring_file(e));
+      }
+    }
+
     // Try access the snapshot
-    let (old_clock, old_event_clock, old_query_clock, event_tx, alive_nodes, handle) =
-      if let Some(sp) = opts.snapshot_path.as_ref() {
-        let rs = open_and_replay_snapshot::<_, _, D, _>(sp, opts.rejoin_after_leave)?;
-        let old_clock = rs.last_clock;
-        let old_event_clock = rs.last_event_clock;
-        let old_query_clock = rs.last_query_clock;
-        let (event_tx, alive_nodes, handle) = Snapshot::from_replay_result(
-          rs,
-          SNAPSHOT_SIZE_LIMIT,
-          opts.rejoin_after_leave,
-          clock.clone(),
-          event_tx,
-          shutdown_rx.clone(),
-          #[cfg(feature = "metrics")]
-          opts.memberlist_options.metric_labels().clone(),
-        )?;
-        event_min_time = old_event_clock + LamportTime::new(1);
-        query_min_time = old_query_clock + LamportTime::new(1);
-        (
-          old_clock,
-          old_event_clock,
-          old_query_clock,
-          event_tx,
-          alive_nodes,
-          Some(handle),
-        )
-      } else {
-        (
+    let snapshot = async {
+      let Some(sp) = opts.snapshot_path.as_ref() else {
+        return Ok((
           LamportTime::new(0),
           LamportTime::new(0),
           LamportTime::new(0),
+          RecentEvents::new(),
           event_tx,
           TinyVec::new(),
           None,
+        ));
+      };
+
+      #[cfg(feature = "encryption")]
+      let rs = if opts.snapshot_encryption {
+        let keyring = memberlist.keyring().ok_or(SnapshotError::NoKeyring)?;
+        let cipher = SnapshotCipher::new(keyring.clone()).await;
+        open_and_replay_encrypted_snapshot::<_, _, D, _>(
+          sp,
+          opts.rejoin_after_leave,
+          cipher,
+          opts.snapshot_compressor.clone(),
+        )
+      } else {
+        open_and_replay_snapshot::<_, _, D, _>(
+          sp,
+          opts.rejoin_after_leave,
+          opts.snapshot_compressor.clone(),
         )
       };
+      #[cfg(not(feature = "encryption"))]
+      let rs = open_and_replay_snapshot::<_, _, D, _>(
+        sp,
+        opts.rejoin_after_leave,
+        opts.snapshot_compressor.clone(),
+      );
+      let rs = rs.map_err(|e| {
+        notify_snapshot_error(opts.snapshot_delegate.as_deref(), &e);
+        e
+      })?;
+      if let Some(d) = opts.snapshot_delegate.as_deref() {
+        d.on_snapshot_replay_complete(&SnapshotReplay {
+          alive_nodes: rs.alive_nodes.len(),
+          clock: rs.last_clock,
+          event_clock: rs.last_event_clock,
+          query_clock: rs.last_query_clock,
+        });
+      }
+
+      let old_clock = rs.last_clock;
+      let old_event_clock = rs.last_event_clock;
+      let old_query_clock = rs.last_query_clock;
+      let mut recent_events = rs.recent_events.clone();
+      trim_recent_events(
+        &mut recent_events,
+        old_event_clock,
+        opts.event_buffer_size as u64,
+      );
+      let (event_tx, alive_nodes, handle) = Snapshot::from_replay_result(
+        rs,
+        SNAPSHOT_SIZE_LIMIT,
+        opts.event_buffer_size,
+        opts.rejoin_after_leave,
+        clock.clone(),
+        event_clock.clone(),
+        query_clock.clone(),
+        opts.snapshot_clock_interval,
+        event_tx,
+        shutdown_rx.clone(),
+        opts.snapshot_delegate.clone(),
+        #[cfg(feature = "metrics")]
+        opts.memberlist_options.metric_labels().clone(),
+      )?;
+      Ok::<_, Error<T, D>>((
+        old_clock,
+        old_event_clock,
+        old_query_clock,
+        recent_events,
+        event_tx,
+        alive_nodes,
+        Some(handle),
+      ))
+    }
+    .await;
+    let (
+      old_clock,
+      old_event_clock,
+      old_query_clock,
+      recent_events,
+      event_tx,
+      mut alive_nodes,
+      handle,
+    ) = match snapshot {
+      Ok(snapshot) => snapshot,
+      Err(e) => {
+        let _ = memberlist.shutdown().await;
+        return Err(e);
+      }
+    };
+    if let Some(handle) = &handle {
+      tasks.track(handle.task());
+    }
+    if handle.is_some() {
+      // The events seen within the restored window are told apart by their
+      // digests, so only the older ones are rejected by their lamport time
+      event_min_time = match recent_events.keys().next() {
+        Some(oldest) => *oldest,
+        None => old_event_clock + LamportTime::new(1),
+      };
+      query_min_time = old_query_clock + LamportTime::new(1);
+    }
 
     // Set up network coordinate client.
     let coord = (!opts.disable_coordinates).then_some({
@@ -171,8 +350,28 @@ where
         ..Default::default()
       })
     });
+    // Warm start from the persisted coordinates, if any
+    let mut coord_cache = HashMap::new();
+    if let (Some(client), Some(path)) = (&coord, opts.coordinate_cache_path.as_ref()) {
+      match coordinate_cache::load::<D>(path) {
+        Ok(Some((local, peers))) => {
+          if let Err(e) = client.set_coordinate(local) {
+            tracing::warn!(err=%e, "ruserf: ignoring the persisted coordinate of the local node");
+          }
+          let local = client.get_coordinate();
+          coord_cache = peers
+            .into_iter()
+            .filter(|(_, c)| c.is_valid() && local.is_compatible_with(c))
+            .collect();
+        }
+        Ok(None) => {}
+        Err(e) => {
+          tracing::warn!(err=%e, path=%path.display(), "ruserf: failed to load the persisted network coordinates");
+        }
+      }
+    }
     let members = Arc::new(RwLock::new(Members::default()));
-    let num_members = NumMembers::from(members.clone());
+    let num_members = NumMembers::new(members.clone(), opts.frozen_broadcast_scale);
     // Setup the various broadcast queues, which we use to send our own
     // custom broadcasts along the gossip channel.
     let broadcasts = Arc::new(TransmitLimitedQueue::<SerfBroadcast, _>::new(
@@ -198,31 +397,24 @@ where
     event_clock.increment();
     query_clock.increment();
 
-    // Restore the clock from snap if we have one
-    clock.witness(old_clock);
-    event_clock.witness(old_event_clock);
-    query_clock.witness(old_query_clock);
+    // Restore the clock from snap if we have one, past the times used
+    // after the last checkpoint
+    let skew = LamportTime::new(if handle.is_some() {
+      opts.clock_skew_recovery
+    } else {
+      0
+    });
+    clock.witness(old_clock + skew);
+    event_clock.witness(old_event_clock + skew);
+    query_clock.witness(old_query_clock + skew);
 
-    // Create the underlying memberlist that will manage membership
-    // and failure detection for the Serf instance.
-    let memberlist = Memberlist::with_delegate(
-      {
-        #[cfg(any(test, feature = "test"))]
-        {
-          match message_dropper {
-            Some(dropper) => SerfDelegate::with_dropper(delegate, dropper, opts.tags.clone()),
-            None => SerfDelegate::new(delegate, opts.tags.clone()),
-          }
-        }
-        #[cfg(not(any(test, feature = "test")))]
-        {
-          SerfDelegate::new(delegate, opts.tags.clone())
-        }
-      },
-      transport,
-      opts.memberlist_options.clone(),
-    )
-    .await?;
+    let mut rng = match opts.rng_seed {
+      Some(seed) => StdRng::seed_from_u64(seed),
+      None => StdRng::from_entropy(),
+    };
+    // Rejoin the members of the snapshot in a random order
+    alive_nodes.shuffle(&mut rng);
+    let reconnect_rng = StdRng::from_rng(&mut rng).unwrap();
 
     let c = SerfCore {
       clock,
@@ -233,29 +425,78 @@ where
       members,
       event_broadcasts,
       event_join_ignore: AtomicBool::new(false),
+      cluster_formed: AtomicBool::new(opts.bootstrap_expect.is_none()),
+      old_events: AtomicU64::new(0),
+      old_queries: AtomicU64::new(0),
+      num_members,
+      kv: parking_lot::Mutex::new(KvStore::new(opts.kv_max_entries)),
+      fragments: parking_lot::Mutex::new(FragmentBuffer::new(
+        opts.fragment_buffer_size,
+        opts.max_fragmented_user_event_size,
+      )),
+      started_at: Epoch::now(),
+      bandwidth: BandwidthCounters::new(
+        #[cfg(feature = "metrics")]
+        opts.memberlist_options.metric_labels().clone(),
+      ),
+      timer,
+      rng: parking_lot::Mutex::new(rng),
+      query_limiter: parking_lot::Mutex::new(QueryLimiter::new(
+        opts.query_rate_limit,
+        opts.query_name_rate_limit,
+      )),
+      query_suppressor: opts
+        .query_suppression_window
+        .map(|window| parking_lot::Mutex::new(QuerySuppressor::new(window))),
+      relay_circuits: opts
+        .relay_circuit_breaker
+        .map(|breaker| parking_lot::Mutex::new(RelayCircuits::new(breaker))),
+      response_cache: opts
+        .internal_query_cache_ttl
+        .map(|ttl| parking_lot::Mutex::new(ResponseCache::new(ttl))),
+      locks: parking_lot::Mutex::new(LockTable::default()),
+      app_queries: AppQueryHandlers::default(),
+      tasks,
+      user_event_validators: UserEventValidators::default(),
+      leader: parking_lot::Mutex::new(None),
+      partition: opts.partition_detection.map(|detection| {
+        // The snapshot remembers the members alive before the restart
+        Arc::new(parking_lot::Mutex::new(PartitionDetector::new(
+          detection,
+          alive_nodes.len() + 1,
+        )))
+      }),
+      hosts,
       event_core: RwLock::new(EventCore {
         min_time: event_min_time,
         buffer: event_buffer,
+        restored: recent_events,
+        undecodable: RecentEvents::new(),
       }),
       query_broadcasts,
       query_core: Arc::new(RwLock::new(QueryCore {
         min_time: query_min_time,
         responses: HashMap::new(),
+        early: VecDeque::new(),
         buffer: query_buffer,
       })),
       opts,
+      reloadable,
       handles: AtomicRefCell::new(handles),
       state: parking_lot::Mutex::new(SerfState::Alive),
       join_lock: Mutex::new(()),
+      join_sync: parking_lot::Mutex::new(HashMap::new()),
+      tags_version: Mutex::new(0),
       snapshot: handle,
       #[cfg(feature = "encryption")]
       key_manager: crate::key_manager::KeyManager::new(),
       shutdown_tx,
       shutdown_rx: shutdown_rx.clone(),
+      shutdown_notifier: ShutdownNotifier::new(),
       coord_core: coord.map(|cc| {
         Arc::new(CoordCore {
           client: cc,
-          cache: parking_lot::RwLock::new(HashMap::new()),
+          cache: parking_lot::RwLock::new(coord_cache),
         })
       }),
       event_tx,
@@ -265,6 +506,16 @@ where
     let that = this.clone();
     let memberlist_delegate = this.inner.memberlist.delegate().unwrap();
     memberlist_delegate.store(that);
+    // The local node was advertised before the transport was known, so a node
+    // which encrypts its gossip advertises it again
+    #[cfg(feature = "encryption")]
+    if this.inner.memberlist.encryption_enabled() {
+      memberlist_delegate.set_encrypted();
+      if let Err(e) = this.inner.memberlist.update_node(Duration::ZERO).await {
+        let _ = this.inner.memberlist.shutdown().await;
+        return Err(e.into());
+      }
+    }
     let local_node = this.inner.memberlist.local_state().await;
     if let Some(local_node) = local_node {
       memberlist_delegate.notify_join(local_node).await;
@@ -286,10 +537,18 @@ where
       members: this.inner.members.clone(),
       event_tx: this.inner.event_tx.clone(),
       shutdown_rx: shutdown_rx.clone(),
-      reap_interval: this.inner.opts.reap_interval,
+      reloadable: this.inner.reloadable.clone(),
       reconnect_timeout: this.inner.opts.reconnect_timeout,
       recent_intent_timeout: this.inner.opts.recent_intent_timeout,
       tombstone_timeout: this.inner.opts.tombstone_timeout,
+      max_left_members: this.inner.opts.max_left_members,
+      tombstone_eviction: this.inner.opts.tombstone_eviction,
+      max_reap_vetoes: this.inner.opts.max_reap_vetoes,
+      timer: this.inner.timer.clone(),
+      scheduler: this.inner.opts.maintenance_scheduler.clone(),
+      heartbeat: this.inner.tasks.register("reaper"),
+      #[cfg(feature = "metrics")]
+      metric_labels: this.inner.opts.memberlist_options.metric_labels().clone(),
     }
     .spawn();
     handles.push(h);
@@ -298,7 +557,12 @@ where
       members: this.inner.members.clone(),
       memberlist: this.inner.memberlist.clone(),
       shutdown_rx: shutdown_rx.clone(),
-      reconnect_interval: this.inner.opts.reconnect_interval,
+      reloadable: this.inner.reloadable.clone(),
+      link_local_scope_id: this.inner.opts.link_local_scope_id,
+      timer: this.inner.timer.clone(),
+      scheduler: this.inner.opts.maintenance_scheduler.clone(),
+      heartbeat: this.inner.tasks.register("reconnector"),
+      rng: reconnect_rng,
     }
     .spawn();
     handles.push(h);
@@ -306,8 +570,9 @@ where
     let h = QueueChecker {
       name: "ruserf.queue.intent",
       queue: this.inner.broadcasts.clone(),
-      members: this.inner.members.clone(),
+      num_members: this.inner.num_members.clone(),
       opts: this.inner.opts.queue_opts(),
+      reloadable: this.inner.reloadable.clone(),
       shutdown_rx: shutdown_rx.clone(),
     }
     .spawn::<T::Runtime>();
@@ -316,8 +581,9 @@ where
     let h = QueueChecker {
       name: "ruserf.queue.event",
       queue: this.inner.event_broadcasts.clone(),
-      members: this.inner.members.clone(),
+      num_members: this.inner.num_members.clone(),
       opts: this.inner.opts.queue_opts(),
+      reloadable: this.inner.reloadable.clone(),
       shutdown_rx: shutdown_rx.clone(),
     }
     .spawn::<T::Runtime>();
@@ -326,17 +592,63 @@ where
     let h = QueueChecker {
       name: "ruserf.queue.query",
       queue: this.inner.query_broadcasts.clone(),
-      members: this.inner.members.clone(),
+      num_members: this.inner.num_members.clone(),
       opts: this.inner.opts.queue_opts(),
+      reloadable: this.inner.reloadable.clone(),
       shutdown_rx: shutdown_rx.clone(),
     }
     .spawn::<T::Runtime>();
     handles.push(h);
 
+    if let (Some(detector), Some(detection)) = (
+      this.inner.partition.clone(),
+      this.inner.opts.partition_detection,
+    ) {
+      let h = PartitionChecker::<T, D> {
+        detector,
+        interval: detection.check_interval(),
+        members: this.inner.members.clone(),
+        event_tx: this.inner.event_tx.clone(),
+        shutdown_rx: shutdown_rx.clone(),
+        timer: this.inner.timer.clone(),
+        #[cfg(feature = "metrics")]
+        metric_labels: this.inner.opts.memberlist_options.metric_labels().clone(),
+      }
+      .spawn();
+      handles.push(h);
+    }
+
+    if let Some(audit) = this.inner.opts.anti_entropy_audit {
+      let h = AntiEntropyAuditor {
+        serf: this.clone(),
+        audit,
+        shutdown_rx: shutdown_rx.clone(),
+        timer: this.inner.timer.clone(),
+      }
+      .spawn();
+      handles.push(h);
+    }
+
+    if let (Some(coord_core), Some(path)) = (
+      this.inner.coord_core.clone(),
+      this.inner.opts.coordinate_cache_path.clone(),
+    ) {
+      let h = CoordinatePersister::<T> {
+        path,
+        interval: this.inner.opts.coordinate_cache_interval,
+        coord_core,
+        members: this.inner.members.clone(),
+        shutdown_rx: shutdown_rx.clone(),
+        timer: this.inner.timer.clone(),
+      }
+      .spawn::<D>();
+      handles.push(h);
+    }
+
     // Attempt to re-join the cluster if we have known nodes
     if !alive_nodes.is_empty() {
       let memberlist = this.inner.memberlist.clone();
-      Self::handle_rejoin(memberlist, alive_nodes);
+      Self::handle_rejoin(memberlist, alive_nodes, &this.inner.opts);
     }
     drop(handles);
     Ok(this)
@@ -357,34 +669,100 @@ where
     false
   }
 
-  /// Takes a Serf message type, encodes it for the wire, and queues
-  /// the broadcast. If a notify channel is given, this channel will be closed
-  /// when the broadcast is sent.
-  pub(crate) async fn broadcast(
+  /// Notifies the [`ShutdownDelegate`](crate::delegate::ShutdownDelegate), if any,
+  /// that the leave/shutdown sequence has reached the given phase.
+  pub(crate) async fn notify_shutdown_phase(&self, phase: ShutdownPhase) {
+    if let Some(d) = self.inner.memberlist.delegate().and_then(|d| d.delegate()) {
+      d.on_shutdown(phase).await;
+    }
+  }
+
+  /// Passes a message originated by this node through the
+  /// [`OutboundDelegate`](crate::delegate::OutboundDelegate), if any, and encodes
+  /// the outcome for the wire, prefixed with the message type. Returns `None` if
+  /// the delegate drops the message.
+  pub(crate) fn encode_outbound(
     &self,
-    msg: SerfMessage<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
-    notify_tx: Option<async_channel::Sender<()>>,
-  ) -> Result<(), Error<T, D>> {
-    let ty = MessageType::from(&msg);
-    let expected_encoded_len = <D as TransformDelegate>::message_encoded_len(&msg);
+    msg: SerfMessageRef<'_, T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+  ) -> Result<Option<Bytes>, Error<T, D>> {
+    let replaced;
+    let msg = match self
+      .inner
+      .memberlist
+      .delegate()
+      .and_then(|d| d.delegate())
+      .map(|d| d.before_broadcast(&msg))
+    {
+      None | Some(Decision::Keep) => msg,
+      Some(Decision::Drop) => return Ok(None),
+      Some(Decision::Replace(msg)) => {
+        replaced = *msg;
+        replaced.as_message_ref()
+      }
+    };
+
+    let ty = msg.ty();
+    let expected_encoded_len = <D as TransformDelegate>::message_encoded_len(msg);
     let mut raw = BytesMut::with_capacity(expected_encoded_len + 1); // + 1 for message type byte
     raw.put_u8(ty as u8);
     raw.resize(expected_encoded_len + 1, 0);
-    let len = <D as TransformDelegate>::encode_message(&msg, &mut raw[1..])
+    let len = <D as TransformDelegate>::encode_message(msg, &mut raw[1..])
       .map_err(Error::transform_delegate)?;
     debug_assert_eq!(
       len, expected_encoded_len,
       "expected encoded len {} mismatch the actual encoded len {}",
       expected_encoded_len, len
     );
+    Ok(Some(raw.freeze()))
+  }
+
+  /// Returns the node with its address rewritten by the
+  /// [`AddressDelegate`](crate::delegate::AddressDelegate), if any, before it
+  /// is embedded in a message for the wire.
+  pub(crate) fn outbound_node(
+    &self,
+    node: &Node<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+  ) -> Node<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress> {
+    match self.inner.memberlist.delegate().and_then(|d| d.delegate()) {
+      Some(d) => Node::new(node.id().cheap_clone(), d.outbound_address(node)),
+      None => node.cheap_clone(),
+    }
+  }
+
+  /// Returns the node decoded from a message with its address rewritten by
+  /// the [`AddressDelegate`](crate::delegate::AddressDelegate), if any.
+  pub(crate) fn inbound_node(
+    &self,
+    node: Node<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+  ) -> Node<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress> {
+    match self.inner.memberlist.delegate().and_then(|d| d.delegate()) {
+      Some(d) => Node::new(node.id().cheap_clone(), d.inbound_address(&node)),
+      None => node,
+    }
+  }
+
+  /// Takes a Serf message type, encodes it for the wire, and queues
+  /// the broadcast. If a notify channel is given, this channel will be closed
+  /// when the broadcast is sent, or right away if the message is dropped by the
+  /// [`OutboundDelegate`](crate::delegate::OutboundDelegate).
+  pub(crate) async fn broadcast(
+    &self,
+    msg: SerfMessage<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+    notify_tx: Option<async_channel::Sender<()>>,
+  ) -> Result<(), Error<T, D>> {
+    // Leaving still works while paused
+    if self.is_paused() && !matches!(msg, SerfMessage::Leave(_)) {
+      return Ok(());
+    }
+
+    let Some(raw) = self.encode_outbound(msg.as_message_ref())? else {
+      return Ok(());
+    };
 
     self
       .inner
       .broadcasts
-      .queue_broadcast(SerfBroadcast {
-        msg: raw.into(),
-        notify_tx,
-      })
+      .queue_broadcast(SerfBroadcast::new(raw, notify_tx))
       .await;
     Ok(())
   }
@@ -414,54 +792,26 @@ where
   /// Serialize the current keyring and save it to a file.
   #[cfg(feature = "encryption")]
   pub(crate) async fn write_keyring_file(&self) -> std::io::Result<()> {
-    use base64::{engine::general_purpose, Engine as _};
-
     let Some(path) = self.inner.opts.keyring_file() else {
       return Ok(());
     };
 
-    if let Some(keyring) = self.inner.memberlist.keyring() {
-      let encoded_keys = keyring
-        .keys()
-        .await
-        .map(|k| general_purpose::STANDARD.encode(k))
-        .collect::<TinyVec<_>>();
-
-      #[cfg(unix)]
-      {
-        use std::os::unix::fs::OpenOptionsExt;
-        let mut opts = std::fs::OpenOptions::new();
-        opts.truncate(true).write(true).create(true).mode(0o600);
-        return opts.open(path).and_then(|file| {
-          serde_json::to_writer_pretty(file, &encoded_keys)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
-        });
-      }
-      // TODO: I don't know how to set permissions on windows
-      // need helps :)
-      #[cfg(windows)]
-      {
-        let mut opts = std::fs::OpenOptions::new();
-        opts.truncate(true).write(true).create(true);
-        return opts.open(path).and_then(|file| {
-          serde_json::to_writer_pretty(file, &encoded_keys)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
-        });
-      }
+    match self.inner.memberlist.keyring() {
+      Some(keyring) => crate::key_manager::write_keyring_file(path, keyring).await,
+      None => Ok(()),
     }
-
-    Ok(())
   }
 
   #[cfg(feature = "test")]
   pub(crate) async fn get_queue_max(&self) -> usize {
-    let mut max = self.inner.opts.max_queue_depth;
-    if self.inner.opts.min_queue_depth > 0 {
-      let num_members = self.inner.members.read().await.states.len();
+    let opts = self.inner.reloadable.load();
+    let mut max = opts.max_queue_depth;
+    if opts.min_queue_depth > 0 {
+      let num_members = self.inner.num_members.num_nodes().await;
       max = num_members * 2;
 
-      if max < self.inner.opts.min_queue_depth {
-        max = self.inner.opts.min_queue_depth;
+      if max < opts.min_queue_depth {
+        max = opts.min_queue_depth;
       }
     }
     max
@@ -493,10 +843,12 @@ where
     self.broadcast(msg, Some(ntx)).await?;
 
     // Wait for the broadcast
-    <T::Runtime as RuntimeLite>::timeout(self.inner.opts.broadcast_timeout, nrx.recv())
-      .await
-      .map_err(|_| Error::removal_broadcast_timeout())?
-      .map_err(|_| Error::broadcast_channel_closed())
+    futures::select! {
+      res = nrx.recv().fuse() => res.map_err(|_| Error::broadcast_channel_closed()),
+      _ = self.inner.timer.sleep::<T::Runtime>(self.inner.opts.broadcast_timeout).fuse() => {
+        Err(Error::removal_broadcast_timeout())
+      }
+    }
   }
 }
 
@@ -510,16 +862,28 @@ where
   members: Arc<RwLock<Members<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>>>,
   event_tx: async_channel::Sender<CrateEvent<T, D>>,
   shutdown_rx: async_channel::Receiver<()>,
-  reap_interval: Duration,
+  reloadable: Arc<ArcSwap<ReloadableOptions>>,
   reconnect_timeout: Duration,
   recent_intent_timeout: Duration,
   tombstone_timeout: Duration,
+  max_left_members: usize,
+  tombstone_eviction: TombstoneEviction,
+  max_reap_vetoes: u32,
+  timer: Timer,
+  scheduler: Option<Arc<dyn MaintenanceScheduler>>,
+  heartbeat: TaskHeartbeat,
+  #[cfg(feature = "metrics")]
+  metric_labels: Arc<memberlist_core::types::MetricLabels>,
 }
 
 macro_rules! erase_node {
   ($tx:ident <- $coord:ident($members:ident[$id:ident].$m:ident)) => {{
     // takes a node completely out of the member list
-    $members.states.remove($id);
+    if let Some(ms) = $members.states.remove($id) {
+      $members.tag_index.remove($id, &ms.member.tags);
+    }
+    $members.flaps.remove($id);
+    $members.timeline.remove($id);
 
     // Tell the coordinate client the node has gone away and delete
     // its cached coordinates.
@@ -538,9 +902,52 @@ macro_rules! erase_node {
   }};
 }
 
+/// Counts how many times the reap of each member was delayed by
+/// [`ReconnectDelegate::on_reap`](crate::delegate::ReconnectDelegate::on_reap).
+struct ReapVetoes<I> {
+  counts: HashMap<I, u32>,
+  max: u32,
+}
+
+impl<I: Eq + core::hash::Hash + CheapClone> ReapVetoes<I> {
+  fn new(max: u32) -> Self {
+    Self {
+      counts: HashMap::new(),
+      max,
+    }
+  }
+
+  /// Returns `true` if the reap of the member can still be delayed, and
+  /// counts the delay.
+  fn veto(&mut self, id: &I) -> bool {
+    let count = self.counts.entry(id.cheap_clone()).or_insert(0);
+    if *count >= self.max {
+      return false;
+    }
+    *count += 1;
+    true
+  }
+
+  fn forget(&mut self, id: &I) {
+    self.counts.remove(id);
+  }
+
+  /// Forgets the members which are no longer waiting to be reaped, e.g.
+  /// because they rejoined.
+  fn retain<A>(&mut self, members: &Members<I, A>) {
+    self.counts.retain(|id, _| {
+      members
+        .failed_members
+        .iter()
+        .chain(members.left_members.iter())
+        .any(|m| m.member.node.id().eq(id))
+    });
+  }
+}
+
 macro_rules! reap {
   (
-    $tx:ident <- $local_id:ident.$reconnector:ident($timeout: ident($members: ident.$ty: ident, $coord:ident))
+    $tx:ident <- $local_id:ident.$reconnector:ident($timeout: ident($members: ident.$ty: ident, $coord:ident, $now:ident), $reason:expr, $vetoes:ident)
   ) => {{
     let mut n = $members.$ty.len();
     let mut i = 0;
@@ -553,12 +960,28 @@ macro_rules! reap {
 
       // Skip if the timeout is not yet reached
       if let Some(leave_time) = m.leave_time {
-        if leave_time.elapsed() <= member_timeout {
+        if $now - leave_time <= member_timeout {
           i += 1;
           continue;
         }
       }
 
+      // Give the application a chance to archive the member, or to keep it a while longer
+      if let Some(r) = $reconnector {
+        if r.on_reap(&m.member, $reason).await == ReapDecision::Delay
+          && $vetoes.veto(m.member.node.id())
+        {
+          tracing::debug!(
+            "ruserf: reap of {} member {} delayed",
+            $reason,
+            m.member.node.id()
+          );
+          i += 1;
+          continue;
+        }
+      }
+      $vetoes.forget(m.member.node.id());
+
       // Delete from the list
       $members.$ty.swap_remove(i);
       n -= 1;
@@ -578,16 +1001,34 @@ where
   T: Transport,
 {
   async fn run(self) {
-    let tick = <T::Runtime as RuntimeLite>::interval(self.reap_interval);
-    futures::pin_mut!(tick);
+    let mut vetoes = ReapVetoes::new(self.max_reap_vetoes);
     loop {
+      self.heartbeat.beat();
+      // Reload the interval on every round, it may be changed at runtime
+      let reap_interval = self.reloadable.load().reap_interval;
+      let state = MaintenanceState::new(&*self.members.read().await, reap_interval);
+      let round = next_round::<T::Runtime>(
+        self.scheduler.as_deref(),
+        &self.timer,
+        MaintenanceTask::Reap,
+        &state,
+      );
       futures::select! {
-        _ = tick.next().fuse() => {
+        _ = round.fuse() => {
           let mut ms = self.members.write().await;
           let local_id = self.memberlist.local_id();
-          Self::reap_failed(local_id, &mut ms, &self.event_tx, self.memberlist.delegate().and_then(|d| d.delegate()), self.coord_core.as_deref(), self.reconnect_timeout).await;
-          Self::reap_left(local_id, &mut ms, &self.event_tx, self.memberlist.delegate().and_then(|d| d.delegate()), self.coord_core.as_deref(), self.tombstone_timeout).await;
-          reap_intents(&mut ms.recent_intents, Epoch::now(), self.recent_intent_timeout);
+          let now = self.timer.now();
+          Self::reap_failed(local_id, &mut ms, &self.event_tx, self.memberlist.delegate().and_then(|d| d.delegate()), self.coord_core.as_deref(), self.reconnect_timeout, now, &mut vetoes).await;
+          Self::reap_left(local_id, &mut ms, &self.event_tx, self.memberlist.delegate().and_then(|d| d.delegate()), self.coord_core.as_deref(), self.tombstone_timeout, now, &mut vetoes).await;
+          Self::evict_left(local_id, &mut ms, &self.event_tx, self.coord_core.as_deref(), self.max_left_members, self.tombstone_eviction).await;
+          vetoes.retain(&ms);
+          reap_intents(&mut ms.recent_intents, now, self.recent_intent_timeout);
+          #[cfg(feature = "metrics")]
+          {
+            report_member_counts(&ms, &self.metric_labels);
+            metrics::gauge!("ruserf.health.score", self.metric_labels.iter())
+              .set(self.memberlist.health_score() as f64);
+          }
           if self.shutdown_rx.is_closed() {
             break;
           }
@@ -601,11 +1042,11 @@ where
   }
 
   fn spawn(self) -> <<T::Runtime as RuntimeLite>::Spawner as AsyncSpawner>::JoinHandle<()> {
-    <T::Runtime as RuntimeLite>::spawn(async move {
-      self.run().await;
-    })
+    let span = self.heartbeat.span();
+    <T::Runtime as RuntimeLite>::spawn(self.run().instrument(span))
   }
 
+  #[allow(clippy::too_many_arguments)]
   async fn reap_failed(
     local_id: &T::Id,
     old: &mut Members<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
@@ -613,10 +1054,13 @@ where
     reconnector: Option<&D>,
     coord: Option<&CoordCore<T::Id>>,
     timeout: Duration,
+    now: Epoch,
+    vetoes: &mut ReapVetoes<T::Id>,
   ) {
-    reap!(event_tx <- local_id.reconnector(timeout(old.failed_members, coord)))
+    reap!(event_tx <- local_id.reconnector(timeout(old.failed_members, coord, now), ReapReason::Failed, vetoes))
   }
 
+  #[allow(clippy::too_many_arguments)]
   async fn reap_left(
     local_id: &T::Id,
     old: &mut Members<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
@@ -624,8 +1068,64 @@ where
     reconnector: Option<&D>,
     coord: Option<&CoordCore<T::Id>>,
     timeout: Duration,
+    now: Epoch,
+    vetoes: &mut ReapVetoes<T::Id>,
   ) {
-    reap!(event_tx <- local_id.reconnector(timeout(old.left_members, coord)))
+    reap!(event_tx <- local_id.reconnector(timeout(old.left_members, coord, now), ReapReason::Left, vetoes))
+  }
+
+  /// Reaps the tombstones in excess of the `max_left_members`.
+  async fn evict_left(
+    local_id: &T::Id,
+    old: &mut Members<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+    event_tx: &async_channel::Sender<CrateEvent<T, D>>,
+    coord: Option<&CoordCore<T::Id>>,
+    max_left_members: usize,
+    eviction: TombstoneEviction,
+  ) {
+    if max_left_members == 0 {
+      return;
+    }
+
+    while old.left_members.len() > max_left_members {
+      let Some(idx) = eviction.select(&old.left_members) else {
+        break;
+      };
+      let m = old.left_members.swap_remove(idx);
+      let id = m.member.node.id();
+      tracing::info!("ruserf: event member reap: {} evicts {}", local_id, id);
+
+      erase_node!(event_tx <- coord(old[id].m));
+    }
+  }
+}
+
+/// Reports the number of members in each status.
+#[cfg(feature = "metrics")]
+fn report_member_counts<I, A>(
+  members: &Members<I, A>,
+  labels: &memberlist_core::types::MetricLabels,
+) {
+  for status in [
+    MemberStatus::Alive,
+    MemberStatus::Leaving,
+    MemberStatus::Left,
+    MemberStatus::Failed,
+  ] {
+    let count = members
+      .states
+      .values()
+      .filter(|s| s.member.status == status)
+      .count();
+    let labels = labels
+      .iter()
+      .cloned()
+      .chain(std::iter::once(metrics::Label::new(
+        "status",
+        status.as_str(),
+      )))
+      .collect::<Vec<_>>();
+    metrics::gauge!("ruserf.members", labels).set(count as f64);
   }
 }
 
@@ -637,7 +1137,12 @@ where
   members: Arc<RwLock<Members<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>>>,
   memberlist: Memberlist<T, SerfDelegate<T, D>>,
   shutdown_rx: async_channel::Receiver<()>,
-  reconnect_interval: Duration,
+  reloadable: Arc<ArcSwap<ReloadableOptions>>,
+  link_local_scope_id: Option<u32>,
+  timer: Timer,
+  scheduler: Option<Arc<dyn MaintenanceScheduler>>,
+  heartbeat: TaskHeartbeat,
+  rng: StdRng,
 }
 
 impl<T, D> Reconnector<T, D>
@@ -645,15 +1150,22 @@ where
   D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
   T: Transport,
 {
-  fn spawn(self) -> <<T::Runtime as RuntimeLite>::Spawner as AsyncSpawner>::JoinHandle<()> {
-    let mut rng = rand::rngs::StdRng::from_rng(rand::thread_rng()).unwrap();
-
-    <T::Runtime as RuntimeLite>::spawn(async move {
-      let tick = <T::Runtime as RuntimeLite>::interval(self.reconnect_interval);
-      futures::pin_mut!(tick);
+  fn spawn(mut self) -> <<T::Runtime as RuntimeLite>::Spawner as AsyncSpawner>::JoinHandle<()> {
+    let span = self.heartbeat.span();
+    let task = async move {
       loop {
+        self.heartbeat.beat();
+        // Reload the interval on every round, it may be changed at runtime
+        let reconnect_interval = self.reloadable.load().reconnect_interval;
+        let state = MaintenanceState::new(&*self.members.read().await, reconnect_interval);
+        let round = next_round::<T::Runtime>(
+          self.scheduler.as_deref(),
+          &self.timer,
+          MaintenanceTask::Reconnect,
+          &state,
+        );
         futures::select! {
-          _ = tick.next().fuse() => {
+          _ = round.fuse() => {
             let mu = self.members.read().await;
             let num_failed = mu.failed_members.len();
             // Nothing to do if there are no failed members
@@ -669,24 +1181,163 @@ where
 
             let num_alive = (mu.states.len() - num_failed - mu.left_members.len()).max(1);
             let prob = num_failed as f32 / num_alive as f32;
-            let r: f32 = rng.gen();
+            let r: f32 = self.rng.gen();
             if r > prob {
               tracing::debug!("ruserf: forgoing reconnect for random throttling");
               continue;
             }
 
             // Select a random member to try and join
-            let idx: usize = rng.gen_range(0..num_failed);
+            let idx: usize = self.rng.gen_range(0..num_failed);
             let member = &mu.failed_members[idx];
 
-            let (id, address) = member.member.node().cheap_clone().into_components();
+            let member = member.member.cheap_clone();
             drop(mu); // release read lock
-            tracing::info!("ruserf: attempting to reconnect to {}", id);
-            // Attempt to join at the memberlist level
-            if let Err(e) = self.memberlist.join(Node::new(id.cheap_clone(), MaybeResolvedAddress::resolved(address))).await {
-              tracing::warn!("ruserf: failed to reconnect {}: {}", id, e);
+
+            self.reconnect(member).await;
+          }
+          _ = self.shutdown_rx.recv().fuse() => {
+            break;
+          }
+        }
+      }
+
+      tracing::debug!("ruserf: reconnector exits");
+    };
+    <T::Runtime as RuntimeLite>::spawn(task.instrument(span))
+  }
+
+  /// Attempts to join a failed member at the address of its node, then at
+  /// each of the additional addresses it advertised, until one of them works.
+  async fn reconnect(
+    &self,
+    member: Member<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+  ) {
+    let (id, mut address) = member.node().cheap_clone().into_components();
+    if let Some(d) = self.memberlist.delegate().and_then(|d| d.delegate()) {
+      if let Some(addr) = d.reconnect_address(&member).await {
+        tracing::debug!(
+          "ruserf: reconnecting to {} at overridden address {}",
+          id,
+          addr
+        );
+        address = addr;
+      }
+    }
+
+    tracing::info!("ruserf: attempting to reconnect to {}", id);
+    let candidates = core::iter::once(address).chain(member.addresses().iter().cloned());
+    for mut address in candidates {
+      // A link-local address is only reachable through the zone it was learned on
+      if let Some(scoped) = self
+        .link_local_scope_id
+        .and_then(|scope_id| scope::with_link_local_scope_id::<D>(&address, scope_id))
+      {
+        address = scoped;
+      }
+
+      // Attempt to join at the memberlist level
+      match self
+        .memberlist
+        .join(Node::new(
+          id.cheap_clone(),
+          MaybeResolvedAddress::resolved(address.cheap_clone()),
+        ))
+        .await
+      {
+        Ok(_) => {
+          tracing::info!("ruserf: successfully reconnected to {} at {}", id, address);
+          return;
+        }
+        Err(e) => tracing::warn!("ruserf: failed to reconnect {} at {}: {}", id, address, e),
+      }
+    }
+  }
+}
+
+/// A query validated and encoded by [`Serf::prepare_query`], not sent yet.
+pub(crate) struct PreparedQuery<T>
+where
+  T: Transport,
+{
+  q: QueryMessage<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+  /// The encoded query, `None` if the outbound delegate dropped it
+  raw: Option<Bytes>,
+  ty: Option<InternalQueryEvent<T::Id>>,
+  timeout: Duration,
+  resp: QueryResponse<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+}
+
+impl<T: Transport> PreparedQuery<T> {
+  #[inline]
+  pub(crate) fn resp(
+    &self,
+  ) -> &QueryResponse<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress> {
+    &self.resp
+  }
+}
+
+/// Periodically checks the membership for a likely network partition, see
+/// [`Options::partition_detection`].
+struct PartitionChecker<T, D>
+where
+  T: Transport,
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+{
+  detector: Arc<parking_lot::Mutex<PartitionDetector>>,
+  interval: Duration,
+  members: Arc<RwLock<Members<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>>>,
+  event_tx: async_channel::Sender<CrateEvent<T, D>>,
+  shutdown_rx: async_channel::Receiver<()>,
+  timer: Timer,
+  #[cfg(feature = "metrics")]
+  metric_labels: Arc<memberlist_core::types::MetricLabels>,
+}
+
+impl<T, D> PartitionChecker<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  fn spawn(self) -> <<T::Runtime as RuntimeLite>::Spawner as AsyncSpawner>::JoinHandle<()> {
+    <T::Runtime as RuntimeLite>::spawn(async move {
+      loop {
+        futures::select! {
+          _ = self.timer.sleep::<T::Runtime>(self.interval).fuse() => {
+            let alive = self
+              .members
+              .read()
+              .await
+              .states
+              .values()
+              .filter(|m| m.member.status == MemberStatus::Alive)
+              .count();
+            let Some(event) = self.detector.lock().check(alive, self.timer.now()) else {
+              continue;
+            };
+
+            if event.partitioned() {
+              tracing::warn!(
+                peak = event.peak(),
+                alive = event.alive(),
+                failed = event.failed(),
+                "ruserf: likely network partition detected"
+              );
             } else {
-              tracing::info!("ruserf: successfully reconnected to {}", id);
+              tracing::info!(peak = event.peak(), alive = event.alive(), "ruserf: network partition healed");
+            }
+
+            #[cfg(feature = "metrics")]
+            {
+              metrics::gauge!("ruserf.partition", self.metric_labels.iter())
+                .set(if event.partitioned() { 1.0 } else { 0.0 });
+              if event.partitioned() {
+                metrics::counter!("ruserf.partition.detected", self.metric_labels.iter()).increment(1);
+              }
+            }
+
+            if let Err(e) = self.event_tx.send(event.into()).await {
+              tracing::error!(err=%e, "ruserf: failed to send partition event");
             }
           }
           _ = self.shutdown_rx.recv().fuse() => {
@@ -695,7 +1346,53 @@ where
         }
       }
 
-      tracing::debug!("ruserf: reconnector exits");
+      tracing::debug!("ruserf: partition checker exits");
+    })
+  }
+}
+
+/// Periodically audits the member map against the one of a random alive
+/// member, see [`Options::anti_entropy_audit`].
+struct AntiEntropyAuditor<T, D>
+where
+  T: Transport,
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+{
+  serf: Serf<T, D>,
+  audit: AntiEntropyAudit,
+  shutdown_rx: async_channel::Receiver<()>,
+  timer: Timer,
+}
+
+impl<T, D> AntiEntropyAuditor<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  fn spawn(self) -> <<T::Runtime as RuntimeLite>::Spawner as AsyncSpawner>::JoinHandle<()> {
+    <T::Runtime as RuntimeLite>::spawn(async move {
+      loop {
+        futures::select! {
+          _ = self.timer.sleep::<T::Runtime>(self.audit.interval()).fuse() => {
+            // The audit waits for the peer to answer, so it has to give way to the shutdown too
+            futures::select! {
+              res = self.serf.audit_member_map(self.audit.max_divergence()).fuse() => {
+                if let Err(e) = res {
+                  tracing::warn!(err=%e, "ruserf: failed to audit the member map");
+                }
+              }
+              _ = self.shutdown_rx.recv().fuse() => {
+                break;
+              }
+            }
+          }
+          _ = self.shutdown_rx.recv().fuse() => {
+            break;
+          }
+        }
+      }
+
+      tracing::debug!("ruserf: anti-entropy auditor exits");
     })
   }
 }
@@ -703,8 +1400,9 @@ where
 struct QueueChecker<I, A> {
   name: &'static str,
   queue: Arc<TransmitLimitedQueue<SerfBroadcast, NumMembers<I, A>>>,
-  members: Arc<RwLock<Members<I, A>>>,
+  num_members: NumMembers<I, A>,
   opts: QueueOptions,
+  reloadable: Arc<ArcSwap<ReloadableOptions>>,
   shutdown_rx: async_channel::Receiver<()>,
 }
 
@@ -725,7 +1423,7 @@ where
             {
               metrics::gauge!(self.name, self.opts.metric_labels.iter()).set(numq as f64);
             }
-            if numq >= self.opts.depth_warning {
+            if numq >= self.reloadable.load().queue_depth_warning {
               tracing::warn!("ruserf: queue {} depth: {}", self.name, numq);
             }
 
@@ -746,13 +1444,14 @@ where
   }
 
   async fn get_queue_max(&self) -> usize {
-    let mut max = self.opts.max_queue_depth;
-    if self.opts.min_queue_depth > 0 {
-      let num_members = self.members.read().await.states.len();
+    let opts = self.reloadable.load();
+    let mut max = opts.max_queue_depth;
+    if opts.min_queue_depth > 0 {
+      let num_members = self.num_members.num_nodes().await;
       max = num_members * 2;
 
-      if max < self.opts.min_queue_depth {
-        max = self.opts.min_queue_depth;
+      if max < opts.min_queue_depth {
+        max = opts.min_queue_depth;
       }
     }
     max
@@ -767,10 +1466,14 @@ where
 {
   /// Called when a user event broadcast is
   /// received. Returns if the message should be rebroadcast.
-  pub(crate) async fn handle_user_event(&self, msg: UserEventMessage) -> bool {
+  pub(crate) async fn handle_user_event(&self, mut msg: UserEventMessage) -> bool {
     // Witness a potentially newer time
     self.inner.event_clock.witness(msg.ltime);
 
+    // The events are buffered and delivered with the original payload, so
+    // the duplicates are detected no matter how the payload was sent.
+    let inflated = self.decompress_user_event(&mut msg);
+
     let mut el = self.inner.event_core.write().await;
 
     // Ignore if it is before our minimum event time
@@ -788,8 +1491,47 @@ where
         msg.ltime,
         cur_time
       );
+      self.inner.old_events.fetch_add(1, Ordering::Relaxed);
+      #[cfg(feature = "metrics")]
+      metrics::counter!(
+        "ruserf.events.too_old",
+        self.inner.opts.memberlist_options.metric_labels().iter()
+      )
+      .increment(1);
+      return false;
+    }
+
+    // The events which cannot be inflated here are still rebroadcast once,
+    // the members configured with the same compressor deliver them
+    if !inflated {
+      if el
+        .undecodable
+        .get(&msg.ltime)
+        .is_some_and(|digests| digests.iter().any(|d| d.matches(&msg.name, &msg.payload)))
+      {
+        return false;
+      }
+      el.undecodable
+        .entry(msg.ltime)
+        .or_default()
+        .push(EventDigest::new(&msg.name, &msg.payload));
+      if cur_time > bltime {
+        trim_recent_events(&mut el.undecodable, cur_time, bltime.into());
+      }
+      return true;
+    }
+
+    // Check if we've seen this before the restart
+    if el
+      .restored
+      .get(&msg.ltime)
+      .is_some_and(|digests| digests.iter().any(|d| d.matches(&msg.name, &msg.payload)))
+    {
       return false;
     }
+    if cur_time > bltime && !el.restored.is_empty() {
+      trim_recent_events(&mut el.restored, cur_time, bltime.into());
+    }
 
     // Check if we've already seen this
     let idx = u64::from(msg.ltime % bltime) as usize;
@@ -812,6 +1554,75 @@ where
       });
     }
 
+    // The fragments are deduplicated one by one above, the event they carry
+    // is handled once the last one arrived
+    if let Some(name) = msg.name.strip_prefix(FRAGMENT_EVENT_PREFIX) {
+      let mut fragments = self.inner.fragments.lock();
+      let Some(assembled) = fragments.insert(msg.ltime, name, &msg.payload, &msg.distribution)
+      else {
+        return true;
+      };
+      drop(fragments);
+      msg = assembled;
+      if !self.decompress_user_event(&mut msg) {
+        return true;
+      }
+    }
+
+    // Key/value writes are applied to the store, not delivered
+    if let Some(key) = msg.name.strip_prefix(KV_EVENT_PREFIX) {
+      if self.inner.kv.lock().apply(key, msg.payload, msg.ltime) {
+        tracing::trace!("ruserf: applied key/value write {} at {}", key, msg.ltime);
+      }
+      return true;
+    }
+
+    if let Some(name) = msg.name.strip_prefix(LOCK_EVENT_PREFIX) {
+      self.handle_lease_event(name, &msg.payload);
+      return true;
+    }
+
+    // The events of the other segments are still rebroadcast
+    if let Some((s, name)) = segment::split_event_name(&msg.name) {
+      if self.inner.opts.segment.as_deref() != Some(s) {
+        tracing::trace!(
+          "ruserf: user event {} is not for the segment of this node",
+          name
+        );
+        return true;
+      }
+      let name = SmolStr::new(name);
+      msg.name = name;
+    }
+
+    // Routed events are still rebroadcast by the members they are not meant for
+    if !self.matches_distribution(&msg.distribution) {
+      tracing::trace!("ruserf: user event {} is not routed to this node", msg.name);
+      return true;
+    }
+
+    // Malformed payloads are surfaced as such rather than delivered, they
+    // are still rebroadcast so the other members decide on their own
+    if let Err(reason) = self
+      .inner
+      .user_event_validators
+      .validate(&msg.name, &msg.payload)
+    {
+      tracing::debug!(reason=%reason, "ruserf: user event {} failed validation", msg.name);
+      #[cfg(feature = "metrics")]
+      metrics::counter!(
+        "ruserf.events.invalid",
+        self.inner.opts.memberlist_options.metric_labels().iter()
+      )
+      .increment(1);
+
+      let event = InvalidUserEvent { event: msg, reason };
+      if let Err(e) = self.inner.event_tx.send(event.into()).await {
+        tracing::error!("ruserf: failed to send invalid user event: {}", e);
+      }
+      return true;
+    }
+
     #[cfg(feature = "metrics")]
     {
       metrics::counter!(
@@ -836,6 +1647,64 @@ where
     true
   }
 
+  /// Returns the compressor of the user event and query payloads, `None` until
+  /// every member speaks [`ProtocolVersion::V2`] or newer.
+  pub(crate) async fn compressor(&self) -> Option<&dyn Compressor> {
+    let compressor = self.inner.opts.compressor.as_deref()?;
+    self
+      .inner
+      .members
+      .read()
+      .await
+      .all_speak(ProtocolVersion::V2)
+      .then_some(compressor)
+  }
+
+  /// Inflates the payload of a compressed user event, returns `false` if it cannot be inflated.
+  fn decompress_user_event(&self, msg: &mut UserEventMessage) -> bool {
+    if !msg.compressed {
+      return true;
+    }
+
+    match decompress_payload(
+      self.inner.opts.compressor.as_deref(),
+      &msg.payload,
+      self.inner.opts.max_decompressed_size,
+    ) {
+      Ok(payload) => {
+        msg.payload = payload;
+        msg.compressed = false;
+        true
+      }
+      Err(e) => {
+        tracing::warn!(err=%e, "ruserf: failed to decompress user event {}", msg.name);
+        false
+      }
+    }
+  }
+
+  /// Returns `true` if the local tags match every `(tag, regex)` pair of the
+  /// distribution of a user event.
+  fn matches_distribution(&self, distribution: &[(SmolStr, SmolStr)]) -> bool {
+    if distribution.is_empty() {
+      return true;
+    }
+
+    let tags = self.inner.opts.tags.load();
+    distribution.iter().all(|(tag, fexpr)| {
+      let Some(expr) = tags.get(tag) else {
+        return false;
+      };
+      match regex::Regex::new(fexpr) {
+        Ok(re) => re.is_match(expr),
+        Err(err) => {
+          tracing::warn!(err=%err, "ruserf: failed to compile distribution regex ({})", fexpr);
+          false
+        }
+      }
+    })
+  }
+
   pub(crate) fn query_event(
     &self,
     q: QueryMessage<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
@@ -845,11 +1714,11 @@ where
       name: q.name,
       payload: q.payload,
       ctx: Arc::new(QueryContext {
-        query_timeout: q.timeout,
-        span: Mutex::new(Some(Epoch::now())),
+        span: Mutex::new(Some(self.inner.timer.deadline(q.timeout))),
         this: self.clone(),
       }),
       id: q.id,
+      respond_to: q.from.cheap_clone(),
       from: q.from,
       relay_factor: q.relay_factor,
     }
@@ -874,6 +1743,21 @@ where
     ty: Option<InternalQueryEvent<T::Id>>,
   ) -> Result<QueryResponse<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>, Error<T, D>>
   {
+    let query = self.prepare_query(name, payload, params, ty).await?;
+    let resp = query.resp.clone();
+    self.dispatch_queries(vec![query]).await?;
+    Ok(resp)
+  }
+
+  /// Validates and encodes a query, and sets up the tracking of its acks
+  /// and responses, without sending it yet.
+  pub(crate) async fn prepare_query(
+    &self,
+    name: SmolStr,
+    payload: Bytes,
+    params: Option<QueryParam<T::Id>>,
+    ty: Option<InternalQueryEvent<T::Id>>,
+  ) -> Result<PreparedQuery<T>, Error<T, D>> {
     // Provide default parameters if none given.
     let params = match params {
       Some(params) if params.timeout != Duration::ZERO => params,
@@ -884,6 +1768,16 @@ where
       None => self.default_query_param().await,
     };
 
+    if self.is_paused() {
+      return Err(Error::paused());
+    }
+
+    let mut params = params;
+    if let Some(n) = params.nearest {
+      let nearest = self.nearest_members(n).await?;
+      params.filters.push(Filter::Id(nearest));
+    }
+
     // Get the local node
     let local = self.inner.memberlist.advertise_node();
 
@@ -893,16 +1787,45 @@ where
       .map_err(Error::transform_delegate)?;
 
     // Setup the flags
-    let flags = if params.request_ack {
+    let mut flags = if params.ack_only {
+      QueryFlag::ACK | QueryFlag::ACK_ONLY
+    } else if params.request_ack {
       QueryFlag::ACK
     } else {
       QueryFlag::empty()
     };
 
-    // Create the message
+    let mut payload = payload;
+    if let Some(compressor) = self.compressor().await {
+      if let Some(c) = compress_payload(compressor, self.inner.opts.compression_threshold, &payload)
+        .map_err(Error::compression)?
+      {
+        payload = c;
+        flags |= QueryFlag::COMPRESSED;
+      }
+    }
+
+    // Name the member collecting the responses ahead of the payload, the local node needs no header.
+    // The members of older protocol versions would deliver the header as part of the payload
+    if let Some(id) = params.respond_via.as_ref().filter(|id| *id != local.id()) {
+      if !self
+        .inner
+        .members
+        .read()
+        .await
+        .all_speak(ProtocolVersion::V2)
+      {
+        return Err(Error::protocol_unsupported(ProtocolVersion::V2));
+      }
+      payload = encode_respond_via::<D>(id, &payload).map_err(Error::transform_delegate)?;
+      flags |= QueryFlag::RESPOND_VIA;
+    }
+
+    // Create the message, each query takes its own Lamport time, as the
+    // responses are tracked by it
     let q = QueryMessage {
-      ltime: self.inner.query_clock.time(),
-      id: rand::random(),
+      ltime: self.inner.query_clock.increment() - LamportTime::new(1),
+      id: self.inner.rng.lock().gen(),
       from: local.cheap_clone(),
       filters,
       flags,
@@ -916,40 +1839,92 @@ where
     let len = <D as TransformDelegate>::message_encoded_len(&q);
 
     // Check the size
-    if len > self.inner.opts.query_size_limit {
+    if len > self.inner.reloadable.load().query_size_limit {
       return Err(Error::query_too_large(len));
     }
 
-    let mut raw = BytesMut::with_capacity(len + 1); // + 1 for message type byte
-    raw.put_u8(MessageType::Query as u8);
-    raw.resize(len + 1, 0);
-    let actual_encoded_len = <D as TransformDelegate>::encode_message(&q, &mut raw[1..])
-      .map_err(Error::transform_delegate)?;
-    debug_assert_eq!(
-      actual_encoded_len, len,
-      "expected encoded len {} mismatch the actual encoded len {}",
-      len, actual_encoded_len
-    );
+    // The peers see the address of the local node as rewritten by the delegate
+    let wire = QueryMessage {
+      from: self.outbound_node(&local),
+      ..q.clone()
+    };
+    let raw = self.encode_outbound(wire.as_message_ref())?;
+    // The delegate may have replaced the query with a larger one
+    if let Some(raw) = &raw {
+      if raw.len() - 1 > self.inner.reloadable.load().query_size_limit {
+        return Err(Error::query_too_large(raw.len() - 1));
+      }
+    }
 
     // Register QueryResponse to track acks and responses
-    let resp = QueryResponse::from_query(&q, self.inner.memberlist.num_online_members().await);
-    self
-      .register_query_response(params.timeout, resp.clone())
-      .await;
+    let resp = QueryResponse::from_query(
+      &self.inner.timer,
+      &q,
+      match params.size_hint {
+        Some(n) => n.max(1),
+        None => self.inner.memberlist.num_online_members().await,
+      },
+      self.expected_responders(&params.filters).await,
+      params.deadline_extension(),
+    );
 
-    // Process query locally
-    self.handle_query(q, ty).await;
+    Ok(PreparedQuery {
+      q,
+      raw,
+      ty,
+      timeout: params.timeout,
+      resp,
+    })
+  }
 
-    // Start broadcasting the event
-    self
-      .inner
-      .query_broadcasts
-      .queue_broadcast(SerfBroadcast {
-        msg: raw.freeze(),
-        notify_tx: None,
-      })
-      .await;
-    Ok(resp)
+  /// Registers the prepared queries and processes them locally, then queues
+  /// their broadcasts together so they share the gossip rounds.
+  ///
+  /// None is sent if one of them repeats a query just sent, the others are
+  /// only recorded by the suppression once all of them are accepted.
+  pub(crate) async fn dispatch_queries(
+    &self,
+    queries: Vec<PreparedQuery<T>>,
+  ) -> Result<(), Error<T, D>> {
+    // Refuse to resend a query just sent, the local queries are not suppressed once processed
+    if let Some(suppressor) = &self.inner.query_suppressor {
+      let local = self.local_id();
+      let now = Epoch::now();
+      let mut suppressor = suppressor.lock();
+      for (idx, query) in queries.iter().enumerate() {
+        let q = &query.q;
+        let repeated = queries[..idx]
+          .iter()
+          .any(|prev| prev.q.name == q.name && prev.q.payload == q.payload);
+        if repeated || suppressor.suppressed(local, &q.name, &q.payload, now) {
+          return Err(Error::query_suppressed(q.name.clone()));
+        }
+      }
+      for query in queries.iter() {
+        suppressor.record(local, &query.q.name, &query.q.payload, now);
+      }
+    }
+
+    let mut raws = Vec::new();
+    for query in queries {
+      self
+        .register_query_response(query.timeout, query.resp)
+        .await;
+
+      // Process query locally
+      self.handle_query(query.q, query.ty).await;
+      raws.extend(query.raw);
+    }
+
+    // Start broadcasting the events
+    for raw in raws {
+      self
+        .inner
+        .query_broadcasts
+        .queue_broadcast(SerfBroadcast::new(raw, None))
+        .await;
+    }
+    Ok(())
   }
 
   /// Used to setup the listeners for the query,
@@ -964,28 +1939,64 @@ where
     // Map the LTime to the QueryResponse. This is necessarily 1-to-1,
     // since we increment the time for each new query.
     let ltime = resp.ltime;
-    resps.responses.insert(ltime, resp);
+    resps.responses.insert(ltime, resp.clone());
+
+    // Take the responses which arrived before the query was registered
+    let mut early = Vec::new();
+    resps.early.retain(|msg| {
+      if msg.ltime == ltime && msg.id == resp.id {
+        early.push(msg.clone());
+        return false;
+      }
+      true
+    });
+    drop(resps);
+    for msg in early {
+      resp
+        .handle_query_response::<T, D>(
+          msg,
+          self.local_id(),
+          #[cfg(feature = "metrics")]
+          self.inner.opts.memberlist_options.metric_labels(),
+        )
+        .await;
+    }
 
     // Setup a timer to close the response and deregister after the timeout
-    <T::Runtime as RuntimeLite>::spawn_after(timeout, async move {
+    let timer = self.inner.timer.clone();
+    <T::Runtime as RuntimeLite>::spawn_detach(async move {
+      timer.sleep::<T::Runtime>(timeout).await;
+      // Leave the query open while the responses push out its deadline
+      loop {
+        let extension = resp.take_extension().await;
+        if extension.is_zero() {
+          break;
+        }
+        timer.sleep::<T::Runtime>(extension).await;
+      }
+
       let mut resps = tresps.write().await;
       if let Some(resp) = resps.responses.remove(&ltime) {
         resp.close().await;
       }
-    })
-    .detach();
+    });
   }
 
   /// Called when a query broadcast is
   /// received. Returns if the message should be rebroadcast.
   pub(crate) async fn handle_query(
     &self,
-    q: QueryMessage<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+    mut q: QueryMessage<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
     ty: Option<InternalQueryEvent<T::Id>>,
   ) -> bool {
     // Witness a potentially newer time
     self.inner.query_clock.witness(q.ltime);
 
+    // The sender may be about to join, favour its buffered intent
+    if q.from.id() != self.local_id() {
+      self.reference_intent(q.from.id()).await;
+    }
+
     let mut query = self.inner.query_core.write().await;
 
     // Ignore if it is before our minimum query time
@@ -996,13 +2007,20 @@ where
     // Check if this message is too old
     let cur_time = self.inner.query_clock.time();
     let q_time = LamportTime::new(query.buffer.len() as u64);
-    if cur_time > q_time && q_time < cur_time - q_time {
+    if cur_time > q_time && q.ltime < cur_time - q_time {
       tracing::warn!(
         "ruserf: received old query {} from time {} (current: {})",
         q.name,
         q.ltime,
         cur_time
       );
+      self.inner.old_queries.fetch_add(1, Ordering::Relaxed);
+      #[cfg(feature = "metrics")]
+      metrics::counter!(
+        "ruserf.queries.too_old",
+        self.inner.opts.memberlist_options.metric_labels().iter()
+      )
+      .increment(1);
       return false;
     }
 
@@ -1025,6 +2043,48 @@ where
         query_ids: MediumVec::from(q.id),
       });
     }
+    drop(query);
+
+    // Drop the queries resubmitted by a buggy client, the local ones were checked when sent
+    if q.from.id() != self.local_id() {
+      if let Some(suppressor) = &self.inner.query_suppressor {
+        if !suppressor
+          .lock()
+          .allow(q.from.id(), &q.name, &q.payload, Epoch::now())
+        {
+          tracing::debug!(
+            "ruserf: dropped query {} from {}, identical to a recent query",
+            q.name,
+            q.from.id()
+          );
+          #[cfg(feature = "metrics")]
+          metrics::counter!(
+            "ruserf.queries.suppressed",
+            self.inner.opts.memberlist_options.metric_labels().iter()
+          )
+          .increment(1);
+          return false;
+        }
+      }
+    }
+
+    // Drop the excess queries of a flooding node, the local queries are never limited
+    if q.from.id() != self.local_id()
+      && !self.inner.query_limiter.lock().allow(q.from.id(), &q.name)
+    {
+      tracing::debug!(
+        "ruserf: dropped query {} from {}, rate limit exceeded",
+        q.name,
+        q.from.id()
+      );
+      #[cfg(feature = "metrics")]
+      metrics::counter!(
+        "ruserf.queries.rate_limited",
+        self.inner.opts.memberlist_options.metric_labels().iter()
+      )
+      .increment(1);
+      return false;
+    }
 
     // update some metrics
     #[cfg(feature = "metrics")]
@@ -1079,13 +2139,15 @@ where
             "expected encoded len {} mismatch the actual encoded len {}",
             expected_encoded_len, len
           );
-          if let Err(e) = self
+          let raw = raw.freeze();
+          match self
             .inner
             .memberlist
-            .send(q.from().address(), raw.freeze())
+            .send(q.from().address(), raw.clone())
             .await
           {
-            tracing::error!(err=%e, "ruserf: failed to send ack");
+            Ok(_) => self.inner.bandwidth.sent(&raw),
+            Err(e) => tracing::error!(err=%e, "ruserf: failed to send ack"),
           }
 
           if let Err(e) = self
@@ -1101,7 +2163,64 @@ where
       }
     }
 
-    let ev = self.query_event(q);
+    // The ack was all the requester asked for
+    if q.ack_only() {
+      return rebroadcast;
+    }
+
+    // Send the responses to the member collecting them, or the requester if it is not alive
+    let mut respond_to = None;
+    if q.respond_via() {
+      match decode_respond_via::<D>(&q.payload) {
+        Ok((id, payload)) => {
+          q.payload = payload;
+          q.flags.remove(QueryFlag::RESPOND_VIA);
+          respond_to = self
+            .inner
+            .members
+            .read()
+            .await
+            .states
+            .get(&id)
+            .filter(|m| m.member.status == MemberStatus::Alive)
+            .map(|m| m.member.node.cheap_clone());
+          if respond_to.is_none() {
+            tracing::debug!(
+              "ruserf: {} collecting the responses to query {} is not alive, responding to {}",
+              id,
+              q.name,
+              q.from.id()
+            );
+          }
+        }
+        Err(e) => {
+          tracing::warn!(err=%e, "ruserf: failed to decode the collector of query {}", q.name);
+          return rebroadcast;
+        }
+      }
+    }
+
+    if q.compressed() {
+      match decompress_payload(
+        self.inner.opts.compressor.as_deref(),
+        &q.payload,
+        self.inner.opts.max_decompressed_size,
+      ) {
+        Ok(payload) => {
+          q.payload = payload;
+          q.flags.remove(QueryFlag::COMPRESSED);
+        }
+        Err(e) => {
+          tracing::warn!(err=%e, "ruserf: failed to decompress query {}", q.name);
+          return rebroadcast;
+        }
+      }
+    }
+
+    let mut ev = self.query_event(q);
+    if let Some(respond_to) = respond_to {
+      ev.respond_to = respond_to;
+    }
 
     if let Err(e) = self
       .inner
@@ -1133,32 +2252,121 @@ where
       .responses
       .get(&resp.ltime)
       .cloned();
-    if let Some(query) = qc {
-      // Verify the ID matches
-      if query.id != resp.id {
+    let query = match qc {
+      Some(query) => query,
+      // The member collecting the responses to the query of another node may
+      // not have registered it yet, keep the response until it does
+      None if !resp.ack() => {
+        let mut qc = self.inner.query_core.write().await;
+        match qc.responses.get(&resp.ltime).cloned() {
+          Some(query) => query,
+          None => {
+            tracing::debug!(
+              "ruserf: kept reply for non-running query (LTime: {}, ID: {}) From: {}",
+              resp.ltime,
+              resp.id,
+              resp.from
+            );
+            if qc.early.len() >= MAX_EARLY_RESPONSES {
+              qc.early.pop_front();
+            }
+            qc.early.push_back(resp);
+            return;
+          }
+        }
+      }
+      None => {
         tracing::warn!(
-          "ruserf: query reply ID mismatch (local: {}, response: {})",
-          query.id,
-          resp.id
+          "ruserf: reply for non-running query (LTime: {}, ID: {}) From: {}",
+          resp.ltime,
+          resp.id,
+          resp.from
         );
         return;
       }
+    };
 
-      query
-        .handle_query_response::<T, D>(
-          resp,
-          self.local_id(),
-          #[cfg(feature = "metrics")]
-          self.inner.opts.memberlist_options.metric_labels(),
-        )
-        .await;
-    } else {
+    // Verify the ID matches
+    if query.id != resp.id {
       tracing::warn!(
-        "ruserf: reply for non-running query (LTime: {}, ID: {}) From: {}",
-        resp.ltime,
-        resp.id,
-        resp.from
+        "ruserf: query reply ID mismatch (local: {}, response: {})",
+        query.id,
+        resp.id
       );
+      return;
+    }
+
+    query
+      .handle_query_response::<T, D>(
+        resp,
+        self.local_id(),
+        #[cfg(feature = "metrics")]
+        self.inner.opts.memberlist_options.metric_labels(),
+      )
+      .await;
+  }
+
+  /// Records the application health reported by a member in its ping ack,
+  /// see [`HealthDelegate`](crate::delegate::HealthDelegate).
+  pub(crate) async fn record_app_health(&self, id: &T::Id, health: Option<u8>) {
+    // Most acks report the same health, only take the write lock on a change
+    let changed = self
+      .inner
+      .members
+      .read()
+      .await
+      .states
+      .get(id)
+      .is_some_and(|m| m.member.app_health != health);
+    if changed {
+      if let Some(m) = self.inner.members.write().await.states.get_mut(id) {
+        m.member.app_health = health;
+      }
+    }
+  }
+
+  /// Records the members reported by the push/pull of a joined node, its
+  /// join waits until they are all known, see [`Options::join_sync_timeout`].
+  ///
+  /// The joined node reports itself, which tells the join the push/pull
+  /// belongs to, so the push/pull of a node joining the local one is ignored.
+  pub(crate) async fn record_join_sync(
+    &self,
+    status_ltimes: &IndexMap<T::Id, LamportTime>,
+    left_members: &IndexSet<T::Id>,
+  ) {
+    if self.inner.join_sync.lock().is_empty() {
+      return;
+    }
+
+    let members = self.inner.members.read().await;
+    let mut pending = self.inner.join_sync.lock();
+    for (id, sync) in pending.iter_mut() {
+      if sync.missing.is_some() || !status_ltimes.contains_key(id) {
+        continue;
+      }
+
+      let missing = status_ltimes
+        .keys()
+        .filter(|node| !left_members.contains(*node) && !members.states.contains_key(*node))
+        .cloned()
+        .collect::<HashSet<_>>();
+      if missing.is_empty() {
+        sync.synced.close();
+      }
+      sync.missing = Some(missing);
+    }
+  }
+
+  /// Wakes up the joins waiting for the member `id`, once it is known.
+  fn join_synced(&self, id: &T::Id) {
+    let mut pending = self.inner.join_sync.lock();
+    for sync in pending.values_mut() {
+      if let Some(missing) = &mut sync.missing {
+        if missing.remove(id) && missing.is_empty() {
+          sync.synced.close();
+        }
+      }
     }
   }
 
@@ -1168,23 +2376,27 @@ where
     &self,
     n: Arc<NodeState<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>>,
   ) {
-    let mut members = self.inner.members.write().await;
-
     #[cfg(any(test, feature = "test"))]
     {
-      if let Some(ref dropper) = self.inner.memberlist.delegate().unwrap().message_dropper {
+      if let Some(ref dropper) = self.inner.opts.message_dropper {
         if dropper.should_drop(MessageType::Join) {
           return;
         }
+
+        if let Some(delay) = dropper.delay(MessageType::Join) {
+          <T::Runtime as RuntimeLite>::sleep(delay).await;
+        }
       }
     }
 
+    let mut members = self.inner.members.write().await;
+
     let node = n.node();
-    let tags = if !n.meta().is_empty() {
+    let (mut tags, app_meta) = if !n.meta().is_empty() {
       match <D as TransformDelegate>::decode_tags(n.meta()) {
         Ok((readed, tags)) => {
           tracing::trace!(read = %readed, tags=?tags, "ruserf: decode tags successfully");
-          tags
+          (tags, app_meta::decode(&n.meta()[readed..]))
         }
         Err(e) => {
           tracing::error!(err=%e, "ruserf: failed to decode tags");
@@ -1195,21 +2407,28 @@ where
       Default::default()
     };
 
-    let (old_status, fut) = if let Some(member) = members.states.get_mut(node.id()) {
+    let versions = Versions::split(&mut tags);
+    let secure = secure::split(&mut tags);
+    conflict::split(&mut tags);
+    cluster::split(&mut tags);
+    let addresses = addresses::split::<D>(&mut tags);
+
+    let old_tags = members
+      .states
+      .get(node.id())
+      .map(|ms| ms.member.tags.clone());
+    let (old_status, fut, flapped) = if let Some(member) = members.states.get_mut(node.id()) {
       let old_status = member.member.status;
+      let dead_time = member.leave_time.map(|t| self.inner.timer.now() - t);
+      let flapped = old_status == MemberStatus::Failed
+        && dead_time.is_some_and(|t| t < self.inner.opts.flap_timeout);
       #[cfg(feature = "metrics")]
-      let dead_time = member.leave_time.map(|t| t.elapsed());
-      #[cfg(feature = "metrics")]
-      if old_status == MemberStatus::Failed {
-        if let Some(dead_time) = dead_time {
-          if dead_time < self.inner.opts.flap_timeout {
-            metrics::counter!(
-              "ruserf.member.flap",
-              self.inner.opts.memberlist_options.metric_labels().iter()
-            )
-            .increment(1);
-          }
-        }
+      if flapped {
+        metrics::counter!(
+          "ruserf.member.flap",
+          self.inner.opts.memberlist_options.metric_labels().iter()
+        )
+        .increment(1);
       }
 
       *member = MemberState {
@@ -1217,10 +2436,14 @@ where
           node: node.cheap_clone(),
           tags: Arc::new(tags),
           status: MemberStatus::Alive,
-          protocol_version: member.member.protocol_version,
-          delegate_version: member.member.delegate_version,
+          protocol_version: versions.protocol_version(),
+          delegate_version: versions.delegate_version(),
           memberlist_delegate_version: member.member.memberlist_delegate_version,
           memberlist_protocol_version: member.member.memberlist_protocol_version,
+          secure,
+          app_meta,
+          addresses,
+          app_health: None,
         },
         status_time: member.status_time,
         leave_time: None,
@@ -1235,6 +2458,7 @@ where
           }
           .into(),
         ),
+        flapped,
       )
     } else {
       // Check if we have a join or leave intent. The intent buffer
@@ -1256,16 +2480,21 @@ where
           node: node.cheap_clone(),
           tags: Arc::new(tags),
           status,
-          protocol_version: self.inner.opts.protocol_version,
-          delegate_version: self.inner.opts.delegate_version,
+          protocol_version: versions.protocol_version(),
+          delegate_version: versions.delegate_version(),
           memberlist_delegate_version: self.inner.opts.memberlist_options.delegate_version(),
           memberlist_protocol_version: self.inner.opts.memberlist_options.protocol_version(),
+          secure,
+          app_meta,
+          addresses,
+          app_health: None,
         },
         status_time: status_ltime,
         leave_time: None,
       };
       let member = ms.member.clone();
       members.states.insert(node.id().cheap_clone(), ms);
+      self.join_synced(node.id());
       (
         MemberStatus::None,
         self.inner.event_tx.send(
@@ -1275,8 +2504,14 @@ where
           }
           .into(),
         ),
+        false,
       )
     };
+    members.reindex_tags(node.id(), old_tags.as_deref());
+
+    if old_status != MemberStatus::Alive {
+      members.record_status_change(node.id().cheap_clone(), self.inner.timer.now());
+    }
 
     if matches!(old_status, MemberStatus::Failed | MemberStatus::Left) {
       remove_old_member(&mut members.failed_members, node.id());
@@ -1295,6 +2530,121 @@ where
     if let Err(e) = fut.await {
       tracing::error!(err=%e, "ruserf: failed to send member event");
     }
+
+    let threshold = self.inner.opts.flap_threshold;
+    if flapped && threshold > 0 {
+      let flaps = members.record_flap(
+        node.id().cheap_clone(),
+        self.inner.timer.now(),
+        self.inner.opts.flap_window,
+      );
+      if flaps > threshold {
+        tracing::warn!(
+          "ruserf: member {} flapped {} times within {:?}",
+          node,
+          flaps,
+          self.inner.opts.flap_window
+        );
+        let member = members.states[node.id()].member.clone();
+        if let Err(e) = self
+          .inner
+          .event_tx
+          .send(
+            MemberEvent {
+              ty: MemberEventType::Flap,
+              members: Arc::new(TinyVec::from(member)),
+            }
+            .into(),
+          )
+          .await
+        {
+          tracing::error!(err=%e, "ruserf: failed to send member event");
+        }
+      }
+    }
+
+    self.check_cluster_formed(&members).await;
+    self.check_leader(&members).await;
+  }
+
+  /// Emits the cluster formed event once the number of alive members
+  /// reaches the bootstrap expectation.
+  async fn check_cluster_formed(
+    &self,
+    members: &Members<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+  ) {
+    let Some(expect) = self.inner.opts.bootstrap_expect else {
+      return;
+    };
+
+    if self.inner.cluster_formed.load(Ordering::Acquire) {
+      return;
+    }
+
+    let alive = members
+      .states
+      .values()
+      .filter(|m| m.member.status == MemberStatus::Alive)
+      .count();
+    if alive < expect || self.inner.cluster_formed.swap(true, Ordering::AcqRel) {
+      return;
+    }
+
+    tracing::info!(expect = expect, alive = alive, "ruserf: cluster formed");
+    if let Err(e) = self
+      .inner
+      .event_tx
+      .send(ClusterFormedEvent { expect, alive }.into())
+      .await
+    {
+      tracing::error!(err=%e, "ruserf: failed to send cluster formed event");
+    }
+  }
+
+  /// Re-runs the leader election and emits an event if the leader changed.
+  async fn check_leader(
+    &self,
+    members: &Members<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+  ) {
+    let Some(opts) = self.inner.opts.election.as_ref() else {
+      return;
+    };
+
+    let current = elect(opts, members.states.values().map(|ms| &ms.member)).cloned();
+    let previous = {
+      let mut leader = self.inner.leader.lock();
+      if leader.as_ref().map(|m| m.node().id()) == current.as_ref().map(|m| m.node().id()) {
+        // Keep the latest view of the leader, e.g. its tags
+        *leader = current;
+        return;
+      }
+      core::mem::replace(&mut *leader, current.clone())
+    };
+
+    match &current {
+      Some(leader) => {
+        tracing::info!(strategy=%opts.strategy(), "ruserf: new leader elected: {}", leader.node())
+      }
+      None => {
+        tracing::warn!(strategy=%opts.strategy(), "ruserf: no eligible member to be the leader")
+      }
+    }
+
+    #[cfg(feature = "metrics")]
+    metrics::counter!(
+      "ruserf.election.leader_changed",
+      self.inner.opts.memberlist_options.metric_labels().iter()
+    )
+    .increment(1);
+
+    if let Err(e) = self
+      .inner
+      .event_tx
+      .send(LeaderChangedEvent { previous, current }.into())
+      .await
+    {
+      tracing::error!(err=%e, "ruserf: failed to send leader changed event");
+    }
   }
 
   /// Called when a node broadcasts a
@@ -1319,18 +2669,19 @@ where
 
         if member.member.status == MemberStatus::Leaving {
           member.member.status = MemberStatus::Alive;
+          members.record_status_change(join_msg.id().cheap_clone(), self.inner.timer.now());
+          self.check_leader(&members).await;
         }
 
         true
       }
       None => {
         // Rebroadcast only if this was an update we hadn't seen before.
-        upsert_intent(
+        self.buffer_intent(
           &mut members.recent_intents,
           join_msg.id(),
           MessageType::Join,
           join_msg.ltime,
-          Epoch::now,
         )
       }
     }
@@ -1352,7 +2703,7 @@ where
         member_state.member.status = MemberStatus::Left;
 
         ms = MemberStatus::Left;
-        member_state.leave_time = Some(Epoch::now());
+        member_state.leave_time = Some(self.inner.timer.now());
         let member_state = member_state.clone();
         let member = member_state.member.clone();
         members.left_members.push(member_state);
@@ -1361,7 +2712,7 @@ where
       MemberStatus::Alive => {
         member_state.member.status = MemberStatus::Failed;
         ms = MemberStatus::Failed;
-        member_state.leave_time = Some(Epoch::now());
+        member_state.leave_time = Some(self.inner.timer.now());
         let member_state = member_state.clone();
         let member = member_state.member.clone();
         members.failed_members.push(member_state);
@@ -1372,6 +2723,10 @@ where
         return;
       }
     };
+    members.record_status_change(n.id().cheap_clone(), self.inner.timer.now());
+
+    // The leases held by the member are no longer renewed
+    self.inner.locks.lock().release_member(member.node().id());
 
     // Send an event along
     let ty = if ms != MemberStatus::Left {
@@ -1380,6 +2735,14 @@ where
       MemberEventType::Leave
     };
 
+    if let Some(detector) = self.inner.partition.as_ref() {
+      let mut detector = detector.lock();
+      match ty {
+        MemberEventType::Failed => detector.record_failure(self.inner.timer.now()),
+        _ => detector.record_leave(),
+      }
+    }
+
     // Update some metrics
     #[cfg(feature = "metrics")]
     metrics::counter!(
@@ -1404,6 +2767,49 @@ where
     {
       tracing::error!(err=%e, "ruserf: failed to send member event: {}", e);
     }
+
+    self.check_leader(&members).await;
+  }
+
+  /// Buffers the intent of an unknown node, see [`Options::max_recent_intents`].
+  /// Returns whether the intent is new and should be rebroadcast.
+  fn buffer_intent(
+    &self,
+    intents: &mut HashMap<T::Id, NodeIntent>,
+    id: &T::Id,
+    ty: MessageType,
+    ltime: LamportTime,
+  ) -> bool {
+    if evict_intent(intents, id, self.inner.opts.max_recent_intents) {
+      tracing::debug!("ruserf: recent intent buffer is full, evicted the oldest intent");
+      #[cfg(feature = "metrics")]
+      metrics::counter!(
+        "ruserf.intents.evicted",
+        self.inner.opts.memberlist_options.metric_labels().iter()
+      )
+      .increment(1);
+    }
+
+    upsert_intent(intents, id, ty, ltime, || self.inner.timer.now())
+  }
+
+  /// Marks the buffered intent of a node not known yet as referenced, so it
+  /// is kept over the other intents when the buffer is full.
+  pub(crate) async fn reference_intent(&self, id: &T::Id) {
+    if !self
+      .inner
+      .members
+      .read()
+      .await
+      .recent_intents
+      .contains_key(id)
+    {
+      return;
+    }
+
+    if let Some(intent) = self.inner.members.write().await.recent_intents.get_mut(id) {
+      intent.referenced = true;
+    }
   }
 
   pub(crate) async fn handle_node_leave_intent(&self, msg: &LeaveMessage<T::Id>) -> bool {
@@ -1415,12 +2821,11 @@ where
     let mut members = self.inner.members.write().await;
 
     if !members.states.contains_key(msg.id()) {
-      return upsert_intent(
+      return self.buffer_intent(
         &mut members.recent_intents,
         msg.id(),
         MessageType::Leave,
         msg.ltime,
-        Epoch::now,
       );
     }
 
@@ -1468,10 +2873,14 @@ where
       MemberStatus::None => false,
       MemberStatus::Alive => {
         member.member.status = MemberStatus::Leaving;
+        let owned = msg.prune.then(|| member.clone());
+        drop(members_mut);
+        members
+          .borrow_mut()
+          .record_status_change(msg.id().cheap_clone(), self.inner.timer.now());
+        self.check_leader(&members.borrow()).await;
 
-        if msg.prune {
-          let owned = member.clone();
-          drop(members_mut);
+        if let Some(owned) = owned {
           self.handle_prune(&owned, *members.borrow_mut()).await;
         }
         true
@@ -1490,6 +2899,7 @@ where
         drop(members_mut);
 
         let mut members_mut = members.borrow_mut();
+        members_mut.record_status_change(msg.id().cheap_clone(), self.inner.timer.now());
         // Remove from the failed list and add to the left list. We add
         // to the left list so that when we do a sync, other nodes will
         // remove it from their failed list.
@@ -1533,28 +2943,43 @@ where
     &self,
     n: Arc<NodeState<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>>,
   ) {
-    let tags = match <D as TransformDelegate>::decode_tags(n.meta()) {
-      Ok((readed, tags)) => {
-        tracing::trace!(read = %readed, tags=?tags, "ruserf: decode tags successfully");
-        tags
-      }
-      Err(e) => {
-        tracing::error!(err=%e, "ruserf: failed to decode tags");
-        return;
+    // An empty meta clears the tags, e.g. when the last tag is removed
+    let (mut tags, app_meta) = if !n.meta().is_empty() {
+      match <D as TransformDelegate>::decode_tags(n.meta()) {
+        Ok((readed, tags)) => {
+          tracing::trace!(read = %readed, tags=?tags, "ruserf: decode tags successfully");
+          (tags, app_meta::decode(&n.meta()[readed..]))
+        }
+        Err(e) => {
+          tracing::error!(err=%e, "ruserf: failed to decode tags");
+          return;
+        }
       }
+    } else {
+      Default::default()
     };
+    let versions = Versions::split(&mut tags);
+    let secure = secure::split(&mut tags);
+    conflict::split(&mut tags);
+    cluster::split(&mut tags);
+    let addresses = addresses::split::<D>(&mut tags);
     let mut members = self.inner.members.write().await;
     let id = n.id();
+    let old_tags = members.states.get(id).map(|ms| ms.member.tags.clone());
     if let Some(ms) = members.states.get_mut(id) {
       // Update the member attributes
       ms.member = Member {
         node: n.node(),
         tags: Arc::new(tags),
         status: ms.member.status,
-        protocol_version: ProtocolVersion::V1,
-        delegate_version: DelegateVersion::V1,
+        protocol_version: versions.protocol_version(),
+        delegate_version: versions.delegate_version(),
         memberlist_delegate_version: MemberlistDelegateVersion::V1,
         memberlist_protocol_version: MemberlistProtocolVersion::V1,
+        secure,
+        app_meta,
+        addresses,
+        app_health: ms.member.app_health,
       };
 
       #[cfg(feature = "metrics")]
@@ -1580,6 +3005,11 @@ where
         tracing::error!(err=%e, "ruserf: failed to send member event");
       }
     }
+    if let Some(old_tags) = old_tags {
+      members.reindex_tags(id, Some(&old_tags));
+    }
+
+    self.check_leader(&members).await;
   }
 
   /// Waits for nodes that are leaving and then forcibly
@@ -1591,10 +3021,13 @@ where
   ) {
     let ms = member.member.status;
     if ms == MemberStatus::Leaving {
-      <T::Runtime as RuntimeLite>::sleep(
-        self.inner.opts.broadcast_timeout + self.inner.opts.leave_propagate_delay,
-      )
-      .await;
+      self
+        .inner
+        .timer
+        .sleep::<T::Runtime>(
+          self.inner.opts.broadcast_timeout + self.inner.opts.leave_propagate_delay,
+        )
+        .await;
     }
 
     let node = member.member.node();
@@ -1641,13 +3074,19 @@ where
     // If automatic resolution is enabled, kick off the resolution
     if self.inner.opts.enable_id_conflict_resolution {
       let this = self.clone();
-      <T::Runtime as RuntimeLite>::spawn_detach(async move { this.resolve_node_conflict().await });
+      <T::Runtime as RuntimeLite>::spawn_detach(async move {
+        this.resolve_node_conflict(existing, other).await
+      });
     }
   }
 
   /// Used to determine which node should remain during
   /// a name conflict. This is done by running an internal query.
-  async fn resolve_node_conflict(&self) {
+  async fn resolve_node_conflict(
+    &self,
+    local: Arc<NodeState<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>>,
+    other: Arc<NodeState<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>>,
+  ) {
     // Get the local node
     let local_id = self.inner.memberlist.local_id();
     let local_advertise_addr = self.inner.memberlist.advertise_address();
@@ -1726,7 +3165,43 @@ where
       return;
     }
 
-    // Since we lost the vote, we need to exit
+    // We lost the vote, the configured resolution decides if we need to exit
+    let resolution = &self.inner.opts.conflict_resolution;
+    let keep = match resolution {
+      ConflictResolution::Shutdown => false,
+      ConflictResolution::KeepOldest | ConflictResolution::KeepNewest => {
+        let local_started = self.inner.memberlist.delegate().and_then(|d| d.started());
+        let other_started = if other.meta().is_empty() {
+          None
+        } else {
+          match <D as TransformDelegate>::decode_tags(other.meta()) {
+            Ok((_, mut tags)) => conflict::split(&mut tags),
+            Err(e) => {
+              tracing::error!(err=%e, "ruserf: failed to decode tags of the conflicting node");
+              None
+            }
+          }
+        };
+        resolution.keep_by_age(local_started, other_started)
+      }
+      ConflictResolution::Custom(handler) => handler.call(&local, &other).unwrap_or_else(|| {
+        tracing::error!(
+          "ruserf: the conflict handler does not match the id and address types of the transport"
+        );
+        false
+      }),
+    };
+
+    if keep {
+      tracing::warn!(
+        "ruserf: minority in name conflict resolution, staying by {} resolution [{} / {}]",
+        resolution,
+        matching,
+        responses
+      );
+      return;
+    }
+
     tracing::warn!(
       "ruserf: minority in name conflict resolution, quiting [{} / {}]",
       matching,
@@ -1740,8 +3215,30 @@ where
 
   pub(crate) fn handle_rejoin(
     memberlist: Memberlist<T, SerfDelegate<T, D>>,
-    alive_nodes: TinyVec<Node<T::Id, MaybeResolvedAddress<T>>>,
+    mut alive_nodes: TinyVec<Node<T::Id, MaybeResolvedAddress<T>>>,
+    opts: &Options,
   ) {
+    if let Some(scope_id) = opts.link_local_scope_id {
+      for prev in alive_nodes.iter_mut() {
+        if let MaybeResolvedAddress::Resolved(addr) = prev.address() {
+          if let Some(addr) = scope::with_link_local_scope_id::<D>(addr, scope_id) {
+            *prev = Node::new(
+              prev.id().cheap_clone(),
+              MaybeResolvedAddress::Resolved(addr),
+            );
+          }
+        }
+      }
+    }
+
+    if opts.prefer_global_addresses {
+      // the sort is stable, so the nodes are still shuffled within each group
+      alive_nodes.sort_by_key(|prev| match prev.address() {
+        MaybeResolvedAddress::Resolved(addr) => !scope::is_global::<D>(addr),
+        MaybeResolvedAddress::Unresolved(_) => false,
+      });
+    }
+
     <T::Runtime as RuntimeLite>::spawn_detach(async move {
       for prev in alive_nodes {
         // Do not attempt to join ourself
@@ -1783,6 +3280,25 @@ fn reap_intents<I>(intents: &mut HashMap<I, NodeIntent>, now: Epoch, timeout: Du
   intents.retain(|_, intent| (now - intent.wall_time) <= timeout);
 }
 
+/// Evicts the least recently updated intent to make room for the intent of
+/// `node`, if the buffer holds `max` intents already. The intents of the nodes
+/// referenced by a query or a relay are only evicted once there are no other
+/// ones. Returns whether an intent was evicted.
+fn evict_intent<I>(intents: &mut HashMap<I, NodeIntent>, node: &I, max: usize) -> bool
+where
+  I: CheapClone + Eq + core::hash::Hash,
+{
+  if max == 0 || intents.len() < max || intents.contains_key(node) {
+    return false;
+  }
+
+  let oldest = intents
+    .iter()
+    .min_by_key(|(_, intent)| (intent.referenced, intent.wall_time))
+    .map(|(id, _)| id.cheap_clone());
+  oldest.is_some_and(|id| intents.remove(&id).is_some())
+}
+
 fn recent_intent<I: core::hash::Hash + Eq>(
   intents: &HashMap<I, NodeIntent>,
   id: &I,
@@ -1821,6 +3337,7 @@ where
         ty: t,
         wall_time: stamper(),
         ltime,
+        referenced: false,
       });
       true
     }
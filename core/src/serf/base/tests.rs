@@ -9,14 +9,16 @@ use memberlist_core::{
   types::{OneOrMore, TinyVec},
 };
 use ruserf_types::{
-  MessageType, Node, PushPullMessage, QueryFlag, QueryMessage, SerfMessage, UserEvent,
-  UserEventMessage,
+  MessageType, Node, PushPullMessage, QueryFlag, QueryMessage, SerfMessage, SerfMessageRef,
+  UserEvent, UserEventMessage,
 };
 use smol_str::SmolStr;
 
 use crate::{
+  clock::ManualClock,
   delegate::TransformDelegate,
   event::{CrateEvent, CrateEventType, MemberEvent, MemberEventType},
+  maintenance::{MaintenanceTask, ManualScheduler},
   types::Epoch,
 };
 
@@ -97,6 +99,17 @@ where
   }
 }
 
+/// Waits until `n` timers sleep on the clock, so advancing it wakes all of them.
+async fn wait_until_sleepers<R: RuntimeLite>(clock: &ManualClock, n: usize) {
+  for _ in 0..1000 {
+    if clock.sleepers() >= n {
+      return;
+    }
+    R::sleep(Duration::from_millis(1)).await;
+  }
+  panic!("expected {} sleepers, got {}", n, clock.sleepers());
+}
+
 /// tests that the given node had the given sequence of events
 /// on the event channel.
 async fn test_events<T, D>(
@@ -221,7 +234,11 @@ where
 {
   let (tx, rx) = async_channel::bounded(4);
   let (_shutdown_tx, shutdown_rx) = async_channel::bounded(1);
-  let (event_tx, _handle) = SerfQueries::<T, DefaultDelegate<T>>::new(Some(tx), shutdown_rx);
+  let (event_tx, _handle) = SerfQueries::<T, DefaultDelegate<T>>::new(
+    Some(tx),
+    shutdown_rx,
+    &crate::task::TaskRegistry::default(),
+  );
 
   // Push a user event
   let event = CrateEvent::from(
@@ -269,7 +286,11 @@ where
 {
   let (tx, rx) = async_channel::bounded(4);
   let (_shutdown_tx, shutdown_rx) = async_channel::bounded(1);
-  let (event_tx, _handle) = SerfQueries::<T, DefaultDelegate<T>>::new(Some(tx), shutdown_rx);
+  let (event_tx, _handle) = SerfQueries::<T, DefaultDelegate<T>>::new(
+    Some(tx),
+    shutdown_rx,
+    &crate::task::TaskRegistry::default(),
+  );
 
   // Push a query
   let query = s.query_event(QueryMessage {
@@ -302,7 +323,11 @@ where
 {
   let (tx, rx) = async_channel::bounded(4);
   let (_shutdown_tx, shutdown_rx) = async_channel::bounded(1);
-  let (event_tx, _handle) = SerfQueries::<T, DefaultDelegate<T>>::new(Some(tx), shutdown_rx);
+  let (event_tx, _handle) = SerfQueries::<T, DefaultDelegate<T>>::new(
+    Some(tx),
+    shutdown_rx,
+    &crate::task::TaskRegistry::default(),
+  );
 
   // Push a query
   let query = s.query_event(QueryMessage {
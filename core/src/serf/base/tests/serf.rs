@@ -4,7 +4,16 @@ use memberlist_core::{tests::AnyError, transport::Id};
 
 use ruserf_types::{Member, MemberStatus, Tags};
 
-use crate::{event::EventProducer, types::MemberState};
+use crate::{
+  conflict::ConflictResolution,
+  coordinate::Coordinate,
+  event::EventProducer,
+  hosts::HostsExport,
+  member_filter::{MemberFilter, MemberStatusMask},
+  partition::PartitionDetection,
+  serf::{ShutdownReport, PAUSED_TAG},
+  types::{scope, MemberState},
+};
 
 use super::*;
 
@@ -32,6 +41,10 @@ pub mod reap;
 /// Unit tests for the serf snapshot related functionalities
 pub mod snapshot;
 
+/// Unit tests for the in-memory transport
+#[cfg(feature = "test-util")]
+pub mod memory;
+
 fn test_member_status<I: Id, A>(
   members: &HashMap<I, MemberState<I, A>>,
   id: I,
@@ -445,21 +458,23 @@ where
   }
 }
 
-/// Unit tests for serf num nodes
-pub async fn serf_num_nodes<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+/// Unit tests for serf set app meta
+pub async fn serf_set_app_meta<T>(transport_opts1: T::Options, transport_opts2: T::Options)
 where
   T: Transport,
 {
-  let s1 = Serf::<T>::new(transport_opts1, test_config())
-    .await
-    .unwrap();
+  let s1 = Serf::<T>::new(
+    transport_opts1,
+    test_config().with_tags([("role", "web")].into_iter()),
+  )
+  .await
+  .unwrap();
   let s2 = Serf::<T>::new(transport_opts2, test_config())
     .await
     .unwrap();
 
-  assert_eq!(s1.num_members().await, 1);
-
   let serfs = [s1, s2];
+
   wait_until_num_nodes(1, &serfs).await;
 
   let node = serfs[1]
@@ -470,200 +485,136 @@ where
   serfs[0].join(node.clone(), false).await.unwrap();
 
   wait_until_num_nodes(2, &serfs).await;
-}
-
-/// Unit tests for serf coordinates
-pub async fn serf_coordinates<T>(
-  transport_opts1: T::Options,
-  transport_opts2: T::Options,
-  transport_opts3: T::Options,
-) where
-  T: Transport,
-{
-  const PROBE_INTERVAL: Duration = Duration::from_millis(2);
-
-  let opts = test_config()
-    .with_disable_coordinates(false)
-    .with_memberlist_options(memberlist_core::Options::lan().with_probe_interval(PROBE_INTERVAL));
-  let s1 = Serf::<T>::new(transport_opts1, opts.clone()).await.unwrap();
-  let s2 = Serf::<T>::new(transport_opts2, opts).await.unwrap();
 
-  let mut serfs = vec![s1, s2];
-  wait_until_num_nodes(1, &serfs).await;
-
-  // Make sure both nodes start out the origin so we can prove they did
-  // an update later.
-  let c1 = serfs[0].cooridate().unwrap();
-  let c2 = serfs[1].cooridate().unwrap();
-
-  const ZERO_THRESHOLD: f64 = 20.0e-6;
-
-  assert!(
-    c1.distance_to(&c2).as_secs_f64() <= ZERO_THRESHOLD,
-    "coordinates didn't start at the origin"
-  );
-
-  // Join the two nodes together and give them time to probe each other.
-  let node = serfs[1]
-    .inner
-    .memberlist
-    .advertise_node()
-    .map_address(MaybeResolvedAddress::resolved);
-  serfs[0].join(node.clone(), false).await.unwrap();
+  // Does not fit beside the tags
+  let err = serfs[0]
+    .set_app_meta(vec![0; Meta::MAX_SIZE])
+    .await
+    .unwrap_err();
+  assert!(matches!(
+    err,
+    Error::Serf(crate::error::SerfError::AppMetaTooLarge(_))
+  ));
 
-  wait_until_num_nodes(2, &serfs).await;
+  let app_meta = Bytes::from_static(&[0, 1, 2, 255, 254]);
+  serfs[0].set_app_meta(app_meta.clone()).await.unwrap();
 
   let start = Epoch::now();
-  let mut cond1 = false;
-  let mut cond2 = false;
-  let mut cond3 = false;
-  let mut cond4 = false;
-  let s2id = serfs[1].local_id().clone();
-  let s1id = serfs[0].local_id().clone();
   loop {
     <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
 
-    // See if they know about each other.
-
-    if serfs[0].cached_coordinate(&s2id.clone()).is_ok() {
-      cond1 = true;
-    } else if start.elapsed() > Duration::from_secs(7) {
-      panic!("s1 didn't get a coordinate for s2");
-    }
-
-    if serfs[1].cached_coordinate(&s1id.clone()).is_ok() {
-      cond2 = true;
-    } else if start.elapsed() > Duration::from_secs(7) {
-      panic!("s2 didn't get a coordinate for s1");
-    }
-
-    // With only one ping they won't have a good estimate of the other node's
-    // coordinate, but they should both have updated their own coordinate.
-    let c1 = serfs[0].cooridate().unwrap();
-    let c2 = serfs[1].cooridate().unwrap();
-
-    if c1.distance_to(&c2).as_secs_f64() >= ZERO_THRESHOLD {
-      cond3 = true;
-    } else if start.elapsed() > Duration::from_secs(7) {
-      panic!("coordinates didn't update after probes");
-    }
-
-    // Make sure they cached their own current coordinate after the update.
-    let c1c = serfs[0].cached_coordinate(&s1id.clone()).unwrap();
-    match c1c {
-      None => {
-        if start.elapsed() > Duration::from_secs(7) {
-          panic!("s1 didn't cache its own coordinate");
-        }
-      }
-      Some(c1c) => {
-        if c1 == c1c {
-          cond4 = true;
-        } else if start.elapsed() > Duration::from_secs(7) {
-          panic!("s1 coordinates are not equal");
-        }
+    let mut synced = 0;
+    for s in serfs.iter() {
+      if s
+        .members()
+        .await
+        .iter()
+        .any(|m| m.node.id() == serfs[0].local_id() && m.app_meta == app_meta)
+      {
+        synced += 1;
       }
     }
-
-    if cond1 && cond2 && cond3 && cond4 {
+    if synced == serfs.len() {
       break;
     }
 
     if start.elapsed() > Duration::from_secs(7) {
-      panic!(
-        "timed out cond1 {} cond2 {} cond3 {} cond4 {}",
-        cond1, cond2, cond3, cond4
-      );
+      panic!("timed out");
     }
   }
 
-  // Break up the cluster and make sure the coordinates get removed by
-  // the reaper.
-  serfs[1].shutdown().await.unwrap();
-  let t = serfs[1].inner.opts.reap_interval * 4;
-  drop(serfs.pop().unwrap());
-
-  <T::Runtime as RuntimeLite>::sleep(t).await;
-
-  wait_until_num_nodes(1, &serfs).await;
-
-  let start = Epoch::now();
-  loop {
-    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
-
-    if serfs[0].cached_coordinate(&s2id.clone()).unwrap().is_none() {
-      break;
-    }
+  // The tags are untouched
+  let local = serfs[0].local_member().await;
+  assert_eq!(local.tags().get("role").map(|v| v.as_str()), Some("web"));
+  assert_eq!(local.app_meta(), &app_meta);
 
-    if start.elapsed() > Duration::from_secs(7) {
-      panic!("s1 should have removed s2's cached coordinate");
-    }
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
   }
+}
 
-  // Try a setup with coordinates disabled.
-  let s3 = Serf::<T>::new(
-    transport_opts3,
-    test_config()
-      .with_disable_coordinates(true)
-      .with_memberlist_options(memberlist_core::Options::lan().with_probe_interval(PROBE_INTERVAL)),
-  )
-  .await
-  .unwrap();
+/// Unit tests for the advertisement of the additional addresses
+pub async fn serf_advertise_addresses<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  let s1 = Serf::<T>::new(transport_opts1, test_config())
+    .await
+    .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
+
+  let serfs = [s1, s2];
 
-  serfs.push(s3);
   wait_until_num_nodes(1, &serfs).await;
 
-  let node = serfs[0]
+  let node = serfs[1]
     .inner
     .memberlist
     .advertise_node()
     .map_address(MaybeResolvedAddress::resolved);
-  serfs[1].join(node.clone(), false).await.unwrap();
+  serfs[0].join(node.clone(), false).await.unwrap();
 
   wait_until_num_nodes(2, &serfs).await;
 
+  // Any address will do, they are only tried on reconnect
+  let addresses = vec![serfs[1]
+    .inner
+    .memberlist
+    .advertise_node()
+    .address()
+    .cheap_clone()];
+  serfs[0]
+    .set_advertise_addresses(addresses.clone())
+    .await
+    .unwrap();
+
   let start = Epoch::now();
-  let mut cond1 = false;
-  let mut cond2 = false;
   loop {
     <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
 
-    // See if they know about each other.
-
-    if let Err(e) = serfs[1].cooridate() {
-      if e.to_string().contains("coordinates are disabled") {
-        cond1 = true;
+    let mut synced = 0;
+    for s in serfs.iter() {
+      if s
+        .members()
+        .await
+        .iter()
+        .any(|m| m.node.id() == serfs[0].local_id() && m.addresses() == addresses.as_slice())
+      {
+        synced += 1;
       }
     }
-
-    if serfs[1].cached_coordinate(&s1id.clone()).is_err() {
-      cond2 = true;
-    }
-
-    if cond1 && cond2 {
+    if synced == serfs.len() {
       break;
     }
 
-    if start.elapsed() > Duration::from_secs(14) {
-      panic!("timed out: cond1 {} cond2 {}", cond1, cond2);
+    if start.elapsed() > Duration::from_secs(7) {
+      panic!("timed out");
     }
   }
 
+  // The reserved tag is not exposed
+  let local = serfs[0].local_member().await;
+  assert!(local.tags().is_empty());
+
+  serfs[0].set_advertise_addresses([]).await.unwrap();
+  assert!(serfs[0]
+    .inner
+    .memberlist
+    .delegate()
+    .unwrap()
+    .addresses()
+    .is_empty());
+
   for s in serfs.iter() {
     s.shutdown().await.unwrap();
   }
 }
 
-/// Unit tests for serf name resolution
-///
-/// set_id is a function that takes the transport options and the id of the node, and returns the
-/// transport options with the id set to the given id.
-pub async fn serf_name_resolution<T>(
-  transport_opts1: T::Options,
-  transport_opts2: T::Options,
-  transport_opts3: T::Options,
-  set_id: impl FnOnce(T::Options, T::Id) -> T::Options,
-) where
+/// Unit tests for serf pause and resume
+pub async fn serf_pause_resume<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
   T: Transport,
 {
   let s1 = Serf::<T>::new(transport_opts1, test_config())
@@ -672,17 +623,11 @@ pub async fn serf_name_resolution<T>(
   let s2 = Serf::<T>::new(transport_opts2, test_config())
     .await
     .unwrap();
-  let s3 = Serf::<T>::new(
-    set_id(transport_opts3, s1.local_id().clone()),
-    test_config(),
-  )
-  .await
-  .unwrap();
 
-  let serfs = [s1, s2, s3];
+  let serfs = [s1, s2];
+
   wait_until_num_nodes(1, &serfs).await;
 
-  // Join s1 to s2 first. s2 should vote for s1 in conflict
   let node = serfs[1]
     .inner
     .memberlist
@@ -690,93 +635,1398 @@ pub async fn serf_name_resolution<T>(
     .map_address(MaybeResolvedAddress::resolved);
   serfs[0].join(node.clone(), false).await.unwrap();
 
-  wait_until_num_nodes(2, &serfs[..2]).await;
-  wait_until_num_nodes(1, &serfs[2..]).await;
-
-  let node = serfs[2]
-    .inner
-    .memberlist
-    .advertise_node()
-    .map_address(MaybeResolvedAddress::resolved);
-  serfs[0].join(node.clone(), false).await.unwrap();
-
-  // Wait for the query period to end
-  <T::Runtime as RuntimeLite>::sleep(serfs[0].default_query_timeout().await * 30).await;
+  wait_until_num_nodes(2, &serfs).await;
 
-  let start = Epoch::now();
-  let mut cond1 = false;
-  let mut cond2 = false;
-  let mut cond3 = false;
-  loop {
-    // s3 should have shutdown, while s1 is running
-    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
+  serfs[0].pause().await.unwrap();
+  assert!(serfs[0].is_paused());
+  // Pausing twice is a no-op
+  serfs[0].pause().await.unwrap();
 
-    if serfs[0].state() == SerfState::Alive {
-      cond1 = true;
-    }
+  let err = serfs[0]
+    .user_event("event", Bytes::from_static(b"test"), false)
+    .await
+    .unwrap_err();
+  assert!(matches!(err, Error::Serf(crate::error::SerfError::Paused)));
+
+  let paused_seen = |paused: bool| {
+    let serfs = &serfs;
+    async move {
+      let start = Epoch::now();
+      loop {
+        <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
+
+        let members = serfs[1].members().await;
+        let m = members
+          .iter()
+          .find(|m| m.node.id() == serfs[0].local_id())
+          .unwrap();
+        // Still a live member of the cluster
+        assert_eq!(m.status, MemberStatus::Alive);
+        if m.tags().contains_key(PAUSED_TAG) == paused {
+          break;
+        }
 
-    if serfs[1].state() == SerfState::Alive {
-      cond2 = true;
+        if start.elapsed() > Duration::from_secs(7) {
+          panic!("timed out");
+        }
+      }
     }
+  };
 
-    if serfs[2].state() == SerfState::Shutdown {
-      cond3 = true;
-    }
+  paused_seen(true).await;
 
-    if cond1 && cond2 && cond3 {
-      break;
-    }
+  serfs[0].resume().await.unwrap();
+  assert!(!serfs[0].is_paused());
+  paused_seen(false).await;
 
-    if start.elapsed() > Duration::from_secs(14) {
-      println!("cond1 {cond1} cond2 {cond2} cond3 {cond3}");
-      panic!("timed out");
-    }
-  }
+  serfs[0]
+    .user_event("event", Bytes::from_static(b"test"), false)
+    .await
+    .unwrap();
 
   for s in serfs.iter() {
     s.shutdown().await.unwrap();
   }
 }
 
-/// Unit test for serf local member
-pub async fn serf_local_member<T>(opts: T::Options)
+/// Unit tests for the partition detection
+pub async fn serf_partition_detection<T>(transport_opts1: T::Options, transport_opts2: T::Options)
 where
   T: Transport,
 {
-  let s = Serf::<T>::new(opts, test_config()).await.unwrap();
-
-  let local = s.local_member().await;
+  let (event_tx, event_rx) = EventProducer::bounded(64);
+  let s1 = Serf::<T>::with_event_producer(
+    transport_opts1,
+    test_config().with_partition_detection(Some(
+      PartitionDetection::new()
+        .with_check_interval(Duration::from_millis(50))
+        .with_failure_ratio(0.4),
+    )),
+    event_tx,
+  )
+  .await
+  .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
+
+  let mut serfs = vec![s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .inner
+    .memberlist
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node.clone(), false).await.unwrap();
+
+  wait_until_num_nodes(2, &serfs).await;
+  assert!(!serfs[0].is_partitioned());
+
+  // Force the shutdown of s2 so it appears to fail
+  serfs[1].shutdown().await.unwrap();
+  drop(serfs.pop().unwrap());
+
+  let event = loop {
+    let event = <T::Runtime as RuntimeLite>::timeout(Duration::from_secs(10), event_rx.rx.recv())
+      .await
+      .expect("timed out")
+      .unwrap();
+    if let CrateEvent::Partition(event) = event {
+      break event;
+    }
+  };
+  assert!(event.partitioned());
+  assert_eq!(event.peak(), 2);
+  assert_eq!(event.alive(), 1);
+  assert_eq!(event.failed(), 1);
+  assert!(serfs[0].is_partitioned());
+
+  serfs[0].shutdown().await.unwrap();
+}
+
+/// Unit test for the anti-entropy audit of the member map
+pub async fn serf_anti_entropy_audit<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  let s1 = Serf::<T>::new(transport_opts1, test_config())
+    .await
+    .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  // Nothing to audit against
+  assert_eq!(serfs[0].audit_member_map(0).await.unwrap(), None);
+
+  let node = serfs[1]
+    .inner
+    .memberlist
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node.clone(), false).await.unwrap();
+
+  wait_until_num_nodes(2, &serfs).await;
+
+  // The member maps converge once the join intents are gossiped
+  let start = Epoch::now();
+  loop {
+    if serfs[0].audit_member_map(0).await.unwrap() == Some(0) {
+      break;
+    }
+
+    if start.elapsed() > Duration::from_secs(7) {
+      panic!("timed out");
+    }
+    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
+  }
+
+  // A status time the peer never saw diverges
+  {
+    let mut members = serfs[0].inner.members.write().await;
+    let state = members.states.get_mut(serfs[1].local_id()).unwrap();
+    state.status_time = LamportTime::new(u64::from(state.status_time) + 1000);
+  }
+  let divergence = serfs[0].audit_member_map(0).await.unwrap().unwrap();
+  assert!(divergence >= 1);
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+/// Unit test for the refusal of the nodes of another cluster
+pub async fn serf_cluster_name<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  let (event_tx, event_rx) = EventProducer::bounded(64);
+  let s1 = Serf::<T>::with_event_producer(
+    transport_opts1,
+    test_config().with_cluster_name(Some(SmolStr::new("prod"))),
+    event_tx,
+  )
+  .await
+  .unwrap();
+  let s2 = Serf::<T>::new(
+    transport_opts2,
+    test_config().with_cluster_name(Some(SmolStr::new("staging"))),
+  )
+  .await
+  .unwrap();
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .inner
+    .memberlist
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  assert!(serfs[0].join(node, false).await.is_err());
+
+  let event = loop {
+    let event = <T::Runtime as RuntimeLite>::timeout(Duration::from_secs(10), event_rx.rx.recv())
+      .await
+      .expect("timed out")
+      .unwrap();
+    if let CrateEvent::ClusterMismatch(event) = event {
+      break event;
+    }
+  };
+  assert_eq!(event.node().id(), serfs[1].local_id());
+  assert_eq!(event.cluster(), Some(&crate::cluster::hash("staging")));
+  assert_eq!(serfs[0].num_members().await, 1);
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+/// Unit test for the export of the member addresses
+pub async fn serf_hosts<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  let s1 = Serf::<T>::new(
+    transport_opts1,
+    test_config().with_hosts(Some(HostsExport::new().with_domain("dc1.serf"))),
+  )
+  .await
+  .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
+  assert!(s2.hosts().is_none());
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .inner
+    .memberlist
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node.clone(), false).await.unwrap();
+
+  wait_until_num_nodes(2, &serfs).await;
+
+  let hosts = serfs[0].hosts().unwrap();
+  let name = format!("{}.dc1.serf", serfs[1].local_id());
+  let ip = scope::as_socket_addr(serfs[1].advertise_node().address())
+    .unwrap()
+    .ip();
+  let start = Epoch::now();
+  while hosts.resolve(&name) != [ip] {
+    if start.elapsed() > Duration::from_secs(5) {
+      panic!("{name} is not exported");
+    }
+    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
+  }
+
+  // The members which left are no longer exported
+  serfs[1].leave().await.unwrap();
+  let start = Epoch::now();
+  while !hosts.resolve(&name).is_empty() {
+    if start.elapsed() > Duration::from_secs(5) {
+      panic!("{name} is still exported");
+    }
+    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
+  }
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+/// Unit tests for serf num nodes
+pub async fn serf_num_nodes<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  let s1 = Serf::<T>::new(transport_opts1, test_config())
+    .await
+    .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
+
+  assert_eq!(s1.num_members().await, 1);
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .inner
+    .memberlist
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node.clone(), false).await.unwrap();
+
+  wait_until_num_nodes(2, &serfs).await;
+}
+
+/// Unit test for the filtered and paged member list
+pub async fn serf_members_filtered<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  let s1 = Serf::<T>::new(
+    transport_opts1,
+    test_config().with_tags([("role", "web")].into_iter()),
+  )
+  .await
+  .unwrap();
+  let s2 = Serf::<T>::new(
+    transport_opts2,
+    test_config().with_tags([("role", "db")].into_iter()),
+  )
+  .await
+  .unwrap();
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node, false).await.unwrap();
+  wait_until_num_nodes(2, &serfs).await;
+
+  let web = MemberFilter::new(MemberStatus::Alive)
+    .with_tag("role", "web|api")
+    .unwrap();
+  let members = serfs[0].members_filtered(&web).await;
+  assert_eq!(members.len(), 1);
+  assert_eq!(members[0].node().id(), serfs[0].local_id());
+
+  let named = MemberFilter::default().with_name(format!("{}*", serfs[1].local_id()));
+  let members = serfs[0].members_filtered(&named).await;
+  assert_eq!(members.len(), 1);
+  assert_eq!(members[0].node().id(), serfs[1].local_id());
+
+  let failed = MemberFilter::new(MemberStatusMask::FAILED | MemberStatus::Left);
+  assert!(serfs[0].members_filtered(&failed).await.is_empty());
+
+  // Pages are ordered by id
+  let mut pages = serfs[0].members_paged(MemberFilter::default(), 1);
+  let mut ids = Vec::new();
+  while let Some(page) = pages.next_page().await {
+    assert_eq!(page.len(), 1);
+    ids.push(page[0].node().id().to_string());
+  }
+  let mut expected = serfs
+    .iter()
+    .map(|s| s.local_id().to_string())
+    .collect::<Vec<_>>();
+  expected.sort();
+  assert_eq!(ids, expected);
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+/// Unit tests for the key/value store
+pub async fn serf_kv_store<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  let s1 = Serf::<T>::new(transport_opts1, test_config())
+    .await
+    .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node, false).await.unwrap();
+  wait_until_num_nodes(2, &serfs).await;
+
+  let watcher = serfs[1].kv_watch("svc/");
+  serfs[0].kv_put("svc/web", "10.0.0.1").await.unwrap();
+  serfs[0].kv_put("svc/web", "10.0.0.2").await.unwrap();
+  serfs[0].kv_put("node/a", "ignored").await.unwrap();
+
+  // The local write is visible right away
+  assert_eq!(
+    serfs[0].kv_get("svc/web").unwrap().value().as_ref(),
+    b"10.0.0.2"
+  );
+
+  let start = Epoch::now();
+  loop {
+    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
+
+    let converged = serfs[1]
+      .kv_get("svc/web")
+      .is_some_and(|e| e.value().as_ref() == b"10.0.0.2")
+      && serfs[1].kv_get("node/a").is_some();
+    if converged {
+      break;
+    }
+
+    if start.elapsed() > Duration::from_secs(7) {
+      panic!("timed out");
+    }
+  }
+
+  // The writes to the watched prefix are received, the stale write may be
+  // skipped if it arrived after the newer one
+  let mut last = None;
+  while let Ok(entry) = watcher.try_recv() {
+    assert_eq!(entry.key(), "svc/web");
+    last = Some(entry);
+  }
+  assert_eq!(last.unwrap().value().as_ref(), b"10.0.0.2");
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+/// Unit tests for the distributed lock
+pub async fn serf_lock<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  let s1 = Serf::<T>::new(transport_opts1, test_config())
+    .await
+    .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node, false).await.unwrap();
+  wait_until_num_nodes(2, &serfs).await;
+
+  let ttl = Duration::from_secs(30);
+  let lock = serfs[0].lock("db", ttl).await.unwrap();
+  assert_eq!(lock.name(), "db");
+
+  // The other member cannot take the lock while the lease is held
+  assert!(serfs[1].lock("db", ttl).await.is_err());
+  // Other locks are independent
+  serfs[1].lock("cache", ttl).await.unwrap();
+
+  lock.release().await.unwrap();
+
+  let start = Epoch::now();
+  loop {
+    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
+
+    if serfs[1].lock("db", ttl).await.is_ok() {
+      break;
+    }
+
+    if start.elapsed() > Duration::from_secs(7) {
+      panic!("timed out");
+    }
+  }
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+/// Unit tests for pinging a member
+pub async fn serf_ping_member<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  let s1 = Serf::<T>::new(
+    transport_opts1,
+    test_config().with_disable_coordinates(true),
+  )
+  .await
+  .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node, false).await.unwrap();
+  wait_until_num_nodes(2, &serfs).await;
+
+  let info = serfs[0]
+    .ping_member(serfs[1].local_id().clone())
+    .await
+    .unwrap();
+  assert!(info.coordinate().is_some());
+  assert!(info.uptime() > Duration::ZERO);
+  assert!(info.rtt() > Duration::ZERO);
+
+  // The members with coordinates disabled do not report one
+  let info = serfs[1]
+    .ping_member(serfs[0].local_id().clone())
+    .await
+    .unwrap();
+  assert!(info.coordinate().is_none());
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+/// Unit tests for the cached responses to the internal queries
+pub async fn serf_ping_member_cached<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  let s1 = Serf::<T>::new(transport_opts1, test_config())
+    .await
+    .unwrap();
+  let s2 = Serf::<T>::new(
+    transport_opts2,
+    test_config().with_internal_query_cache_ttl(Some(Duration::from_secs(3600))),
+  )
+  .await
+  .unwrap();
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node, false).await.unwrap();
+  wait_until_num_nodes(2, &serfs).await;
+
+  let first = serfs[0]
+    .ping_member(serfs[1].local_id().clone())
+    .await
+    .unwrap();
+  <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(50)).await;
+
+  // The second ping is answered from the cache, the uptime did not move
+  let second = serfs[0]
+    .ping_member(serfs[1].local_id().clone())
+    .await
+    .unwrap();
+  assert_eq!(first.uptime(), second.uptime());
+
+  // Without the cache, the response is computed again
+  let first = serfs[1]
+    .ping_member(serfs[0].local_id().clone())
+    .await
+    .unwrap();
+  <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(50)).await;
+  let second = serfs[1]
+    .ping_member(serfs[0].local_id().clone())
+    .await
+    .unwrap();
+  assert!(second.uptime() > first.uptime());
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+/// Unit tests for serf coordinates
+pub async fn serf_coordinates<T>(
+  transport_opts1: T::Options,
+  transport_opts2: T::Options,
+  transport_opts3: T::Options,
+) where
+  T: Transport,
+{
+  const PROBE_INTERVAL: Duration = Duration::from_millis(2);
+
+  let opts = test_config()
+    .with_disable_coordinates(false)
+    .with_memberlist_options(memberlist_core::Options::lan().with_probe_interval(PROBE_INTERVAL));
+  let s1 = Serf::<T>::new(transport_opts1, opts.clone()).await.unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, opts).await.unwrap();
+
+  let mut serfs = vec![s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  // Make sure both nodes start out the origin so we can prove they did
+  // an update later.
+  let c1 = serfs[0].cooridate().unwrap();
+  let c2 = serfs[1].cooridate().unwrap();
+
+  const ZERO_THRESHOLD: f64 = 20.0e-6;
+
+  assert!(
+    c1.distance_to(&c2).as_secs_f64() <= ZERO_THRESHOLD,
+    "coordinates didn't start at the origin"
+  );
+
+  // Join the two nodes together and give them time to probe each other.
+  let node = serfs[1]
+    .inner
+    .memberlist
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node.clone(), false).await.unwrap();
+
+  wait_until_num_nodes(2, &serfs).await;
+
+  let start = Epoch::now();
+  let mut cond1 = false;
+  let mut cond2 = false;
+  let mut cond3 = false;
+  let mut cond4 = false;
+  let s2id = serfs[1].local_id().clone();
+  let s1id = serfs[0].local_id().clone();
+  loop {
+    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
+
+    // See if they know about each other.
+
+    if serfs[0].cached_coordinate(&s2id.clone()).is_ok() {
+      cond1 = true;
+    } else if start.elapsed() > Duration::from_secs(7) {
+      panic!("s1 didn't get a coordinate for s2");
+    }
+
+    if serfs[1].cached_coordinate(&s1id.clone()).is_ok() {
+      cond2 = true;
+    } else if start.elapsed() > Duration::from_secs(7) {
+      panic!("s2 didn't get a coordinate for s1");
+    }
+
+    // With only one ping they won't have a good estimate of the other node's
+    // coordinate, but they should both have updated their own coordinate.
+    let c1 = serfs[0].cooridate().unwrap();
+    let c2 = serfs[1].cooridate().unwrap();
+
+    if c1.distance_to(&c2).as_secs_f64() >= ZERO_THRESHOLD {
+      cond3 = true;
+    } else if start.elapsed() > Duration::from_secs(7) {
+      panic!("coordinates didn't update after probes");
+    }
+
+    // Make sure they cached their own current coordinate after the update.
+    let c1c = serfs[0].cached_coordinate(&s1id.clone()).unwrap();
+    match c1c {
+      None => {
+        if start.elapsed() > Duration::from_secs(7) {
+          panic!("s1 didn't cache its own coordinate");
+        }
+      }
+      Some(c1c) => {
+        if c1 == c1c {
+          cond4 = true;
+        } else if start.elapsed() > Duration::from_secs(7) {
+          panic!("s1 coordinates are not equal");
+        }
+      }
+    }
+
+    if cond1 && cond2 && cond3 && cond4 {
+      break;
+    }
+
+    if start.elapsed() > Duration::from_secs(7) {
+      panic!(
+        "timed out cond1 {} cond2 {} cond3 {} cond4 {}",
+        cond1, cond2, cond3, cond4
+      );
+    }
+  }
+
+  // Break up the cluster and make sure the coordinates get removed by
+  // the reaper.
+  serfs[1].shutdown().await.unwrap();
+  let t = serfs[1].inner.opts.reap_interval * 4;
+  drop(serfs.pop().unwrap());
+
+  <T::Runtime as RuntimeLite>::sleep(t).await;
+
+  wait_until_num_nodes(1, &serfs).await;
+
+  let start = Epoch::now();
+  loop {
+    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
+
+    if serfs[0].cached_coordinate(&s2id.clone()).unwrap().is_none() {
+      break;
+    }
+
+    if start.elapsed() > Duration::from_secs(7) {
+      panic!("s1 should have removed s2's cached coordinate");
+    }
+  }
+
+  // Try a setup with coordinates disabled.
+  let s3 = Serf::<T>::new(
+    transport_opts3,
+    test_config()
+      .with_disable_coordinates(true)
+      .with_memberlist_options(memberlist_core::Options::lan().with_probe_interval(PROBE_INTERVAL)),
+  )
+  .await
+  .unwrap();
+
+  serfs.push(s3);
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[0]
+    .inner
+    .memberlist
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[1].join(node.clone(), false).await.unwrap();
+
+  wait_until_num_nodes(2, &serfs).await;
+
+  let start = Epoch::now();
+  let mut cond1 = false;
+  let mut cond2 = false;
+  loop {
+    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
+
+    // See if they know about each other.
+
+    if let Err(e) = serfs[1].cooridate() {
+      if e.to_string().contains("coordinates are disabled") {
+        cond1 = true;
+      }
+    }
+
+    if serfs[1].cached_coordinate(&s1id.clone()).is_err() {
+      cond2 = true;
+    }
+
+    if cond1 && cond2 {
+      break;
+    }
+
+    if start.elapsed() > Duration::from_secs(14) {
+      panic!("timed out: cond1 {} cond2 {}", cond1, cond2);
+    }
+  }
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+/// Unit test for the persisted network coordinates
+pub async fn serf_coordinates_warm_start<T>(
+  transport_opts1: T::Options,
+  transport_opts2: T::Options,
+  transport_opts3: T::Options,
+) where
+  T: Transport,
+{
+  const PROBE_INTERVAL: Duration = Duration::from_millis(2);
+  const ZERO_THRESHOLD: f64 = 20.0e-6;
+
+  let td = tempfile::tempdir().unwrap();
+  let path = td.path().join("coordinates");
+  let opts = test_config()
+    .with_disable_coordinates(false)
+    .with_memberlist_options(memberlist_core::Options::lan().with_probe_interval(PROBE_INTERVAL));
+  let s1 = Serf::<T>::new(
+    transport_opts1,
+    opts.clone().with_coordinate_cache_path(Some(path.clone())),
+  )
+  .await
+  .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, opts.clone()).await.unwrap();
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node, false).await.unwrap();
+
+  wait_until_num_nodes(2, &serfs).await;
+
+  let s2id = serfs[1].local_id().clone();
+  let start = Epoch::now();
+  loop {
+    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
+
+    let c1 = serfs[0].cooridate().unwrap();
+    if serfs[0].cached_coordinate(&s2id).unwrap().is_some()
+      && c1.distance_to(&Coordinate::new()).as_secs_f64() >= ZERO_THRESHOLD
+    {
+      break;
+    }
+
+    if start.elapsed() > Duration::from_secs(7) {
+      panic!("s1 didn't get a coordinate for s2");
+    }
+  }
+
+  // The coordinates are persisted on shutdown
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+  assert!(path.exists());
+
+  let s3 = Serf::<T>::new(transport_opts3, opts.with_coordinate_cache_path(Some(path)))
+    .await
+    .unwrap();
+  let c3 = s3.cooridate().unwrap();
+  assert!(
+    c3.distance_to(&Coordinate::new()).as_secs_f64() >= ZERO_THRESHOLD,
+    "the coordinate should not start at the origin"
+  );
+  assert!(
+    s3.cached_coordinate(&s2id).unwrap().is_some(),
+    "the cached coordinates should be restored"
+  );
+
+  s3.shutdown().await.unwrap();
+}
+
+/// Unit tests for serf name resolution
+///
+/// set_id is a function that takes the transport options and the id of the node, and returns the
+/// transport options with the id set to the given id.
+pub async fn serf_name_resolution<T>(
+  transport_opts1: T::Options,
+  transport_opts2: T::Options,
+  transport_opts3: T::Options,
+  set_id: impl FnOnce(T::Options, T::Id) -> T::Options,
+) where
+  T: Transport,
+{
+  let s1 = Serf::<T>::new(transport_opts1, test_config())
+    .await
+    .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
+  let s3 = Serf::<T>::new(
+    set_id(transport_opts3, s1.local_id().clone()),
+    test_config(),
+  )
+  .await
+  .unwrap();
+
+  let serfs = [s1, s2, s3];
+  wait_until_num_nodes(1, &serfs).await;
+
+  // Join s1 to s2 first. s2 should vote for s1 in conflict
+  let node = serfs[1]
+    .inner
+    .memberlist
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node.clone(), false).await.unwrap();
+
+  wait_until_num_nodes(2, &serfs[..2]).await;
+  wait_until_num_nodes(1, &serfs[2..]).await;
+
+  let node = serfs[2]
+    .inner
+    .memberlist
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node.clone(), false).await.unwrap();
+
+  // Wait for the query period to end
+  <T::Runtime as RuntimeLite>::sleep(serfs[0].default_query_timeout().await * 30).await;
+
+  let start = Epoch::now();
+  let mut cond1 = false;
+  let mut cond2 = false;
+  let mut cond3 = false;
+  loop {
+    // s3 should have shutdown, while s1 is running
+    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
+
+    if serfs[0].state() == SerfState::Alive {
+      cond1 = true;
+    }
+
+    if serfs[1].state() == SerfState::Alive {
+      cond2 = true;
+    }
+
+    if serfs[2].state() == SerfState::Shutdown {
+      cond3 = true;
+    }
+
+    if cond1 && cond2 && cond3 {
+      break;
+    }
+
+    if start.elapsed() > Duration::from_secs(14) {
+      println!("cond1 {cond1} cond2 {cond2} cond3 {cond3}");
+      panic!("timed out");
+    }
+  }
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+/// Unit tests for serf name resolution, where the newest node stays after
+/// losing the vote
+///
+/// set_id is a function that takes the transport options and the id of the node, and returns the
+/// transport options with the id set to the given id.
+pub async fn serf_name_resolution_keep_newest<T>(
+  transport_opts1: T::Options,
+  transport_opts2: T::Options,
+  transport_opts3: T::Options,
+  set_id: impl FnOnce(T::Options, T::Id) -> T::Options,
+) where
+  T: Transport,
+{
+  let opts = || test_config().with_conflict_resolution(ConflictResolution::KeepNewest);
+  let s1 = Serf::<T>::new(transport_opts1, opts()).await.unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, opts()).await.unwrap();
+  // Make sure s3 starts last
+  <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(10)).await;
+  let s3 = Serf::<T>::new(set_id(transport_opts3, s1.local_id().clone()), opts())
+    .await
+    .unwrap();
+
+  let serfs = [s1, s2, s3];
+  wait_until_num_nodes(1, &serfs).await;
+
+  // Join s1 to s2 first. s2 should vote for s1 in conflict
+  let node = serfs[1]
+    .inner
+    .memberlist
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node.clone(), false).await.unwrap();
+
+  wait_until_num_nodes(2, &serfs[..2]).await;
+  wait_until_num_nodes(1, &serfs[2..]).await;
+
+  // The start time is not part of the member tags
+  for m in serfs[1].members().await {
+    assert!(m.tags().get(crate::conflict::STARTED_TAG).is_none());
+  }
+
+  let node = serfs[2]
+    .inner
+    .memberlist
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node.clone(), false).await.unwrap();
+
+  // Wait for the query period to end
+  <T::Runtime as RuntimeLite>::sleep(serfs[0].default_query_timeout().await * 30).await;
+
+  // s3 lost the vote, but it is the newest one, so every node keeps running
+  for s in serfs.iter() {
+    assert_eq!(s.state(), SerfState::Alive);
+  }
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+/// Unit test for serf local member
+pub async fn serf_local_member<T>(opts: T::Options)
+where
+  T: Transport,
+{
+  let s = Serf::<T>::new(opts, test_config()).await.unwrap();
+
+  let local = s.local_member().await;
   assert_eq!(local.node.id(), s.local_id());
 
-  assert_eq!(local.tags, s.inner.opts.tags());
-  assert_eq!(local.status, MemberStatus::Alive);
+  assert_eq!(local.tags, s.inner.opts.tags());
+  assert_eq!(local.status, MemberStatus::Alive);
+
+  let new_tags = [("foo", "bar"), ("test", "ing")]
+    .into_iter()
+    .collect::<Tags>();
+  s.set_tags(new_tags.clone()).await.unwrap();
+
+  let local = s.local_member().await;
+  assert_eq!(&*local.tags, &new_tags);
+}
+
+/// Unit test for serf set tag if
+pub async fn serf_set_tag_if<T>(opts: T::Options)
+where
+  T: Transport,
+{
+  let s = Serf::<T>::new(opts, test_config()).await.unwrap();
+  assert_eq!(s.tags_version().await, 0);
+
+  // the tag must be absent
+  assert_eq!(s.set_tag_if("state", None, "active").await.unwrap(), 1);
+  assert!(s.set_tag_if("state", None, "draining").await.is_err());
+
+  // a stale expectation must not overwrite the tag
+  assert!(s
+    .set_tag_if("state", Some("draining"), "active")
+    .await
+    .is_err());
+  assert_eq!(s.tags_version().await, 1);
+
+  assert_eq!(
+    s.set_tag_if("state", Some("active"), "draining")
+      .await
+      .unwrap(),
+    2
+  );
+
+  let local = s.local_member().await;
+  assert_eq!(local.tags.get("state"), Some(&"draining".into()));
+
+  s.set_tags(Tags::default()).await.unwrap();
+  assert_eq!(s.tags_version().await, 3);
+}
+
+/// Unit tests for reloading the options at runtime
+pub async fn serf_reload<T>(opts: T::Options)
+where
+  T: Transport,
+{
+  let s = Serf::<T>::new(opts, test_config()).await.unwrap();
+  let current = s.reloadable_options();
+  assert_eq!(current, s.inner.opts.reloadable());
+
+  // invalid options are rejected and the current ones stay in effect
+  assert!(s
+    .reload(current.with_reap_interval(Duration::ZERO))
+    .is_err());
+  assert!(s.reload(current.with_query_timeout_mult(0)).is_err());
+  assert_eq!(s.reloadable_options(), current);
+
+  s.reload(current.with_max_queue_depth(128).with_query_size_limit(16))
+    .unwrap();
+  assert_eq!(s.get_queue_max().await, 128);
+  assert_eq!(s.reloadable_options().query_size_limit(), 16);
+
+  // the new query size limit is used right away
+  assert!(s
+    .query("load", Bytes::from_static(&[0; 32]), None)
+    .await
+    .is_err());
+
+  s.shutdown().await.unwrap();
+}
+
+/// Unit test for serf stats
+pub async fn serf_stats<T>(opts: T::Options)
+where
+  T: Transport,
+{
+  let s = Serf::<T>::new(opts, test_config()).await.unwrap();
+
+  let stats = s.stats().await;
+  assert_eq!(stats.get_event_queue(), 0);
+  assert_eq!(stats.get_event_time(), 1);
+  assert_eq!(stats.get_failed(), 0);
+  assert_eq!(stats.get_intent_queue(), 0);
+  assert_eq!(stats.get_left(), 0);
+  assert_eq!(stats.get_health_score(), 0);
+  assert_eq!(stats.get_member_time(), 1);
+  assert_eq!(stats.get_members(), 1);
+  assert_eq!(stats.get_alive(), 1);
+  assert_eq!(stats.get_leaving(), 0);
+  assert!(!stats.get_encrypted());
+  assert_eq!(stats.get_coordinate_cache(), Some(0));
+  assert_eq!(stats.get_bandwidth(), Default::default());
+}
+
+/// Unit test for the seeded random choices
+pub async fn serf_rng_seed<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  // Two nodes with the same seed draw the same query ids
+  let s1 = Serf::<T>::new(transport_opts1, test_config().with_rng_seed(Some(7)))
+    .await
+    .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config().with_rng_seed(Some(7)))
+    .await
+    .unwrap();
+
+  for name in ["first", "second"] {
+    let q1 = s1.query(name, Bytes::new(), None).await.unwrap();
+    let q2 = s2.query(name, Bytes::new(), None).await.unwrap();
+    assert_eq!(q1.id(), q2.id());
+  }
+
+  s1.shutdown().await.unwrap();
+  s2.shutdown().await.unwrap();
+}
+
+/// Unit test for freezing the scaling of the broadcasts
+pub async fn serf_broadcast_scaling<T>(opts: T::Options)
+where
+  T: Transport,
+{
+  let s = Serf::<T>::new(opts, test_config().with_frozen_broadcast_scale(Some(999)))
+    .await
+    .unwrap();
+  let mult = s.inner.opts.memberlist_options.retransmit_mult();
+
+  // Scaled as if the cluster had 999 members
+  let stats = s.stats().await;
+  assert!(stats.get_broadcast_scale_frozen());
+  assert_eq!(stats.get_broadcast_retransmits(), mult * 3);
 
-  let new_tags = [("foo", "bar"), ("test", "ing")]
-    .into_iter()
-    .collect::<Tags>();
-  s.set_tags(new_tags.clone()).await.unwrap();
+  // Scaled with the single local member
+  s.unfreeze_broadcast_scaling();
+  let stats = s.stats().await;
+  assert!(!stats.get_broadcast_scale_frozen());
+  assert_eq!(stats.get_broadcast_retransmits(), mult);
 
-  let local = s.local_member().await;
-  assert_eq!(&*local.tags, &new_tags);
+  assert_eq!(s.freeze_broadcast_scaling().await, 1);
+  let stats = s.stats().await;
+  assert!(stats.get_broadcast_scale_frozen());
+  assert_eq!(stats.get_broadcast_retransmits(), mult);
+
+  s.shutdown().await.unwrap();
 }
 
-/// Unit test for serf stats
-pub async fn serf_stats<T>(opts: T::Options)
+/// Unit test for the health of the background tasks
+pub async fn serf_task_health<T>(opts: T::Options)
+where
+  T: Transport,
+{
+  let td = tempfile::tempdir().unwrap();
+  let (event_tx, _event_rx) = EventProducer::bounded(4);
+  let s = Serf::<T>::with_event_producer(
+    opts,
+    test_config()
+      .with_snapshot_path(Some(td.path().join("serf_task_health")))
+      .with_coalesce_period(Duration::from_millis(5))
+      .with_quiescent_period(Duration::from_millis(5)),
+    event_tx,
+  )
+  .await
+  .unwrap();
+
+  <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(250)).await;
+  let health = s.task_health();
+  let mut names = health.iter().map(|h| h.name()).collect::<Vec<_>>();
+  names.sort_unstable();
+  assert_eq!(
+    names,
+    [
+      "member_event_coalescer",
+      "queries_handler",
+      "reaper",
+      "reconnector",
+      "snapshotter"
+    ]
+  );
+  for h in health.iter() {
+    assert!(!h.exited(), "{} should be running", h.name());
+    assert!(h.iterations() > 0, "{} should have iterated", h.name());
+    assert!(h.last_iteration().is_some());
+  }
+
+  s.shutdown().await.unwrap();
+  <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(100)).await;
+  // The coalescers only exit once the event channel is closed
+  for h in s
+    .task_health()
+    .iter()
+    .filter(|h| h.name() != "member_event_coalescer")
+  {
+    assert!(h.exited(), "{} should have exited", h.name());
+  }
+}
+
+/// Unit test for the report of the shutdown
+pub async fn serf_shutdown_report<T>(opts: T::Options)
+where
+  T: Transport,
+{
+  let td = tempfile::tempdir().unwrap();
+  let s = Serf::<T>::new(
+    opts,
+    test_config()
+      .with_snapshot_path(Some(td.path().join("serf_shutdown_report")))
+      .with_shutdown_timeout(Some(Duration::from_secs(10))),
+  )
+  .await
+  .unwrap();
+
+  s.leave().await.unwrap();
+  let report = s.shutdown().await.unwrap();
+  assert!(report.left());
+  assert_eq!(report.unflushed_broadcasts(), 0);
+  assert_eq!(report.snapshot_synced(), Some(true));
+  assert!(report.tasks_joined() > 0);
+  assert_eq!(report.tasks_abandoned(), 0);
+  assert!(!report.timed_out());
+
+  // The later calls have nothing left to tear down
+  assert_eq!(s.shutdown().await.unwrap(), ShutdownReport::default());
+}
+
+/// Unit test for the shutdown signal
+pub async fn serf_shutdown_signal<T>(opts: T::Options)
 where
   T: Transport,
 {
   let s = Serf::<T>::new(opts, test_config()).await.unwrap();
 
-  let stats = s.stats().await;
-  assert_eq!(stats.get_event_queue(), 0);
-  assert_eq!(stats.get_event_time(), 1);
-  assert_eq!(stats.get_failed(), 0);
-  assert_eq!(stats.get_intent_queue(), 0);
-  assert_eq!(stats.get_left(), 0);
-  assert_eq!(stats.get_health_score(), 0);
-  assert_eq!(stats.get_member_time(), 1);
-  assert_eq!(stats.get_members(), 1);
-  assert!(!stats.get_encrypted());
+  let signal = s.shutdown_signal();
+  assert!(!signal.is_started());
+  assert!(!signal.is_completed());
+
+  let waiter = signal.clone();
+  let (tx, rx) = async_channel::bounded(1);
+  <T::Runtime as RuntimeLite>::spawn_detach(async move {
+    waiter.started().await;
+    waiter.await;
+    tx.send(()).await.unwrap();
+  });
+
+  s.shutdown().await.unwrap();
+  assert!(signal.is_started());
+  assert!(signal.is_completed());
+  futures::select! {
+    _ = rx.recv().fuse() => {},
+    _ = <T::Runtime as RuntimeLite>::sleep(Duration::from_secs(1)).fuse() => {
+      panic!("the shutdown signal did not resolve");
+    }
+  }
+
+  // A handle taken after the shutdown resolves right away
+  s.shutdown_signal().completed().await;
+}
+
+/// Unit test for the bandwidth accounted by message type
+pub async fn serf_stats_bandwidth<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  let s1 = Serf::<T>::new(transport_opts1, test_config())
+    .await
+    .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .inner
+    .memberlist
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node.clone(), false).await.unwrap();
+  wait_until_num_nodes(2, &serfs).await;
+
+  // The join syncs the state both ways
+  for s in serfs.iter() {
+    let push_pull = s.stats().await.get_bandwidth().get_push_pull();
+    assert!(push_pull.get_sent() > 0);
+    assert!(push_pull.get_received() > 0);
+  }
+
+  serfs[0]
+    .user_event("event", Bytes::from_static(&[0; 64]), false)
+    .await
+    .unwrap();
+
+  let start = Epoch::now();
+  loop {
+    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
+
+    let sent = serfs[0].stats().await.get_bandwidth().get_user_event();
+    let received = serfs[1].stats().await.get_bandwidth().get_user_event();
+    if sent.get_sent() > 64 && received.get_received() > 64 {
+      break;
+    }
+
+    if start.elapsed() > Duration::from_secs(7) {
+      panic!("timed out");
+    }
+  }
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+/// Unit test for the health of the local node and its failed peers
+pub async fn serf_health<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  let s1 = Serf::<T>::new(
+    transport_opts1,
+    test_config().with_reconnect_timeout(Duration::from_secs(30)),
+  )
+  .await
+  .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
+
+  let health = s1.health().await;
+  assert_eq!(health.score(), 0);
+  assert_eq!(health.max_score(), 8);
+  assert!(health.peers().is_empty());
+
+  let node = s2
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  s1.join(node, false).await.unwrap();
+  wait_until_num_nodes(2, &[s1.clone(), s2.clone()]).await;
+
+  s2.shutdown().await.unwrap();
+  let start = Epoch::now();
+  loop {
+    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(10)).await;
+    let health = s1.health().await;
+    if let Some(peer) = health.peers().first() {
+      assert_eq!(peer.node().id(), s2.local_id());
+      assert_eq!(peer.status(), MemberStatus::Failed);
+      assert_eq!(peer.flaps(), 0);
+      break;
+    }
+
+    if start.elapsed() > Duration::from_secs(7) {
+      panic!("s2 is not reported as failed");
+    }
+  }
+
+  s1.shutdown().await.unwrap();
+}
+
+/// Unit test for the first seen and status change times of the members
+pub async fn serf_members_info<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  let s1 = Serf::<T>::new(
+    transport_opts1,
+    test_config().with_reconnect_timeout(Duration::from_secs(30)),
+  )
+  .await
+  .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
+
+  let node = s2
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  s1.join(node, false).await.unwrap();
+  wait_until_num_nodes(2, &[s1.clone(), s2.clone()]).await;
+
+  let info = s1.members_info().await;
+  let peer = info
+    .iter()
+    .find(|m| m.member().node().id() == s2.local_id())
+    .unwrap();
+  assert_eq!(peer.first_seen(), peer.status_changed_at());
+  assert!(peer.uptime().is_some());
+  let joined_at = peer.status_changed_at();
+  assert!(s1.stats().await.get_last_status_change_ms().is_some());
+
+  s2.shutdown().await.unwrap();
+  let start = Epoch::now();
+  loop {
+    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(10)).await;
+    let info = s1.members_info().await;
+    let peer = info
+      .iter()
+      .find(|m| m.member().node().id() == s2.local_id())
+      .unwrap();
+    if peer.member().status() == &MemberStatus::Failed {
+      assert!(peer.status_changed_at() > joined_at);
+      assert!(peer.first_seen() <= joined_at);
+      assert!(peer.uptime().is_none());
+      break;
+    }
+
+    if start.elapsed() > Duration::from_secs(7) {
+      panic!("s2 is not reported as failed");
+    }
+  }
+
+  s1.shutdown().await.unwrap();
 }
 
 /// Unit test for serf write keying file
@@ -863,6 +2113,159 @@ pub async fn serf_write_keyring_file<T>(
   assert_eq!(resp.keys().len(), 1);
 }
 
+/// Unit test for the staged key rotation
+#[cfg(feature = "encryption")]
+pub async fn serf_rotate_keys<T>(
+  get_transport_opts: impl FnOnce(memberlist_core::types::SecretKey) -> T::Options,
+) where
+  T: Transport,
+{
+  use crate::key_manager::RotationStage;
+
+  let existing = memberlist_core::types::SecretKey::from([1; 32]);
+  let new_key = memberlist_core::types::SecretKey::from([2; 32]);
+
+  let serf = Serf::<T>::new(get_transport_opts(existing), test_config())
+    .await
+    .unwrap();
+  assert!(serf.encryption_enabled());
+
+  let mut stages = Vec::new();
+  serf
+    .key_manager()
+    .rotate_keys(new_key, None, |stage, resp| {
+      assert_eq!(resp.num_err(), 0);
+      stages.push(stage);
+    })
+    .await
+    .unwrap();
+  assert_eq!(
+    stages,
+    [
+      RotationStage::Install,
+      RotationStage::VerifyInstall,
+      RotationStage::Use,
+      RotationStage::VerifyUse,
+      RotationStage::RemoveOld,
+    ]
+  );
+
+  // Only the new key is left, as the primary key
+  let resp = serf.key_manager().list_keys().await.unwrap();
+  assert_eq!(resp.keys().len(), 1);
+  assert_eq!(resp.primary_keys().get(&new_key), Some(&1));
+
+  // Rotating to the key in use has no previous key to remove
+  let mut stages = Vec::new();
+  let resp = serf
+    .key_manager()
+    .rotate_keys(new_key, None, |stage, _| stages.push(stage))
+    .await
+    .unwrap();
+  assert_eq!(
+    stages,
+    [
+      RotationStage::Install,
+      RotationStage::VerifyInstall,
+      RotationStage::Use,
+      RotationStage::VerifyUse,
+    ]
+  );
+  assert_eq!(resp.num_nodes(), 1);
+  assert_eq!(resp.primary_keys().get(&new_key), Some(&1));
+
+  serf.shutdown().await.unwrap();
+}
+
+/// Unit test for loading the keyring file at startup
+#[cfg(feature = "encryption")]
+pub async fn serf_load_keyring_file<T>(
+  get_transport_opts: impl FnOnce(memberlist_core::types::SecretKey) -> T::Options,
+) where
+  T: Transport,
+{
+  use base64::{engine::general_purpose, Engine as _};
+
+  const EXISTING: &str = "T9jncgl9mbLus+baTTa7q7nPSUrXwbDi2dhbtqir37s=";
+  const PERSISTED: &str = "HvY8ubRZMgafUOWvrOadwOckVa1wN3QWAo46FVKbVN8=";
+
+  let td = tempfile::tempdir().unwrap();
+  let p = td.path().join("serf_load_keyring_file.json");
+  std::fs::write(&p, format!("[\"{PERSISTED}\"]")).unwrap();
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(&p, std::fs::Permissions::from_mode(0o600)).unwrap();
+  }
+
+  let existing_bytes = general_purpose::STANDARD.decode(EXISTING).unwrap();
+  let sk = memberlist_core::types::SecretKey::try_from(existing_bytes.as_slice()).unwrap();
+  let persisted_bytes = general_purpose::STANDARD.decode(PERSISTED).unwrap();
+  let persisted = memberlist_core::types::SecretKey::try_from(persisted_bytes.as_slice()).unwrap();
+
+  let serf = Serf::<T>::new(
+    get_transport_opts(sk),
+    test_config().with_keyring_file(Some(p.clone())),
+  )
+  .await
+  .unwrap();
+
+  // The persisted key replaces the one of the transport
+  let keyring = serf.inner.memberlist.keyring().unwrap();
+  assert_eq!(keyring.primary_key().await, persisted);
+  assert_eq!(keyring.keys().await.collect::<Vec<_>>(), vec![persisted]);
+
+  let resp = serf.key_manager().list_keys().await.unwrap();
+  assert_eq!(resp.keys().len(), 1);
+  serf.shutdown().await.unwrap();
+}
+
+/// Unit test for the encryption state advertised by the members
+#[cfg(feature = "encryption")]
+pub async fn serf_unencrypted_members<T>(
+  get_transport_opts: impl Fn(memberlist_core::types::SecretKey, bool) -> T::Options,
+) where
+  T: Transport,
+{
+  use base64::{engine::general_purpose, Engine as _};
+
+  const KEY: &str = "T9jncgl9mbLus+baTTa7q7nPSUrXwbDi2dhbtqir37s=";
+
+  let key_bytes = general_purpose::STANDARD.decode(KEY).unwrap();
+  let sk = memberlist_core::types::SecretKey::try_from(key_bytes.as_slice()).unwrap();
+
+  // s2 has the key installed, but does not encrypt its gossip yet
+  let s1 = Serf::<T>::new(get_transport_opts(sk, true), test_config())
+    .await
+    .unwrap();
+  let s2 = Serf::<T>::new(get_transport_opts(sk, false), test_config())
+    .await
+    .unwrap();
+  assert!(s1.encryption_enabled());
+  assert!(!s2.encryption_enabled());
+  assert!(s1.local_member().await.secure());
+  assert!(!s2.local_member().await.secure());
+
+  let serfs = [s1, s2];
+  let node = serfs[1]
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node, false).await.unwrap();
+  wait_until_num_nodes(2, &serfs).await;
+
+  let unencrypted = serfs[0].unencrypted_members().await;
+  assert_eq!(unencrypted.len(), 1);
+  assert_eq!(unencrypted[0].node().id(), serfs[1].local_id());
+
+  let unencrypted = serfs[1].unencrypted_members().await;
+  assert_eq!(unencrypted.len(), 1);
+  assert_eq!(unencrypted[0].node().id(), serfs[1].local_id());
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
 #[test]
 fn test_recent_intent() {
   assert!(recent_intent::<SmolStr>(&HashMap::new(), &"foo".into(), MessageType::Join).is_none());
@@ -941,3 +2344,37 @@ fn test_recent_intent() {
   );
   assert!(recent_intent(&intents, &"baz".into(), MessageType::Join).is_none());
 }
+
+#[test]
+fn test_evict_intent() {
+  let now = Epoch::now();
+  let mut intents = HashMap::<SmolStr, _>::new();
+  for (i, id) in ["foo", "bar", "baz"].into_iter().enumerate() {
+    assert!(!evict_intent(&mut intents, &id.into(), 3));
+    upsert_intent(
+      &mut intents,
+      &id.into(),
+      MessageType::Join,
+      (i as u64).into(),
+      || now + Duration::from_secs(i as u64),
+    );
+  }
+
+  // Updating a buffered intent does not evict another one
+  assert!(!evict_intent(&mut intents, &"foo".into(), 3));
+  upsert_intent(
+    &mut intents,
+    &"foo".into(),
+    MessageType::Leave,
+    5.into(),
+    || now + Duration::from_secs(5),
+  );
+
+  // The least recently updated intent makes room for the new one
+  assert!(evict_intent(&mut intents, &"tubez".into(), 3));
+  assert_eq!(intents.len(), 2);
+  assert!(!intents.contains_key("bar"));
+
+  // Unlimited
+  assert!(!evict_intent(&mut intents, &"zip".into(), 0));
+}
@@ -41,11 +41,12 @@ pub async fn delegate_local_state<T>(transport_opts1: T::Options, transport_opts
 where
   T: Transport,
 {
-  let opts = test_config().with_event_buffer_size(0);
-  let s1 = Serf::<T>::new(transport_opts1, opts).await.unwrap();
-
-  let opts = test_config().with_event_buffer_size(0);
-  let s2 = Serf::<T>::new(transport_opts2, opts).await.unwrap();
+  let s1 = Serf::<T>::new(transport_opts1, test_config())
+    .await
+    .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
 
   let serfs = [s1, s2];
   wait_until_num_nodes(1, &serfs).await;
@@ -117,6 +118,125 @@ where
   }
 }
 
+/// Unit test for delegate local state in incremental push/pull mode
+pub async fn delegate_local_state_incremental<T>(transport_opts: T::Options)
+where
+  T: Transport,
+{
+  let opts = test_config().with_incremental_push_pull(true);
+  let s = Serf::<T>::new(transport_opts, opts).await.unwrap();
+
+  s.user_event("test", Bytes::from_static(b"test"), false)
+    .await
+    .unwrap();
+
+  let decode = |buf: Bytes| {
+    let (_, pp) =
+      <DefaultDelegate<T> as TransformDelegate>::decode_message(MessageType::PushPull, &buf[1..])
+        .unwrap();
+    let SerfMessage::PushPull(pp) = pp else {
+      panic!("bad message")
+    };
+    pp
+  };
+
+  let d = s.memberlist().delegate().unwrap();
+
+  // Periodic syncs omit the events, but still carry the event clock
+  let pp = decode(d.local_state(false).await);
+  assert!(pp.events().is_empty(), "should omit the event buffer");
+  assert_eq!(
+    pp.event_ltime(),
+    s.inner.event_clock.time(),
+    "bad event clock"
+  );
+  assert_eq!(pp.status_ltimes().len(), 1, "missing ltimes");
+
+  // Joins still send the full event buffer
+  let pp = decode(d.local_state(true).await);
+  assert_eq!(
+    pp.events().len(),
+    s.inner.event_core.read().await.buffer.len(),
+    "should send full event buffer"
+  );
+  assert!(pp.events().iter().any(|e| e.is_some()));
+
+  s.shutdown().await.unwrap();
+}
+
+/// Unit test for delegate local state compressed with LZ4
+#[cfg(feature = "lz4")]
+pub async fn delegate_local_state_compressed<T>(
+  transport_opts1: T::Options,
+  transport_opts2: T::Options,
+) where
+  T: Transport,
+{
+  use crate::compression::{decompress_push_pull, COMPRESSED_PUSH_PULL_TAG};
+
+  let opts = |vsn| {
+    test_config()
+      .with_protocol_version(vsn)
+      .with_push_pull_compression(true)
+  };
+  let s1 = Serf::<T>::new(transport_opts1, opts(ruserf_types::ProtocolVersion::V2))
+    .await
+    .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, opts(ruserf_types::ProtocolVersion::V1))
+    .await
+    .unwrap();
+
+  for s in [&s1, &s2] {
+    for _ in 0..8 {
+      s.user_event("test", Bytes::from(vec![b'a'; 64]), false)
+        .await
+        .unwrap();
+    }
+  }
+
+  // The joining nodes are not known to understand the compressed state
+  let buf = s1.memberlist().delegate().unwrap().local_state(true).await;
+  assert_eq!(
+    buf[0],
+    MessageType::PushPull as u8,
+    "should not be compressed"
+  );
+
+  let buf = s1.memberlist().delegate().unwrap().local_state(false).await;
+  assert_eq!(buf[0], COMPRESSED_PUSH_PULL_TAG, "should be compressed");
+  let decompressed = decompress_push_pull(&buf[1..]).unwrap();
+  assert!(decompressed.len() > buf.len());
+  assert_eq!(decompressed[0], MessageType::PushPull as u8);
+  let (_, pp) = <DefaultDelegate<T> as TransformDelegate>::decode_message(
+    MessageType::PushPull,
+    &decompressed[1..],
+  )
+  .unwrap();
+  let SerfMessage::PushPull(pp) = pp else {
+    panic!("bad message")
+  };
+  assert_eq!(pp.event_ltime(), s1.inner.event_clock.time());
+
+  // The compressed state is merged like the plain one
+  s2.memberlist()
+    .delegate()
+    .unwrap()
+    .merge_remote_state(buf, false)
+    .await;
+  assert_eq!(s2.inner.event_clock.time(), s1.inner.event_clock.time());
+
+  // A member speaking the first protocol sends the plain state
+  let buf = s2.memberlist().delegate().unwrap().local_state(false).await;
+  assert_eq!(
+    buf[0],
+    MessageType::PushPull as u8,
+    "should not be compressed"
+  );
+
+  s1.shutdown().await.unwrap();
+  s2.shutdown().await.unwrap();
+}
+
 /// Unit test for delegate merge remote state
 pub async fn delegate_merge_remote_state<T>(transport_opts: T::Options)
 where
@@ -354,3 +474,413 @@ pub async fn serf_ping_delegate_rogue_coordinate<T>(
     s.shutdown().await.unwrap();
   }
 }
+
+struct ShutdownRecorder<I, A> {
+  phases: std::sync::Arc<parking_lot::Mutex<Vec<crate::delegate::ShutdownPhase>>>,
+  _marker: std::marker::PhantomData<(I, A)>,
+}
+
+impl<I, A> crate::delegate::ShutdownDelegate for ShutdownRecorder<I, A>
+where
+  I: Id,
+  A: CheapClone + Send + Sync + 'static,
+{
+  type Id = I;
+
+  type Address = A;
+
+  async fn on_shutdown(&self, phase: crate::delegate::ShutdownPhase) {
+    self.phases.lock().push(phase);
+  }
+}
+
+/// Unit test for delegate shutdown phases
+pub async fn delegate_shutdown_phases<T>(transport_opts: T::Options)
+where
+  T: Transport,
+{
+  use crate::delegate::ShutdownPhase;
+
+  let phases = std::sync::Arc::new(parking_lot::Mutex::new(Vec::new()));
+  let s = Serf::<T, _>::with_delegate(
+    transport_opts,
+    test_config(),
+    DefaultDelegate::<T>::new().with_shutdown_delegate(ShutdownRecorder {
+      phases: phases.clone(),
+      _marker: std::marker::PhantomData,
+    }),
+  )
+  .await
+  .unwrap();
+
+  s.leave().await.unwrap();
+  s.shutdown().await.unwrap();
+
+  assert_eq!(
+    phases.lock().as_slice(),
+    &[
+      ShutdownPhase::BeforeLeaveBroadcast,
+      ShutdownPhase::LeaveConfirmed,
+      ShutdownPhase::MemberlistShutdown,
+      ShutdownPhase::SnapshotFlushed,
+    ]
+  );
+}
+
+struct ShutdownStall<I, A> {
+  _marker: std::marker::PhantomData<(I, A)>,
+}
+
+impl<I, A> crate::delegate::ShutdownDelegate for ShutdownStall<I, A>
+where
+  I: Id,
+  A: CheapClone + Send + Sync + 'static,
+{
+  type Id = I;
+
+  type Address = A;
+
+  async fn on_shutdown(&self, phase: crate::delegate::ShutdownPhase) {
+    if phase == crate::delegate::ShutdownPhase::MemberlistShutdown {
+      futures::future::pending::<()>().await;
+    }
+  }
+}
+
+/// Unit test for the shutdown deadline raced by the delegate callbacks
+pub async fn delegate_shutdown_timeout<T>(transport_opts: T::Options)
+where
+  T: Transport,
+{
+  let s = Serf::<T, _>::with_delegate(
+    transport_opts,
+    test_config().with_shutdown_timeout(Some(Duration::from_millis(200))),
+    DefaultDelegate::<T>::new().with_shutdown_delegate(ShutdownStall {
+      _marker: std::marker::PhantomData,
+    }),
+  )
+  .await
+  .unwrap();
+
+  let err = s.shutdown().await.unwrap_err();
+  assert_eq!(err.kind(), crate::error::ErrorKind::Timeout);
+  let Error::Serf(crate::error::SerfError::ShutdownTimeout(report)) = err else {
+    panic!("unexpected error: {err}");
+  };
+  assert!(report.timed_out());
+  assert_eq!(report.snapshot_synced(), None);
+  assert_eq!(report.tasks_joined(), 0);
+
+  // The instance is shut down nonetheless
+  assert_eq!(s.state(), SerfState::Shutdown);
+  assert_eq!(s.shutdown().await.unwrap(), ShutdownReport::default());
+}
+
+/// Unit test for rejecting the members speaking incompatible versions
+pub async fn delegate_notify_alive_versions<T>(transport_opts: T::Options)
+where
+  T: Transport<Id = SmolStr>,
+{
+  use memberlist_core::delegate::AliveDelegate;
+
+  let s = Serf::<T>::new(transport_opts, test_config()).await.unwrap();
+  let addr = s.inner.memberlist.advertise_node().address().clone();
+
+  let node = |vsn: Option<&str>| {
+    let mut tags: Tags = [("role", "web")].into_iter().collect();
+    if let Some(vsn) = vsn {
+      tags.insert(SmolStr::new("_ruserf_vsn"), SmolStr::new(vsn));
+    }
+    let mut meta = vec![0; <DefaultDelegate<T> as TransformDelegate>::tags_encoded_len(&tags)];
+    <DefaultDelegate<T> as TransformDelegate>::encode_tags(&tags, &mut meta).unwrap();
+    Arc::new(NodeState {
+      id: "peer".into(),
+      addr: addr.clone(),
+      meta: meta.try_into().unwrap(),
+      state: memberlist_core::types::State::Alive,
+      protocol_version: ruserf_types::MemberlistProtocolVersion::V1,
+      delegate_version: ruserf_types::MemberlistDelegateVersion::V1,
+    })
+  };
+
+  let delegate = s.inner.memberlist.delegate().unwrap();
+  // members which do not advertise their versions speak the first ones
+  delegate.notify_alive(node(None)).await.unwrap();
+  delegate
+    .notify_alive(node(Some("1.2.1.1.1.1")))
+    .await
+    .unwrap();
+  let err = delegate
+    .notify_alive(node(Some("2.2.2.1.1.1")))
+    .await
+    .unwrap_err();
+  assert!(err.to_string().contains("incompatible versions"), "{err}");
+
+  s.shutdown().await.unwrap();
+}
+
+struct OutboundRewriter<I, A>(std::marker::PhantomData<(I, A)>);
+
+impl<I, A> crate::delegate::OutboundDelegate for OutboundRewriter<I, A>
+where
+  I: Id,
+  A: CheapClone + Send + Sync + 'static,
+{
+  type Id = I;
+
+  type Address = A;
+
+  fn before_broadcast(&self, msg: &SerfMessageRef<'_, I, A>) -> crate::delegate::Decision<I, A> {
+    use crate::delegate::Decision;
+
+    match msg {
+      SerfMessageRef::UserEvent(e) if e.name == "secret" => Decision::Drop,
+      SerfMessageRef::UserEvent(e) if e.name == "redact" => {
+        let mut e = (*e).clone();
+        e.payload = Bytes::from_static(b"***");
+        Decision::Replace(Box::new(SerfMessage::UserEvent(e)))
+      }
+      _ => Decision::Keep,
+    }
+  }
+}
+
+/// Unit test for delegate outbound filtering
+pub async fn delegate_outbound<T>(transport_opts: T::Options)
+where
+  T: Transport,
+{
+  let s = Serf::<T, _>::with_delegate(
+    transport_opts,
+    test_config(),
+    DefaultDelegate::<T>::new().with_outbound_delegate(OutboundRewriter(std::marker::PhantomData)),
+  )
+  .await
+  .unwrap();
+
+  s.user_event("secret", Bytes::from_static(b"hunter2"), false)
+    .await
+    .unwrap();
+  assert_eq!(s.inner.event_broadcasts.num_queued().await, 0);
+
+  s.user_event("redact", Bytes::from_static(b"hunter2"), false)
+    .await
+    .unwrap();
+  let msgs = s.inner.event_broadcasts.get_broadcasts(0, usize::MAX).await;
+  assert_eq!(msgs.len(), 1);
+  let (_, msg) = <DefaultDelegate<T> as TransformDelegate>::decode_message(
+    MessageType::UserEvent,
+    &msgs[0].payload[1..],
+  )
+  .unwrap();
+  match msg {
+    SerfMessage::UserEvent(e) => {
+      assert_eq!(e.name, "redact");
+      assert_eq!(e.payload.as_ref(), b"***");
+    }
+    msg => panic!("unexpected message {msg}"),
+  }
+
+  // Both events are still delivered to the local node unchanged
+  let events = s.inner.event_core.read().await;
+  let payloads = events
+    .buffer
+    .iter()
+    .flatten()
+    .flat_map(|e| e.events.iter())
+    .map(|e| (e.name.clone(), e.payload.clone()))
+    .collect::<Vec<_>>();
+  assert!(payloads.contains(&(SmolStr::new("secret"), Bytes::from_static(b"hunter2"))));
+  assert!(payloads.contains(&(SmolStr::new("redact"), Bytes::from_static(b"hunter2"))));
+  drop(events);
+
+  s.shutdown().await.unwrap();
+}
+
+struct AddressRecorder<I, A> {
+  outbound: std::sync::Arc<AtomicUsize>,
+  inbound: std::sync::Arc<AtomicUsize>,
+  _marker: std::marker::PhantomData<(I, A)>,
+}
+
+impl<I, A> AddressRecorder<I, A> {
+  fn new() -> Self {
+    Self {
+      outbound: Default::default(),
+      inbound: Default::default(),
+      _marker: std::marker::PhantomData,
+    }
+  }
+}
+
+impl<I, A> crate::delegate::AddressDelegate for AddressRecorder<I, A>
+where
+  I: Id,
+  A: CheapClone + Send + Sync + 'static,
+{
+  type Id = I;
+
+  type Address = A;
+
+  fn outbound_address(&self, node: &Node<I, A>) -> A {
+    self.outbound.fetch_add(1, Ordering::SeqCst);
+    node.address().cheap_clone()
+  }
+
+  fn inbound_address(&self, node: &Node<I, A>) -> A {
+    self.inbound.fetch_add(1, Ordering::SeqCst);
+    node.address().cheap_clone()
+  }
+}
+
+/// Unit test for delegate address rewriting
+pub async fn delegate_address<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  let r1 = AddressRecorder::new();
+  let r2 = AddressRecorder::new();
+  let (outbound, inbound) = (r1.outbound.clone(), r2.inbound.clone());
+  let s1 = Serf::<T, _>::with_delegate(
+    transport_opts1,
+    test_config(),
+    DefaultDelegate::<T>::new().with_address_delegate(r1),
+  )
+  .await
+  .unwrap();
+  let s2 = Serf::<T, _>::with_delegate(
+    transport_opts2,
+    test_config(),
+    DefaultDelegate::<T>::new().with_address_delegate(r2),
+  )
+  .await
+  .unwrap();
+
+  let serfs = [s1, s2];
+  let node = serfs[1]
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node, false).await.unwrap();
+  wait_until_num_nodes(2, &serfs).await;
+
+  let mut params = serfs[0].default_query_param().await;
+  params.request_ack = true;
+  let before = outbound.load(Ordering::SeqCst);
+  let resp = serfs[0]
+    .query("where", Bytes::new(), Some(params))
+    .await
+    .unwrap();
+  // The origin of the query is rewritten on the way out
+  assert_eq!(outbound.load(Ordering::SeqCst), before + 1);
+
+  // s2 acks to the origin rewritten on the way in
+  let ack_rx = resp.ack_rx().unwrap();
+  futures::select! {
+    a = ack_rx.recv().fuse() => {
+      assert_eq!(a.unwrap().id(), serfs[1].advertise_node().id());
+    },
+    _ = <T::Runtime as RuntimeLite>::sleep(Duration::from_secs(1)).fuse() => {
+      panic!("timeout");
+    }
+  }
+  assert!(inbound.load(Ordering::SeqCst) >= 1);
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+struct StaticHealth<I, A> {
+  health: std::sync::Arc<std::sync::atomic::AtomicU8>,
+  _marker: std::marker::PhantomData<(I, A)>,
+}
+
+impl<I, A> crate::delegate::HealthDelegate for StaticHealth<I, A>
+where
+  I: Id,
+  A: CheapClone + Send + Sync + 'static,
+{
+  type Id = I;
+
+  type Address = A;
+
+  fn app_health(&self) -> Option<u8> {
+    Some(self.health.load(Ordering::SeqCst))
+  }
+}
+
+/// Unit test for the application health carried in the ping acks
+pub async fn delegate_app_health<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  const PROBE_INTERVAL: Duration = Duration::from_millis(2);
+
+  let health = std::sync::Arc::new(std::sync::atomic::AtomicU8::new(3));
+  let s1 = Serf::<T, _>::with_delegate(
+    transport_opts1,
+    test_config()
+      .with_disable_coordinates(false)
+      .with_memberlist_options(memberlist_core::Options::lan().with_probe_interval(PROBE_INTERVAL)),
+    DefaultDelegate::<T>::new().with_health_delegate(StaticHealth {
+      health: health.clone(),
+      _marker: std::marker::PhantomData,
+    }),
+  )
+  .await
+  .unwrap();
+  let s2 = Serf::<T>::new(
+    transport_opts2,
+    test_config()
+      .with_disable_coordinates(false)
+      .with_memberlist_options(memberlist_core::Options::lan().with_probe_interval(PROBE_INTERVAL)),
+  )
+  .await
+  .unwrap();
+
+  let node = s2
+    .memberlist()
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  s1.join(node, false).await.unwrap();
+  // The delegates differ, so the nodes are waited on one by one
+  wait_until_num_nodes(2, std::slice::from_ref(&s1)).await;
+  wait_until_num_nodes(2, std::slice::from_ref(&s2)).await;
+
+  let s1id = s1.local_id().clone();
+  let s2id = s2.local_id().clone();
+  for expected in [3, 7] {
+    health.store(expected, Ordering::SeqCst);
+    let start = Epoch::now();
+    loop {
+      let reported = s2
+        .members()
+        .await
+        .iter()
+        .find(|m| m.node().id() == &s1id)
+        .and_then(|m| m.app_health());
+      if reported == Some(expected) {
+        break;
+      }
+
+      if start.elapsed() > Duration::from_secs(7) {
+        panic!("s2 got health {reported:?} for s1, expected {expected}");
+      }
+      <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
+    }
+  }
+
+  // The coordinate preceding the health is still applied
+  assert!(s2.cached_coordinate(&s1id).unwrap().is_some());
+  // s2 reports no health
+  let reported = s1
+    .members()
+    .await
+    .iter()
+    .find(|m| m.node().id() == &s2id)
+    .and_then(|m| m.app_health());
+  assert_eq!(reported, None);
+
+  s1.shutdown().await.unwrap();
+  s2.shutdown().await.unwrap();
+}
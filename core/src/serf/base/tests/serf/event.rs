@@ -1,12 +1,35 @@
+use futures::{future::BoxFuture, stream::FusedStream};
 use ruserf_types::{Filter, FilterType};
 
+use crate::{
+  app_query::AppQueryHandler,
+  delegate::{LossyNetwork, MessageDropper},
+  election::{ElectionOptions, ElectionStrategy},
+  error::SerfError,
+  event::{Event, EventRecord, EventRecordKind, EventSink},
+  rate_limit::RateLimit,
+  segment::SEGMENT_TAG,
+  QueryProgress,
+};
+
 use super::*;
 
 /// Unit tests for the user event old message
 pub async fn user_event_old_message<T>(transport_opts: T::Options)
 where
   T: Transport,
+  T::Options: Clone,
 {
+  // The event buffer cannot be empty
+  let err = Serf::<T>::new(
+    transport_opts.clone(),
+    test_config().with_event_buffer_size(0),
+  )
+  .await
+  .err()
+  .unwrap();
+  assert!(matches!(err, Error::Serf(SerfError::InvalidOptions(_))));
+
   let opts = test_config();
   let event_buffer = opts.event_buffer_size;
   let s1 = Serf::<T>::new(transport_opts, opts).await.unwrap();
@@ -25,6 +48,7 @@ where
       .await,
     "should not rebroadcast"
   );
+  assert_eq!(s1.stats().await.get_old_events(), 1);
   s1.shutdown().await.unwrap();
 }
 
@@ -73,6 +97,225 @@ where
   s1.shutdown().await.unwrap();
 }
 
+async fn fire_user_events_with_backpressure<T>(
+  transport_opts: T::Options,
+  policy: crate::event::EventBackpressure,
+  expected: [&'static str; 2],
+) where
+  T: Transport,
+{
+  let opts = test_config().with_event_backpressure(policy);
+  let (event_tx, event_rx) = EventProducer::bounded(2);
+  let s1 = Serf::<T>::with_event_producer(transport_opts, opts, event_tx)
+    .await
+    .unwrap();
+
+  // drain the member events of the local node, so the channel starts empty
+  test_user_events(event_rx.rx.clone(), vec![], vec![]).await;
+
+  for (ltime, name) in ["first", "second", "third", "fourth"]
+    .into_iter()
+    .enumerate()
+  {
+    let msg = UserEventMessage::default()
+      .with_ltime((ltime as u64 + 1).into())
+      .with_name(name.into())
+      .with_payload(Bytes::from_static(b"test"));
+    assert!(s1.handle_user_event(msg).await, "should rebroadcast");
+  }
+
+  // give the dispatcher a chance to apply the policy
+  <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(50)).await;
+
+  test_user_events(
+    event_rx.rx,
+    expected.into_iter().map(Into::into).collect(),
+    vec![Bytes::from_static(b"test"); 2],
+  )
+  .await;
+
+  s1.shutdown().await.unwrap();
+}
+
+/// Unit tests for the drop oldest event backpressure policy
+pub async fn event_backpressure_drop_oldest<T>(transport_opts: T::Options)
+where
+  T: Transport,
+{
+  fire_user_events_with_backpressure::<T>(
+    transport_opts,
+    crate::event::EventBackpressure::DropOldest,
+    ["third", "fourth"],
+  )
+  .await;
+}
+
+/// Unit tests for the drop newest event backpressure policy
+pub async fn event_backpressure_drop_newest<T>(transport_opts: T::Options)
+where
+  T: Transport,
+{
+  fire_user_events_with_backpressure::<T>(
+    transport_opts,
+    crate::event::EventBackpressure::DropNewest,
+    ["first", "second"],
+  )
+  .await;
+}
+
+/// Unit tests for the event stream and its sub-streams
+pub async fn event_stream<T>(transport_opts: T::Options)
+where
+  T: Transport,
+{
+  let s = Serf::<T>::new(transport_opts, test_config()).await.unwrap();
+  let query = || {
+    s.query_event(QueryMessage {
+      ltime: 1.into(),
+      id: 1,
+      from: s.advertise_node(),
+      filters: TinyVec::new(),
+      flags: QueryFlag::empty(),
+      relay_factor: 0,
+      timeout: Default::default(),
+      name: "load".into(),
+      payload: Bytes::new(),
+    })
+  };
+  let subscribe = || {
+    let (producer, subscriber) = EventProducer::<T, DefaultDelegate<T>>::unbounded();
+    let events = [
+      CrateEvent::from((InternalQueryEvent::Ping, query())),
+      CrateEvent::from(MemberEvent {
+        ty: MemberEventType::Join,
+        members: Arc::new(TinyVec::new()),
+      }),
+      CrateEvent::from(UserEventMessage::default().with_name("deploy".into())),
+      CrateEvent::from(query()),
+    ];
+    for event in events {
+      producer.tx.try_send(event).unwrap();
+    }
+    subscriber.into_stream()
+  };
+
+  // The internal queries are skipped
+  let mut stream = subscribe();
+  assert!(matches!(stream.next().await, Some(Event::Member(_))));
+  assert!(matches!(stream.next().await, Some(Event::User(_))));
+  assert!(matches!(stream.next().await, Some(Event::Query(_))));
+  assert!(stream.next().await.is_none());
+  assert!(stream.is_terminated());
+
+  let members = subscribe().member_events().collect::<Vec<_>>().await;
+  assert_eq!(members.len(), 1);
+  assert_eq!(members[0].ty(), MemberEventType::Join);
+
+  let users = subscribe().user_events().collect::<Vec<_>>().await;
+  assert_eq!(users.len(), 1);
+  assert_eq!(users[0].name(), "deploy");
+
+  let mut queries = subscribe().queries();
+  assert_eq!(queries.next().await.unwrap().name(), "load");
+  assert!(queries.next().await.is_none());
+  assert!(queries.is_terminated());
+
+  s.shutdown().await.unwrap();
+}
+
+/// Unit tests for the typed user events
+#[cfg(feature = "serde")]
+pub async fn serf_typed_user_events<T>(transport_opts: T::Options)
+where
+  T: Transport,
+{
+  #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+  struct Deploy {
+    service: String,
+    version: u32,
+  }
+
+  let (event_tx, event_rx) = EventProducer::unbounded();
+  let s = Serf::<T>::with_event_producer(transport_opts, test_config(), event_tx)
+    .await
+    .unwrap();
+
+  let deploy = Deploy {
+    service: "web".into(),
+    version: 2,
+  };
+  s.typed_user_event("deploy", &deploy).await.unwrap();
+  s.user_event("other", Bytes::from_static(b"{}"), false)
+    .await
+    .unwrap();
+  s.user_event("deploy", Bytes::from_static(b"not json"), false)
+    .await
+    .unwrap();
+
+  let mut events = event_rx.into_stream().typed_user_events::<Deploy>("deploy");
+  let event = events.next().await.unwrap().unwrap();
+  assert_eq!(event.name(), "deploy");
+  assert_eq!(event.value(), &deploy);
+
+  // The other events are skipped, the malformed ones are reported
+  let malformed = events.next().await.unwrap().unwrap_err();
+  assert_eq!(malformed.event().payload().as_ref(), b"not json");
+
+  s.shutdown().await.unwrap();
+}
+
+/// Unit tests for the validation of the user event payloads
+pub async fn serf_user_event_validator<T>(transport_opts: T::Options)
+where
+  T: Transport,
+{
+  let (event_tx, event_rx) = EventProducer::unbounded();
+  let s = Serf::<T>::with_event_producer(transport_opts, test_config(), event_tx)
+    .await
+    .unwrap();
+
+  let previous = s.register_user_event_validator("deploy", |payload: &Bytes| {
+    if payload.starts_with(b"v") {
+      Ok(())
+    } else {
+      Err(SmolStr::new("missing version"))
+    }
+  });
+  assert!(previous.is_none());
+
+  s.user_event("deploy", Bytes::from_static(b"v2"), false)
+    .await
+    .unwrap();
+  s.user_event("deploy", Bytes::from_static(b"latest"), false)
+    .await
+    .unwrap();
+  // The other events are not validated
+  s.user_event("other", Bytes::from_static(b"latest"), false)
+    .await
+    .unwrap();
+
+  let mut events = event_rx.into_stream();
+  let mut delivered = Vec::new();
+  let mut invalid = Vec::new();
+  while delivered.len() + invalid.len() < 3 {
+    match events.next().await.unwrap() {
+      Event::User(e) => delivered.push(e),
+      Event::InvalidUser(e) => invalid.push(e),
+      _ => {}
+    }
+  }
+
+  assert_eq!(delivered.len(), 2);
+  assert_eq!(delivered[0].payload().as_ref(), b"v2");
+  assert_eq!(delivered[1].name(), "other");
+  assert_eq!(invalid.len(), 1);
+  assert_eq!(invalid[0].event().payload().as_ref(), b"latest");
+  assert_eq!(invalid[0].reason(), "missing version");
+
+  assert!(s.unregister_user_event_validator("deploy").is_some());
+  s.shutdown().await.unwrap();
+}
+
 /// Unit tests for the events failed
 pub async fn serf_events_failed<T>(transport_opts1: T::Options, transport_opts2: T::Options)
 where
@@ -158,6 +401,143 @@ where
   }
 }
 
+/// Unit tests for the cluster formed event
+pub async fn serf_events_cluster_formed<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  let (event_tx, event_rx) = EventProducer::unbounded();
+  let s1 = Serf::<T>::with_event_producer(
+    transport_opts1,
+    test_config().with_bootstrap_expect(Some(2)),
+    event_tx,
+  )
+  .await
+  .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
+  assert!(s2.is_cluster_formed(), "no expectation, formed right away");
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+  assert!(!serfs[0].is_cluster_formed());
+
+  let node = serfs[1]
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node, false).await.unwrap();
+
+  wait_until_num_nodes(2, &serfs).await;
+
+  let mut formed = None;
+  loop {
+    futures::select! {
+      e = event_rx.rx.recv().fuse() => {
+        if let CrateEvent::ClusterFormed(e) = e.unwrap() {
+          assert!(formed.replace(e).is_none(), "cluster formed should only be emitted once");
+        }
+      },
+      _ = <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(500)).fuse() => break,
+    }
+  }
+
+  let formed = formed.expect("should receive the cluster formed event");
+  assert_eq!(formed.expect(), 2);
+  assert_eq!(formed.alive(), 2);
+  assert!(serfs[0].is_cluster_formed());
+
+  for s in serfs {
+    let _ = s.shutdown().await;
+  }
+}
+
+/// Unit test for the leader election
+pub async fn serf_events_leader_election<T>(
+  transport_opts1: T::Options,
+  transport_opts2: T::Options,
+) where
+  T: Transport,
+{
+  let election = ElectionOptions::new(ElectionStrategy::Lexicographic);
+  let s1 = Serf::<T>::new(
+    transport_opts1,
+    test_config().with_election(Some(election.clone())),
+  )
+  .await
+  .unwrap();
+  let (event_tx, event_rx) = EventProducer::unbounded();
+  let s2 = Serf::<T>::with_event_producer(
+    transport_opts2,
+    test_config().with_election(Some(election)),
+    event_tx,
+  )
+  .await
+  .unwrap();
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  // s1 has the lower id, so s2 only leads until s1 joins
+  let id1 = serfs[0].local_id().clone();
+  let id2 = serfs[1].local_id().clone();
+  assert!(id1.to_string() < id2.to_string());
+  assert_eq!(serfs[1].current_leader().unwrap().node().id(), &id2);
+
+  let node = serfs[0]
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[1].join(node, false).await.unwrap();
+  wait_until_num_nodes(2, &serfs).await;
+
+  for s in serfs.iter() {
+    assert_eq!(s.current_leader().unwrap().node().id(), &id1);
+  }
+
+  serfs[0].leave().await.unwrap();
+
+  let start = Epoch::now();
+  loop {
+    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
+
+    if serfs[1].current_leader().unwrap().node().id() == &id2 {
+      break;
+    }
+
+    if start.elapsed() > Duration::from_secs(7) {
+      panic!("timed out");
+    }
+  }
+
+  let mut changes = vec![];
+  loop {
+    futures::select! {
+      e = event_rx.rx.recv().fuse() => {
+        if let CrateEvent::LeaderChanged(e) = e.unwrap() {
+          changes.push((
+            e.previous().map(|m| m.node().id().clone()),
+            e.current().map(|m| m.node().id().clone()),
+          ));
+        }
+      },
+      _ = <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(500)).fuse() => break,
+    }
+  }
+
+  assert_eq!(
+    changes,
+    [
+      (None, Some(id2.clone())),
+      (Some(id2.clone()), Some(id1.clone())),
+      (Some(id1), Some(id2)),
+    ]
+  );
+
+  for s in serfs {
+    let _ = s.shutdown().await;
+  }
+}
+
 /// Unit tests for the events leave
 /// Unit tests for the events failed
 pub async fn serf_events_leave<T>(transport_opts1: T::Options, transport_opts2: T::Options)
@@ -270,17 +650,15 @@ pub async fn serf_events_leave_avoid_infinite_rebroadcast<T, F>(
 
   // Allow s3 and s4 to drop joins in the future.
   let d = DropJoins::new();
-  let s3 = Serf::<T>::with_message_dropper(
+  let s3 = Serf::<T>::new(
     transport_opts3,
-    config_local(test_config()),
-    Box::new(d.clone()),
+    config_local(test_config()).with_message_dropper(Some(Arc::new(d.clone()))),
   )
   .await
   .unwrap();
-  let s4 = Serf::<T>::with_message_dropper(
+  let s4 = Serf::<T>::new(
     transport_opts4,
-    config_local(test_config()),
-    Box::new(d.clone()),
+    config_local(test_config()).with_message_dropper(Some(Arc::new(d.clone()))),
   )
   .await
   .unwrap();
@@ -502,42 +880,450 @@ where
   .await;
 }
 
-/// Unit tests for the events user size limit
-pub async fn serf_event_user_size_limit<T>(transport_opts1: T::Options)
-where
+/// Unit tests for the user events routed by tags
+pub async fn serf_event_user_distribution<T>(
+  transport_opts1: T::Options,
+  transport_opts2: T::Options,
+) where
   T: Transport,
 {
-  let (event_tx, _event_rx) = EventProducer::bounded(4);
-  let s1 = Serf::<T>::with_event_producer(transport_opts1, test_config(), event_tx)
-    .await
-    .unwrap();
+  let (event_tx, event_rx) = EventProducer::bounded(4);
+  let s1 = Serf::<T>::new(
+    transport_opts1,
+    test_config().with_tags([("role", "web")].into_iter()),
+  )
+  .await
+  .unwrap();
+  let s2 = Serf::<T>::with_event_producer(
+    transport_opts2,
+    test_config().with_tags([("role", "cache")].into_iter()),
+    event_tx,
+  )
+  .await
+  .unwrap();
 
-  let serfs = [s1];
+  let serfs = [s1, s2];
   wait_until_num_nodes(1, &serfs).await;
 
-  let p = Bytes::from(vec![0; serfs[0].inner.opts.max_user_event_size]);
-  let err = serfs[0]
-    .user_event("this is too large an event", p, false)
+  let node = serfs[1]
+    .inner
+    .memberlist
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node.clone(), false).await.unwrap();
+
+  wait_until_num_nodes(2, &serfs).await;
+
+  // Not meant for a cache node
+  serfs[0]
+    .user_event_with_distribution(
+      "purge",
+      Bytes::from_static(b"db"),
+      false,
+      [("role", "^db$")],
+    )
     .await
-    .unwrap_err()
-    .to_string();
-  assert!(err.contains("user event exceeds"));
+    .unwrap();
+
+  serfs[0]
+    .user_event_with_distribution(
+      "reload",
+      Bytes::from_static(b"cache"),
+      false,
+      [("role", "cache")],
+    )
+    .await
+    .unwrap();
+
+  test_user_events(
+    event_rx.rx,
+    vec!["reload".into()],
+    vec![Bytes::from_static(b"cache")],
+  )
+  .await;
+
+  // The event is not delivered, but it is still buffered to be rebroadcast
+  let start = Epoch::now();
+  loop {
+    let buffered = serfs[1]
+      .inner
+      .event_core
+      .read()
+      .await
+      .buffer
+      .iter()
+      .flatten()
+      .any(|events| events.events.iter().any(|e| e.name == "purge"));
+    if buffered {
+      break;
+    }
+
+    if start.elapsed() > Duration::from_secs(5) {
+      panic!("the routed event is not buffered");
+    }
+    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
+  }
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
 }
 
-/// Unit test for default query
-pub async fn default_query<T>(transport_opts: T::Options)
+/// Unit tests for the user events scoped to a segment
+pub async fn serf_event_user_segment<T>(transport_opts1: T::Options, transport_opts2: T::Options)
 where
   T: Transport,
 {
-  let opts = test_config();
-  let s = [Serf::<T>::new(transport_opts, opts).await.unwrap()];
-
-  wait_until_num_nodes(1, &s).await;
-  let timeout = s[0].default_query_timeout().await;
-  assert_eq!(
-    timeout,
-    s[0].inner.opts.memberlist_options.gossip_interval()
-      * (s[0].inner.opts.query_timeout_mult as u32)
+  let (event_tx, event_rx) = EventProducer::bounded(4);
+  let s1 = Serf::<T>::new(
+    transport_opts1,
+    test_config().with_segment(Some("a".into())),
+  )
+  .await
+  .unwrap();
+  let s2 = Serf::<T>::with_event_producer(
+    transport_opts2,
+    test_config().with_segment(Some("b".into())),
+    event_tx,
+  )
+  .await
+  .unwrap();
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .inner
+    .memberlist
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node.clone(), false).await.unwrap();
+
+  wait_until_num_nodes(2, &serfs).await;
+
+  // The segment is advertised in the tags
+  let segment = serfs[0]
+    .inner
+    .members
+    .read()
+    .await
+    .states
+    .get(serfs[1].local_id())
+    .and_then(|m| m.member.tags().get(SEGMENT_TAG).cloned());
+  assert_eq!(segment.as_deref(), Some("b"));
+
+  serfs[0]
+    .user_event("deploy", Bytes::from_static(b"a"), false)
+    .await
+    .unwrap();
+
+  // The event of the other segment is buffered to be rebroadcast
+  let start = Epoch::now();
+  loop {
+    let buffered = serfs[1]
+      .inner
+      .event_core
+      .read()
+      .await
+      .buffer
+      .iter()
+      .flatten()
+      .any(|events| events.events.iter().any(|e| e.name.ends_with("deploy")));
+    if buffered {
+      break;
+    }
+
+    if start.elapsed() > Duration::from_secs(5) {
+      panic!("the event of the other segment is not buffered");
+    }
+    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
+  }
+
+  serfs[1]
+    .user_event("deploy", Bytes::from_static(b"b"), false)
+    .await
+    .unwrap();
+
+  // Only the event of the local segment is delivered, without the segment
+  test_user_events(
+    event_rx.rx,
+    vec!["deploy".into()],
+    vec![Bytes::from_static(b"b")],
+  )
+  .await;
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+/// Unit tests for replaying the buffered user events
+pub async fn serf_event_user_replay<T>(transport_opts: T::Options)
+where
+  T: Transport,
+{
+  let s = Serf::<T>::new(transport_opts, test_config()).await.unwrap();
+
+  let event = |ltime: u64, name: &'static str| {
+    UserEventMessage::default()
+      .with_ltime(ltime.into())
+      .with_name(name.into())
+      .with_payload(Bytes::from_static(b"payload"))
+  };
+  for (ltime, name) in [(3, "third"), (1, "first"), (2, "second"), (3, "fourth")] {
+    assert!(s.handle_user_event(event(ltime, name)).await);
+  }
+  s.kv_put("key", Bytes::from_static(b"value")).await.unwrap();
+
+  let replayed = s.replay_user_events(LamportTime::new(2)).await;
+  let mut names = replayed
+    .iter()
+    .map(|e| e.name().as_str())
+    .collect::<Vec<_>>();
+  // The events of the same lamport time are not ordered
+  names[1..].sort();
+  assert_eq!(names, ["second", "fourth", "third"]);
+  assert!(replayed.iter().all(|e| e.payload().as_ref() == b"payload"));
+
+  assert_eq!(s.replay_user_events(LamportTime::new(0)).await.len(), 4);
+  assert!(s.replay_user_events(LamportTime::new(10)).await.is_empty());
+
+  s.shutdown().await.unwrap();
+}
+
+/// A run-length encoding, only used to check the compressed payloads
+/// are transparent to the application.
+#[cfg(feature = "lz4")]
+struct RunLength;
+
+#[cfg(feature = "lz4")]
+impl crate::compression::Compressor for RunLength {
+  fn id(&self) -> u8 {
+    u8::MAX
+  }
+
+  fn compress(&self, src: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut dst: Vec<u8> = Vec::new();
+    for &b in src {
+      match dst.len() {
+        len if len >= 2 && dst[len - 1] == b && dst[len - 2] < u8::MAX => dst[len - 2] += 1,
+        _ => dst.extend_from_slice(&[1, b]),
+      }
+    }
+    Ok(dst)
+  }
+
+  fn decompress(&self, src: &[u8], limit: usize) -> std::io::Result<Vec<u8>> {
+    let dst: Vec<u8> = src
+      .chunks_exact(2)
+      .flat_map(|run| std::iter::repeat(run[1]).take(run[0] as usize))
+      .collect();
+    if dst.len() > limit {
+      return Err(std::io::Error::other("too large"));
+    }
+    Ok(dst)
+  }
+}
+
+/// Unit tests for the compressed user event and query payloads
+#[cfg(feature = "lz4")]
+pub async fn serf_event_user_compressed<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  let opts = || {
+    test_config()
+      .with_protocol_version(ruserf_types::ProtocolVersion::V2)
+      .with_compressor(Some(Arc::new(RunLength)))
+      .with_compression_threshold(64)
+      .with_max_decompressed_size(8192)
+  };
+  let (event_tx, event_rx) = EventProducer::unbounded();
+  let s1 = Serf::<T>::new(transport_opts1, opts()).await.unwrap();
+  let s2 = Serf::<T>::with_event_producer(transport_opts2, opts(), event_tx)
+    .await
+    .unwrap();
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node, false).await.unwrap();
+
+  wait_until_num_nodes(2, &serfs).await;
+
+  // Way above the size limits, unless compressed
+  let payload = Bytes::from(vec![b'a'; 4096]);
+  serfs[0]
+    .user_event("manifest", payload.clone(), false)
+    .await
+    .unwrap();
+  serfs[0]
+    .query("manifest", payload.clone(), None)
+    .await
+    .unwrap();
+
+  let (mut got_event, mut got_query) = (false, false);
+  while !(got_event && got_query) {
+    futures::select! {
+      e = event_rx.rx.recv().fuse() => {
+        match e.unwrap() {
+          CrateEvent::User(e) => {
+            assert_eq!(e.name(), "manifest");
+            assert_eq!(e.payload(), &payload);
+            assert!(!e.compressed());
+            got_event = true;
+          }
+          CrateEvent::Query(q) => {
+            assert_eq!(q.name(), "manifest");
+            assert_eq!(q.payload(), &payload);
+            got_query = true;
+          }
+          _ => continue,
+        }
+      },
+      _ = <T::Runtime as RuntimeLite>::sleep(Duration::from_secs(2)).fuse() => {
+        panic!("timeout, user event: {got_event}, query: {got_query}");
+      },
+    }
+  }
+
+  // The events which cannot be inflated are rebroadcast once, not delivered
+  let event = |payload: Vec<u8>| {
+    UserEventMessage::default()
+      .with_ltime(serfs[1].inner.event_clock.time())
+      .with_name("opaque".into())
+      .with_payload(Bytes::from(payload))
+      .with_compressed(true)
+  };
+  // compressed by an unknown algorithm
+  let unknown = event(vec![7, 1, 2, 3]);
+  assert!(serfs[1].handle_user_event(unknown.clone()).await);
+  assert!(!serfs[1].handle_user_event(unknown).await);
+  // inflating past the limit
+  let bomb = event([vec![u8::MAX], [u8::MAX, b'a'].repeat(64)].concat());
+  assert!(serfs[1].handle_user_event(bomb.clone()).await);
+  assert!(!serfs[1].handle_user_event(bomb).await);
+  futures::select! {
+    e = event_rx.rx.recv().fuse() => {
+      if let CrateEvent::User(e) = e.unwrap() {
+        panic!("unexpected user event {}", e.name());
+      }
+    },
+    _ = <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(200)).fuse() => {},
+  }
+
+  for s in serfs {
+    let _ = s.shutdown().await;
+  }
+}
+
+/// Unit tests for the user events over a lossy network
+pub async fn serf_event_user_lossy_network<T>(
+  transport_opts1: T::Options,
+  transport_opts2: T::Options,
+) where
+  T: Transport,
+{
+  // s2 holds back the user events and loses all the queries
+  let network = LossyNetwork::new(7)
+    .with_delay(
+      MessageType::UserEvent,
+      Duration::from_millis(500),
+      Duration::from_millis(600),
+    )
+    .with_drop(MessageType::Query, 1.0);
+  let (event_tx, event_rx) = EventProducer::unbounded();
+  let s1 = Serf::<T>::new(transport_opts1, test_config())
+    .await
+    .unwrap();
+  let s2 = Serf::<T>::with_event_producer(
+    transport_opts2,
+    test_config().with_message_dropper(Some(Arc::new(network))),
+    event_tx,
+  )
+  .await
+  .unwrap();
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node, false).await.unwrap();
+
+  wait_until_num_nodes(2, &serfs).await;
+
+  serfs[0].query("lost", Bytes::new(), None).await.unwrap();
+  serfs[0]
+    .user_event("delayed", Bytes::from_static(b"test"), false)
+    .await
+    .unwrap();
+  let sent = Epoch::now();
+
+  loop {
+    futures::select! {
+      e = event_rx.rx.recv().fuse() => {
+        match e.unwrap() {
+          CrateEvent::User(e) => {
+            assert_eq!(e.name(), "delayed");
+            assert!(sent.elapsed() >= Duration::from_millis(500));
+            break;
+          }
+          CrateEvent::Query(q) => panic!("query {} should be dropped", q.name()),
+          _ => continue,
+        }
+      },
+      _ = <T::Runtime as RuntimeLite>::sleep(Duration::from_secs(3)).fuse() => {
+        panic!("timeout waiting for the delayed user event");
+      },
+    }
+  }
+
+  for s in serfs {
+    let _ = s.shutdown().await;
+  }
+}
+
+/// Unit tests for the events user size limit
+pub async fn serf_event_user_size_limit<T>(transport_opts1: T::Options)
+where
+  T: Transport,
+{
+  let (event_tx, _event_rx) = EventProducer::bounded(4);
+  let s1 = Serf::<T>::with_event_producer(transport_opts1, test_config(), event_tx)
+    .await
+    .unwrap();
+
+  let serfs = [s1];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let p = Bytes::from(vec![0; serfs[0].inner.opts.max_user_event_size]);
+  let err = serfs[0]
+    .user_event("this is too large an event", p, false)
+    .await
+    .unwrap_err()
+    .to_string();
+  assert!(err.contains("user event exceeds"));
+}
+
+/// Unit test for default query
+pub async fn default_query<T>(transport_opts: T::Options)
+where
+  T: Transport,
+{
+  let opts = test_config();
+  let s = [Serf::<T>::new(transport_opts, opts).await.unwrap()];
+
+  wait_until_num_nodes(1, &s).await;
+  let timeout = s[0].default_query_timeout().await;
+  assert_eq!(
+    timeout,
+    s[0].inner.opts.memberlist_options.gossip_interval()
+      * (s[0].inner.opts.query_timeout_mult as u32)
   );
 
   let params = s[0].default_query_param().await;
@@ -682,6 +1468,7 @@ pub async fn query_old_message<T>(
       .await,
     "should not rebroadcast"
   );
+  assert_eq!(s1.stats().await.get_old_queries(), 1);
 
   s1.shutdown().await.unwrap();
 }
@@ -774,6 +1561,93 @@ pub async fn query_same_clock<T>(
   s1.shutdown().await.unwrap();
 }
 
+/// Unit tests for the query rate limit
+pub async fn query_rate_limit<T>(
+  transport_opts: T::Options,
+  from: Node<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+) where
+  T: Transport,
+{
+  let opts = test_config().with_query_rate_limit(Some(RateLimit::new(0.0, 2)));
+  let s1 = Serf::<T>::new(transport_opts, opts).await.unwrap();
+
+  let msg = |id, from| QueryMessage {
+    ltime: 1.into(),
+    id,
+    from,
+    filters: Default::default(),
+    flags: QueryFlag::empty(),
+    relay_factor: 0,
+    timeout: Default::default(),
+    name: "foo".into(),
+    payload: Bytes::new(),
+  };
+
+  assert!(s1.handle_query(msg(1, from.clone()), None).await);
+  assert!(s1.handle_query(msg(2, from.clone()), None).await);
+  assert!(
+    !s1.handle_query(msg(3, from.clone()), None).await,
+    "should drop the query over the limit"
+  );
+
+  // The local queries are not limited
+  let local = s1.advertise_node();
+  for id in 4..8 {
+    assert!(s1.handle_query(msg(id, local.clone()), None).await);
+  }
+
+  s1.shutdown().await.unwrap();
+}
+
+/// Unit tests for the suppression of the identical queries
+pub async fn query_suppression<T>(
+  transport_opts: T::Options,
+  from: Node<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+) where
+  T: Transport,
+{
+  let opts = test_config().with_query_suppression_window(Some(Duration::from_millis(200)));
+  let s1 = Serf::<T>::new(transport_opts, opts).await.unwrap();
+
+  let msg = |id, payload| QueryMessage {
+    ltime: 1.into(),
+    id,
+    from: from.clone(),
+    filters: Default::default(),
+    flags: QueryFlag::empty(),
+    relay_factor: 0,
+    timeout: Default::default(),
+    name: "foo".into(),
+    payload: Bytes::from_static(payload),
+  };
+
+  assert!(s1.handle_query(msg(1, b"a"), None).await);
+  assert!(
+    !s1.handle_query(msg(2, b"a"), None).await,
+    "should drop the identical query"
+  );
+  assert!(s1.handle_query(msg(3, b"b"), None).await);
+
+  <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(250)).await;
+  assert!(s1.handle_query(msg(4, b"a"), None).await);
+
+  // The local clients are told their query was suppressed
+  s1.query("bar", Bytes::from_static(b"a"), None)
+    .await
+    .unwrap();
+  let err = s1
+    .query("bar", Bytes::from_static(b"a"), None)
+    .await
+    .err()
+    .unwrap();
+  assert!(matches!(
+    err,
+    Error::Serf(SerfError::QuerySuppressed(name)) if name == "bar"
+  ));
+
+  s1.shutdown().await.unwrap();
+}
+
 /// Unit test for serf query
 pub async fn serf_query<T>(transport_opts1: T::Options, transport_opts2: T::Options)
 where
@@ -869,24 +1743,567 @@ where
   assert_eq!(responses.len(), 1, "missing responses {responses:?}");
 }
 
-/// Unit test for serf query filter
-pub async fn serf_query_filter<T>(
-  transport_opts1: T::Options,
-  transport_opts2: T::Options,
-  transport_opts3: T::Options,
-) where
+/// Unit test for the ack only queries
+pub async fn serf_query_ack_only<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
   T: Transport,
 {
-  let (event_tx, event_rx) = EventProducer::bounded(4);
+  let (event_tx, event_rx) = EventProducer::bounded(64);
 
   let s1 = Serf::<T>::with_event_producer(transport_opts1, test_config(), event_tx)
     .await
     .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
 
-  let wg = Arc::new(());
-  let wg1 = wg.clone();
-  let (ctx_tx, ctx_rx) = async_channel::bounded::<()>(1);
-  scopeguard::defer!(ctx_tx.close(););
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node, false).await.unwrap();
+
+  wait_until_num_nodes(2, &serfs).await;
+
+  let params = serfs[1]
+    .default_query_param()
+    .await
+    .with_ack_only(true)
+    .with_timeout(Duration::from_millis(500));
+  let resp = serfs[1]
+    .query("ping", Bytes::new(), Some(params))
+    .await
+    .unwrap();
+
+  let acks = resp.acks();
+  futures::pin_mut!(acks);
+  let mut acked = vec![];
+  while let Some(ack) = acks.next().await {
+    acked.push(ack);
+  }
+  assert_eq!(acked.len(), 2, "missing acks {acked:?}");
+  for s in serfs.iter() {
+    assert!(acked.contains(&s.advertise_node()));
+  }
+  assert_eq!(resp.num_acks().await, 2);
+  assert!(resp.finished().await);
+  assert!(resp.response_rx().try_recv().is_err());
+
+  // The query was not delivered to the application
+  while let Ok(e) = event_rx.rx.try_recv() {
+    assert!(!matches!(e, CrateEvent::Query(_)), "unexpected query event");
+  }
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+/// Unit test for the query progress
+pub async fn serf_query_progress<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  let s1 = Serf::<T>::new(transport_opts1, test_config())
+    .await
+    .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node, false).await.unwrap();
+
+  wait_until_num_nodes(2, &serfs).await;
+
+  let params = serfs[1]
+    .default_query_param()
+    .await
+    .with_request_ack(true)
+    .with_timeout(Duration::from_millis(500));
+  let resp = serfs[1]
+    .query("progress", Bytes::new(), Some(params))
+    .await
+    .unwrap();
+  assert_eq!(resp.expected(), 2);
+
+  let progress = resp.progress().await;
+  futures::pin_mut!(progress);
+  let mut last: Option<QueryProgress> = None;
+  while let Some(p) = progress.next().await {
+    assert_eq!(p.expected(), 2);
+    if let Some(prev) = last {
+      assert!(p.acked() >= prev.acked());
+    }
+    last = Some(p);
+  }
+  let last = last.expect("missing progress");
+  assert_eq!(last.acked(), 2);
+  assert_eq!(last.responded(), 0);
+
+  // Subscribing to a finished query yields the final progress only
+  let progress = resp.progress().await;
+  futures::pin_mut!(progress);
+  assert_eq!(progress.next().await, Some(last));
+  assert_eq!(progress.next().await, None);
+
+  // Only the targeted members are expected
+  let mut params = serfs[1]
+    .default_query_param()
+    .await
+    .with_timeout(Duration::from_millis(100));
+  params
+    .filters
+    .push(Filter::Id(TinyVec::from(serfs[0].local_id().clone())));
+  let resp = serfs[1]
+    .query("progress", Bytes::new(), Some(params))
+    .await
+    .unwrap();
+  assert_eq!(resp.expected(), 1);
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+/// Unit test for the query builder
+pub async fn serf_query_builder<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  let s1 = Serf::<T>::new(
+    transport_opts1,
+    test_config().with_tags([("role", "web")].into_iter()),
+  )
+  .await
+  .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node, false).await.unwrap();
+
+  wait_until_num_nodes(2, &serfs).await;
+
+  let builder = serfs[1]
+    .query_builder("builder")
+    .with_payload(Bytes::from_static(b"test"))
+    .with_timeout(Duration::from_millis(500))
+    .with_relay_factor(1)
+    .with_ids([serfs[0].local_id().clone()])
+    .with_tag("role", "^web$")
+    .with_ack(true)
+    .with_size_hint(1);
+  let params = builder.params();
+  assert_eq!(params.filters().len(), 2);
+  assert!(params.request_ack());
+  assert_eq!(params.relay_factor(), 1);
+  assert_eq!(params.size_hint(), Some(1));
+
+  let resp = builder.send().await.unwrap();
+  assert_eq!(resp.expected(), 1);
+
+  let acks = resp.acks().collect::<Vec<_>>().await;
+  assert_eq!(acks.len(), 1);
+  assert_eq!(acks[0].id(), serfs[0].local_id());
+
+  // The defaults of the unset parameters
+  let resp = serfs[1]
+    .query_builder("builder")
+    .with_timeout(Duration::from_millis(100))
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(resp.expected(), 2);
+  assert!(resp.ack_rx().is_none());
+
+  // The members of the first protocol version cannot send the responses elsewhere
+  let Err(err) = serfs[1]
+    .query_builder("builder")
+    .with_respond_via(serfs[0].local_id().clone())
+    .send()
+    .await
+  else {
+    panic!("the responses were sent elsewhere");
+  };
+  assert_eq!(err.kind(), crate::error::ErrorKind::Unsupported);
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+/// Unit test for sending several queries at once
+pub async fn serf_multi_query<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  let opts = test_config().with_query_suppression_window(Some(Duration::from_secs(5)));
+  let s1 = Serf::<T>::new(transport_opts1, opts.clone()).await.unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, opts).await.unwrap();
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node, false).await.unwrap();
+
+  wait_until_num_nodes(2, &serfs).await;
+
+  let queries = ["first", "second", "third"]
+    .into_iter()
+    .map(|name| {
+      serfs[1]
+        .query_builder(name)
+        .with_timeout(Duration::from_millis(500))
+        .with_ids([serfs[0].local_id().clone()])
+        .with_ack(true)
+    })
+    .collect();
+  let resps = serfs[1].multi_query(queries).await.unwrap();
+  assert_eq!(resps.len(), 3);
+
+  // Each query has its own Lamport time
+  let ltimes = resps.iter().map(|r| r.ltime()).collect::<HashSet<_>>();
+  assert_eq!(ltimes.len(), 3);
+
+  for resp in resps {
+    let acks = resp.acks().collect::<Vec<_>>().await;
+    assert_eq!(acks.len(), 1);
+    assert_eq!(acks[0].id(), serfs[0].local_id());
+  }
+
+  // None is sent if one of them is invalid, nor suppressed later
+  let queries = vec![
+    serfs[1].query_builder("valid"),
+    serfs[1]
+      .query_builder("too-large")
+      .with_payload(vec![0; 2048]),
+  ];
+  assert!(serfs[1].multi_query(queries).await.is_err());
+  let queries = vec![
+    serfs[1].query_builder("valid"),
+    serfs[1].query_builder("valid"),
+  ];
+  let Err(err) = serfs[1].multi_query(queries).await else {
+    panic!("the repeated query was sent");
+  };
+  assert_eq!(err.kind(), crate::error::ErrorKind::Throttled);
+  let queries = vec![serfs[1].query_builder("valid")];
+  assert!(serfs[1].multi_query(queries).await.is_ok());
+
+  // A query just sent is suppressed
+  assert!(serfs[1].query("first", Bytes::new(), None).await.is_err());
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+/// Unit test for the application queries
+pub async fn serf_app_query<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  struct Echo;
+
+  impl AppQueryHandler for Echo {
+    fn handle(&self, name: &str, payload: Bytes) -> BoxFuture<'static, Option<Bytes>> {
+      let name = SmolStr::new(name);
+      Box::pin(async move {
+        let mut resp = name.as_bytes().to_vec();
+        resp.extend_from_slice(&payload);
+        Some(Bytes::from(resp))
+      })
+    }
+  }
+
+  let (event_tx, event_rx) = EventProducer::bounded(64);
+  let s1 = Serf::<T>::with_event_producer(transport_opts1, test_config(), event_tx)
+    .await
+    .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node, false).await.unwrap();
+
+  wait_until_num_nodes(2, &serfs).await;
+
+  assert!(serfs[0].register_query_handler("echo", Echo).is_none());
+
+  let params = serfs[1]
+    .default_query_param()
+    .await
+    .with_timeout(Duration::from_millis(500));
+  let resp = serfs[1]
+    .app_query("echo", Bytes::from_static(b"-sup"), Some(params.clone()))
+    .await
+    .unwrap();
+  let resp_rx = resp.response_rx();
+  let r = resp_rx.recv().await.unwrap();
+  assert_eq!(r.from, serfs[0].advertise_node());
+  assert_eq!(r.payload, Bytes::from_static(b"echo-sup"));
+  // s2 has no handler and does not respond
+  assert!(resp_rx.recv().await.is_err());
+
+  // The query was not delivered to the application
+  while let Ok(e) = event_rx.rx.try_recv() {
+    assert!(!matches!(e, CrateEvent::Query(_)), "unexpected query event");
+  }
+
+  assert!(serfs[0].unregister_query_handler("echo").is_some());
+  let resp = serfs[1]
+    .app_query("echo", Bytes::new(), Some(params))
+    .await
+    .unwrap();
+  assert!(resp.response_rx().recv().await.is_err());
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+/// Unit test for the event sink
+pub async fn serf_event_sink<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  #[derive(Default)]
+  struct Records(parking_lot::Mutex<Vec<EventRecord>>);
+
+  impl EventSink for Records {
+    fn record(&self, record: &EventRecord) -> std::io::Result<()> {
+      self.0.lock().push(record.clone());
+      Ok(())
+    }
+  }
+
+  let records = Arc::new(Records::default());
+  // No event subscriber, the events are recorded nevertheless
+  let s1 = Serf::<T>::new(
+    transport_opts1,
+    test_config().with_event_sink(Some(records.clone())),
+  )
+  .await
+  .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node, false).await.unwrap();
+  wait_until_num_nodes(2, &serfs).await;
+
+  serfs[1]
+    .user_event("deploy", Bytes::from_static(b"v2"), false)
+    .await
+    .unwrap();
+  serfs[1].query("load", Bytes::new(), None).await.unwrap();
+
+  let source = SmolStr::from(serfs[1].advertise_node().to_string());
+  let kinds = || {
+    records
+      .0
+      .lock()
+      .iter()
+      .map(|r| (r.kind(), r.name().clone()))
+      .collect::<Vec<_>>()
+  };
+  for _ in 0..20 {
+    let kinds = kinds();
+    if kinds.contains(&(EventRecordKind::User, "deploy".into()))
+      && kinds.contains(&(EventRecordKind::Query, "load".into()))
+    {
+      break;
+    }
+    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(100)).await;
+  }
+
+  let records = records.0.lock();
+  assert!(records.iter().any(
+    |r| r.kind() == EventRecordKind::Member(MemberEventType::Join) && r.members().contains(&source)
+  ));
+  let user = records
+    .iter()
+    .find(|r| r.kind() == EventRecordKind::User)
+    .expect("missing user event record");
+  assert_eq!(user.name(), "deploy");
+  assert_eq!(user.payload(), &Bytes::from_static(b"v2"));
+  let query = records
+    .iter()
+    .find(|r| r.kind() == EventRecordKind::Query)
+    .expect("missing query record");
+  assert_eq!(query.name(), "load");
+  assert_eq!(query.source(), Some(&source));
+  assert!(query.ltime().is_some());
+}
+
+/// Unit test for responding to a query from another task after the event was handled
+pub async fn serf_query_deferred_response<T>(transport_opts: T::Options)
+where
+  T: Transport,
+{
+  let (event_tx, event_rx) = EventProducer::bounded(4);
+
+  let s = Serf::<T>::with_event_producer(transport_opts, test_config(), event_tx)
+    .await
+    .unwrap();
+
+  let (result_tx, result_rx) = async_channel::bounded(2);
+  <T::Runtime as RuntimeLite>::spawn_detach(async move {
+    loop {
+      futures::select! {
+        e = event_rx.rx.recv().fuse() => {
+          let Ok(CrateEvent::Query(q)) = e else {
+            continue;
+          };
+
+          // Hand the responder to a worker and return from the event loop right away
+          let responder = q.responder();
+          drop(q);
+          <T::Runtime as RuntimeLite>::spawn_detach(async move {
+            <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(50)).await;
+            let other = responder.clone();
+            assert!(other.is_pending().await);
+            let first = other.respond(Bytes::from_static(b"deferred")).await;
+            let second = responder.respond(Bytes::from_static(b"again")).await;
+            assert!(!responder.is_pending().await);
+            let _ = result_tx.send((first.is_ok(), second.is_ok())).await;
+          });
+          break;
+        },
+        _ = <T::Runtime as RuntimeLite>::sleep(Duration::from_secs(1)).fuse() => {
+          tracing::error!("timeout");
+          break;
+        },
+      }
+    }
+  });
+
+  let resp = s
+    .query("load", Bytes::from_static(b"sup"), None)
+    .await
+    .unwrap();
+  let resp_rx = resp.response_rx();
+  futures::select! {
+    r = resp_rx.recv().fuse() => {
+      let r = r.unwrap();
+      assert_eq!(r.from, s.advertise_node());
+      assert_eq!(r.payload, Bytes::from_static(b"deferred"));
+    },
+    _ = <T::Runtime as RuntimeLite>::sleep(Duration::from_secs(1)).fuse() => {
+      panic!("timeout");
+    },
+  }
+
+  let (first, second) = result_rx.recv().await.unwrap();
+  assert!(first, "the first response should be sent");
+  assert!(!second, "only one response should be sent");
+
+  s.shutdown().await.unwrap();
+}
+
+/// Unit test for truncating a query response which exceeds the size limit
+pub async fn serf_query_respond_lossy<T>(transport_opts: T::Options)
+where
+  T: Transport,
+{
+  let (event_tx, event_rx) = EventProducer::bounded(4);
+
+  let s = Serf::<T>::with_event_producer(transport_opts, test_config(), event_tx)
+    .await
+    .unwrap();
+
+  let payload = (0..2048).map(|i| i as u8).collect::<Bytes>();
+  let expected = payload.clone();
+  <T::Runtime as RuntimeLite>::spawn_detach(async move {
+    loop {
+      futures::select! {
+        e = event_rx.rx.recv().fuse() => {
+          let Ok(CrateEvent::Query(q)) = e else {
+            continue;
+          };
+
+          let err = q.respond(payload.clone()).await.unwrap_err();
+          assert!(err.to_string().contains("exceeds limit"), "{err}");
+          // A callback which cannot shorten the payload gives up
+          let err = q.respond_lossy_with(payload.clone(), |p, _| Some(p)).await.unwrap_err();
+          assert!(err.to_string().contains("failed to truncate"), "{err}");
+          q.respond_lossy(payload).await.unwrap();
+          break;
+        },
+        _ = <T::Runtime as RuntimeLite>::sleep(Duration::from_secs(1)).fuse() => {
+          tracing::error!("timeout");
+          break;
+        },
+      }
+    }
+  });
+
+  let resp = s
+    .query("load", Bytes::from_static(b"sup"), None)
+    .await
+    .unwrap();
+  let resp_rx = resp.response_rx();
+  futures::select! {
+    r = resp_rx.recv().fuse() => {
+      let r = r.unwrap();
+      assert!(!r.payload.is_empty() && r.payload.len() < expected.len());
+      assert_eq!(r.payload, expected.slice(..r.payload.len()));
+    },
+    _ = <T::Runtime as RuntimeLite>::sleep(Duration::from_secs(1)).fuse() => {
+      panic!("timeout");
+    },
+  }
+
+  s.shutdown().await.unwrap();
+}
+
+/// Unit test for serf query filter
+pub async fn serf_query_filter<T>(
+  transport_opts1: T::Options,
+  transport_opts2: T::Options,
+  transport_opts3: T::Options,
+) where
+  T: Transport,
+{
+  let (event_tx, event_rx) = EventProducer::bounded(4);
+
+  let s1 = Serf::<T>::with_event_producer(transport_opts1, test_config(), event_tx)
+    .await
+    .unwrap();
+
+  let wg = Arc::new(());
+  let wg1 = wg.clone();
+  let (ctx_tx, ctx_rx) = async_channel::bounded::<()>(1);
+  scopeguard::defer!(ctx_tx.close(););
 
   <T::Runtime as RuntimeLite>::spawn_detach(async move {
     let _wg = wg1;
@@ -994,6 +2411,191 @@ pub async fn serf_query_filter<T>(
   }
 }
 
+/// Unit test for the responses sent to a member collecting them
+#[cfg(feature = "lz4")]
+pub async fn serf_query_respond_via<T>(
+  transport_opts1: T::Options,
+  transport_opts2: T::Options,
+  transport_opts3: T::Options,
+) where
+  T: Transport,
+{
+  let opts = || test_config().with_protocol_version(ruserf_types::ProtocolVersion::V2);
+  let (event_tx, event_rx) = EventProducer::bounded(4);
+
+  let s1 = Serf::<T>::with_event_producer(transport_opts1, opts(), event_tx)
+    .await
+    .unwrap();
+
+  // Only respond once the query was sent
+  let (go_tx, go_rx) = async_channel::bounded::<()>(1);
+  let (respond_to_tx, respond_to_rx) = async_channel::bounded(1);
+  <T::Runtime as RuntimeLite>::spawn_detach(async move {
+    loop {
+      futures::select! {
+        e = event_rx.rx.recv().fuse() => {
+          let e = e.unwrap();
+          if let CrateEvent::Query(q) = e {
+            let _ = go_rx.recv().await;
+            respond_to_tx.send(q.respond_to().id().clone()).await.unwrap();
+            q.respond(Bytes::from_static(b"test")).await.unwrap();
+            break;
+          }
+        },
+        _ = <T::Runtime as RuntimeLite>::sleep(Duration::from_secs(2)).fuse() => {
+          tracing::error!("timeout");
+          break;
+        },
+      }
+    }
+  });
+
+  let s2 = Serf::<T>::new(transport_opts2, opts()).await.unwrap();
+  let s3 = Serf::<T>::new(transport_opts3, opts()).await.unwrap();
+
+  let serfs = [s1, s2, s3];
+  wait_until_num_nodes(1, &serfs).await;
+
+  for s in serfs[1..].iter() {
+    let node = s
+      .advertise_node()
+      .map_address(MaybeResolvedAddress::resolved);
+    serfs[0].join(node, false).await.unwrap();
+  }
+
+  wait_until_num_nodes(3, &serfs).await;
+
+  // Query s1 from s2, the response is collected by s3
+  let timeout = Duration::from_secs(1);
+  let resp = serfs[1]
+    .query_builder("load")
+    .with_payload(Bytes::from_static(b"sup girl"))
+    .with_timeout(timeout)
+    .with_ids([serfs[0].local_id().clone()])
+    .with_respond_via(serfs[2].local_id().clone())
+    .send()
+    .await
+    .unwrap();
+
+  // The response arrives before the collector is ready, it is kept until then
+  go_tx.send(()).await.unwrap();
+  assert_eq!(&respond_to_rx.recv().await.unwrap(), serfs[2].local_id());
+  <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(200)).await;
+  let collected = serfs[2]
+    .collect_query_responses(resp.ltime(), resp.id(), timeout)
+    .await;
+
+  let collected_rx = collected.response_rx();
+  let r = futures::select! {
+    r = collected_rx.recv().fuse() => r.unwrap(),
+    _ = <T::Runtime as RuntimeLite>::sleep(timeout).fuse() => panic!("timeout"),
+  };
+  assert_eq!(r.from, serfs[0].advertise_node());
+  assert_eq!(r.payload, Bytes::from_static(b"test"));
+
+  // The requester does not receive the response
+  let responses = resp.response_rx().collect::<Vec<_>>().await;
+  assert!(responses.is_empty(), "unexpected responses {responses:?}");
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+/// Unit test for serf query relayed response
+pub async fn serf_query_relayed_response<T>(transport_opts: T::Options)
+where
+  T: Transport,
+{
+  let opts = test_config();
+  let s = Serf::<T>::new(transport_opts, opts).await.unwrap();
+
+  // Set up a dummy query and a response which was relayed by another node
+  let mq = QueryMessage {
+    ltime: 123.into(),
+    id: 123,
+    from: s.advertise_node(),
+    filters: Default::default(),
+    flags: QueryFlag::empty(),
+    relay_factor: 1,
+    timeout: Duration::from_secs(1),
+    name: Default::default(),
+    payload: Default::default(),
+  };
+  let query = QueryResponse::from_query(&Timer::default(), &mq, 3, 1, None);
+  let response = QueryResponseMessage {
+    ltime: mq.ltime,
+    id: mq.id,
+    from: s.advertise_node(),
+    flags: QueryFlag::RELAYED,
+    payload: Default::default(),
+  };
+  {
+    let mut qc = s.inner.query_core.write().await;
+    qc.responses.insert(mq.ltime, query.clone());
+  }
+
+  s.handle_query_response(response).await;
+
+  let resp_rx = query.response_rx();
+  futures::select! {
+    r = resp_rx.recv().fuse() => {
+      assert!(r.unwrap().relayed(), "response should be marked as relayed");
+    },
+    default => {
+      panic!("should have a response")
+    }
+  }
+
+  s.shutdown().await.unwrap();
+}
+
+/// Unit test for serf query deadline extension
+pub async fn serf_query_deadline_extension<T>(transport_opts: T::Options)
+where
+  T: Transport,
+{
+  let opts = test_config();
+  let s = Serf::<T>::new(transport_opts, opts).await.unwrap();
+
+  // Set up a dummy query whose deadline is pushed out by the responses
+  let mq = QueryMessage {
+    ltime: 123.into(),
+    id: 123,
+    from: s.advertise_node(),
+    filters: Default::default(),
+    flags: QueryFlag::empty(),
+    relay_factor: 0,
+    timeout: Duration::from_millis(100),
+    name: Default::default(),
+    payload: Default::default(),
+  };
+  let extension = DeadlineExtension::new(Duration::from_millis(500), Duration::from_secs(1));
+  let query = QueryResponse::from_query(&Timer::default(), &mq, 3, 1, Some(extension));
+  let response = QueryResponseMessage {
+    ltime: mq.ltime,
+    id: mq.id,
+    from: s.advertise_node(),
+    flags: QueryFlag::empty(),
+    payload: Default::default(),
+  };
+  s.register_query_response(mq.timeout, query.clone()).await;
+
+  // A duplicate response does not extend the deadline again
+  s.handle_query_response(response.clone()).await;
+  s.handle_query_response(response).await;
+  assert_eq!(query.response_rx().len(), 1);
+
+  // The query is left open past its timeout
+  <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(300)).await;
+  assert!(!query.finished().await, "query should be extended");
+
+  <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(600)).await;
+  assert!(query.finished().await, "query should be closed");
+
+  s.shutdown().await.unwrap();
+}
+
 /// Unit test for serf query deduplicate
 pub async fn serf_query_deduplicate<T>(transport_opts: T::Options)
 where
@@ -1014,7 +2616,7 @@ where
     name: Default::default(),
     payload: Default::default(),
   };
-  let query = QueryResponse::from_query(&mq, 3);
+  let query = QueryResponse::from_query(&Timer::default(), &mq, 3, 1, None);
   let mut response = QueryResponseMessage {
     ltime: mq.ltime,
     id: mq.id,
@@ -1098,3 +2700,70 @@ where
   let payload = vec![0; size_limit];
   s.query(name, payload, None).await.unwrap();
 }
+
+/// Unit tests for the user events split into fragments
+#[cfg(feature = "lz4")]
+pub async fn serf_event_user_fragmented<T>(transport_opts1: T::Options, transport_opts2: T::Options)
+where
+  T: Transport,
+{
+  let opts = test_config()
+    .with_protocol_version(ruserf_types::ProtocolVersion::V2)
+    .with_max_user_event_size(256)
+    .with_max_fragmented_user_event_size(4096);
+  let (event_tx, event_rx) = EventProducer::bounded(4);
+  let s1 = Serf::<T>::new(transport_opts1, opts.clone()).await.unwrap();
+  let s2 = Serf::<T>::with_event_producer(transport_opts2, opts, event_tx)
+    .await
+    .unwrap();
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1]
+    .inner
+    .memberlist
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  serfs[0].join(node.clone(), false).await.unwrap();
+
+  wait_until_num_nodes(2, &serfs).await;
+
+  let payload = Bytes::from((0..=255u8).cycle().take(2000).collect::<Vec<_>>());
+  serfs[0]
+    .user_event("big", payload.clone(), false)
+    .await
+    .unwrap();
+
+  let err = serfs[0]
+    .user_event("bigger", Bytes::from(vec![0; 4096]), false)
+    .await
+    .unwrap_err();
+  assert!(matches!(
+    err,
+    Error::Serf(SerfError::UserEventLimitTooLarge(4096))
+  ));
+
+  // The event is delivered once, in one piece
+  let mut events = Vec::new();
+  let start = Epoch::now();
+  while start.elapsed() < Duration::from_secs(5) {
+    futures::select! {
+      event = event_rx.rx.recv().fuse() => {
+        if let CrateEvent::User(e) = event.unwrap() {
+          events.push((e.name.clone(), e.payload.clone()));
+        }
+      }
+      _ = <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(500)).fuse() => {
+        if !events.is_empty() {
+          break;
+        }
+      }
+    }
+  }
+  assert_eq!(events, vec![(SmolStr::new("big"), payload)]);
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
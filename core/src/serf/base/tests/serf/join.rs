@@ -1,6 +1,6 @@
 use std::marker::PhantomData;
 
-use crate::delegate::MergeDelegate;
+use crate::{delegate::MergeDelegate, error::SerfError};
 
 use super::*;
 
@@ -57,6 +57,10 @@ pub async fn join_intent_old_message<T>(
           memberlist_delegate_version: ruserf_types::MemberlistDelegateVersion::V1,
           protocol_version: ruserf_types::ProtocolVersion::V1,
           delegate_version: ruserf_types::DelegateVersion::V1,
+          secure: false,
+          app_meta: Default::default(),
+          addresses: Arc::from(Vec::new()),
+          app_health: None,
         },
         status_time: 12.into(),
         leave_time: None,
@@ -108,6 +112,10 @@ pub async fn join_intent_newer<T>(
           memberlist_delegate_version: ruserf_types::MemberlistDelegateVersion::V1,
           protocol_version: ruserf_types::ProtocolVersion::V1,
           delegate_version: ruserf_types::DelegateVersion::V1,
+          secure: false,
+          app_meta: Default::default(),
+          addresses: Arc::from(Vec::new()),
+          app_health: None,
         },
         status_time: 12.into(),
         leave_time: None,
@@ -160,6 +168,10 @@ pub async fn join_intent_reset_leaving<T>(
           memberlist_delegate_version: ruserf_types::MemberlistDelegateVersion::V1,
           protocol_version: ruserf_types::ProtocolVersion::V1,
           delegate_version: ruserf_types::DelegateVersion::V1,
+          secure: false,
+          app_meta: Default::default(),
+          addresses: Arc::from(Vec::new()),
+          app_health: None,
         },
         status_time: 12.into(),
         leave_time: None,
@@ -618,3 +630,222 @@ where
     s.shutdown().await.unwrap();
   }
 }
+
+#[derive(Debug, Clone)]
+struct StallMergeDelegate<A: CheapClone + Send + Sync + 'static> {
+  stalled: SmolStr,
+  release: async_channel::Receiver<()>,
+  _phantom: PhantomData<A>,
+}
+
+impl<A: CheapClone + Send + Sync + 'static> MergeDelegate for StallMergeDelegate<A> {
+  type Error = CancelMergeError;
+
+  type Id = SmolStr;
+
+  type Address = A;
+
+  async fn notify_merge(
+    &self,
+    members: TinyVec<Member<Self::Id, Self::Address>>,
+  ) -> Result<(), Self::Error> {
+    // Stall merging the member until the sender is dropped
+    if members.iter().any(|m| m.node().id() == &self.stalled) {
+      let _ = self.release.recv().await;
+    }
+    Ok(())
+  }
+}
+
+/// Unit test for joining many nodes concurrently
+pub async fn serf_join_many<T>(
+  transport_opts1: T::Options,
+  transport_opts2: T::Options,
+  transport_opts3: T::Options,
+) where
+  T: Transport<Id = SmolStr>,
+{
+  let s1 = Serf::<T>::new(transport_opts1, test_config().with_join_parallelism(1))
+    .await
+    .unwrap();
+  let s3 = Serf::<T>::new(transport_opts3, test_config())
+    .await
+    .unwrap();
+  let (release_tx, release_rx) = async_channel::bounded::<()>(1);
+  let s2 = Serf::<T, _>::with_delegate(
+    transport_opts2,
+    test_config().with_join_timeout(Some(Duration::from_millis(200))),
+    DefaultDelegate::<T>::new().with_merge_delegate(StallMergeDelegate {
+      stalled: s3.local_id().clone(),
+      release: release_rx,
+      _phantom: PhantomData,
+    }),
+  )
+  .await
+  .unwrap();
+
+  let n2 = s2
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  let n3 = s3
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+
+  // The stalled join is not done before the deadline
+  let err = s2
+    .join_many([n3.clone()].into_iter(), false)
+    .await
+    .unwrap_err();
+  assert!(err.joined().is_empty());
+  assert_eq!(err.errors().len(), 1);
+  assert!(matches!(
+    err.errors().get(&n3),
+    Some(Error::Serf(SerfError::JoinTimeout))
+  ));
+  drop(release_tx);
+
+  let joined = s1.join_many([n2, n3].into_iter(), false).await.unwrap();
+  assert_eq!(joined.len(), 2);
+
+  let serfs = [s1, s3];
+  wait_until_num_nodes(3, &serfs).await;
+
+  s2.shutdown().await.unwrap();
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+/// Unit tests for the flap events of a member which keeps failing and rejoining
+pub async fn join_flap<T>(
+  transport_opts: T::Options,
+  addr: <T::Resolver as AddressResolver>::ResolvedAddress,
+) where
+  T: Transport<Id = SmolStr>,
+{
+  let opts = test_config().with_flap_threshold(1);
+  let (event_tx, event_rx) = EventProducer::bounded(8);
+  let s1 = Serf::<T>::with_event_producer(transport_opts, opts, event_tx)
+    .await
+    .unwrap();
+
+  let node = Arc::new(NodeState {
+    id: "test".into(),
+    addr,
+    meta: Meta::empty(),
+    state: memberlist_core::types::State::Alive,
+    protocol_version: ruserf_types::MemberlistProtocolVersion::V1,
+    delegate_version: ruserf_types::MemberlistDelegateVersion::V1,
+  });
+
+  s1.handle_node_join(node.clone()).await;
+  for _ in 0..2 {
+    {
+      let mut members = s1.inner.members.write().await;
+      let m = members.states.get_mut("test").unwrap();
+      m.member.status = MemberStatus::Failed;
+      m.leave_time = Some(Epoch::now());
+    }
+    s1.handle_node_join(node.clone()).await;
+  }
+
+  // The first rejoin is within the threshold, the second one is a flap
+  test_events(
+    event_rx.rx,
+    "test".into(),
+    [
+      CrateEventType::Member(MemberEventType::Join),
+      CrateEventType::Member(MemberEventType::Join),
+      CrateEventType::Member(MemberEventType::Join),
+      CrateEventType::Member(MemberEventType::Flap),
+    ]
+    .into_iter()
+    .collect(),
+  )
+  .await;
+
+  s1.shutdown().await.unwrap();
+}
+
+/// Unit tests for the priority of the intents of the referenced nodes
+pub async fn join_intent_priority<T>(transport_opts: T::Options)
+where
+  T: Transport<Id = SmolStr>,
+{
+  let opts = test_config().with_max_recent_intents(2);
+  let s1 = Serf::<T>::new(transport_opts, opts).await.unwrap();
+
+  for (i, id) in ["a", "b"].into_iter().enumerate() {
+    let j = JoinMessage {
+      ltime: (10 + i as u64).into(),
+      id: id.into(),
+    };
+    assert!(s1.handle_node_join_intent(&j).await, "should rebroadcast");
+    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
+  }
+
+  // A query from "a" makes its intent outlive the newer one of "b"
+  s1.reference_intent(&"a".into()).await;
+  let j = JoinMessage {
+    ltime: 12.into(),
+    id: "c".into(),
+  };
+  assert!(s1.handle_node_join_intent(&j).await, "should rebroadcast");
+
+  {
+    let members = s1.inner.members.read().await;
+    assert!(recent_intent(&members.recent_intents, &"a".into(), MessageType::Join).is_some());
+    assert!(recent_intent(&members.recent_intents, &"b".into(), MessageType::Join).is_none());
+    assert!(recent_intent(&members.recent_intents, &"c".into(), MessageType::Join).is_some());
+  }
+
+  let ages = s1.stats().await.get_intent_ages();
+  assert_eq!(ages.get_buffered(), 2);
+  assert_eq!(ages.get_referenced(), 1);
+  assert!(ages.get_p99_ms() >= 50);
+  assert!(ages.get_p50_ms() <= ages.get_p90_ms());
+
+  s1.shutdown().await.unwrap();
+}
+
+/// Unit test for waiting until the member list is synced by a join
+pub async fn serf_join_sync<T>(
+  transport_opts1: T::Options,
+  transport_opts2: T::Options,
+  transport_opts3: T::Options,
+) where
+  T: Transport<Id = SmolStr>,
+{
+  let s1 = Serf::<T>::new(transport_opts1, test_config())
+    .await
+    .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
+  let s3 = Serf::<T>::new(
+    transport_opts3,
+    test_config().with_join_sync_timeout(Some(Duration::from_secs(5))),
+  )
+  .await
+  .unwrap();
+
+  let node = s2
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  s1.join(node, false).await.unwrap();
+  let mut serfs = vec![s1, s2];
+  wait_until_num_nodes(2, &serfs).await;
+
+  // The members of the joined node are all known once the join returns
+  let node = serfs[0]
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  s3.join(node, false).await.unwrap();
+  assert_eq!(s3.num_members().await, 3);
+  assert!(s3.inner.join_sync.lock().is_empty());
+
+  serfs.push(s3);
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
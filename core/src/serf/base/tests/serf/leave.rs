@@ -54,6 +54,10 @@ pub async fn leave_intent_old_message<T>(
           memberlist_delegate_version: ruserf_types::MemberlistDelegateVersion::V1,
           protocol_version: ruserf_types::ProtocolVersion::V1,
           delegate_version: ruserf_types::DelegateVersion::V1,
+          secure: false,
+          app_meta: Default::default(),
+          addresses: Arc::from(Vec::new()),
+          app_health: None,
         },
         status_time: 12.into(),
         leave_time: None,
@@ -105,6 +109,10 @@ pub async fn leave_intent_newer<T>(
           memberlist_delegate_version: ruserf_types::MemberlistDelegateVersion::V1,
           protocol_version: ruserf_types::ProtocolVersion::V1,
           delegate_version: ruserf_types::DelegateVersion::V1,
+          secure: false,
+          app_meta: Default::default(),
+          addresses: Arc::from(Vec::new()),
+          app_health: None,
         },
         status_time: 12.into(),
         leave_time: None,
@@ -518,3 +526,37 @@ pub async fn serf_leave_snapshot_recovery<T, F>(
     <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
   }
 }
+
+/// Unit test for the leave waiting on the clock of the node
+pub async fn serf_leave_manual_clock<T>(transport_opts: T::Options)
+where
+  T: Transport<Id = SmolStr>,
+{
+  let clock = ManualClock::new();
+  let s = Serf::<T>::new(
+    transport_opts,
+    test_config()
+      .with_leave_propagate_delay(Duration::from_secs(3600))
+      .with_clock(Some(Arc::new(clock.clone()))),
+  )
+  .await
+  .unwrap();
+
+  let mut leave = Box::pin(s.leave().fuse());
+  futures::select! {
+    _ = leave => panic!("leave did not wait for the propagate delay"),
+    _ = <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(500)).fuse() => {}
+  }
+  assert_eq!(s.state(), SerfState::Leaving);
+
+  clock.advance(Duration::from_secs(3600));
+  futures::select! {
+    res = leave => res.unwrap(),
+    _ = <T::Runtime as RuntimeLite>::sleep(Duration::from_secs(5)).fuse() => {
+      panic!("leave did not complete once the clock advanced");
+    }
+  }
+  assert_eq!(s.state(), SerfState::Left);
+
+  s.shutdown().await.unwrap();
+}
@@ -0,0 +1,182 @@
+use crate::memory::{Link, MemoryNetwork, MemoryTransport, MemoryTransportOptions};
+
+use super::*;
+
+/// Unit test for a large cluster spun up over the in-memory transport.
+pub async fn serf_memory_cluster<R>()
+where
+  R: RuntimeLite,
+{
+  const NODES: usize = 100;
+
+  // Timers relaxed enough for a hundred nodes to share a few cores
+  let mut opts = test_config();
+  opts.memberlist_options = opts
+    .memberlist_options
+    .with_gossip_interval(Duration::from_millis(100))
+    .with_probe_interval(Duration::from_secs(1))
+    .with_probe_timeout(Duration::from_millis(500))
+    .with_timeout(Duration::from_secs(5))
+    // Repairs the alive broadcasts dropped from the full gossip queues
+    .with_push_pull_interval(Duration::from_secs(2));
+
+  let network = MemoryNetwork::<SmolStr>::new();
+  network.set_default_link(Link::new().with_latency(Duration::from_micros(200)));
+  let serfs = network
+    .spawn_serfs::<R>(NODES, opts, |idx| {
+      SmolStr::new(format!("serf_memory_cluster_{idx}"))
+    })
+    .await
+    .unwrap();
+  assert_eq!(network.num_nodes(), NODES);
+
+  // Converging takes a while when the cores are shared with other tests
+  let start = Epoch::now();
+  loop {
+    R::sleep(Duration::from_millis(100)).await;
+    let mut converged = 0;
+    for s in serfs.iter() {
+      if s.num_members().await == NODES {
+        converged += 1;
+      }
+    }
+    if converged == NODES {
+      break;
+    }
+
+    if start.elapsed() > Duration::from_secs(30) {
+      panic!("{converged} of {NODES} nodes converged");
+    }
+  }
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+  assert_eq!(network.num_nodes(), 0);
+}
+
+/// Unit test for a partition of the in-memory network, and its healing.
+pub async fn serf_memory_partition<R>()
+where
+  R: RuntimeLite,
+{
+  let network = MemoryNetwork::<SmolStr>::new();
+  let opts = test_config().with_reconnect_timeout(Duration::from_secs(30));
+  let serfs = network
+    .spawn_serfs::<R>(3, opts, |idx| {
+      SmolStr::new(format!("serf_memory_partition_{idx}"))
+    })
+    .await
+    .unwrap();
+  wait_until_num_nodes(3, &serfs).await;
+
+  // The address in use is refused
+  let taken = *serfs[0].advertise_node().address();
+  let err = <MemoryTransport<SmolStr, R> as Transport>::new(
+    MemoryTransportOptions::new("taken".into(), network.clone()).with_address(taken),
+  )
+  .await
+  .unwrap_err();
+  assert!(err.to_string().contains("in use"));
+
+  let addrs = serfs
+    .iter()
+    .map(|s| *s.advertise_node().address())
+    .collect::<Vec<_>>();
+  network.partition(&addrs[..1], &addrs[1..]);
+  wait_until_status::<R>(&serfs[0], 2, MemberStatus::Failed).await;
+
+  network.heal();
+  wait_until_status::<R>(&serfs[0], 3, MemberStatus::Alive).await;
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
+
+async fn wait_until_status<R: RuntimeLite>(
+  s: &Serf<MemoryTransport<SmolStr, R>>,
+  n: usize,
+  status: MemberStatus,
+) {
+  let start = Epoch::now();
+  loop {
+    R::sleep(Duration::from_millis(25)).await;
+    let members = s.members().await;
+    if members.iter().filter(|m| m.status == status).count() == n {
+      return;
+    }
+
+    if start.elapsed() > Duration::from_secs(10) {
+      panic!("expected {n} members with status {status:?}, got {members:?}");
+    }
+  }
+}
+
+/// Unit test for a query targeting the nearest members by their network
+/// coordinates.
+pub async fn serf_memory_query_nearest<R>()
+where
+  R: RuntimeLite,
+{
+  // Probes slow enough to tell the links apart and leave room to the other tests
+  let mut opts = test_config();
+  opts.memberlist_options = opts
+    .memberlist_options
+    .with_gossip_interval(Duration::from_millis(50))
+    .with_probe_interval(Duration::from_millis(100))
+    .with_probe_timeout(Duration::from_millis(200))
+    .with_timeout(Duration::from_millis(500));
+
+  let network = MemoryNetwork::<SmolStr>::new();
+  network.set_default_link(Link::new().with_latency(Duration::from_millis(1)));
+  let serfs = network
+    .spawn_serfs::<R>(3, opts, |idx| {
+      SmolStr::new(format!("serf_memory_query_nearest_{idx}"))
+    })
+    .await
+    .unwrap();
+  wait_until_num_nodes(3, &serfs).await;
+
+  // The third node is far away from the first one
+  let far = Link::new().with_latency(Duration::from_millis(20));
+  let (first, third) = (
+    *serfs[0].advertise_node().address(),
+    *serfs[2].advertise_node().address(),
+  );
+  network.set_link(first, third, far);
+  network.set_link(third, first, far);
+
+  let start = Epoch::now();
+  loop {
+    R::sleep(Duration::from_millis(50)).await;
+    if serfs[0].nearest_members(1).await.unwrap().as_slice() == [serfs[1].local_id().clone()] {
+      break;
+    }
+
+    if start.elapsed() > Duration::from_secs(10) {
+      panic!("the coordinates did not converge");
+    }
+  }
+
+  let params = serfs[0]
+    .default_query_param()
+    .await
+    .with_request_ack(true)
+    .nearest(1);
+  assert_eq!(params.nearest_count(), Some(1));
+  let resp = serfs[0]
+    .query("nearest", Bytes::new(), Some(params))
+    .await
+    .unwrap();
+  let acks = resp.ack_rx().unwrap();
+  let mut acked = Vec::new();
+  while let Ok(node) = acks.recv().await {
+    acked.push(node.id().clone());
+  }
+  assert_eq!(acked, [serfs[1].local_id().clone()]);
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
@@ -17,10 +17,18 @@ where
     members: s1.inner.members.clone(),
     event_tx: s1.inner.event_tx.clone(),
     shutdown_rx: s1.inner.shutdown_rx.clone(),
-    reap_interval: s1.inner.opts.reap_interval,
+    reloadable: s1.inner.reloadable.clone(),
     reconnect_timeout: s1.inner.opts.reconnect_timeout,
     recent_intent_timeout: s1.inner.opts.recent_intent_timeout,
     tombstone_timeout: s1.inner.opts.tombstone_timeout,
+    max_left_members: s1.inner.opts.max_left_members,
+    tombstone_eviction: s1.inner.opts.tombstone_eviction,
+    max_reap_vetoes: s1.inner.opts.max_reap_vetoes,
+    timer: s1.inner.timer.clone(),
+    scheduler: None,
+    heartbeat: s1.inner.tasks.register("reaper"),
+    #[cfg(feature = "metrics")]
+    metric_labels: s1.inner.opts.memberlist_options.metric_labels().clone(),
   };
   <T::Runtime as RuntimeLite>::spawn_detach(async move {
     reap.run().await;
@@ -44,90 +52,144 @@ pub async fn serf_reap_handler<T>(
 ) where
   T: Transport<Id = SmolStr>,
 {
-  let s = Serf::<T>::new(
+  let clock = ManualClock::new();
+  let (event_tx, event_rx) = EventProducer::bounded(64);
+  let s = Serf::<T>::with_event_producer(
     opts,
     test_config()
-      .with_reap_interval(Duration::from_nanos(1))
+      .with_reap_interval(Duration::from_secs(1))
       .with_tombstone_timeout(Duration::from_secs(6))
-      .with_recent_intent_timeout(Duration::from_secs(7)),
+      .with_recent_intent_timeout(Duration::from_secs(7))
+      .with_clock(Some(Arc::new(clock.clone()))),
+    event_tx,
   )
   .await
   .unwrap();
 
   {
     let mut members = s.inner.members.write().await;
+    let now = s.inner.timer.now();
     let n = Node::new("foo".into(), addr.clone());
     members.left_members.push(MemberState {
       member: Member::new(n.clone(), Default::default(), MemberStatus::None),
       status_time: 0.into(),
-      leave_time: Some(Epoch::now()),
+      leave_time: Some(now),
     });
     members.left_members.push(MemberState {
       member: Member::new(n.clone(), Default::default(), MemberStatus::None),
       status_time: 0.into(),
-      leave_time: Some(Epoch::now() - Duration::from_secs(5)),
+      leave_time: Some(now - Duration::from_secs(5)),
     });
     members.left_members.push(MemberState {
       member: Member::new(n.clone(), Default::default(), MemberStatus::None),
       status_time: 0.into(),
-      leave_time: Some(Epoch::now() - Duration::from_secs(10)),
+      leave_time: Some(now - Duration::from_secs(10)),
     });
     upsert_intent::<SmolStr>(
       &mut members.recent_intents,
       &"alice".into(),
       MessageType::Join,
       1.into(),
-      Epoch::now,
+      || now,
     );
     upsert_intent::<SmolStr>(
       &mut members.recent_intents,
       &"bob".into(),
       MessageType::Join,
       2.into(),
-      || Epoch::now() - Duration::from_secs(10),
+      || now - Duration::from_secs(10),
     );
     upsert_intent::<SmolStr>(
       &mut members.recent_intents,
       &"carol".into(),
       MessageType::Leave,
       1.into(),
-      Epoch::now,
+      || now,
     );
     upsert_intent::<SmolStr>(
       &mut members.recent_intents,
       &"doug".into(),
       MessageType::Leave,
       2.into(),
-      || Epoch::now() - Duration::from_secs(10),
+      || now - Duration::from_secs(10),
     );
   }
 
-  let s1 = s.clone();
-  <T::Runtime as RuntimeLite>::spawn_detach(async move {
-    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(1)).await;
-    s1.shutdown().await.unwrap();
-  });
+  // Run a single reap round, once the reaper and the reconnector sleep on the clock
+  wait_until_sleepers::<T::Runtime>(&clock, 2).await;
+  clock.advance(Duration::from_secs(1));
+  loop {
+    let CrateEvent::Member(e) = event_rx.rx.recv().await.unwrap() else {
+      continue;
+    };
+    if e.ty == MemberEventType::Reap {
+      break;
+    }
+  }
 
-  let reap = Reaper {
-    coord_core: s.inner.coord_core.clone(),
-    memberlist: s.inner.memberlist.clone(),
-    members: s.inner.members.clone(),
-    event_tx: s.inner.event_tx.clone(),
-    shutdown_rx: s.inner.shutdown_rx.clone(),
-    reap_interval: s.inner.opts.reap_interval,
-    reconnect_timeout: s.inner.opts.reconnect_timeout,
-    recent_intent_timeout: s.inner.opts.recent_intent_timeout,
-    tombstone_timeout: s.inner.opts.tombstone_timeout,
-  };
-  reap.run().await;
+  {
+    let members = s.inner.members.read().await;
+    assert_eq!(members.left_members.len(), 2);
+
+    recent_intent(&members.recent_intents, &"alice".into(), MessageType::Join).unwrap();
+    assert!(recent_intent(&members.recent_intents, &"bob".into(), MessageType::Join).is_none());
+    recent_intent(&members.recent_intents, &"carol".into(), MessageType::Leave).unwrap();
+    assert!(recent_intent(&members.recent_intents, &"doug".into(), MessageType::Leave).is_none());
+  }
+
+  s.shutdown().await.unwrap();
+}
+
+/// Unit test for the reap rounds driven by a maintenance scheduler
+pub async fn serf_reap_manual_scheduler<T>(
+  opts: T::Options,
+  addr: <T::Resolver as AddressResolver>::ResolvedAddress,
+) where
+  T: Transport<Id = SmolStr>,
+{
+  let scheduler = ManualScheduler::new();
+  let (event_tx, event_rx) = EventProducer::bounded(64);
+  let s = Serf::<T>::with_event_producer(
+    opts,
+    test_config()
+      .with_reap_interval(Duration::from_millis(1))
+      .with_tombstone_timeout(Duration::from_secs(6))
+      .with_maintenance_scheduler(Some(Arc::new(scheduler.clone()))),
+    event_tx,
+  )
+  .await
+  .unwrap();
+
+  {
+    let mut members = s.inner.members.write().await;
+    let now = s.inner.timer.now();
+    members.left_members.push(MemberState {
+      member: Member::new(
+        Node::new("foo".into(), addr.clone()),
+        Default::default(),
+        MemberStatus::None,
+      ),
+      status_time: 0.into(),
+      leave_time: Some(now - Duration::from_secs(10)),
+    });
+  }
+
+  // The reap interval is ignored
+  <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(100)).await;
+  assert_eq!(s.inner.members.read().await.left_members.len(), 1);
 
-  let members = s.inner.members.read().await;
-  assert_eq!(members.left_members.len(), 2);
+  scheduler.trigger(MaintenanceTask::Reap);
+  loop {
+    let CrateEvent::Member(e) = event_rx.rx.recv().await.unwrap() else {
+      continue;
+    };
+    if e.ty == MemberEventType::Reap {
+      break;
+    }
+  }
+  assert!(s.inner.members.read().await.left_members.is_empty());
 
-  recent_intent(&members.recent_intents, &"alice".into(), MessageType::Join).unwrap();
-  assert!(recent_intent(&members.recent_intents, &"bob".into(), MessageType::Join).is_none());
-  recent_intent(&members.recent_intents, &"carol".into(), MessageType::Leave).unwrap();
-  assert!(recent_intent(&members.recent_intents, &"doug".into(), MessageType::Leave).is_none());
+  s.shutdown().await.unwrap();
 }
 
 /// Unit test for reap
@@ -165,9 +227,168 @@ where
       None,
       None,
       Duration::from_secs(6),
+      Epoch::now(),
+      &mut ReapVetoes::new(0),
+    )
+    .await;
+  }
+
+  s.shutdown().await.unwrap();
+}
+
+/// Unit test for evicting the left members above the cap
+pub async fn serf_reap_evict_left<T>(
+  opts: T::Options,
+  addr: <T::Resolver as AddressResolver>::ResolvedAddress,
+) where
+  T: Transport<Id = SmolStr>,
+{
+  let s = Serf::<T>::new(opts, test_config()).await.unwrap();
+
+  {
+    let mut members = s.inner.members.write().await;
+    for (id, age) in [("foo", 5), ("bar", 10), ("baz", 0)] {
+      let ms = MemberState {
+        member: Member::new(
+          Node::new(id.into(), addr.clone()),
+          Default::default(),
+          MemberStatus::Left,
+        ),
+        status_time: 0.into(),
+        leave_time: Some(Epoch::now() - Duration::from_secs(age)),
+      };
+      members.states.insert(id.into(), ms.clone());
+      members.left_members.push(ms);
+    }
+
+    let (tx, rx) = async_channel::bounded(64);
+
+    // Without a cap, nothing is evicted
+    Reaper::<T, DefaultDelegate<T>>::evict_left(
+      s.local_id(),
+      &mut members,
+      &tx,
+      None,
+      0,
+      TombstoneEviction::Oldest,
     )
     .await;
+    assert_eq!(members.left_members.len(), 3);
+
+    Reaper::<T, DefaultDelegate<T>>::evict_left(
+      s.local_id(),
+      &mut members,
+      &tx,
+      None,
+      2,
+      TombstoneEviction::Oldest,
+    )
+    .await;
+    assert_eq!(members.left_members.len(), 2);
+    assert!(!members.states.contains_key("bar"));
+    assert_eq!(rx.len(), 1);
+
+    Reaper::<T, DefaultDelegate<T>>::evict_left(
+      s.local_id(),
+      &mut members,
+      &tx,
+      None,
+      1,
+      TombstoneEviction::Newest,
+    )
+    .await;
+    assert_eq!(members.left_members.len(), 1);
+    assert_eq!(members.left_members[0].member.node().id(), "foo");
+    assert!(!members.states.contains_key("baz"));
+    assert_eq!(rx.len(), 2);
+  }
+
+  s.shutdown().await.unwrap();
+}
+
+#[derive(Clone)]
+struct ReapDelay<A> {
+  reasons: Arc<parking_lot::Mutex<Vec<ReapReason>>>,
+  _marker: std::marker::PhantomData<A>,
+}
+
+impl<A> crate::delegate::ReconnectDelegate for ReapDelay<A>
+where
+  A: CheapClone + Send + Sync + 'static,
+{
+  type Id = SmolStr;
+
+  type Address = A;
+
+  fn reconnect_timeout(
+    &self,
+    _member: &Member<Self::Id, Self::Address>,
+    timeout: Duration,
+  ) -> Duration {
+    timeout
+  }
+
+  async fn on_reap(
+    &self,
+    _member: &Member<Self::Id, Self::Address>,
+    reason: ReapReason,
+  ) -> ReapDecision {
+    self.reasons.lock().push(reason);
+    ReapDecision::Delay
   }
+}
+
+/// Unit test for delaying the reap of a member from the reconnect delegate
+pub async fn serf_reap_delay<T>(
+  opts: T::Options,
+  addr: <T::Resolver as AddressResolver>::ResolvedAddress,
+) where
+  T: Transport<Id = SmolStr>,
+{
+  let s = Serf::<T>::new(opts, test_config()).await.unwrap();
+  let reasons = Arc::new(parking_lot::Mutex::new(Vec::new()));
+  let delegate = DefaultDelegate::<T>::new().with_reconnect_delegate(ReapDelay {
+    reasons: reasons.clone(),
+    _marker: std::marker::PhantomData,
+  });
+
+  {
+    let mut members = s.inner.members.write().await;
+    let ms = MemberState {
+      member: Member::new(
+        Node::new("foo".into(), addr.clone()),
+        Default::default(),
+        MemberStatus::Left,
+      ),
+      status_time: 0.into(),
+      leave_time: Some(Epoch::now() - Duration::from_secs(10)),
+    };
+    members.states.insert("foo".into(), ms.clone());
+    members.left_members.push(ms);
+
+    let (tx, rx) = async_channel::bounded(64);
+    let mut vetoes = ReapVetoes::new(2);
+
+    // The member is kept for as many reap intervals as the vetoes allow
+    for kept in [true, true, false] {
+      Reaper::<T, _>::reap_left(
+        s.local_id(),
+        &mut members,
+        &tx,
+        Some(&delegate),
+        None,
+        Duration::from_secs(6),
+        Epoch::now(),
+        &mut vetoes,
+      )
+      .await;
+      assert_eq!(members.states.contains_key("foo"), kept);
+    }
+    assert!(members.left_members.is_empty());
+    assert_eq!(rx.len(), 1);
+  }
+
+  assert_eq!(reasons.lock().as_slice(), &[ReapReason::Left; 3]);
 
   s.shutdown().await.unwrap();
 }
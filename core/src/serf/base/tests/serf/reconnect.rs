@@ -280,3 +280,83 @@ pub async fn serf_per_node_reconnect_timeout<T>(
   );
   serfs[0].shutdown().await.unwrap();
 }
+
+#[derive(Clone)]
+struct ReconnectAddress<A> {
+  address: Arc<parking_lot::Mutex<Option<A>>>,
+}
+
+impl<A> ReconnectDelegate for ReconnectAddress<A>
+where
+  A: CheapClone + Send + Sync + 'static,
+{
+  type Id = SmolStr;
+
+  type Address = A;
+
+  fn reconnect_timeout(
+    &self,
+    _member: &Member<Self::Id, Self::Address>,
+    timeout: Duration,
+  ) -> Duration {
+    timeout
+  }
+
+  async fn reconnect_address(&self, _member: &Member<Self::Id, Self::Address>) -> Option<A> {
+    self.address.lock().clone()
+  }
+}
+
+/// Unit test for serf reconnecting to the address returned by the reconnect delegate
+pub async fn serf_reconnect_address_override<T>(
+  transport_opts1: T::Options,
+  transport_opts2: T::Options,
+  transport_opts3: T::Options,
+) where
+  T: Transport<Id = SmolStr>,
+{
+  let ra = ReconnectAddress {
+    address: Arc::new(parking_lot::Mutex::new(None)),
+  };
+
+  let s1 = Serf::<T, _>::with_delegate(
+    transport_opts1,
+    test_config().with_reconnect_timeout(Duration::from_secs(30)),
+    DefaultDelegate::<T>::new().with_reconnect_delegate(ra.clone()),
+  )
+  .await
+  .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
+
+  let node = s2
+    .advertise_node()
+    .map_address(MaybeResolvedAddress::resolved);
+  s1.join(node, false).await.unwrap();
+  wait_until_num_nodes(2, std::slice::from_ref(&s1)).await;
+
+  s2.shutdown().await.unwrap();
+  drop(s2);
+
+  // The failed node comes back somewhere else, and never joins by itself
+  let s3 = Serf::<T>::new(transport_opts3, test_config())
+    .await
+    .unwrap();
+  *ra.address.lock() = Some(s3.advertise_node().address().cheap_clone());
+
+  let start = Epoch::now();
+  loop {
+    <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(25)).await;
+    if s3.num_members().await == 2 {
+      break;
+    }
+
+    if start.elapsed() > Duration::from_secs(7) {
+      panic!("s1 did not reconnect at the overridden address");
+    }
+  }
+
+  s1.shutdown().await.unwrap();
+  s3.shutdown().await.unwrap();
+}
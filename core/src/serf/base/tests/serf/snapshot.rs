@@ -1,7 +1,34 @@
 use std::io::Read;
 
+use crate::{
+  delegate::{SnapshotCompaction, SnapshotDelegate, SnapshotReplay},
+  snapshot::{SnapshotError, SnapshotFormat},
+};
+
 use super::*;
 
+/// Records the snapshot lifecycle.
+#[derive(Default)]
+struct SnapshotRecorder {
+  compactions: parking_lot::Mutex<Vec<SnapshotCompaction>>,
+  replays: parking_lot::Mutex<Vec<SnapshotReplay>>,
+  errors: AtomicUsize,
+}
+
+impl SnapshotDelegate for SnapshotRecorder {
+  fn on_snapshot_compact(&self, compaction: SnapshotCompaction) {
+    self.compactions.lock().push(compaction);
+  }
+
+  fn on_snapshot_replay_complete(&self, replay: &SnapshotReplay) {
+    self.replays.lock().push(*replay);
+  }
+
+  fn on_snapshot_error(&self, _err: &SnapshotError) {
+    self.errors.fetch_add(1, Ordering::SeqCst);
+  }
+}
+
 /// Unit test for the snapshoter.
 pub async fn snapshoter<T>(
   transport_opts: T::Options,
@@ -16,14 +43,19 @@ pub async fn snapshoter<T>(
   let clock = LamportClock::new();
   let (out_tx, out_rx) = async_channel::bounded(64);
   let (shutdown_tx, shutdown_rx) = async_channel::bounded(1);
-  let res = open_and_replay_snapshot::<_, _, DefaultDelegate<T>, _>(&p, false).unwrap();
+  let res = open_and_replay_snapshot::<_, _, DefaultDelegate<T>, _>(&p, false, None).unwrap();
   let (event_tx, _, handle) = Snapshot::<T, DefaultDelegate<T>>::from_replay_result(
     res,
     SNAPSHOT_SIZE_LIMIT,
+    512,
     false,
     clock.clone(),
+    LamportClock::new(),
+    LamportClock::new(),
+    Duration::from_millis(500),
     out_tx,
     shutdown_rx.clone(),
+    None,
     #[cfg(feature = "metrics")]
     Default::default(),
   )
@@ -41,12 +73,12 @@ pub async fn snapshoter<T>(
     name: "bar".into(),
     payload: Default::default(),
     ctx: Arc::new(QueryContext {
-      query_timeout: Duration::default(),
       span: Mutex::new(None),
       this: s,
     }),
     id: 0,
     from: Node::new("baz".into(), addr.clone()),
+    respond_to: Node::new("baz".into(), addr.clone()),
     relay_factor: 0,
   };
   event_tx.send(qe.clone().into()).await.unwrap();
@@ -162,7 +194,7 @@ pub async fn snapshoter<T>(
 
   // Open the snapshoter
   let (shutdown_tx, shutdown_rx) = async_channel::bounded(1);
-  let res = open_and_replay_snapshot::<_, _, DefaultDelegate<T>, _>(&p, false).unwrap();
+  let res = open_and_replay_snapshot::<_, _, DefaultDelegate<T>, _>(&p, false, None).unwrap();
 
   assert_eq!(res.last_clock, 100.into());
   assert_eq!(res.last_event_clock, 42.into());
@@ -172,10 +204,15 @@ pub async fn snapshoter<T>(
   let (_event_tx, alive_nodes, handle) = Snapshot::<T, DefaultDelegate<T>>::from_replay_result(
     res,
     SNAPSHOT_SIZE_LIMIT,
+    512,
     false,
     clock.clone(),
+    LamportClock::new(),
+    LamportClock::new(),
+    Duration::from_millis(500),
     out_tx,
     shutdown_rx.clone(),
+    None,
     #[cfg(feature = "metrics")]
     Default::default(),
   )
@@ -193,16 +230,21 @@ pub async fn snapshoter<T>(
   // Open the snapshoter, make sure nothing dies reading with coordinates
   // disabled.
   let (shutdown_tx, shutdown_rx) = async_channel::bounded(1);
-  let res = open_and_replay_snapshot::<_, _, DefaultDelegate<T>, _>(&p, false).unwrap();
+  let res = open_and_replay_snapshot::<_, _, DefaultDelegate<T>, _>(&p, false, None).unwrap();
 
   let (out_tx, _out_rx) = async_channel::bounded(64);
   let (_event_tx, _, handle) = Snapshot::<T, DefaultDelegate<T>>::from_replay_result(
     res,
     SNAPSHOT_SIZE_LIMIT,
+    512,
     false,
     clock.clone(),
+    LamportClock::new(),
+    LamportClock::new(),
+    Duration::from_millis(500),
     out_tx,
     shutdown_rx.clone(),
+    None,
     #[cfg(feature = "metrics")]
     Default::default(),
   )
@@ -222,19 +264,32 @@ pub async fn snapshoter_force_compact<T>(
   let p = dir.path().join("snapshoter_force_compact");
   let s = Serf::<T>::new(transport_opts, test_config()).await.unwrap();
 
+  // Start from an unframed snapshot holding a single clock record
+  let mut v1 = vec![2];
+  v1.extend_from_slice(&7u64.to_le_bytes());
+  std::fs::write(&p, v1).unwrap();
+
   let clock = LamportClock::new();
   let (shutdown_tx, shutdown_rx) = async_channel::bounded(1);
 
   // Create a very low limit
-  let res = open_and_replay_snapshot::<_, _, DefaultDelegate<T>, _>(&p, false).unwrap();
+  let res = open_and_replay_snapshot::<_, _, DefaultDelegate<T>, _>(&p, false, None).unwrap();
+  assert_eq!(res.format, SnapshotFormat::V1);
+  assert_eq!(res.last_clock, 7.into());
   let (out_tx, _out_rx) = async_channel::unbounded();
+  let recorder = Arc::new(SnapshotRecorder::default());
   let (event_tx, _, handle) = Snapshot::<T, DefaultDelegate<T>>::from_replay_result(
     res,
     1024,
+    512,
     false,
     clock.clone(),
+    LamportClock::new(),
+    LamportClock::new(),
+    Duration::from_millis(500),
     out_tx,
     shutdown_rx.clone(),
+    Some(recorder.clone()),
     #[cfg(feature = "metrics")]
     Default::default(),
   )
@@ -253,12 +308,12 @@ pub async fn snapshoter_force_compact<T>(
       name: "bar".into(),
       payload: Default::default(),
       ctx: Arc::new(QueryContext {
-        query_timeout: Duration::default(),
         span: Mutex::new(None),
         this: s.clone(),
       }),
       id: 0,
       from: Node::new("baz".into(), addr.clone()),
+      respond_to: Node::new("baz".into(), addr.clone()),
       relay_factor: 0,
     };
     event_tx.send(qe.clone().into()).await.unwrap();
@@ -273,11 +328,23 @@ pub async fn snapshoter_force_compact<T>(
   shutdown_tx.close();
   handle.wait().await;
 
+  // Every compaction is reported when it starts and when it is done
+  let compactions = recorder.compactions.lock().clone();
+  assert!(!compactions.is_empty());
+  for pair in compactions.chunks(2) {
+    assert_eq!(pair[0], SnapshotCompaction::Started);
+    assert!(matches!(pair[1], SnapshotCompaction::Finished(size) if size > 0));
+  }
+  assert_eq!(recorder.errors.load(Ordering::SeqCst), 0);
+
   // Open the snapshoter
-  let res = open_and_replay_snapshot::<_, _, DefaultDelegate<T>, _>(&p, false).unwrap();
+  let res = open_and_replay_snapshot::<_, _, DefaultDelegate<T>, _>(&p, false, None).unwrap();
 
   assert_eq!(res.last_event_clock, 1023.into());
   assert_eq!(res.last_query_clock, 1023.into());
+  // Migrated by the compaction
+  assert_eq!(res.format, SnapshotFormat::V2);
+  assert_eq!(res.last_clock, 7.into());
 }
 
 /// Unit test for the snapshoter leave
@@ -293,15 +360,20 @@ pub async fn snapshoter_leave<T>(
 
   let clock = LamportClock::new();
   let (shutdown_tx, shutdown_rx) = async_channel::bounded(1);
-  let res = open_and_replay_snapshot::<_, _, DefaultDelegate<T>, _>(&p, false).unwrap();
+  let res = open_and_replay_snapshot::<_, _, DefaultDelegate<T>, _>(&p, false, None).unwrap();
   let (out_tx, _out_rx) = async_channel::unbounded();
   let (event_tx, _, handle) = Snapshot::<T, DefaultDelegate<T>>::from_replay_result(
     res,
     SNAPSHOT_SIZE_LIMIT,
+    512,
     false,
     clock.clone(),
+    LamportClock::new(),
+    LamportClock::new(),
+    Duration::from_millis(500),
     out_tx,
     shutdown_rx.clone(),
+    None,
     #[cfg(feature = "metrics")]
     Default::default(),
   )
@@ -319,12 +391,12 @@ pub async fn snapshoter_leave<T>(
     name: "uptime".into(),
     payload: Default::default(),
     ctx: Arc::new(QueryContext {
-      query_timeout: Duration::default(),
       span: Mutex::new(None),
       this: s.clone(),
     }),
     id: 0,
     from: Node::new("baz".into(), addr.clone()),
+    respond_to: Node::new("baz".into(), addr.clone()),
     relay_factor: 0,
   };
   event_tx.send(qe.clone().into()).await.unwrap();
@@ -359,7 +431,7 @@ pub async fn snapshoter_leave<T>(
 
   // Open the snapshoter
   let (shutdown_tx, shutdown_rx) = async_channel::bounded(1);
-  let res = open_and_replay_snapshot::<_, _, DefaultDelegate<T>, _>(&p, false).unwrap();
+  let res = open_and_replay_snapshot::<_, _, DefaultDelegate<T>, _>(&p, false, None).unwrap();
   assert!(res.last_clock == 0.into(), "last_clock: {}", res.last_clock);
   assert!(
     res.last_event_clock == 0.into(),
@@ -375,10 +447,15 @@ pub async fn snapshoter_leave<T>(
   let (_, alive_nodes, _) = Snapshot::<T, DefaultDelegate<T>>::from_replay_result(
     res,
     SNAPSHOT_SIZE_LIMIT,
+    512,
     false,
     clock.clone(),
+    LamportClock::new(),
+    LamportClock::new(),
+    Duration::from_millis(500),
     out_tx,
     shutdown_rx.clone(),
+    None,
     #[cfg(feature = "metrics")]
     Default::default(),
   )
@@ -404,15 +481,20 @@ pub async fn snapshoter_leave_rejoin<T>(
 
   let clock = LamportClock::new();
   let (shutdown_tx, shutdown_rx) = async_channel::bounded(1);
-  let res = open_and_replay_snapshot::<_, _, DefaultDelegate<T>, _>(&p, true).unwrap();
+  let res = open_and_replay_snapshot::<_, _, DefaultDelegate<T>, _>(&p, true, None).unwrap();
   let (out_tx, _out_rx) = async_channel::unbounded();
   let (event_tx, _, handle) = Snapshot::<T, DefaultDelegate<T>>::from_replay_result(
     res,
     SNAPSHOT_SIZE_LIMIT,
+    512,
     true,
     clock.clone(),
+    LamportClock::new(),
+    LamportClock::new(),
+    Duration::from_millis(500),
     out_tx,
     shutdown_rx.clone(),
+    None,
     #[cfg(feature = "metrics")]
     Default::default(),
   )
@@ -430,12 +512,12 @@ pub async fn snapshoter_leave_rejoin<T>(
     name: "uptime".into(),
     payload: Default::default(),
     ctx: Arc::new(QueryContext {
-      query_timeout: Duration::default(),
       span: Mutex::new(None),
       this: s.clone(),
     }),
     id: 0,
     from: Node::new("baz".into(), addr.clone()),
+    respond_to: Node::new("baz".into(), addr.clone()),
     relay_factor: 0,
   };
   event_tx.send(qe.clone().into()).await.unwrap();
@@ -474,7 +556,7 @@ pub async fn snapshoter_leave_rejoin<T>(
 
   // Open the snapshoter
   let (shutdown_tx, shutdown_rx) = async_channel::bounded(1);
-  let res = open_and_replay_snapshot::<_, _, DefaultDelegate<T>, _>(&p, true).unwrap();
+  let res = open_and_replay_snapshot::<_, _, DefaultDelegate<T>, _>(&p, true, None).unwrap();
   assert!(res.last_clock == 100.into());
   assert!(res.last_event_clock == 42.into());
   assert!(res.last_query_clock == 50.into());
@@ -482,10 +564,15 @@ pub async fn snapshoter_leave_rejoin<T>(
   let (_, alive_nodes, handle) = Snapshot::<T, DefaultDelegate<T>>::from_replay_result(
     res,
     SNAPSHOT_SIZE_LIMIT,
+    512,
     false,
     clock.clone(),
+    LamportClock::new(),
+    LamportClock::new(),
+    Duration::from_millis(500),
     out_tx,
     shutdown_rx.clone(),
+    None,
     #[cfg(feature = "metrics")]
     Default::default(),
   )
@@ -638,14 +725,19 @@ async fn test_snapshoter_slow_disk_not_blocking_event_tx() {
   let clock = LamportClock::new();
   let (shutdown_tx, shutdown_rx) = async_channel::bounded(1);
   let (out_tx, out_rx) = async_channel::bounded(1024);
-  let res = open_and_replay_snapshot::<_, _, Delegate, _>(&p, true).unwrap();
+  let res = open_and_replay_snapshot::<_, _, Delegate, _>(&p, true, None).unwrap();
   let (event_tx, _, handle) = Snapshot::<Transport, Delegate>::from_replay_result(
     res,
     SNAPSHOT_SIZE_LIMIT,
+    512,
     true,
     clock.clone(),
+    LamportClock::new(),
+    LamportClock::new(),
+    Duration::from_millis(500),
     out_tx,
     shutdown_rx.clone(),
+    None,
     #[cfg(feature = "metrics")]
     Default::default(),
   )
@@ -753,14 +845,19 @@ async fn test_snapshoter_slow_disk_not_blocking_memberlist() {
   let clock = LamportClock::new();
   let (shutdown_tx, shutdown_rx) = async_channel::bounded(1);
   let (out_tx, _out_rx) = async_channel::bounded(1);
-  let res = open_and_replay_snapshot::<_, _, Delegate, _>(&p, true).unwrap();
+  let res = open_and_replay_snapshot::<_, _, Delegate, _>(&p, true, None).unwrap();
   let (event_tx, _, handle) = Snapshot::<Transport, Delegate>::from_replay_result(
     res,
     SNAPSHOT_SIZE_LIMIT,
+    512,
     true,
     clock.clone(),
+    LamportClock::new(),
+    LamportClock::new(),
+    Duration::from_millis(500),
     out_tx,
     shutdown_rx.clone(),
+    None,
     #[cfg(feature = "metrics")]
     Default::default(),
   )
@@ -806,3 +903,230 @@ async fn test_snapshoter_slow_disk_not_blocking_memberlist() {
   shutdown_tx.close();
   handle.wait().await;
 }
+
+/// Unit test for the encrypted snapshot surviving a key rotation.
+#[cfg(feature = "encryption")]
+pub async fn serf_snapshot_encrypted<T>(
+  get_transport_opts: impl FnOnce(memberlist_core::types::SecretKey) -> T::Options,
+) where
+  T: Transport,
+{
+  use memberlist_core::types::{SecretKey, SecretKeyring};
+
+  let old_key = SecretKey::Aes128([1; 16]);
+  let new_key = SecretKey::Aes256([2; 32]);
+
+  let td = tempfile::tempdir().unwrap();
+  let snap_path = td.path().join("serf_snapshot_encrypted");
+  let s = Serf::<T>::new(
+    get_transport_opts(old_key),
+    test_config()
+      .with_snapshot_path(Some(snap_path.clone()))
+      .with_snapshot_encryption(true),
+  )
+  .await
+  .unwrap();
+  let id = s.local_id().to_string();
+
+  // Rotate the primary key, and wait for the snapshot to be re-encrypted
+  // before removing the old key
+  let manager = s.key_manager();
+  manager.install_key(new_key, None).await.unwrap();
+  manager.use_key(new_key, None).await.unwrap();
+  <T::Runtime as RuntimeLite>::sleep(Duration::from_secs(1)).await;
+  manager.remove_key(old_key, None).await.unwrap();
+  s.shutdown().await.unwrap();
+
+  let mut raw = Vec::new();
+  std::fs::File::open(&snap_path)
+    .unwrap()
+    .read_to_end(&mut raw)
+    .unwrap();
+  assert!(!raw.windows(id.len()).any(|w| w == id.as_bytes()));
+
+  let snap_path = &snap_path;
+  let replay = |key| async move {
+    let cipher = SnapshotCipher::new(SecretKeyring::new(key)).await;
+    open_and_replay_encrypted_snapshot::<_, _, DefaultDelegate<T>, _>(
+      snap_path, false, cipher, None,
+    )
+  };
+
+  let res = replay(new_key).await.unwrap();
+  assert!(res.alive_nodes.iter().any(|n| n.id().to_string() == id));
+  drop(res);
+  assert!(replay(old_key).await.is_err());
+}
+
+/// Unit test for the user events deduplicated across restarts
+pub async fn serf_snapshot_event_dedup<T>(transport_opts: T::Options)
+where
+  T: Transport,
+  T::Options: Clone,
+{
+  let td = tempfile::tempdir().unwrap();
+  let snap_path = td.path().join("serf_snapshot_event_dedup");
+  let opts = test_config().with_snapshot_path(Some(snap_path.clone()));
+
+  let event = |name: &'static str, payload: &'static [u8]| {
+    UserEventMessage::default()
+      .with_ltime(5.into())
+      .with_name(name.into())
+      .with_payload(Bytes::from_static(payload))
+  };
+
+  let s = Serf::<T>::new(transport_opts.clone(), opts.clone())
+    .await
+    .unwrap();
+  assert!(s.handle_user_event(event("foo", b"a")).await);
+  <T::Runtime as RuntimeLite>::sleep(Duration::from_secs(1)).await;
+  s.shutdown().await.unwrap();
+  drop(s);
+
+  let s = Serf::<T>::new(transport_opts, opts).await.unwrap();
+  // Seen before the restart
+  assert!(!s.handle_user_event(event("foo", b"a")).await);
+  // Not seen yet, even though it has the same lamport time
+  assert!(s.handle_user_event(event("foo", b"b")).await);
+  assert!(s.handle_user_event(event("bar", b"a")).await);
+  assert!(!s.handle_user_event(event("bar", b"a")).await);
+  s.shutdown().await.unwrap();
+}
+
+/// Unit test for the clocks checkpointed to the snapshot and bumped on restart
+pub async fn serf_snapshot_clock_checkpoint<T>(transport_opts: T::Options)
+where
+  T: Transport,
+  T::Options: Clone,
+{
+  let td = tempfile::tempdir().unwrap();
+  let snap_path = td.path().join("serf_snapshot_clock_checkpoint");
+  let opts = test_config()
+    .with_snapshot_path(Some(snap_path.clone()))
+    .with_snapshot_clock_interval(Duration::from_millis(50))
+    .with_clock_skew_recovery(10);
+
+  let s = Serf::<T>::new(transport_opts.clone(), opts.clone())
+    .await
+    .unwrap();
+  // Witnessed without delivering any event
+  s.inner.event_clock.witness(100.into());
+  s.inner.query_clock.witness(200.into());
+  <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(200)).await;
+  s.shutdown().await.unwrap();
+  drop(s);
+
+  let rs =
+    open_and_replay_snapshot::<_, _, DefaultDelegate<T>, _>(&snap_path, false, None).unwrap();
+  assert_eq!(rs.last_event_clock, 100.into());
+  assert_eq!(rs.last_query_clock, 200.into());
+  drop(rs);
+
+  let s = Serf::<T>::new(transport_opts, opts).await.unwrap();
+  assert_eq!(s.inner.event_clock.time(), 111.into());
+  assert_eq!(s.inner.query_clock.time(), 211.into());
+  s.shutdown().await.unwrap();
+}
+
+/// Unit test for the snapshot delegate notified of the replays and the errors
+pub async fn serf_snapshot_delegate<T>(transport_opts: T::Options)
+where
+  T: Transport,
+  T::Options: Clone,
+{
+  let td = tempfile::tempdir().unwrap();
+  let snap_path = td.path().join("serf_snapshot_delegate");
+  let recorder = Arc::new(SnapshotRecorder::default());
+  let opts = test_config()
+    .with_snapshot_path(Some(snap_path.clone()))
+    .with_snapshot_clock_interval(Duration::from_millis(50))
+    .with_snapshot_delegate(Some(recorder.clone()));
+
+  let s = Serf::<T>::new(transport_opts.clone(), opts.clone())
+    .await
+    .unwrap();
+  s.inner.event_clock.witness(100.into());
+  <T::Runtime as RuntimeLite>::sleep(Duration::from_millis(200)).await;
+  s.shutdown().await.unwrap();
+  drop(s);
+
+  let s = Serf::<T>::new(transport_opts.clone(), opts.clone())
+    .await
+    .unwrap();
+  s.shutdown().await.unwrap();
+  drop(s);
+
+  let replays = recorder.replays.lock().clone();
+  assert_eq!(replays.len(), 2);
+  assert_eq!(replays[0].event_clock(), 0.into());
+  assert_eq!(replays[1].event_clock(), 100.into());
+  assert_eq!(recorder.errors.load(Ordering::SeqCst), 0);
+
+  // The snapshot cannot be opened
+  let opts = opts.with_snapshot_path(Some(td.path().to_path_buf()));
+  assert!(Serf::<T>::new(transport_opts, opts).await.is_err());
+  assert_eq!(recorder.errors.load(Ordering::SeqCst), 1);
+  assert_eq!(recorder.replays.lock().len(), 2);
+}
+
+/// Unit test for seeding a standby instance with an exported state
+pub async fn serf_state_export_import<T>(
+  transport_opts1: T::Options,
+  transport_opts2: T::Options,
+  transport_opts3: T::Options,
+) where
+  T: Transport,
+{
+  let s1 = Serf::<T>::new(transport_opts1, test_config())
+    .await
+    .unwrap();
+  let s2 = Serf::<T>::new(transport_opts2, test_config())
+    .await
+    .unwrap();
+
+  let serfs = [s1, s2];
+  wait_until_num_nodes(1, &serfs).await;
+
+  let node = serfs[1].advertise_node();
+  serfs[0]
+    .join(node.map_address(MaybeResolvedAddress::resolved), false)
+    .await
+    .unwrap();
+  wait_until_num_nodes(2, &serfs).await;
+
+  let event = |ltime: u64, name: &'static str| {
+    UserEventMessage::default()
+      .with_ltime(ltime.into())
+      .with_name(name.into())
+      .with_payload(Bytes::from_static(b"v1"))
+  };
+  assert!(serfs[0].handle_user_event(event(5, "deploy")).await);
+
+  let state = serfs[0].export_state().await;
+  assert_eq!(state.members().len(), 2);
+  assert_eq!(state.events().len(), 1);
+
+  let s3 = Serf::<T>::new(transport_opts3, test_config())
+    .await
+    .unwrap();
+  s3.import_state(state.clone()).await;
+  assert!(s3.inner.clock.time() >= state.ltime());
+  assert!(s3.inner.event_clock.time() >= state.event_ltime());
+  assert!(s3.inner.query_clock.time() >= state.query_ltime());
+
+  // The event already seen by the exporting instance is not delivered again
+  assert!(!s3.handle_user_event(event(5, "deploy")).await);
+  assert!(!s3.handle_user_event(event(4, "rollback")).await);
+  assert!(s3.handle_user_event(event(5, "rollback")).await);
+
+  assert_eq!(s3.failed_members().await.len(), 2);
+
+  // The imported members are reconnected to without a join
+  let [s1, s2] = serfs;
+  let serfs = [s1, s2, s3];
+  wait_until_num_nodes(3, &serfs).await;
+
+  for s in serfs.iter() {
+    s.shutdown().await.unwrap();
+  }
+}
@@ -0,0 +1,220 @@
+use std::{
+  collections::HashMap,
+  io,
+  path::{Path, PathBuf},
+  sync::Arc,
+  time::Duration,
+};
+
+use async_lock::RwLock;
+use futures::FutureExt;
+use memberlist_core::{
+  agnostic_lite::{AsyncSpawner, RuntimeLite},
+  bytes::{Buf, BufMut},
+  tracing,
+  transport::{AddressResolver, Transport},
+};
+
+use super::CoordCore;
+use crate::{clock::Timer, coordinate::Coordinate, delegate::TransformDelegate, types::Members};
+
+/// Identifies a file of persisted network coordinates, followed by the
+/// version of the format.
+const MAGIC: &[u8; 4] = b"RSCC";
+const VERSION: u8 = 1;
+
+/// Encodes the coordinate of the local node, followed by the ones of the
+/// peers. Every coordinate is a length prefixed record, the ones of the
+/// peers start with the id of the peer.
+fn encode<T: TransformDelegate>(
+  local: &Coordinate,
+  peers: &[(&T::Id, &Coordinate)],
+) -> Result<Vec<u8>, T::Error> {
+  let mut buf = Vec::with_capacity(MAGIC.len() + 1);
+  buf.put_slice(MAGIC);
+  buf.put_u8(VERSION);
+
+  let mut record = vec![0; T::coordinate_encoded_len(local)];
+  T::encode_coordinate(local, &mut record)?;
+  buf.put_u32_le(record.len() as u32);
+  buf.put_slice(&record);
+
+  for (id, coord) in peers {
+    let id_len = T::id_encoded_len(id);
+    let mut record = vec![0; id_len + T::coordinate_encoded_len(coord)];
+    T::encode_id(id, &mut record)?;
+    T::encode_coordinate(coord, &mut record[id_len..])?;
+    buf.put_u32_le(record.len() as u32);
+    buf.put_slice(&record);
+  }
+  Ok(buf)
+}
+
+/// Splits the next length prefixed record off `src`, or returns `None` at
+/// the end of the file.
+fn next_record<'a>(src: &mut &'a [u8]) -> io::Result<Option<&'a [u8]>> {
+  let mut buf: &'a [u8] = src;
+  if buf.is_empty() {
+    return Ok(None);
+  }
+  if buf.len() < 4 {
+    return Err(io::ErrorKind::UnexpectedEof.into());
+  }
+  let len = buf.get_u32_le() as usize;
+  if buf.len() < len {
+    return Err(io::ErrorKind::UnexpectedEof.into());
+  }
+  let (record, rest) = buf.split_at(len);
+  *src = rest;
+  Ok(Some(record))
+}
+
+/// Decodes the coordinates encoded by [`encode`].
+fn decode<T: TransformDelegate>(
+  mut src: &[u8],
+) -> io::Result<(Coordinate, HashMap<T::Id, Coordinate>)> {
+  if src.len() < MAGIC.len() + 1 || &src[..MAGIC.len()] != MAGIC {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidData,
+      "not a network coordinates file",
+    ));
+  }
+  if src[MAGIC.len()] != VERSION {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidData,
+      format!(
+        "unsupported network coordinates file version {}",
+        src[MAGIC.len()]
+      ),
+    ));
+  }
+  src.advance(MAGIC.len() + 1);
+
+  let Some(record) = next_record(&mut src)? else {
+    return Err(io::ErrorKind::UnexpectedEof.into());
+  };
+  let (_, local) = T::decode_coordinate(record).map_err(crate::invalid_data_io_error)?;
+
+  let mut peers = HashMap::new();
+  while let Some(record) = next_record(&mut src)? {
+    let (id_len, id) = T::decode_id(record).map_err(crate::invalid_data_io_error)?;
+    let (_, coord) =
+      T::decode_coordinate(&record[id_len..]).map_err(crate::invalid_data_io_error)?;
+    peers.insert(id, coord);
+  }
+  Ok((local, peers))
+}
+
+/// Loads the coordinates persisted to `path`, or `None` if the file does
+/// not exist yet.
+pub(crate) fn load<T: TransformDelegate>(
+  path: &Path,
+) -> io::Result<Option<(Coordinate, HashMap<T::Id, Coordinate>)>> {
+  match std::fs::read(path) {
+    Ok(src) => decode::<T>(&src).map(Some),
+    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+    Err(e) => Err(e),
+  }
+}
+
+/// Persists the coordinates to `path`. The file is replaced at once, so a
+/// crash never leaves a partial file behind.
+fn save<T: TransformDelegate>(
+  path: &Path,
+  local: &Coordinate,
+  peers: &[(&T::Id, &Coordinate)],
+) -> io::Result<()> {
+  let buf = encode::<T>(local, peers).map_err(crate::invalid_data_io_error)?;
+  let mut tmp = path.to_path_buf().into_os_string();
+  tmp.push(".tmp");
+  std::fs::write(&tmp, buf)?;
+  std::fs::rename(tmp, path)
+}
+
+/// Persists the network coordinates every `interval` and on shutdown, see
+/// [`Options::coordinate_cache_path`](crate::Options::coordinate_cache_path).
+pub(crate) struct CoordinatePersister<T: Transport> {
+  pub(crate) path: PathBuf,
+  pub(crate) interval: Duration,
+  pub(crate) coord_core: Arc<CoordCore<T::Id>>,
+  pub(crate) members:
+    Arc<RwLock<Members<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>>>,
+  pub(crate) shutdown_rx: async_channel::Receiver<()>,
+  pub(crate) timer: Timer,
+}
+
+impl<T: Transport> CoordinatePersister<T> {
+  pub(crate) fn spawn<D>(
+    self,
+  ) -> <<T::Runtime as RuntimeLite>::Spawner as AsyncSpawner>::JoinHandle<()>
+  where
+    D: TransformDelegate<Id = T::Id>,
+  {
+    <T::Runtime as RuntimeLite>::spawn(async move {
+      loop {
+        futures::select! {
+          _ = self.timer.sleep::<T::Runtime>(self.interval).fuse() => {
+            self.persist::<D>().await;
+          }
+          _ = self.shutdown_rx.recv().fuse() => {
+            self.persist::<D>().await;
+            break;
+          }
+        }
+      }
+
+      tracing::debug!("ruserf: coordinate persister exits");
+    })
+  }
+
+  /// Persists the coordinates of the known members only, the ones of the
+  /// reaped members are not worth restoring.
+  async fn persist<D>(&self)
+  where
+    D: TransformDelegate<Id = T::Id>,
+  {
+    let local = self.coord_core.client.get_coordinate();
+    let members = self.members.read().await;
+    let cache = self.coord_core.cache.read();
+    let peers = cache
+      .iter()
+      .filter(|(id, _)| members.states.contains_key(*id))
+      .collect::<Vec<_>>();
+    if let Err(e) = save::<D>(&self.path, &local, &peers) {
+      tracing::warn!(err=%e, path=%self.path.display(), "ruserf: failed to persist the network coordinates");
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::SocketAddr;
+
+  use smol_str::SmolStr;
+
+  use super::*;
+
+  type Delegate = crate::delegate::CompositeDelegate<SmolStr, SocketAddr>;
+
+  #[test]
+  fn test_coordinate_cache_roundtrip() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("coordinates");
+    assert!(load::<Delegate>(&path).unwrap().is_none());
+
+    let local = Coordinate::new().with_height(0.25);
+    let peer = Coordinate::new().with_adjustment(0.5);
+    let id = SmolStr::new("peer");
+    save::<Delegate>(&path, &local, &[(&id, &peer)]).unwrap();
+
+    let (got_local, got_peers) = load::<Delegate>(&path).unwrap().unwrap();
+    assert_eq!(got_local, local);
+    assert_eq!(got_peers.len(), 1);
+    assert_eq!(got_peers[&id], peer);
+
+    // Truncated and foreign files are rejected
+    let src = std::fs::read(&path).unwrap();
+    assert!(decode::<Delegate>(&src[..src.len() - 1]).is_err());
+    assert!(decode::<Delegate>(b"not coordinates").is_err());
+  }
+}
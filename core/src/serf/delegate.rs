@@ -1,17 +1,26 @@
+#[cfg(any(test, feature = "test"))]
+use crate::delegate::MessageDropper;
 use crate::{
+  addresses, app_meta,
   broadcast::SerfBroadcast,
+  cluster, compression, conflict,
   delegate::{Delegate, TransformDelegate},
   error::{SerfDelegateError, SerfError},
-  event::QueryMessageExt,
+  event::{ClusterMismatchEvent, QueryMessageExt},
+  secure, segment,
+  serf::CorrelationId,
   types::{
-    DelegateVersion, JoinMessage, LamportTime, LeaveMessage, Member, MemberStatus,
-    MemberlistDelegateVersion, MemberlistProtocolVersion, MessageType, ProtocolVersion,
-    PushPullMessageRef, SerfMessage, UserEventMessage,
+    JoinMessage, LamportTime, LeaveMessage, Member, MemberStatus, MemberlistDelegateVersion,
+    MemberlistProtocolVersion, MessageType, PushPullMessageRef, SerfMessage, UserEventMessage,
   },
-  Serf,
+  version::Versions,
+  Serf, PAUSED_TAG,
 };
 
-use std::sync::{atomic::Ordering, Arc, OnceLock};
+use std::sync::{
+  atomic::{AtomicBool, Ordering},
+  Arc, OnceLock,
+};
 
 use arc_swap::ArcSwap;
 use indexmap::IndexSet;
@@ -21,22 +30,23 @@ use memberlist_core::{
     AliveDelegate, ConflictDelegate, Delegate as MemberlistDelegate, EventDelegate,
     MergeDelegate as MemberlistMergeDelegate, NodeDelegate, PingDelegate,
   },
-  tracing,
+  tracing::{self, Instrument},
   transport::{AddressResolver, Transport},
   types::{Meta, NodeState, SmallVec, State, TinyVec},
   CheapClone, META_MAX_SIZE,
 };
 use ruserf_types::Tags;
+use smol_str::{format_smolstr, SmolStr};
 
 // PingVersion is an internal version for the ping message, above the normal
 // versioning we get from the protocol version. This enables small updates
 // to the ping message without a full protocol bump.
 const PING_VERSION: u8 = 1;
 
-#[cfg(any(test, feature = "test"))]
-pub(crate) trait MessageDropper: Send + Sync + 'static {
-  fn should_drop(&self, ty: MessageType) -> bool;
-}
+/// Marks the application health trailing the coordinate in the ping acks,
+/// see [`HealthDelegate`](crate::delegate::HealthDelegate). The older nodes
+/// only read the coordinate, so they ignore it.
+const PING_HEALTH_TRAILER: u8 = 0xA1;
 
 /// The memberlist delegate for Serf.
 pub struct SerfDelegate<T, D>
@@ -47,8 +57,24 @@ where
   serf: OnceLock<Serf<T, D>>,
   delegate: Option<D>,
   tags: Arc<ArcSwap<Tags>>,
+  /// The opaque application metadata advertised after the tags
+  app_meta: ArcSwap<Bytes>,
+  /// The encoded additional addresses, see [`Serf::set_advertise_addresses`]
+  addresses: ArcSwap<SmolStr>,
+  versions: Versions,
+  /// The start time advertised to settle the name conflicts by age
+  started: Option<u64>,
+  /// The segment of the local node, see [`Options::segment`](crate::Options::segment)
+  segment: Option<SmolStr>,
+  /// The hash of the cluster name of the local node, see
+  /// [`Options::cluster_name`](crate::Options::cluster_name)
+  cluster: Option<SmolStr>,
+  /// Whether the transport encrypts the gossip, only known once the memberlist is created
+  encrypted: AtomicBool,
+  /// Whether the gossip participation is paused, see [`Serf::pause`]
+  paused: AtomicBool,
   #[cfg(any(test, feature = "test"))]
-  pub(crate) message_dropper: Option<Box<dyn MessageDropper>>,
+  message_dropper: Option<Arc<dyn MessageDropper>>,
   /// Only used for testing purposes
   #[cfg(any(test, feature = "test"))]
   pub(crate) ping_versioning_test: core::sync::atomic::AtomicBool,
@@ -61,32 +87,29 @@ where
   D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
   T: Transport,
 {
-  pub(crate) fn new(d: Option<D>, tags: Arc<ArcSwap<Tags>>) -> Self {
-    Self {
-      serf: OnceLock::new(),
-      delegate: d,
-      tags,
-      #[cfg(any(test, feature = "test"))]
-      message_dropper: None,
-      #[cfg(any(test, feature = "test"))]
-      ping_versioning_test: core::sync::atomic::AtomicBool::new(false),
-      #[cfg(any(test, feature = "test"))]
-      ping_dimension_test: core::sync::atomic::AtomicBool::new(false),
-    }
-  }
-
-  #[cfg(any(test, feature = "test"))]
-  pub(crate) fn with_dropper(
+  pub(crate) fn new(
     d: Option<D>,
-    dropper: Box<dyn MessageDropper>,
     tags: Arc<ArcSwap<Tags>>,
+    versions: Versions,
+    started: Option<u64>,
+    segment: Option<SmolStr>,
+    cluster: Option<SmolStr>,
+    #[cfg(any(test, feature = "test"))] message_dropper: Option<Arc<dyn MessageDropper>>,
   ) -> Self {
     Self {
       serf: OnceLock::new(),
       delegate: d,
       tags,
+      app_meta: ArcSwap::from_pointee(Bytes::new()),
+      addresses: ArcSwap::from_pointee(SmolStr::default()),
+      versions,
+      started,
+      segment,
+      cluster,
+      encrypted: AtomicBool::new(false),
+      paused: AtomicBool::new(false),
       #[cfg(any(test, feature = "test"))]
-      message_dropper: Some(dropper),
+      message_dropper,
       #[cfg(any(test, feature = "test"))]
       ping_versioning_test: core::sync::atomic::AtomicBool::new(false),
       #[cfg(any(test, feature = "test"))]
@@ -94,6 +117,67 @@ where
     }
   }
 
+  pub(crate) fn app_meta(&self) -> Arc<Bytes> {
+    self.app_meta.load_full()
+  }
+
+  pub(crate) fn set_app_meta(&self, app_meta: Bytes) {
+    self.app_meta.store(Arc::new(app_meta));
+  }
+
+  pub(crate) fn addresses(&self) -> Arc<SmolStr> {
+    self.addresses.load_full()
+  }
+
+  pub(crate) fn set_addresses(&self, encoded: SmolStr) {
+    self.addresses.store(Arc::new(encoded));
+  }
+
+  pub(crate) fn started(&self) -> Option<u64> {
+    self.started
+  }
+
+  pub(crate) fn cluster(&self) -> Option<&SmolStr> {
+    self.cluster.as_ref()
+  }
+
+  /// Emits a [`ClusterMismatchEvent`] if the member was refused because it
+  /// belongs to another cluster.
+  async fn notify_cluster_mismatch(
+    &self,
+    node: &NodeState<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+    err: &SerfDelegateError<D>,
+  ) {
+    let SerfDelegateError::Serf(SerfError::ClusterMismatch { cluster, .. }) = err else {
+      return;
+    };
+    tracing::warn!(id=%node.id(), "ruserf: refused member of another cluster");
+    let Some(this) = self.serf.get() else {
+      return;
+    };
+    let event = ClusterMismatchEvent {
+      node: node.node(),
+      cluster: cluster.clone(),
+    };
+    if let Err(e) = this.inner.event_tx.send(event.into()).await {
+      tracing::error!(err=%e, "ruserf: failed to send cluster mismatch event");
+    }
+  }
+
+  pub(crate) fn paused(&self) -> bool {
+    self.paused.load(Ordering::Acquire)
+  }
+
+  /// Returns the previous value.
+  pub(crate) fn set_paused(&self, paused: bool) -> bool {
+    self.paused.swap(paused, Ordering::AcqRel)
+  }
+
+  #[cfg(feature = "encryption")]
+  pub(crate) fn set_encrypted(&self) {
+    self.encrypted.store(true, Ordering::Release);
+  }
+
   pub(crate) fn delegate(&self) -> Option<&D> {
     self.delegate.as_ref()
   }
@@ -106,6 +190,222 @@ where
   fn this(&self) -> &Serf<T, D> {
     self.serf.get().unwrap()
   }
+
+  async fn handle_message(&self, mut msg: Bytes) {
+    let this = self.this();
+    // Decode from a view of the incoming buffer (without the message type byte), so that
+    // the payloads of the decoded messages can share the memory of `msg`.
+    let body = msg.slice(1..);
+    let mut rebroadcast = None;
+    let mut rebroadcast_queue = &this.inner.broadcasts;
+    match MessageType::try_from(msg[0]) {
+      Ok(ty) => {
+        match ty {
+          MessageType::Leave => {
+            match <D as TransformDelegate>::decode_message_from_bytes(ty, &body) {
+              Ok((_, l)) => {
+                if let SerfMessage::Leave(l) = &l {
+                  let span =
+                    message_span(ty, *l.ltime(), l.id(), CorrelationId::generate(*l.ltime()));
+                  rebroadcast = traced(span, this.handle_node_leave_intent(l))
+                    .await
+                    .then(|| msg.clone());
+                } else {
+                  tracing::warn!("ruserf: receive unexpected message: {}", l.ty().as_str());
+                }
+              }
+              Err(e) => {
+                tracing::warn!(err=%e, "ruserf: failed to decode message");
+              }
+            }
+          }
+          MessageType::Join => match <D as TransformDelegate>::decode_message_from_bytes(ty, &body)
+          {
+            Ok((_, j)) => {
+              if let SerfMessage::Join(j) = &j {
+                let span =
+                  message_span(ty, *j.ltime(), j.id(), CorrelationId::generate(*j.ltime()));
+                rebroadcast = traced(span, this.handle_node_join_intent(j))
+                  .await
+                  .then(|| msg.clone());
+              } else {
+                tracing::warn!("ruserf: receive unexpected message: {}", j.ty().as_str());
+              }
+            }
+            Err(e) => {
+              tracing::warn!(err=%e, "ruserf: failed to decode message");
+            }
+          },
+          MessageType::UserEvent => {
+            match <D as TransformDelegate>::decode_message_from_bytes(ty, &body) {
+              Ok((_, ue)) => {
+                if let SerfMessage::UserEvent(ue) = ue {
+                  // User events do not carry their origin
+                  let span = tracing::debug_span!(
+                    "ruserf.message",
+                    msg_type = ty.as_str(),
+                    ltime = %ue.ltime,
+                    name = %ue.name,
+                    correlation_id = %CorrelationId::generate(ue.ltime),
+                  );
+                  rebroadcast = traced(span, this.handle_user_event(ue))
+                    .await
+                    .then(|| msg.clone());
+                  rebroadcast_queue = &this.inner.event_broadcasts;
+                } else {
+                  tracing::warn!("ruserf: receive unexpected message: {}", ue.ty().as_str());
+                }
+              }
+              Err(e) => {
+                tracing::warn!(err=%e, "ruserf: failed to decode message");
+              }
+            }
+          }
+          MessageType::Query => {
+            match <D as TransformDelegate>::decode_message_from_bytes(ty, &body) {
+              Ok((_, q)) => {
+                if let SerfMessage::Query(mut q) = q {
+                  q.from = this.inbound_node(q.from);
+                  let span =
+                    message_span(ty, q.ltime, &q.from, CorrelationId::query(q.ltime, q.id));
+                  span.record("name", tracing::field::display(&q.name));
+                  match q.decode_internal_query::<D>() {
+                    Some(Err(e)) => {
+                      let _enter = span.enter();
+                      tracing::warn!(err=%e, "ruserf: failed to decode message");
+                    }
+                    Some(Ok(res)) => {
+                      rebroadcast = traced(span, this.handle_query(q, Some(res)))
+                        .await
+                        .then(|| msg.clone());
+                      rebroadcast_queue = &this.inner.query_broadcasts;
+                    }
+                    None => {
+                      rebroadcast = traced(span, this.handle_query(q, None))
+                        .await
+                        .then(|| msg.clone());
+                      rebroadcast_queue = &this.inner.query_broadcasts;
+                    }
+                  };
+                } else {
+                  tracing::warn!("ruserf: receive unexpected message: {}", q.ty().as_str());
+                }
+              }
+              Err(e) => {
+                tracing::warn!(err=%e, "ruserf: failed to decode message");
+              }
+            }
+          }
+          MessageType::QueryResponse => {
+            match <D as TransformDelegate>::decode_message_from_bytes(ty, &body) {
+              Ok((_, qr)) => {
+                if let SerfMessage::QueryResponse(qr) = qr {
+                  let span = message_span(
+                    ty,
+                    qr.ltime,
+                    &qr.from,
+                    CorrelationId::query(qr.ltime, qr.id),
+                  );
+                  traced(span, this.handle_query_response(qr)).await;
+                } else {
+                  tracing::warn!("ruserf: receive unexpected message: {}", qr.ty().as_str());
+                }
+              }
+              Err(e) => {
+                tracing::warn!(err=%e, "ruserf: failed to decode message");
+              }
+            }
+          }
+          MessageType::Relay => match <D as TransformDelegate>::decode_node(&msg[1..]) {
+            Ok((consumed, n)) => {
+              let n = this.inbound_node(n);
+              // + 1 for the message type byte
+              msg.advance(consumed + 1);
+              // The relayed response keeps the correlation id of its query,
+              // so the hop shows up in the trace of the query.
+              let span = match msg
+                .first()
+                .and_then(|ty| MessageType::try_from(*ty).ok())
+                .filter(|ty| *ty == MessageType::QueryResponse)
+                .and_then(|inner| {
+                  <D as TransformDelegate>::decode_message_from_bytes(inner, &msg.slice(1..)).ok()
+                }) {
+                Some((_, SerfMessage::QueryResponse(qr))) => message_span(
+                  ty,
+                  qr.ltime,
+                  &qr.from,
+                  CorrelationId::query(qr.ltime, qr.id),
+                ),
+                _ => tracing::debug_span!(
+                  "ruserf.message",
+                  msg_type = ty.as_str(),
+                  to = tracing::field::Empty,
+                ),
+              };
+              span.record("to", tracing::field::display(&n));
+              // The destination may be about to join, favour its buffered intent
+              this.reference_intent(n.id()).await;
+              traced(span, async {
+                match this.inner.memberlist.send(n.address(), msg.clone()).await {
+                  Ok(_) => this.inner.bandwidth.sent(&msg),
+                  Err(e) => {
+                    tracing::error!(err=%e, "ruserf: failed to forwarding message to {}", n)
+                  }
+                }
+              })
+              .await;
+            }
+            Err(e) => {
+              tracing::warn!(err=%e, "ruserf: failed to decode relay destination");
+            }
+          },
+          ty => {
+            tracing::warn!("ruserf: receive unexpected message: {}", ty.as_str());
+          }
+        }
+      }
+      Err(e) => {
+        tracing::warn!(err=%e, "ruserf: receive unknown message type");
+      }
+    }
+
+    // A paused node handles the messages, but does not gossip them further
+    if let Some(msg) = rebroadcast.filter(|_| !self.paused()) {
+      rebroadcast_queue
+        .queue_broadcast(SerfBroadcast::new(msg, None))
+        .await;
+    }
+  }
+}
+
+/// Returns the span a received message is handled in.
+///
+/// `name` and `to` are left empty, only queries and relays fill them in.
+fn message_span(
+  ty: MessageType,
+  ltime: LamportTime,
+  from: impl core::fmt::Display,
+  correlation_id: CorrelationId,
+) -> tracing::Span {
+  tracing::debug_span!(
+    "ruserf.message",
+    msg_type = ty.as_str(),
+    ltime = %ltime,
+    from = %from,
+    correlation_id = %correlation_id,
+    name = tracing::field::Empty,
+    to = tracing::field::Empty,
+  )
+}
+
+/// Runs the handling of a received message in its span.
+async fn traced<F: core::future::Future>(span: tracing::Span, fut: F) -> F::Output {
+  async move {
+    tracing::debug!("ruserf: handling message");
+    fut.await
+  }
+  .instrument(span)
+  .await
 }
 
 impl<D, T> NodeDelegate for SerfDelegate<T, D>
@@ -114,10 +414,20 @@ where
   T: Transport,
 {
   async fn node_meta(&self, limit: usize) -> Meta {
-    let tags = self.tags.load();
-    match tags.is_empty() {
+    let mut tags = self.versions.advertise(&self.tags.load());
+    secure::advertise(&mut tags, self.encrypted.load(Ordering::Acquire));
+    conflict::advertise(&mut tags, self.started);
+    segment::advertise(&mut tags, self.segment.as_ref());
+    cluster::advertise(&mut tags, self.cluster.as_ref());
+    addresses::advertise(&mut tags, &self.addresses.load());
+    if self.paused() {
+      tags.insert(SmolStr::new(PAUSED_TAG), SmolStr::new("1"));
+    }
+    let app_meta = self.app_meta.load();
+    match tags.is_empty() && app_meta.is_empty() {
       false => {
-        let encoded_len = <D as TransformDelegate>::tags_encoded_len(&tags);
+        let tags_encoded_len = <D as TransformDelegate>::tags_encoded_len(&tags);
+        let encoded_len = tags_encoded_len + app_meta::encoded_len(&app_meta);
         let limit = limit.min(Meta::MAX_SIZE);
         if encoded_len > limit {
           panic!(
@@ -129,6 +439,12 @@ where
         let mut role_bytes = vec![0; encoded_len];
         match <D as TransformDelegate>::encode_tags(&tags, &mut role_bytes) {
           Ok(len) => {
+            debug_assert_eq!(
+              len, tags_encoded_len,
+              "expected encoded len {} mismatch the actual encoded len {}",
+              tags_encoded_len, len
+            );
+            let len = len + app_meta::encode(&app_meta, &mut role_bytes[len..]);
             debug_assert_eq!(
               len, encoded_len,
               "expected encoded len {} mismatch the actual encoded len {}",
@@ -154,12 +470,14 @@ where
     }
   }
 
-  async fn notify_message(&self, mut msg: Bytes) {
+  async fn notify_message(&self, msg: Bytes) {
     // If we didn't actually receive any data, then ignore it.
     if msg.is_empty() {
       return;
     }
 
+    self.this().inner.bandwidth.received(&msg);
+
     #[cfg(feature = "metrics")]
     {
       metrics::histogram!(
@@ -175,133 +493,32 @@ where
       .record(msg.len() as f64);
     }
 
-    let this = self.this();
-    let mut rebroadcast = None;
-    let mut rebroadcast_queue = &this.inner.broadcasts;
-    match MessageType::try_from(msg[0]) {
-      Ok(ty) => {
-        #[cfg(any(test, feature = "test"))]
-        {
-          if let Some(ref dropper) = this.inner.memberlist.delegate().unwrap().message_dropper {
-            if dropper.should_drop(MessageType::Join) {
-              return;
-            }
-          }
-        }
+    #[cfg(any(test, feature = "test"))]
+    {
+      use memberlist_core::agnostic_lite::RuntimeLite;
 
-        match ty {
-          MessageType::Leave => match <D as TransformDelegate>::decode_message(ty, &msg[1..]) {
-            Ok((_, l)) => {
-              if let SerfMessage::Leave(l) = &l {
-                tracing::debug!("ruserf: leave message: {}", l.id());
-                rebroadcast = this.handle_node_leave_intent(l).await.then(|| msg.clone());
-              } else {
-                tracing::warn!("ruserf: receive unexpected message: {}", l.ty().as_str());
-              }
-            }
-            Err(e) => {
-              tracing::warn!(err=%e, "ruserf: failed to decode message");
-            }
-          },
-          MessageType::Join => match <D as TransformDelegate>::decode_message(ty, &msg[1..]) {
-            Ok((_, j)) => {
-              if let SerfMessage::Join(j) = &j {
-                tracing::debug!("ruserf: join message: {}", j.id());
-                rebroadcast = this.handle_node_join_intent(j).await.then(|| msg.clone());
-              } else {
-                tracing::warn!("ruserf: receive unexpected message: {}", j.ty().as_str());
-              }
-            }
-            Err(e) => {
-              tracing::warn!(err=%e, "ruserf: failed to decode message");
-            }
-          },
-          MessageType::UserEvent => match <D as TransformDelegate>::decode_message(ty, &msg[1..]) {
-            Ok((_, ue)) => {
-              if let SerfMessage::UserEvent(ue) = ue {
-                tracing::debug!("ruserf: user event message: {}", ue.name);
-                rebroadcast = this.handle_user_event(ue).await.then(|| msg.clone());
-                rebroadcast_queue = &this.inner.event_broadcasts;
-              } else {
-                tracing::warn!("ruserf: receive unexpected message: {}", ue.ty().as_str());
-              }
-            }
-            Err(e) => {
-              tracing::warn!(err=%e, "ruserf: failed to decode message");
-            }
-          },
-          MessageType::Query => match <D as TransformDelegate>::decode_message(ty, &msg[1..]) {
-            Ok((_, q)) => {
-              if let SerfMessage::Query(q) = q {
-                tracing::debug!("ruserf: query message: {}", q.name);
-                match q.decode_internal_query::<D>() {
-                  Some(Err(e)) => {
-                    tracing::warn!(err=%e, "ruserf: failed to decode message");
-                  }
-                  Some(Ok(res)) => {
-                    rebroadcast = this.handle_query(q, Some(res)).await.then(|| msg.clone());
-                    rebroadcast_queue = &this.inner.query_broadcasts;
-                  }
-                  None => {
-                    rebroadcast = this.handle_query(q, None).await.then(|| msg.clone());
-                    rebroadcast_queue = &this.inner.query_broadcasts;
-                  }
-                };
-              } else {
-                tracing::warn!("ruserf: receive unexpected message: {}", q.ty().as_str());
-              }
-            }
-            Err(e) => {
-              tracing::warn!(err=%e, "ruserf: failed to decode message");
-            }
-          },
-          MessageType::QueryResponse => {
-            match <D as TransformDelegate>::decode_message(ty, &msg[1..]) {
-              Ok((_, qr)) => {
-                if let SerfMessage::QueryResponse(qr) = qr {
-                  tracing::debug!("ruserf: query response message: {}", qr.from);
-                  this.handle_query_response(qr).await;
-                } else {
-                  tracing::warn!("ruserf: receive unexpected message: {}", qr.ty().as_str());
-                }
-              }
-              Err(e) => {
-                tracing::warn!(err=%e, "ruserf: failed to decode message");
-              }
-            }
+      if let Some(ref dropper) = self.message_dropper {
+        if let Ok(ty) = MessageType::try_from(msg[0]) {
+          if dropper.should_drop(ty) {
+            return;
           }
-          MessageType::Relay => match <D as TransformDelegate>::decode_node(&msg[1..]) {
-            Ok((consumed, n)) => {
-              tracing::debug!("ruserf: relay message",);
-              tracing::debug!("ruserf: relaying response to node: {}", n);
-              // + 1 for the message type byte
-              msg.advance(consumed + 1);
-              if let Err(e) = this.inner.memberlist.send(n.address(), msg.clone()).await {
-                tracing::error!(err=%e, "ruserf: failed to forwarding message to {}", n);
+
+          // Let the messages received meanwhile overtake the delayed one
+          if let Some(delay) = dropper.delay(ty) {
+            let this = self.this().clone();
+            <T::Runtime as RuntimeLite>::spawn_detach(async move {
+              <T::Runtime as RuntimeLite>::sleep(delay).await;
+              if let Some(d) = this.inner.memberlist.delegate() {
+                d.handle_message(msg).await;
               }
-            }
-            Err(e) => {
-              tracing::warn!(err=%e, "ruserf: failed to decode relay destination");
-            }
-          },
-          ty => {
-            tracing::warn!("ruserf: receive unexpected message: {}", ty.as_str());
+            });
+            return;
           }
         }
       }
-      Err(e) => {
-        tracing::warn!(err=%e, "ruserf: receive unknown message type");
-      }
     }
 
-    if let Some(msg) = rebroadcast {
-      rebroadcast_queue
-        .queue_broadcast(SerfBroadcast {
-          msg,
-          notify_tx: None,
-        })
-        .await;
-    }
+    self.handle_message(msg).await;
   }
 
   async fn broadcast_messages<F>(
@@ -314,12 +531,12 @@ where
     F: Fn(Bytes) -> (usize, Bytes) + Send,
   {
     let this = self.this();
-    let mut msgs = this.inner.broadcasts.get_broadcasts(overhead, limit).await;
+    let msgs = this.inner.broadcasts.get_broadcasts(overhead, limit).await;
 
     // Determine the bytes used already
     let mut bytes_used = 0;
     for msg in msgs.iter() {
-      let (encoded_len, _) = encoded_len(msg.clone());
+      let (encoded_len, _) = encoded_len(msg.payload.clone());
       bytes_used += encoded_len;
       #[cfg(feature = "metrics")]
       {
@@ -328,6 +545,11 @@ where
           this.inner.opts.memberlist_options.metric_labels.iter()
         )
         .record(encoded_len as f64);
+        metrics::histogram!(
+          "ruserf.queue.intent.age",
+          this.inner.opts.memberlist_options.metric_labels.iter()
+        )
+        .record(msg.queued_at.elapsed().as_millis() as f64);
       }
     }
 
@@ -335,10 +557,10 @@ where
     let query_msgs = this
       .inner
       .query_broadcasts
-      .get_broadcasts(overhead, limit - bytes_used)
+      .get_broadcasts(overhead, limit.saturating_sub(bytes_used))
       .await;
     for msg in query_msgs.iter() {
-      let (encoded_len, _) = encoded_len(msg.clone());
+      let (encoded_len, _) = encoded_len(msg.payload.clone());
       bytes_used += encoded_len;
       #[cfg(feature = "metrics")]
       {
@@ -347,6 +569,11 @@ where
           this.inner.opts.memberlist_options.metric_labels.iter()
         )
         .record(encoded_len as f64);
+        metrics::histogram!(
+          "ruserf.queue.query.age",
+          this.inner.opts.memberlist_options.metric_labels.iter()
+        )
+        .record(msg.queued_at.elapsed().as_millis() as f64);
       }
     }
 
@@ -354,10 +581,10 @@ where
     let event_msgs = this
       .inner
       .event_broadcasts
-      .get_broadcasts(overhead, limit - bytes_used)
+      .get_broadcasts(overhead, limit.saturating_sub(bytes_used))
       .await;
     for msg in event_msgs.iter() {
-      let (encoded_len, _) = encoded_len(msg.clone());
+      let (encoded_len, _) = encoded_len(msg.payload.clone());
       bytes_used += encoded_len;
       #[cfg(feature = "metrics")]
       {
@@ -366,17 +593,47 @@ where
           this.inner.opts.memberlist_options.metric_labels.iter()
         )
         .record(encoded_len as f64);
+        metrics::histogram!(
+          "ruserf.queue.event.age",
+          this.inner.opts.memberlist_options.metric_labels.iter()
+        )
+        .record(msg.queued_at.elapsed().as_millis() as f64);
       }
     }
-    msgs.extend(query_msgs);
-    msgs.extend(event_msgs);
+
     msgs
+      .into_iter()
+      .chain(query_msgs)
+      .chain(event_msgs)
+      .map(|msg| {
+        this.inner.bandwidth.sent(&msg.payload);
+        msg.payload
+      })
+      .collect()
   }
 
-  async fn local_state(&self, _join: bool) -> Bytes {
+  async fn local_state(&self, join: bool) -> Bytes {
     let this = self.this();
     let members = this.inner.members.read().await;
-    let events = this.inner.event_core.read().await;
+    let event_core = this.inner.event_core.read().await;
+    // The periodic syncs only repair the member state in incremental mode
+    let events = if join || !this.inner.opts.incremental_push_pull {
+      event_core.buffer.as_slice()
+    } else {
+      &[]
+    };
+    // Only the events of the local segment are replayed
+    let segmented;
+    let events = match self.segment.as_deref() {
+      Some(s) if !events.is_empty() => {
+        segmented = events
+          .iter()
+          .map(|e| e.as_ref().and_then(|e| segment::retain_events(e, s)))
+          .collect::<Vec<_>>();
+        segmented.as_slice()
+      }
+      _ => events,
+    };
 
     // Create the message to send
     let status_ltimes = members
@@ -394,9 +651,15 @@ where
       status_ltimes: &status_ltimes,
       left_members: &left_members,
       event_ltime: this.inner.event_clock.time(),
-      events: events.buffer.as_slice(),
+      events,
       query_ltime: this.inner.query_clock.time(),
     };
+    // Only compress once every member understands the compressed state, a
+    // joining node is not known yet
+    #[cfg(feature = "lz4")]
+    let compress = this.inner.opts.push_pull_compression
+      && !join
+      && members.all_speak(crate::types::ProtocolVersion::V2);
     drop(members);
 
     let expected_encoded_len = <D as TransformDelegate>::message_encoded_len(pp);
@@ -410,7 +673,41 @@ where
           "expected encoded len {} mismatch the actual encoded len {}",
           expected_encoded_len, encoded_len
         );
-        buf.freeze()
+
+        #[cfg(feature = "metrics")]
+        {
+          metrics::histogram!(
+            "ruserf.push_pull.size",
+            this.inner.opts.memberlist_options.metric_labels.iter()
+          )
+          .record(buf.len() as f64);
+        }
+
+        let threshold = this.inner.opts.push_pull_size_warning;
+        if threshold > 0 && buf.len() > threshold {
+          tracing::warn!(
+            "ruserf: push/pull state of {} bytes exceeds {} bytes, consider enabling incremental push/pull",
+            buf.len(),
+            threshold
+          );
+          #[cfg(feature = "metrics")]
+          {
+            metrics::counter!(
+              "ruserf.push_pull.oversized",
+              this.inner.opts.memberlist_options.metric_labels.iter()
+            )
+            .increment(1);
+          }
+        }
+        #[cfg(feature = "lz4")]
+        let buf = match compress {
+          true => compression::compress_push_pull(&buf).unwrap_or_else(|| buf.freeze()),
+          false => buf.freeze(),
+        };
+        #[cfg(not(feature = "lz4"))]
+        let buf = buf.freeze();
+        this.inner.bandwidth.sent(&buf);
+        buf
       }
       Err(e) => {
         tracing::error!(err=%e, "ruserf: failed to encode local state");
@@ -420,6 +717,32 @@ where
   }
 
   async fn merge_remote_state(&self, buf: Bytes, is_join: bool) {
+    self.this().inner.bandwidth.received(&buf);
+
+    if self.paused() {
+      tracing::debug!("ruserf: gossip is paused, ignoring remote state");
+      return;
+    }
+
+    let buf = match buf.first() {
+      #[cfg(feature = "lz4")]
+      Some(&compression::COMPRESSED_PUSH_PULL_TAG) => {
+        match compression::decompress_push_pull(&buf[1..]) {
+          Ok(buf) => buf,
+          Err(e) => {
+            tracing::error!(err=%e, "ruserf: failed to decompress remote state");
+            return;
+          }
+        }
+      }
+      #[cfg(not(feature = "lz4"))]
+      Some(&compression::COMPRESSED_PUSH_PULL_TAG) => {
+        tracing::error!("ruserf: remote state is compressed, which needs the lz4 feature");
+        return;
+      }
+      _ => buf,
+    };
+
     if buf.is_empty() {
       tracing::error!("ruserf: remote state is zero bytes");
       return;
@@ -433,23 +756,22 @@ where
 
     #[cfg(any(test, feature = "test"))]
     {
-      if let Some(ref dropper) = self
-        .this()
-        .inner
-        .memberlist
-        .delegate()
-        .unwrap()
-        .message_dropper
-      {
+      use memberlist_core::agnostic_lite::RuntimeLite;
+
+      if let Some(ref dropper) = self.message_dropper {
         if dropper.should_drop(MessageType::PushPull) {
           return;
         }
+
+        if let Some(delay) = dropper.delay(MessageType::PushPull) {
+          <T::Runtime as RuntimeLite>::sleep(delay).await;
+        }
       }
     }
 
     match ty {
       MessageType::PushPull => {
-        match <D as TransformDelegate>::decode_message(ty, &buf[1..]) {
+        match <D as TransformDelegate>::decode_message_from_bytes(ty, &buf.slice(1..)) {
           Err(e) => {
             tracing::error!(err=%e, "ruserf: failed to decode remote state");
           }
@@ -497,6 +819,13 @@ where
                   }
                 }
 
+                // Remember the members of the joined node, the join waits for them
+                if is_join {
+                  this
+                    .record_join_sync(&pp.status_ltimes, &pp.left_members)
+                    .await;
+                }
+
                 // Update any other LTimes
                 for (node, ltime) in pp.status_ltimes {
                   // Skip the left nodes
@@ -532,6 +861,8 @@ where
                             name: e.name,
                             payload: e.payload,
                             cc: false,
+                            compressed: false,
+                            distribution: Default::default(),
                           })
                           .await;
                       }
@@ -590,8 +921,15 @@ where
     &self,
     node: Arc<NodeState<Self::Id, Self::Address>>,
   ) -> Result<(), Self::Error> {
+    // Reject the members we cannot talk to before they join
+    let member = match node_to_member::<T, D>(&node, &self.versions, self.cluster.as_ref()) {
+      Ok(member) => member,
+      Err(e) => {
+        self.notify_cluster_mismatch(&node, &e).await;
+        return Err(e);
+      }
+    };
     if let Some(ref d) = self.delegate {
-      let member = node_to_member::<T, D>(node)?;
       return d
         .notify_merge(TinyVec::from(member))
         .await
@@ -615,13 +953,19 @@ where
     &self,
     peers: SmallVec<Arc<NodeState<Self::Id, Self::Address>>>,
   ) -> Result<(), Self::Error> {
+    let mut members = TinyVec::new();
+    for n in peers {
+      match node_to_member::<T, D>(&n, &self.versions, self.cluster.as_ref()) {
+        Ok(member) => members.push(member),
+        Err(e) => {
+          self.notify_cluster_mismatch(&n, &e).await;
+          return Err(e);
+        }
+      }
+    }
     if let Some(ref d) = self.delegate {
-      let peers = peers
-        .into_iter()
-        .map(node_to_member::<T, D>)
-        .collect::<Result<TinyVec<_>, _>>()?;
       return d
-        .notify_merge(peers)
+        .notify_merge(members)
         .await
         .map_err(SerfDelegateError::merge);
     }
@@ -685,20 +1029,32 @@ where
       return buf.freeze();
     }
 
-    if let Some(c) = self.this().inner.coord_core.as_ref() {
-      let coord = c.client.get_coordinate();
-      let encoded_len = <D as TransformDelegate>::coordinate_encoded_len(&coord) + 1;
-      let mut buf = BytesMut::with_capacity(encoded_len);
-      buf.put_u8(PING_VERSION);
-      buf.resize(encoded_len, 0);
+    let Some(coord) = self
+      .this()
+      .inner
+      .coord_core
+      .as_ref()
+      .map(|c| c.client.get_coordinate())
+    else {
+      return Bytes::new();
+    };
 
-      if let Err(e) = <D as TransformDelegate>::encode_coordinate(&coord, &mut buf[1..]) {
-        tracing::error!(err=%e, "ruserf: failed to encode coordinate");
-      }
-      buf.into()
-    } else {
-      Bytes::new()
+    // The health trails the coordinate, the peers not knowing about it
+    // decode the coordinate and ignore the rest.
+    let health = self.delegate.as_ref().and_then(|d| d.app_health());
+    let len = <D as TransformDelegate>::coordinate_encoded_len(&coord);
+    let mut buf = BytesMut::with_capacity(1 + len + 2);
+    buf.put_u8(PING_VERSION);
+    buf.resize(1 + len, 0);
+    if let Err(e) = <D as TransformDelegate>::encode_coordinate(&coord, &mut buf[1..]) {
+      tracing::error!(err=%e, "ruserf: failed to encode coordinate");
     }
+
+    if let Some(health) = health {
+      buf.put_u8(PING_HEALTH_TRAILER);
+      buf.put_u8(health);
+    }
+    buf.into()
   }
 
   async fn notify_ping_complete(
@@ -707,31 +1063,42 @@ where
     rtt: std::time::Duration,
     payload: Bytes,
   ) {
+    let this = self.this();
+
     if payload.is_empty() {
+      this.record_app_health(node.id(), None).await;
       return;
     }
 
-    let this = self.this();
-
-    if let Some(ref c) = this.inner.coord_core {
-      // Verify ping version in the header.
-      if payload[0] != PING_VERSION {
+    // Verify ping version in the header.
+    if payload[0] != PING_VERSION {
+      if this.inner.coord_core.is_some() {
         tracing::error!("ruserf: unsupported ping version: {}", payload[0]);
-        return;
       }
+      return;
+    }
 
-      // Process the remainder of the message as a coordinate.
-      let coord = match <D as TransformDelegate>::decode_coordinate(&payload[1..]) {
-        Ok((readed, c)) => {
-          tracing::trace!(read=%readed, coordinate=?c, "ruserf: decode coordinate successfully");
-          c
-        }
-        Err(e) => {
+    // The coordinate comes first, the health may trail it.
+    let (coord, rest) = match <D as TransformDelegate>::decode_coordinate(&payload[1..]) {
+      Ok((readed, c)) => {
+        tracing::trace!(read=%readed, coordinate=?c, "ruserf: decode coordinate successfully");
+        (c, &payload[1 + readed..])
+      }
+      Err(e) => {
+        if this.inner.coord_core.is_some() {
           tracing::error!(err=%e, "ruserf: failed to decode coordinate from ping");
-          return;
         }
-      };
+        return;
+      }
+    };
+
+    let health = match rest {
+      [PING_HEALTH_TRAILER, health] => Some(*health),
+      _ => None,
+    };
+    this.record_app_health(node.id(), health).await;
 
+    if let Some(c) = this.inner.coord_core.as_ref() {
       // Apply the update.
       #[cfg(feature = "metrics")]
       let before = c.client.get_coordinate();
@@ -795,7 +1162,9 @@ where
 }
 
 fn node_to_member<T, D>(
-  node: Arc<NodeState<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>>,
+  node: &NodeState<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+  local: &Versions,
+  local_cluster: Option<&SmolStr>,
 ) -> Result<Member<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>, SerfDelegateError<D>>
 where
   D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
@@ -812,22 +1181,45 @@ where
     return Err(SerfDelegateError::serf(SerfError::TagsTooLarge(meta.len())));
   }
 
+  let (mut tags, app_meta) = if !node.meta().is_empty() {
+    <D as TransformDelegate>::decode_tags(node.meta())
+      .map(|(read, tags)| {
+        tracing::trace!(read=%read, tags=?tags, "ruserf: decode tags successfully");
+        (tags, app_meta::decode(&node.meta()[read..]))
+      })
+      .map_err(SerfDelegateError::transform)?
+  } else {
+    Default::default()
+  };
+  let versions = Versions::split(&mut tags);
+  let secure = secure::split(&mut tags);
+  conflict::split(&mut tags);
+  let cluster = cluster::split(&mut tags);
+  let addresses = addresses::split::<D>(&mut tags);
+  if !local.compatible_with(&versions) {
+    return Err(SerfDelegateError::serf(SerfError::IncompatibleVersion {
+      id: format_smolstr!("{}", node.id()),
+      versions: format_smolstr!("{versions}"),
+    }));
+  }
+  if cluster.as_ref() != local_cluster {
+    return Err(SerfDelegateError::serf(SerfError::ClusterMismatch {
+      id: format_smolstr!("{}", node.id()),
+      cluster,
+    }));
+  }
+
   Ok(Member {
     node: node.node(),
-    tags: if !node.meta().is_empty() {
-      <D as TransformDelegate>::decode_tags(node.meta())
-        .map(|(read, tags)| {
-          tracing::trace!(read=%read, tags=?tags, "ruserf: decode tags successfully");
-          Arc::new(tags)
-        })
-        .map_err(SerfDelegateError::transform)?
-    } else {
-      Default::default()
-    },
+    tags: Arc::new(tags),
     status,
-    protocol_version: ProtocolVersion::V1,
-    delegate_version: DelegateVersion::V1,
+    protocol_version: versions.protocol_version(),
+    delegate_version: versions.delegate_version(),
     memberlist_delegate_version: MemberlistDelegateVersion::V1,
     memberlist_protocol_version: MemberlistProtocolVersion::V1,
+    secure,
+    app_meta,
+    addresses,
+    app_health: None,
   })
 }
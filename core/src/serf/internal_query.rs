@@ -3,13 +3,16 @@ use futures::FutureExt;
 use memberlist_core::{
   agnostic_lite::{AsyncSpawner, RuntimeLite},
   bytes::{BufMut, Bytes, BytesMut},
-  tracing,
+  tracing::{self, Instrument},
   transport::{AddressResolver, Transport},
 };
 
 use crate::{
+  app_query::APP_QUERY_PREFIX,
   delegate::{Delegate, TransformDelegate},
   event::{CrateEvent, InternalQueryEvent, QueryEvent},
+  lock::decode_acquire,
+  task::{TaskHeartbeat, TaskRegistry},
   types::MessageType,
 };
 
@@ -22,6 +25,9 @@ use crate::{
 #[cfg(feature = "encryption")]
 use smol_str::SmolStr;
 
+#[cfg(feature = "encryption")]
+use crate::event::INTERNAL_LIST_KEYS;
+
 /// Used to compute the max number of keys in a list key
 /// response. eg 1024/25 = 40. a message with max size of 1024 bytes cannot
 /// contain more than 40 keys. There is a test
@@ -38,6 +44,7 @@ where
   in_rx: Receiver<CrateEvent<T, D>>,
   out_tx: Option<Sender<CrateEvent<T, D>>>,
   shutdown_rx: Receiver<()>,
+  heartbeat: TaskHeartbeat,
 }
 
 impl<D, T> SerfQueries<T, D>
@@ -49,6 +56,7 @@ where
   pub(crate) fn new(
     out_tx: Option<Sender<CrateEvent<T, D>>>,
     shutdown_rx: Receiver<()>,
+    tasks: &TaskRegistry,
   ) -> (
     Sender<CrateEvent<T, D>>,
     <<T::Runtime as RuntimeLite>::Spawner as AsyncSpawner>::JoinHandle<()>,
@@ -58,14 +66,17 @@ where
       in_rx,
       out_tx,
       shutdown_rx,
+      heartbeat: tasks.register("queries_handler"),
     };
     (in_tx, this.stream())
   }
 
   /// A long running routine to ingest the event stream
   fn stream(self) -> <<T::Runtime as RuntimeLite>::Spawner as AsyncSpawner>::JoinHandle<()> {
-    <T::Runtime as RuntimeLite>::spawn(async move {
+    let span = self.heartbeat.span();
+    let task = async move {
       loop {
+        self.heartbeat.beat();
         futures::select! {
           ev = self.in_rx.recv().fuse() => {
             match ev {
@@ -92,16 +103,25 @@ where
           }
         }
       }
-    })
+    };
+    <T::Runtime as RuntimeLite>::spawn(task.instrument(span))
   }
 
   async fn handle_query(ev: CrateEvent<T, D>) {
     match ev {
       CrateEvent::InternalQuery { kind, query } => match kind {
-        InternalQueryEvent::Ping => {}
+        InternalQueryEvent::Ping => {
+          Self::handle_ping(&query).await;
+        }
         InternalQueryEvent::Conflict(conflict) => {
           Self::handle_conflict(&conflict, &query).await;
         }
+        InternalQueryEvent::AcquireLock => {
+          Self::handle_acquire_lock(&query).await;
+        }
+        InternalQueryEvent::Audit => {
+          Self::handle_audit(&query).await;
+        }
         #[cfg(feature = "encryption")]
         InternalQueryEvent::InstallKey => {
           Self::handle_install_key(&query).await;
@@ -118,11 +138,30 @@ where
         InternalQueryEvent::ListKey => {
           Self::handle_list_keys(&query).await;
         }
+        InternalQueryEvent::App => {
+          Self::handle_app_query(&query).await;
+        }
       },
       _ => unreachable!(),
     }
   }
 
+  /// Invoked when a member pings this node, the response carries the
+  /// coordinate, uptime and queue depths of this node.
+  async fn handle_ping(ev: &QueryEvent<T, D>) {
+    let payload = match Self::cached_response(ev) {
+      Some(payload) => payload,
+      None => {
+        let payload = ev.ctx.this.ping_payload().await;
+        Self::cache_response(ev, payload.clone());
+        payload
+      }
+    };
+    if let Err(e) = ev.respond(payload).await {
+      tracing::error!(target="ruserf", err=%e, "failed to respond to ping query");
+    }
+  }
+
   /// invoked when we get a query that is attempting to
   /// disambiguate a name conflict. They payload is a node name, and the response
   /// should the address we believe that node is at, if any.
@@ -183,6 +222,71 @@ where
     }
   }
 
+  /// Invoked when a member asks for the lease of a lock. The payload is the
+  /// ttl of the lease and the lock name, the response is a single byte which
+  /// is `1` if the lease was granted.
+  async fn handle_acquire_lock(ev: &QueryEvent<T, D>) {
+    let Some((name, ttl)) = decode_acquire(&ev.payload) else {
+      tracing::error!("ruserf: invalid acquire lock query payload");
+      return;
+    };
+
+    let granted = ev
+      .ctx
+      .this
+      .inner
+      .locks
+      .lock()
+      .grant(name, ev.from.id().clone(), ttl);
+    tracing::debug!(
+      "ruserf: {} lock {} to {}",
+      if granted { "granted" } else { "denied" },
+      name,
+      ev.from.id()
+    );
+
+    if let Err(e) = ev
+      .respond(Bytes::from_static(if granted { &[1] } else { &[0] }))
+      .await
+    {
+      tracing::error!(target="ruserf", err=%e, "failed to respond to acquire lock query");
+    }
+  }
+
+  /// Invoked when a member audits its member map against ours, the response
+  /// is the digest of our member map.
+  async fn handle_audit(ev: &QueryEvent<T, D>) {
+    let digest = match ev.ctx.this.member_digest().await {
+      Ok(digest) => digest,
+      Err(e) => {
+        tracing::error!(target="ruserf", err=%e, "failed to compute the member digest");
+        return;
+      }
+    };
+
+    if let Err(e) = ev.respond(digest.encode()).await {
+      tracing::error!(target="ruserf", err=%e, "failed to respond to audit query");
+    }
+  }
+
+  /// Invoked when a member sends an application query, answered by the
+  /// handler registered for its name, if any.
+  async fn handle_app_query(ev: &QueryEvent<T, D>) {
+    let Some(name) = ev.name.strip_prefix(APP_QUERY_PREFIX) else {
+      return;
+    };
+    let Some(handler) = ev.ctx.this.inner.app_queries.get(name) else {
+      tracing::debug!("ruserf: no handler for application query {}", name);
+      return;
+    };
+
+    if let Some(resp) = handler.handle(name, ev.payload.clone()).await {
+      if let Err(e) = ev.respond(resp).await {
+        tracing::error!(target="ruserf", err=%e, "failed to respond to application query");
+      }
+    }
+  }
+
   /// Invoked whenever a new encryption key is received from
   /// another member in the cluster, and handles the process of installing it onto
   /// the memberlist keyring. This type of query may fail if the provided key does
@@ -227,7 +331,12 @@ where
     let kr = q.ctx.this.inner.memberlist.keyring();
     match kr {
       Some(kr) => {
-        kr.insert(req.key.unwrap()).await;
+        // The primary key is kept apart from the other keys of the ring,
+        // installing it again would list it twice
+        let key = req.key.unwrap();
+        if kr.primary_key().await != key {
+          kr.insert(key).await;
+        }
         if q.ctx.this.inner.opts.keyring_file.is_some() {
           if let Err(e) = q.ctx.this.write_keyring_file().await {
             tracing::error!(err=%e, "ruserf: failed to write keyring file");
@@ -396,6 +505,13 @@ where
   #[cfg(feature = "encryption")]
   async fn handle_list_keys(ev: impl AsRef<QueryEvent<T, D>> + Send) {
     let q = ev.as_ref();
+    if let Some(payload) = Self::cached_response(q) {
+      if let Err(e) = q.respond(payload).await {
+        tracing::error!(target="ruserf", err=%e, "failed to respond to key query");
+      }
+      return;
+    }
+
     let mut response = KeyResponseMessage::default();
     if !q.ctx.this.encryption_enabled() {
       tracing::error!(
@@ -431,6 +547,34 @@ where
     }
   }
 
+  /// Returns the cached response to the query, see [`Options::internal_query_cache_ttl`](crate::Options::internal_query_cache_ttl).
+  fn cached_response(ev: &QueryEvent<T, D>) -> Option<Bytes> {
+    let inner = &ev.ctx.this.inner;
+    let payload =
+      inner
+        .response_cache
+        .as_ref()?
+        .lock()
+        .get(&ev.name, &ev.payload, inner.timer.now())?;
+
+    #[cfg(feature = "metrics")]
+    metrics::counter!(
+      "ruserf.query.cache_hits",
+      inner.opts.memberlist_options.metric_labels().iter()
+    )
+    .increment(1);
+    Some(payload)
+  }
+
+  fn cache_response(ev: &QueryEvent<T, D>, response: Bytes) {
+    let inner = &ev.ctx.this.inner;
+    if let Some(cache) = &inner.response_cache {
+      cache
+        .lock()
+        .insert(&ev.name, ev.payload.clone(), response, inner.timer.now());
+    }
+  }
+
   #[cfg(feature = "encryption")]
   pub(crate) fn key_list_response_with_correct_size(
     q: &QueryEvent<T, D>,
@@ -446,8 +590,9 @@ where
 
     // if the provided list of keys is smaller then the max allowed, just iterate over it
     // to avoid an out of bound access when truncating
-    let max_list_keys =
-      (q.ctx.this.inner.opts.query_response_size_limit / MIN_ENCODED_KEY_LENGTH).min(actual);
+    let max_list_keys = (q.ctx.this.inner.reloadable.load().query_response_size_limit
+      / MIN_ENCODED_KEY_LENGTH)
+      .min(actual);
 
     for i in (0..=max_list_keys).rev() {
       let expected_k_encoded_len = <D as TransformDelegate>::message_encoded_len(&*resp);
@@ -506,7 +651,7 @@ where
   #[cfg(feature = "encryption")]
   async fn send_key_response(q: &QueryEvent<T, D>, resp: &mut KeyResponseMessage) {
     match q.name.as_str() {
-      "ruserf-list-keys" => {
+      INTERNAL_LIST_KEYS => {
         let (raw, qresp) = match Self::key_list_response_with_correct_size(q, resp) {
           Ok((raw, qresp)) => (raw, qresp),
           Err(e) => {
@@ -514,12 +659,18 @@ where
             return;
           }
         };
+        Self::cache_response(q, qresp.payload.clone());
 
         if let Err(e) = q.respond_with_message_and_response(raw, qresp).await {
           tracing::error!(target="ruserf", err=%e, "failed to respond to key query");
         }
       }
       _ => {
+        // The keyring may have changed, the cached key listings are stale
+        if let Some(cache) = &q.ctx.this.inner.response_cache {
+          cache.lock().clear();
+        }
+
         let expected_encoded_len = <D as TransformDelegate>::message_encoded_len(&*resp);
         let mut raw = BytesMut::with_capacity(expected_encoded_len + 1); // +1 for the message type
         raw.put_u8(MessageType::KeyResponse as u8);
@@ -14,12 +14,16 @@ use memberlist_core::{
   types::{OneOrMore, SmallVec, TinyVec},
   CheapClone,
 };
+use rand::Rng;
+use smol_str::SmolStr;
 
 use crate::{
+  clock::{Deadline, Timer},
   delegate::{Delegate, TransformDelegate},
-  error::Error,
+  error::{Error, RelayError},
   types::{
-    Filter, LamportTime, Member, MemberStatus, MessageType, QueryMessage, QueryResponseMessage,
+    Epoch, Filter, LamportTime, Member, MemberStatus, MessageType, QueryFlag, QueryMessage,
+    QueryResponseMessage,
   },
 };
 
@@ -60,6 +64,20 @@ pub struct QueryParam<I> {
   )]
   request_ack: bool,
 
+  /// If true, the query only collects the acks, see [`QueryParam::with_ack_only`].
+  #[viewit(
+    getter(
+      const,
+      style = "move",
+      attrs(doc = "Returns if the query only collects the acks.")
+    ),
+    setter(attrs(
+      doc = "Sets if the query only collects the acks, to confirm the reachability of the members without application responders. The members meeting the filters ack the query without delivering it to the application, so no response can be received. Implies `request_ack`."
+    ))
+  )]
+  #[cfg_attr(feature = "serde", serde(default))]
+  ack_only: bool,
+
   /// Controls the number of duplicate responses to relay
   /// back to the sender through other nodes for redundancy.
   #[viewit(
@@ -90,6 +108,113 @@ pub struct QueryParam<I> {
   )]
   #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
   timeout: Duration,
+
+  /// If set, only the given number of alive members with the lowest
+  /// estimated round trip time process the query, see [`QueryParam::nearest`].
+  #[viewit(
+    getter(
+      const,
+      style = "move",
+      rename = "nearest_count",
+      attrs(doc = "Returns the number of the nearest members targeted by the query, if any.")
+    ),
+    setter(skip)
+  )]
+  #[cfg_attr(feature = "serde", serde(default))]
+  nearest: Option<usize>,
+
+  /// The number of the acks and the responses buffered until they are
+  /// received from the [`QueryResponse`]. If not provided, the number of the
+  /// online members is used.
+  #[viewit(
+    getter(
+      const,
+      style = "move",
+      attrs(doc = "Returns the number of the acks and the responses buffered, if set.")
+    ),
+    setter(attrs(
+      doc = "Sets the number of the acks and the responses buffered until they are received. The ones arriving while the buffer is full are dropped."
+    ))
+  )]
+  #[cfg_attr(feature = "serde", serde(default))]
+  size_hint: Option<usize>,
+
+  /// If set, every response received pushes the deadline of the query out
+  /// by this increment, up to the [`QueryParam::max_timeout`].
+  #[viewit(
+    getter(
+      const,
+      style = "move",
+      attrs(
+        doc = "Returns the increment the deadline of the query is pushed out by on every response, if set."
+      )
+    ),
+    setter(attrs(
+      doc = "Sets the increment the deadline of the query is pushed out by on every response, so the query is left open while the responses of the stragglers keep arriving."
+    ))
+  )]
+  #[cfg_attr(feature = "serde", serde(default, with = "humantime_serde"))]
+  extend_by: Option<Duration>,
+
+  /// The longest the query is left open when its deadline is pushed out by
+  /// the responses. If not provided, twice the timeout is used.
+  #[viewit(
+    getter(
+      const,
+      style = "move",
+      attrs(
+        doc = "Returns the longest the query is left open when its deadline is extended, if set."
+      )
+    ),
+    setter(attrs(
+      doc = "Sets the longest the query is left open when its deadline is extended by the responses, see [`QueryParam::extend_by`]."
+    ))
+  )]
+  #[cfg_attr(feature = "serde", serde(default, with = "humantime_serde"))]
+  max_timeout: Option<Duration>,
+
+  /// The member the responses are sent to instead of the requester, see
+  /// [`Serf::collect_query_responses`]. Only supported once every member
+  /// speaks [`ProtocolVersion::V2`](crate::types::ProtocolVersion::V2) or newer, the query
+  /// fails otherwise.
+  #[viewit(
+    getter(
+      const,
+      style = "ref",
+      attrs(doc = "Returns the id of the member collecting the responses, if set.")
+    ),
+    setter(attrs(
+      doc = "Sets the id of the member the responders send their responses to, directly and through the relays, instead of the requester. The acks are still sent to the requester. The member has to collect them with [`Serf::collect_query_responses`]."
+    ))
+  )]
+  #[cfg_attr(feature = "serde", serde(default))]
+  respond_via: Option<I>,
+}
+
+impl<I> QueryParam<I> {
+  /// Targets the query to the `n` alive members with the lowest estimated
+  /// round trip time from the local node, according to the network
+  /// coordinates cached when the query is sent.
+  ///
+  /// The members are added as an id filter, in addition to the other
+  /// filters. The members without a cached coordinate are never picked,
+  /// and querying fails if the coordinates are disabled.
+  #[inline]
+  pub fn nearest(mut self, n: usize) -> Self {
+    self.nearest = Some(n);
+    self
+  }
+}
+
+impl<I> QueryParam<I> {
+  /// Returns how the deadline of the query is extended by the responses,
+  /// if it is.
+  pub(crate) fn deadline_extension(&self) -> Option<DeadlineExtension> {
+    self.extend_by.map(|increment| {
+      let max_timeout = self.max_timeout.unwrap_or(self.timeout * 2);
+      DeadlineExtension::new(increment, max_timeout.saturating_sub(self.timeout))
+    })
+  }
 }
 
 impl<I> QueryParam<I>
@@ -109,6 +234,163 @@ where
   }
 }
 
+/// Prefixes the payload of a query with the id of the member collecting the
+/// responses, see [`QueryFlag::RESPOND_VIA`].
+pub(crate) fn encode_respond_via<W: TransformDelegate>(
+  id: &W::Id,
+  payload: &[u8],
+) -> Result<Bytes, W::Error> {
+  let id_len = W::id_encoded_len(id);
+  let mut buf = BytesMut::with_capacity(id_len + payload.len());
+  buf.resize(id_len, 0);
+  let len = W::encode_id(id, &mut buf)?;
+  buf.truncate(len);
+  buf.put_slice(payload);
+  Ok(buf.freeze())
+}
+
+/// Splits the id of the member collecting the responses off the payload of
+/// a query produced by [`encode_respond_via`].
+pub(crate) fn decode_respond_via<W: TransformDelegate>(
+  payload: &Bytes,
+) -> Result<(W::Id, Bytes), W::Error> {
+  let (len, id) = W::decode_id(payload)?;
+  Ok((id, payload.slice(len..)))
+}
+
+/// Builds a query, see [`Serf::query_builder`].
+pub struct QueryBuilder<'a, T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  serf: &'a Serf<T, D>,
+  name: SmolStr,
+  payload: Bytes,
+  params: QueryParam<T::Id>,
+}
+
+impl<T, D> QueryBuilder<'_, T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  /// Sets the payload of the query, empty by default.
+  #[inline]
+  pub fn with_payload(mut self, payload: impl Into<Bytes>) -> Self {
+    self.payload = payload.into();
+    self
+  }
+
+  /// Sets how long the query is left open, instead of the
+  /// [`Serf::default_query_timeout`].
+  #[inline]
+  pub fn with_timeout(mut self, timeout: Duration) -> Self {
+    self.params.timeout = timeout;
+    self
+  }
+
+  /// Sets the number of duplicate responses to relay back through other
+  /// nodes for redundancy.
+  #[inline]
+  pub fn with_relay_factor(mut self, relay_factor: u8) -> Self {
+    self.params.relay_factor = relay_factor;
+    self
+  }
+
+  /// Only the members with one of the given ids process the query. Can be
+  /// combined with other filters, which all have to be met.
+  #[inline]
+  pub fn with_ids(mut self, ids: impl IntoIterator<Item = T::Id>) -> Self {
+    self
+      .params
+      .filters
+      .push(Filter::Id(ids.into_iter().collect()));
+    self
+  }
+
+  /// Only the members whose `tag` matches the regular expression `expr`
+  /// process the query.
+  #[inline]
+  pub fn with_tag(mut self, tag: impl Into<SmolStr>, expr: impl Into<SmolStr>) -> Self {
+    self.params.filters.push(Filter::Tag {
+      tag: tag.into(),
+      expr: expr.into(),
+    });
+    self
+  }
+
+  /// Sets if the members processing the query ack it, see
+  /// [`QueryParam::with_request_ack`].
+  #[inline]
+  pub fn with_ack(mut self, ack: bool) -> Self {
+    self.params.request_ack = ack;
+    self
+  }
+
+  /// Sets if the query only collects the acks, see
+  /// [`QueryParam::with_ack_only`].
+  #[inline]
+  pub fn with_ack_only(mut self, ack_only: bool) -> Self {
+    self.params.ack_only = ack_only;
+    self
+  }
+
+  /// Leaves the query open while the responses arrive: every response
+  /// pushes the deadline out by `increment`, until the query has been open
+  /// for `max_timeout`. Large clusters with stragglers do not have to guess
+  /// a fixed timeout this way.
+  #[inline]
+  pub fn with_extension(mut self, increment: Duration, max_timeout: Duration) -> Self {
+    self.params.extend_by = Some(increment);
+    self.params.max_timeout = Some(max_timeout);
+    self
+  }
+
+  /// Targets the query to the `n` nearest alive members, see
+  /// [`QueryParam::nearest`].
+  #[inline]
+  pub fn with_nearest(mut self, n: usize) -> Self {
+    self.params.nearest = Some(n);
+    self
+  }
+
+  /// Sets the number of the acks and the responses buffered until they are
+  /// received, e.g. the number of the expected destinations of a narrowly
+  /// filtered query in a large cluster.
+  #[inline]
+  pub fn with_size_hint(mut self, size_hint: usize) -> Self {
+    self.params.size_hint = Some(size_hint);
+    self
+  }
+
+  /// Sends the responses to the member `id` instead of the local node, see
+  /// [`QueryParam::with_respond_via`].
+  #[inline]
+  pub fn with_respond_via(mut self, id: T::Id) -> Self {
+    self.params.respond_via = Some(id);
+    self
+  }
+
+  /// Returns the parameters of the query built so far. The timeout is zero
+  /// if not set, i.e. the default one.
+  #[inline]
+  pub const fn params(&self) -> &QueryParam<T::Id> {
+    &self.params
+  }
+
+  /// Sends the query, see [`Serf::query`].
+  pub async fn send(
+    self,
+  ) -> Result<QueryResponse<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>, Error<T, D>>
+  {
+    self
+      .serf
+      .query_in(self.name, self.payload, Some(self.params), None)
+      .await
+  }
+}
+
 struct QueryResponseChannel<I, A> {
   /// Used to send the name of a node for which we've received an ack
   ack_ch: Option<(Sender<Node<I, A>>, Receiver<Node<I, A>>)>,
@@ -120,6 +402,84 @@ pub(crate) struct QueryResponseCore<I, A> {
   closed: bool,
   acks: HashSet<Node<I, A>>,
   responses: HashSet<Node<I, A>>,
+  /// The subscribers of [`QueryResponse::progress`]
+  progress: Vec<Sender<QueryProgress>>,
+  /// Pushes out the deadline on every response, if set
+  extension: Option<DeadlineExtension>,
+}
+
+/// Pushes out the deadline of a query as the responses arrive, see
+/// [`QueryParam::extend_by`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DeadlineExtension {
+  increment: Duration,
+  /// How much longer the query can still be left open
+  remaining: Duration,
+  /// The extension not yet waited for by the closing task
+  pending: Duration,
+}
+
+impl DeadlineExtension {
+  #[inline]
+  pub(crate) const fn new(increment: Duration, max_extension: Duration) -> Self {
+    Self {
+      increment,
+      remaining: max_extension,
+      pending: Duration::ZERO,
+    }
+  }
+
+  fn extend(&mut self) {
+    let extension = self.increment.min(self.remaining);
+    self.remaining -= extension;
+    self.pending += extension;
+  }
+}
+
+impl<I, A> QueryResponseCore<I, A> {
+  #[inline]
+  fn progress(&self, expected: usize) -> QueryProgress {
+    QueryProgress {
+      responded: self.responses.len(),
+      acked: self.acks.len(),
+      expected,
+    }
+  }
+
+  fn notify_progress(&mut self, expected: usize) {
+    let progress = self.progress(expected);
+    self.progress.retain(|tx| tx.try_send(progress).is_ok());
+  }
+}
+
+/// The progress of a query, see [`QueryResponse::progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct QueryProgress {
+  responded: usize,
+  acked: usize,
+  expected: usize,
+}
+
+impl QueryProgress {
+  /// Returns the number of the nodes which responded so far.
+  #[inline]
+  pub const fn responded(&self) -> usize {
+    self.responded
+  }
+
+  /// Returns the number of the nodes which acked so far, always `0` if the
+  /// query did not specify `request_ack`.
+  #[inline]
+  pub const fn acked(&self) -> usize {
+    self.acked
+  }
+
+  /// Returns the number of the nodes expected to process the query, see
+  /// [`QueryResponse::expected`].
+  #[inline]
+  pub const fn expected(&self) -> usize {
+    self.expected
+  }
 }
 
 pub(crate) struct QueryResponseInner<I, A> {
@@ -132,16 +492,9 @@ pub(crate) struct QueryResponseInner<I, A> {
 #[viewit::viewit(vis_all = "pub(crate)")]
 #[derive(Clone)]
 pub struct QueryResponse<I, A> {
-  /// The duration of the query
-  #[viewit(
-    getter(
-      style = "move",
-      const,
-      attrs(doc = "Returns the ending deadline of the query")
-    ),
-    setter(skip)
-  )]
-  deadline: Instant,
+  /// The ending deadline of the query
+  #[viewit(getter(skip), setter(skip))]
+  deadline: Deadline,
 
   /// The query id
   #[viewit(
@@ -161,20 +514,64 @@ pub struct QueryResponse<I, A> {
   )]
   ltime: LamportTime,
 
+  /// The number of the alive members meeting the filters when the query was sent
+  #[viewit(
+    getter(
+      style = "move",
+      const,
+      attrs(
+        doc = "Returns the number of the alive members, the local node included, meeting the filters of the query when it was sent. The members joining or failing afterwards are not accounted for, and the members with an invalid tag filter never respond."
+      )
+    ),
+    setter(skip)
+  )]
+  expected: usize,
+
   #[viewit(getter(vis = "pub(crate)", const, style = "ref"), setter(skip))]
   inner: Arc<QueryResponseInner<I, A>>,
 }
 
 impl<I, A> QueryResponse<I, A> {
-  pub(crate) fn from_query(q: &QueryMessage<I, A>, num_nodes: usize) -> Self {
+  pub(crate) fn from_query(
+    timer: &Timer,
+    q: &QueryMessage<I, A>,
+    num_nodes: usize,
+    expected: usize,
+    extension: Option<DeadlineExtension>,
+  ) -> Self {
+    // The extended queries are closed once their deadline stops moving, at
+    // the latest when all the extension has been used up
+    let timeout = match extension {
+      Some(extension) => q.timeout() + extension.remaining,
+      None => q.timeout(),
+    };
     QueryResponse::new(
       q.id(),
       q.ltime(),
       num_nodes,
-      Instant::now() + q.timeout(),
+      expected,
+      timer.deadline(timeout),
       q.ack(),
+      extension,
     )
   }
+
+  /// Returns the ending deadline of the query, the latest one if the
+  /// responses push it out, see [`QueryParam::extend_by`].
+  #[inline]
+  pub fn deadline(&self) -> Instant {
+    self.deadline.instant()
+  }
+
+  /// Returns the extension of the deadline accumulated since the last call,
+  /// zero once the query is to be closed.
+  pub(crate) async fn take_extension(&self) -> Duration {
+    let mut c = self.inner.core.write().await;
+    match c.extension.as_mut() {
+      Some(extension) => core::mem::take(&mut extension.pending),
+      None => Duration::ZERO,
+    }
+  }
 }
 
 impl<I, A> QueryResponse<I, A> {
@@ -183,8 +580,10 @@ impl<I, A> QueryResponse<I, A> {
     id: u32,
     ltime: LamportTime,
     num_nodes: usize,
-    deadline: Instant,
+    expected: usize,
+    deadline: Deadline,
     ack: bool,
+    extension: Option<DeadlineExtension>,
   ) -> Self {
     let (ack_ch, acks) = if ack {
       (
@@ -199,11 +598,14 @@ impl<I, A> QueryResponse<I, A> {
       deadline,
       id,
       ltime,
+      expected,
       inner: Arc::new(QueryResponseInner {
         core: RwLock::new(QueryResponseCore {
           closed: false,
           acks,
           responses: HashSet::with_capacity(num_nodes),
+          progress: Vec::new(),
+          extension,
         }),
         channel: QueryResponseChannel {
           ack_ch,
@@ -221,6 +623,41 @@ impl<I, A> QueryResponse<I, A> {
     self.inner.channel.ack_ch.as_ref().map(|(_, r)| r.clone())
   }
 
+  /// Returns the acks as a stream, which ends when the query is finished.
+  /// The stream is empty if the query did not specify `request_ack`.
+  #[inline]
+  pub fn acks(&self) -> impl futures::Stream<Item = Node<I, A>> + Send + 'static
+  where
+    I: Send + 'static,
+    A: Send + 'static,
+  {
+    match self.ack_rx() {
+      Some(rx) => futures::future::Either::Left(rx),
+      None => futures::future::Either::Right(futures::stream::empty()),
+    }
+  }
+
+  /// Returns the number of acks received so far.
+  #[inline]
+  pub async fn num_acks(&self) -> usize {
+    self.inner.core.read().await.acks.len()
+  }
+
+  /// Returns the progress of the query as a stream, which ends when the
+  /// query is finished.
+  ///
+  /// The current progress is yielded first, then a new one on every ack and
+  /// response, e.g. to show how many of the expected nodes responded.
+  pub async fn progress(&self) -> impl futures::Stream<Item = QueryProgress> + Send + 'static {
+    let (tx, rx) = async_channel::unbounded();
+    let mut c = self.inner.core.write().await;
+    let _ = tx.try_send(c.progress(self.expected));
+    if !c.closed {
+      c.progress.push(tx);
+    }
+    rx
+  }
+
   /// Returns a receiver that can be used to listen for responses.
   /// Channel will be closed when the query is finished.
   #[inline]
@@ -232,7 +669,7 @@ impl<I, A> QueryResponse<I, A> {
   #[inline]
   pub async fn finished(&self) -> bool {
     let c = self.inner.core.read().await;
-    c.closed || (Instant::now() > self.deadline)
+    c.closed || self.deadline.is_expired()
   }
 
   /// Used to close the query, which will close the underlying
@@ -251,6 +688,8 @@ impl<I, A> QueryResponse<I, A> {
     }
 
     self.inner.channel.resp_ch.0.close();
+    // Ends the progress streams
+    c.progress.clear();
   }
 
   #[inline]
@@ -267,7 +706,7 @@ impl<I, A> QueryResponse<I, A> {
   {
     // Check if the query is closed
     let c = self.inner.core.read().await;
-    if c.closed || (Instant::now() > self.deadline) {
+    if c.closed || self.deadline.is_expired() {
       return;
     }
 
@@ -309,6 +748,7 @@ impl<I, A> QueryResponse<I, A> {
 
       if let Err(e) = self
         .send_response::<T, D>(NodeResponse {
+          relayed: resp.relayed(),
           from: resp.from,
           payload: resp.payload,
         })
@@ -341,6 +781,10 @@ impl<I, A> QueryResponse<I, A> {
       futures::select! {
         _ = self.inner.channel.resp_ch.0.send(nr).fuse() => {
           c.responses.insert(id);
+          if let Some(extension) = c.extension.as_mut() {
+            extension.extend();
+          }
+          c.notify_progress(self.expected);
           Ok(())
         },
         default => {
@@ -374,6 +818,7 @@ impl<I, A> QueryResponse<I, A> {
       futures::select! {
         _ = tx.send(nr.from.cheap_clone()).fuse() => {
           c.acks.insert(nr.from.clone());
+          c.notify_progress(self.expected);
           Ok(())
         },
         default => {
@@ -399,10 +844,54 @@ pub struct NodeResponse<I, A> {
   from: Node<I, A>,
   #[viewit(getter(attrs(doc = "Returns the payload of the response")))]
   payload: Bytes,
+  #[viewit(getter(
+    const,
+    style = "move",
+    attrs(
+      doc = "Returns `true` if the response was relayed through another node, instead of being sent directly by the responder"
+    )
+  ))]
+  relayed: bool,
+}
+
+/// Ties the tracing spans of the messages which belong together.
+///
+/// A query, its acks, its responses and the relayed copies of them carry the
+/// same Lamport time and query id, so they share the correlation id on every
+/// node they pass through. The other messages get a random id for the hop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CorrelationId {
+  ltime: LamportTime,
+  id: u32,
+}
+
+impl CorrelationId {
+  #[inline]
+  pub(crate) const fn query(ltime: LamportTime, id: u32) -> Self {
+    Self { ltime, id }
+  }
+
+  #[inline]
+  pub(crate) fn generate(ltime: LamportTime) -> Self {
+    Self {
+      ltime,
+      id: rand::random(),
+    }
+  }
+}
+
+impl core::fmt::Display for CorrelationId {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "{}-{:08x}", self.ltime, self.id)
+  }
 }
 
 #[inline]
-fn random_members<I, A>(k: usize, mut members: SmallVec<Member<I, A>>) -> SmallVec<Member<I, A>> {
+fn random_members<I, A>(
+  k: usize,
+  mut members: SmallVec<Member<I, A>>,
+  rng: &mut impl Rng,
+) -> SmallVec<Member<I, A>> {
   let n = members.len();
   if n == 0 {
     return SmallVec::new();
@@ -413,7 +902,7 @@ fn random_members<I, A>(k: usize, mut members: SmallVec<Member<I, A>>) -> SmallV
   let mut i = 0;
 
   while i < rounds && i < n {
-    let j = rand::random::<usize>() % (n - i) + i;
+    let j = rng.gen_range(i..n);
     members.swap(i, j);
     i += 1;
     if i >= k && i >= rounds {
@@ -425,6 +914,25 @@ fn random_members<I, A>(k: usize, mut members: SmallVec<Member<I, A>>) -> SmallV
   members
 }
 
+/// Returns the `k` members nearest to the destination by `distance`, the
+/// members whose distance is unknown come last in random order.
+fn nearest_members<I, A>(
+  k: usize,
+  members: SmallVec<Member<I, A>>,
+  rng: &mut impl Rng,
+  distance: impl Fn(&Member<I, A>) -> Option<Duration>,
+) -> SmallVec<Member<I, A>> {
+  // Shuffle first so the ties, and the members without a distance, are picked at random
+  let n = members.len();
+  let mut members = random_members(n, members, rng);
+  members.sort_by_cached_key(|m| match distance(m) {
+    Some(d) => (false, d),
+    None => (true, Duration::ZERO),
+  });
+  members.truncate(k);
+  members
+}
+
 impl<T, D> Serf<T, D>
 where
   D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
@@ -438,7 +946,7 @@ where
   pub async fn default_query_timeout(&self) -> Duration {
     let n = self.inner.memberlist.num_online_members().await;
     let mut timeout = self.inner.opts.memberlist_options.gossip_interval();
-    timeout *= self.inner.opts.query_timeout_mult as u32;
+    timeout *= self.inner.reloadable.load().query_timeout_mult as u32;
     timeout *= ((n + 1) as f64).log10().ceil() as u32; // Using ceil approximation
     timeout
   }
@@ -448,11 +956,158 @@ where
     QueryParam {
       filters: OneOrMore::new(),
       request_ack: false,
+      ack_only: false,
       relay_factor: 0,
       timeout: self.default_query_timeout().await,
+      nearest: None,
+      size_hint: None,
+      extend_by: None,
+      max_timeout: None,
+      respond_via: None,
+    }
+  }
+
+  /// Collects the responses to the query `id` sent at `ltime` by another
+  /// member, which named the local node with
+  /// [`QueryParam::with_respond_via`]. The requester shares the id and the
+  /// Lamport time of the query, from its [`QueryResponse`], out of band.
+  ///
+  /// The responses are collected for `timeout`, which should match the
+  /// timeout of the query. Only the responses arrive here, the acks are
+  /// still sent to the requester, so the expected number of responders is
+  /// unknown and [`QueryResponse::expected`] is always `0`. A query of the
+  /// local node sent at the same Lamport time replaces the collection.
+  pub async fn collect_query_responses(
+    &self,
+    ltime: LamportTime,
+    id: u32,
+    timeout: Duration,
+  ) -> QueryResponse<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress> {
+    let resp = QueryResponse::new(
+      id,
+      ltime,
+      self.inner.memberlist.num_online_members().await,
+      0,
+      self.inner.timer.deadline(timeout),
+      false,
+      None,
+    );
+    self.register_query_response(timeout, resp.clone()).await;
+    resp
+  }
+
+  /// Returns a builder for a query named `name`, sent with
+  /// [`QueryBuilder::send`]. The parameters not set on the builder take
+  /// their default value, see [`Serf::default_query_param`].
+  #[inline]
+  pub fn query_builder(&self, name: impl Into<SmolStr>) -> QueryBuilder<'_, T, D> {
+    QueryBuilder {
+      serf: self,
+      name: name.into(),
+      payload: Bytes::new(),
+      params: QueryParam {
+        filters: OneOrMore::new(),
+        request_ack: false,
+        ack_only: false,
+        relay_factor: 0,
+        timeout: Duration::ZERO,
+        nearest: None,
+        size_hint: None,
+        extend_by: None,
+        max_timeout: None,
+        respond_via: None,
+      },
     }
   }
 
+  /// Sends several queries at once, e.g. for a control plane probing a few
+  /// facts together, and returns their responses in the same order.
+  ///
+  /// All the queries are validated before any is sent, so none is sent if
+  /// one of them fails. Their broadcasts are queued together, to share the
+  /// gossip rounds rather than paying the overhead of each query on its own.
+  /// The builders must have been created by [`Serf::query_builder`] on this
+  /// node.
+  pub async fn multi_query(
+    &self,
+    queries: Vec<QueryBuilder<'_, T, D>>,
+  ) -> Result<
+    Vec<QueryResponse<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>>,
+    Error<T, D>,
+  > {
+    let mut prepared = Vec::with_capacity(queries.len());
+    for query in queries {
+      debug_assert!(
+        Arc::ptr_eq(&query.serf.inner, &self.inner),
+        "the query was built for another serf"
+      );
+      prepared.push(
+        self
+          .prepare_query(query.name, query.payload, Some(query.params), None)
+          .await?,
+      );
+    }
+
+    let resps = prepared.iter().map(|q| q.resp().clone()).collect();
+    self.dispatch_queries(prepared).await?;
+    Ok(resps)
+  }
+
+  /// Returns the ids of the `n` alive members, other than the local node,
+  /// with the lowest estimated round trip time.
+  pub(crate) async fn nearest_members(&self, n: usize) -> Result<TinyVec<T::Id>, Error<T, D>> {
+    let Some(coord) = self.inner.coord_core.as_ref() else {
+      return Err(Error::coordinates_disabled());
+    };
+
+    let local_id = self.inner.memberlist.local_id();
+    let local = coord.client.get_coordinate();
+    let mut candidates = {
+      let members = self.inner.members.read().await;
+      let cache = coord.cache.read();
+      members
+        .states
+        .iter()
+        .filter(|(id, m)| *id != local_id && m.member.status == MemberStatus::Alive)
+        .filter_map(|(id, _)| {
+          cache
+            .get(id)
+            .map(|other| (local.distance_to(other), id.cheap_clone()))
+        })
+        .collect::<Vec<_>>()
+    };
+    candidates.sort_by_key(|(distance, _)| *distance);
+    Ok(candidates.into_iter().take(n).map(|(_, id)| id).collect())
+  }
+
+  /// Returns the number of the alive members, the local node included,
+  /// meeting the filters of a query.
+  pub(crate) async fn expected_responders(&self, filters: &[Filter<T::Id>]) -> usize {
+    let mut tags = Vec::new();
+    for filter in filters.iter() {
+      if let Filter::Tag { tag, expr } = filter {
+        match regex::Regex::new(expr) {
+          Ok(re) => tags.push((tag, re)),
+          // No member processes a query with an invalid filter
+          Err(_) => return 0,
+        }
+      }
+    }
+
+    // The tag index only returns the members matching every tag filter
+    let members = self.inner.members.read().await;
+    members
+      .states_tagged(tags.iter().map(|(tag, re)| (tag.as_str(), re)))
+      .filter(|m| m.member.status == MemberStatus::Alive)
+      .filter(|m| {
+        filters.iter().all(|filter| match filter {
+          Filter::Id(ids) => ids.iter().any(|id| id.eq(m.member.node.id())),
+          Filter::Tag { .. } => true,
+        })
+      })
+      .count()
+  }
+
   pub(crate) fn should_process_query(&self, filters: &[Bytes]) -> bool {
     for filter in filters.iter() {
       if filter.is_empty() {
@@ -515,12 +1170,15 @@ where
     &self,
     relay_factor: u8,
     node: Node<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
-    resp: QueryResponseMessage<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
+    mut resp: QueryResponseMessage<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
   ) -> Result<(), Error<T, D>> {
     if relay_factor == 0 {
       return Ok(());
     }
 
+    // Let the requester know this copy did not come straight from the responder
+    resp.flags |= QueryFlag::RELAYED;
+
     // Needs to be worth it; we need to have at least relayFactor *other*
     // nodes. If you have a tiny cluster then the relayFactor shouldn't
     // be needed.
@@ -547,14 +1205,15 @@ where
     }
 
     // Prep the relay message, which is a wrapped version of the original.
+    let node = self.outbound_node(&node);
     // let relay_msg = SerfRelayMessage::new(node, SerfMessage::QueryResponse(resp));
     let expected_encoded_len = 1
       + <D as TransformDelegate>::node_encoded_len(&node)
       + 1
       + <D as TransformDelegate>::message_encoded_len(&resp); // +1 for relay message type byte, +1 for the message type
-    if expected_encoded_len > self.inner.opts.query_response_size_limit {
+    if expected_encoded_len > self.inner.reloadable.load().query_response_size_limit {
       return Err(Error::relayed_response_too_large(
-        self.inner.opts.query_response_size_limit,
+        self.inner.reloadable.load().query_response_size_limit,
       ));
     }
 
@@ -576,19 +1235,77 @@ where
     );
 
     let raw = raw.freeze();
-    // Relay to a random set of peers.
-    let relay_members = random_members(relay_factor as usize, members);
+    // Skip the peers the previous relays consistently failed through
+    let mut skipped = TinyVec::new();
+    let members = match &self.inner.relay_circuits {
+      Some(circuits) => {
+        let circuits = circuits.lock();
+        let now = Epoch::now();
+        members
+          .into_iter()
+          .filter(|m| {
+            let allowed = circuits.allow(m.node.id(), now);
+            if !allowed {
+              skipped.push(m.clone());
+            }
+            allowed
+          })
+          .collect()
+      }
+      None => members,
+    };
+    for m in skipped.iter() {
+      tracing::debug!(
+        "ruserf: skipped relaying response through {}, circuit is open",
+        m.node.id()
+      );
+    }
+
+    // Relay through the peers nearest to the destination, if the coordinates are enabled
+    // and the destination has one, otherwise through a random set of peers.
+    let dest = self
+      .inner
+      .coord_core
+      .as_ref()
+      .and_then(|c| c.cache.read().get(node.id()).cloned().map(|dest| (c, dest)));
+    let relay_members = match dest {
+      Some((coord, dest)) => {
+        let cache = coord.cache.read();
+        nearest_members(
+          relay_factor as usize,
+          members,
+          &mut *self.inner.rng.lock(),
+          |m| {
+            cache
+              .get(m.node.id())
+              .filter(|c| c.is_compatible_with(&dest))
+              .map(|c| c.distance_to(&dest))
+          },
+        )
+      }
+      None => random_members(relay_factor as usize, members, &mut *self.inner.rng.lock()),
+    };
 
     let futs: FuturesUnordered<_> = relay_members
       .into_iter()
       .map(|m| {
         let raw = raw.clone();
         async move {
-          self
+          let res = self
             .inner
             .memberlist
-            .send(m.node.address(), raw)
-            .await
+            .send(m.node.address(), raw.clone())
+            .await;
+          if let Some(circuits) = &self.inner.relay_circuits {
+            match &res {
+              Ok(_) => circuits.lock().record_success(m.node.id()),
+              Err(_) => circuits
+                .lock()
+                .record_failure(m.node.id().cheap_clone(), Epoch::now()),
+            }
+          }
+          res
+            .map(|_| self.inner.bandwidth.sent(&raw))
             .map_err(|e| (m, e))
         }
       })
@@ -608,6 +1325,81 @@ where
       errs.push(err);
     }
 
-    Ok(())
+    if errs.is_empty() {
+      return Ok(());
+    }
+    Err(Error::relay(RelayError {
+      failures: Box::new(errs),
+      skipped: Box::new(skipped),
+    }))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{collections::HashMap, net::SocketAddr};
+
+  use rand::{rngs::StdRng, SeedableRng};
+
+  use crate::{delegate::LpeTransfromDelegate, types::Tags};
+
+  use super::*;
+
+  type Lpe = LpeTransfromDelegate<SmolStr, SocketAddr>;
+
+  #[test]
+  fn test_respond_via_header() {
+    let id = SmolStr::new("collector");
+    let payload = encode_respond_via::<Lpe>(&id, b"payload").unwrap();
+    let (decoded, rest) = decode_respond_via::<Lpe>(&payload).unwrap();
+    assert_eq!(decoded, id);
+    assert_eq!(rest, Bytes::from_static(b"payload"));
+
+    // An empty payload still names the collector
+    let payload = encode_respond_via::<Lpe>(&id, &[]).unwrap();
+    let (decoded, rest) = decode_respond_via::<Lpe>(&payload).unwrap();
+    assert_eq!(decoded, id);
+    assert!(rest.is_empty());
+
+    assert!(decode_respond_via::<Lpe>(&Bytes::new()).is_err());
+  }
+
+  #[test]
+  fn test_nearest_members() {
+    let addr: SocketAddr = "127.0.0.1:7946".parse().unwrap();
+    let members = (0..5)
+      .map(|i| {
+        Member::new(
+          Node::new(SmolStr::new(format!("node-{i}")), addr),
+          Tags::default(),
+          MemberStatus::Alive,
+        )
+      })
+      .collect::<SmallVec<_>>();
+    let distances = [("node-3", 5), ("node-1", 10), ("node-4", 20)]
+      .into_iter()
+      .map(|(id, ms)| (SmolStr::new(id), Duration::from_millis(ms)))
+      .collect::<HashMap<_, _>>();
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let nearest = nearest_members(2, members.clone(), &mut rng, |m| {
+      distances.get(m.node.id()).copied()
+    });
+    let ids = nearest
+      .iter()
+      .map(|m| m.node.id().as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(ids, ["node-3", "node-1"]);
+
+    // The members without a distance come last
+    let nearest = nearest_members(5, members, &mut rng, |m| {
+      distances.get(m.node.id()).copied()
+    });
+    let ids = nearest
+      .iter()
+      .map(|m| m.node.id().as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(ids[..3], ["node-3", "node-1", "node-4"]);
+    assert!(ids[3..].contains(&"node-0") && ids[3..].contains(&"node-2"));
   }
 }
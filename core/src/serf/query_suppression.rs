@@ -0,0 +1,107 @@
+use std::{
+  collections::{hash_map::DefaultHasher, HashMap},
+  hash::{Hash, Hasher},
+  time::Duration,
+};
+
+use smol_str::SmolStr;
+
+use crate::types::Epoch;
+
+/// The number of remembered queries above which the expired ones are evicted.
+const MAX_IDLE_ENTRIES: usize = 1024;
+
+/// Drops the queries identical to one accepted less than the window ago,
+/// keyed by the source node, the query name and the hash of its payload, see
+/// [`Options::query_suppression_window`](crate::Options::query_suppression_window).
+pub(crate) struct QuerySuppressor<I> {
+  window: Duration,
+  seen: HashMap<(I, SmolStr, u64), Epoch>,
+}
+
+impl<I: Eq + Hash> QuerySuppressor<I> {
+  pub(crate) fn new(window: Duration) -> Self {
+    Self {
+      window,
+      seen: HashMap::new(),
+    }
+  }
+
+  /// Returns `true` if the query is accepted, `false` if it repeats a query
+  /// accepted within the window. Only the accepted queries open a new window,
+  /// so a client resubmitting in a tight loop gets one query through per
+  /// window.
+  pub(crate) fn allow(&mut self, from: &I, name: &SmolStr, payload: &[u8], now: Epoch) -> bool
+  where
+    I: Clone,
+  {
+    if self.suppressed(from, name, payload, now) {
+      return false;
+    }
+    self.record(from, name, payload, now);
+    true
+  }
+
+  /// Returns `true` if the query repeats a query accepted within the window,
+  /// without accepting it.
+  pub(crate) fn suppressed(&self, from: &I, name: &SmolStr, payload: &[u8], now: Epoch) -> bool
+  where
+    I: Clone,
+  {
+    self
+      .seen
+      .get(&Self::key(from, name, payload))
+      .is_some_and(|at| now - *at < self.window)
+  }
+
+  /// Accepts the query, opening a new window.
+  pub(crate) fn record(&mut self, from: &I, name: &SmolStr, payload: &[u8], now: Epoch)
+  where
+    I: Clone,
+  {
+    if self.seen.len() >= MAX_IDLE_ENTRIES {
+      let window = self.window;
+      self.seen.retain(|_, at| now - *at < window);
+    }
+    self.seen.insert(Self::key(from, name, payload), now);
+  }
+
+  fn key(from: &I, name: &SmolStr, payload: &[u8]) -> (I, SmolStr, u64)
+  where
+    I: Clone,
+  {
+    let mut hasher = DefaultHasher::new();
+    payload.hash(&mut hasher);
+    (from.clone(), name.clone(), hasher.finish())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_query_suppressor() {
+    let mut suppressor = QuerySuppressor::new(Duration::from_millis(100));
+    let now = Epoch::now();
+    let name = SmolStr::new("foo");
+    assert!(suppressor.allow(&1, &name, b"a", now));
+    assert!(!suppressor.allow(&1, &name, b"a", now));
+
+    // Keyed by the source, the name and the payload
+    assert!(suppressor.allow(&2, &name, b"a", now));
+    assert!(suppressor.allow(&1, &SmolStr::new("bar"), b"a", now));
+    assert!(suppressor.allow(&1, &name, b"b", now));
+
+    // The dropped queries do not extend the window
+    assert!(!suppressor.allow(&1, &name, b"a", now + Duration::from_millis(50)));
+    assert!(suppressor.allow(&1, &name, b"a", now + Duration::from_millis(100)));
+    assert!(!suppressor.allow(&1, &name, b"a", now + Duration::from_millis(150)));
+
+    // Checking a query does not accept it
+    assert!(!suppressor.suppressed(&3, &name, b"a", now));
+    assert!(!suppressor.suppressed(&3, &name, b"a", now));
+    suppressor.record(&3, &name, b"a", now);
+    assert!(suppressor.suppressed(&3, &name, b"a", now));
+  }
+}
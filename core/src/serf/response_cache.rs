@@ -0,0 +1,105 @@
+use std::{
+  collections::{hash_map::DefaultHasher, HashMap},
+  hash::{Hash, Hasher},
+  time::Duration,
+};
+
+use memberlist_core::bytes::Bytes;
+use smol_str::SmolStr;
+
+use crate::types::Epoch;
+
+/// The maximum number of cached responses, the idempotent internal queries
+/// are few and their payloads rarely vary.
+const MAX_ENTRIES: usize = 64;
+
+struct Entry {
+  payload: Bytes,
+  response: Bytes,
+  expires_at: Epoch,
+}
+
+/// Caches the responses to the idempotent internal queries, keyed by the
+/// query name and the hash of its payload, see
+/// [`Options::internal_query_cache_ttl`](crate::Options::internal_query_cache_ttl).
+pub(crate) struct ResponseCache {
+  ttl: Duration,
+  entries: HashMap<(SmolStr, u64), Entry>,
+}
+
+impl ResponseCache {
+  pub(crate) fn new(ttl: Duration) -> Self {
+    Self {
+      ttl,
+      entries: HashMap::new(),
+    }
+  }
+
+  fn key(name: &str, payload: &[u8]) -> (SmolStr, u64) {
+    let mut hasher = DefaultHasher::new();
+    payload.hash(&mut hasher);
+    (SmolStr::new(name), hasher.finish())
+  }
+
+  /// Returns the cached response to the query, if it has not expired.
+  pub(crate) fn get(&self, name: &str, payload: &[u8], now: Epoch) -> Option<Bytes> {
+    self
+      .entries
+      .get(&Self::key(name, payload))
+      // The payload is compared as well, in case of a hash collision
+      .filter(|e| e.expires_at > now && e.payload == payload)
+      .map(|e| e.response.clone())
+  }
+
+  pub(crate) fn insert(&mut self, name: &str, payload: Bytes, response: Bytes, now: Epoch) {
+    if self.entries.len() >= MAX_ENTRIES {
+      self.entries.retain(|_, e| e.expires_at > now);
+      if self.entries.len() >= MAX_ENTRIES {
+        return;
+      }
+    }
+
+    self.entries.insert(
+      Self::key(name, &payload),
+      Entry {
+        payload,
+        response,
+        expires_at: now + self.ttl,
+      },
+    );
+  }
+
+  /// Drops the cached responses, e.g. once the keyring changed.
+  pub(crate) fn clear(&mut self) {
+    self.entries.clear();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_response_cache() {
+    let now = Epoch::now();
+    let mut cache = ResponseCache::new(Duration::from_secs(1));
+    assert!(cache.get("ping", b"", now).is_none());
+
+    cache.insert("ping", Bytes::new(), Bytes::from_static(b"pong"), now);
+    assert_eq!(
+      cache.get("ping", b"", now).unwrap(),
+      Bytes::from_static(b"pong")
+    );
+    // Keyed by the payload as well
+    assert!(cache.get("ping", b"other", now).is_none());
+    assert!(cache.get("list", b"", now).is_none());
+
+    // Expired
+    assert!(cache
+      .get("ping", b"", now + Duration::from_secs(1))
+      .is_none());
+
+    cache.clear();
+    assert!(cache.get("ping", b"", now).is_none());
+  }
+}
@@ -0,0 +1,91 @@
+use std::{
+  future::{Future, IntoFuture},
+  pin::Pin,
+};
+
+use async_channel::{Receiver, Sender};
+
+/// A handle telling when a [`Serf`](crate::Serf) begins and completes its
+/// shutdown, see [`Serf::shutdown_signal`](crate::Serf::shutdown_signal).
+///
+/// The handle is cheap to clone, so every task of the embedder can hold
+/// one. Awaiting the handle itself waits for the completion, and
+/// [`started`](ShutdownSignal::started) and
+/// [`completed`](ShutdownSignal::completed) return futures which can be
+/// used as the branches of a `select!`.
+#[derive(Debug, Clone)]
+pub struct ShutdownSignal {
+  started: Receiver<()>,
+  completed: Receiver<()>,
+}
+
+impl ShutdownSignal {
+  /// Waits until the shutdown begins, returns right away if it already has.
+  pub async fn started(&self) {
+    let _ = self.started.recv().await;
+  }
+
+  /// Waits until the shutdown completes, returns right away if it already
+  /// has. The shutdown completes even if it fails or times out.
+  pub async fn completed(&self) {
+    let _ = self.completed.recv().await;
+  }
+
+  /// Returns `true` if the shutdown has begun.
+  #[inline]
+  pub fn is_started(&self) -> bool {
+    self.started.is_closed()
+  }
+
+  /// Returns `true` if the shutdown has completed.
+  #[inline]
+  pub fn is_completed(&self) -> bool {
+    self.completed.is_closed()
+  }
+}
+
+impl IntoFuture for ShutdownSignal {
+  type Output = ();
+
+  type IntoFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+  fn into_future(self) -> Self::IntoFuture {
+    Box::pin(async move { self.completed().await })
+  }
+}
+
+/// Signals the [`ShutdownSignal`]s handed out, by closing their channels.
+pub(crate) struct ShutdownNotifier {
+  started: Sender<()>,
+  completed: Sender<()>,
+  signal: ShutdownSignal,
+}
+
+impl ShutdownNotifier {
+  pub(crate) fn new() -> Self {
+    let (started, started_rx) = async_channel::bounded(1);
+    let (completed, completed_rx) = async_channel::bounded(1);
+    Self {
+      started,
+      completed,
+      signal: ShutdownSignal {
+        started: started_rx,
+        completed: completed_rx,
+      },
+    }
+  }
+
+  #[inline]
+  pub(crate) fn signal(&self) -> ShutdownSignal {
+    self.signal.clone()
+  }
+
+  pub(crate) fn start(&self) {
+    self.started.close();
+  }
+
+  pub(crate) fn complete(&self) {
+    self.started.close();
+    self.completed.close();
+  }
+}
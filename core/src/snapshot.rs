@@ -1,11 +1,13 @@
 use std::{
   borrow::Cow,
-  collections::HashSet,
+  collections::{BTreeMap, HashSet},
   fs::{File, OpenOptions},
-  io::{BufReader, BufWriter, Read, Seek, Write},
+  io::{BufRead, BufReader, BufWriter, Read, Seek, Write},
   mem,
-  path::PathBuf,
-  time::Duration,
+  ops::Range,
+  path::{Path, PathBuf},
+  sync::Arc,
+  time::{Duration, SystemTime},
 };
 
 #[cfg(unix)]
@@ -17,27 +19,33 @@ use futures::FutureExt;
 use memberlist_core::{
   agnostic_lite::{AsyncSpawner, RuntimeLite},
   bytes::{BufMut, BytesMut},
-  tracing,
+  tracing::{self, Instrument},
   transport::{AddressResolver, Id, MaybeResolvedAddress, Node, Transport},
   types::TinyVec,
   CheapClone,
 };
-use rand::seq::SliceRandom;
 use ruserf_types::UserEventMessage;
+use smol_str::SmolStr;
 
 use crate::{
-  delegate::{Delegate, TransformDelegate},
+  compression::{self, Compressor},
+  delegate::{Delegate, SnapshotCompaction, SnapshotDelegate, TransformDelegate},
   event::{CrateEvent, MemberEvent, MemberEventType},
   invalid_data_io_error,
+  task::{TaskHeartbeat, TaskState},
   types::{Epoch, LamportClock, LamportTime},
 };
 
+#[cfg(feature = "encryption")]
+mod cipher;
+#[cfg(feature = "encryption")]
+pub(crate) use cipher::SnapshotCipher;
+#[cfg(feature = "encryption")]
+use memberlist_core::types::SecretKey;
+
 /// How often we force a flush of the snapshot file
 const FLUSH_INTERVAL: Duration = Duration::from_millis(500);
 
-/// How often we fetch the current lamport time of the cluster and write to the snapshot file
-const CLOCK_UPDATE_INTERVAL: Duration = Duration::from_millis(500);
-
 /// The extention we use for the temporary file during compaction
 const TMP_EXT: &str = "compact";
 
@@ -55,10 +63,36 @@ const SHUTDOWN_FLUSH_TIMEOUT: Duration = Duration::from_millis(250);
 /// An estimated bytes per node to snapshot
 const SNAPSHOT_BYTES_PER_NODE: usize = 128;
 
+/// An estimated bytes per user event digest to snapshot
+const SNAPSHOT_BYTES_PER_EVENT: usize = 32;
+
 /// The threshold we apply to
 /// the snapshot size estimate (nodes * bytes per node) before compacting.
 const SNAPSHOT_COMPACTION_THRESHOLD: usize = 2;
 
+/// The magic bytes starting a framed snapshot. The first byte is not a
+/// record type, which tells the framed snapshots apart from the older ones.
+const MAGIC: [u8; 4] = *b"RSNP";
+
+/// The size of the magic bytes and the format version.
+const HEADER_SIZE: usize = MAGIC.len() + 1;
+
+/// The size of the length and the CRC32 prefixing each framed record.
+const FRAME_HEADER_SIZE: usize = 8;
+
+/// The on-disk format of a snapshot.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum SnapshotFormat {
+  /// The records follow each other unframed, so a partial write makes the
+  /// rest of the snapshot unreadable. Only read, and appended to until the
+  /// next compaction rewrites the snapshot in the framed format.
+  V1 = 1,
+  /// A header with the magic bytes and the version, then each record
+  /// prefixed with its length and its CRC32.
+  V2 = 2,
+}
+
 /// Errors that can occur while interacting with snapshots
 #[derive(Debug, thiserror::Error)]
 pub enum SnapshotError {
@@ -101,9 +135,26 @@ pub enum SnapshotError {
   /// Returned when replaying a snapshot fails
   #[error("failed to replay snapshot: {0}")]
   Replay(std::io::Error),
+  /// Returned when truncating the corrupted tail of a snapshot fails
+  #[error("failed to truncate corrupted snapshot: {0}")]
+  Truncate(std::io::Error),
+  /// Returned when the snapshot was written in a newer format.
+  #[error("unsupported snapshot format version: {0}")]
+  UnsupportedVersion(u8),
   /// Returned when fail to decode snapshot record type.
   #[error(transparent)]
   UnknownRecordType(#[from] UnknownRecordType),
+  /// Returned when a compressed segment cannot be decompressed, e.g. when
+  /// its algorithm is neither configured nor compiled in.
+  #[error("failed to decompress snapshot segment: {0}")]
+  Decompress(std::io::Error),
+  /// Returned when an encrypted record cannot be decrypted with any key of the keyring.
+  #[error("failed to decrypt snapshot record with any key of the keyring")]
+  Decrypt,
+  /// Returned when the snapshot encryption is enabled, but the transport has no keyring.
+  #[cfg(feature = "encryption")]
+  #[error("snapshot encryption requires a keyring")]
+  NoKeyring,
 }
 
 /// UnknownRecordType is used to indicate that we encountered an unknown
@@ -123,6 +174,9 @@ enum SnapshotRecordType {
   Coordinate = 5,
   Leave = 6,
   Comment = 7,
+  Encrypted = 8,
+  UserEvent = 9,
+  Compressed = 10,
 }
 
 impl TryFrom<u8> for SnapshotRecordType {
@@ -138,6 +192,9 @@ impl TryFrom<u8> for SnapshotRecordType {
       5 => Ok(Self::Coordinate),
       6 => Ok(Self::Leave),
       7 => Ok(Self::Comment),
+      8 => Ok(Self::Encrypted),
+      9 => Ok(Self::UserEvent),
+      10 => Ok(Self::Compressed),
       v => Err(UnknownRecordType(v)),
     }
   }
@@ -153,26 +210,41 @@ enum SnapshotRecord<'a, I: Clone, A: Clone> {
   Coordinate,
   Leave,
   Comment,
+  UserEvent(LamportTime, &'a EventDigest),
+  /// The id of the compressor and the compressed records.
+  Compressed(u8, &'a [u8]),
 }
 
 const MAX_INLINED_BYTES: usize = 64;
+const SCOPE_ID_SIZE: usize = mem::size_of::<u32>();
 
 macro_rules! encode {
   ($w:ident.$node: ident::$status: ident) => {{
     let node = $node.as_ref();
     let encoded_node_len = T::node_encoded_len(node);
-    let encoded_len = 4 + 1 + encoded_node_len;
+    // The IPv6 zone id is not part of the node encoding, so it is appended
+    // after the node. Replays which do not know about it just ignore the trailer.
+    let scope_id = T::address_scope_id(node.address());
+    let record_len = encoded_node_len + scope_id.map_or(0, |_| SCOPE_ID_SIZE);
+    let encoded_len = 4 + 1 + record_len;
     if encoded_len <= MAX_INLINED_BYTES {
       let mut buf = [0u8; MAX_INLINED_BYTES];
       buf[0] = Self::$status;
-      buf[1..5].copy_from_slice(&(encoded_node_len as u32).to_le_bytes());
+      buf[1..5].copy_from_slice(&(record_len as u32).to_le_bytes());
       T::encode_node(node, &mut buf[5..]).map_err(invalid_data_io_error)?;
+      if let Some(scope_id) = scope_id {
+        buf[5 + encoded_node_len..encoded_len].copy_from_slice(&scope_id.to_le_bytes());
+      }
       $w.write_all(&buf[..encoded_len]).map(|_| encoded_len)
     } else {
       let mut buf = BytesMut::with_capacity(encoded_len);
       buf.put_u8(Self::$status);
-      buf.put_u32_le(encoded_node_len as u32);
-      T::encode_node(node, &mut buf).map_err(invalid_data_io_error)?;
+      buf.put_u32_le(record_len as u32);
+      buf.resize(5 + encoded_node_len, 0);
+      T::encode_node(node, &mut buf[5..]).map_err(invalid_data_io_error)?;
+      if let Some(scope_id) = scope_id {
+        buf.put_u32_le(scope_id);
+      }
       $w.write_all(&buf).map(|_| encoded_len)
     }
   }};
@@ -201,6 +273,8 @@ where
   const COORDINATE: u8 = 5;
   const LEAVE: u8 = 6;
   const COMMENT: u8 = 7;
+  const USER_EVENT: u8 = 9;
+  const COMPRESSED: u8 = 10;
 
   fn encode<T: TransformDelegate<Id = I, Address = A>, W: Write>(
     &self,
@@ -215,19 +289,555 @@ where
       Self::Coordinate => encode!(w.COORDINATE),
       Self::Leave => encode!(w.LEAVE),
       Self::Comment => encode!(w.COMMENT),
+      Self::UserEvent(ltime, digest) => {
+        let record_len = 16 + digest.name.len();
+        let mut buf = BytesMut::with_capacity(5 + record_len);
+        buf.put_u8(Self::USER_EVENT);
+        buf.put_u32_le(record_len as u32);
+        buf.put_u64_le(u64::from(*ltime));
+        buf.put_u64_le(digest.hash);
+        buf.put_slice(digest.name.as_bytes());
+        w.write_all(&buf).map(|_| buf.len())
+      }
+      Self::Compressed(id, segment) => {
+        let mut header = [0u8; 6];
+        header[0] = Self::COMPRESSED;
+        header[1..5].copy_from_slice(&(segment.len() as u32 + 1).to_le_bytes());
+        header[5] = *id;
+        w.write_all(&header)?;
+        w.write_all(segment).map(|_| header.len() + segment.len())
+      }
+    }
+  }
+}
+
+/// Identifies a user event without keeping its payload, used to persist
+/// the deduplication window of the user events across restarts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct EventDigest {
+  name: SmolStr,
+  hash: u64,
+}
+
+impl EventDigest {
+  pub(crate) fn new(name: &SmolStr, payload: &[u8]) -> Self {
+    Self {
+      name: name.clone(),
+      hash: fnv1a(payload),
+    }
+  }
+
+  /// Returns `true` if the digest was computed from the given event.
+  pub(crate) fn matches(&self, name: &str, payload: &[u8]) -> bool {
+    self.name == name && self.hash == fnv1a(payload)
+  }
+
+  fn decode(buf: &[u8]) -> Result<(LamportTime, Self), SnapshotError> {
+    if buf.len() < 16 {
+      return Err(SnapshotError::Replay(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "user event record too short",
+      )));
+    }
+    let ltime = u64::from_le_bytes(buf[..8].try_into().unwrap());
+    let hash = u64::from_le_bytes(buf[8..16].try_into().unwrap());
+    let name = core::str::from_utf8(&buf[16..])
+      .map_err(|e| SnapshotError::Replay(invalid_data_io_error(e)))?;
+    Ok((
+      LamportTime::new(ltime),
+      Self {
+        name: SmolStr::new(name),
+        hash,
+      },
+    ))
+  }
+}
+
+/// The digests of the recently seen user events, by lamport time.
+pub(crate) type RecentEvents = BTreeMap<LamportTime, Vec<EventDigest>>;
+
+/// Reports a snapshot error to the [`SnapshotDelegate`], if any.
+pub(crate) fn notify_snapshot_error(delegate: Option<&dyn SnapshotDelegate>, err: &SnapshotError) {
+  if let Some(d) = delegate {
+    d.on_snapshot_error(err);
+  }
+}
+
+/// Drops the digests which fell out of the window ending at `latest`.
+pub(crate) fn trim_recent_events(recent: &mut RecentEvents, latest: LamportTime, window: u64) {
+  let low = LamportTime::new(u64::from(latest).saturating_sub(window));
+  *recent = recent.split_off(&low);
+}
+
+/// 64-bit FNV-1a, stable across builds unlike the std hashers.
+fn fnv1a(data: &[u8]) -> u64 {
+  data.iter().fold(0xcbf29ce484222325, |hash, b| {
+    (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+  })
+}
+
+/// Writes a record, encrypting it first if the snapshot is encrypted.
+fn write_record<I, A, T, W>(
+  record: SnapshotRecord<'_, I, A>,
+  w: &mut W,
+  #[cfg(feature = "encryption")] cipher: Option<&SnapshotCipher>,
+) -> std::io::Result<usize>
+where
+  I: Id,
+  A: CheapClone + Send + Sync + 'static,
+  T: TransformDelegate<Id = I, Address = A>,
+  W: Write,
+{
+  #[cfg(feature = "encryption")]
+  if let Some(cipher) = cipher {
+    let mut plain = Vec::new();
+    record.encode::<T, _>(&mut plain)?;
+    let sealed = cipher.seal(&plain)?;
+
+    let mut header = [0u8; 5];
+    header[0] = SnapshotRecordType::Encrypted as u8;
+    header[1..].copy_from_slice(&(sealed.len() as u32).to_le_bytes());
+    w.write_all(&header)?;
+    w.write_all(&sealed)?;
+    return Ok(header.len() + sealed.len());
+  }
+
+  record.encode::<T, _>(w)
+}
+
+/// Writes a record in the given format.
+fn append_record<I, A, T, W>(
+  record: SnapshotRecord<'_, I, A>,
+  w: &mut W,
+  format: SnapshotFormat,
+  #[cfg(feature = "encryption")] cipher: Option<&SnapshotCipher>,
+) -> std::io::Result<usize>
+where
+  I: Id,
+  A: CheapClone + Send + Sync + 'static,
+  T: TransformDelegate<Id = I, Address = A>,
+  W: Write,
+{
+  match format {
+    SnapshotFormat::V1 => write_record::<I, A, T, W>(
+      record,
+      w,
+      #[cfg(feature = "encryption")]
+      cipher,
+    ),
+    SnapshotFormat::V2 => {
+      let mut payload = Vec::new();
+      write_record::<I, A, T, _>(
+        record,
+        &mut payload,
+        #[cfg(feature = "encryption")]
+        cipher,
+      )?;
+
+      let mut header = [0u8; FRAME_HEADER_SIZE];
+      header[..4].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+      header[4..].copy_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+      w.write_all(&header)?;
+      w.write_all(&payload)?;
+      Ok(FRAME_HEADER_SIZE + payload.len())
     }
   }
 }
 
+/// Writes the header of a framed snapshot.
+fn write_header<W: Write>(w: &mut W) -> std::io::Result<usize> {
+  let mut header = [0u8; HEADER_SIZE];
+  header[..MAGIC.len()].copy_from_slice(&MAGIC);
+  header[MAGIC.len()] = SnapshotFormat::V2 as u8;
+  w.write_all(&header).map(|_| HEADER_SIZE)
+}
+
+enum Frame {
+  Record(Vec<u8>),
+  End,
+  /// A partial write or a corruption, nothing after it can be trusted.
+  Corrupted(&'static str),
+}
+
+fn read_frame<R: Read>(reader: &mut R) -> Result<Frame, SnapshotError> {
+  let mut header = Vec::with_capacity(FRAME_HEADER_SIZE);
+  reader
+    .take(FRAME_HEADER_SIZE as u64)
+    .read_to_end(&mut header)
+    .map_err(SnapshotError::Replay)?;
+  match header.len() {
+    0 => return Ok(Frame::End),
+    FRAME_HEADER_SIZE => {}
+    _ => return Ok(Frame::Corrupted("torn record header")),
+  }
+
+  let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+  let crc = u32::from_le_bytes(header[4..].try_into().unwrap());
+  // Reads at most what the file has, a corrupted length cannot blow up the allocation
+  let mut payload = Vec::new();
+  reader
+    .take(len as u64)
+    .read_to_end(&mut payload)
+    .map_err(SnapshotError::Replay)?;
+  if payload.len() < len {
+    return Ok(Frame::Corrupted("torn record"));
+  }
+  if crc32fast::hash(&payload) != crc {
+    return Ok(Frame::Corrupted("checksum mismatch"));
+  }
+  Ok(Frame::Record(payload))
+}
+
 #[viewit::viewit]
 pub(crate) struct ReplayResult<I, A> {
   alive_nodes: HashSet<Node<I, A>>,
   last_clock: LamportTime,
   last_event_clock: LamportTime,
   last_query_clock: LamportTime,
+  recent_events: RecentEvents,
   offset: u64,
+  format: SnapshotFormat,
   fh: File,
   path: PathBuf,
+  compressor: Option<Arc<dyn Compressor>>,
+  #[cfg(feature = "encryption")]
+  #[viewit(
+    getter(attrs(cfg(feature = "encryption"))),
+    setter(attrs(cfg(feature = "encryption")))
+  )]
+  cipher: Option<SnapshotCipher>,
+}
+
+fn decode_node<T: TransformDelegate>(buf: &[u8]) -> Result<Node<T::Id, T::Address>, SnapshotError> {
+  let (read, node) =
+    T::decode_node(buf).map_err(|e| SnapshotError::Replay(invalid_data_io_error(e)))?;
+
+  // Restore the IPv6 zone id, if one was persisted after the node
+  match buf.get(read..read + SCOPE_ID_SIZE) {
+    Some(trailer) => {
+      let scope_id = u32::from_le_bytes(trailer.try_into().unwrap());
+      Ok(node.map_address(|addr| T::with_address_scope_id(&addr, scope_id).unwrap_or(addr)))
+    }
+    None => Ok(node),
+  }
+}
+
+struct ReplayState<I, A> {
+  alive_nodes: HashSet<Node<I, A>>,
+  last_clock: LamportTime,
+  last_event_clock: LamportTime,
+  last_query_clock: LamportTime,
+  recent_events: RecentEvents,
+  /// Whether a leave was replayed, even if it was ignored.
+  left: bool,
+}
+
+impl<I, A> ReplayState<I, A> {
+  fn new() -> Self {
+    Self {
+      alive_nodes: HashSet::new(),
+      last_clock: LamportTime::ZERO,
+      last_event_clock: LamportTime::ZERO,
+      last_query_clock: LamportTime::ZERO,
+      recent_events: RecentEvents::new(),
+      left: false,
+    }
+  }
+}
+
+fn read_record<R: Read>(reader: &mut R) -> Result<Vec<u8>, SnapshotError> {
+  let len = reader
+    .read_u32::<LittleEndian>()
+    .map_err(SnapshotError::Replay)? as usize;
+  let mut buf = vec![0; len];
+  reader.read_exact(&mut buf).map_err(SnapshotError::Replay)?;
+  Ok(buf)
+}
+
+fn replay_record<I, A, T, R>(
+  reader: &mut R,
+  kind: SnapshotRecordType,
+  state: &mut ReplayState<I, A>,
+  rejoin_after_leave: bool,
+  compressor: Option<&dyn Compressor>,
+  #[cfg(feature = "encryption")] keys: &[SecretKey],
+) -> Result<(), SnapshotError>
+where
+  I: Id,
+  A: CheapClone + core::hash::Hash + Eq + Send + Sync + 'static,
+  T: TransformDelegate<Id = I, Address = A>,
+  R: Read,
+{
+  match kind {
+    SnapshotRecordType::Alive => {
+      let node = decode_node::<T>(&read_record(reader)?)?;
+      state.alive_nodes.insert(node);
+    }
+    SnapshotRecordType::NotAlive => {
+      let node = decode_node::<T>(&read_record(reader)?)?;
+      state.alive_nodes.remove(&node);
+    }
+    SnapshotRecordType::Clock => {
+      let t = reader
+        .read_u64::<LittleEndian>()
+        .map_err(SnapshotError::Replay)?;
+      state.last_clock = LamportTime::new(t);
+    }
+    SnapshotRecordType::EventClock => {
+      let t = reader
+        .read_u64::<LittleEndian>()
+        .map_err(SnapshotError::Replay)?;
+      state.last_event_clock = LamportTime::new(t);
+    }
+    SnapshotRecordType::QueryClock => {
+      let t = reader
+        .read_u64::<LittleEndian>()
+        .map_err(SnapshotError::Replay)?;
+      state.last_query_clock = LamportTime::new(t);
+    }
+    SnapshotRecordType::Coordinate => {}
+    SnapshotRecordType::Leave => {
+      state.left = true;
+      // Ignore a leave if we plan on re-joining
+      if rejoin_after_leave {
+        tracing::info!("ruserf: ignoring previous leave in snapshot");
+        return Ok(());
+      }
+      state.alive_nodes.clear();
+      state.last_clock = LamportTime::ZERO;
+      state.last_event_clock = LamportTime::ZERO;
+      state.last_query_clock = LamportTime::ZERO;
+      state.recent_events.clear();
+    }
+    SnapshotRecordType::Comment => {}
+    SnapshotRecordType::UserEvent => {
+      let (ltime, digest) = EventDigest::decode(&read_record(reader)?)?;
+      let digests = state.recent_events.entry(ltime).or_default();
+      if !digests.contains(&digest) {
+        digests.push(digest);
+      }
+    }
+    SnapshotRecordType::Encrypted => {
+      let sealed = read_record(reader)?;
+      #[cfg(feature = "encryption")]
+      {
+        // Records written before a key rotation are still readable as long
+        // as their key is in the keyring
+        let record = cipher::open(keys, &sealed).ok_or(SnapshotError::Decrypt)?;
+        let mut reader = record.as_slice();
+        let kind = SnapshotRecordType::try_from(reader.read_u8().map_err(SnapshotError::Replay)?)?;
+        if kind == SnapshotRecordType::Encrypted {
+          return Err(SnapshotError::Decrypt);
+        }
+        return replay_record::<I, A, T, _>(
+          &mut reader,
+          kind,
+          state,
+          rejoin_after_leave,
+          compressor,
+          keys,
+        );
+      }
+
+      #[cfg(not(feature = "encryption"))]
+      {
+        let _ = sealed;
+        return Err(SnapshotError::Decrypt);
+      }
+    }
+    SnapshotRecordType::Compressed => {
+      let segment = read_record(reader)?;
+      let Some((&id, compressed)) = segment.split_first() else {
+        return Err(SnapshotError::Replay(std::io::Error::new(
+          std::io::ErrorKind::InvalidData,
+          "empty compressed snapshot segment",
+        )));
+      };
+      // The segments were written by this node, their size is not capped
+      let records = compression::decompress_with(compressor, id, compressed, usize::MAX)
+        .map_err(SnapshotError::Decompress)?;
+
+      // The records of a segment follow each other unframed, the segment is checksummed as a whole
+      let mut reader = records.as_slice();
+      while !reader.is_empty() {
+        let kind = SnapshotRecordType::try_from(reader.read_u8().map_err(SnapshotError::Replay)?)?;
+        if kind == SnapshotRecordType::Compressed {
+          return Err(SnapshotError::Replay(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "nested compressed snapshot segment",
+          )));
+        }
+        replay_record::<I, A, T, _>(
+          &mut reader,
+          kind,
+          state,
+          rejoin_after_leave,
+          compressor,
+          #[cfg(feature = "encryption")]
+          keys,
+        )?;
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Replays the unframed records until the end of the snapshot.
+fn replay_v1<I, A, T, R>(
+  reader: &mut R,
+  state: &mut ReplayState<I, A>,
+  rejoin_after_leave: bool,
+  compressor: Option<&dyn Compressor>,
+  #[cfg(feature = "encryption")] keys: &[SecretKey],
+) -> Result<(), SnapshotError>
+where
+  I: Id,
+  A: CheapClone + core::hash::Hash + Eq + Send + Sync + 'static,
+  T: TransformDelegate<Id = I, Address = A>,
+  R: Read,
+{
+  loop {
+    let kind = match reader.read_u8() {
+      Ok(b) => SnapshotRecordType::try_from(b)?,
+      Err(e) => {
+        if e.kind() == std::io::ErrorKind::UnexpectedEof {
+          return Ok(());
+        }
+        return Err(SnapshotError::Replay(e));
+      }
+    };
+
+    replay_record::<I, A, T, _>(
+      reader,
+      kind,
+      state,
+      rejoin_after_leave,
+      compressor,
+      #[cfg(feature = "encryption")]
+      keys,
+    )?;
+  }
+}
+
+/// Replays the framed records following the header, until the end of the
+/// snapshot or the first corrupted record. Returns the length of the
+/// snapshot up to the corrupted record, and why the record is corrupted.
+fn replay_v2<I, A, T, R>(
+  reader: &mut R,
+  state: &mut ReplayState<I, A>,
+  rejoin_after_leave: bool,
+  compressor: Option<&dyn Compressor>,
+  #[cfg(feature = "encryption")] keys: &[SecretKey],
+) -> Result<(u64, Option<&'static str>), SnapshotError>
+where
+  I: Id,
+  A: CheapClone + core::hash::Hash + Eq + Send + Sync + 'static,
+  T: TransformDelegate<Id = I, Address = A>,
+  R: Read,
+{
+  let mut len = HEADER_SIZE as u64;
+  loop {
+    let record = match read_frame(reader)? {
+      Frame::Record(record) => record,
+      Frame::End => return Ok((len, None)),
+      Frame::Corrupted(reason) => {
+        tracing::warn!(offset = len, reason, "ruserf: corrupted snapshot record");
+        return Ok((len, Some(reason)));
+      }
+    };
+
+    // The checksum matches, so a record which fails to replay is not a torn write
+    let mut payload = record.as_slice();
+    let kind = SnapshotRecordType::try_from(payload.read_u8().map_err(SnapshotError::Replay)?)?;
+    replay_record::<I, A, T, _>(
+      &mut payload,
+      kind,
+      state,
+      rejoin_after_leave,
+      compressor,
+      #[cfg(feature = "encryption")]
+      keys,
+    )?;
+    len += (FRAME_HEADER_SIZE + record.len()) as u64;
+  }
+}
+
+/// How far the records of a snapshot could be replayed.
+struct Replayed {
+  format: SnapshotFormat,
+  /// The length of the snapshot up to the first corrupted record, and why
+  /// the record is corrupted, if any.
+  corrupted: Option<(u64, &'static str)>,
+}
+
+/// Replays the records of a snapshot of `len` bytes, in whichever format it
+/// was written.
+fn replay_records<I, A, T, R>(
+  reader: &mut R,
+  len: u64,
+  state: &mut ReplayState<I, A>,
+  rejoin_after_leave: bool,
+  compressor: Option<&dyn Compressor>,
+  #[cfg(feature = "encryption")] keys: &[SecretKey],
+) -> Result<Replayed, SnapshotError>
+where
+  I: Id,
+  A: CheapClone + core::hash::Hash + Eq + Send + Sync + 'static,
+  T: TransformDelegate<Id = I, Address = A>,
+  R: BufRead,
+{
+  let first = reader
+    .fill_buf()
+    .map_err(SnapshotError::Replay)?
+    .first()
+    .copied();
+  let mut corrupted = None;
+  let format = match first {
+    Some(b) if b != MAGIC[0] => {
+      replay_v1::<I, A, T, _>(
+        reader,
+        state,
+        rejoin_after_leave,
+        compressor,
+        #[cfg(feature = "encryption")]
+        keys,
+      )?;
+      SnapshotFormat::V1
+    }
+    Some(_) => {
+      let mut header = Vec::with_capacity(HEADER_SIZE);
+      (&mut *reader)
+        .take(HEADER_SIZE as u64)
+        .read_to_end(&mut header)
+        .map_err(SnapshotError::Replay)?;
+      if header.len() < HEADER_SIZE {
+        // Torn while creating the snapshot, start over
+        corrupted = Some((0, "torn header"));
+      } else if header[..MAGIC.len()] != MAGIC {
+        return Err(SnapshotError::Replay(std::io::Error::new(
+          std::io::ErrorKind::InvalidData,
+          "invalid snapshot header",
+        )));
+      } else if header[MAGIC.len()] != SnapshotFormat::V2 as u8 {
+        return Err(SnapshotError::UnsupportedVersion(header[MAGIC.len()]));
+      } else {
+        let (valid, reason) = replay_v2::<I, A, T, _>(
+          reader,
+          state,
+          rejoin_after_leave,
+          compressor,
+          #[cfg(feature = "encryption")]
+          keys,
+        )?;
+        if valid < len {
+          corrupted = Some((valid, reason.unwrap_or("trailing bytes")));
+        }
+      }
+      SnapshotFormat::V2
+    }
+    None => SnapshotFormat::V2,
+  };
+  Ok(Replayed { format, corrupted })
 }
 
 pub(crate) fn open_and_replay_snapshot<
@@ -238,6 +848,235 @@ pub(crate) fn open_and_replay_snapshot<
 >(
   p: &P,
   rejoin_after_leave: bool,
+  compressor: Option<Arc<dyn Compressor>>,
+) -> Result<ReplayResult<I, A>, SnapshotError> {
+  replay_snapshot::<I, A, T, P>(
+    p,
+    rejoin_after_leave,
+    compressor,
+    #[cfg(feature = "encryption")]
+    None,
+  )
+}
+
+/// Replays a snapshot whose records may be encrypted, the new records are
+/// encrypted with the primary key of the cipher.
+#[cfg(feature = "encryption")]
+pub(crate) fn open_and_replay_encrypted_snapshot<
+  I: Id,
+  A: CheapClone + core::hash::Hash + Eq + Send + Sync + 'static,
+  T: TransformDelegate<Id = I, Address = A>,
+  P: AsRef<std::path::Path>,
+>(
+  p: &P,
+  rejoin_after_leave: bool,
+  cipher: SnapshotCipher,
+  compressor: Option<Arc<dyn Compressor>>,
+) -> Result<ReplayResult<I, A>, SnapshotError> {
+  replay_snapshot::<I, A, T, P>(p, rejoin_after_leave, compressor, Some(cipher))
+}
+
+/// The corrupted tail of a snapshot, see [`SnapshotReport::corruption`].
+///
+/// Nothing after the first corrupted record can be trusted, so the range
+/// always extends to the end of the snapshot.
+#[viewit::viewit(vis_all = "", getters(vis_all = "pub", style = "ref"), setters(skip))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotCorruption {
+  /// The byte range of the corrupted records.
+  #[viewit(getter(const, attrs(doc = "Returns the byte range of the corrupted records.")))]
+  range: Range<u64>,
+  /// Why the first record of the range is corrupted.
+  #[viewit(getter(
+    const,
+    style = "move",
+    attrs(doc = "Returns why the first record of the range is corrupted.")
+  ))]
+  reason: &'static str,
+}
+
+/// The state a snapshot restores, see [`inspect_snapshot`].
+#[viewit::viewit(vis_all = "", getters(vis_all = "pub", style = "ref"), setters(skip))]
+#[derive(Debug, Clone)]
+pub struct SnapshotReport<I, A> {
+  /// The version of the on-disk format of the snapshot.
+  #[viewit(getter(
+    const,
+    style = "move",
+    attrs(doc = "Returns the version of the on-disk format of the snapshot.")
+  ))]
+  format_version: u8,
+  /// The members which would be rejoined.
+  #[viewit(getter(
+    result(converter(fn = "Vec::as_slice"), type = "&[Node<I, A>]"),
+    attrs(doc = "Returns the members which would be rejoined.")
+  ))]
+  alive_nodes: Vec<Node<I, A>>,
+  /// The restored member clock.
+  #[viewit(getter(
+    const,
+    style = "move",
+    attrs(doc = "Returns the restored member clock.")
+  ))]
+  clock: LamportTime,
+  /// The restored user event clock.
+  #[viewit(getter(
+    const,
+    style = "move",
+    attrs(doc = "Returns the restored user event clock.")
+  ))]
+  event_clock: LamportTime,
+  /// The restored query clock.
+  #[viewit(getter(
+    const,
+    style = "move",
+    attrs(doc = "Returns the restored query clock.")
+  ))]
+  query_clock: LamportTime,
+  /// The number of the restored user event digests.
+  #[viewit(getter(
+    const,
+    style = "move",
+    attrs(doc = "Returns the number of the restored user event digests.")
+  ))]
+  recent_events: usize,
+  /// Whether the snapshot records a leave, even if it is ignored.
+  #[viewit(getter(
+    const,
+    style = "move",
+    attrs(doc = "Returns `true` if the snapshot records a leave, even if it is ignored.")
+  ))]
+  left: bool,
+  /// The size of the snapshot in bytes.
+  #[viewit(getter(
+    const,
+    style = "move",
+    attrs(doc = "Returns the size of the snapshot in bytes.")
+  ))]
+  len: u64,
+  /// When the snapshot was last written to, if the platform records it.
+  #[viewit(getter(
+    const,
+    style = "move",
+    attrs(doc = "Returns when the snapshot was last written to, if the platform records it.")
+  ))]
+  last_flush: Option<SystemTime>,
+  /// The corrupted tail of the snapshot, truncated when it is replayed.
+  #[viewit(getter(
+    result(converter(fn = "Option::as_ref"), type = "Option<&SnapshotCorruption>"),
+    attrs(doc = "Returns the corrupted tail of the snapshot, if any.")
+  ))]
+  corruption: Option<SnapshotCorruption>,
+}
+
+impl<I, A> SnapshotReport<I, A> {
+  /// Returns the number of the members which would be rejoined.
+  #[inline]
+  pub fn num_alive_nodes(&self) -> usize {
+    self.alive_nodes.len()
+  }
+
+  /// Returns `true` if the snapshot is empty.
+  #[inline]
+  pub const fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+}
+
+/// Replays the snapshot at `path` as [`Serf`](crate::Serf) does when it
+/// starts with the same `rejoin_after_leave`, without modifying it, so the
+/// snapshot can be validated before trusting the automatic rejoin.
+///
+/// The corrupted tail of the snapshot is reported rather than truncated. The
+/// encrypted records fail with [`SnapshotError::Decrypt`], they are replayed
+/// by [`inspect_encrypted_snapshot`]. The compressed segments are inflated
+/// if their algorithm is compiled in, the ones written with a custom
+/// [`Compressor`] fail with [`SnapshotError::Decompress`].
+pub fn inspect_snapshot<T>(
+  path: impl AsRef<Path>,
+  rejoin_after_leave: bool,
+) -> Result<SnapshotReport<T::Id, T::Address>, SnapshotError>
+where
+  T: TransformDelegate,
+  T::Address: core::hash::Hash + Eq,
+{
+  inspect::<T>(
+    path.as_ref(),
+    rejoin_after_leave,
+    #[cfg(feature = "encryption")]
+    &[],
+  )
+}
+
+/// Replays the snapshot at `path` like [`inspect_snapshot`], decrypting the
+/// encrypted records with any of the `keys`.
+#[cfg(feature = "encryption")]
+#[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+pub fn inspect_encrypted_snapshot<T>(
+  path: impl AsRef<Path>,
+  rejoin_after_leave: bool,
+  keys: &[SecretKey],
+) -> Result<SnapshotReport<T::Id, T::Address>, SnapshotError>
+where
+  T: TransformDelegate,
+  T::Address: core::hash::Hash + Eq,
+{
+  inspect::<T>(path.as_ref(), rejoin_after_leave, keys)
+}
+
+fn inspect<T>(
+  path: &Path,
+  rejoin_after_leave: bool,
+  #[cfg(feature = "encryption")] keys: &[SecretKey],
+) -> Result<SnapshotReport<T::Id, T::Address>, SnapshotError>
+where
+  T: TransformDelegate,
+  T::Address: core::hash::Hash + Eq,
+{
+  let fh = File::open(path).map_err(SnapshotError::Open)?;
+  let metadata = fh.metadata().map_err(SnapshotError::Stat)?;
+  let len = metadata.len();
+
+  let mut state = ReplayState::new();
+  let replayed = replay_records::<T::Id, T::Address, T, _>(
+    &mut BufReader::new(fh),
+    len,
+    &mut state,
+    rejoin_after_leave,
+    None,
+    #[cfg(feature = "encryption")]
+    keys,
+  )?;
+
+  Ok(SnapshotReport {
+    format_version: replayed.format as u8,
+    alive_nodes: state.alive_nodes.into_iter().collect(),
+    clock: state.last_clock,
+    event_clock: state.last_event_clock,
+    query_clock: state.last_query_clock,
+    recent_events: state.recent_events.values().map(Vec::len).sum(),
+    left: state.left,
+    len,
+    last_flush: metadata.modified().ok(),
+    corruption: replayed
+      .corrupted
+      .map(|(offset, reason)| SnapshotCorruption {
+        range: offset..len,
+        reason,
+      }),
+  })
+}
+
+fn replay_snapshot<
+  I: Id,
+  A: CheapClone + core::hash::Hash + Eq + Send + Sync + 'static,
+  T: TransformDelegate<Id = I, Address = A>,
+  P: AsRef<std::path::Path>,
+>(
+  p: &P,
+  rejoin_after_leave: bool,
+  compressor: Option<Arc<dyn Compressor>>,
+  #[cfg(feature = "encryption")] cipher: Option<SnapshotCipher>,
 ) -> Result<ReplayResult<I, A>, SnapshotError> {
   // Try to open the file
   #[cfg(unix)]
@@ -258,110 +1097,87 @@ pub(crate) fn open_and_replay_snapshot<
     .map_err(SnapshotError::Open)?;
 
   // Determine the offset
-  let offset = fh.metadata().map_err(SnapshotError::Stat)?.len();
+  let mut offset = fh.metadata().map_err(SnapshotError::Stat)?.len();
 
-  // Read each line
   let mut reader = BufReader::new(fh);
-  let mut buf = Vec::new();
-  let mut alive_nodes = HashSet::new();
-  let mut last_clock = LamportTime::ZERO;
-  let mut last_event_clock = LamportTime::ZERO;
-  let mut last_query_clock = LamportTime::ZERO;
+  let mut state = ReplayState::new();
+  let replayed = replay_records::<I, A, T, _>(
+    &mut reader,
+    offset,
+    &mut state,
+    rejoin_after_leave,
+    compressor.as_deref(),
+    #[cfg(feature = "encryption")]
+    cipher.as_ref().map_or(&[][..], |c| c.keys()),
+  )?;
+  let format = replayed.format;
+  // The length to truncate the snapshot to, if its tail is corrupted
+  let truncate_at = replayed.corrupted.map(|(len, _)| len);
 
-  loop {
-    let kind = match reader.read_u8() {
-      Ok(b) => SnapshotRecordType::try_from(b)?,
-      Err(e) => {
-        if e.kind() == std::io::ErrorKind::UnexpectedEof {
-          break;
-        }
-        return Err(SnapshotError::Replay(e));
-      }
-    };
+  let mut f = reader.into_inner();
+  if let Some(len) = truncate_at {
+    tracing::warn!(
+      offset = len,
+      dropped = offset - len,
+      "ruserf: truncating corrupted snapshot tail"
+    );
+    f.set_len(len).map_err(SnapshotError::Truncate)?;
+    offset = len;
+  }
 
-    match kind {
-      SnapshotRecordType::Alive => {
-        let len = reader
-          .read_u32::<LittleEndian>()
-          .map_err(SnapshotError::Replay)? as usize;
-        buf.resize(len, 0);
-        reader.read_exact(&mut buf).map_err(SnapshotError::Replay)?;
-
-        let (_, node) =
-          T::decode_node(&buf).map_err(|e| SnapshotError::Replay(invalid_data_io_error(e)))?;
-        alive_nodes.insert(node);
-      }
-      SnapshotRecordType::NotAlive => {
-        let len = reader
-          .read_u32::<LittleEndian>()
-          .map_err(SnapshotError::Replay)? as usize;
-        buf.resize(len, 0);
-        reader.read_exact(&mut buf).map_err(SnapshotError::Replay)?;
-
-        let (_, node) =
-          T::decode_node(&buf).map_err(|e| SnapshotError::Replay(invalid_data_io_error(e)))?;
-        alive_nodes.remove(&node);
-      }
-      SnapshotRecordType::Clock => {
-        let t = reader
-          .read_u64::<LittleEndian>()
-          .map_err(SnapshotError::Replay)?;
-        last_clock = LamportTime::new(t);
-      }
-      SnapshotRecordType::EventClock => {
-        let t = reader
-          .read_u64::<LittleEndian>()
-          .map_err(SnapshotError::Replay)?;
-        last_event_clock = LamportTime::new(t);
-      }
-      SnapshotRecordType::QueryClock => {
-        let t = reader
-          .read_u64::<LittleEndian>()
-          .map_err(SnapshotError::Replay)?;
-        last_query_clock = LamportTime::new(t);
-      }
-      SnapshotRecordType::Coordinate => continue,
-      SnapshotRecordType::Leave => {
-        // Ignore a leave if we plan on re-joining
-        if rejoin_after_leave {
-          tracing::info!("ruserf: ignoring previous leave in snapshot");
-          continue;
-        }
-        alive_nodes.clear();
-        last_clock = LamportTime::ZERO;
-        last_event_clock = LamportTime::ZERO;
-        last_query_clock = LamportTime::ZERO;
-      }
-      SnapshotRecordType::Comment => continue,
-    }
+  // A new snapshot starts with the header
+  if offset == 0 {
+    write_header(&mut f)
+      .and_then(|_| f.sync_all())
+      .map_err(SnapshotError::Write)?;
+    offset = HEADER_SIZE as u64;
   }
 
   // Seek to the end
-  let mut f = reader.into_inner();
 
+  let ReplayState {
+    alive_nodes,
+    last_clock,
+    last_event_clock,
+    last_query_clock,
+    recent_events,
+    ..
+  } = state;
   f.seek(std::io::SeekFrom::End(0))
     .map(|_| ReplayResult {
       alive_nodes,
       last_clock,
       last_event_clock,
       last_query_clock,
+      recent_events,
       offset,
+      format,
       fh: f,
       path: p.as_ref().to_path_buf(),
+      compressor,
+      #[cfg(feature = "encryption")]
+      cipher,
     })
     .map_err(SnapshotError::SeekEnd)
 }
 
 pub(crate) struct SnapshotHandle {
-  wait_rx: Receiver<()>,
+  wait_rx: Receiver<bool>,
   shutdown_rx: Receiver<()>,
   leave_tx: Sender<()>,
+  task: Arc<TaskState>,
 }
 
 impl SnapshotHandle {
-  /// Used to wait until the snapshotter finishes shut down
-  pub(crate) async fn wait(&self) {
-    let _ = self.wait_rx.recv().await;
+  /// Returns the state of the snapshotter task.
+  pub(crate) fn task(&self) -> Arc<TaskState> {
+    self.task.clone()
+  }
+
+  /// Used to wait until the snapshotter finishes shut down, returns whether
+  /// the snapshot was flushed and synced to disk.
+  pub(crate) async fn wait(&self) -> bool {
+    self.wait_rx.recv().await.unwrap_or(false)
   }
 
   /// Used to remove known nodes to prevent a restart from
@@ -383,21 +1199,33 @@ where
 {
   alive_nodes: HashSet<Node<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>>,
   clock: LamportClock,
+  event_clock: LamportClock,
+  query_clock: LamportClock,
+  clock_interval: Duration,
   fh: Option<BufWriter<File>>,
   last_flush: Epoch,
   last_clock: LamportTime,
   last_event_clock: LamportTime,
   last_query_clock: LamportTime,
+  /// The user events seen within the last `event_window` lamport times.
+  recent_events: RecentEvents,
+  event_window: u64,
   leave_rx: Receiver<()>,
   leaving: bool,
   min_compact_size: u64,
   path: PathBuf,
   offset: u64,
+  format: SnapshotFormat,
   rejoin_after_leave: bool,
   stream_rx: Receiver<CrateEvent<T, D>>,
   shutdown_rx: Receiver<()>,
-  wait_tx: Sender<()>,
+  wait_tx: Sender<bool>,
   last_attempted_compaction: Epoch,
+  delegate: Option<Arc<dyn SnapshotDelegate>>,
+  heartbeat: TaskHeartbeat,
+  compressor: Option<Arc<dyn Compressor>>,
+  #[cfg(feature = "encryption")]
+  cipher: Option<SnapshotCipher>,
   #[cfg(feature = "metrics")]
   metric_labels: std::sync::Arc<memberlist_core::types::MetricLabels>,
 }
@@ -415,6 +1243,11 @@ macro_rules! stream_flush_event {
       CrateEvent::User(e) => $this.process_user_event(e),
       CrateEvent::Query(e) => $this.process_query_event(e.ltime),
       CrateEvent::InternalQuery { query, .. } => $this.process_query_event(query.ltime),
+      CrateEvent::ClusterFormed(_)
+      | CrateEvent::LeaderChanged(_)
+      | CrateEvent::Partition(_)
+      | CrateEvent::ClusterMismatch(_)
+      | CrateEvent::InvalidUser(_) => {}
     }
   }};
 }
@@ -440,14 +1273,19 @@ where
   D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
   T: Transport,
 {
-  #[allow(clippy::type_complexity)]
+  #[allow(clippy::type_complexity, clippy::too_many_arguments)]
   pub(crate) fn from_replay_result(
     replay_result: ReplayResult<T::Id, <T::Resolver as AddressResolver>::ResolvedAddress>,
     min_compact_size: u64,
+    event_window: usize,
     rejoin_after_leave: bool,
     clock: LamportClock,
+    event_clock: LamportClock,
+    query_clock: LamportClock,
+    clock_interval: Duration,
     out_tx: Sender<CrateEvent<T, D>>,
     shutdown_rx: Receiver<()>,
+    delegate: Option<Arc<dyn SnapshotDelegate>>,
     #[cfg(feature = "metrics")] metric_labels: std::sync::Arc<memberlist_core::types::MetricLabels>,
   ) -> Result<
     (
@@ -467,35 +1305,54 @@ where
       last_clock,
       last_event_clock,
       last_query_clock,
+      mut recent_events,
       offset,
+      format,
       fh,
       path,
+      compressor,
+      #[cfg(feature = "encryption")]
+      cipher,
     } = replay_result;
 
+    let event_window = event_window as u64;
+    trim_recent_events(&mut recent_events, last_event_clock, event_window);
+
     // Create the snapshotter
     let this = Self {
       alive_nodes,
       clock,
+      event_clock,
+      query_clock,
+      clock_interval,
       fh: Some(BufWriter::new(fh)),
       last_flush: Epoch::now(),
       last_clock,
       last_event_clock,
       last_query_clock,
+      recent_events,
+      event_window,
       leave_rx,
       leaving: false,
       min_compact_size,
       path,
       offset,
+      format,
       rejoin_after_leave,
       stream_rx,
       shutdown_rx: shutdown_rx.clone(),
       wait_tx,
       last_attempted_compaction: Epoch::now(),
+      delegate,
+      heartbeat: TaskHeartbeat::new("snapshotter"),
+      compressor,
+      #[cfg(feature = "encryption")]
+      cipher,
       #[cfg(feature = "metrics")]
       metric_labels,
     };
 
-    let mut alive_nodes = this
+    let alive_nodes = this
       .alive_nodes
       .iter()
       .map(|n| {
@@ -504,7 +1361,6 @@ where
         Node::new(id, MaybeResolvedAddress::resolved(addr))
       })
       .collect::<TinyVec<_>>();
-    alive_nodes.shuffle(&mut rand::thread_rng());
 
     // Start handling new commands
     let handle = <T::Runtime as RuntimeLite>::spawn(Self::tee_stream(
@@ -513,7 +1369,9 @@ where
       out_tx,
       shutdown_rx.clone(),
     ));
-    <T::Runtime as RuntimeLite>::spawn_detach(this.stream(handle));
+    let task = this.heartbeat.state();
+    let span = this.heartbeat.span();
+    <T::Runtime as RuntimeLite>::spawn_detach(this.stream(handle).instrument(span));
 
     Ok((
       in_tx,
@@ -522,6 +1380,7 @@ where
         wait_rx,
         shutdown_rx,
         leave_tx,
+        task,
       },
     ))
   }
@@ -571,15 +1430,20 @@ where
     // If we plan to re-join, keep our state
     if !self.rejoin_after_leave {
       self.alive_nodes.clear();
+      self.recent_events.clear();
     }
     self.try_append(SnapshotRecord::Leave);
     if let Some(fh) = self.fh.as_mut() {
       if let Err(e) = fh.flush() {
-        tracing::error!(target="ruserf", err=%SnapshotError::Flush(e), "failed to flush leave to snapshot");
+        let e = SnapshotError::Flush(e);
+        tracing::error!(target="ruserf", err=%e, "failed to flush leave to snapshot");
+        notify_snapshot_error(self.delegate.as_deref(), &e);
       }
 
       if let Err(e) = fh.get_mut().sync_all() {
-        tracing::error!(target="ruserf", err=%SnapshotError::Sync(e), "failed to sync leave to snapshot");
+        let e = SnapshotError::Sync(e);
+        tracing::error!(target="ruserf", err=%e, "failed to sync leave to snapshot");
+        notify_snapshot_error(self.delegate.as_deref(), &e);
       }
     }
   }
@@ -589,9 +1453,10 @@ where
     mut self,
     tee_handle: <<T::Runtime as RuntimeLite>::Spawner as AsyncSpawner>::JoinHandle<()>,
   ) {
-    let mut clock_ticker = <T::Runtime as RuntimeLite>::interval(CLOCK_UPDATE_INTERVAL);
+    let mut clock_ticker = <T::Runtime as RuntimeLite>::interval(self.clock_interval);
 
     loop {
+      self.heartbeat.beat();
       futures::select! {
         signal = self.leave_rx.recv().fuse() => {
           if signal.is_ok() {
@@ -606,7 +1471,9 @@ where
           }
         }
         _ = futures::StreamExt::next(&mut clock_ticker).fuse() => {
-          self.update_clock();
+          self.checkpoint_clocks();
+          #[cfg(feature = "encryption")]
+          self.check_key_rotation().await;
         }
         _ = self.shutdown_rx.recv().fuse() => {
           break;
@@ -622,8 +1489,8 @@ where
     let flush_timeout = <T::Runtime as RuntimeLite>::sleep(SHUTDOWN_FLUSH_TIMEOUT);
     futures::pin_mut!(flush_timeout);
 
-    // snapshot the clock
-    self.update_clock();
+    // snapshot the clocks
+    self.checkpoint_clocks();
 
     // Clear out the buffers
     loop {
@@ -644,31 +1511,65 @@ where
       }
     }
 
+    let mut synced = false;
     if let Some(fh) = self.fh.as_mut() {
+      synced = true;
       if let Err(e) = fh.flush() {
-        tracing::error!(target="ruserf", err=%SnapshotError::Flush(e), "failed to flush leave to snapshot");
+        synced = false;
+        let e = SnapshotError::Flush(e);
+        tracing::error!(target="ruserf", err=%e, "failed to flush leave to snapshot");
+        notify_snapshot_error(self.delegate.as_deref(), &e);
       }
 
       if let Err(e) = fh.get_mut().sync_all() {
-        tracing::error!(target="ruserf", err=%SnapshotError::Sync(e), "failed to sync leave to snapshot");
+        synced = false;
+        let e = SnapshotError::Sync(e);
+        tracing::error!(target="ruserf", err=%e, "failed to sync leave to snapshot");
+        notify_snapshot_error(self.delegate.as_deref(), &e);
       }
     }
 
+    let _ = self.wait_tx.try_send(synced);
     self.wait_tx.close();
     tee_handle.await;
     tracing::debug!("ruserf: snapshotter stream exits");
   }
 
+  /// Rewrites the snapshot with the new primary key once the keyring is
+  /// rotated, so the old key can be removed without losing the snapshot.
+  #[cfg(feature = "encryption")]
+  async fn check_key_rotation(&mut self) {
+    let Some(cipher) = self.cipher.as_mut() else {
+      return;
+    };
+
+    if cipher.rotated().await {
+      tracing::info!("ruserf: primary key changed, re-encrypting snapshot");
+      if let Err(e) = self.compact() {
+        tracing::error!(err = %e, "ruserf: failed to re-encrypt snapshot");
+        notify_snapshot_error(self.delegate.as_deref(), &e);
+      }
+    }
+  }
+
   /// Used to handle a single user event
   fn process_user_event(&mut self, e: &UserEventMessage) {
-    // Ignore old clocks
     let ltime = e.ltime();
+    if self.event_window > 0 && ltime + LamportTime::new(self.event_window) > self.last_event_clock
+    {
+      let digest = EventDigest::new(e.name(), e.payload());
+      self.try_append(SnapshotRecord::UserEvent(ltime, &digest));
+      self.recent_events.entry(ltime).or_default().push(digest);
+    }
+
+    // Ignore old clocks
     if ltime <= self.last_event_clock {
       return;
     }
 
     self.last_event_clock = ltime;
     self.try_append(SnapshotRecord::EventClock(ltime));
+    trim_recent_events(&mut self.recent_events, ltime, self.event_window);
   }
 
   /// Used to handle a single query event
@@ -707,6 +1608,27 @@ where
     self.update_clock();
   }
 
+  /// Called periodically to write the clocks witnessed since the last
+  /// checkpoint, the event and query clocks are otherwise only written
+  /// when the events are processed.
+  fn checkpoint_clocks(&mut self) {
+    self.update_clock();
+
+    let t: u64 = self.event_clock.time().into();
+    let last_seen = LamportTime::from(t.saturating_sub(1));
+    if last_seen > self.last_event_clock {
+      self.last_event_clock = last_seen;
+      self.try_append(SnapshotRecord::EventClock(last_seen));
+    }
+
+    let t: u64 = self.query_clock.time().into();
+    let last_seen = LamportTime::from(t.saturating_sub(1));
+    if last_seen > self.last_query_clock {
+      self.last_query_clock = last_seen;
+      self.try_append(SnapshotRecord::QueryClock(last_seen));
+    }
+  }
+
   /// Called periodically to check if we should udpate our
   /// clock value. This is done after member events but should also be done
   /// periodically due to race conditions with join and leave intents
@@ -725,11 +1647,13 @@ where
   ) {
     if let Err(e) = self.append_line(l) {
       tracing::error!(err = %e, "ruserf: failed to update snapshot");
+      notify_snapshot_error(self.delegate.as_deref(), &e);
       if self.last_attempted_compaction.elapsed() > SNAPSHOT_ERROR_RECOVERY_INTERVAL {
         self.last_attempted_compaction = Epoch::now();
         tracing::info!("ruserf: attempting compaction to recover from error...");
         if let Err(e) = self.compact() {
           tracing::error!(err = %e, "ruserf: compaction failed, will reattempt after {}s", SNAPSHOT_ERROR_RECOVERY_INTERVAL.as_secs());
+          notify_snapshot_error(self.delegate.as_deref(), &e);
         } else {
           tracing::info!("ruserf: finished compaction, successfully recovered from error state");
         }
@@ -753,7 +1677,14 @@ where
     );
 
     let f = self.fh.as_mut().unwrap();
-    let n = l.encode::<D, _>(f).map_err(SnapshotError::Write)?;
+    let n = append_record::<_, _, D, _>(
+      l,
+      f,
+      self.format,
+      #[cfg(feature = "encryption")]
+      self.cipher.as_ref(),
+    )
+    .map_err(SnapshotError::Write)?;
 
     // check if we should flush
     if self.last_flush.elapsed() > FLUSH_INTERVAL {
@@ -777,13 +1708,30 @@ where
   /// Computes the maximum size and is used to force periodic compaction.
   fn snapshot_max_size(&self) -> u64 {
     let nodes = self.alive_nodes.len() as u64;
-    let est_size = nodes * SNAPSHOT_BYTES_PER_NODE as u64;
+    let events = self.recent_events.values().map(Vec::len).sum::<usize>() as u64;
+    let est_size =
+      nodes * SNAPSHOT_BYTES_PER_NODE as u64 + events * SNAPSHOT_BYTES_PER_EVENT as u64;
     let threshold = est_size * SNAPSHOT_COMPACTION_THRESHOLD as u64;
     threshold.max(self.min_compact_size)
   }
 
-  /// Used to compact the snapshot once it is too large
+  /// Used to compact the snapshot once it is too large, the
+  /// [`SnapshotDelegate`] is told when the compaction starts and ends.
   fn compact(&mut self) -> Result<(), SnapshotError> {
+    if let Some(d) = self.delegate.as_deref() {
+      d.on_snapshot_compact(SnapshotCompaction::Started);
+    }
+    let res = self.compact_in();
+    if let Some(d) = self.delegate.as_deref() {
+      d.on_snapshot_compact(match res {
+        Ok(()) => SnapshotCompaction::Finished(self.offset),
+        Err(_) => SnapshotCompaction::Failed,
+      });
+    }
+    res
+  }
+
+  fn compact_in(&mut self) -> Result<(), SnapshotError> {
     #[cfg(feature = "metrics")]
     let start = crate::types::Epoch::now();
 
@@ -817,26 +1765,75 @@ where
     // Create a buffered writer
     let mut buf = BufWriter::new(fh);
 
-    // Write out the live nodes
-    let mut offset = 0u64;
-    for node in self.alive_nodes.iter() {
-      offset += SnapshotRecord::Alive(Cow::Borrowed(node))
-        .encode::<D, _>(&mut buf)
-        .map_err(SnapshotError::WriteNew)? as u64;
+    // The compaction always writes the framed format, migrating the older snapshots
+    if self.format != SnapshotFormat::V2 {
+      tracing::info!("ruserf: migrating snapshot to the framed format");
     }
+    let mut offset = write_header(&mut buf).map_err(SnapshotError::WriteNew)? as u64;
 
-    // Write out the clocks
-    offset += SnapshotRecord::Clock(self.last_clock)
-      .encode::<D, _>(&mut buf)
-      .map_err(SnapshotError::WriteNew)? as u64;
+    // Write out the live nodes
+    let records = || {
+      self
+        .alive_nodes
+        .iter()
+        .map(|node| SnapshotRecord::Alive(Cow::Borrowed(node)))
+        // Write out the clocks
+        .chain([
+          SnapshotRecord::Clock(self.last_clock),
+          SnapshotRecord::EventClock(self.last_event_clock),
+          SnapshotRecord::QueryClock(self.last_query_clock),
+        ])
+        // Write out the deduplication window
+        .chain(self.recent_events.iter().flat_map(|(ltime, digests)| {
+          digests
+            .iter()
+            .map(|digest| SnapshotRecord::UserEvent(*ltime, digest))
+        }))
+    };
 
-    offset += SnapshotRecord::EventClock(self.last_event_clock)
-      .encode::<D, _>(&mut buf)
-      .map_err(SnapshotError::WriteNew)? as u64;
+    // Fold the records into a single compressed segment, encrypted as a
+    // whole, unless the compression saves no space
+    let mut segment = None;
+    if let Some(compressor) = self.compressor.as_deref() {
+      let mut plain = Vec::new();
+      for record in records() {
+        record
+          .encode::<D, _>(&mut plain)
+          .map_err(SnapshotError::WriteNew)?;
+      }
+      let compressed = compressor
+        .compress(&plain)
+        .map_err(SnapshotError::WriteNew)?;
+      // + 1 for the compressor id
+      if compressed.len() + 1 < plain.len() {
+        segment = Some((compressor.id(), compressed));
+      }
+    }
 
-    offset += SnapshotRecord::QueryClock(self.last_query_clock)
-      .encode::<D, _>(&mut buf)
-      .map_err(SnapshotError::WriteNew)? as u64;
+    match segment {
+      Some((id, compressed)) => {
+        offset += append_record::<_, _, D, _>(
+          SnapshotRecord::Compressed(id, &compressed),
+          &mut buf,
+          SnapshotFormat::V2,
+          #[cfg(feature = "encryption")]
+          self.cipher.as_ref(),
+        )
+        .map_err(SnapshotError::WriteNew)? as u64;
+      }
+      None => {
+        for record in records() {
+          offset += append_record::<_, _, D, _>(
+            record,
+            &mut buf,
+            SnapshotFormat::V2,
+            #[cfg(feature = "encryption")]
+            self.cipher.as_ref(),
+          )
+          .map_err(SnapshotError::WriteNew)? as u64;
+        }
+      }
+    }
 
     // Flush the new snapshot
     buf.flush().map_err(SnapshotError::Flush)?;
@@ -889,7 +1886,370 @@ where
 
     self.fh = Some(BufWriter::new(fh));
     self.offset = offset;
+    self.format = SnapshotFormat::V2;
     self.last_flush = Epoch::now();
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use std::net::SocketAddr;
+
+  use smol_str::SmolStr;
+
+  use super::*;
+  use crate::delegate::LpeTransfromDelegate;
+
+  type Lpe = LpeTransfromDelegate<SmolStr, SocketAddr>;
+
+  #[test]
+  fn test_replay_keeps_ipv6_scope_id() {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("replay_keeps_ipv6_scope_id");
+
+    let scoped = Node::new(
+      SmolStr::new("scoped"),
+      "[fe80::1%3]:7946".parse::<SocketAddr>().unwrap(),
+    );
+    let plain = Node::new(
+      SmolStr::new("plain"),
+      "127.0.0.1:7946".parse::<SocketAddr>().unwrap(),
+    );
+
+    let mut fh = File::create(&p).unwrap();
+    SnapshotRecord::Alive(Cow::Borrowed(&scoped))
+      .encode::<Lpe, _>(&mut fh)
+      .unwrap();
+    SnapshotRecord::Alive(Cow::Borrowed(&plain))
+      .encode::<Lpe, _>(&mut fh)
+      .unwrap();
+    drop(fh);
+
+    let res = open_and_replay_snapshot::<_, _, Lpe, _>(&p, false, None).unwrap();
+    assert_eq!(res.alive_nodes.len(), 2);
+    assert!(res.alive_nodes.contains(&scoped));
+    assert!(res.alive_nodes.contains(&plain));
+  }
+
+  #[test]
+  fn test_replay_user_event_digests() {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("replay_user_event_digests");
+
+    let foo = EventDigest::new(&SmolStr::new("foo"), b"a");
+    let bar = EventDigest::new(&SmolStr::new("bar"), b"b");
+    let records = [
+      SnapshotRecord::<SmolStr, SocketAddr>::UserEvent(LamportTime::new(3), &foo),
+      SnapshotRecord::UserEvent(LamportTime::new(3), &bar),
+      SnapshotRecord::UserEvent(LamportTime::new(3), &foo),
+      SnapshotRecord::EventClock(LamportTime::new(3)),
+    ];
+    let mut fh = File::create(&p).unwrap();
+    for record in records {
+      record.encode::<Lpe, _>(&mut fh).unwrap();
+    }
+    drop(fh);
+
+    let res = open_and_replay_snapshot::<_, _, Lpe, _>(&p, false, None).unwrap();
+    let digests = &res.recent_events[&LamportTime::new(3)];
+    assert_eq!(digests.len(), 2);
+    assert!(digests[0].matches("foo", b"a"));
+    assert!(!digests[0].matches("foo", b"b"));
+    assert!(digests[1].matches("bar", b"b"));
+    drop(res);
+
+    // A leave forgets the events seen before it
+    let mut fh = OpenOptions::new().append(true).open(&p).unwrap();
+    SnapshotRecord::<SmolStr, SocketAddr>::Leave
+      .encode::<Lpe, _>(&mut fh)
+      .unwrap();
+    drop(fh);
+    let res = open_and_replay_snapshot::<_, _, Lpe, _>(&p, false, None).unwrap();
+    assert!(res.recent_events.is_empty());
+    drop(res);
+    let res = open_and_replay_snapshot::<_, _, Lpe, _>(&p, true, None).unwrap();
+    assert_eq!(res.recent_events.len(), 1);
+  }
+
+  #[test]
+  fn test_replay_truncates_corrupted_tail() {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("replay_truncates_corrupted_tail");
+
+    // A new snapshot is framed
+    let res = open_and_replay_snapshot::<SmolStr, SocketAddr, Lpe, _>(&p, false, None).unwrap();
+    assert_eq!(res.format, SnapshotFormat::V2);
+    assert_eq!(res.offset, HEADER_SIZE as u64);
+    let mut fh = res.fh;
+
+    let node = Node::new(
+      SmolStr::new("foo"),
+      "127.0.0.1:7946".parse::<SocketAddr>().unwrap(),
+    );
+    let mut good = HEADER_SIZE;
+    for record in [
+      SnapshotRecord::Alive(Cow::Borrowed(&node)),
+      SnapshotRecord::Clock(LamportTime::new(5)),
+    ] {
+      good += append_record::<_, _, Lpe, _>(
+        record,
+        &mut fh,
+        SnapshotFormat::V2,
+        #[cfg(feature = "encryption")]
+        None,
+      )
+      .unwrap();
+    }
+
+    // A record whose payload got corrupted
+    let mut corrupted = Vec::new();
+    append_record::<SmolStr, SocketAddr, Lpe, _>(
+      SnapshotRecord::Clock(LamportTime::new(9)),
+      &mut corrupted,
+      SnapshotFormat::V2,
+      #[cfg(feature = "encryption")]
+      None,
+    )
+    .unwrap();
+    *corrupted.last_mut().unwrap() ^= 0xff;
+    fh.write_all(&corrupted).unwrap();
+    drop(fh);
+
+    let res = open_and_replay_snapshot::<_, _, Lpe, _>(&p, false, None).unwrap();
+    assert!(res.alive_nodes.contains(&node));
+    assert_eq!(res.last_clock, LamportTime::new(5));
+    assert_eq!(res.offset, good as u64);
+    assert_eq!(std::fs::metadata(&p).unwrap().len(), good as u64);
+    let mut fh = res.fh;
+
+    // A torn write
+    fh.write_all(&corrupted[..FRAME_HEADER_SIZE + 2]).unwrap();
+    drop(fh);
+
+    let res = open_and_replay_snapshot::<SmolStr, SocketAddr, Lpe, _>(&p, false, None).unwrap();
+    assert_eq!(res.last_clock, LamportTime::new(5));
+    assert_eq!(std::fs::metadata(&p).unwrap().len(), good as u64);
+  }
+
+  #[test]
+  fn test_inspect_snapshot() {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("inspect_snapshot");
+
+    let res = open_and_replay_snapshot::<SmolStr, SocketAddr, Lpe, _>(&p, false, None).unwrap();
+    let mut fh = res.fh;
+    let node = Node::new(
+      SmolStr::new("foo"),
+      "127.0.0.1:7946".parse::<SocketAddr>().unwrap(),
+    );
+    let mut good = HEADER_SIZE;
+    for record in [
+      SnapshotRecord::Alive(Cow::Borrowed(&node)),
+      SnapshotRecord::Clock(LamportTime::new(5)),
+      SnapshotRecord::QueryClock(LamportTime::new(3)),
+      SnapshotRecord::Leave,
+    ] {
+      good += append_record::<_, _, Lpe, _>(
+        record,
+        &mut fh,
+        SnapshotFormat::V2,
+        #[cfg(feature = "encryption")]
+        None,
+      )
+      .unwrap();
+    }
+    // A torn write
+    fh.write_all(&[1, 2, 3]).unwrap();
+    drop(fh);
+    let len = good as u64 + 3;
+
+    // The leave is ignored if the node rejoins after leaving
+    let report = inspect_snapshot::<Lpe>(&p, true).unwrap();
+    assert_eq!(report.format_version(), SnapshotFormat::V2 as u8);
+    assert_eq!(report.alive_nodes(), &[node]);
+    assert_eq!(report.num_alive_nodes(), 1);
+    assert_eq!(report.clock(), LamportTime::new(5));
+    assert_eq!(report.query_clock(), LamportTime::new(3));
+    assert!(report.left());
+    assert_eq!(report.len(), len);
+    let corruption = report.corruption().unwrap();
+    assert_eq!(corruption.range(), &(good as u64..len));
+    assert_eq!(corruption.reason(), "torn record header");
+
+    let report = inspect_snapshot::<Lpe>(&p, false).unwrap();
+    assert_eq!(report.num_alive_nodes(), 0);
+    assert_eq!(report.clock(), LamportTime::ZERO);
+
+    // The snapshot is left untouched
+    assert_eq!(std::fs::metadata(&p).unwrap().len(), len);
+  }
+
+  #[test]
+  fn test_replay_v1() {
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("replay_v1");
+
+    let mut fh = File::create(&p).unwrap();
+    SnapshotRecord::<SmolStr, SocketAddr>::Clock(LamportTime::new(3))
+      .encode::<Lpe, _>(&mut fh)
+      .unwrap();
+    drop(fh);
+
+    let res = open_and_replay_snapshot::<SmolStr, SocketAddr, Lpe, _>(&p, false, None).unwrap();
+    assert_eq!(res.format, SnapshotFormat::V1);
+    assert_eq!(res.last_clock, LamportTime::new(3));
+    assert_eq!(res.offset, 9);
+  }
+
+  #[test]
+  fn test_replay_compressed() {
+    struct Invert;
+
+    impl Compressor for Invert {
+      fn id(&self) -> u8 {
+        42
+      }
+
+      fn compress(&self, src: &[u8]) -> std::io::Result<Vec<u8>> {
+        Ok(src.iter().map(|b| !b).collect())
+      }
+
+      fn decompress(&self, src: &[u8], _limit: usize) -> std::io::Result<Vec<u8>> {
+        self.compress(src)
+      }
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("replay_compressed");
+
+    let res = open_and_replay_snapshot::<SmolStr, SocketAddr, Lpe, _>(&p, false, None).unwrap();
+    let mut fh = res.fh;
+    let (foo, bar) = (
+      Node::new(
+        SmolStr::new("foo-node"),
+        "127.0.0.1:7946".parse::<SocketAddr>().unwrap(),
+      ),
+      Node::new(
+        SmolStr::new("bar-node"),
+        "127.0.0.1:7947".parse::<SocketAddr>().unwrap(),
+      ),
+    );
+    let mut plain = Vec::new();
+    for record in [
+      SnapshotRecord::Alive(Cow::Borrowed(&foo)),
+      SnapshotRecord::Alive(Cow::Borrowed(&bar)),
+      SnapshotRecord::Clock(LamportTime::new(5)),
+    ] {
+      record.encode::<Lpe, _>(&mut plain).unwrap();
+    }
+    let segment = Invert.compress(&plain).unwrap();
+    // The records appended after the compaction are not compressed
+    for record in [
+      SnapshotRecord::Compressed(42, &segment),
+      SnapshotRecord::NotAlive(Cow::Borrowed(&bar)),
+    ] {
+      append_record::<_, _, Lpe, _>(
+        record,
+        &mut fh,
+        SnapshotFormat::V2,
+        #[cfg(feature = "encryption")]
+        None,
+      )
+      .unwrap();
+    }
+    drop(fh);
+
+    let raw = std::fs::read(&p).unwrap();
+    assert!(!raw.windows(8).any(|w| w == b"foo-node"));
+
+    let res =
+      open_and_replay_snapshot::<SmolStr, SocketAddr, Lpe, _>(&p, false, Some(Arc::new(Invert)))
+        .unwrap();
+    assert_eq!(res.alive_nodes, HashSet::from([foo]));
+    assert_eq!(res.last_clock, LamportTime::new(5));
+    drop(res);
+
+    // The segment cannot be inflated without its compressor
+    assert!(matches!(
+      inspect_snapshot::<Lpe>(&p, false),
+      Err(SnapshotError::Decompress(_))
+    ));
+  }
+
+  #[test]
+  fn test_trim_recent_events() {
+    let digest = EventDigest::new(&SmolStr::new("foo"), b"");
+    let mut recent = (1..=10)
+      .map(|t| (LamportTime::new(t), vec![digest.clone()]))
+      .collect::<RecentEvents>();
+    trim_recent_events(&mut recent, LamportTime::new(10), 4);
+    assert_eq!(
+      recent.keys().copied().collect::<Vec<_>>(),
+      (6..=10).map(LamportTime::new).collect::<Vec<_>>()
+    );
+  }
+
+  #[cfg(feature = "encryption")]
+  #[test]
+  fn test_replay_encrypted() {
+    use memberlist_core::types::SecretKeyring;
+
+    let dir = tempfile::tempdir().unwrap();
+    let p = dir.path().join("replay_encrypted");
+
+    let old_key = SecretKey::Aes128([1; 16]);
+    let new_key = SecretKey::Aes256([2; 32]);
+    let keyring = SecretKeyring::new(old_key);
+    let cipher = futures::executor::block_on(SnapshotCipher::new(keyring.clone()));
+
+    let node = Node::new(
+      SmolStr::new("secret-node"),
+      "127.0.0.1:7946".parse::<SocketAddr>().unwrap(),
+    );
+    let mut fh = File::create(&p).unwrap();
+    // Plaintext records written before the encryption was enabled are still replayed
+    SnapshotRecord::<SmolStr, SocketAddr>::Clock(LamportTime::new(3))
+      .encode::<Lpe, _>(&mut fh)
+      .unwrap();
+    write_record::<_, _, Lpe, _>(
+      SnapshotRecord::Alive(Cow::Borrowed(&node)),
+      &mut fh,
+      Some(&cipher),
+    )
+    .unwrap();
+    write_record::<_, _, Lpe, _>(
+      SnapshotRecord::EventClock(LamportTime::new(7)),
+      &mut fh,
+      Some(&cipher),
+    )
+    .unwrap();
+    drop(fh);
+
+    let raw = std::fs::read(&p).unwrap();
+    assert!(!raw.windows(11).any(|w| w == b"secret-node"));
+
+    // The old key is still in the keyring after the rotation
+    futures::executor::block_on(async {
+      keyring.insert(new_key).await;
+      keyring.use_key(new_key.as_ref()).await.unwrap();
+    });
+    let cipher = futures::executor::block_on(SnapshotCipher::new(keyring));
+    let res = open_and_replay_encrypted_snapshot::<_, _, Lpe, _>(&p, false, cipher, None).unwrap();
+    assert!(res.alive_nodes.contains(&node));
+    assert_eq!(res.last_clock, LamportTime::new(3));
+    assert_eq!(res.last_event_clock, LamportTime::new(7));
+    drop(res);
+
+    // Without the key the snapshot cannot be replayed
+    let cipher = futures::executor::block_on(SnapshotCipher::new(SecretKeyring::new(new_key)));
+    assert!(matches!(
+      open_and_replay_encrypted_snapshot::<_, _, Lpe, _>(&p, false, cipher, None),
+      Err(SnapshotError::Decrypt)
+    ));
+    assert!(matches!(
+      open_and_replay_snapshot::<_, _, Lpe, _>(&p, false, None),
+      Err(SnapshotError::Decrypt)
+    ));
+  }
+}
@@ -0,0 +1,101 @@
+use aes_gcm::{
+  aead::{generic_array::GenericArray, AeadInPlace, KeyInit},
+  aes::{cipher::consts::U12, Aes192},
+  Aes128Gcm, Aes256Gcm, AesGcm,
+};
+use memberlist_core::types::{SecretKey, SecretKeyring};
+use rand::Rng;
+
+type Aes192Gcm = AesGcm<Aes192, U12>;
+
+const NONCE_SIZE: usize = 12;
+
+/// Binds the ciphertexts to the snapshot, so they cannot be replayed as gossip.
+const AUTH_DATA: &[u8] = b"ruserf-snapshot";
+
+macro_rules! with_gcm {
+  ($key:expr, |$gcm:ident| $body:expr) => {
+    match $key {
+      SecretKey::Aes128(k) => {
+        let $gcm = Aes128Gcm::new(GenericArray::from_slice(k));
+        $body
+      }
+      SecretKey::Aes192(k) => {
+        let $gcm = Aes192Gcm::new(GenericArray::from_slice(k));
+        $body
+      }
+      SecretKey::Aes256(k) => {
+        let $gcm = Aes256Gcm::new(GenericArray::from_slice(k));
+        $body
+      }
+    }
+  };
+}
+
+/// Encrypts the snapshot records with the primary key of the keyring.
+pub(crate) struct SnapshotCipher {
+  keyring: SecretKeyring,
+  /// All the keys of the keyring when the snapshot was opened, primary first.
+  keys: Vec<SecretKey>,
+}
+
+impl SnapshotCipher {
+  pub(crate) async fn new(keyring: SecretKeyring) -> Self {
+    let keys = keyring.keys().await.collect();
+    Self { keyring, keys }
+  }
+
+  /// Returns the keys tried in order to decrypt a record.
+  pub(crate) fn keys(&self) -> &[SecretKey] {
+    &self.keys
+  }
+
+  /// Returns `true` if the primary key of the keyring changed since the last call.
+  pub(crate) async fn rotated(&mut self) -> bool {
+    let primary = self.keyring.primary_key().await;
+    if self.keys.first() == Some(&primary) {
+      return false;
+    }
+
+    self.keys = self.keyring.keys().await.collect();
+    true
+  }
+
+  /// Encrypts a record with the primary key, the nonce is prepended to the ciphertext.
+  pub(crate) fn seal(&self, record: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_SIZE];
+    rand::thread_rng().fill(&mut nonce);
+
+    let mut ciphertext = record.to_vec();
+    with_gcm!(&self.keys[0], |gcm| gcm.encrypt_in_place(
+      GenericArray::from_slice(&nonce),
+      AUTH_DATA,
+      &mut ciphertext
+    ))
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    let mut sealed = Vec::with_capacity(NONCE_SIZE + ciphertext.len());
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+  }
+}
+
+/// Decrypts a record sealed by [`SnapshotCipher::seal`], trying each key in order.
+pub(crate) fn open(keys: &[SecretKey], sealed: &[u8]) -> Option<Vec<u8>> {
+  if sealed.len() < NONCE_SIZE {
+    return None;
+  }
+
+  let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE);
+  keys.iter().find_map(|key| {
+    let mut buf = ciphertext.to_vec();
+    with_gcm!(key, |gcm| gcm.decrypt_in_place(
+      GenericArray::from_slice(nonce),
+      AUTH_DATA,
+      &mut buf
+    ))
+    .ok()
+    .map(|_| buf)
+  })
+}
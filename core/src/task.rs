@@ -0,0 +1,171 @@
+use std::{
+  sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc,
+  },
+  time::{Duration, SystemTime},
+};
+
+use memberlist_core::tracing;
+
+/// The health of a long-lived background task, see
+/// [`Serf::task_health`](crate::Serf::task_health).
+///
+/// The periodic tasks, e.g. the reaper, iterate at least once per interval,
+/// while the event driven ones, e.g. the coalescers, only iterate once per
+/// event, so an old last iteration is only a sign of a stuck loop for the
+/// former.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaskHealth {
+  name: &'static str,
+  iterations: u64,
+  last_iteration: Option<SystemTime>,
+  exited: bool,
+}
+
+impl TaskHealth {
+  /// Returns the name of the task, also the `task` field of its tracing span.
+  #[inline]
+  pub const fn name(&self) -> &'static str {
+    self.name
+  }
+
+  /// Returns how many iterations the loop of the task started.
+  #[inline]
+  pub const fn iterations(&self) -> u64 {
+    self.iterations
+  }
+
+  /// Returns when the loop of the task last started an iteration, `None` if
+  /// it never did.
+  #[inline]
+  pub const fn last_iteration(&self) -> Option<SystemTime> {
+    self.last_iteration
+  }
+
+  /// Returns how long ago the loop of the task last started an iteration.
+  pub fn since_last_iteration(&self) -> Option<Duration> {
+    self
+      .last_iteration
+      .map(|at| at.elapsed().unwrap_or(Duration::ZERO))
+  }
+
+  /// Returns `true` if the task is over, either because [`Serf`](crate::Serf)
+  /// was shut down or because it stopped unexpectedly.
+  #[inline]
+  pub const fn exited(&self) -> bool {
+    self.exited
+  }
+}
+
+/// The state of a task shared with the [`TaskRegistry`].
+pub(crate) struct TaskState {
+  name: &'static str,
+  iterations: AtomicU64,
+  /// The milliseconds since the unix epoch, `0` if the loop never iterated.
+  last_iteration: AtomicU64,
+  exited: AtomicBool,
+}
+
+impl TaskState {
+  fn health(&self) -> TaskHealth {
+    let last_iteration = self.last_iteration.load(Ordering::Acquire);
+    TaskHealth {
+      name: self.name,
+      iterations: self.iterations.load(Ordering::Acquire),
+      last_iteration: (last_iteration != 0)
+        .then(|| SystemTime::UNIX_EPOCH + Duration::from_millis(last_iteration)),
+      exited: self.exited.load(Ordering::Acquire),
+    }
+  }
+}
+
+/// Owned by a background task to report its iterations, the task is reported
+/// as exited once the heartbeat is dropped, even if the task panicked.
+pub(crate) struct TaskHeartbeat(Arc<TaskState>);
+
+impl TaskHeartbeat {
+  pub(crate) fn new(name: &'static str) -> Self {
+    Self(Arc::new(TaskState {
+      name,
+      iterations: AtomicU64::new(0),
+      last_iteration: AtomicU64::new(0),
+      exited: AtomicBool::new(false),
+    }))
+  }
+
+  /// Records the start of an iteration of the loop of the task.
+  pub(crate) fn beat(&self) {
+    let now = SystemTime::now()
+      .duration_since(SystemTime::UNIX_EPOCH)
+      .map_or(1, |d| d.as_millis().max(1) as u64);
+    self.0.last_iteration.store(now, Ordering::Release);
+    self.0.iterations.fetch_add(1, Ordering::AcqRel);
+  }
+
+  pub(crate) fn state(&self) -> Arc<TaskState> {
+    self.0.clone()
+  }
+
+  /// Returns the tracing span the task runs in, so the events it logs, and
+  /// the task itself in `tokio-console`, are named after it.
+  pub(crate) fn span(&self) -> tracing::Span {
+    tracing::info_span!("ruserf.task", task = self.0.name)
+  }
+}
+
+impl Drop for TaskHeartbeat {
+  fn drop(&mut self) {
+    self.0.exited.store(true, Ordering::Release);
+  }
+}
+
+/// The background tasks of a [`Serf`](crate::Serf) instance.
+#[derive(Default)]
+pub(crate) struct TaskRegistry {
+  tasks: parking_lot::Mutex<Vec<Arc<TaskState>>>,
+}
+
+impl TaskRegistry {
+  /// Returns the heartbeat of a new task named `name`.
+  pub(crate) fn register(&self, name: &'static str) -> TaskHeartbeat {
+    let heartbeat = TaskHeartbeat::new(name);
+    self.track(heartbeat.state());
+    heartbeat
+  }
+
+  /// Tracks a task whose heartbeat was created on its own.
+  pub(crate) fn track(&self, state: Arc<TaskState>) {
+    self.tasks.lock().push(state);
+  }
+
+  pub(crate) fn health(&self) -> Vec<TaskHealth> {
+    self.tasks.lock().iter().map(|t| t.health()).collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_task_health() {
+    let tasks = TaskRegistry::default();
+    let heartbeat = tasks.register("reaper");
+    let health = tasks.health();
+    assert_eq!(health.len(), 1);
+    assert_eq!(health[0].name(), "reaper");
+    assert_eq!(health[0].iterations(), 0);
+    assert!(health[0].last_iteration().is_none());
+    assert!(!health[0].exited());
+
+    heartbeat.beat();
+    heartbeat.beat();
+    let health = tasks.health();
+    assert_eq!(health[0].iterations(), 2);
+    assert!(health[0].since_last_iteration().unwrap() < Duration::from_secs(60));
+
+    drop(heartbeat);
+    assert!(tasks.health()[0].exited());
+  }
+}
@@ -2,6 +2,15 @@ pub use ruserf_types::*;
 
 mod member;
 pub(crate) use member::*;
+pub use member::{DepartedMember, MemberInfo, TombstoneEviction};
+
+mod state;
+pub use state::*;
+
+pub(crate) mod scope;
+
+mod tag_index;
+pub(crate) use tag_index::TagIndex;
 
 use std::time::Duration;
 
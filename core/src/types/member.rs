@@ -1,9 +1,14 @@
 use memberlist_core::types::OneOrMore;
+use regex::Regex;
 use ruserf_types::Member;
 
-use std::collections::HashMap;
+use std::{
+  collections::{HashMap, VecDeque},
+  hash::Hash,
+  time::{Duration, SystemTime},
+};
 
-use super::{Epoch, LamportTime, MessageType};
+use super::{Epoch, LamportTime, MemberStatus, MessageType, ProtocolVersion, TagIndex, Tags};
 
 /// Used to track members that are no longer active due to
 /// leaving, failing, partitioning, etc. It tracks the member along with
@@ -18,12 +23,166 @@ pub(crate) struct MemberState<I, A> {
   leave_time: Option<Epoch>,
 }
 
+/// A member which left or failed, see [`Serf::left_members`](crate::Serf::left_members)
+/// and [`Serf::failed_members`](crate::Serf::failed_members).
+#[derive(Clone, Debug, PartialEq)]
+pub struct DepartedMember<I, A> {
+  member: Member<I, A>,
+  status_time: LamportTime,
+  departed_at: SystemTime,
+}
+
+impl<I, A> DepartedMember<I, A> {
+  /// Returns the member.
+  #[inline]
+  pub const fn member(&self) -> &Member<I, A> {
+    &self.member
+  }
+
+  /// Returns the lamport time of the last status change of the member.
+  #[inline]
+  pub const fn status_time(&self) -> LamportTime {
+    self.status_time
+  }
+
+  /// Returns when the member left or failed, as seen by the local node.
+  #[inline]
+  pub const fn departed_at(&self) -> SystemTime {
+    self.departed_at
+  }
+}
+
+impl<I: Clone, A: Clone> DepartedMember<I, A> {
+  pub(crate) fn from_state(state: &MemberState<I, A>, now: Epoch) -> Self {
+    let elapsed = state.leave_time.map_or(Duration::ZERO, |t| now - t);
+    Self {
+      member: state.member.clone(),
+      status_time: state.status_time,
+      departed_at: SystemTime::now() - elapsed,
+    }
+  }
+}
+
+/// A known member, with when the local node first saw it and when its
+/// status last changed, see [`Serf::members_info`](crate::Serf::members_info).
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemberInfo<I, A> {
+  member: Member<I, A>,
+  status_time: LamportTime,
+  first_seen: SystemTime,
+  status_changed_at: SystemTime,
+}
+
+impl<I, A> MemberInfo<I, A> {
+  /// Returns the member.
+  #[inline]
+  pub const fn member(&self) -> &Member<I, A> {
+    &self.member
+  }
+
+  /// Returns the lamport time of the last status change of the member.
+  #[inline]
+  pub const fn status_time(&self) -> LamportTime {
+    self.status_time
+  }
+
+  /// Returns when the local node first saw the member.
+  #[inline]
+  pub const fn first_seen(&self) -> SystemTime {
+    self.first_seen
+  }
+
+  /// Returns when the status of the member last changed, as seen by the
+  /// local node.
+  #[inline]
+  pub const fn status_changed_at(&self) -> SystemTime {
+    self.status_changed_at
+  }
+
+  /// Returns how long the member has been alive since its last status
+  /// change, `None` if it is not alive.
+  pub fn uptime(&self) -> Option<Duration> {
+    (self.member.status == MemberStatus::Alive)
+      .then(|| self.status_changed_at.elapsed().unwrap_or_default())
+  }
+}
+
+impl<I: Clone, A: Clone> MemberInfo<I, A> {
+  pub(crate) fn from_state(
+    state: &MemberState<I, A>,
+    timeline: Option<&MemberTimeline>,
+    now: Epoch,
+  ) -> Self {
+    let wall_now = SystemTime::now();
+    let at = |t: Epoch| wall_now - (now - t);
+    let (first_seen, status_changed_at) = timeline.map_or((wall_now, wall_now), |t| {
+      (at(t.first_seen), at(t.status_changed_at))
+    });
+    Self {
+      member: state.member.clone(),
+      status_time: state.status_time,
+      first_seen,
+      status_changed_at,
+    }
+  }
+}
+
+/// When the local node first saw a member and when its status last changed.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct MemberTimeline {
+  pub(crate) first_seen: Epoch,
+  pub(crate) status_changed_at: Epoch,
+}
+
+/// Which tombstone is evicted once [`Options::max_left_members`](crate::Options::max_left_members)
+/// is exceeded.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum TombstoneEviction {
+  /// Evict the member which left the longest time ago.
+  #[default]
+  Oldest,
+  /// Evict the member which left most recently, so the older tombstones are
+  /// kept until their timeout.
+  Newest,
+}
+
+impl TombstoneEviction {
+  /// Returns the string representation of the eviction strategy
+  #[inline]
+  pub const fn as_str(&self) -> &'static str {
+    match self {
+      Self::Oldest => "oldest",
+      Self::Newest => "newest",
+    }
+  }
+
+  /// Returns the index of the member to evict.
+  pub(crate) fn select<I, A>(&self, members: &[MemberState<I, A>]) -> Option<usize> {
+    let departed = members.iter().enumerate().map(|(i, m)| (m.leave_time, i));
+    match self {
+      Self::Oldest => departed.min().map(|(_, i)| i),
+      Self::Newest => departed.max().map(|(_, i)| i),
+    }
+  }
+}
+
+impl core::fmt::Display for TombstoneEviction {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
 /// Used to buffer intents for out-of-order deliveries.
 #[derive(Debug)]
 pub(crate) struct NodeIntent {
   pub(crate) ty: MessageType,
   pub(crate) wall_time: Epoch,
   pub(crate) ltime: LamportTime,
+  /// Whether a query or a relay referenced the node while it was unknown,
+  /// such intents are evicted last as the node is likely about to join.
+  pub(crate) referenced: bool,
 }
 
 pub(crate) struct Members<I, A> {
@@ -31,6 +190,12 @@ pub(crate) struct Members<I, A> {
   pub(crate) recent_intents: HashMap<I, NodeIntent>,
   pub(crate) left_members: OneOrMore<MemberState<I, A>>,
   pub(crate) failed_members: OneOrMore<MemberState<I, A>>,
+  /// When each member recently failed and rejoined, see [`Options::flap_threshold`](crate::Options::flap_threshold).
+  pub(crate) flaps: HashMap<I, VecDeque<Epoch>>,
+  /// When each member was first seen and last changed status, see [`MemberInfo`].
+  pub(crate) timeline: HashMap<I, MemberTimeline>,
+  /// The members by their tags, see [`TagIndex`].
+  pub(crate) tag_index: TagIndex<I>,
 }
 
 impl<I, A> Default for Members<I, A> {
@@ -40,6 +205,86 @@ impl<I, A> Default for Members<I, A> {
       recent_intents: Default::default(),
       left_members: Default::default(),
       failed_members: Default::default(),
+      flaps: Default::default(),
+      timeline: Default::default(),
+      tag_index: Default::default(),
     }
   }
 }
+
+impl<I: Eq + Hash + Clone, A> Members<I, A> {
+  /// Re-indexes the tags of a member once they changed from `old`, `None`
+  /// if the member was not known before.
+  pub(crate) fn reindex_tags(&mut self, id: &I, old: Option<&Tags>) {
+    if let Some(old) = old {
+      self.tag_index.remove(id, old);
+    }
+    if let Some(ms) = self.states.get(id) {
+      self.tag_index.insert(id, &ms.member.tags);
+    }
+  }
+
+  /// Returns the states of the members which may match every tag filter,
+  /// looked up through the tag index rather than scanning every member.
+  pub(crate) fn states_tagged<'a, 'b>(
+    &'a self,
+    filters: impl IntoIterator<Item = (&'b str, &'b Regex)>,
+  ) -> impl Iterator<Item = &'a MemberState<I, A>> {
+    let (indexed, all) = match self.tag_index.lookup(filters) {
+      Some(ids) => (
+        Some(ids.into_iter().filter_map(|id| self.states.get(id))),
+        None,
+      ),
+      None => (None, Some(self.states.values())),
+    };
+    indexed
+      .into_iter()
+      .flatten()
+      .chain(all.into_iter().flatten())
+  }
+
+  /// Returns `true` if every member which did not leave, the local node
+  /// included, speaks `vsn` or a newer protocol. The newer protocols are
+  /// only used once every member speaks them, a failed member may still
+  /// come back.
+  pub(crate) fn all_speak(&self, vsn: ProtocolVersion) -> bool {
+    self
+      .states
+      .values()
+      .filter(|m| m.member.status != MemberStatus::Left)
+      .all(|m| m.member.protocol_version as u8 >= vsn as u8)
+  }
+
+  /// Records a flap of the member, and returns how many times it flapped within the window.
+  pub(crate) fn record_flap(&mut self, id: I, now: Epoch, window: Duration) -> usize {
+    let flaps = self.flaps.entry(id).or_default();
+    while flaps.front().is_some_and(|t| now - *t > window) {
+      flaps.pop_front();
+    }
+    flaps.push_back(now);
+    flaps.len()
+  }
+
+  /// Records a status change of the member, the first one is when it is
+  /// first seen.
+  pub(crate) fn record_status_change(&mut self, id: I, now: Epoch) {
+    self
+      .timeline
+      .entry(id)
+      .and_modify(|t| t.status_changed_at = now)
+      .or_insert(MemberTimeline {
+        first_seen: now,
+        status_changed_at: now,
+      });
+  }
+
+  /// Returns how long ago a member last changed status, if any did.
+  pub(crate) fn last_status_change(&self, now: Epoch) -> Option<Duration> {
+    self
+      .timeline
+      .values()
+      .map(|t| t.status_changed_at)
+      .max()
+      .map(|t| now - t)
+  }
+}
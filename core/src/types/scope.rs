@@ -0,0 +1,119 @@
+use std::{
+  any::Any,
+  net::{IpAddr, Ipv6Addr, SocketAddr},
+};
+
+use crate::delegate::TransformDelegate;
+
+/// Returns the address as a [`SocketAddr`], if that is the concrete address type.
+///
+/// Serf is generic over the address type, the default
+/// [`TransformDelegate`] and the discovery only know about plain socket
+/// addresses.
+#[inline]
+pub(crate) fn as_socket_addr<A: 'static>(addr: &A) -> Option<&SocketAddr> {
+  (addr as &dyn Any).downcast_ref::<SocketAddr>()
+}
+
+#[inline]
+pub(crate) fn from_socket_addr<A: 'static>(addr: SocketAddr) -> Option<A> {
+  let addr: Box<dyn Any> = Box::new(addr);
+  addr.downcast::<A>().ok().map(|addr| *addr)
+}
+
+#[inline]
+const fn is_link_local_v6(ip: &Ipv6Addr) -> bool {
+  (ip.segments()[0] & 0xffc0) == 0xfe80
+}
+
+/// Returns the address with `scope_id` applied, if it is a link-local IPv6
+/// address which was learned without a zone.
+pub(crate) fn with_link_local_scope_id<D: TransformDelegate>(
+  addr: &D::Address,
+  scope_id: u32,
+) -> Option<D::Address> {
+  match D::address_ip(addr)? {
+    IpAddr::V6(ip) if is_link_local_v6(&ip) && D::address_scope_id(addr).is_none() => {
+      D::with_address_scope_id(addr, scope_id)
+    }
+    _ => None,
+  }
+}
+
+/// Returns `false` if the address is known to only be reachable from a
+/// limited scope (loopback, link-local, private or unique local addresses).
+///
+/// Addresses without an IP are always considered global.
+pub(crate) fn is_global<D: TransformDelegate>(addr: &D::Address) -> bool {
+  let Some(ip) = D::address_ip(addr) else {
+    return true;
+  };
+
+  match ip {
+    IpAddr::V4(ip) => {
+      !(ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_private()
+        || ip.is_unspecified()
+        || ip.is_broadcast())
+    }
+    IpAddr::V6(ip) => {
+      let first = ip.segments()[0];
+      !(ip.is_loopback()
+        || ip.is_unspecified()
+        || is_link_local_v6(&ip)
+        || (first & 0xfe00) == 0xfc00)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use smol_str::SmolStr;
+
+  use super::*;
+  use crate::delegate::LpeTransfromDelegate;
+
+  type Lpe = LpeTransfromDelegate<SmolStr, SocketAddr>;
+
+  #[test]
+  fn test_link_local_scope_id() {
+    let addr: SocketAddr = "[fe80::1]:7946".parse().unwrap();
+    assert_eq!(Lpe::address_scope_id(&addr), None);
+
+    let scoped = with_link_local_scope_id::<Lpe>(&addr, 3).unwrap();
+    assert_eq!(Lpe::address_scope_id(&scoped), Some(3));
+    assert_eq!(scoped.to_string(), "[fe80::1%3]:7946");
+
+    // an existing zone is never overridden
+    assert!(with_link_local_scope_id::<Lpe>(&scoped, 4).is_none());
+    // only link-local addresses need a zone
+    let global: SocketAddr = "[2001:db8::1]:7946".parse().unwrap();
+    assert!(with_link_local_scope_id::<Lpe>(&global, 3).is_none());
+    // other address types are left untouched
+    let name = SmolStr::new("fe80::1");
+    assert!(with_link_local_scope_id::<LpeTransfromDelegate<SmolStr, SmolStr>>(&name, 3).is_none());
+  }
+
+  #[test]
+  fn test_is_global() {
+    for addr in [
+      "127.0.0.1:1",
+      "10.0.0.1:1",
+      "169.254.0.1:1",
+      "[fe80::1]:1",
+      "[fd00::1]:1",
+    ] {
+      let addr: SocketAddr = addr.parse().unwrap();
+      assert!(!is_global::<Lpe>(&addr), "{addr}");
+    }
+
+    for addr in ["1.1.1.1:1", "[2001:db8::1]:1"] {
+      let addr: SocketAddr = addr.parse().unwrap();
+      assert!(is_global::<Lpe>(&addr), "{addr}");
+    }
+
+    let name = SmolStr::new("node.local");
+    assert!(is_global::<LpeTransfromDelegate<SmolStr, SmolStr>>(&name));
+  }
+}
@@ -0,0 +1,70 @@
+use ruserf_types::{LamportTime, Member, UserEvents};
+
+/// A member recorded in a [`ClusterState`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExportedMember<I, A> {
+  pub(crate) member: Member<I, A>,
+  pub(crate) status_time: LamportTime,
+}
+
+impl<I, A> ExportedMember<I, A> {
+  /// Returns the member.
+  #[inline]
+  pub const fn member(&self) -> &Member<I, A> {
+    &self.member
+  }
+
+  /// Returns the lamport time of the last status change of the member.
+  #[inline]
+  pub const fn status_time(&self) -> LamportTime {
+    self.status_time
+  }
+}
+
+/// The state of the cluster as seen by a node, see [`Serf::export_state`](crate::Serf::export_state).
+///
+/// It holds the Lamport clocks, the members and the buffered user events, so a
+/// standby instance seeded with [`Serf::import_state`](crate::Serf::import_state)
+/// carries on from where the exporting instance stopped.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClusterState<I, A> {
+  pub(crate) ltime: LamportTime,
+  pub(crate) event_ltime: LamportTime,
+  pub(crate) query_ltime: LamportTime,
+  pub(crate) members: Vec<ExportedMember<I, A>>,
+  pub(crate) events: Vec<UserEvents>,
+}
+
+impl<I, A> ClusterState<I, A> {
+  /// Returns the time of the member clock.
+  #[inline]
+  pub const fn ltime(&self) -> LamportTime {
+    self.ltime
+  }
+
+  /// Returns the time of the user event clock.
+  #[inline]
+  pub const fn event_ltime(&self) -> LamportTime {
+    self.event_ltime
+  }
+
+  /// Returns the time of the query clock.
+  #[inline]
+  pub const fn query_ltime(&self) -> LamportTime {
+    self.query_ltime
+  }
+
+  /// Returns the members, including the ones which left or failed.
+  #[inline]
+  pub fn members(&self) -> &[ExportedMember<I, A>] {
+    &self.members
+  }
+
+  /// Returns the buffered user events.
+  #[inline]
+  pub fn events(&self) -> &[UserEvents] {
+    &self.events
+  }
+}
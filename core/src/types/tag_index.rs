@@ -0,0 +1,139 @@
+use std::{
+  collections::{HashMap, HashSet},
+  hash::Hash,
+};
+
+use regex::Regex;
+use smol_str::SmolStr;
+
+use super::Tags;
+
+/// An inverted index of the tags of the known members, from a tag key and
+/// value to the ids of the members carrying it.
+///
+/// The tag filters only ever match the values of a single key, so a lookup
+/// matches the distinct values of the key rather than every member, which
+/// is what makes the filtered operations cheap on the large clusters where
+/// most members share a handful of roles or zones.
+#[derive(Debug)]
+pub(crate) struct TagIndex<I> {
+  index: HashMap<SmolStr, HashMap<SmolStr, HashSet<I>>>,
+}
+
+impl<I> Default for TagIndex<I> {
+  fn default() -> Self {
+    Self {
+      index: HashMap::new(),
+    }
+  }
+}
+
+impl<I: Eq + Hash + Clone> TagIndex<I> {
+  /// Indexes the tags of a member.
+  pub(crate) fn insert(&mut self, id: &I, tags: &Tags) {
+    for (key, value) in tags.iter() {
+      self
+        .index
+        .entry(key.clone())
+        .or_default()
+        .entry(value.clone())
+        .or_default()
+        .insert(id.clone());
+    }
+  }
+
+  /// Removes the tags of a member from the index, dropping the keys and
+  /// the values no member carries anymore.
+  pub(crate) fn remove(&mut self, id: &I, tags: &Tags) {
+    for (key, value) in tags.iter() {
+      let Some(values) = self.index.get_mut(key) else {
+        continue;
+      };
+
+      if let Some(ids) = values.get_mut(value) {
+        ids.remove(id);
+        if ids.is_empty() {
+          values.remove(value);
+        }
+      }
+
+      if values.is_empty() {
+        self.index.remove(key);
+      }
+    }
+  }
+
+  /// Returns the ids of the members with a value matching the regular
+  /// expression for every tag key, or `None` if there is no tag filter and
+  /// every member is a candidate.
+  pub(crate) fn lookup<'a>(
+    &self,
+    filters: impl IntoIterator<Item = (&'a str, &'a Regex)>,
+  ) -> Option<HashSet<&I>> {
+    let mut candidates: Option<HashSet<&I>> = None;
+    for (key, re) in filters {
+      let matched = self
+        .index
+        .get(key)
+        .into_iter()
+        .flat_map(|values| values.iter())
+        .filter(|(value, _)| re.is_match(value))
+        .flat_map(|(_, ids)| ids.iter())
+        .filter(|id| candidates.as_ref().map_or(true, |c| c.contains(id)))
+        .collect::<HashSet<_>>();
+
+      if matched.is_empty() {
+        return Some(matched);
+      }
+      candidates = Some(matched);
+    }
+    candidates
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn tags(tags: &[(&'static str, &'static str)]) -> Tags {
+    tags.iter().copied().collect()
+  }
+
+  #[test]
+  fn test_tag_index() {
+    let mut index = TagIndex::default();
+    let a = tags(&[("role", "web"), ("zone", "eu-1")]);
+    let b = tags(&[("role", "db"), ("zone", "eu-1")]);
+    index.insert(&"a", &a);
+    index.insert(&"b", &b);
+
+    let role = Regex::new("^(?:web|api)$").unwrap();
+    let zone = Regex::new("^eu-").unwrap();
+    assert!(index.lookup(None::<(&str, &Regex)>).is_none());
+    assert_eq!(index.lookup([("role", &role)]), Some(HashSet::from([&"a"])));
+    assert_eq!(
+      index.lookup([("zone", &zone)]),
+      Some(HashSet::from([&"a", &"b"]))
+    );
+    assert_eq!(
+      index.lookup([("zone", &zone), ("role", &role)]),
+      Some(HashSet::from([&"a"]))
+    );
+    assert_eq!(index.lookup([("rack", &zone)]), Some(HashSet::new()));
+
+    // A member updating its tags is re-indexed
+    let c = tags(&[("role", "api")]);
+    index.remove(&"b", &b);
+    index.insert(&"b", &c);
+    assert_eq!(
+      index.lookup([("role", &role)]),
+      Some(HashSet::from([&"a", &"b"]))
+    );
+    assert_eq!(index.lookup([("zone", &zone)]), Some(HashSet::from([&"a"])));
+
+    // The keys and values no member carries anymore are dropped
+    index.remove(&"a", &a);
+    index.remove(&"b", &c);
+    assert!(index.index.is_empty());
+  }
+}
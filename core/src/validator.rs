@@ -0,0 +1,113 @@
+use std::{collections::HashMap, sync::Arc};
+
+use memberlist_core::{
+  bytes::Bytes,
+  transport::{AddressResolver, Transport},
+};
+use smol_str::SmolStr;
+
+use crate::{delegate::Delegate, Serf};
+
+/// Validates the payload of the user events of a name before they are
+/// delivered, see [`Serf::register_user_event_validator`].
+///
+/// The events whose payload is rejected are delivered as an
+/// [`InvalidUserEvent`](crate::event::InvalidUserEvent) instead, so the
+/// consumers of the user events only ever see the well-formed payloads.
+#[auto_impl::auto_impl(Arc)]
+pub trait UserEventValidator: Send + Sync + 'static {
+  /// Returns why the payload is rejected, if it is.
+  fn validate(&self, payload: &Bytes) -> Result<(), SmolStr>;
+}
+
+impl<F> UserEventValidator for F
+where
+  F: Fn(&Bytes) -> Result<(), SmolStr> + Send + Sync + 'static,
+{
+  fn validate(&self, payload: &Bytes) -> Result<(), SmolStr> {
+    self(payload)
+  }
+}
+
+/// The validators registered on the local node, by user event name.
+#[derive(Default)]
+pub(crate) struct UserEventValidators {
+  validators: parking_lot::RwLock<HashMap<SmolStr, Arc<dyn UserEventValidator>>>,
+}
+
+impl UserEventValidators {
+  /// Returns why the payload of the user event `name` is rejected, the
+  /// events without a registered validator are always accepted.
+  pub(crate) fn validate(&self, name: &str, payload: &Bytes) -> Result<(), SmolStr> {
+    // Do not hold the lock while the validator runs
+    let validator = self.validators.read().get(name).cloned();
+    match validator {
+      Some(validator) => validator.validate(payload),
+      None => Ok(()),
+    }
+  }
+}
+
+impl<T, D> Serf<T, D>
+where
+  D: Delegate<Id = T::Id, Address = <T::Resolver as AddressResolver>::ResolvedAddress>,
+  T: Transport,
+{
+  /// Registers the validator of the payloads of the user events named
+  /// `name` received by this node, replacing and returning the previous one,
+  /// if any.
+  pub fn register_user_event_validator(
+    &self,
+    name: impl Into<SmolStr>,
+    validator: impl UserEventValidator,
+  ) -> Option<Arc<dyn UserEventValidator>> {
+    self
+      .inner
+      .user_event_validators
+      .validators
+      .write()
+      .insert(name.into(), Arc::new(validator))
+  }
+
+  /// Unregisters the validator of the user events named `name`, returns it
+  /// if it was registered.
+  pub fn unregister_user_event_validator(&self, name: &str) -> Option<Arc<dyn UserEventValidator>> {
+    self
+      .inner
+      .user_event_validators
+      .validators
+      .write()
+      .remove(name)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_user_event_validators() {
+    let validators = UserEventValidators::default();
+    let not_empty = |payload: &Bytes| {
+      if payload.is_empty() {
+        Err(SmolStr::new("empty payload"))
+      } else {
+        Ok(())
+      }
+    };
+    validators
+      .validators
+      .write()
+      .insert(SmolStr::new("deploy"), Arc::new(not_empty));
+
+    assert!(validators
+      .validate("deploy", &Bytes::from_static(b"v1"))
+      .is_ok());
+    assert_eq!(
+      validators.validate("deploy", &Bytes::new()),
+      Err(SmolStr::new("empty payload"))
+    );
+    // The other events are not validated
+    assert!(validators.validate("restart", &Bytes::new()).is_ok());
+  }
+}
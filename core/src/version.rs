@@ -0,0 +1,160 @@
+use core::fmt;
+
+use smol_str::{format_smolstr, SmolStr};
+
+use crate::{
+  types::{DelegateVersion, ProtocolVersion, Tags},
+  Options,
+};
+
+/// The reserved tag carrying the versions advertised by a member in its meta.
+pub(crate) const VERSION_TAG: &str = "_ruserf_vsn";
+
+/// The newest protocol version understood by the local node, the compressed
+/// push/pull state of the second one needs the `lz4` feature.
+#[cfg(feature = "lz4")]
+pub(crate) const PROTOCOL_MAX: ProtocolVersion = ProtocolVersion::MAX;
+/// The newest protocol version understood by the local node, the compressed
+/// push/pull state of the second one needs the `lz4` feature.
+#[cfg(not(feature = "lz4"))]
+pub(crate) const PROTOCOL_MAX: ProtocolVersion = ProtocolVersion::V1;
+
+/// The protocol and delegate versions a member speaks, and the ranges it understands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Versions {
+  protocol_min: u8,
+  protocol_max: u8,
+  protocol: u8,
+  delegate_min: u8,
+  delegate_max: u8,
+  delegate: u8,
+}
+
+impl Versions {
+  /// The versions of the members which do not advertise them, they only
+  /// understand the first protocol.
+  const LEGACY: Self = Self {
+    protocol_min: ProtocolVersion::V1 as u8,
+    protocol_max: ProtocolVersion::V1 as u8,
+    protocol: ProtocolVersion::V1 as u8,
+    delegate_min: DelegateVersion::V1 as u8,
+    delegate_max: DelegateVersion::V1 as u8,
+    delegate: DelegateVersion::V1 as u8,
+  };
+
+  /// Returns the versions spoken by the local node.
+  pub(crate) const fn local(opts: &Options) -> Self {
+    Self {
+      protocol_min: ProtocolVersion::MIN as u8,
+      protocol_max: PROTOCOL_MAX as u8,
+      protocol: opts.protocol_version as u8,
+      delegate_min: DelegateVersion::MIN as u8,
+      delegate_max: DelegateVersion::MAX as u8,
+      delegate: opts.delegate_version as u8,
+    }
+  }
+
+  /// Removes the version tag from the decoded tags of a member, and
+  /// returns the versions it advertises.
+  pub(crate) fn split(tags: &mut Tags) -> Self {
+    tags
+      .shift_remove(VERSION_TAG)
+      .and_then(|v| Self::parse(&v))
+      .unwrap_or(Self::LEGACY)
+  }
+
+  /// Returns the tags advertised in the meta of the local node. The legacy
+  /// versions are implied, so the meta stays the same for the nodes only
+  /// speaking them.
+  pub(crate) fn advertise(&self, tags: &Tags) -> Tags {
+    let mut advertised = tags.clone();
+    if *self != Self::LEGACY {
+      advertised.insert(SmolStr::new(VERSION_TAG), format_smolstr!("{self}"));
+    }
+    advertised
+  }
+
+  fn parse(src: &str) -> Option<Self> {
+    let mut parts = src.split('.').map(|v| v.parse::<u8>().ok());
+    let mut next = || parts.next().flatten();
+    let versions = Self {
+      protocol_min: next()?,
+      protocol_max: next()?,
+      protocol: next()?,
+      delegate_min: next()?,
+      delegate_max: next()?,
+      delegate: next()?,
+    };
+    parts.next().is_none().then_some(versions)
+  }
+
+  /// Returns `true` if both sides speak a version the other one understands.
+  pub(crate) fn compatible_with(&self, other: &Self) -> bool {
+    (self.protocol_min..=self.protocol_max).contains(&other.protocol)
+      && (other.protocol_min..=other.protocol_max).contains(&self.protocol)
+      && (self.delegate_min..=self.delegate_max).contains(&other.delegate)
+      && (other.delegate_min..=other.delegate_max).contains(&self.delegate)
+  }
+
+  /// Returns the protocol version spoken, or the default one if it is unknown.
+  pub(crate) fn protocol_version(&self) -> ProtocolVersion {
+    ProtocolVersion::try_from(self.protocol).unwrap_or_default()
+  }
+
+  /// Returns the delegate version spoken, or the default one if it is unknown.
+  pub(crate) fn delegate_version(&self) -> DelegateVersion {
+    DelegateVersion::try_from(self.delegate).unwrap_or_default()
+  }
+}
+
+impl fmt::Display for Versions {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{}.{}.{}.{}.{}.{}",
+      self.protocol_min,
+      self.protocol_max,
+      self.protocol,
+      self.delegate_min,
+      self.delegate_max,
+      self.delegate
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_versions_tag() {
+    let versions = Versions::parse("1.2.2.1.1.1").unwrap();
+    let mut tags = versions.advertise(&[("role", "web")].into_iter().collect());
+    assert_eq!(tags.len(), 2);
+    assert_eq!(Versions::split(&mut tags), versions);
+    assert_eq!(tags, [("role", "web")].into_iter().collect());
+
+    // members without the tag speak the legacy versions
+    assert_eq!(Versions::LEGACY.advertise(&tags), tags);
+    assert_eq!(Versions::split(&mut tags), Versions::LEGACY);
+    assert!(Versions::parse("1.1.1.1.1").is_none());
+    assert!(Versions::parse("1.1.1.1.1.1.1").is_none());
+    assert!(Versions::parse("1.1.x.1.1.1").is_none());
+  }
+
+  #[test]
+  fn test_versions_compatible() {
+    let local = Versions::local(&Options::new());
+    assert!(local.compatible_with(&Versions::LEGACY));
+
+    let newer = Versions::parse("2.3.3.1.1.1").unwrap();
+    assert!(!local.compatible_with(&newer));
+    // a newer member speaking an old protocol is understood
+    let downgraded = Versions::parse("1.3.1.1.1.1").unwrap();
+    assert!(local.compatible_with(&downgraded));
+    assert!(downgraded.compatible_with(&local));
+    // the members which do not advertise their versions only understand the first protocol
+    let upgraded = Versions::parse("1.2.2.1.1.1").unwrap();
+    assert!(!upgraded.compatible_with(&Versions::LEGACY));
+  }
+}
@@ -16,6 +16,11 @@ pub use memberlist::net;
 #[cfg(feature = "quic")]
 pub use memberlist::quic;
 
+/// Mutual TLS for the [`Tls`](memberlist::net::stream_layer::tls::Tls) stream layer.
+#[cfg(all(feature = "tls", not(target_family = "wasm")))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "tls", not(target_family = "wasm")))))]
+pub mod tls;
+
 /// [`Serf`](ruserf_core::Serf) for `tokio` runtime.
 #[cfg(feature = "tokio")]
 #[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
@@ -0,0 +1,424 @@
+use std::{
+  collections::HashMap,
+  fmt,
+  sync::{Arc, RwLock},
+};
+
+use memberlist::net::stream_layer::tls::{
+  rustls::{
+    self,
+    client::{danger::HandshakeSignatureValid, verify_server_name, ResolvesClientCert},
+    crypto::ring::sign::any_supported_type,
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+    server::{
+      danger::{ClientCertVerified, ClientCertVerifier},
+      ClientHello, ParsedCertificate, ResolvesServerCert, VerifierBuilderError,
+      WebPkiClientVerifier,
+    },
+    sign::CertifiedKey,
+    ClientConfig, DigitallySignedStruct, DistinguishedName, RootCertStore, ServerConfig,
+    SignatureScheme,
+  },
+  TlsAcceptor, TlsConnector, TlsOptions,
+};
+
+/// Errors returned when building the TLS configuration.
+#[derive(Debug)]
+pub enum TlsConfigError {
+  /// Returned when the certificate or the private key is invalid.
+  Rustls(rustls::Error),
+  /// Returned when the client certificate verifier cannot be built, e.g.
+  /// the root certificates are empty.
+  Verifier(VerifierBuilderError),
+}
+
+impl fmt::Display for TlsConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Rustls(e) => write!(f, "ruserf: tls: {e}"),
+      Self::Verifier(e) => write!(f, "ruserf: tls: {e}"),
+    }
+  }
+}
+
+impl std::error::Error for TlsConfigError {}
+
+impl From<rustls::Error> for TlsConfigError {
+  fn from(e: rustls::Error) -> Self {
+    Self::Rustls(e)
+  }
+}
+
+impl From<VerifierBuilderError> for TlsConfigError {
+  fn from(e: VerifierBuilderError) -> Self {
+    Self::Verifier(e)
+  }
+}
+
+/// How the server side of the stream layer authenticates the clients, the
+/// same policies as the `ClientAuthType` of Go's `crypto/tls`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClientAuth {
+  /// No client certificate is requested.
+  None,
+  /// A client certificate is requested, and verified if given.
+  VerifyIfGiven,
+  /// A valid client certificate is required.
+  #[default]
+  RequireAndVerify,
+}
+
+/// The certificate chain and the private key of the local node, shared by
+/// the server and the client sides of the stream layer.
+///
+/// Calling [`reload`](CertificateReloader::reload) rotates the certificate
+/// without restarting [`Serf`](crate::Serf): the new handshakes use the new
+/// certificate, while the established connections are kept.
+#[derive(Clone)]
+pub struct CertificateReloader {
+  current: Arc<RwLock<Arc<CertifiedKey>>>,
+}
+
+impl fmt::Debug for CertificateReloader {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("CertificateReloader")
+      .finish_non_exhaustive()
+  }
+}
+
+impl CertificateReloader {
+  /// Returns a new reloader, serving the given certificate chain, which
+  /// starts with the end-entity certificate.
+  pub fn new(
+    certs: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'_>,
+  ) -> Result<Self, TlsConfigError> {
+    Ok(Self {
+      current: Arc::new(RwLock::new(certified_key(certs, key)?)),
+    })
+  }
+
+  /// Replaces the certificate chain and the private key. The current ones
+  /// are kept if the new ones are invalid.
+  pub fn reload(
+    &self,
+    certs: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'_>,
+  ) -> Result<(), TlsConfigError> {
+    let key = certified_key(certs, key)?;
+    *self.current.write().unwrap_or_else(|e| e.into_inner()) = key;
+    Ok(())
+  }
+
+  /// Returns the certificate chain currently served.
+  pub fn certs(&self) -> Vec<CertificateDer<'static>> {
+    self.current().cert.clone()
+  }
+
+  fn current(&self) -> Arc<CertifiedKey> {
+    self
+      .current
+      .read()
+      .unwrap_or_else(|e| e.into_inner())
+      .clone()
+  }
+}
+
+fn certified_key(
+  certs: Vec<CertificateDer<'static>>,
+  key: PrivateKeyDer<'_>,
+) -> Result<Arc<CertifiedKey>, TlsConfigError> {
+  if certs.is_empty() {
+    return Err(rustls::Error::NoCertificatesPresented.into());
+  }
+  Ok(Arc::new(CertifiedKey::new(
+    certs,
+    any_supported_type(&key)?,
+  )))
+}
+
+impl ResolvesServerCert for CertificateReloader {
+  fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+    Some(self.current())
+  }
+}
+
+impl ResolvesClientCert for CertificateReloader {
+  fn resolve(
+    &self,
+    _root_hint_subjects: &[&[u8]],
+    _sigschemes: &[SignatureScheme],
+  ) -> Option<Arc<CertifiedKey>> {
+    Some(self.current())
+  }
+
+  fn has_certs(&self) -> bool {
+    true
+  }
+}
+
+/// Accepts the client certificates accepted by the inner verifier, and only
+/// if they are valid for one of the allowed subject alternative names.
+#[derive(Debug)]
+struct SanAllowlistVerifier {
+  inner: Arc<dyn ClientCertVerifier>,
+  allowed: Vec<ServerName<'static>>,
+}
+
+impl ClientCertVerifier for SanAllowlistVerifier {
+  fn offer_client_auth(&self) -> bool {
+    self.inner.offer_client_auth()
+  }
+
+  fn client_auth_mandatory(&self) -> bool {
+    self.inner.client_auth_mandatory()
+  }
+
+  fn root_hint_subjects(&self) -> &[DistinguishedName] {
+    self.inner.root_hint_subjects()
+  }
+
+  fn verify_client_cert(
+    &self,
+    end_entity: &CertificateDer<'_>,
+    intermediates: &[CertificateDer<'_>],
+    now: UnixTime,
+  ) -> Result<ClientCertVerified, rustls::Error> {
+    let verified = self
+      .inner
+      .verify_client_cert(end_entity, intermediates, now)?;
+    let cert = ParsedCertificate::try_from(end_entity)?;
+    self
+      .allowed
+      .iter()
+      .find_map(|name| verify_server_name(&cert, name).ok())
+      .map(|_| verified)
+      .ok_or(rustls::Error::InvalidCertificate(
+        rustls::CertificateError::NotValidForName,
+      ))
+  }
+
+  fn verify_tls12_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, rustls::Error> {
+    self.inner.verify_tls12_signature(message, cert, dss)
+  }
+
+  fn verify_tls13_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, rustls::Error> {
+    self.inner.verify_tls13_signature(message, cert, dss)
+  }
+
+  fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+    self.inner.supported_verify_schemes()
+  }
+}
+
+/// Builds the [`TlsOptions`] of the [`Tls`](memberlist::net::stream_layer::tls::Tls)
+/// stream layer for mutual TLS.
+///
+/// Both sides present the certificate of the [`CertificateReloader`] and
+/// verify the peer against the same root certificates. The client
+/// certificates can additionally be restricted to a list of subject
+/// alternative names per cluster, so the nodes of another cluster signed by
+/// the same CA are rejected.
+///
+/// ```rust,ignore
+/// let certs = CertificateReloader::new(chain, key)?;
+/// let opts = MtlsOptions::new(ServerName::try_from("node.prod")?, roots, certs.clone())
+///   .with_allowed_sans("prod", [ServerName::try_from("node.prod")?])
+///   .build("prod")?;
+///
+/// // Later on, once the certificate was renewed
+/// certs.reload(new_chain, new_key)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct MtlsOptions {
+  server_name: ServerName<'static>,
+  roots: Arc<RootCertStore>,
+  certs: CertificateReloader,
+  client_auth: ClientAuth,
+  allowed_sans: HashMap<String, Vec<ServerName<'static>>>,
+}
+
+impl MtlsOptions {
+  /// Returns new options, requiring and verifying the client certificates.
+  ///
+  /// `server_name` is the name the servers are verified against, as all the
+  /// nodes are dialed by address.
+  pub fn new(
+    server_name: ServerName<'static>,
+    roots: impl Into<Arc<RootCertStore>>,
+    certs: CertificateReloader,
+  ) -> Self {
+    Self {
+      server_name,
+      roots: roots.into(),
+      certs,
+      client_auth: ClientAuth::default(),
+      allowed_sans: HashMap::new(),
+    }
+  }
+
+  /// Sets how the clients are authenticated.
+  pub fn with_client_auth(mut self, client_auth: ClientAuth) -> Self {
+    self.client_auth = client_auth;
+    self
+  }
+
+  /// Restricts the client certificates accepted in `cluster` to the ones
+  /// valid for one of `sans`. The clusters without an allowlist accept any
+  /// certificate signed by the roots.
+  pub fn with_allowed_sans(
+    mut self,
+    cluster: impl Into<String>,
+    sans: impl IntoIterator<Item = ServerName<'static>>,
+  ) -> Self {
+    self
+      .allowed_sans
+      .entry(cluster.into())
+      .or_default()
+      .extend(sans);
+    self
+  }
+
+  /// Returns how the clients are authenticated.
+  pub const fn client_auth(&self) -> ClientAuth {
+    self.client_auth
+  }
+
+  /// Returns the subject alternative names allowed in `cluster`, if any.
+  pub fn allowed_sans(&self, cluster: &str) -> Option<&[ServerName<'static>]> {
+    self.allowed_sans.get(cluster).map(Vec::as_slice)
+  }
+
+  /// Returns the reloader of the local certificate.
+  pub const fn certs(&self) -> &CertificateReloader {
+    &self.certs
+  }
+
+  /// Returns the client certificate verifier of the server side.
+  pub fn client_verifier(
+    &self,
+    cluster: &str,
+  ) -> Result<Arc<dyn ClientCertVerifier>, TlsConfigError> {
+    let verifier = match self.client_auth {
+      ClientAuth::None => return Ok(WebPkiClientVerifier::no_client_auth()),
+      ClientAuth::VerifyIfGiven => WebPkiClientVerifier::builder(self.roots.clone())
+        .allow_unauthenticated()
+        .build()?,
+      ClientAuth::RequireAndVerify => WebPkiClientVerifier::builder(self.roots.clone()).build()?,
+    };
+
+    Ok(match self.allowed_sans.get(cluster) {
+      Some(allowed) => Arc::new(SanAllowlistVerifier {
+        inner: verifier,
+        allowed: allowed.clone(),
+      }),
+      None => verifier,
+    })
+  }
+
+  /// Builds the options of the stream layer for the nodes of `cluster`.
+  pub fn build(&self, cluster: &str) -> Result<TlsOptions, TlsConfigError> {
+    let server = ServerConfig::builder()
+      .with_client_cert_verifier(self.client_verifier(cluster)?)
+      .with_cert_resolver(Arc::new(self.certs.clone()));
+    let client = ClientConfig::builder()
+      .with_root_certificates(self.roots.clone())
+      .with_client_cert_resolver(Arc::new(self.certs.clone()));
+
+    Ok(TlsOptions::new(
+      self.server_name.clone(),
+      TlsAcceptor::from(Arc::new(server)),
+      TlsConnector::from(Arc::new(client)),
+    ))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  struct Ca {
+    cert: rcgen::Certificate,
+    der: CertificateDer<'static>,
+  }
+
+  fn ca() -> Ca {
+    let mut params = rcgen::CertificateParams::new(Vec::new());
+    params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    let cert = rcgen::Certificate::from_params(params).unwrap();
+    let der = CertificateDer::from(cert.serialize_der().unwrap());
+    Ca { cert, der }
+  }
+
+  fn leaf(ca: &Ca, san: &str) -> (CertificateDer<'static>, PrivateKeyDer<'static>) {
+    let cert =
+      rcgen::Certificate::from_params(rcgen::CertificateParams::new(vec![san.into()])).unwrap();
+    (
+      CertificateDer::from(cert.serialize_der_with_signer(&ca.cert).unwrap()),
+      PrivateKeyDer::Pkcs8(cert.serialize_private_key_der().into()),
+    )
+  }
+
+  fn options(ca: &Ca) -> MtlsOptions {
+    let mut roots = RootCertStore::empty();
+    roots.add(ca.der.clone()).unwrap();
+    let (cert, key) = leaf(ca, "node.prod");
+    MtlsOptions::new(
+      ServerName::try_from("node.prod").unwrap(),
+      roots,
+      CertificateReloader::new(vec![cert], key).unwrap(),
+    )
+  }
+
+  #[test]
+  fn test_san_allowlist() {
+    let ca = ca();
+    let opts = options(&ca).with_allowed_sans("prod", [ServerName::try_from("node.prod").unwrap()]);
+    let prod = opts.client_verifier("prod").unwrap();
+    let other = opts.client_verifier("staging").unwrap();
+    assert!(prod.client_auth_mandatory());
+
+    let now = UnixTime::now();
+    let (cert, _) = leaf(&ca, "node.prod");
+    assert!(prod.verify_client_cert(&cert, &[], now).is_ok());
+    let (cert, _) = leaf(&ca, "node.staging");
+    assert!(prod.verify_client_cert(&cert, &[], now).is_err());
+    // No allowlist for the cluster
+    assert!(other.verify_client_cert(&cert, &[], now).is_ok());
+
+    // Not signed by the roots
+    let (cert, _) = leaf(&self::ca(), "node.prod");
+    assert!(prod.verify_client_cert(&cert, &[], now).is_err());
+
+    assert!(opts.build("prod").is_ok());
+    assert!(!opts
+      .with_client_auth(ClientAuth::VerifyIfGiven)
+      .client_verifier("prod")
+      .unwrap()
+      .client_auth_mandatory());
+  }
+
+  #[test]
+  fn test_certificate_reload() {
+    let ca = ca();
+    let opts = options(&ca);
+    let (cert, key) = leaf(&ca, "node2.prod");
+    opts.certs().reload(vec![cert.clone()], key).unwrap();
+    assert_eq!(opts.certs().certs(), [cert]);
+
+    // The invalid certificates are rejected and the current one kept
+    let (_, key) = leaf(&ca, "node3.prod");
+    assert!(opts.certs().reload(Vec::new(), key).is_err());
+    assert_eq!(opts.certs().certs().len(), 1);
+  }
+}
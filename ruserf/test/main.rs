@@ -5,6 +5,10 @@ use ruserf_core::tests::run as run_unit_test;
 #[path = "./main/net.rs"]
 mod net;
 
+#[cfg(feature = "test-util")]
+#[path = "./main/memory.rs"]
+mod memory;
+
 #[cfg(feature = "tokio")]
 fn tokio_run(fut: impl Future<Output = ()>) {
   let runtime = ::tokio::runtime::Builder::new_multi_thread()
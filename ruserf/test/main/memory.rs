@@ -0,0 +1,37 @@
+macro_rules! test_mod {
+  ($rt:ident) => {
+    paste::paste! {
+      mod [< $rt:snake >] {
+        use crate::[< $rt:snake _run >];
+        use ruserf::[< $rt:snake >]::[< $rt:camel Runtime >];
+        use ruserf_core::tests::memory::{
+          serf_memory_cluster, serf_memory_partition, serf_memory_query_nearest,
+        };
+
+        #[test]
+        fn test_serf_memory_cluster() {
+          [< $rt:snake _run >](serf_memory_cluster::<[< $rt:camel Runtime >]>());
+        }
+
+        #[test]
+        fn test_serf_memory_partition() {
+          [< $rt:snake _run >](serf_memory_partition::<[< $rt:camel Runtime >]>());
+        }
+
+        #[test]
+        fn test_serf_memory_query_nearest() {
+          [< $rt:snake _run >](serf_memory_query_nearest::<[< $rt:camel Runtime >]>());
+        }
+      }
+    }
+  };
+}
+
+#[cfg(feature = "tokio")]
+test_mod!(tokio);
+
+#[cfg(feature = "async-std")]
+test_mod!(async_std);
+
+#[cfg(feature = "smol")]
+test_mod!(smol);
@@ -28,12 +28,42 @@ mod role;
 #[path = "./net/set_tags.rs"]
 mod set_tags;
 
+#[path = "./net/set_app_meta.rs"]
+mod set_app_meta;
+
+#[path = "./net/advertise_addresses.rs"]
+mod advertise_addresses;
+
+#[path = "./net/pause_resume.rs"]
+mod pause_resume;
+
+#[path = "./net/set_tag_if.rs"]
+mod set_tag_if;
+
 #[path = "./net/get_queue_max.rs"]
 mod get_queue_max;
 
+#[path = "./net/reload.rs"]
+mod reload;
+
+#[path = "./net/kv_store.rs"]
+mod kv_store;
+
 #[path = "./net/local_member.rs"]
 mod local_member;
 
+#[path = "./net/lock.rs"]
+mod lock;
+
+#[path = "./net/members_filtered.rs"]
+mod members_filtered;
+
+#[path = "./net/ping_member.rs"]
+mod ping_member;
+
+#[path = "./net/ping_member_cached.rs"]
+mod ping_member_cached;
+
 #[path = "./net/num_nodes.rs"]
 mod num_nodes;
 
@@ -43,15 +73,69 @@ mod state;
 #[path = "./net/stats.rs"]
 mod stats;
 
+#[path = "./net/stats_bandwidth.rs"]
+mod stats_bandwidth;
+
+#[path = "./net/rng_seed.rs"]
+mod rng_seed;
+
+#[path = "./net/task_health.rs"]
+mod task_health;
+
+#[path = "./net/shutdown_report.rs"]
+mod shutdown_report;
+
+#[path = "./net/shutdown_signal.rs"]
+mod shutdown_signal;
+
+#[path = "./net/health.rs"]
+mod health;
+
+#[path = "./net/members_info.rs"]
+mod members_info;
+
 #[path = "./net/coordinates.rs"]
 mod coordinates;
 
+#[path = "./net/coordinates_warm_start.rs"]
+mod coordinates_warm_start;
+
 #[path = "./net/name_resolution.rs"]
 mod name_resolution;
 
+#[path = "./net/name_resolution_keep_newest.rs"]
+mod name_resolution_keep_newest;
+
 #[path = "./net/join.rs"]
 mod join;
 
 #[cfg(feature = "encryption")]
 #[path = "./net/write_keyring_file.rs"]
 mod write_keyring_file;
+
+#[cfg(feature = "encryption")]
+#[path = "./net/rotate_keys.rs"]
+mod rotate_keys;
+
+#[cfg(feature = "encryption")]
+#[path = "./net/load_keyring_file.rs"]
+mod load_keyring_file;
+
+#[cfg(feature = "encryption")]
+#[path = "./net/unencrypted_members.rs"]
+mod unencrypted_members;
+
+#[path = "./net/partition_detection.rs"]
+mod partition_detection;
+
+#[path = "./net/anti_entropy_audit.rs"]
+mod anti_entropy_audit;
+
+#[path = "./net/cluster_name.rs"]
+mod cluster_name;
+
+#[path = "./net/hosts.rs"]
+mod hosts;
+
+#[path = "./net/broadcast_scaling.rs"]
+mod broadcast_scaling;
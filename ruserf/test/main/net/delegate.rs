@@ -4,8 +4,34 @@ mod nodemeta;
 #[path = "./delegate/local_state.rs"]
 mod local_state;
 
+#[path = "./delegate/local_state_incremental.rs"]
+mod local_state_incremental;
+
+// The second protocol version needs the lz4 support of the core
+#[cfg(feature = "lz4")]
+#[path = "./delegate/local_state_compressed.rs"]
+mod local_state_compressed;
+
 #[path = "./delegate/remote_state.rs"]
 mod remote_state;
 
+#[path = "./delegate/notify_alive_versions.rs"]
+mod notify_alive_versions;
+
 #[path = "./delegate/ping_delegate.rs"]
 mod ping_delegate;
+
+#[path = "./delegate/shutdown.rs"]
+mod shutdown;
+
+#[path = "./delegate/shutdown_timeout.rs"]
+mod shutdown_timeout;
+
+#[path = "./delegate/outbound.rs"]
+mod outbound;
+
+#[path = "./delegate/address.rs"]
+mod address;
+
+#[path = "./delegate/app_health.rs"]
+mod app_health;
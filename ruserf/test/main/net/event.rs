@@ -7,6 +7,51 @@ mod event_user_size_limit;
 #[path = "./event/event_user.rs"]
 mod event_user;
 
+// The second protocol version needs the lz4 support of the core
+#[cfg(feature = "lz4")]
+#[path = "./event/event_user_compressed.rs"]
+mod event_user_compressed;
+
+#[path = "./event/event_user_distribution.rs"]
+mod event_user_distribution;
+
+#[path = "./event/event_user_segment.rs"]
+mod event_user_segment;
+
+// The second protocol version needs the lz4 support of the core
+#[cfg(feature = "lz4")]
+#[path = "./event/event_user_fragmented.rs"]
+mod event_user_fragmented;
+
+#[path = "./event/event_user_replay.rs"]
+mod event_user_replay;
+
+#[path = "./event/event_user_lossy_network.rs"]
+mod event_user_lossy_network;
+
+#[path = "./event/event_stream.rs"]
+mod event_stream;
+
+// The encryption enables the serde support of the core
+#[cfg(any(feature = "serde", feature = "encryption"))]
+#[path = "./event/typed_user_events.rs"]
+mod typed_user_events;
+
+#[path = "./event/user_event_validator.rs"]
+mod user_event_validator;
+
+#[path = "./event/event_sink.rs"]
+mod event_sink;
+
+#[path = "./event/event_backpressure_drop_newest.rs"]
+mod event_backpressure_drop_newest;
+
+#[path = "./event/event_backpressure_drop_oldest.rs"]
+mod event_backpressure_drop_oldest;
+
+#[path = "./event/events_cluster_formed.rs"]
+mod events_cluster_formed;
+
 #[path = "./event/events_failed.rs"]
 mod events_failed;
 
@@ -19,9 +64,21 @@ mod events_leave_avoid_infinite_rebroadcast;
 #[path = "./event/events_leave.rs"]
 mod events_leave;
 
+#[path = "./event/events_leader_election.rs"]
+mod events_leader_election;
+
+#[path = "./event/query_deadline_extension.rs"]
+mod query_deadline_extension;
+
 #[path = "./event/query_deduplicate.rs"]
 mod query_deduplicate;
 
+#[path = "./event/query_deferred_response.rs"]
+mod query_deferred_response;
+
+#[path = "./event/query_respond_lossy.rs"]
+mod query_respond_lossy;
+
 #[path = "./event/query_filter.rs"]
 mod query_filter;
 
@@ -31,12 +88,41 @@ mod query_old_message;
 #[path = "./event/query_params_encode_filters.rs"]
 mod query_params_encode_filters;
 
+#[path = "./event/query_relayed_response.rs"]
+mod query_relayed_response;
+
+// The second protocol version needs the lz4 support of the core
+#[cfg(feature = "lz4")]
+#[path = "./event/query_respond_via.rs"]
+mod query_respond_via;
+
 #[path = "./event/query.rs"]
 mod query;
 
+#[path = "./event/query_ack_only.rs"]
+mod query_ack_only;
+
+#[path = "./event/query_progress.rs"]
+mod query_progress;
+
+#[path = "./event/query_builder.rs"]
+mod query_builder;
+
+#[path = "./event/multi_query.rs"]
+mod multi_query;
+
+#[path = "./event/app_query.rs"]
+mod app_query;
+
 #[path = "./event/query_same_clock.rs"]
 mod query_same_clock;
 
+#[path = "./event/query_rate_limit.rs"]
+mod query_rate_limit;
+
+#[path = "./event/query_suppression.rs"]
+mod query_suppression;
+
 #[path = "./event/query_size_limit.rs"]
 mod query_size_limit;
 
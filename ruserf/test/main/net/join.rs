@@ -1,3 +1,6 @@
+#[path = "./join/flap.rs"]
+mod flap;
+
 #[path = "./join/intent_buffer_early.rs"]
 mod intent_buffer_early;
 
@@ -7,6 +10,9 @@ mod intent_newer;
 #[path = "./join/intent_old_message.rs"]
 mod intent_old_message;
 
+#[path = "./join/intent_priority.rs"]
+mod intent_priority;
+
 #[path = "./join/intent_reset_leaving.rs"]
 mod intent_reset_leaving;
 
@@ -22,6 +28,12 @@ mod join_leave_join;
 #[path = "./join/join_leave.rs"]
 mod join_leave;
 
+#[path = "./join/join_many.rs"]
+mod join_many;
+
+#[path = "./join/join_sync.rs"]
+mod join_sync;
+
 #[path = "./join/leave_ltime.rs"]
 mod leave_ltime;
 
@@ -16,6 +16,9 @@ mod intent_newer;
 #[path = "./leave/intent_old_message.rs"]
 mod intent_old_message;
 
+#[path = "./leave/manual_clock.rs"]
+mod manual_clock;
+
 #[path = "./leave/rejoin_different_role.rs"]
 mod rejoin_different_role;
 
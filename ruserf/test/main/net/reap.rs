@@ -7,3 +7,12 @@ mod handler;
 
 #[path = "./reap/handler_shutdown.rs"]
 mod handler_shutdown;
+
+#[path = "./reap/evict_left.rs"]
+mod evict_left;
+
+#[path = "./reap/manual_scheduler.rs"]
+mod manual_scheduler;
+
+#[path = "./reap/delay.rs"]
+mod delay;
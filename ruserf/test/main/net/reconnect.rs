@@ -2,6 +2,9 @@
 #[path = "./reconnect/reconnect.rs"]
 mod reconnect;
 
+#[path = "./reconnect/address_override.rs"]
+mod address_override;
+
 #[path = "./reconnect/same_ip.rs"]
 mod same_ip;
 
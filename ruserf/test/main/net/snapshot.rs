@@ -12,3 +12,19 @@ mod snapshoter_recovery;
 
 #[path = "./snapshot/snapshoter_force_compact.rs"]
 mod snapshoter_force_compact;
+
+#[path = "./snapshot/snapshot_event_dedup.rs"]
+mod snapshot_event_dedup;
+
+#[path = "./snapshot/snapshot_clock_checkpoint.rs"]
+mod snapshot_clock_checkpoint;
+
+#[path = "./snapshot/snapshot_delegate.rs"]
+mod snapshot_delegate;
+
+#[path = "./snapshot/state_export_import.rs"]
+mod state_export_import;
+
+#[cfg(feature = "encryption")]
+#[path = "./snapshot/snapshot_encrypted.rs"]
+mod snapshot_encrypted;
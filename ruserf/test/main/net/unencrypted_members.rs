@@ -0,0 +1,66 @@
+macro_rules! test_mod {
+  ($rt:ident) => {
+    paste::paste! {
+      mod [< $rt:snake >] {
+        use std::net::SocketAddr;
+
+        use crate::[< $rt:snake _run >];
+        use ruserf::{
+          net::{
+            resolver::socket_addr::SocketAddrResolver, stream_layer::tcp::Tcp, NetTransport,
+            NetTransportOptions,
+          },
+          [< $rt:snake >]::[< $rt:camel Runtime >],
+          transport::Lpe,
+        };
+        use ruserf_core::tests::{serf_unencrypted_members, next_socket_addr_v4, next_socket_addr_v6};
+        use smol_str::SmolStr;
+
+        #[test]
+        fn test_serf_unencrypted_members_v4() {
+          [< $rt:snake _run >](serf_unencrypted_members::<
+            NetTransport<
+              SmolStr,
+              SocketAddrResolver<[< $rt:camel Runtime >]>,
+              Tcp<[< $rt:camel Runtime >]>,
+              Lpe<SmolStr, SocketAddr>,
+              [< $rt:camel Runtime >],
+            >,
+          >(|kr, outgoing| {
+            let name = format!("serf_unencrypted_members_v4_{outgoing}");
+            let mut opts = NetTransportOptions::new(SmolStr::new(name));
+            opts.add_bind_address(next_socket_addr_v4(0));
+            opts.with_primary_key(Some(kr)).with_gossip_verify_outgoing(outgoing).with_encryption_algo(Some(ruserf::net::security::EncryptionAlgo::default()))
+          }));
+        }
+
+        #[test]
+        fn test_serf_unencrypted_members_v6() {
+          [< $rt:snake _run >](serf_unencrypted_members::<
+            NetTransport<
+              SmolStr,
+              SocketAddrResolver<[< $rt:camel Runtime >]>,
+              Tcp<[< $rt:camel Runtime >]>,
+              Lpe<SmolStr, SocketAddr>,
+              [< $rt:camel Runtime >],
+            >,
+          >(|kr, outgoing| {
+            let name = format!("serf_unencrypted_members_v6_{outgoing}");
+            let mut opts = NetTransportOptions::new(SmolStr::new(name));
+            opts.add_bind_address(next_socket_addr_v6());
+            opts.with_primary_key(Some(kr)).with_gossip_verify_outgoing(outgoing).with_encryption_algo(Some(ruserf::net::security::EncryptionAlgo::default()))
+          }));
+        }
+      }
+    }
+  };
+}
+
+#[cfg(feature = "tokio")]
+test_mod!(tokio);
+
+#[cfg(feature = "async-std")]
+test_mod!(async_std);
+
+#[cfg(feature = "smol")]
+test_mod!(smol);
@@ -1,7 +1,5 @@
-use std::sync::{
-  atomic::{AtomicU64, Ordering},
-  Arc,
-};
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU64, Ordering};
 
 use transformable::{
   utils::{decode_varint, encode_varint, encoded_len_varint, DecodeVarintError, EncodeVarintError},
@@ -96,14 +94,43 @@ impl core::ops::Rem<Self> for LamportTime {
 }
 
 /// Error that can occur when transforming a lamport time
-#[derive(thiserror::Error, Debug)]
+#[derive(Debug)]
 pub enum LamportTimeTransformError {
   /// Encode varint error
-  #[error(transparent)]
-  Encode(#[from] EncodeVarintError),
+  Encode(EncodeVarintError),
   /// Decode varint error
-  #[error(transparent)]
-  Decode(#[from] DecodeVarintError),
+  Decode(DecodeVarintError),
+}
+
+impl From<EncodeVarintError> for LamportTimeTransformError {
+  fn from(e: EncodeVarintError) -> Self {
+    Self::Encode(e)
+  }
+}
+
+impl From<DecodeVarintError> for LamportTimeTransformError {
+  fn from(e: DecodeVarintError) -> Self {
+    Self::Decode(e)
+  }
+}
+
+impl core::fmt::Display for LamportTimeTransformError {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    match self {
+      Self::Encode(e) => e.fmt(f),
+      Self::Decode(e) => e.fmt(f),
+    }
+  }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LamportTimeTransformError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Self::Encode(e) => e.source(),
+      Self::Decode(e) => e.source(),
+    }
+  }
 }
 
 impl Transformable for LamportTime {
@@ -181,7 +208,7 @@ impl LamportClock {
   }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 impl LamportTime {
   pub(crate) fn random() -> Self {
     use rand::Rng;
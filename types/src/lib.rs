@@ -1,11 +1,21 @@
 //! Types used by the [`ruserf`](https://crates.io/crates/ruserf) crate.
+//!
+//! Without the default `std` feature the crate is `no_std + alloc` and only
+//! provides the Lamport clock and the versions. The other wire types embed the
+//! node types of [`memberlist-types`](https://crates.io/crates/memberlist-types),
+//! which requires `std`.
 #![doc(html_logo_url = "https://raw.githubusercontent.com/al8n/memberlist/main/art/logo_72x72.png")]
 #![forbid(unsafe_code)]
 #![deny(warnings, missing_docs)]
 #![allow(clippy::type_complexity)]
 #![cfg_attr(docsrs, feature(doc_cfg))]
 #![cfg_attr(docsrs, allow(unused_attributes))]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
 pub use memberlist_types::{
   DelegateVersion as MemberlistDelegateVersion, Node, NodeAddress, NodeAddressError, NodeId,
   NodeIdTransformError, NodeTransformError, ProtocolVersion as MemberlistProtocolVersion,
@@ -17,31 +27,58 @@ pub use transformable::{Encodable, Transformable};
 mod clock;
 pub use clock::*;
 
+#[cfg(feature = "std")]
 mod filter;
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
 pub use filter::*;
 
+#[cfg(feature = "std")]
 mod leave;
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
 pub use leave::*;
 
+#[cfg(feature = "std")]
 mod member;
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
 pub use member::*;
 
+#[cfg(feature = "std")]
 mod message;
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
 pub use message::*;
 
+#[cfg(feature = "std")]
 mod join;
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
 pub use join::*;
 
+#[cfg(feature = "std")]
 mod tags;
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
 pub use tags::*;
 
+#[cfg(feature = "std")]
 mod push_pull;
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
 pub use push_pull::*;
 
+#[cfg(feature = "std")]
 mod user_event;
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
 pub use user_event::*;
 
+#[cfg(feature = "std")]
 mod query;
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
 pub use query::*;
 
 mod version;
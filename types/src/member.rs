@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use byteorder::{ByteOrder, NetworkEndian};
-use memberlist_types::CheapClone;
+use memberlist_types::{bytes::Bytes, CheapClone};
 
 use super::{
   DelegateVersion, MemberlistDelegateVersion, MemberlistProtocolVersion, Node, NodeTransformError,
@@ -126,6 +126,63 @@ pub struct Member<I, A> {
     )
   )]
   delegate_version: DelegateVersion,
+
+  /// Whether the member encrypts the gossip it sends
+  #[viewit(
+    getter(
+      const,
+      attrs(doc = "Returns whether the member encrypts the gossip it sends")
+    ),
+    setter(
+      const,
+      attrs(doc = "Sets whether the member encrypts the gossip it sends (Builder pattern)")
+    )
+  )]
+  #[cfg_attr(feature = "serde", serde(default))]
+  secure: bool,
+
+  /// The opaque application metadata
+  #[viewit(
+    getter(
+      const,
+      style = "ref",
+      attrs(doc = "Returns the opaque application metadata")
+    ),
+    setter(attrs(doc = "Sets the opaque application metadata (Builder pattern)"))
+  )]
+  #[cfg_attr(feature = "serde", serde(default))]
+  app_meta: Bytes,
+
+  /// The additional addresses advertised by the member, in order of
+  /// preference, e.g. a public address beside the private one of its node,
+  /// at most 255
+  #[viewit(
+    getter(skip),
+    setter(attrs(doc = "Sets the additional addresses of the member (Builder pattern)"))
+  )]
+  #[cfg_attr(feature = "serde", serde(default = "no_addresses"))]
+  addresses: Arc<[A]>,
+
+  /// The application health last reported by the member in its ping acks,
+  /// as observed by the local node, it is not encoded
+  #[viewit(
+    getter(
+      const,
+      style = "move",
+      attrs(doc = "Returns the application health last reported by the member, if any")
+    ),
+    setter(
+      const,
+      attrs(doc = "Sets the application health last reported by the member (Builder pattern)")
+    )
+  )]
+  #[cfg_attr(feature = "serde", serde(default))]
+  app_health: Option<u8>,
+}
+
+#[cfg(feature = "serde")]
+fn no_addresses<A>() -> Arc<[A]> {
+  Arc::from(Vec::new())
 }
 
 impl<I, A> Member<I, A> {
@@ -141,8 +198,19 @@ impl<I, A> Member<I, A> {
       memberlist_delegate_version: MemberlistDelegateVersion::V1,
       protocol_version: ProtocolVersion::V1,
       delegate_version: DelegateVersion::V1,
+      secure: false,
+      app_meta: Bytes::new(),
+      addresses: Arc::from(Vec::new()),
+      app_health: None,
     }
   }
+
+  /// Returns the additional addresses advertised by the member, in order of
+  /// preference. The address of its node is tried first.
+  #[inline]
+  pub fn addresses(&self) -> &[A] {
+    &self.addresses
+  }
 }
 
 impl<I: Clone, A: Clone> Clone for Member<I, A> {
@@ -155,6 +223,10 @@ impl<I: Clone, A: Clone> Clone for Member<I, A> {
       memberlist_delegate_version: self.memberlist_delegate_version,
       protocol_version: self.protocol_version,
       delegate_version: self.delegate_version,
+      secure: self.secure,
+      app_meta: self.app_meta.clone(),
+      addresses: self.addresses.clone(),
+      app_health: self.app_health,
     }
   }
 }
@@ -169,6 +241,10 @@ impl<I: CheapClone, A: CheapClone> CheapClone for Member<I, A> {
       memberlist_delegate_version: self.memberlist_delegate_version,
       protocol_version: self.protocol_version,
       delegate_version: self.delegate_version,
+      secure: self.secure,
+      app_meta: self.app_meta.clone(),
+      addresses: self.addresses.clone(),
+      app_health: self.app_health,
     }
   }
 }
@@ -211,6 +287,10 @@ where
   /// Error transforming the `delegate_version` field
   #[error(transparent)]
   DelegateVersion(#[from] UnknownDelegateVersion),
+
+  /// Error transforming the `addresses` field
+  #[error(transparent)]
+  Address(A::Error),
 }
 
 impl<I, A> core::fmt::Debug for MemberTransformError<I, A>
@@ -257,6 +337,32 @@ where
     dst[offset] = self.delegate_version as u8;
     offset += 1;
 
+    // Only written for the secure members, the members with application
+    // metadata or additional addresses, the older versions skip them by the
+    // length prefix
+    let has_addresses = !self.addresses.is_empty();
+    if self.secure || !self.app_meta.is_empty() || has_addresses {
+      dst[offset] = self.secure as u8;
+      offset += 1;
+    }
+
+    if !self.app_meta.is_empty() || has_addresses {
+      NetworkEndian::write_u32(&mut dst[offset..], self.app_meta.len() as u32);
+      offset += 4;
+      dst[offset..offset + self.app_meta.len()].copy_from_slice(&self.app_meta);
+      offset += self.app_meta.len();
+    }
+
+    if has_addresses {
+      dst[offset] = self.addresses.len() as u8;
+      offset += 1;
+      for addr in self.addresses.iter() {
+        offset += addr
+          .encode(&mut dst[offset..])
+          .map_err(Self::Error::Address)?;
+      }
+    }
+
     debug_assert_eq!(
       offset, encoded_len,
       "expect write {} bytes, but actually write {} bytes",
@@ -267,6 +373,17 @@ where
   }
 
   fn encoded_len(&self) -> usize {
+    let has_addresses = !self.addresses.is_empty();
+    let addresses_len = if has_addresses {
+      1 + self
+        .addresses
+        .iter()
+        .map(Transformable::encoded_len)
+        .sum::<usize>()
+    } else {
+      0
+    };
+
     4 + self.node.encoded_len()
       + self.tags.encoded_len()
       + 1 // status
@@ -274,6 +391,9 @@ where
       + 1 // memberlist_delegate_version
       + 1 // protocol_version
       + 1 // delegate_version
+      + (self.secure || !self.app_meta.is_empty() || has_addresses) as usize // secure
+      + if self.app_meta.is_empty() && !has_addresses { 0 } else { 4 + self.app_meta.len() } // app_meta
+      + addresses_len
   }
 
   fn decode(src: &[u8]) -> Result<(usize, Self), Self::Error>
@@ -317,6 +437,38 @@ where
     let delegate_version = DelegateVersion::try_from(src[offset])?;
     offset += 1;
 
+    let mut secure = false;
+    if offset < encoded_len {
+      secure = src[offset] != 0;
+      offset += 1;
+    }
+
+    let mut app_meta = Bytes::new();
+    if offset < encoded_len {
+      if encoded_len < offset + 4 {
+        return Err(Self::Error::NotEnoughBytes);
+      }
+      let len = NetworkEndian::read_u32(&src[offset..offset + 4]) as usize;
+      offset += 4;
+      if encoded_len < offset + len {
+        return Err(Self::Error::NotEnoughBytes);
+      }
+      app_meta = Bytes::copy_from_slice(&src[offset..offset + len]);
+      offset += len;
+    }
+
+    let mut addresses = Vec::new();
+    if offset < encoded_len {
+      let num = src[offset] as usize;
+      offset += 1;
+      addresses.reserve_exact(num);
+      for _ in 0..num {
+        let (len, addr) = A::decode(&src[offset..encoded_len]).map_err(Self::Error::Address)?;
+        offset += len;
+        addresses.push(addr);
+      }
+    }
+
     debug_assert_eq!(
       offset, encoded_len,
       "expect read {} bytes, but actually read {} bytes",
@@ -333,6 +485,10 @@ where
         memberlist_delegate_version,
         protocol_version,
         delegate_version,
+        secure,
+        app_meta,
+        addresses: Arc::from(addresses),
+        app_health: None,
       },
     ))
   }
@@ -366,6 +522,16 @@ mod tests {
         memberlist_delegate_version: MemberlistDelegateVersion::V1,
         protocol_version: ProtocolVersion::V1,
         delegate_version: DelegateVersion::V1,
+        secure: random(),
+        app_meta: if random() {
+          Bytes::from((0..size).map(|_| random::<u8>()).collect::<Vec<_>>())
+        } else {
+          Bytes::new()
+        },
+        addresses: (0..random::<u8>() % 3)
+          .map(|_| SocketAddr::from(([10, 0, 0, random()], random::<u16>())))
+          .collect(),
+        app_health: None,
       }
     }
   }
@@ -1,4 +1,7 @@
-use std::sync::Arc;
+use std::{ops::Range, sync::Arc};
+
+use byteorder::{ByteOrder, NetworkEndian};
+use transformable::BytesTransformError;
 
 use crate::{
   JoinMessageTransformError, LeaveMessageTransformError, MemberTransformError,
@@ -27,6 +30,28 @@ const KEY_REQUEST_MESSAGE_TAG: u8 = 253;
 #[cfg(feature = "encryption")]
 const KEY_RESPONSE_MESSAGE_TAG: u8 = 254;
 
+/// Decodes a length-prefixed bytes field located at `src[offset..]`, returning the
+/// number of bytes consumed and the range of the field's data within `src`.
+///
+/// This lets the message decoders share the memory of the incoming buffer
+/// instead of copying the data out of it.
+pub(crate) fn decode_bytes_range(
+  src: &[u8],
+  offset: usize,
+) -> Result<(usize, Range<usize>), BytesTransformError> {
+  if offset + 4 > src.len() {
+    return Err(BytesTransformError::NotEnoughBytes);
+  }
+
+  let len = NetworkEndian::read_u32(&src[offset..]) as usize;
+  let start = offset + 4;
+  if start + len > src.len() {
+    return Err(BytesTransformError::NotEnoughBytes);
+  }
+
+  Ok((4 + len, start..start + len))
+}
+
 /// Unknown message type error
 #[derive(Debug, thiserror::Error)]
 #[error("unknown message type byte: {0}")]
@@ -142,6 +167,26 @@ pub enum SerfMessageRef<'a, I, A> {
   KeyResponse(&'a KeyResponseMessage),
 }
 
+impl<'a, I, A> SerfMessageRef<'a, I, A> {
+  /// Returns the message type of this message
+  #[inline]
+  pub const fn ty(&self) -> MessageType {
+    match self {
+      Self::Leave(_) => MessageType::Leave,
+      Self::Join(_) => MessageType::Join,
+      Self::PushPull(_) => MessageType::PushPull,
+      Self::UserEvent(_) => MessageType::UserEvent,
+      Self::Query(_) => MessageType::Query,
+      Self::QueryResponse(_) => MessageType::QueryResponse,
+      Self::ConflictResponse(_) => MessageType::ConflictResponse,
+      #[cfg(feature = "encryption")]
+      Self::KeyRequest(_) => MessageType::KeyRequest,
+      #[cfg(feature = "encryption")]
+      Self::KeyResponse(_) => MessageType::KeyResponse,
+    }
+  }
+}
+
 impl<'a, I, A> Clone for SerfMessageRef<'a, I, A> {
   fn clone(&self) -> Self {
     *self
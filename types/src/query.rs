@@ -4,11 +4,11 @@ use transformable::{
   BytesTransformError, DurationTransformError, StringTransformError, Transformable,
 };
 
-use std::time::Duration;
+use std::{ops::Range, time::Duration};
 
 use memberlist_types::{bytes::Bytes, Node, NodeTransformError, TinyVec};
 
-use super::{LamportTime, LamportTimeTransformError};
+use super::{message::decode_bytes_range, LamportTime, LamportTimeTransformError};
 
 bitflags::bitflags! {
   /// Flags for query message
@@ -21,6 +21,17 @@ bitflags::bitflags! {
     /// NoBroadcast is used to prevent re-broadcast of a query.
     /// this can be used to selectively send queries to individual members
     const NO_BROADCAST = 1 << 1;
+    /// Relayed is set on a query response which is delivered to the
+    /// requester through another node, instead of directly by the responder.
+    const RELAYED = 1 << 2;
+    /// Compressed is set on a query whose payload is compressed.
+    const COMPRESSED = 1 << 3;
+    /// AckOnly is set on a query which only collects acks, the receivers
+    /// do not deliver it to the application. Always set along with [`ACK`](Self::ACK).
+    const ACK_ONLY = 1 << 4;
+    /// RespondVia is set on a query whose payload starts with the encoded id
+    /// of the node the responses are sent to, instead of the requester.
+    const RESPOND_VIA = 1 << 5;
   }
 }
 
@@ -105,6 +116,24 @@ impl<I, A> QueryMessage<I, A> {
   pub fn no_broadcast(&self) -> bool {
     self.flags.contains(QueryFlag::NO_BROADCAST)
   }
+
+  /// Checks if the compressed flag is set
+  #[inline]
+  pub fn compressed(&self) -> bool {
+    self.flags.contains(QueryFlag::COMPRESSED)
+  }
+
+  /// Checks if the ack only flag is set
+  #[inline]
+  pub fn ack_only(&self) -> bool {
+    self.flags.contains(QueryFlag::ACK_ONLY)
+  }
+
+  /// Checks if the respond via flag is set
+  #[inline]
+  pub fn respond_via(&self) -> bool {
+    self.flags.contains(QueryFlag::RESPOND_VIA)
+  }
 }
 
 /// Error that can occur when transforming a [`QueryMessage`].
@@ -153,79 +182,30 @@ where
   }
 }
 
-impl<I, A> Transformable for QueryMessage<I, A>
+impl<I, A> QueryMessage<I, A>
 where
   I: Transformable,
   A: Transformable,
 {
-  type Error = QueryMessageTransformError<I, A>;
-
-  fn encode(&self, dst: &mut [u8]) -> Result<usize, Self::Error> {
-    let encoded_len = self.encoded_len();
-    if dst.len() < encoded_len {
-      return Err(Self::Error::BufferTooSmall);
-    }
-
-    let mut offset = 0;
-    NetworkEndian::write_u32(&mut dst[offset..], encoded_len as u32);
-    offset += 4;
-    offset += self.ltime.encode(&mut dst[offset..])?;
-    NetworkEndian::write_u32(&mut dst[offset..], self.id);
-    offset += 4;
-    offset += self.from.encode(&mut dst[offset..])?;
-    NetworkEndian::write_u32(&mut dst[offset..], self.filters.len() as u32);
-    offset += 4;
-    for filter in self.filters.iter() {
-      offset += filter
-        .encode(&mut dst[offset..])
-        .map_err(Self::Error::Filters)?;
-    }
-    NetworkEndian::write_u32(&mut dst[offset..], self.flags.bits());
-    offset += 4;
-    dst[offset] = self.relay_factor;
-    offset += 1;
-    offset += self.timeout.encode(&mut dst[offset..])?;
-    offset += self.name.encode(&mut dst[offset..])?;
-    offset += self
-      .payload
-      .encode(&mut dst[offset..])
-      .map_err(Self::Error::Payload)?;
-
-    debug_assert_eq!(
-      offset, encoded_len,
-      "expect write {} bytes, but actual write {} bytes",
-      encoded_len, offset
-    );
-
-    Ok(offset)
-  }
-
-  fn encoded_len(&self) -> usize {
-    4 + self.ltime.encoded_len()
-      + 4 // id
-      + self.from.encoded_len()
-      + 4 // num filters
-      + self.filters.iter().map(|f| f.encoded_len()).sum::<usize>()
-      + 4 // flags
-      + 1 // relay_factor
-      + self.timeout.encoded_len()
-      + self.name.encoded_len()
-      + self.payload.encoded_len()
+  /// Decodes a [`QueryMessage`] from the given buffer, the filters and payload of the
+  /// returned message share the memory of `src` instead of being copied out of it.
+  pub fn decode_from_bytes(src: &Bytes) -> Result<(usize, Self), QueryMessageTransformError<I, A>> {
+    Self::decode_in(src, |r| src.slice(r))
   }
 
-  fn decode(src: &[u8]) -> Result<(usize, Self), Self::Error>
-  where
-    Self: Sized,
-  {
+  fn decode_in(
+    src: &[u8],
+    slice: impl Fn(Range<usize>) -> Bytes,
+  ) -> Result<(usize, Self), QueryMessageTransformError<I, A>> {
     let src_len = src.len();
     if src.len() < 4 {
-      return Err(Self::Error::NotEnoughBytes);
+      return Err(QueryMessageTransformError::NotEnoughBytes);
     }
 
     let mut offset = 0;
     let len = NetworkEndian::read_u32(&src[offset..]) as usize;
     if src.len() < len {
-      return Err(Self::Error::NotEnoughBytes);
+      return Err(QueryMessageTransformError::NotEnoughBytes);
     }
     offset += 4;
 
@@ -233,7 +213,7 @@ where
     offset += n;
 
     if offset + 4 > src_len {
-      return Err(Self::Error::NotEnoughBytes);
+      return Err(QueryMessageTransformError::NotEnoughBytes);
     }
 
     let id = NetworkEndian::read_u32(&src[offset..]);
@@ -243,7 +223,7 @@ where
     offset += n;
 
     if offset + 4 > src_len {
-      return Err(Self::Error::NotEnoughBytes);
+      return Err(QueryMessageTransformError::NotEnoughBytes);
     }
 
     let num_filters = NetworkEndian::read_u32(&src[offset..]) as usize;
@@ -251,20 +231,21 @@ where
 
     let mut filters = TinyVec::with_capacity(num_filters);
     for _ in 0..num_filters {
-      let (n, filter) = Bytes::decode(&src[offset..]).map_err(Self::Error::Filters)?;
-      filters.push(filter);
+      let (n, filter) =
+        decode_bytes_range(src, offset).map_err(QueryMessageTransformError::Filters)?;
+      filters.push(slice(filter));
       offset += n;
     }
 
     if offset + 4 > src_len {
-      return Err(Self::Error::NotEnoughBytes);
+      return Err(QueryMessageTransformError::NotEnoughBytes);
     }
 
     let flags = QueryFlag::from_bits_retain(NetworkEndian::read_u32(&src[offset..]));
     offset += 4;
 
     if offset + 1 > src_len {
-      return Err(Self::Error::NotEnoughBytes);
+      return Err(QueryMessageTransformError::NotEnoughBytes);
     }
 
     let relay_factor = src[offset];
@@ -276,7 +257,9 @@ where
     let (n, name) = SmolStr::decode(&src[offset..])?;
     offset += n;
 
-    let (n, payload) = Bytes::decode(&src[offset..]).map_err(Self::Error::Payload)?;
+    let (n, payload) =
+      decode_bytes_range(src, offset).map_err(QueryMessageTransformError::Payload)?;
+    let payload = slice(payload);
     offset += n;
 
     debug_assert_eq!(
@@ -302,6 +285,74 @@ where
   }
 }
 
+impl<I, A> Transformable for QueryMessage<I, A>
+where
+  I: Transformable,
+  A: Transformable,
+{
+  type Error = QueryMessageTransformError<I, A>;
+
+  fn encode(&self, dst: &mut [u8]) -> Result<usize, Self::Error> {
+    let encoded_len = self.encoded_len();
+    if dst.len() < encoded_len {
+      return Err(Self::Error::BufferTooSmall);
+    }
+
+    let mut offset = 0;
+    NetworkEndian::write_u32(&mut dst[offset..], encoded_len as u32);
+    offset += 4;
+    offset += self.ltime.encode(&mut dst[offset..])?;
+    NetworkEndian::write_u32(&mut dst[offset..], self.id);
+    offset += 4;
+    offset += self.from.encode(&mut dst[offset..])?;
+    NetworkEndian::write_u32(&mut dst[offset..], self.filters.len() as u32);
+    offset += 4;
+    for filter in self.filters.iter() {
+      offset += filter
+        .encode(&mut dst[offset..])
+        .map_err(Self::Error::Filters)?;
+    }
+    NetworkEndian::write_u32(&mut dst[offset..], self.flags.bits());
+    offset += 4;
+    dst[offset] = self.relay_factor;
+    offset += 1;
+    offset += self.timeout.encode(&mut dst[offset..])?;
+    offset += self.name.encode(&mut dst[offset..])?;
+    offset += self
+      .payload
+      .encode(&mut dst[offset..])
+      .map_err(Self::Error::Payload)?;
+
+    debug_assert_eq!(
+      offset, encoded_len,
+      "expect write {} bytes, but actual write {} bytes",
+      encoded_len, offset
+    );
+
+    Ok(offset)
+  }
+
+  fn encoded_len(&self) -> usize {
+    4 + self.ltime.encoded_len()
+      + 4 // id
+      + self.from.encoded_len()
+      + 4 // num filters
+      + self.filters.iter().map(|f| f.encoded_len()).sum::<usize>()
+      + 4 // flags
+      + 1 // relay_factor
+      + self.timeout.encoded_len()
+      + self.name.encoded_len()
+      + self.payload.encoded_len()
+  }
+
+  fn decode(src: &[u8]) -> Result<(usize, Self), Self::Error>
+  where
+    Self: Sized,
+  {
+    Self::decode_in(src, |r| Bytes::copy_from_slice(&src[r]))
+  }
+}
+
 /// Query response message
 #[viewit::viewit(getters(style = "ref"), setters(prefix = "with"))]
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -354,6 +405,12 @@ impl<I, A> QueryResponseMessage<I, A> {
   pub fn no_broadcast(&self) -> bool {
     self.flags.contains(QueryFlag::NO_BROADCAST)
   }
+
+  /// Checks if the relayed flag is set
+  #[inline]
+  pub fn relayed(&self) -> bool {
+    self.flags.contains(QueryFlag::RELAYED)
+  }
 }
 
 /// Error that can occur when transforming a [`QueryResponseMessage`].
@@ -390,56 +447,32 @@ where
   }
 }
 
-impl<I, A> Transformable for QueryResponseMessage<I, A>
+impl<I, A> QueryResponseMessage<I, A>
 where
   I: Transformable,
   A: Transformable,
 {
-  type Error = QueryResponseMessageTransformError<I, A>;
-
-  fn encode(&self, dst: &mut [u8]) -> Result<usize, Self::Error> {
-    let encoded_len = self.encoded_len();
-    if dst.len() < encoded_len {
-      return Err(Self::Error::BufferTooSmall);
-    }
-
-    let mut offset = 0;
-    NetworkEndian::write_u32(&mut dst[offset..], encoded_len as u32);
-    offset += 4;
-    offset += self.ltime.encode(&mut dst[offset..])?;
-    NetworkEndian::write_u32(&mut dst[offset..], self.id);
-    offset += 4;
-    offset += self.from.encode(&mut dst[offset..])?;
-    NetworkEndian::write_u32(&mut dst[offset..], self.flags.bits());
-    offset += 4;
-    offset += self.payload.encode(&mut dst[offset..])?;
-
-    debug_assert_eq!(
-      offset, encoded_len,
-      "expect write {} bytes, but actual write {} bytes",
-      encoded_len, offset
-    );
-
-    Ok(offset)
-  }
-
-  fn encoded_len(&self) -> usize {
-    4 + self.ltime.encoded_len() + 4 + self.from.encoded_len() + 4 + self.payload.encoded_len()
+  /// Decodes a [`QueryResponseMessage`] from the given buffer, the payload of the
+  /// returned message share the memory of `src` instead of being copied out of it.
+  pub fn decode_from_bytes(
+    src: &Bytes,
+  ) -> Result<(usize, Self), QueryResponseMessageTransformError<I, A>> {
+    Self::decode_in(src, |r| src.slice(r))
   }
 
-  fn decode(src: &[u8]) -> Result<(usize, Self), Self::Error>
-  where
-    Self: Sized,
-  {
+  fn decode_in(
+    src: &[u8],
+    slice: impl Fn(Range<usize>) -> Bytes,
+  ) -> Result<(usize, Self), QueryResponseMessageTransformError<I, A>> {
     let src_len = src.len();
     if src.len() < 4 {
-      return Err(Self::Error::NotEnoughBytes);
+      return Err(QueryResponseMessageTransformError::NotEnoughBytes);
     }
 
     let mut offset = 0;
     let len = NetworkEndian::read_u32(&src[offset..]) as usize;
     if src.len() < len {
-      return Err(Self::Error::NotEnoughBytes);
+      return Err(QueryResponseMessageTransformError::NotEnoughBytes);
     }
 
     offset += 4;
@@ -447,7 +480,7 @@ where
     offset += n;
 
     if offset + 4 > src_len {
-      return Err(Self::Error::NotEnoughBytes);
+      return Err(QueryResponseMessageTransformError::NotEnoughBytes);
     }
     let id = NetworkEndian::read_u32(&src[offset..]);
     offset += 4;
@@ -456,13 +489,14 @@ where
     offset += n;
 
     if offset + 4 > src_len {
-      return Err(Self::Error::NotEnoughBytes);
+      return Err(QueryResponseMessageTransformError::NotEnoughBytes);
     }
 
     let flags = QueryFlag::from_bits_retain(NetworkEndian::read_u32(&src[offset..]));
     offset += 4;
 
-    let (n, payload) = Bytes::decode(&src[offset..])?;
+    let (n, payload) = decode_bytes_range(src, offset)?;
+    let payload = slice(payload);
     offset += n;
 
     debug_assert_eq!(
@@ -484,6 +518,51 @@ where
   }
 }
 
+impl<I, A> Transformable for QueryResponseMessage<I, A>
+where
+  I: Transformable,
+  A: Transformable,
+{
+  type Error = QueryResponseMessageTransformError<I, A>;
+
+  fn encode(&self, dst: &mut [u8]) -> Result<usize, Self::Error> {
+    let encoded_len = self.encoded_len();
+    if dst.len() < encoded_len {
+      return Err(Self::Error::BufferTooSmall);
+    }
+
+    let mut offset = 0;
+    NetworkEndian::write_u32(&mut dst[offset..], encoded_len as u32);
+    offset += 4;
+    offset += self.ltime.encode(&mut dst[offset..])?;
+    NetworkEndian::write_u32(&mut dst[offset..], self.id);
+    offset += 4;
+    offset += self.from.encode(&mut dst[offset..])?;
+    NetworkEndian::write_u32(&mut dst[offset..], self.flags.bits());
+    offset += 4;
+    offset += self.payload.encode(&mut dst[offset..])?;
+
+    debug_assert_eq!(
+      offset, encoded_len,
+      "expect write {} bytes, but actual write {} bytes",
+      encoded_len, offset
+    );
+
+    Ok(offset)
+  }
+
+  fn encoded_len(&self) -> usize {
+    4 + self.ltime.encoded_len() + 4 + self.from.encoded_len() + 4 + self.payload.encoded_len()
+  }
+
+  fn decode(src: &[u8]) -> Result<(usize, Self), Self::Error>
+  where
+    Self: Sized,
+  {
+    Self::decode_in(src, |r| Bytes::copy_from_slice(&src[r]))
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use std::net::SocketAddr;
@@ -595,6 +674,12 @@ mod tests {
           .unwrap();
         assert_eq!(decoded_len, encoded_len);
         assert_eq!(decoded, filter);
+
+        let buf = Bytes::from(buf);
+        let (decoded_len, decoded) =
+          QueryResponseMessage::<SmolStr, SocketAddr>::decode_from_bytes(&buf).unwrap();
+        assert_eq!(decoded_len, encoded_len);
+        assert_eq!(decoded, filter);
       }
     });
   }
@@ -625,6 +710,12 @@ mod tests {
         .unwrap();
         assert_eq!(decoded_len, encoded_len);
         assert_eq!(decoded, filter);
+
+        let buf = Bytes::from(buf);
+        let (decoded_len, decoded) =
+          QueryMessage::<SmolStr, SocketAddr>::decode_from_bytes(&buf).unwrap();
+        assert_eq!(decoded_len, encoded_len);
+        assert_eq!(decoded, filter);
       }
     });
   }
@@ -1,9 +1,11 @@
 use byteorder::{ByteOrder, NetworkEndian};
-use memberlist_types::{bytes::Bytes, CheapClone, OneOrMore};
+use memberlist_types::{bytes::Bytes, CheapClone, OneOrMore, TinyVec};
 use smol_str::SmolStr;
 use transformable::{BytesTransformError, StringTransformError, Transformable};
 
-use super::{LamportTime, LamportTimeTransformError};
+use std::ops::Range;
+
+use super::{message::decode_bytes_range, LamportTime, LamportTimeTransformError};
 
 /// Used to buffer events to prevent re-delivery
 #[viewit::viewit(setters(prefix = "with"))]
@@ -264,8 +266,44 @@ pub struct UserEventMessage {
     )
   )]
   cc: bool,
+  /// Whether the payload is compressed.
+  #[viewit(
+    getter(
+      const,
+      style = "move",
+      attrs(doc = "Returns if the payload of this message is compressed")
+    ),
+    setter(
+      const,
+      attrs(doc = "Sets if the payload of this message is compressed (Builder pattern)")
+    )
+  )]
+  compressed: bool,
+  /// The tag filters a member must match to deliver the event.
+  #[viewit(
+    getter(
+      const,
+      style = "ref",
+      attrs(
+        doc = "Returns the tag filters, as `(tag, regex)` pairs, a member must all match to deliver the event. The event is delivered everywhere if there are none."
+      )
+    ),
+    setter(attrs(
+      doc = "Sets the tag filters, as `(tag, regex)` pairs, a member must all match to deliver the event (Builder pattern)"
+    ))
+  )]
+  #[cfg_attr(feature = "serde", serde(default))]
+  distribution: TinyVec<(SmolStr, SmolStr)>,
 }
 
+/// The flags of a [`UserEventMessage`], encoded in a single byte. Older
+/// versions only ever wrote `0` or `1` (the coalesce flag) in this byte.
+const USER_EVENT_CC: u8 = 1 << 0;
+const USER_EVENT_COMPRESSED: u8 = 1 << 1;
+/// The tag filters follow the payload. Older versions skip them, since they
+/// are covered by the length prefix, and deliver the event everywhere.
+const USER_EVENT_DISTRIBUTION: u8 = 1 << 2;
+
 impl CheapClone for UserEventMessage {
   fn cheap_clone(&self) -> Self {
     Self {
@@ -273,6 +311,8 @@ impl CheapClone for UserEventMessage {
       name: self.name.cheap_clone(),
       payload: self.payload.clone(),
       cc: self.cc,
+      compressed: self.compressed,
+      distribution: self.distribution.clone(),
     }
   }
 }
@@ -298,62 +338,60 @@ pub enum UserEventMessageTransformError {
   /// Error transforming Bytes
   #[error(transparent)]
   Payload(#[from] BytesTransformError),
-}
-
-impl Transformable for UserEventMessage {
-  type Error = UserEventMessageTransformError;
-
-  fn encode(&self, dst: &mut [u8]) -> Result<usize, Self::Error> {
-    let encoded_len = self.encoded_len();
-    if dst.len() < encoded_len {
-      return Err(Self::Error::BufferTooSmall);
-    }
-
-    let mut offset = 0;
-    NetworkEndian::write_u32(&mut dst[offset..], encoded_len as u32);
-    offset += 4;
-    dst[offset] = self.cc as u8;
-    offset += 1;
-    offset += self.ltime.encode(&mut dst[offset..])?;
-    offset += self.name.encode(&mut dst[offset..])?;
-    offset += self.payload.encode(&mut dst[offset..])?;
-
-    debug_assert_eq!(
-      offset, encoded_len,
-      "expect write {} bytes, actual read {} bytes",
-      encoded_len, offset
-    );
 
-    Ok(offset)
-  }
+  /// Returned when there are too many tag filters to encode
+  #[error("too many tag filters {0}, the limit is {max}", max = u16::MAX)]
+  TooManyFilters(usize),
+}
 
-  fn encoded_len(&self) -> usize {
-    4 + self.ltime.encoded_len() + self.name.encoded_len() + self.payload.encoded_len() + 1
+impl UserEventMessage {
+  /// Decodes a [`UserEventMessage`] from the given buffer, the payload of the returned
+  /// message shares the memory of `src` instead of being copied out of it.
+  pub fn decode_from_bytes(src: &Bytes) -> Result<(usize, Self), UserEventMessageTransformError> {
+    Self::decode_in(src, |r| src.slice(r))
   }
 
-  fn decode(src: &[u8]) -> Result<(usize, Self), Self::Error>
-  where
-    Self: Sized,
-  {
+  fn decode_in(
+    src: &[u8],
+    payload: impl Fn(Range<usize>) -> Bytes,
+  ) -> Result<(usize, Self), UserEventMessageTransformError> {
     let src_len = src.len();
     if src_len < 4 {
-      return Err(Self::Error::NotEnoughBytes);
+      return Err(UserEventMessageTransformError::NotEnoughBytes);
     }
 
     let len = NetworkEndian::read_u32(&src[0..4]) as usize;
     if src_len < len {
-      return Err(Self::Error::NotEnoughBytes);
+      return Err(UserEventMessageTransformError::NotEnoughBytes);
     }
 
     let mut offset = 4;
-    let cc = src[offset] != 0;
+    let flags = src[offset];
+    let cc = flags & USER_EVENT_CC != 0;
+    let compressed = flags & USER_EVENT_COMPRESSED != 0;
     offset += 1;
     let (ltime_offset, ltime) = LamportTime::decode(&src[offset..])?;
     offset += ltime_offset;
     let (name_offset, name) = SmolStr::decode(&src[offset..])?;
     offset += name_offset;
-    let (payload_offset, payload) = Bytes::decode(&src[offset..])?;
+    let (payload_offset, payload_range) = decode_bytes_range(src, offset)?;
+    let payload = payload(payload_range);
     offset += payload_offset;
+    let mut distribution = TinyVec::new();
+    if flags & USER_EVENT_DISTRIBUTION != 0 {
+      if src_len < offset + 2 {
+        return Err(UserEventMessageTransformError::NotEnoughBytes);
+      }
+      let num = NetworkEndian::read_u16(&src[offset..]) as usize;
+      offset += 2;
+      for _ in 0..num {
+        let (tag_offset, tag) = SmolStr::decode(&src[offset..])?;
+        offset += tag_offset;
+        let (expr_offset, expr) = SmolStr::decode(&src[offset..])?;
+        offset += expr_offset;
+        distribution.push((tag, expr));
+      }
+    }
 
     debug_assert_eq!(
       offset, len,
@@ -368,11 +406,85 @@ impl Transformable for UserEventMessage {
         name,
         payload,
         cc,
+        compressed,
+        distribution,
       },
     ))
   }
 }
 
+impl Transformable for UserEventMessage {
+  type Error = UserEventMessageTransformError;
+
+  fn encode(&self, dst: &mut [u8]) -> Result<usize, Self::Error> {
+    let encoded_len = self.encoded_len();
+    if dst.len() < encoded_len {
+      return Err(Self::Error::BufferTooSmall);
+    }
+
+    let mut offset = 0;
+    NetworkEndian::write_u32(&mut dst[offset..], encoded_len as u32);
+    offset += 4;
+    let mut flags = 0;
+    if self.cc {
+      flags |= USER_EVENT_CC;
+    }
+    if self.compressed {
+      flags |= USER_EVENT_COMPRESSED;
+    }
+    if !self.distribution.is_empty() {
+      flags |= USER_EVENT_DISTRIBUTION;
+    }
+    dst[offset] = flags;
+    offset += 1;
+    offset += self.ltime.encode(&mut dst[offset..])?;
+    offset += self.name.encode(&mut dst[offset..])?;
+    offset += self.payload.encode(&mut dst[offset..])?;
+    if !self.distribution.is_empty() {
+      let num = u16::try_from(self.distribution.len())
+        .map_err(|_| Self::Error::TooManyFilters(self.distribution.len()))?;
+      NetworkEndian::write_u16(&mut dst[offset..], num);
+      offset += 2;
+      for (tag, expr) in self.distribution.iter() {
+        offset += tag.encode(&mut dst[offset..])?;
+        offset += expr.encode(&mut dst[offset..])?;
+      }
+    }
+
+    debug_assert_eq!(
+      offset, encoded_len,
+      "expect write {} bytes, actual read {} bytes",
+      encoded_len, offset
+    );
+
+    Ok(offset)
+  }
+
+  fn encoded_len(&self) -> usize {
+    let distribution = if self.distribution.is_empty() {
+      0
+    } else {
+      2 + self
+        .distribution
+        .iter()
+        .map(|(tag, expr)| tag.encoded_len() + expr.encoded_len())
+        .sum::<usize>()
+    };
+    4 + self.ltime.encoded_len()
+      + self.name.encoded_len()
+      + self.payload.encoded_len()
+      + 1
+      + distribution
+  }
+
+  fn decode(src: &[u8]) -> Result<(usize, Self), Self::Error>
+  where
+    Self: Sized,
+  {
+    Self::decode_in(src, |r| Bytes::copy_from_slice(&src[r]))
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use rand::{distributions::Alphanumeric, random, Rng};
@@ -430,11 +542,17 @@ mod tests {
         .take(size)
         .collect::<Vec<u8>>();
 
+      let distribution = (0..size % 3)
+        .map(|i| (SmolStr::new(format!("tag{i}")), SmolStr::new(&name)))
+        .collect();
+
       Self {
         ltime: LamportTime::random(),
         name: name.into(),
         payload: payload.into(),
         cc: random(),
+        compressed: random(),
+        distribution,
       }
     }
   }
@@ -519,6 +637,11 @@ mod tests {
             .unwrap();
         assert_eq!(decoded_len, encoded_len);
         assert_eq!(decoded, event);
+
+        let buf = Bytes::from(buf);
+        let (decoded_len, decoded) = UserEventMessage::decode_from_bytes(&buf).unwrap();
+        assert_eq!(decoded_len, encoded_len);
+        assert_eq!(decoded, event);
       }
     })
   }
@@ -1,8 +1,16 @@
 /// Unknown delegate version
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, thiserror::Error)]
-#[error("V{0} is not a valid delegate version")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct UnknownDelegateVersion(u8);
 
+impl core::fmt::Display for UnknownDelegateVersion {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "V{} is not a valid delegate version", self.0)
+  }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for UnknownDelegateVersion {}
+
 /// Delegate version
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
@@ -27,8 +35,15 @@ pub enum DelegateVersion {
   V1 = 1,
 }
 
+impl DelegateVersion {
+  /// The oldest delegate version understood.
+  pub const MIN: Self = Self::V1;
+  /// The newest delegate version understood.
+  pub const MAX: Self = Self::V1;
+}
+
 impl core::fmt::Display for DelegateVersion {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
     match self {
       DelegateVersion::V1 => write!(f, "V1"),
     }
@@ -65,10 +80,18 @@ const _: () = {
 };
 
 /// Unknown protocol version
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, thiserror::Error)]
-#[error("V{0} is not a valid protocol version")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct UnknownProtocolVersion(u8);
 
+impl core::fmt::Display for UnknownProtocolVersion {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "V{} is not a valid protocol version", self.0)
+  }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for UnknownProtocolVersion {}
+
 /// Protocol version
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
@@ -91,12 +114,24 @@ pub enum ProtocolVersion {
   /// Version 1
   #[default]
   V1 = 1,
+  /// Version 2, the push/pull state and the gossiped payloads may be
+  /// compressed, the large user events fragmented and the query responses
+  /// sent to another member than the requester
+  V2 = 2,
+}
+
+impl ProtocolVersion {
+  /// The oldest protocol version understood.
+  pub const MIN: Self = Self::V1;
+  /// The newest protocol version understood.
+  pub const MAX: Self = Self::V2;
 }
 
 impl core::fmt::Display for ProtocolVersion {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
     match self {
       Self::V1 => write!(f, "V1"),
+      Self::V2 => write!(f, "V2"),
     }
   }
 }
@@ -106,6 +141,7 @@ impl TryFrom<u8> for ProtocolVersion {
   fn try_from(v: u8) -> Result<Self, Self::Error> {
     match v {
       1 => Ok(Self::V1),
+      2 => Ok(Self::V2),
       _ => Err(UnknownProtocolVersion(v)),
     }
   }
@@ -117,6 +153,7 @@ const _: () = {
     fn from(value: ArchivedProtocolVersion) -> Self {
       match value {
         ArchivedProtocolVersion::V1 => Self::V1,
+        ArchivedProtocolVersion::V2 => Self::V2,
       }
     }
   }
@@ -125,6 +162,7 @@ const _: () = {
     fn from(value: ProtocolVersion) -> Self {
       match value {
         ProtocolVersion::V1 => Self::V1,
+        ProtocolVersion::V2 => Self::V2,
       }
     }
   }
@@ -137,7 +175,7 @@ mod tests {
   #[test]
   fn test_delegate_version() {
     assert_eq!(DelegateVersion::V1 as u8, 1);
-    assert_eq!(DelegateVersion::V1.to_string(), "V1");
+    assert_eq!(alloc::format!("{}", DelegateVersion::V1), "V1");
     assert_eq!(DelegateVersion::try_from(1), Ok(DelegateVersion::V1));
     assert_eq!(DelegateVersion::try_from(0), Err(UnknownDelegateVersion(0)));
   }
@@ -145,8 +183,9 @@ mod tests {
   #[test]
   fn test_protocol_version() {
     assert_eq!(ProtocolVersion::V1 as u8, 1);
-    assert_eq!(ProtocolVersion::V1.to_string(), "V1");
+    assert_eq!(alloc::format!("{}", ProtocolVersion::V1), "V1");
     assert_eq!(ProtocolVersion::try_from(1), Ok(ProtocolVersion::V1));
+    assert_eq!(ProtocolVersion::try_from(2), Ok(ProtocolVersion::V2));
     assert_eq!(ProtocolVersion::try_from(0), Err(UnknownProtocolVersion(0)));
   }
 }